@@ -0,0 +1,61 @@
+#![allow(dead_code)]
+
+//! The CPU features the kernel cannot run without, checked against raw
+//! CPUID words. Reading CPUID is the architecture layer's job; deciding
+//! what is missing is kept here so it can be fed made-up words in tests.
+
+/// Leaf 1 EDX: physical address extension, needed for 4-level paging.
+pub const LEAF1_EDX_PAE: u32 = 1 << 6;
+/// Leaf 1 EDX: SSE2, which user programs built for x86_64 assume.
+pub const LEAF1_EDX_SSE2: u32 = 1 << 26;
+/// Leaf 0x8000_0001 EDX: long mode.
+pub const EXT1_EDX_LONG_MODE: u32 = 1 << 29;
+
+/// The extended leaf that carries the long mode bit.
+pub const EXTENDED_FEATURE_LEAF: u32 = 0x8000_0001;
+
+/// The CPUID output `missing_feature` looks at.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct CpuidWords {
+    /// EDX of leaf 1.
+    pub basic_edx: u32,
+    /// EAX of leaf 0x8000_0000: the highest extended leaf.
+    pub max_extended_leaf: u32,
+    /// EDX of leaf 0x8000_0001. Ignored when that leaf does not exist.
+    pub extended_edx: u32,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RequiredFeature {
+    Pae,
+    LongMode,
+    Sse2,
+}
+
+impl RequiredFeature {
+    pub const ALL: [RequiredFeature; 3] = [RequiredFeature::Pae, RequiredFeature::LongMode, RequiredFeature::Sse2];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RequiredFeature::Pae => "PAE",
+            RequiredFeature::LongMode => "long mode",
+            RequiredFeature::Sse2 => "SSE2",
+        }
+    }
+
+    pub fn present(self, words: &CpuidWords) -> bool {
+        match self {
+            RequiredFeature::Pae => words.basic_edx & LEAF1_EDX_PAE != 0,
+            RequiredFeature::LongMode => {
+                words.max_extended_leaf >= EXTENDED_FEATURE_LEAF && words.extended_edx & EXT1_EDX_LONG_MODE != 0
+            }
+            RequiredFeature::Sse2 => words.basic_edx & LEAF1_EDX_SSE2 != 0,
+        }
+    }
+}
+
+/// The first required feature `words` do not report, in `ALL` order, or
+/// `None` when the CPU has everything.
+pub fn missing_feature(words: &CpuidWords) -> Option<RequiredFeature> {
+    RequiredFeature::ALL.iter().copied().find(|feature| !feature.present(words))
+}
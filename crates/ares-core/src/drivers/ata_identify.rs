@@ -0,0 +1,79 @@
+#![allow(dead_code)]
+
+//! Decoding of the 256-word block an ATA drive returns for IDENTIFY DEVICE.
+//! Only the fields the kernel uses are pulled out: the addressable sector
+//! counts and the model string.
+
+pub const IDENTIFY_WORDS: usize = 256;
+pub const MODEL_LEN: usize = 40;
+
+/// Highest sector count a 28-bit LBA command can reach.
+pub const LBA28_LIMIT: u64 = 1 << 28;
+
+const WORD_MODEL: usize = 27;
+const WORD_LBA28_SECTORS: usize = 60;
+const WORD_COMMAND_SETS: usize = 83;
+const WORD_LBA48_SECTORS: usize = 100;
+const COMMAND_SET_LBA48: u16 = 1 << 10;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct AtaGeometry {
+    /// Sectors reachable with 28-bit commands (words 60-61).
+    pub lba28_sectors: u32,
+    /// Sectors reachable with 48-bit commands (words 100-103), or 0 when the
+    /// drive does not advertise LBA48.
+    pub lba48_sectors: u64,
+    model: [u8; MODEL_LEN],
+    model_len: usize,
+}
+
+impl AtaGeometry {
+    pub fn parse(words: &[u16; IDENTIFY_WORDS]) -> Self {
+        let lba28_sectors = words[WORD_LBA28_SECTORS] as u32 | (words[WORD_LBA28_SECTORS + 1] as u32) << 16;
+
+        let lba48_sectors = if words[WORD_COMMAND_SETS] & COMMAND_SET_LBA48 != 0 {
+            (0..4).fold(0u64, |acc, index| {
+                acc | (words[WORD_LBA48_SECTORS + index] as u64) << (16 * index)
+            })
+        } else {
+            0
+        };
+
+        // Each word holds two characters, first one in the high byte.
+        let mut model = [0u8; MODEL_LEN];
+        for (index, word) in words[WORD_MODEL..WORD_MODEL + MODEL_LEN / 2].iter().enumerate() {
+            model[index * 2..index * 2 + 2].copy_from_slice(&word.to_be_bytes());
+        }
+        let model_len = model
+            .iter()
+            .rposition(|byte| *byte != b' ' && *byte != 0)
+            .map_or(0, |last| last + 1);
+
+        Self {
+            lba28_sectors,
+            lba48_sectors,
+            model,
+            model_len,
+        }
+    }
+
+    /// Total sectors on the drive, preferring the LBA48 count when present.
+    pub fn sectors(&self) -> u64 {
+        if self.lba48_sectors != 0 {
+            self.lba48_sectors
+        } else {
+            self.lba28_sectors as u64
+        }
+    }
+
+    /// The model string with its space padding trimmed, or `?` if the
+    /// drive filled it with anything but ASCII.
+    pub fn model(&self) -> &str {
+        let bytes = &self.model[..self.model_len];
+        if bytes.is_ascii() {
+            core::str::from_utf8(bytes).unwrap_or("?")
+        } else {
+            "?"
+        }
+    }
+}
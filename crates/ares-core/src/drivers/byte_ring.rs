@@ -0,0 +1,93 @@
+#![allow(dead_code)]
+
+//! Fixed-size byte FIFO with one writer and any number of readers, and no
+//! lock. `head` and `tail` count bytes ever taken and ever written; they
+//! only grow, and the slot for a count is `count % N`. The writer alone
+//! moves `tail`. Readers and the writer both move `head` with a
+//! compare-and-swap: a reader to take the oldest byte, the writer to throw
+//! it away when the ring is full. The keyboard IRQ path writes and `read()`
+//! callers drain, neither ever waits for the other.
+
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+pub struct ByteRing<const N: usize> {
+    slots: [AtomicU8; N],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+impl<const N: usize> ByteRing<N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { AtomicU8::new(0) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Append `byte`. When the ring is full the oldest byte is dropped to
+    /// make room and `push` returns false.
+    ///
+    /// Only one context may push at a time.
+    pub fn push(&self, byte: u8) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let mut kept_all = true;
+        let mut head = self.head.load(Ordering::Acquire);
+        while tail.wrapping_sub(head) >= N {
+            // A reader may take the oldest byte first; then there is room.
+            match self
+                .head
+                .compare_exchange(head, head.wrapping_add(1), Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    kept_all = false;
+                    break;
+                }
+                Err(current) => head = current,
+            }
+        }
+        self.slots[tail % N].store(byte, Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        kept_all
+    }
+
+    pub fn pop(&self) -> Option<u8> {
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            if head == self.tail.load(Ordering::Acquire) {
+                return None;
+            }
+            // Read before claiming: once `head` moves the writer may reuse
+            // the slot. A lost race re-reads at the new head.
+            let byte = self.slots[head % N].load(Ordering::Relaxed);
+            match self
+                .head
+                .compare_exchange(head, head.wrapping_add(1), Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Some(byte),
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Bytes waiting. Only a snapshot while the ring is in use.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(N)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> Default for ByteRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
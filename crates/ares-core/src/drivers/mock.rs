@@ -1,4 +1,4 @@
-use crate::drivers::{BlockDevice, Driver, DriverError, DriverKind};
+use crate::drivers::{check_block_range, BlockDevice, CharDevice, Driver, DriverError, DriverKind};
 
 pub struct MemBlockDevice {
     name: &'static str,
@@ -46,40 +46,96 @@ impl BlockDevice for MemBlockDevice {
     }
 
     fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), DriverError> {
-        let block_size = self.block_size();
-        if buf.len() % block_size != 0 {
-            return Err(DriverError::Unsupported);
-        }
-        let offset = (lba as usize)
-            .checked_mul(block_size)
-            .ok_or(DriverError::IoError)?;
-
+        check_block_range(self, lba, buf.len())?;
+        let offset = lba as usize * self.block;
         self.with_storage(|storage| {
-            let end = offset + buf.len();
-            if end > storage.len() {
-                return Err(DriverError::IoError);
-            }
-            buf.copy_from_slice(&storage[offset..end]);
+            buf.copy_from_slice(&storage[offset..offset + buf.len()]);
             Ok(())
         })
     }
 
     fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), DriverError> {
-        let block_size = self.block_size();
-        if buf.len() % block_size != 0 {
-            return Err(DriverError::Unsupported);
-        }
-        let offset = (lba as usize)
-            .checked_mul(block_size)
-            .ok_or(DriverError::IoError)?;
-
+        check_block_range(self, lba, buf.len())?;
+        let offset = lba as usize * self.block;
         self.with_storage(|storage| {
-            let end = offset + buf.len();
-            if end > storage.len() {
-                return Err(DriverError::IoError);
-            }
-            storage[offset..end].copy_from_slice(buf);
+            storage[offset..offset + buf.len()].copy_from_slice(buf);
             Ok(())
         })
     }
+
+    fn capacity_sectors(&self) -> u64 {
+        self.with_storage(|storage| (storage.len() / self.block) as u64)
+    }
+}
+
+/// Character device whose input is fed by the test and whose output is
+/// recorded for inspection.
+pub struct MemCharDevice {
+    name: &'static str,
+    input: std::sync::Mutex<Vec<u8>>,
+    output: std::sync::Mutex<Vec<u8>>,
+}
+
+impl MemCharDevice {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            input: std::sync::Mutex::new(Vec::new()),
+            output: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Queue bytes for later `read` calls.
+    pub fn push_input(&self, bytes: &[u8]) {
+        self.input
+            .lock()
+            .expect("mem char device poisoned")
+            .extend_from_slice(bytes);
+    }
+
+    pub fn pending_input(&self) -> usize {
+        self.input.lock().expect("mem char device poisoned").len()
+    }
+
+    /// Everything written so far.
+    pub fn output(&self) -> Vec<u8> {
+        self.output.lock().expect("mem char device poisoned").clone()
+    }
+
+    /// Everything written so far, clearing the log.
+    pub fn take_output(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.output.lock().expect("mem char device poisoned"))
+    }
+}
+
+impl Driver for MemCharDevice {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Char
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+impl CharDevice for MemCharDevice {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, DriverError> {
+        let mut input = self.input.lock().expect("mem char device poisoned");
+        let count = buf.len().min(input.len());
+        buf[..count].copy_from_slice(&input[..count]);
+        input.drain(..count);
+        Ok(count)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, DriverError> {
+        self.output
+            .lock()
+            .expect("mem char device poisoned")
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
 }
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+use crate::vfs::handle::SeekFrom;
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum DriverKind {
     Block,
@@ -29,12 +31,94 @@ pub trait BlockDevice: Driver {
     fn flush(&self) -> Result<(), DriverError> {
         Ok(())
     }
+
+    /// Number of blocks on the device, or `u64::MAX` when the size is not
+    /// known.
+    fn capacity_sectors(&self) -> u64 {
+        u64::MAX
+    }
+}
+
+/// Check a request of `len` bytes at `lba` before it reaches the hardware.
+/// A length that is not whole blocks is `Unsupported`; a range that ends
+/// past `capacity_sectors()` is `IoError`. Returns the block count.
+pub fn check_block_range<D: BlockDevice + ?Sized>(device: &D, lba: u64, len: usize) -> Result<u64, DriverError> {
+    let block_size = device.block_size();
+    if block_size == 0 || !len.is_multiple_of(block_size) {
+        return Err(DriverError::Unsupported);
+    }
+    let blocks = (len / block_size) as u64;
+    match lba.checked_add(blocks) {
+        Some(end) if end <= device.capacity_sectors() => Ok(blocks),
+        _ => Err(DriverError::IoError),
+    }
 }
 
+/// Largest write a `CharDevice` must take in one piece.
+pub const ATOMIC_WRITE_MAX: usize = 512;
+
+/// A `write` of at most `ATOMIC_WRITE_MAX` bytes is atomic: it is taken
+/// whole or not at all, and no other writer's bytes land in the middle of
+/// it. Longer writes may be split or come back short.
 pub trait CharDevice: Driver {
     fn read(&self, buf: &mut [u8]) -> Result<usize, DriverError>;
     fn write(&self, buf: &[u8]) -> Result<usize, DriverError>;
+
+    /// Push out anything `write` has accepted but not yet delivered. Called
+    /// when an fd is closed, including the closes at process exit.
+    fn flush(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+
+    /// Out-of-band control request; `cmd` is one of the `ioctl` constants.
+    fn ioctl(&self, _cmd: u32, _arg: u64) -> Result<u64, DriverError> {
+        Err(DriverError::Unsupported)
+    }
+
+    /// Whether `read` would return without sleeping. Devices that never
+    /// block keep the default.
+    fn can_read(&self) -> bool {
+        true
+    }
+
+    /// Whether `write` would return without sleeping.
+    fn can_write(&self) -> bool {
+        true
+    }
+
+    /// Reposition a device that keeps its own cursor and return the new
+    /// offset. Streams such as the console and keyboard keep the default.
+    fn seek(&self, _pos: SeekFrom) -> Result<u64, DriverError> {
+        Err(DriverError::Unsupported)
+    }
+}
+
+/// Write all of `buf` in `ATOMIC_WRITE_MAX` pieces, so each piece keeps
+/// the atomic-write guarantee even when `buf` is longer. A short write is
+/// continued from where it stopped. Returns the bytes written, which is
+/// less than `buf.len()` only if the device stops taking data.
+pub fn write_all<D: CharDevice + ?Sized>(device: &D, buf: &[u8]) -> Result<usize, DriverError> {
+    let mut written = 0;
+    while written < buf.len() {
+        let end = buf.len().min(written + ATOMIC_WRITE_MAX);
+        match device.write(&buf[written..end]) {
+            Ok(0) => break,
+            Ok(count) => written += count,
+            Err(err) if written == 0 => return Err(err),
+            Err(_) => break,
+        }
+    }
+    Ok(written)
 }
 
+pub mod ansi;
+pub mod ata_identify;
+pub mod byte_ring;
+pub mod line_discipline;
+pub mod partition;
+pub mod poll;
+pub mod scancode;
+pub mod screen;
+
 #[cfg(any(test, feature = "std"))]
 pub mod mock;
@@ -20,6 +20,62 @@ pub enum FatError {
     InvalidPath,
     NotFound,
     Io,
+    /// Every slot in the volume table is taken by another name.
+    TooManyVolumes,
+    /// A cluster chain loops, or links to a cluster outside the data region.
+    CorruptChain,
+}
+
+/// The boot sector fields later LBA arithmetic depends on.
+struct Bpb {
+    sectors_per_cluster: u8,
+    reserved_sectors: u16,
+    num_fats: u8,
+    sectors_per_fat: u16,
+    root_dir_sectors: u32,
+    /// 0 when neither the 16- nor the 32-bit count is filled in.
+    total_sectors: u32,
+}
+
+impl Bpb {
+    /// Catch a blank or non-FAT boot sector before its values are used as
+    /// divisors and offsets.
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.sectors_per_cluster == 0 || !self.sectors_per_cluster.is_power_of_two() {
+            return Err("sectors per cluster is not a power of two");
+        }
+        if self.reserved_sectors == 0 {
+            return Err("no reserved sectors for the boot sector");
+        }
+        if self.num_fats != 1 && self.num_fats != 2 {
+            return Err("FAT count is not 1 or 2");
+        }
+        if self.sectors_per_fat == 0 {
+            return Err("FAT has no sectors");
+        }
+        let metadata = self.reserved_sectors as u64
+            + self.num_fats as u64 * self.sectors_per_fat as u64
+            + self.root_dir_sectors as u64;
+        if self.total_sectors != 0 && metadata >= self.total_sectors as u64 {
+            return Err("FATs and root directory do not fit in the volume");
+        }
+        Ok(())
+    }
+
+    /// Data clusters the volume can address, numbered from 2: what fits in
+    /// the sectors after the metadata, when the sector count is recorded,
+    /// and never more than the FAT has entries for.
+    fn cluster_count(&self) -> u32 {
+        let by_fat = (self.sectors_per_fat as u32 * SECTOR_SIZE as u32 / 2).saturating_sub(2);
+        if self.total_sectors == 0 {
+            return by_fat;
+        }
+        let metadata = self.reserved_sectors as u32
+            + self.num_fats as u32 * self.sectors_per_fat as u32
+            + self.root_dir_sectors;
+        let by_size = self.total_sectors.saturating_sub(metadata) / self.sectors_per_cluster as u32;
+        by_size.min(by_fat)
+    }
 }
 
 struct FatVolume {
@@ -36,6 +92,7 @@ struct FatVolume {
     root_dir_sectors: u32,
     data_lba: u64,
     bytes_per_cluster: usize,
+    cluster_count: u32,
 }
 
 impl FatVolume {
@@ -55,6 +112,10 @@ impl FatVolume {
         let num_fats = sector[16];
         let root_entries = u16::from_le_bytes([sector[17], sector[18]]);
         let sectors_per_fat = u16::from_le_bytes([sector[22], sector[23]]);
+        let total_sectors = match u16::from_le_bytes([sector[19], sector[20]]) {
+            0 => u32::from_le_bytes([sector[32], sector[33], sector[34], sector[35]]),
+            small => small as u32,
+        };
 
         let fat_lba = start_lba + reserved_sectors as u64;
         let root_dir_lba = fat_lba + (num_fats as u64 * sectors_per_fat as u64);
@@ -62,6 +123,16 @@ impl FatVolume {
             ((root_entries as u32 * 32) + (bytes_per_sector as u32 - 1)) / bytes_per_sector as u32;
         let data_lba = root_dir_lba + root_dir_sectors as u64;
 
+        let bpb = Bpb {
+            sectors_per_cluster,
+            reserved_sectors,
+            num_fats,
+            sectors_per_fat,
+            root_dir_sectors,
+            total_sectors,
+        };
+        bpb.validate().map_err(|_| FatError::Io)?;
+
         Ok(Self {
             device,
             start_lba,
@@ -76,6 +147,7 @@ impl FatVolume {
             root_dir_sectors,
             data_lba,
             bytes_per_cluster: bytes_per_sector * sectors_per_cluster as usize,
+            cluster_count: bpb.cluster_count(),
         })
     }
 
@@ -89,6 +161,12 @@ impl FatVolume {
         self.data_lba + ((cluster as u64 - 2) * self.sectors_per_cluster as u64)
     }
 
+    fn is_data_cluster(&self, cluster: u16) -> bool {
+        cluster >= 2 && (cluster as u32) < self.cluster_count + 2
+    }
+
+    /// The cluster after `cluster` in its chain, or `None` at the end. A
+    /// link to a reserved or out-of-range cluster is `CorruptChain`.
     fn next_cluster(&self, cluster: u16) -> Result<Option<u16>, FatError> {
         let fat_offset = cluster as usize * 2;
         let fat_sector = fat_offset / self.bytes_per_sector;
@@ -102,8 +180,10 @@ impl FatVolume {
 
         if entry >= FAT16_END {
             Ok(None)
-        } else {
+        } else if self.is_data_cluster(entry) {
             Ok(Some(entry))
+        } else {
+            Err(FatError::CorruptChain)
         }
     }
 
@@ -116,9 +196,20 @@ impl FatVolume {
             return Ok(None);
         }
 
+        if !self.is_data_cluster(start_cluster) {
+            return Err(FatError::CorruptChain);
+        }
+
+        // No chain is longer than the volume, so more hops than that means
+        // it loops back on itself.
         let cluster_bytes = self.bytes_per_cluster as u64;
         let mut cluster = start_cluster;
+        let mut hops = 0u32;
         while offset >= cluster_bytes {
+            hops += 1;
+            if hops >= self.cluster_count {
+                return Err(FatError::CorruptChain);
+            }
             match self.next_cluster(cluster)? {
                 Some(next) => {
                     cluster = next;
@@ -265,43 +356,119 @@ impl VfsFile for FatFile {
     }
 }
 
-static FAT_VOLUME: SpinLock<Option<FatVolume>> = SpinLock::new(None);
+pub const MAX_FAT_VOLUMES: usize = 4;
+pub const MAX_VOLUME_NAME: usize = 16;
+
+/// A loaded volume under its mount name. Volumes are leaked on mount so the
+/// files opened on them keep a stable reference even if the name is later
+/// mounted over.
+#[derive(Copy, Clone)]
+struct MountedVolume {
+    name: [u8; MAX_VOLUME_NAME],
+    name_len: usize,
+    volume: &'static FatVolume,
+}
 
-pub fn mount(device: &'static dyn BlockDevice, start_lba: u64) -> Result<(), FatError> {
-    let volume = FatVolume::load(device, start_lba)?;
-    let mut slot = FAT_VOLUME.lock();
-    *slot = Some(volume);
-    klog!("[fat] mounted at LBA {}\n", start_lba);
+impl MountedVolume {
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+}
+
+struct VolumeTable {
+    slots: [Option<MountedVolume>; MAX_FAT_VOLUMES],
+}
+
+impl VolumeTable {
+    const fn new() -> Self {
+        Self {
+            slots: [None; MAX_FAT_VOLUMES],
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&'static FatVolume> {
+        self.slots
+            .iter()
+            .flatten()
+            .find(|mounted| mounted.name() == name)
+            .map(|mounted| mounted.volume)
+    }
+
+    /// Put `volume` under `name`, replacing a volume already mounted there.
+    fn insert(&mut self, name: &str, volume: &'static FatVolume) -> Result<(), FatError> {
+        let mut entry = MountedVolume {
+            name: [0; MAX_VOLUME_NAME],
+            name_len: name.len(),
+            volume,
+        };
+        entry.name[..name.len()].copy_from_slice(name.as_bytes());
+
+        let slot = match self.slots.iter().position(|slot| matches!(slot, Some(mounted) if mounted.name() == name)) {
+            Some(index) => index,
+            None => self
+                .slots
+                .iter()
+                .position(|slot| slot.is_none())
+                .ok_or(FatError::TooManyVolumes)?,
+        };
+        self.slots[slot] = Some(entry);
+        Ok(())
+    }
+}
+
+fn valid_volume_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= MAX_VOLUME_NAME && !name.contains('/')
+}
+
+static FAT_VOLUMES: SpinLock<VolumeTable> = SpinLock::new(VolumeTable::new());
+
+/// Load the FAT volume starting at `start_lba` on `device` and mount it as
+/// `name`, replacing any volume of that name.
+pub fn mount(name: &str, device: &'static dyn BlockDevice, start_lba: u64) -> Result<(), FatError> {
+    if !valid_volume_name(name) {
+        return Err(FatError::InvalidPath);
+    }
+    let volume = leak(FatVolume::load(device, start_lba)?)?;
+    FAT_VOLUMES.lock().insert(name, volume)?;
+    klog!("[fat] mounted '{}' at LBA {}\n", name, start_lba);
     Ok(())
 }
 
-pub fn open_file(path: &str) -> Result<&'static dyn VfsFile, FatError> {
+pub fn is_mounted(name: &str) -> bool {
+    FAT_VOLUMES.lock().find(name).is_some()
+}
+
+/// Open a root-directory file on volume `name`.
+pub fn open_file(name: &str, path: &str) -> Result<&'static dyn VfsFile, FatError> {
     let trimmed = path.trim_matches('/');
     if trimmed.is_empty() {
         return Err(FatError::InvalidPath);
     }
 
-    let (volume_ptr, entry) = {
-        let guard = FAT_VOLUME.lock();
-        let volume = guard.as_ref().ok_or(FatError::NotMounted)?;
+    let (volume_ref, entry) = {
+        let table = FAT_VOLUMES.lock();
+        let volume = table.find(name).ok_or(FatError::NotMounted)?;
         let info = volume.find_root_file(trimmed)?;
-        (volume as *const FatVolume, info)
+        (volume, info)
     };
 
-    let volume_ref = unsafe { &*volume_ptr };
     let file = FatFile {
         volume: volume_ref,
         start_cluster: entry.0,
         size: entry.1,
     };
 
-    let layout = Layout::new::<FatFile>();
-    let raw = unsafe { heap::allocate(layout) } as *mut FatFile;
+    leak(file).map(|file| file as &'static dyn VfsFile)
+}
+
+fn leak<T>(value: T) -> Result<&'static T, FatError> {
+    let layout = Layout::new::<T>();
+    let raw = unsafe { heap::allocate(layout) } as *mut T;
     if raw.is_null() {
         return Err(FatError::Io);
     }
     unsafe {
-        raw.write(file);
+        raw.write(value);
         Ok(&*raw)
     }
 }
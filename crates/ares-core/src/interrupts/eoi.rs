@@ -0,0 +1,45 @@
+#![allow(dead_code)]
+
+//! End-of-interrupt policy for the cascaded 8259 PICs. The hardware reports
+//! a spurious interrupt on the lowest-priority line of a chip (IRQ7 on the
+//! master, IRQ15 on the slave) when a request goes away before it is
+//! acknowledged; the matching in-service bit is then clear and the line must
+//! not be EOI'd, or a real interrupt in service would be acknowledged early.
+
+/// Which PICs should receive an EOI after an IRQ.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Eoi {
+    None,
+    Master,
+    /// Slave first, then the master for the cascade line.
+    Both,
+}
+
+const MASTER_SPURIOUS: u8 = 7;
+const SLAVE_SPURIOUS: u8 = 15;
+
+/// Whether `irq` can be spurious at all. The in-service register only
+/// matters for these lines; for every other one `isr` may be passed as 0.
+pub fn may_be_spurious(irq: u8) -> bool {
+    matches!(irq, MASTER_SPURIOUS | SLAVE_SPURIOUS)
+}
+
+/// `isr` is the combined in-service register: master in the low byte,
+/// slave in the high byte.
+pub fn is_spurious(irq: u8, isr: u16) -> bool {
+    match irq {
+        MASTER_SPURIOUS | SLAVE_SPURIOUS => isr & (1 << irq) == 0,
+        _ => false,
+    }
+}
+
+pub fn eoi_for(irq: u8, isr: u16) -> Eoi {
+    match irq {
+        MASTER_SPURIOUS if is_spurious(irq, isr) => Eoi::None,
+        // The master did see a real request on the cascade line, so it
+        // still needs its EOI even though the slave's was spurious.
+        SLAVE_SPURIOUS if is_spurious(irq, isr) => Eoi::Master,
+        0..=7 => Eoi::Master,
+        _ => Eoi::Both,
+    }
+}
@@ -0,0 +1,3 @@
+pub mod eoi;
+pub mod pit;
+pub mod work;
@@ -0,0 +1,31 @@
+#![allow(dead_code)]
+
+//! Divisor math for channel 0 of the 8253/8254 PIT. The chip divides a fixed
+//! 1.193182 MHz input clock by a 16-bit reload value, so only rates between
+//! about 18.2 Hz (divisor 65535) and the input clock itself (divisor 1) can
+//! be produced, and most requests land on the nearest achievable rate.
+
+pub const PIT_CLOCK_HZ: u32 = 1_193_182;
+pub const MIN_DIVISOR: u16 = 1;
+pub const MAX_DIVISOR: u16 = u16::MAX;
+
+/// Slowest rate the PIT reaches, rounded: 1193182 / 65535 ≈ 18.2 Hz.
+pub const MIN_FREQUENCY_HZ: u32 = 18;
+pub const MAX_FREQUENCY_HZ: u32 = PIT_CLOCK_HZ;
+
+/// Reload value giving the rate closest to `hz`. Requests below the floor,
+/// including 0, get the slowest divisor; requests above the input clock get
+/// divisor 1.
+pub fn divisor_for(hz: u32) -> u16 {
+    if hz == 0 {
+        return MAX_DIVISOR;
+    }
+    let divisor = (PIT_CLOCK_HZ + hz / 2) / hz;
+    divisor.clamp(MIN_DIVISOR as u32, MAX_DIVISOR as u32) as u16
+}
+
+/// Rate, rounded to the nearest hertz, that `divisor` actually produces.
+pub fn frequency_for(divisor: u16) -> u32 {
+    let divisor = divisor.max(MIN_DIVISOR) as u32;
+    (PIT_CLOCK_HZ + divisor / 2) / divisor
+}
@@ -3,12 +3,20 @@
 #[cfg(feature = "std")]
 extern crate std;
 
+pub mod boot;
+pub mod cpu;
 pub mod drivers;
+pub mod interrupts;
 pub mod klog;
 pub mod mem;
 pub mod sync;
 pub mod vfs;
 
+pub mod time {
+    pub mod civil;
+}
+
 pub mod fs {
     pub mod fat;
+    pub mod fat_time;
 }
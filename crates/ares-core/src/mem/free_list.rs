@@ -0,0 +1,279 @@
+#![allow(dead_code)]
+
+//! First-fit free-list allocator over a caller-supplied region. Free regions
+//! are kept sorted by address and coalesced on insert. Nothing here touches
+//! a global, so the kernel heap wraps one in a lock and the host tests drive
+//! one over a plain buffer.
+
+use core::alloc::Layout;
+use core::mem::{align_of, size_of};
+use core::ptr::null_mut;
+
+/// Heap usage at a glance. `peak_used` is the high-water mark since `init`,
+/// and `free_regions` against `largest_free` shows how fragmented the free
+/// space is.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct HeapStats {
+    pub remaining: usize,
+    pub peak_used: usize,
+    pub free_regions: usize,
+    pub largest_free: usize,
+}
+
+struct ListNode {
+    size: usize,
+    next: Option<&'static mut ListNode>,
+}
+
+impl ListNode {
+    const fn new(size: usize) -> Self {
+        Self { size, next: None }
+    }
+
+    fn start_addr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn end_addr(&self) -> usize {
+        self.start_addr() + self.size
+    }
+}
+
+pub struct LinkedListAllocator {
+    head: ListNode,
+    size: usize,
+    /// Bytes on the free list, kept up to date by every call that changes
+    /// the list so `remaining` never has to walk it.
+    free: usize,
+    /// Smallest `free` has been since `init`.
+    low_water: usize,
+}
+
+impl LinkedListAllocator {
+    pub const fn new() -> Self {
+        Self {
+            head: ListNode::new(0),
+            size: 0,
+            free: 0,
+            low_water: 0,
+        }
+    }
+
+    /// # Safety
+    /// `heap_start..heap_start + heap_size` must be writable memory that
+    /// outlives the allocator and is used for nothing else.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.head.next = None;
+        self.free = self.insert_region(heap_start, heap_size);
+        self.size = self.free;
+        self.low_water = self.free;
+        self.check_free_total();
+    }
+
+    fn min_region_size() -> usize {
+        size_of::<ListNode>()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.free
+    }
+
+    /// Free bytes counted the slow way, region by region. Always equal to
+    /// `remaining`; debug builds check that after every change.
+    pub fn recount_free(&self) -> usize {
+        let mut total = 0;
+        let mut current = &self.head;
+        while let Some(node) = current.next.as_deref() {
+            total += node.size;
+            current = node;
+        }
+        total
+    }
+
+    fn check_free_total(&self) {
+        debug_assert_eq!(self.free, self.recount_free(), "free total drifted from the free list");
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        let mut free_regions = 0;
+        let mut largest_free = 0;
+        let mut current = &self.head;
+        while let Some(node) = current.next.as_deref() {
+            free_regions += 1;
+            largest_free = largest_free.max(node.size);
+            current = node;
+        }
+        HeapStats {
+            remaining: self.free,
+            peak_used: self.size - self.low_water,
+            free_regions,
+            largest_free,
+        }
+    }
+
+    /// # Safety
+    /// The allocator must have been initialised over valid memory.
+    pub unsafe fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let size = Self::reserved_size(layout);
+        let align = layout.align().max(align_of::<ListNode>());
+
+        let mut current = &mut self.head;
+        while let Some(region) = current.next.as_mut() {
+            let mut alloc_start = align_up(region.start_addr(), align);
+            // The gap in front goes back on the list, so it must be able to
+            // hold a node; if not, move up to the next aligned address that
+            // leaves room for one.
+            let gap = alloc_start - region.start_addr();
+            if gap != 0 && gap < Self::min_region_size() {
+                alloc_start = align_up(region.start_addr() + Self::min_region_size(), align);
+            }
+            let alloc_end = match alloc_start.checked_add(size) {
+                Some(end) => end,
+                None => return null_mut(),
+            };
+
+            // Likewise a tail too small for a node would be lost for good.
+            let fits = alloc_end <= region.end_addr()
+                && (alloc_end == region.end_addr() || region.end_addr() - alloc_end >= Self::min_region_size());
+            if !fits {
+                current = current.next.as_mut().unwrap();
+                continue;
+            }
+
+            let next = region.next.take();
+            let region_start = region.start_addr();
+            let region_size = region.size;
+
+            current.next = next;
+            self.free -= region_size;
+
+            let excess_before = alloc_start - region_start;
+            if excess_before != 0 {
+                self.free += self.insert_region(region_start, excess_before);
+            }
+
+            let excess_after = region_start + region_size - alloc_end;
+            if excess_after != 0 {
+                self.free += self.insert_region(alloc_end, excess_after);
+            }
+            self.low_water = self.low_water.min(self.free);
+            self.check_free_total();
+            return alloc_start as *mut u8;
+        }
+
+        null_mut()
+    }
+
+    /// # Safety
+    /// `ptr` must have come from `allocate` with the same `layout`.
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        self.free += self.insert_region(ptr as usize, Self::reserved_size(layout));
+        self.check_free_total();
+    }
+
+    /// Bytes `allocate(layout)` sets aside at the pointer it returns. It is
+    /// a whole number of nodes' alignment, so the free space after it starts
+    /// where a node can go and nothing is rounded away.
+    pub fn reserved_size(layout: Layout) -> usize {
+        align_up(layout.size().max(Self::min_region_size()), align_of::<ListNode>())
+    }
+
+    /// Give back `addr..addr + size` without rounding it up, so part of an
+    /// allocation can be freed while the rest stays in use. Pieces too small
+    /// to hold a free-list node are lost until the heap is reset.
+    ///
+    /// # Safety
+    /// The range must lie within one live allocation and never be used again.
+    pub unsafe fn release(&mut self, addr: usize, size: usize) {
+        self.free += self.insert_region(addr, size);
+        self.check_free_total();
+    }
+
+    /// Add a region to the free list and return how many of its bytes were
+    /// kept. Both ends are trimmed to node alignment, and slivers too small
+    /// for a node are dropped.
+    unsafe fn insert_region(&mut self, addr: usize, size: usize) -> usize {
+        let align = align_of::<ListNode>();
+        let start = align_up(addr, align);
+        let end = match addr.checked_add(size) {
+            Some(end) => end & !(align - 1),
+            None => return 0,
+        };
+
+        if start >= end {
+            return 0;
+        }
+
+        let size = end - start;
+        if size < Self::min_region_size() {
+            return 0;
+        }
+
+        let mut current = &mut self.head;
+        while let Some(next) = current.next.as_ref() {
+            if next.start_addr() >= start {
+                break;
+            }
+            current = current.next.as_mut().unwrap();
+        }
+
+        let mut node = ListNode::new(size);
+        node.next = current.next.take();
+
+        let node_ptr = start as *mut ListNode;
+        node_ptr.write(node);
+        current.next = Some(&mut *node_ptr);
+
+        self.merge_with_next(node_ptr);
+        self.merge_with_previous(node_ptr);
+        size
+    }
+
+    unsafe fn merge_with_next(&mut self, node_ptr: *mut ListNode) {
+        let node = &mut *node_ptr;
+        loop {
+            let node_end = node.end_addr();
+            let next = match node.next.as_mut() {
+                Some(next) => next,
+                None => break,
+            };
+
+            if node_end != next.start_addr() {
+                break;
+            }
+
+            let next_next = next.next.take();
+            node.size += next.size;
+            node.next = next_next;
+        }
+    }
+
+    unsafe fn merge_with_previous(&mut self, node_ptr: *mut ListNode) {
+        let mut current = &mut self.head;
+        while let Some(next) = current.next.as_mut() {
+            let next_ptr = &mut **next as *mut ListNode;
+            if next_ptr == node_ptr {
+                if current.size != 0 && current.end_addr() == (*node_ptr).start_addr() {
+                    let node = &mut *node_ptr;
+                    let next_next = node.next.take();
+                    current.size += node.size;
+                    current.next = next_next;
+                    let current_ptr = current as *mut ListNode;
+                    self.merge_with_next(current_ptr);
+                }
+                break;
+            }
+            current = current.next.as_mut().unwrap();
+        }
+    }
+}
+
+impl Default for LinkedListAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
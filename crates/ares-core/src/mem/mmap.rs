@@ -0,0 +1,133 @@
+#![allow(dead_code)]
+
+//! Decoding of the multiboot2 memory map tag (type 6). The tag is read from a
+//! plain byte slice so the parser does not care where the bootloader put it,
+//! and every entry is reported, usable or not.
+
+pub const TAG_HEADER_LEN: usize = 16;
+/// Bytes of an entry the parser reads; `entry_size` may be larger.
+pub const ENTRY_LEN: usize = 24;
+
+pub const MEMORY_TYPE_AVAILABLE: u32 = 1;
+pub const MEMORY_TYPE_RESERVED: u32 = 2;
+pub const MEMORY_TYPE_ACPI_RECLAIMABLE: u32 = 3;
+pub const MEMORY_TYPE_ACPI_NVS: u32 = 4;
+pub const MEMORY_TYPE_BAD: u32 = 5;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MmapEntry {
+    pub base: u64,
+    pub length: u64,
+    pub entry_type: u32,
+}
+
+impl MmapEntry {
+    pub fn is_available(&self) -> bool {
+        self.entry_type == MEMORY_TYPE_AVAILABLE
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self.entry_type {
+            MEMORY_TYPE_AVAILABLE => "available",
+            MEMORY_TYPE_ACPI_RECLAIMABLE => "acpi",
+            MEMORY_TYPE_ACPI_NVS => "acpi-nvs",
+            MEMORY_TYPE_BAD => "bad",
+            _ => "reserved",
+        }
+    }
+}
+
+/// Walk the entries of a memory map tag. `tag` starts at the tag header and
+/// covers at least the tag's `size`; anything past it is ignored. A header
+/// with an entry size too small for an entry yields nothing.
+pub fn entries(tag: &[u8]) -> impl Iterator<Item = MmapEntry> + '_ {
+    let (end, step) = if tag.len() >= TAG_HEADER_LEN && read_u32(tag, 8) as usize >= ENTRY_LEN {
+        ((read_u32(tag, 4) as usize).min(tag.len()), read_u32(tag, 8) as usize)
+    } else {
+        (0, ENTRY_LEN)
+    };
+    let mut offset = TAG_HEADER_LEN;
+    core::iter::from_fn(move || {
+        if end.saturating_sub(offset) < step {
+            return None;
+        }
+        let entry = MmapEntry {
+            base: read_u64(tag, offset),
+            length: read_u64(tag, offset + 8),
+            entry_type: read_u32(tag, offset + 16),
+        };
+        offset += step;
+        Some(entry)
+    })
+}
+
+/// Usable versus installed memory across a set of entries. Zero-length
+/// entries are skipped.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MmapTotals {
+    pub usable_regions: usize,
+    pub usable_bytes: u64,
+    pub reserved_regions: usize,
+    pub reserved_bytes: u64,
+    /// Every region the firmware reported, usable or not.
+    pub total_installed: u64,
+    /// Length of the biggest single usable region.
+    pub largest_contiguous: u64,
+}
+
+impl MmapTotals {
+    pub const fn new() -> Self {
+        Self {
+            usable_regions: 0,
+            usable_bytes: 0,
+            reserved_regions: 0,
+            reserved_bytes: 0,
+            total_installed: 0,
+            largest_contiguous: 0,
+        }
+    }
+
+    pub fn add(&mut self, entry: &MmapEntry) {
+        if entry.length == 0 {
+            return;
+        }
+        self.total_installed = self.total_installed.saturating_add(entry.length);
+        if entry.is_available() {
+            self.usable_regions += 1;
+            self.usable_bytes = self.usable_bytes.saturating_add(entry.length);
+            self.largest_contiguous = self.largest_contiguous.max(entry.length);
+        } else {
+            self.reserved_regions += 1;
+            self.reserved_bytes = self.reserved_bytes.saturating_add(entry.length);
+        }
+    }
+}
+
+impl Default for MmapTotals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn totals<I>(entries: I) -> MmapTotals
+where
+    I: IntoIterator<Item = MmapEntry>,
+{
+    let mut totals = MmapTotals::new();
+    for entry in entries {
+        totals.add(&entry);
+    }
+    totals
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut raw = [0u8; 4];
+    raw.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(raw)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut raw = [0u8; 8];
+    raw.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(raw)
+}
@@ -1,4 +1,7 @@
 #![allow(dead_code)]
 
+pub mod free_list;
 pub mod heap;
+pub mod mmap;
 pub mod paging;
+pub mod region;
@@ -0,0 +1,293 @@
+#![allow(dead_code)]
+
+//! Sorted, non-overlapping list of address ranges, each tagged with
+//! attributes such as kind and permissions. Inserting a range next to one
+//! with equal attributes merges the two, and removing part of a range
+//! splits it, handing every removed piece back so the caller can free or
+//! unmap exactly that memory. The backing array comes from `mem::heap`, so
+//! the list works in the kernel and in the host tests alike.
+
+use core::alloc::Layout;
+use core::{ptr, slice};
+
+use crate::mem::heap;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Region<T> {
+    pub start: usize,
+    /// One past the last byte.
+    pub end: usize,
+    pub attrs: T,
+}
+
+impl<T> Region<T> {
+    pub const fn new(start: usize, len: usize, attrs: T) -> Self {
+        Self {
+            start,
+            end: start.wrapping_add(len),
+            attrs,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        self.start <= addr && addr < self.end
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RegionError {
+    /// Zero-length or wrapping range.
+    Empty,
+    /// The new range overlaps one already in the list.
+    Overlap,
+    /// Nothing in the list intersects the range.
+    NotMapped,
+    /// The backing array could not grow.
+    NoMemory,
+}
+
+pub struct RegionList<T> {
+    regions: *mut Region<T>,
+    len: usize,
+    capacity: usize,
+}
+
+impl<T: Copy + PartialEq> RegionList<T> {
+    pub const fn new() -> Self {
+        Self {
+            regions: ptr::null_mut(),
+            len: 0,
+            capacity: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Region<T>> {
+        self.as_slice().iter()
+    }
+
+    pub fn as_slice(&self) -> &[Region<T>] {
+        if self.len == 0 {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.regions, self.len) }
+        }
+    }
+
+    pub fn find(&self, addr: usize) -> Option<&Region<T>> {
+        self.iter().find(|region| region.contains(addr))
+    }
+
+    /// Add `region`, merging it with a neighbour that ends where it starts
+    /// (or starts where it ends) and has equal attributes.
+    pub fn insert(&mut self, region: Region<T>) -> Result<(), RegionError> {
+        if region.is_empty() {
+            return Err(RegionError::Empty);
+        }
+        let index = self.as_slice().partition_point(|existing| existing.start < region.start);
+        let before = index.checked_sub(1).map(|i| self.as_slice()[i]);
+        let after = self.as_slice().get(index).copied();
+        if before.is_some_and(|b| b.end > region.start) || after.is_some_and(|a| a.start < region.end) {
+            return Err(RegionError::Overlap);
+        }
+
+        let joins_before = before.is_some_and(|b| b.end == region.start && b.attrs == region.attrs);
+        let joins_after = after.is_some_and(|a| a.start == region.end && a.attrs == region.attrs);
+        match (joins_before, joins_after) {
+            (true, true) => {
+                let end = self.as_slice()[index].end;
+                self.as_slice_mut()[index - 1].end = end;
+                self.remove_at(index);
+            }
+            (true, false) => self.as_slice_mut()[index - 1].end = region.end,
+            (false, true) => self.as_slice_mut()[index].start = region.start,
+            (false, false) => self.insert_at(index, region)?,
+        }
+        Ok(())
+    }
+
+    /// Take `[start, end)` out of the list, trimming or splitting any region
+    /// it cuts through. `removed` sees each piece taken out, in address
+    /// order, with the attributes of the region it came from. Gaps inside
+    /// the range are skipped; a range that touches nothing is `NotMapped`.
+    pub fn remove(
+        &mut self,
+        start: usize,
+        end: usize,
+        mut removed: impl FnMut(Region<T>),
+    ) -> Result<usize, RegionError> {
+        if end <= start {
+            return Err(RegionError::Empty);
+        }
+        // A split adds an entry, so make room before anything changes.
+        self.ensure_capacity(1)?;
+
+        let mut total = 0;
+        let mut index = self.as_slice().partition_point(|region| region.end <= start);
+        while index < self.len {
+            let region = self.as_slice()[index];
+            if region.start >= end {
+                break;
+            }
+            let cut_start = region.start.max(start);
+            let cut_end = region.end.min(end);
+            removed(Region {
+                start: cut_start,
+                end: cut_end,
+                attrs: region.attrs,
+            });
+            total += cut_end - cut_start;
+
+            let keep_front = region.start < cut_start;
+            let keep_back = cut_end < region.end;
+            match (keep_front, keep_back) {
+                (true, true) => {
+                    self.as_slice_mut()[index].end = cut_start;
+                    self.insert_at(
+                        index + 1,
+                        Region {
+                            start: cut_end,
+                            end: region.end,
+                            attrs: region.attrs,
+                        },
+                    )?;
+                    break;
+                }
+                (true, false) => {
+                    self.as_slice_mut()[index].end = cut_start;
+                    index += 1;
+                }
+                (false, true) => {
+                    self.as_slice_mut()[index].start = cut_end;
+                    break;
+                }
+                (false, false) => self.remove_at(index),
+            }
+        }
+
+        if total == 0 {
+            return Err(RegionError::NotMapped);
+        }
+        Ok(total)
+    }
+
+    /// Empty the list, yielding the regions from the highest address down.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain { list: self }
+    }
+
+    fn as_slice_mut(&mut self) -> &mut [Region<T>] {
+        if self.len == 0 {
+            &mut []
+        } else {
+            unsafe { slice::from_raw_parts_mut(self.regions, self.len) }
+        }
+    }
+
+    fn insert_at(&mut self, index: usize, region: Region<T>) -> Result<(), RegionError> {
+        self.ensure_capacity(1)?;
+        unsafe {
+            let slot = self.regions.add(index);
+            ptr::copy(slot, slot.add(1), self.len - index);
+            slot.write(region);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    fn remove_at(&mut self, index: usize) {
+        unsafe {
+            let slot = self.regions.add(index);
+            ptr::copy(slot.add(1), slot, self.len - index - 1);
+        }
+        self.len -= 1;
+    }
+
+    fn ensure_capacity(&mut self, additional: usize) -> Result<(), RegionError> {
+        let required = self.len.checked_add(additional).ok_or(RegionError::NoMemory)?;
+        if required <= self.capacity {
+            return Ok(());
+        }
+
+        let mut new_capacity = if self.capacity == 0 { 4 } else { self.capacity };
+        while new_capacity < required {
+            new_capacity = new_capacity.checked_mul(2).ok_or(RegionError::NoMemory)?;
+        }
+
+        let layout = Layout::array::<Region<T>>(new_capacity).map_err(|_| RegionError::NoMemory)?;
+        let new_ptr = unsafe { heap::allocate(layout) } as *mut Region<T>;
+        if new_ptr.is_null() {
+            return Err(RegionError::NoMemory);
+        }
+
+        unsafe {
+            if self.len > 0 {
+                ptr::copy(self.regions, new_ptr, self.len);
+            }
+        }
+
+        if self.capacity != 0 {
+            if let Ok(old_layout) = Layout::array::<Region<T>>(self.capacity) {
+                unsafe {
+                    heap::deallocate(self.regions as *mut u8, old_layout);
+                }
+            }
+        }
+
+        self.regions = new_ptr;
+        self.capacity = new_capacity;
+        Ok(())
+    }
+}
+
+impl<T: Copy + PartialEq> Default for RegionList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for RegionList<T> {
+    fn drop(&mut self) {
+        if self.capacity != 0 && !self.regions.is_null() {
+            if let Ok(layout) = Layout::array::<Region<T>>(self.capacity) {
+                unsafe {
+                    heap::deallocate(self.regions as *mut u8, layout);
+                }
+            }
+        }
+        self.regions = ptr::null_mut();
+        self.len = 0;
+        self.capacity = 0;
+    }
+}
+
+pub struct Drain<'a, T> {
+    list: &'a mut RegionList<T>,
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = Region<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.list.len == 0 {
+            return None;
+        }
+        self.list.len -= 1;
+        unsafe { Some(self.list.regions.add(self.list.len).read()) }
+    }
+}
@@ -1 +1,2 @@
 pub mod spinlock;
+pub mod ticket;
@@ -0,0 +1,85 @@
+#![allow(dead_code)]
+
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Fair spinlock: each caller takes a ticket and waits for it to be served,
+/// so the lock is granted in the order it was requested. Same interface as
+/// `SpinLock`.
+pub struct TicketLock<T> {
+    next_ticket: AtomicU32,
+    now_serving: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for TicketLock<T> {}
+unsafe impl<T: Send> Sync for TicketLock<T> {}
+
+impl<T> TicketLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            next_ticket: AtomicU32::new(0),
+            now_serving: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> TicketLockGuard<'_, T> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            spin_loop();
+        }
+
+        TicketLockGuard { lock: self }
+    }
+
+    pub fn try_lock(&self) -> Option<TicketLockGuard<'_, T>> {
+        let serving = self.now_serving.load(Ordering::Relaxed);
+        self.next_ticket
+            .compare_exchange(serving, serving.wrapping_add(1), Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| TicketLockGuard { lock: self })
+    }
+
+    /// Tickets handed out but not yet released: the holder plus everyone
+    /// spinning behind it.
+    pub fn queued(&self) -> u32 {
+        let next = self.next_ticket.load(Ordering::Relaxed);
+        next.wrapping_sub(self.now_serving.load(Ordering::Relaxed))
+    }
+}
+
+pub struct TicketLockGuard<'a, T> {
+    lock: &'a TicketLock<T>,
+}
+
+impl<'a, T> TicketLockGuard<'a, T> {
+    /// The lock this guard holds, so a caller that has to drop the guard can
+    /// take the same lock again later.
+    pub fn ticketlock(guard: &Self) -> &'a TicketLock<T> {
+        guard.lock
+    }
+}
+
+impl<T> Drop for TicketLockGuard<'_, T> {
+    fn drop(&mut self) {
+        // Only the holder writes now_serving, so a plain increment is safe.
+        let next = self.lock.now_serving.load(Ordering::Relaxed).wrapping_add(1);
+        self.lock.now_serving.store(next, Ordering::Release);
+    }
+}
+
+impl<T> core::ops::Deref for TicketLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> core::ops::DerefMut for TicketLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
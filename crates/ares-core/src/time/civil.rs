@@ -0,0 +1,96 @@
+#![allow(dead_code)]
+
+//! Calendar arithmetic for UTC wall time: a broken-down `DateTime` and its
+//! conversion to and from Unix seconds. The RTC hands back a `DateTime` and
+//! FAT's packed stamps convert through one, so neither carries its own
+//! copy of the day counting.
+
+/// The Unix epoch's year; `to_unix` has nothing to count before it.
+pub const UNIX_EPOCH_YEAR: u16 = 1970;
+
+pub const SECS_PER_DAY: u64 = 86_400;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// A real date from 1970 on, with no leap second.
+    pub fn is_valid(&self) -> bool {
+        self.year >= UNIX_EPOCH_YEAR
+            && (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+
+    /// Seconds since 1970-01-01 00:00:00. Only meaningful for a valid date.
+    pub fn to_unix(&self) -> u64 {
+        let days = days_from_civil(self.year as u64, self.month as u64, self.day as u64);
+        days * SECS_PER_DAY + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64
+    }
+
+    /// `None` once the year no longer fits in a `u16`.
+    pub fn from_unix(secs: u64) -> Option<Self> {
+        let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+        if year > u16::MAX as u64 {
+            return None;
+        }
+        let within = secs % SECS_PER_DAY;
+        Some(Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (within / 3600) as u8,
+            minute: (within / 60 % 60) as u8,
+            second: (within % 60) as u8,
+        })
+    }
+}
+
+pub fn is_leap(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Proleptic Gregorian day counts relative to 1970-01-01, using March-based
+// years so the leap day falls at the end. Only years from 1970 on reach
+// here, so nothing goes negative.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
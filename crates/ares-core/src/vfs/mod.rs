@@ -9,6 +9,16 @@ pub enum VfsError {
     Io,
     Unsupported,
     InvalidOffset,
+    /// The path does not name an existing file or directory.
+    NotFound,
+    /// The file exists but the operation is not allowed on it.
+    PermissionDenied,
+    /// The backing store has no room left for the data.
+    NoSpace,
+    /// Following symlinks did not reach a real path.
+    TooManyLinks,
+    /// The path is relative, climbs above `/`, or is too long.
+    InvalidPath,
 }
 
 impl From<DriverError> for VfsError {
@@ -17,6 +27,88 @@ impl From<DriverError> for VfsError {
     }
 }
 
+/// Longest entry name a directory listing will report.
+pub const DIR_NAME_MAX: usize = 255;
+
+/// Attribute bits carried by `VfsDirEntry::attr`; values follow FAT.
+pub mod attr {
+    #![allow(dead_code)]
+
+    pub const READ_ONLY: u8 = 0x01;
+    pub const HIDDEN: u8 = 0x02;
+    pub const SYSTEM: u8 = 0x04;
+    pub const DIRECTORY: u8 = 0x10;
+    pub const ARCHIVE: u8 = 0x20;
+}
+
+/// One entry produced while enumerating a directory.
+#[derive(Copy, Clone)]
+pub struct VfsDirEntry {
+    name: [u8; DIR_NAME_MAX],
+    name_len: u8,
+    pub attr: u8,
+    pub size: u64,
+}
+
+impl VfsDirEntry {
+    pub fn new(name: &[u8], attr: u8, size: u64) -> Self {
+        let len = name.len().min(DIR_NAME_MAX);
+        let mut buf = [0u8; DIR_NAME_MAX];
+        buf[..len].copy_from_slice(&name[..len]);
+        Self {
+            name: buf,
+            name_len: len as u8,
+            attr,
+            size,
+        }
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_len as usize]
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attr & attr::DIRECTORY != 0
+    }
+}
+
+/// `VfsFileStat::mode` bits. The type occupies the top nibble and the low
+/// nine bits are rwx permissions, as in POSIX `st_mode`.
+pub mod mode {
+    #![allow(dead_code)]
+
+    pub const TYPE_MASK: u32 = 0o170000;
+    pub const FILE: u32 = 0o100000;
+    pub const DIR: u32 = 0o040000;
+    pub const CHAR: u32 = 0o020000;
+
+    pub const READ: u32 = 0o444;
+    pub const WRITE: u32 = 0o222;
+    pub const EXEC: u32 = 0o111;
+}
+
+/// Metadata reported by `VfsFile::stat`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct VfsFileStat {
+    pub size: u64,
+    pub mode: u32,
+    pub block_size: u32,
+    /// Unix seconds, or 0 where the filesystem keeps no such time.
+    pub created: u64,
+    pub modified: u64,
+    pub accessed: u64,
+}
+
+impl VfsFileStat {
+    pub fn is_dir(&self) -> bool {
+        self.mode & mode::TYPE_MASK == mode::DIR
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.mode & mode::WRITE == 0
+    }
+}
+
 /// Behaviour common to readable/writable file-like objects in the kernel.
 pub trait VfsFile: Sync {
     fn name(&self) -> &'static str;
@@ -27,7 +119,67 @@ pub trait VfsFile: Sync {
 
     fn flush(&self) -> VfsResult<()>;
 
+    /// Whether writes are held that `flush` has yet to push out.
+    fn is_dirty(&self) -> bool {
+        false
+    }
+
     fn size(&self) -> VfsResult<u64>;
+
+    /// Set the file's length, dropping bytes past `len` or zero-filling up
+    /// to it.
+    fn truncate(&self, _len: u64) -> VfsResult<()> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn is_dir(&self) -> bool {
+        false
+    }
+
+    /// The file's `attr` bits. Opens check `attr::READ_ONLY` against the
+    /// caller's credentials; files without attributes report none.
+    fn attributes(&self) -> u8 {
+        0
+    }
+
+    /// Readiness for `poll`. Files backed by memory or disk never make a
+    /// reader or writer sleep, so the defaults say ready.
+    fn can_read(&self) -> bool {
+        true
+    }
+
+    fn can_write(&self) -> bool {
+        true
+    }
+
+    fn stat(&self) -> VfsResult<VfsFileStat> {
+        let mode = if self.is_dir() {
+            mode::DIR | mode::READ | mode::EXEC
+        } else {
+            mode::FILE | mode::READ | mode::WRITE
+        };
+        Ok(VfsFileStat {
+            size: self.size()?,
+            mode,
+            block_size: 512,
+            created: 0,
+            modified: 0,
+            accessed: 0,
+        })
+    }
+
+    /// Return the first entry at or after `cursor` along with the cursor of
+    /// the entry following it, or `None` once the directory is exhausted.
+    /// Cursors are opaque to callers; `0` always means "from the start".
+    fn read_dir(&self, _cursor: u64) -> VfsResult<Option<(VfsDirEntry, u64)>> {
+        Err(VfsError::Unsupported)
+    }
 }
 
 pub mod ata;
+pub mod handle;
+pub mod mount;
+pub mod path;
+pub mod symlink;
+
+pub use self::path::normalize_path;
@@ -0,0 +1,103 @@
+#![allow(dead_code)]
+
+//! Lexical path cleanup. `normalize_path` turns `/fat/SUB/../HELLO.TXT` into
+//! `/fat/HELLO.TXT` without asking any filesystem, so mount and symlink
+//! lookups only ever see one spelling of a path.
+
+use super::{VfsError, VfsResult};
+
+/// Longest normalized path, including the leading `/`.
+pub const MAX_PATH: usize = 256;
+
+/// A normalized absolute path: one leading `/`, no empty, `.` or `..`
+/// components, and no trailing `/` except for the root itself.
+#[derive(Clone)]
+pub struct NormalPath {
+    buf: [u8; MAX_PATH],
+    len: usize,
+}
+
+impl NormalPath {
+    const fn root() -> Self {
+        let mut buf = [0u8; MAX_PATH];
+        buf[0] = b'/';
+        Self { buf, len: 1 }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only whole `&str` components and `/` are ever copied in.
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("/")
+    }
+
+    fn push(&mut self, component: &str) -> VfsResult<()> {
+        let sep = if self.len == 1 { 0 } else { 1 };
+        let end = self.len + sep + component.len();
+        if end > MAX_PATH {
+            return Err(VfsError::InvalidPath);
+        }
+        if sep == 1 {
+            self.buf[self.len] = b'/';
+        }
+        self.buf[self.len + sep..end].copy_from_slice(component.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+
+    /// Drop the last component, returning false at the root.
+    fn pop(&mut self) -> bool {
+        if self.len == 1 {
+            return false;
+        }
+        let cut = self.buf[..self.len].iter().rposition(|&b| b == b'/').unwrap_or(0);
+        self.len = cut.max(1);
+        true
+    }
+}
+
+impl core::ops::Deref for NormalPath {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl core::fmt::Debug for NormalPath {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl PartialEq<str> for NormalPath {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for NormalPath {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// Collapse `.`, `..` and repeated or trailing slashes in an absolute path.
+/// Relative paths and `..` above the root are `InvalidPath`; there is no
+/// working directory to resolve against.
+pub fn normalize_path(path: &str) -> VfsResult<NormalPath> {
+    if !path.starts_with('/') {
+        return Err(VfsError::InvalidPath);
+    }
+    let mut normal = NormalPath::root();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if !normal.pop() {
+                    return Err(VfsError::InvalidPath);
+                }
+            }
+            name => normal.push(name)?,
+        }
+    }
+    Ok(normal)
+}
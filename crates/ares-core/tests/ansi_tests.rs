@@ -0,0 +1,82 @@
+use ares_core::drivers::ansi::{AnsiAction, AnsiParser};
+
+const DEFAULT_ATTR: u8 = 0x0F;
+
+fn feed_all(parser: &mut AnsiParser, bytes: &[u8], attr: u8) -> Vec<AnsiAction> {
+    bytes
+        .iter()
+        .map(|&byte| parser.feed(byte, attr))
+        .filter(|action| *action != AnsiAction::None)
+        .collect()
+}
+
+#[test]
+fn plain_text_passes_through() {
+    let mut parser = AnsiParser::new(DEFAULT_ATTR);
+    let actions = feed_all(&mut parser, b"hi\n", DEFAULT_ATTR);
+    assert_eq!(
+        actions,
+        vec![AnsiAction::Print(b'h'), AnsiAction::Print(b'i'), AnsiAction::Print(b'\n')]
+    );
+}
+
+#[test]
+fn sgr_foreground_and_background_map_to_vga() {
+    let mut parser = AnsiParser::new(DEFAULT_ATTR);
+    // ANSI red foreground is VGA 4, blue background is VGA 1.
+    assert_eq!(feed_all(&mut parser, b"\x1b[31m", DEFAULT_ATTR), vec![AnsiAction::SetAttr(0x04)]);
+    assert_eq!(feed_all(&mut parser, b"\x1b[44m", 0x04), vec![AnsiAction::SetAttr(0x14)]);
+    assert_eq!(feed_all(&mut parser, b"\x1b[33;42m", DEFAULT_ATTR), vec![AnsiAction::SetAttr(0x26)]);
+}
+
+#[test]
+fn sgr_reset_restores_default() {
+    let mut parser = AnsiParser::new(DEFAULT_ATTR);
+    assert_eq!(feed_all(&mut parser, b"\x1b[0m", 0x14), vec![AnsiAction::SetAttr(DEFAULT_ATTR)]);
+    assert_eq!(feed_all(&mut parser, b"\x1b[m", 0x14), vec![AnsiAction::SetAttr(DEFAULT_ATTR)]);
+}
+
+#[test]
+fn cursor_position_is_zero_based() {
+    let mut parser = AnsiParser::new(DEFAULT_ATTR);
+    assert_eq!(
+        feed_all(&mut parser, b"\x1b[5;10H", DEFAULT_ATTR),
+        vec![AnsiAction::CursorTo { row: 4, col: 9 }]
+    );
+    assert_eq!(
+        feed_all(&mut parser, b"\x1b[H", DEFAULT_ATTR),
+        vec![AnsiAction::CursorTo { row: 0, col: 0 }]
+    );
+}
+
+#[test]
+fn relative_cursor_moves_default_to_one() {
+    let mut parser = AnsiParser::new(DEFAULT_ATTR);
+    let actions = feed_all(&mut parser, b"\x1b[A\x1b[3B\x1b[2C\x1b[0D", DEFAULT_ATTR);
+    assert_eq!(
+        actions,
+        vec![
+            AnsiAction::CursorUp(1),
+            AnsiAction::CursorDown(3),
+            AnsiAction::CursorForward(2),
+            AnsiAction::CursorBack(1),
+        ]
+    );
+}
+
+#[test]
+fn unknown_sequences_are_consumed() {
+    let mut parser = AnsiParser::new(DEFAULT_ATTR);
+    let actions = feed_all(&mut parser, b"\x1b[1J\x1b[?25lx\x1b7y", DEFAULT_ATTR);
+    assert_eq!(actions, vec![AnsiAction::Print(b'x'), AnsiAction::Print(b'y')]);
+}
+
+#[test]
+fn erase_display_clears_whole_screen_only() {
+    let mut parser = AnsiParser::new(DEFAULT_ATTR);
+    let actions = feed_all(&mut parser, b"\x1b[2J\x1b[H\x1b[J\x1b[0J", DEFAULT_ATTR);
+    assert_eq!(
+        actions,
+        vec![AnsiAction::ClearScreen, AnsiAction::CursorTo { row: 0, col: 0 }]
+    );
+}
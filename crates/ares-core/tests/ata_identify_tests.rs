@@ -0,0 +1,72 @@
+use ares_core::drivers::ata_identify::{AtaGeometry, IDENTIFY_WORDS};
+
+/// A blank identify block with `model` in words 27-46, stored the way a
+/// drive sends it: two characters per word, first in the high byte.
+fn identify_with_model(model: &str) -> [u16; IDENTIFY_WORDS] {
+    let mut words = [0u16; IDENTIFY_WORDS];
+    let mut padded = [b' '; 40];
+    padded[..model.len()].copy_from_slice(model.as_bytes());
+    for (index, pair) in padded.chunks(2).enumerate() {
+        words[27 + index] = u16::from_be_bytes([pair[0], pair[1]]);
+    }
+    words
+}
+
+#[test]
+fn lba28_only_drive() {
+    let mut words = identify_with_model("QEMU HARDDISK");
+    // 0x0003_F000 sectors, low word first.
+    words[60] = 0xF000;
+    words[61] = 0x0003;
+    let geometry = AtaGeometry::parse(&words);
+    assert_eq!(geometry.lba28_sectors, 0x0003_F000);
+    assert_eq!(geometry.lba48_sectors, 0);
+    assert_eq!(geometry.sectors(), 0x0003_F000);
+    assert_eq!(geometry.model(), "QEMU HARDDISK");
+}
+
+#[test]
+fn lba48_count_wins_when_advertised() {
+    let mut words = identify_with_model("BIG DISK");
+    words[60] = 0xFFFF;
+    words[61] = 0x0FFF;
+    words[83] = 1 << 10;
+    // 0x0000_0001_2345_6789 sectors across words 100-103.
+    words[100] = 0x6789;
+    words[101] = 0x2345;
+    words[102] = 0x0001;
+    words[103] = 0x0000;
+    let geometry = AtaGeometry::parse(&words);
+    assert_eq!(geometry.lba48_sectors, 0x1_2345_6789);
+    assert_eq!(geometry.sectors(), 0x1_2345_6789);
+}
+
+#[test]
+fn lba48_words_ignored_without_feature_bit() {
+    let mut words = identify_with_model("OLD DISK");
+    words[60] = 100;
+    words[100] = 0xFFFF;
+    let geometry = AtaGeometry::parse(&words);
+    assert_eq!(geometry.lba48_sectors, 0);
+    assert_eq!(geometry.sectors(), 100);
+}
+
+#[test]
+fn model_is_byte_swapped_and_trimmed() {
+    // Odd length, so the last word holds one character and a pad space.
+    let words = identify_with_model("WDC WD5000AAKX-001CA0");
+    assert_eq!(AtaGeometry::parse(&words).model(), "WDC WD5000AAKX-001CA0");
+
+    let full = identify_with_model("ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789abcd");
+    assert_eq!(AtaGeometry::parse(&full).model(), "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789abcd");
+
+    let blank = [0u16; IDENTIFY_WORDS];
+    assert_eq!(AtaGeometry::parse(&blank).model(), "");
+}
+
+#[test]
+fn non_ascii_model_is_replaced() {
+    let mut words = identify_with_model("DISK");
+    words[27] = 0xFFFE;
+    assert_eq!(AtaGeometry::parse(&words).model(), "?");
+}
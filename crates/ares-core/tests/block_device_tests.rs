@@ -0,0 +1,74 @@
+use ares_core::drivers::mock::MemBlockDevice;
+use ares_core::drivers::{check_block_range, BlockDevice, Driver, DriverError, DriverKind};
+
+const BLOCK: usize = 512;
+
+/// A device that never learns its size, to exercise the trait default.
+struct Unsized;
+
+impl Driver for Unsized {
+    fn name(&self) -> &'static str {
+        "unsized"
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Block
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+impl BlockDevice for Unsized {
+    fn block_size(&self) -> usize {
+        BLOCK
+    }
+
+    fn read_blocks(&self, _lba: u64, _buf: &mut [u8]) -> Result<(), DriverError> {
+        Ok(())
+    }
+
+    fn write_blocks(&self, _lba: u64, _buf: &[u8]) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn capacity_defaults_to_unknown() {
+    assert_eq!(Unsized.capacity_sectors(), u64::MAX);
+    assert_eq!(check_block_range(&Unsized, 1 << 40, BLOCK * 4), Ok(4));
+    // Even an unknown size cannot wrap around.
+    assert_eq!(check_block_range(&Unsized, u64::MAX, BLOCK), Err(DriverError::IoError));
+}
+
+#[test]
+fn mem_device_reports_its_size() {
+    let device = MemBlockDevice::new("mem", vec![0u8; BLOCK * 8], BLOCK);
+    assert_eq!(device.capacity_sectors(), 8);
+}
+
+#[test]
+fn range_check_edges() {
+    let device = MemBlockDevice::new("mem", vec![0u8; BLOCK * 8], BLOCK);
+    assert_eq!(check_block_range(&device, 0, BLOCK * 8), Ok(8));
+    assert_eq!(check_block_range(&device, 7, BLOCK), Ok(1));
+    assert_eq!(check_block_range(&device, 8, 0), Ok(0));
+    assert_eq!(check_block_range(&device, 8, BLOCK), Err(DriverError::IoError));
+    assert_eq!(check_block_range(&device, 7, BLOCK * 2), Err(DriverError::IoError));
+    assert_eq!(check_block_range(&device, 0, BLOCK + 1), Err(DriverError::Unsupported));
+}
+
+#[test]
+fn over_range_requests_fail_without_touching_storage() {
+    let device = MemBlockDevice::new("mem", vec![0x5A; BLOCK * 4], BLOCK);
+    let mut buf = [0u8; BLOCK * 2];
+    assert_eq!(device.read_blocks(3, &mut buf), Err(DriverError::IoError));
+    assert!(buf.iter().all(|&b| b == 0), "failed read must not fill the buffer");
+
+    assert_eq!(device.write_blocks(4, &[0xFF; BLOCK]), Err(DriverError::IoError));
+    assert_eq!(device.write_blocks(u64::MAX, &[0xFF; BLOCK]), Err(DriverError::IoError));
+    let mut all = [0u8; BLOCK * 4];
+    device.read_blocks(0, &mut all).unwrap();
+    assert!(all.iter().all(|&b| b == 0x5A));
+}
@@ -0,0 +1,108 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use ares_core::drivers::byte_ring::ByteRing;
+
+#[test]
+fn fifo_order_and_len() {
+    let ring: ByteRing<4> = ByteRing::new();
+    assert!(ring.is_empty());
+    assert!(ring.push(1) && ring.push(2) && ring.push(3));
+    assert_eq!(ring.len(), 3);
+    assert_eq!(ring.pop(), Some(1));
+    assert_eq!(ring.pop(), Some(2));
+    assert_eq!(ring.pop(), Some(3));
+    assert_eq!(ring.pop(), None);
+}
+
+#[test]
+fn full_ring_drops_the_oldest() {
+    let ring: ByteRing<4> = ByteRing::new();
+    for byte in 0..4 {
+        assert!(ring.push(byte));
+    }
+    assert!(!ring.push(4));
+    assert!(!ring.push(5));
+    assert_eq!(ring.len(), 4);
+    let drained: Vec<u8> = std::iter::from_fn(|| ring.pop()).collect();
+    assert_eq!(drained, [2, 3, 4, 5]);
+}
+
+#[test]
+fn concurrent_push_and_pop_lose_nothing() {
+    const COUNT: usize = 200_000;
+    let ring: Arc<ByteRing<16>> = Arc::new(ByteRing::new());
+    let done = Arc::new(AtomicBool::new(false));
+
+    let consumer = {
+        let ring = Arc::clone(&ring);
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            let mut expected = 0usize;
+            while expected < COUNT {
+                match ring.pop() {
+                    Some(byte) => {
+                        assert_eq!(byte, expected as u8, "byte {} lost or duplicated", expected);
+                        expected += 1;
+                    }
+                    None if done.load(Ordering::Acquire) && ring.is_empty() => break,
+                    None => thread::yield_now(),
+                }
+            }
+            expected
+        })
+    };
+
+    // Never let the ring fill, so nothing may be dropped.
+    for i in 0..COUNT {
+        while ring.len() == ring.capacity() {
+            thread::yield_now();
+        }
+        assert!(ring.push(i as u8));
+    }
+    done.store(true, Ordering::Release);
+
+    assert_eq!(consumer.join().unwrap(), COUNT);
+    assert!(ring.is_empty());
+}
+
+#[test]
+fn overflowing_writer_and_two_readers_account_for_every_byte() {
+    const COUNT: usize = 200_000;
+    let ring: Arc<ByteRing<8>> = Arc::new(ByteRing::new());
+    let done = Arc::new(AtomicBool::new(false));
+
+    // The writer never waits, so bytes get dropped. Every byte pushed must
+    // be read exactly once, dropped, or still queued.
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let ring = Arc::clone(&ring);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                let mut seen = 0;
+                loop {
+                    match ring.pop() {
+                        Some(_) => seen += 1,
+                        None if done.load(Ordering::Acquire) => return seen,
+                        None => thread::yield_now(),
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let mut dropped = 0;
+    for i in 0..COUNT {
+        if !ring.push(i as u8) {
+            dropped += 1;
+        }
+    }
+    done.store(true, Ordering::Release);
+
+    let mut total = 0;
+    for reader in readers {
+        total += reader.join().unwrap();
+    }
+    assert_eq!(total + ring.len() + dropped, COUNT);
+}
@@ -0,0 +1,131 @@
+use std::sync::Mutex;
+
+use ares_core::drivers::mock::MemCharDevice;
+use ares_core::drivers::{write_all, CharDevice, Driver, DriverError, DriverKind, ATOMIC_WRITE_MAX};
+use ares_core::vfs::handle::SeekFrom;
+
+fn as_char(dev: &MemCharDevice) -> &dyn CharDevice {
+    dev
+}
+
+#[test]
+fn read_drains_seeded_input() {
+    let dev = MemCharDevice::new("tty0");
+    assert_eq!(dev.kind(), DriverKind::Char);
+    dev.push_input(b"hello");
+
+    let chars = as_char(&dev);
+    let mut buf = [0u8; 3];
+    assert_eq!(chars.read(&mut buf).unwrap(), 3);
+    assert_eq!(&buf, b"hel");
+    assert_eq!(dev.pending_input(), 2);
+
+    assert_eq!(chars.read(&mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"lo");
+    assert_eq!(chars.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn write_appends_to_output_log() {
+    let dev = MemCharDevice::new("tty0");
+    let chars = as_char(&dev);
+    assert_eq!(chars.write(b"ab").unwrap(), 2);
+    assert_eq!(chars.write(b"cd").unwrap(), 2);
+    assert_eq!(dev.output(), b"abcd");
+    assert_eq!(dev.take_output(), b"abcd");
+    assert!(dev.output().is_empty());
+}
+
+#[test]
+fn echo_roundtrip_through_trait() {
+    let dev = MemCharDevice::new("tty0");
+    dev.push_input(b"ping\n");
+
+    let chars = as_char(&dev);
+    let mut buf = [0u8; 16];
+    let count = chars.read(&mut buf).unwrap();
+    chars.write(&buf[..count]).unwrap();
+    assert_eq!(dev.output(), b"ping\n");
+    assert_eq!(dev.pending_input(), 0);
+}
+
+#[test]
+fn streams_are_not_seekable() {
+    let dev = MemCharDevice::new("tty0");
+    assert_eq!(as_char(&dev).seek(SeekFrom::Start(0)), Err(DriverError::Unsupported));
+}
+
+/// Takes at most `limit` bytes per call and remembers each call's length.
+struct ShortWriter {
+    limit: usize,
+    calls: Mutex<Vec<usize>>,
+    out: Mutex<Vec<u8>>,
+}
+
+impl ShortWriter {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            calls: Mutex::new(Vec::new()),
+            out: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Driver for ShortWriter {
+    fn name(&self) -> &'static str {
+        "short"
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Char
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+impl CharDevice for ShortWriter {
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, DriverError> {
+        Ok(0)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, DriverError> {
+        self.calls.lock().unwrap().push(buf.len());
+        let count = buf.len().min(self.limit);
+        self.out.lock().unwrap().extend_from_slice(&buf[..count]);
+        Ok(count)
+    }
+}
+
+#[test]
+fn write_all_splits_at_the_atomic_limit() {
+    let data: Vec<u8> = (0..ATOMIC_WRITE_MAX * 2 + 7).map(|i| i as u8).collect();
+    let dev = ShortWriter::new(usize::MAX);
+    assert_eq!(write_all(&dev, &data).unwrap(), data.len());
+    assert_eq!(*dev.calls.lock().unwrap(), vec![ATOMIC_WRITE_MAX, ATOMIC_WRITE_MAX, 7]);
+    assert_eq!(*dev.out.lock().unwrap(), data);
+}
+
+#[test]
+fn write_all_continues_short_writes() {
+    let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    let dev = ShortWriter::new(128);
+    assert_eq!(write_all(&dev, &data).unwrap(), data.len());
+    assert_eq!(*dev.calls.lock().unwrap(), vec![300, 172, 44]);
+    assert_eq!(*dev.out.lock().unwrap(), data);
+}
+
+#[test]
+fn write_all_stops_when_the_device_does() {
+    let dev = ShortWriter::new(0);
+    assert_eq!(write_all(&dev, b"lost").unwrap(), 0);
+    assert_eq!(*dev.calls.lock().unwrap(), vec![4]);
+}
+
+#[test]
+fn flush_defaults_to_nothing_pending() {
+    let dev = MemCharDevice::new("tty0");
+    assert_eq!(as_char(&dev).flush(), Ok(()));
+}
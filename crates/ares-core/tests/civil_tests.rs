@@ -0,0 +1,54 @@
+use ares_core::time::civil::{days_in_month, is_leap, DateTime};
+
+fn date(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+    DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+    }
+}
+
+#[test]
+fn epoch_is_1970() {
+    assert_eq!(date(1970, 1, 1, 0, 0, 0).to_unix(), 0);
+    assert_eq!(DateTime::from_unix(0), Some(date(1970, 1, 1, 0, 0, 0)));
+}
+
+#[test]
+fn unix_round_trips_known_dates() {
+    for (stamp, unix) in [
+        (date(2000, 2, 29, 0, 0, 0), 951_782_400),
+        (date(2001, 9, 9, 1, 46, 40), 1_000_000_000),
+        (date(2038, 1, 19, 3, 14, 8), 2_147_483_648),
+    ] {
+        assert_eq!(stamp.to_unix(), unix);
+        assert_eq!(DateTime::from_unix(unix), Some(stamp));
+    }
+}
+
+#[test]
+fn leap_years_follow_the_gregorian_rules() {
+    assert!(is_leap(2024) && is_leap(2000));
+    assert!(!is_leap(1900) && !is_leap(2023));
+    assert_eq!(days_in_month(2024, 2), 29);
+    assert_eq!(days_in_month(2100, 2), 28);
+    assert_eq!(days_in_month(2023, 4), 30);
+}
+
+#[test]
+fn impossible_dates_are_invalid() {
+    assert!(date(2024, 2, 29, 23, 59, 59).is_valid());
+    assert!(!date(2023, 2, 29, 0, 0, 0).is_valid());
+    assert!(!date(1969, 12, 31, 23, 59, 59).is_valid());
+    assert!(!date(2024, 13, 1, 0, 0, 0).is_valid());
+    assert!(!date(2024, 1, 1, 24, 0, 0).is_valid());
+    assert!(!date(2024, 1, 1, 0, 0, 60).is_valid());
+}
+
+#[test]
+fn from_unix_stops_where_the_year_overflows() {
+    assert_eq!(DateTime::from_unix(u64::MAX), None);
+}
@@ -0,0 +1,51 @@
+use ares_core::cpu::support::{
+    missing_feature, CpuidWords, RequiredFeature, EXT1_EDX_LONG_MODE, EXTENDED_FEATURE_LEAF, LEAF1_EDX_PAE,
+    LEAF1_EDX_SSE2,
+};
+
+/// What QEMU's default `qemu64` model reports for the bits that matter.
+fn capable() -> CpuidWords {
+    CpuidWords {
+        basic_edx: LEAF1_EDX_PAE | LEAF1_EDX_SSE2 | 0x0781_ABFD,
+        max_extended_leaf: 0x8000_000A,
+        extended_edx: EXT1_EDX_LONG_MODE | (1 << 20),
+    }
+}
+
+#[test]
+fn capable_cpu_passes() {
+    assert_eq!(missing_feature(&capable()), None);
+}
+
+#[test]
+fn each_missing_bit_is_named() {
+    let mut words = capable();
+    words.basic_edx &= !LEAF1_EDX_PAE;
+    assert_eq!(missing_feature(&words), Some(RequiredFeature::Pae));
+
+    let mut words = capable();
+    words.extended_edx &= !EXT1_EDX_LONG_MODE;
+    assert_eq!(missing_feature(&words), Some(RequiredFeature::LongMode));
+
+    let mut words = capable();
+    words.basic_edx &= !LEAF1_EDX_SSE2;
+    assert_eq!(missing_feature(&words), Some(RequiredFeature::Sse2));
+    assert_eq!(RequiredFeature::Sse2.name(), "SSE2");
+}
+
+#[test]
+fn long_mode_bit_is_ignored_without_the_extended_leaf() {
+    let mut words = capable();
+    words.max_extended_leaf = EXTENDED_FEATURE_LEAF - 1;
+    assert_eq!(missing_feature(&words), Some(RequiredFeature::LongMode));
+}
+
+#[test]
+fn first_missing_feature_wins() {
+    assert_eq!(missing_feature(&CpuidWords::default()), Some(RequiredFeature::Pae));
+
+    let mut words = capable();
+    words.extended_edx = 0;
+    words.basic_edx &= !LEAF1_EDX_SSE2;
+    assert_eq!(missing_feature(&words), Some(RequiredFeature::LongMode));
+}
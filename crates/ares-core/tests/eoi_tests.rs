@@ -0,0 +1,42 @@
+use ares_core::interrupts::eoi::{eoi_for, is_spurious, may_be_spurious, Eoi};
+
+#[test]
+fn ordinary_lines_always_eoi() {
+    assert_eq!(eoi_for(0, 0x0001), Eoi::Master);
+    assert_eq!(eoi_for(1, 0x0000), Eoi::Master);
+    assert_eq!(eoi_for(14, 0x4004), Eoi::Both);
+    assert_eq!(eoi_for(8, 0x0000), Eoi::Both);
+    assert!(!is_spurious(1, 0));
+}
+
+#[test]
+fn only_the_lowest_priority_lines_need_the_isr() {
+    assert!(may_be_spurious(7));
+    assert!(may_be_spurious(15));
+    assert!((0..16).filter(|&irq| may_be_spurious(irq)).count() == 2);
+}
+
+#[test]
+fn real_irq7_is_acknowledged() {
+    assert!(!is_spurious(7, 0x0080));
+    assert_eq!(eoi_for(7, 0x0080), Eoi::Master);
+}
+
+#[test]
+fn spurious_irq7_gets_no_eoi() {
+    assert!(is_spurious(7, 0x0000));
+    // Another line in service doesn't make IRQ7 real.
+    assert_eq!(eoi_for(7, 0x0001), Eoi::None);
+}
+
+#[test]
+fn real_irq15_is_acknowledged_on_both_chips() {
+    assert!(!is_spurious(15, 0x8004));
+    assert_eq!(eoi_for(15, 0x8004), Eoi::Both);
+}
+
+#[test]
+fn spurious_irq15_still_acknowledges_master() {
+    assert!(is_spurious(15, 0x0004));
+    assert_eq!(eoi_for(15, 0x0004), Eoi::Master);
+}
@@ -4,6 +4,7 @@ use ares_core::drivers::mock::MemBlockDevice;
 use ares_core::fs::fat::{self, FatError};
 
 const SECTOR_SIZE: usize = 512;
+const VOLUME: &str = "test";
 static FAT_GUARD: Mutex<()> = Mutex::new(());
 
 fn fat_image_with_hello() -> Vec<u8> {
@@ -120,8 +121,8 @@ fn open_and_read_file() {
     let _guard = FAT_GUARD.lock().unwrap();
     let image = fat_image_with_hello();
     let dev = Box::leak(Box::new(MemBlockDevice::new("mem-fat", image, SECTOR_SIZE)));
-    fat::mount(dev, 0).expect("mount");
-    let file = fat::open_file("HELLO.TXT").expect("open");
+    fat::mount(VOLUME, dev, 0).expect("mount");
+    let file = fat::open_file(VOLUME, "HELLO.TXT").expect("open");
     let mut buf = [0u8; 8];
     let read = file.read_at(0, &mut buf).expect("read");
     assert_eq!(read, 5);
@@ -133,8 +134,8 @@ fn missing_file_errors() {
     let _guard = FAT_GUARD.lock().unwrap();
     let image = fat_image_with_hello();
     let dev = Box::leak(Box::new(MemBlockDevice::new("mem-fat", image, SECTOR_SIZE)));
-    fat::mount(dev, 0).expect("mount");
-    let result = fat::open_file("MISSING.TXT");
+    fat::mount(VOLUME, dev, 0).expect("mount");
+    let result = fat::open_file(VOLUME, "MISSING.TXT");
     assert!(matches!(result, Err(FatError::NotFound)));
 }
 
@@ -143,8 +144,8 @@ fn read_beyond_end_returns_zero() {
     let _guard = FAT_GUARD.lock().unwrap();
     let image = fat_image_with_hello();
     let dev = Box::leak(Box::new(MemBlockDevice::new("mem-fat", image, SECTOR_SIZE)));
-    fat::mount(dev, 0).expect("mount");
-    let file = fat::open_file("HELLO.TXT").expect("open");
+    fat::mount(VOLUME, dev, 0).expect("mount");
+    let file = fat::open_file(VOLUME, "HELLO.TXT").expect("open");
     let mut buf = [0u8; 32];
     let count = file.read_at(0, &mut buf).expect("read");
     assert_eq!(count, 5);
@@ -157,8 +158,8 @@ fn multi_cluster_read() {
     let _guard = FAT_GUARD.lock().unwrap();
     let image = fat_image_with_large_file();
     let dev = Box::leak(Box::new(MemBlockDevice::new("mem-fat", image, SECTOR_SIZE)));
-    fat::mount(dev, 0).expect("mount");
-    let file = fat::open_file("BIGFILE.TXT").expect("open");
+    fat::mount(VOLUME, dev, 0).expect("mount");
+    let file = fat::open_file(VOLUME, "BIGFILE.TXT").expect("open");
     let mut buf = [0u8; 700];
     let count = file.read_at(0, &mut buf).expect("read");
     assert_eq!(count, 600);
@@ -171,10 +172,106 @@ fn large_buffer_partial_read() {
     let _guard = FAT_GUARD.lock().unwrap();
     let image = fat_image_with_large_file();
     let dev = Box::leak(Box::new(MemBlockDevice::new("mem-fat", image, SECTOR_SIZE)));
-    fat::mount(dev, 0).expect("mount");
-    let file = fat::open_file("BIGFILE.TXT").expect("open");
+    fat::mount(VOLUME, dev, 0).expect("mount");
+    let file = fat::open_file(VOLUME, "BIGFILE.TXT").expect("open");
     let mut buf = [0u8; 256];
     let count = file.read_at(256, &mut buf).expect("read slice");
     assert_eq!(count, 256);
     assert!(buf[..count].iter().all(|&b| b == b'A'));
 }
+
+fn mount_image(image: Vec<u8>) -> Result<(), FatError> {
+    let dev = Box::leak(Box::new(MemBlockDevice::new("mem-fat", image, SECTOR_SIZE)));
+    fat::mount(VOLUME, dev, 0)
+}
+
+#[test]
+fn zeroed_image_fails_to_mount() {
+    let _guard = FAT_GUARD.lock().unwrap();
+    assert_eq!(mount_image(vec![0u8; SECTOR_SIZE * 10]), Err(FatError::Io));
+}
+
+#[test]
+fn implausible_bpb_fields_fail_to_mount() {
+    let _guard = FAT_GUARD.lock().unwrap();
+    let corrupt = |offset: usize, bytes: &[u8]| {
+        let mut image = fat_image_with_hello();
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+        mount_image(image)
+    };
+
+    // Sectors per cluster: zero, then not a power of two.
+    assert_eq!(corrupt(13, &[0]), Err(FatError::Io));
+    assert_eq!(corrupt(13, &[3]), Err(FatError::Io));
+    // No reserved sectors.
+    assert_eq!(corrupt(14, &0u16.to_le_bytes()), Err(FatError::Io));
+    // FAT count outside 1..=2.
+    assert_eq!(corrupt(16, &[0]), Err(FatError::Io));
+    assert_eq!(corrupt(16, &[3]), Err(FatError::Io));
+    // Empty FAT, then one bigger than the 10-sector volume.
+    assert_eq!(corrupt(22, &0u16.to_le_bytes()), Err(FatError::Io));
+    let mut image = fat_image_with_hello();
+    image[19..21].copy_from_slice(&10u16.to_le_bytes());
+    image[22..24].copy_from_slice(&9u16.to_le_bytes());
+    assert_eq!(mount_image(image), Err(FatError::Io));
+
+    // The same image with a truthful sector count still mounts.
+    let mut image = fat_image_with_hello();
+    image[19..21].copy_from_slice(&10u16.to_le_bytes());
+    assert_eq!(mount_image(image), Ok(()));
+}
+
+#[test]
+fn two_volumes_mounted_side_by_side() {
+    let _guard = FAT_GUARD.lock().unwrap();
+    let mut other = fat_image_with_hello();
+    let root = SECTOR_SIZE * 2;
+    other[root..root + 11].copy_from_slice(b"WORLD   TXT");
+    let data = SECTOR_SIZE * 3;
+    other[data..data + 5].copy_from_slice(b"World");
+
+    let first = Box::leak(Box::new(MemBlockDevice::new("mem-fat", fat_image_with_hello(), SECTOR_SIZE)));
+    let second = Box::leak(Box::new(MemBlockDevice::new("mem-fat2", other, SECTOR_SIZE)));
+    fat::mount(VOLUME, first, 0).expect("mount first");
+    fat::mount("other", second, 0).expect("mount second");
+
+    let mut buf = [0u8; 5];
+    let hello = fat::open_file(VOLUME, "HELLO.TXT").expect("open hello");
+    hello.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"Hello");
+    let world = fat::open_file("other", "WORLD.TXT").expect("open world");
+    world.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"World");
+    assert_eq!(fat::open_file(VOLUME, "WORLD.TXT").err(), Some(FatError::NotFound));
+    assert_eq!(fat::open_file("missing", "HELLO.TXT").err(), Some(FatError::NotMounted));
+    assert_eq!(fat::mount("a/b", first, 0), Err(FatError::InvalidPath));
+}
+
+#[test]
+fn corrupt_chains_fail_instead_of_looping() {
+    let _guard = FAT_GUARD.lock().unwrap();
+    // BIGFILE.TXT claims 8 KiB, more than the nine data clusters a 12-sector
+    // volume holds, so following its chain has to run past the end.
+    let corrupt = |entry: u16| {
+        let mut image = fat_image_with_large_file();
+        image[19..21].copy_from_slice(&12u16.to_le_bytes());
+        let cluster4 = SECTOR_SIZE + 4 * 2;
+        image[cluster4..cluster4 + 2].copy_from_slice(&entry.to_le_bytes());
+        let size = SECTOR_SIZE * 2 + 32 + 28;
+        image[size..size + 4].copy_from_slice(&(8192u32).to_le_bytes());
+        mount_image(image).expect("mount");
+        fat::open_file(VOLUME, "BIGFILE.TXT").expect("open")
+    };
+
+    let mut buf = [0u8; 16];
+    // 3 -> 4 -> 3 -> ...
+    let file = corrupt(3);
+    assert_eq!(file.read_at(1024, &mut buf).unwrap(), 16);
+    assert!(file.read_at(5000, &mut buf).is_err());
+    let mut whole = vec![0u8; 8192];
+    assert!(file.read_at(0, &mut whole).is_err());
+
+    // Links to a reserved cluster and past the data region.
+    assert!(corrupt(1).read_at(2 * SECTOR_SIZE as u64, &mut buf).is_err());
+    assert!(corrupt(0x0100).read_at(2 * SECTOR_SIZE as u64, &mut buf).is_err());
+}
@@ -0,0 +1,58 @@
+use ares_core::fs::fat_time::{FatTimestamp, FAT_EPOCH_UNIX};
+
+fn stamp(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> FatTimestamp {
+    FatTimestamp {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+    }
+}
+
+#[test]
+fn epoch_is_1980() {
+    // 1980-01-01 00:00:00 packs as day 1 of month 1, year offset 0.
+    let epoch = FatTimestamp::decode(0x0021, 0x0000).expect("epoch decodes");
+    assert_eq!(epoch, stamp(1980, 1, 1, 0, 0, 0));
+    assert_eq!(epoch.to_unix(), FAT_EPOCH_UNIX);
+    assert_eq!(FatTimestamp::from_unix(FAT_EPOCH_UNIX), Some(epoch));
+    assert_eq!(FatTimestamp::from_unix(FAT_EPOCH_UNIX - 1), None);
+}
+
+#[test]
+fn fields_unpack() {
+    let date = (44 << 9) | (2 << 5) | 29;
+    let time = (23 << 11) | (59 << 5) | 29;
+    assert_eq!(FatTimestamp::decode(date, time), Some(stamp(2024, 2, 29, 23, 59, 58)));
+}
+
+#[test]
+fn seconds_are_stored_halved() {
+    // 2001-09-09 01:46:40, Unix second 1e9; the time word holds 20.
+    let decoded = FatTimestamp::decode((21 << 9) | (9 << 5) | 9, (1 << 11) | (46 << 5) | 20).unwrap();
+    assert_eq!(decoded.second, 40);
+    assert_eq!(decoded.to_unix(), 1_000_000_000);
+}
+
+#[test]
+fn unix_conversion_matches_known_dates() {
+    assert_eq!(stamp(2000, 1, 1, 0, 0, 0).to_unix(), 946_684_800);
+    assert_eq!(stamp(2024, 3, 1, 12, 30, 0).to_unix(), 1_709_296_200);
+    assert_eq!(FatTimestamp::from_unix(951_782_400), Some(stamp(2000, 2, 29, 0, 0, 0)));
+    assert_eq!(FatTimestamp::from_unix(4_354_819_199), Some(stamp(2107, 12, 31, 23, 59, 59)));
+    assert_eq!(FatTimestamp::from_unix(4_354_819_200), None);
+}
+
+#[test]
+fn unset_or_corrupt_fields_decode_to_none() {
+    assert_eq!(FatTimestamp::decode(0, 0), None);
+    // Month 13, day 0, 30 February and a seconds field of 30 (60 s).
+    assert_eq!(FatTimestamp::decode((13 << 5) | 1, 0), None);
+    assert_eq!(FatTimestamp::decode(1 << 5, 0), None);
+    assert_eq!(FatTimestamp::decode((2 << 5) | 30, 0), None);
+    assert_eq!(FatTimestamp::decode(0x0021, 30), None);
+    // 1981 is not a leap year.
+    assert_eq!(FatTimestamp::decode((1 << 9) | (2 << 5) | 29, 0), None);
+}
@@ -0,0 +1,293 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use ares_core::drivers::mock::MemBlockDevice;
+use ares_core::vfs::ata::AtaScratchFile;
+use ares_core::vfs::handle::{resolve_seek, SeekFrom, VfsHandle, READ_AHEAD_LEN};
+use ares_core::vfs::{VfsError, VfsFile, VfsResult};
+
+static SCRATCH_GUARD: Mutex<()> = Mutex::new(());
+const BLOCK_SIZE: usize = 512;
+
+fn scratch_handle() -> VfsHandle {
+    let dev = Box::leak(Box::new(MemBlockDevice::new("scratch", vec![0u8; BLOCK_SIZE * 2], BLOCK_SIZE)));
+    let file = unsafe { AtaScratchFile::init(dev, 0, "scratch") };
+    VfsHandle::new(file)
+}
+
+/// Grows on write, like a tmpfs file. With `sized` off it behaves like a
+/// stream that cannot say how long it is.
+struct GrowFile {
+    data: Mutex<Vec<u8>>,
+    sized: bool,
+}
+
+impl GrowFile {
+    fn leak(contents: &[u8], sized: bool) -> &'static GrowFile {
+        Box::leak(Box::new(GrowFile {
+            data: Mutex::new(contents.to_vec()),
+            sized,
+        }))
+    }
+}
+
+impl VfsFile for GrowFile {
+    fn name(&self) -> &'static str {
+        "grow"
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let data = self.data.lock().unwrap();
+        let start = (offset as usize).min(data.len());
+        let count = buf.len().min(data.len() - start);
+        buf[..count].copy_from_slice(&data[start..start + count]);
+        Ok(count)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut data = self.data.lock().unwrap();
+        let end = offset as usize + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[offset as usize..end].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> VfsResult<u64> {
+        if self.sized {
+            Ok(self.data.lock().unwrap().len() as u64)
+        } else {
+            Err(VfsError::Unsupported)
+        }
+    }
+}
+
+#[test]
+fn reads_and_writes_advance_offset() {
+    let _guard = SCRATCH_GUARD.lock().unwrap();
+    let mut handle = scratch_handle();
+
+    assert_eq!(handle.write(b"hello").unwrap(), 5);
+    assert_eq!(handle.offset(), 5);
+
+    handle.seek(SeekFrom::Start(0)).unwrap();
+    let mut buf = [0u8; 3];
+    assert_eq!(handle.read(&mut buf).unwrap(), 3);
+    assert_eq!(&buf, b"hel");
+    assert_eq!(handle.offset(), 3);
+}
+
+#[test]
+fn seek_before_start_is_rejected() {
+    let _guard = SCRATCH_GUARD.lock().unwrap();
+    let mut handle = scratch_handle();
+    handle.seek(SeekFrom::Start(10)).unwrap();
+
+    assert_eq!(handle.seek(SeekFrom::Current(-11)), Err(VfsError::InvalidOffset));
+    assert_eq!(handle.seek(SeekFrom::End(-(BLOCK_SIZE as i64) - 1)), Err(VfsError::InvalidOffset));
+    // A failed seek leaves the cursor alone.
+    assert_eq!(handle.offset(), 10);
+}
+
+#[test]
+fn seek_past_end_is_rejected() {
+    let _guard = SCRATCH_GUARD.lock().unwrap();
+    let mut handle = scratch_handle();
+    let size = BLOCK_SIZE as u64;
+
+    assert_eq!(handle.seek(SeekFrom::End(0)).unwrap(), size);
+    assert_eq!(handle.seek(SeekFrom::Start(size + 1)), Err(VfsError::InvalidOffset));
+    assert_eq!(handle.seek(SeekFrom::End(1)), Err(VfsError::InvalidOffset));
+    assert_eq!(handle.seek(SeekFrom::Current(1)), Err(VfsError::InvalidOffset));
+    assert_eq!(handle.offset(), size);
+}
+
+#[test]
+fn relative_seeks() {
+    let _guard = SCRATCH_GUARD.lock().unwrap();
+    let mut handle = scratch_handle();
+
+    assert_eq!(handle.seek(SeekFrom::Current(100)).unwrap(), 100);
+    assert_eq!(handle.seek(SeekFrom::Current(-40)).unwrap(), 60);
+    assert_eq!(handle.seek(SeekFrom::End(-12)).unwrap(), BLOCK_SIZE as u64 - 12);
+    assert_eq!(handle.seek(SeekFrom::Current(0)).unwrap(), BLOCK_SIZE as u64 - 12);
+}
+
+#[test]
+fn resolve_seek_handles_extremes() {
+    assert_eq!(resolve_seek(SeekFrom::Current(i64::MIN), u64::MAX, u64::MAX), Ok(u64::MAX - (1u64 << 63)));
+    assert_eq!(resolve_seek(SeekFrom::End(i64::MAX), 0, u64::MAX), Err(VfsError::InvalidOffset));
+    assert_eq!(resolve_seek(SeekFrom::Start(0), 7, 0), Ok(0));
+}
+
+#[test]
+fn append_writes_land_at_the_end_after_a_seek() {
+    let file = GrowFile::leak(b"log:", true);
+    let mut handle = VfsHandle::append(file);
+    assert!(handle.is_append());
+
+    assert_eq!(handle.write(b"one").unwrap(), 3);
+    assert_eq!(handle.offset(), 7);
+    handle.seek(SeekFrom::Start(0)).unwrap();
+    assert_eq!(handle.write(b"two").unwrap(), 3);
+    assert_eq!(handle.offset(), 10);
+    assert_eq!(&*file.data.lock().unwrap(), b"log:onetwo");
+
+    // Someone else growing the file moves the end too.
+    file.write_at(10, b"!").unwrap();
+    handle.seek(SeekFrom::Start(2)).unwrap();
+    handle.write(b"3").unwrap();
+    assert_eq!(&*file.data.lock().unwrap(), b"log:onetwo!3");
+
+    // Without the flag the same seek overwrites.
+    handle.set_append(false);
+    handle.seek(SeekFrom::Start(0)).unwrap();
+    handle.write(b"LOG").unwrap();
+    assert_eq!(&*file.data.lock().unwrap(), b"LOG:onetwo!3");
+}
+
+/// A file on a disk that reads one sector at a time, like FAT over the ATA
+/// driver: every sector a `read_at` touches is one read from the device.
+struct SectorFile {
+    data: Mutex<Vec<u8>>,
+    sector_reads: AtomicUsize,
+}
+
+impl SectorFile {
+    fn leak(len: usize) -> &'static SectorFile {
+        let data = (0..len).map(|i| (i % 251) as u8).collect();
+        Box::leak(Box::new(SectorFile {
+            data: Mutex::new(data),
+            sector_reads: AtomicUsize::new(0),
+        }))
+    }
+
+    fn sector_reads(&self) -> usize {
+        self.sector_reads.load(Ordering::SeqCst)
+    }
+}
+
+impl VfsFile for SectorFile {
+    fn name(&self) -> &'static str {
+        "sectors"
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let data = self.data.lock().unwrap();
+        let start = (offset as usize).min(data.len());
+        let count = buf.len().min(data.len() - start);
+        if count > 0 {
+            let sectors = (start + count).div_ceil(BLOCK_SIZE) - start / BLOCK_SIZE;
+            self.sector_reads.fetch_add(sectors, Ordering::SeqCst);
+        }
+        buf[..count].copy_from_slice(&data[start..start + count]);
+        Ok(count)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut data = self.data.lock().unwrap();
+        let end = (offset as usize + buf.len()).min(data.len());
+        let count = end.saturating_sub(offset as usize);
+        data[offset as usize..end].copy_from_slice(&buf[..count]);
+        Ok(count)
+    }
+
+    fn flush(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> VfsResult<u64> {
+        Ok(self.data.lock().unwrap().len() as u64)
+    }
+}
+
+fn stream(handle: &mut VfsHandle, chunk: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut buf = vec![0u8; chunk];
+    loop {
+        let count = handle.read(&mut buf).unwrap();
+        if count == 0 {
+            return out;
+        }
+        out.extend_from_slice(&buf[..count]);
+    }
+}
+
+#[test]
+fn sequential_reads_are_served_from_read_ahead() {
+    let len = 4 * READ_AHEAD_LEN;
+    let file = SectorFile::leak(len);
+    let mut handle = VfsHandle::new(file);
+
+    let out = stream(&mut handle, 16);
+    assert_eq!(out, *file.data.lock().unwrap());
+    // Without read-ahead every 16-byte read costs a sector: 1024 of them.
+    // With it the device sees each sector once, plus the first read.
+    let direct = len / 16;
+    assert!(
+        file.sector_reads() <= len / BLOCK_SIZE + 1,
+        "{} sector reads for {} bytes ({} without read-ahead)",
+        file.sector_reads(),
+        len,
+        direct
+    );
+
+    // Reads that straddle the end of the buffer still come back whole.
+    let mut handle = VfsHandle::new(file);
+    assert_eq!(stream(&mut handle, 100), out);
+}
+
+#[test]
+fn random_reads_skip_read_ahead() {
+    let file = SectorFile::leak(2 * READ_AHEAD_LEN);
+    let mut handle = VfsHandle::new(file);
+    let mut buf = [0u8; 8];
+
+    for &offset in &[4000u64, 100, 6000, 2000] {
+        handle.seek(SeekFrom::Start(offset)).unwrap();
+        assert_eq!(handle.read(&mut buf).unwrap(), 8);
+        assert_eq!(buf[0], (offset % 251) as u8);
+    }
+    // Each seek ends the run, so nothing past the 8 bytes was fetched.
+    assert_eq!(file.sector_reads(), 4);
+}
+
+#[test]
+fn write_and_seek_drop_read_ahead() {
+    let file = SectorFile::leak(READ_AHEAD_LEN);
+    let mut handle = VfsHandle::new(file);
+    let mut buf = [0u8; 4];
+    handle.read(&mut buf).unwrap();
+    handle.read(&mut buf).unwrap();
+
+    // The bytes at 8.. are buffered now; writing through the handle must
+    // not leave the old ones to be read back.
+    assert_eq!(handle.write(b"WXYZ").unwrap(), 4);
+    handle.seek(SeekFrom::Start(8)).unwrap();
+    handle.read(&mut buf).unwrap();
+    assert_eq!(&buf, b"WXYZ");
+
+    // Same after a seek back over bytes changed behind the handle's back.
+    handle.read(&mut buf).unwrap();
+    file.write_at(12, b"abcd").unwrap();
+    handle.seek(SeekFrom::Start(12)).unwrap();
+    handle.read(&mut buf).unwrap();
+    assert_eq!(&buf, b"abcd");
+}
+
+#[test]
+fn streams_without_a_size_are_not_read_ahead() {
+    let file = GrowFile::leak(b"abcdef", false);
+    let mut handle = VfsHandle::new(file);
+    let mut buf = [0u8; 2];
+    handle.read(&mut buf).unwrap();
+    handle.read(&mut buf).unwrap();
+    file.write_at(4, b"XY").unwrap();
+    handle.read(&mut buf).unwrap();
+    assert_eq!(&buf, b"XY");
+}
@@ -0,0 +1,217 @@
+use core::alloc::Layout;
+
+use ares_core::mem::free_list::LinkedListAllocator;
+
+const ARENA_WORDS: usize = 1024;
+const ARENA_BYTES: usize = ARENA_WORDS * 8;
+
+fn with_allocator<F: FnOnce(&mut LinkedListAllocator)>(body: F) {
+    let mut arena = vec![0u64; ARENA_WORDS];
+    let mut allocator = LinkedListAllocator::new();
+    unsafe { allocator.init(arena.as_mut_ptr() as usize, ARENA_BYTES) };
+    body(&mut allocator);
+}
+
+fn block() -> Layout {
+    Layout::from_size_align(1024, 8).unwrap()
+}
+
+#[test]
+fn fresh_heap_is_one_region() {
+    with_allocator(|heap| {
+        let stats = heap.stats();
+        assert_eq!(stats.remaining, ARENA_BYTES);
+        assert_eq!(stats.peak_used, 0);
+        assert_eq!(stats.free_regions, 1);
+        assert_eq!(stats.largest_free, ARENA_BYTES);
+    });
+}
+
+#[test]
+fn peak_used_keeps_the_worst_case() {
+    with_allocator(|heap| unsafe {
+        let a = heap.allocate(block());
+        let b = heap.allocate(block());
+        let c = heap.allocate(block());
+        assert!(!a.is_null() && !b.is_null() && !c.is_null());
+        assert_eq!(heap.stats().peak_used, 3 * 1024);
+
+        heap.deallocate(a, block());
+        heap.deallocate(b, block());
+        heap.deallocate(c, block());
+        let stats = heap.stats();
+        assert_eq!(stats.remaining, ARENA_BYTES);
+        assert_eq!(stats.peak_used, 3 * 1024);
+
+        // A smaller burst later does not lower the mark.
+        let d = heap.allocate(block());
+        heap.deallocate(d, block());
+        assert_eq!(heap.stats().peak_used, 3 * 1024);
+    });
+}
+
+#[test]
+fn holes_show_up_as_fragmentation() {
+    with_allocator(|heap| unsafe {
+        let blocks: Vec<*mut u8> = (0..6).map(|_| heap.allocate(block())).collect();
+        assert!(blocks.iter().all(|ptr| !ptr.is_null()));
+        let tail = heap.stats().largest_free;
+        assert_eq!(tail, ARENA_BYTES - 6 * 1024);
+
+        // Free every other block: three holes that cannot merge.
+        for ptr in blocks.iter().step_by(2) {
+            heap.deallocate(*ptr, block());
+        }
+        let stats = heap.stats();
+        assert_eq!(stats.free_regions, 4);
+        assert_eq!(stats.remaining, tail + 3 * 1024);
+        assert_eq!(stats.largest_free, tail);
+        assert!(stats.largest_free < stats.remaining);
+
+        // Filling the holes back in coalesces everything.
+        for ptr in blocks.iter().skip(1).step_by(2) {
+            heap.deallocate(*ptr, block());
+        }
+        let stats = heap.stats();
+        assert_eq!(stats.free_regions, 1);
+        assert_eq!(stats.largest_free, ARENA_BYTES);
+    });
+}
+
+#[test]
+fn exhaustion_returns_null_and_counts_full_use() {
+    with_allocator(|heap| unsafe {
+        let all = Layout::from_size_align(ARENA_BYTES, 8).unwrap();
+        let ptr = heap.allocate(all);
+        assert!(!ptr.is_null());
+        assert!(heap.allocate(block()).is_null());
+        let stats = heap.stats();
+        assert_eq!(stats.remaining, 0);
+        assert_eq!(stats.free_regions, 0);
+        assert_eq!(stats.peak_used, ARENA_BYTES);
+        heap.deallocate(ptr, all);
+        assert_eq!(heap.stats().remaining, ARENA_BYTES);
+    });
+}
+
+#[test]
+fn over_aligned_page_is_aligned_and_reclaimed() {
+    with_allocator(|heap| unsafe {
+        // Push the free space off any 64-byte boundary first.
+        let small = Layout::from_size_align(8, 8).unwrap();
+        let page = Layout::from_size_align(4096, 64).unwrap();
+        let a = heap.allocate(small);
+        let b = heap.allocate(page);
+        assert!(!a.is_null() && !b.is_null());
+        assert_eq!(b as usize % 64, 0);
+
+        // The gap in front of the page went back on the list.
+        let used = LinkedListAllocator::reserved_size(small) + LinkedListAllocator::reserved_size(page);
+        assert_eq!(heap.stats().remaining, ARENA_BYTES - used);
+
+        heap.deallocate(b, page);
+        heap.deallocate(a, small);
+        let stats = heap.stats();
+        assert_eq!(stats.remaining, ARENA_BYTES);
+        assert_eq!(stats.free_regions, 1);
+    });
+}
+
+#[test]
+fn gap_too_small_for_a_node_moves_the_allocation_up() {
+    with_allocator(|heap| unsafe {
+        let odd = Layout::from_size_align(24, 8).unwrap();
+        let aligned = Layout::from_size_align(16, 16).unwrap();
+        let a = heap.allocate(odd);
+        let b = heap.allocate(aligned);
+        assert!(!a.is_null() && !b.is_null());
+        assert_eq!(b as usize % 16, 0);
+        assert!(b as usize >= a as usize + 24);
+        assert_eq!(heap.stats().remaining, ARENA_BYTES - 24 - 16);
+
+        heap.deallocate(a, odd);
+        heap.deallocate(b, aligned);
+        assert_eq!(heap.stats().remaining, ARENA_BYTES);
+        assert_eq!(heap.stats().free_regions, 1);
+    });
+}
+
+#[test]
+fn mixed_alignments_give_every_byte_back() {
+    with_allocator(|heap| unsafe {
+        let layouts: Vec<Layout> = [(1, 1), (17, 2), (4096, 64), (3, 4), (100, 16), (24, 8), (200, 128), (9, 32)]
+            .iter()
+            .map(|&(size, align)| Layout::from_size_align(size, align).unwrap())
+            .collect();
+        let ptrs: Vec<*mut u8> = layouts.iter().map(|&layout| heap.allocate(layout)).collect();
+
+        let mut spans = Vec::new();
+        for (&ptr, layout) in ptrs.iter().zip(&layouts) {
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % layout.align(), 0);
+            // Scribble over the whole block; a broken free list would show
+            // up as a corrupted node on the next call.
+            core::ptr::write_bytes(ptr, 0xA5, layout.size());
+            spans.push((ptr as usize, ptr as usize + LinkedListAllocator::reserved_size(*layout)));
+        }
+        spans.sort();
+        assert!(spans.windows(2).all(|pair| pair[0].1 <= pair[1].0));
+
+        let reserved: usize = layouts.iter().map(|&layout| LinkedListAllocator::reserved_size(layout)).sum();
+        assert_eq!(heap.stats().remaining, ARENA_BYTES - reserved);
+
+        // Odd slots first, then the rest, so frees land between live blocks.
+        for index in (1..ptrs.len()).step_by(2).chain((0..ptrs.len()).step_by(2)) {
+            heap.deallocate(ptrs[index], layouts[index]);
+        }
+        let stats = heap.stats();
+        assert_eq!(stats.remaining, ARENA_BYTES);
+        assert_eq!(stats.free_regions, 1);
+        assert_eq!(stats.largest_free, ARENA_BYTES);
+    });
+}
+
+#[test]
+fn free_total_matches_a_recount() {
+    with_allocator(|heap| unsafe {
+        assert_eq!(heap.remaining(), heap.recount_free());
+
+        let sizes = [24, 1024, 8, 300, 4096, 64, 16];
+        let layouts: Vec<Layout> = sizes
+            .iter()
+            .enumerate()
+            .map(|(index, &size)| Layout::from_size_align(size, 8 << (index % 4)).unwrap())
+            .collect();
+        let mut live = Vec::new();
+        for &layout in &layouts {
+            let ptr = heap.allocate(layout);
+            assert!(!ptr.is_null());
+            live.push((ptr, layout));
+            assert_eq!(heap.remaining(), heap.recount_free());
+        }
+
+        // Free from the middle outwards so frees both split and coalesce.
+        while !live.is_empty() {
+            let (ptr, layout) = live.remove(live.len() / 2);
+            heap.deallocate(ptr, layout);
+            assert_eq!(heap.remaining(), heap.recount_free());
+        }
+        assert_eq!(heap.remaining(), ARENA_BYTES);
+    });
+}
+
+#[test]
+fn partial_release_keeps_the_total_in_step() {
+    with_allocator(|heap| unsafe {
+        let layout = Layout::from_size_align(2048, 8).unwrap();
+        let ptr = heap.allocate(layout);
+        assert!(!ptr.is_null());
+
+        // Hand back the back half, plus an odd sliver that gets dropped.
+        heap.release(ptr as usize + 1024, 1024);
+        assert_eq!(heap.remaining(), heap.recount_free());
+        heap.release(ptr as usize + 3, 5);
+        assert_eq!(heap.remaining(), heap.recount_free());
+        assert_eq!(heap.remaining(), ARENA_BYTES - 1024);
+    });
+}
@@ -0,0 +1,72 @@
+use ares_core::drivers::line_discipline::{LineDiscipline, LINE_MAX};
+
+fn feed(line: &mut LineDiscipline, bytes: &[u8]) -> Vec<u8> {
+    let mut echoed = Vec::new();
+    for &byte in bytes {
+        line.input(byte, |out| echoed.extend_from_slice(out));
+    }
+    echoed
+}
+
+#[test]
+fn backspace_edits_before_delivery() {
+    let mut line = LineDiscipline::new();
+    let echoed = feed(&mut line, b"ab\x08c\n");
+    assert_eq!(echoed, b"ab\x08 \x08c\n");
+
+    let mut buf = [0u8; 16];
+    let count = line.read(&mut buf);
+    assert_eq!(&buf[..count], b"ac\n");
+}
+
+#[test]
+fn partial_line_is_withheld() {
+    let mut line = LineDiscipline::new();
+    feed(&mut line, b"abc");
+    assert!(!line.has_line());
+    assert_eq!(line.read(&mut [0u8; 8]), 0);
+
+    feed(&mut line, b"\n");
+    assert!(line.has_line());
+}
+
+#[test]
+fn backspace_on_empty_line_is_ignored() {
+    let mut line = LineDiscipline::new();
+    assert!(feed(&mut line, b"\x08\x7f").is_empty());
+    feed(&mut line, b"x\n\x08");
+
+    let mut buf = [0u8; 8];
+    let count = line.read(&mut buf);
+    assert_eq!(&buf[..count], b"x\n");
+}
+
+#[test]
+fn short_reads_resume_and_typeahead_survives() {
+    let mut line = LineDiscipline::new();
+    feed(&mut line, b"hello\nwor");
+
+    let mut buf = [0u8; 4];
+    assert_eq!(line.read(&mut buf), 4);
+    assert_eq!(&buf, b"hell");
+    assert_eq!(line.read(&mut buf), 2);
+    assert_eq!(&buf[..2], b"o\n");
+    assert_eq!(line.read(&mut buf), 0);
+
+    feed(&mut line, b"ld\n");
+    let count = line.read(&mut buf);
+    assert_eq!(&buf[..count], b"worl");
+}
+
+#[test]
+fn overlong_line_keeps_newline() {
+    let mut line = LineDiscipline::new();
+    let long = vec![b'z'; LINE_MAX + 10];
+    feed(&mut line, &long);
+    feed(&mut line, b"\n");
+
+    let mut buf = vec![0u8; LINE_MAX * 2];
+    let count = line.read(&mut buf);
+    assert_eq!(count, LINE_MAX);
+    assert_eq!(buf[count - 1], b'\n');
+}
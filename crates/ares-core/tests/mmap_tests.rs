@@ -0,0 +1,105 @@
+use ares_core::mem::mmap::{
+    entries, totals, MmapEntry, MEMORY_TYPE_ACPI_NVS, MEMORY_TYPE_ACPI_RECLAIMABLE, MEMORY_TYPE_AVAILABLE,
+    MEMORY_TYPE_BAD, MEMORY_TYPE_RESERVED, TAG_HEADER_LEN,
+};
+
+const MIB: u64 = 1024 * 1024;
+
+/// Build a type-6 tag holding `regions`, padding each entry out to
+/// `entry_size` as a newer bootloader might.
+fn build_tag(regions: &[(u64, u64, u32)], entry_size: usize) -> Vec<u8> {
+    let size = TAG_HEADER_LEN + regions.len() * entry_size;
+    let mut tag = Vec::with_capacity(size);
+    tag.extend_from_slice(&6u32.to_le_bytes());
+    tag.extend_from_slice(&(size as u32).to_le_bytes());
+    tag.extend_from_slice(&(entry_size as u32).to_le_bytes());
+    tag.extend_from_slice(&0u32.to_le_bytes());
+    for &(base, length, entry_type) in regions {
+        let start = tag.len();
+        tag.extend_from_slice(&base.to_le_bytes());
+        tag.extend_from_slice(&length.to_le_bytes());
+        tag.extend_from_slice(&entry_type.to_le_bytes());
+        tag.resize(start + entry_size, 0);
+    }
+    tag
+}
+
+/// A typical QEMU map: low memory, the VGA/BIOS hole, the bulk of RAM,
+/// then ACPI tables and firmware just below 4 GiB.
+fn qemu_like() -> Vec<(u64, u64, u32)> {
+    vec![
+        (0x0, 0x9FC00, MEMORY_TYPE_AVAILABLE),
+        (0x9FC00, 0x400, MEMORY_TYPE_RESERVED),
+        (0xF0000, 0x10000, MEMORY_TYPE_RESERVED),
+        (0x10_0000, 126 * MIB, MEMORY_TYPE_AVAILABLE),
+        (0x7FE_0000, 0x20000, MEMORY_TYPE_ACPI_RECLAIMABLE),
+        (0x7FF_0000, 0x10000, MEMORY_TYPE_ACPI_NVS),
+        (0xFFFC_0000, 0x40000, MEMORY_TYPE_RESERVED),
+    ]
+}
+
+#[test]
+fn parses_every_entry_in_order() {
+    let regions = qemu_like();
+    let tag = build_tag(&regions, 24);
+    let parsed: Vec<MmapEntry> = entries(&tag).collect();
+    assert_eq!(parsed.len(), regions.len());
+    for (entry, &(base, length, entry_type)) in parsed.iter().zip(regions.iter()) {
+        assert_eq!(*entry, MmapEntry { base, length, entry_type });
+    }
+}
+
+#[test]
+fn splits_usable_from_installed() {
+    let tag = build_tag(&qemu_like(), 24);
+    let totals = totals(entries(&tag));
+    assert_eq!(totals.usable_regions, 2);
+    assert_eq!(totals.usable_bytes, 0x9FC00 + 126 * MIB);
+    assert_eq!(totals.reserved_regions, 5);
+    assert_eq!(totals.reserved_bytes, 0x400 + 0x10000 + 0x20000 + 0x10000 + 0x40000);
+    assert_eq!(totals.total_installed, totals.usable_bytes + totals.reserved_bytes);
+    assert_eq!(totals.largest_contiguous, 126 * MIB);
+}
+
+#[test]
+fn honours_larger_entry_size() {
+    let regions = [
+        (0x10_0000, 4 * MIB, MEMORY_TYPE_AVAILABLE),
+        (0x50_0000, MIB, MEMORY_TYPE_BAD),
+    ];
+    let tag = build_tag(&regions, 32);
+    let parsed: Vec<MmapEntry> = entries(&tag).collect();
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed[1].entry_type, MEMORY_TYPE_BAD);
+    assert_eq!(parsed[1].type_name(), "bad");
+    let totals = totals(parsed);
+    assert_eq!(totals.usable_bytes, 4 * MIB);
+    assert_eq!(totals.reserved_bytes, MIB);
+}
+
+#[test]
+fn skips_empty_entries_in_totals() {
+    let regions = [
+        (0x10_0000, 0, MEMORY_TYPE_AVAILABLE),
+        (0x20_0000, 0, MEMORY_TYPE_RESERVED),
+        (0x30_0000, MIB, MEMORY_TYPE_AVAILABLE),
+    ];
+    let totals = totals(entries(&build_tag(&regions, 24)));
+    assert_eq!(totals.usable_regions, 1);
+    assert_eq!(totals.reserved_regions, 0);
+    assert_eq!(totals.total_installed, MIB);
+}
+
+#[test]
+fn truncated_or_malformed_tags_stop_cleanly() {
+    let tag = build_tag(&qemu_like(), 24);
+    // Cut the last entry in half; only the whole ones come back.
+    let cut = &tag[..tag.len() - 12];
+    assert_eq!(entries(cut).count(), qemu_like().len() - 1);
+
+    let mut tiny = build_tag(&qemu_like(), 24);
+    tiny[8..12].copy_from_slice(&8u32.to_le_bytes());
+    assert_eq!(entries(&tiny).count(), 0);
+
+    assert_eq!(entries(&tag[..4]).count(), 0);
+}
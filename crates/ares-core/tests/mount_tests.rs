@@ -0,0 +1,66 @@
+use ares_core::vfs::mount::{FileSystem, MountTable, MAX_MOUNTS};
+use ares_core::vfs::{VfsError, VfsFile, VfsResult};
+
+struct Named(&'static str);
+
+impl FileSystem for Named {
+    fn open(&'static self, _path: &str) -> VfsResult<&'static dyn VfsFile> {
+        Err(VfsError::NotFound)
+    }
+}
+
+static ROOT_FS: Named = Named("tmp");
+static NESTED_FS: Named = Named("cache");
+
+fn owner<'a>(table: &MountTable, path: &'a str) -> Option<(&'static str, &'a str)> {
+    table.lookup(path).map(|(fs, rest)| {
+        let ptr = fs as *const dyn FileSystem as *const Named;
+        (unsafe { &*ptr }.0, rest)
+    })
+}
+
+#[test]
+fn prefix_is_stripped() {
+    let mut table = MountTable::new();
+    table.mount("/tmp", &ROOT_FS).unwrap();
+    assert_eq!(owner(&table, "/tmp"), Some(("tmp", "")));
+    assert_eq!(owner(&table, "/tmp/"), Some(("tmp", "")));
+    assert_eq!(owner(&table, "/tmp/a/b.txt"), Some(("tmp", "a/b.txt")));
+}
+
+#[test]
+fn partial_component_does_not_match() {
+    let mut table = MountTable::new();
+    table.mount("/tmp", &ROOT_FS).unwrap();
+    assert!(owner(&table, "/tmpfile").is_none());
+    assert!(owner(&table, "/dev/null").is_none());
+}
+
+#[test]
+fn longest_prefix_wins() {
+    let mut table = MountTable::new();
+    table.mount("/tmp", &ROOT_FS).unwrap();
+    table.mount("/tmp/cache", &NESTED_FS).unwrap();
+    assert_eq!(owner(&table, "/tmp/cache/x"), Some(("cache", "x")));
+    assert_eq!(owner(&table, "/tmp/cachex"), Some(("tmp", "cachex")));
+
+    assert!(table.unmount("/tmp/cache"));
+    assert_eq!(owner(&table, "/tmp/cache/x"), Some(("tmp", "cache/x")));
+}
+
+#[test]
+fn bad_prefixes_and_full_table_are_rejected() {
+    let mut table = MountTable::new();
+    for prefix in ["tmp", "/", "/tmp/"] {
+        assert_eq!(table.mount(prefix, &ROOT_FS), Err(VfsError::Unsupported));
+    }
+    let prefixes: Vec<&'static str> = (0..=MAX_MOUNTS)
+        .map(|i| &*Box::leak(format!("/m{i}").into_boxed_str()))
+        .collect();
+    for prefix in &prefixes[..MAX_MOUNTS] {
+        table.mount(prefix, &ROOT_FS).unwrap();
+    }
+    // Remounting an existing prefix replaces it instead of taking a slot.
+    table.mount(prefixes[0], &NESTED_FS).unwrap();
+    assert_eq!(table.mount(prefixes[MAX_MOUNTS], &ROOT_FS), Err(VfsError::NoSpace));
+}
@@ -0,0 +1,106 @@
+use ares_core::boot::multiboot::{self, BootModule, TAG_TYPE_CMDLINE, TAG_TYPE_END, TAG_TYPE_MMAP, TAG_TYPE_MODULE};
+
+/// Builds an info block tag by tag, padding each to 8 bytes as GRUB does.
+struct InfoBlock {
+    bytes: Vec<u8>,
+}
+
+impl InfoBlock {
+    fn new() -> Self {
+        Self { bytes: vec![0; 8] }
+    }
+
+    fn tag(mut self, tag_type: u32, body: &[u8]) -> Self {
+        self.bytes.extend_from_slice(&tag_type.to_le_bytes());
+        self.bytes.extend_from_slice(&(8 + body.len() as u32).to_le_bytes());
+        self.bytes.extend_from_slice(body);
+        while !self.bytes.len().is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        self
+    }
+
+    fn module(self, start: u32, end: u32, name: &str) -> Self {
+        let mut body = Vec::new();
+        body.extend_from_slice(&start.to_le_bytes());
+        body.extend_from_slice(&end.to_le_bytes());
+        body.extend_from_slice(name.as_bytes());
+        body.push(0);
+        self.tag(TAG_TYPE_MODULE, &body)
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut block = self.tag(TAG_TYPE_END, &[]).bytes;
+        let total = block.len() as u32;
+        block[..4].copy_from_slice(&total.to_le_bytes());
+        block
+    }
+}
+
+#[test]
+fn finds_a_module_among_other_tags() {
+    let info = InfoBlock::new()
+        .tag(TAG_TYPE_CMDLINE, b"init=/fat/initrd/INIT\0")
+        .module(0x0020_0000, 0x0020_8000, "initrd")
+        .tag(TAG_TYPE_MMAP, &[0; 40])
+        .finish();
+
+    let modules: Vec<_> = multiboot::modules(&info).collect();
+    assert_eq!(
+        modules,
+        vec![BootModule {
+            start: 0x0020_0000,
+            end: 0x0020_8000,
+            name: "initrd",
+        }]
+    );
+    assert_eq!(modules[0].len(), 0x8000);
+}
+
+#[test]
+fn lists_every_tag_up_to_the_end_tag() {
+    let info = InfoBlock::new()
+        .tag(TAG_TYPE_CMDLINE, b"x\0")
+        .module(0x1000, 0x2000, "a")
+        .module(0x3000, 0x5000, "b")
+        .finish();
+    let types: Vec<_> = multiboot::tags(&info).map(|tag| tag.tag_type).collect();
+    assert_eq!(types, vec![TAG_TYPE_CMDLINE, TAG_TYPE_MODULE, TAG_TYPE_MODULE]);
+
+    let names: Vec<_> = multiboot::modules(&info).map(|module| module.name).collect();
+    assert_eq!(names, vec!["a", "b"]);
+}
+
+#[test]
+fn module_without_a_string_has_an_empty_name() {
+    let mut body = Vec::new();
+    body.extend_from_slice(&0x4000u32.to_le_bytes());
+    body.extend_from_slice(&0x5000u32.to_le_bytes());
+    let info = InfoBlock::new().tag(TAG_TYPE_MODULE, &body).finish();
+
+    let module = multiboot::modules(&info).next().unwrap();
+    assert_eq!(module.name, "");
+    assert_eq!((module.start, module.end), (0x4000, 0x5000));
+}
+
+#[test]
+fn malformed_blocks_stop_the_walk() {
+    // A tag claiming to run past the block.
+    let mut info = InfoBlock::new().module(0x1000, 0x2000, "cut").finish();
+    info[12..16].copy_from_slice(&0x1000u32.to_le_bytes());
+    assert_eq!(multiboot::tags(&info).count(), 0);
+
+    // A total size shorter than the buffer hides what follows.
+    let mut info = InfoBlock::new().module(0x1000, 0x2000, "a").module(0x3000, 0x4000, "b").finish();
+    let first_only = 8 + 24;
+    info[..4].copy_from_slice(&(first_only as u32).to_le_bytes());
+    assert_eq!(multiboot::modules(&info).count(), 1);
+
+    // Too short to hold even the header.
+    assert_eq!(multiboot::tags(&[0u8; 4]).count(), 0);
+
+    // A module tag too short for its addresses is skipped.
+    let info = InfoBlock::new().tag(TAG_TYPE_MODULE, &[0; 4]).finish();
+    assert_eq!(multiboot::tags(&info).count(), 1);
+    assert_eq!(multiboot::modules(&info).count(), 0);
+}
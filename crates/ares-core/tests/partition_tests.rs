@@ -0,0 +1,102 @@
+use ares_core::drivers::mock::MemBlockDevice;
+use ares_core::drivers::partition::{
+    parse_gpt_entry, parse_gpt_header, parse_mbr, Partition, PartitionEntry, PartitionTable, SECTOR_SIZE,
+};
+use ares_core::drivers::{BlockDevice, DriverError};
+
+fn disk(sectors: usize) -> &'static MemBlockDevice {
+    let mut data = vec![0u8; sectors * SECTOR_SIZE];
+    for (index, sector) in data.chunks_mut(SECTOR_SIZE).enumerate() {
+        sector.fill(index as u8);
+    }
+    Box::leak(Box::new(MemBlockDevice::new("disk", data, SECTOR_SIZE)))
+}
+
+#[test]
+fn partition_offsets_requests() {
+    let parent = disk(16);
+    let part = Partition::new("diskp1", parent, 4, 8);
+
+    let mut buf = [0u8; SECTOR_SIZE * 2];
+    part.read_blocks(0, &mut buf).unwrap();
+    assert!(buf[..SECTOR_SIZE].iter().all(|&b| b == 4));
+    assert!(buf[SECTOR_SIZE..].iter().all(|&b| b == 5));
+
+    part.write_blocks(7, &[0xAB; SECTOR_SIZE]).unwrap();
+    let mut raw = [0u8; SECTOR_SIZE];
+    parent.read_blocks(11, &mut raw).unwrap();
+    assert!(raw.iter().all(|&b| b == 0xAB));
+}
+
+#[test]
+fn partition_rejects_out_of_range() {
+    let parent = disk(16);
+    let part = Partition::new("diskp1", parent, 4, 8);
+
+    let mut one = [0u8; SECTOR_SIZE];
+    assert_eq!(part.read_blocks(8, &mut one), Err(DriverError::IoError));
+    let mut two = [0u8; SECTOR_SIZE * 2];
+    assert_eq!(part.read_blocks(7, &mut two), Err(DriverError::IoError));
+    assert_eq!(part.write_blocks(u64::MAX, &one), Err(DriverError::IoError));
+    assert_eq!(part.read_blocks(0, &mut [0u8; 3]), Err(DriverError::Unsupported));
+
+    // Nothing past the partition was touched.
+    parent.read_blocks(12, &mut one).unwrap();
+    assert!(one.iter().all(|&b| b == 12));
+}
+
+fn mbr_with(entries: &[(usize, u8, u32, u32)]) -> [u8; SECTOR_SIZE] {
+    let mut sector = [0u8; SECTOR_SIZE];
+    for &(slot, kind, start, count) in entries {
+        let raw = &mut sector[446 + slot * 16..][..16];
+        raw[4] = kind;
+        raw[8..12].copy_from_slice(&start.to_le_bytes());
+        raw[12..16].copy_from_slice(&count.to_le_bytes());
+    }
+    sector[510] = 0x55;
+    sector[511] = 0xAA;
+    sector
+}
+
+#[test]
+fn mbr_lists_used_slots() {
+    let sector = mbr_with(&[(0, 0x06, 2048, 4096), (2, 0x83, 8192, 100)]);
+    match parse_mbr(&sector) {
+        PartitionTable::Mbr(entries) => {
+            assert_eq!(entries[0], Some(PartitionEntry { start_lba: 2048, blocks: 4096, kind: 0x06 }));
+            assert_eq!(entries[1], None);
+            assert_eq!(entries[2], Some(PartitionEntry { start_lba: 8192, blocks: 100, kind: 0x83 }));
+            assert_eq!(entries[3], None);
+        }
+        other => panic!("expected MBR, got {:?}", other),
+    }
+}
+
+#[test]
+fn mbr_without_signature_or_protective() {
+    let mut sector = mbr_with(&[(0, 0x06, 2048, 4096)]);
+    sector[511] = 0;
+    assert_eq!(parse_mbr(&sector), PartitionTable::None);
+
+    let sector = mbr_with(&[(0, 0xEE, 1, 0xFFFF_FFFF)]);
+    assert_eq!(parse_mbr(&sector), PartitionTable::Gpt);
+}
+
+#[test]
+fn gpt_header_and_entries() {
+    let mut header = [0u8; SECTOR_SIZE];
+    header[0..8].copy_from_slice(b"EFI PART");
+    header[72..80].copy_from_slice(&2u64.to_le_bytes());
+    header[80..84].copy_from_slice(&128u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    let parsed = parse_gpt_header(&header).unwrap();
+    assert_eq!(parsed.entries_lba, 2);
+    assert_eq!(parsed.entry_count, 128);
+
+    let mut entry = [0u8; 128];
+    assert_eq!(parse_gpt_entry(&entry), None);
+    entry[0] = 0xAF;
+    entry[32..40].copy_from_slice(&34u64.to_le_bytes());
+    entry[40..48].copy_from_slice(&133u64.to_le_bytes());
+    assert_eq!(parse_gpt_entry(&entry), Some(PartitionEntry { start_lba: 34, blocks: 100, kind: 0 }));
+}
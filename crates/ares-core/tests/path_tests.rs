@@ -0,0 +1,50 @@
+use ares_core::vfs::path::MAX_PATH;
+use ares_core::vfs::{normalize_path, VfsError};
+
+fn normal(path: &str) -> String {
+    normalize_path(path).expect("path should normalize").as_str().to_string()
+}
+
+#[test]
+fn dot_components_collapse() {
+    assert_eq!(normal("/fat/./HELLO.TXT"), "/fat/HELLO.TXT");
+    assert_eq!(normal("/fat/SUB/../HELLO.TXT"), "/fat/HELLO.TXT");
+    assert_eq!(normal("/fat/A/B/../../C"), "/fat/C");
+    assert_eq!(normal("/fat/A/./../B/."), "/fat/B");
+}
+
+#[test]
+fn slashes_are_tidied() {
+    assert_eq!(normal("/"), "/");
+    assert_eq!(normal("//"), "/");
+    assert_eq!(normal("/fat/"), "/fat");
+    assert_eq!(normal("//dev///null//"), "/dev/null");
+    assert_eq!(normal("/fat/.."), "/");
+    assert_eq!(normal("/."), "/");
+}
+
+#[test]
+fn names_with_dots_are_kept() {
+    assert_eq!(normal("/tmp/.hidden"), "/tmp/.hidden");
+    assert_eq!(normal("/tmp/..."), "/tmp/...");
+    assert_eq!(normal("/tmp/a..b"), "/tmp/a..b");
+}
+
+#[test]
+fn escaping_root_is_rejected() {
+    assert_eq!(normalize_path("/..").unwrap_err(), VfsError::InvalidPath);
+    assert_eq!(normalize_path("/fat/../..").unwrap_err(), VfsError::InvalidPath);
+    assert_eq!(normalize_path("/fat/../../fat/HELLO.TXT").unwrap_err(), VfsError::InvalidPath);
+}
+
+#[test]
+fn relative_and_oversized_paths_are_rejected() {
+    assert_eq!(normalize_path("").unwrap_err(), VfsError::InvalidPath);
+    assert_eq!(normalize_path("fat/HELLO.TXT").unwrap_err(), VfsError::InvalidPath);
+
+    let long = format!("/{}", "a".repeat(MAX_PATH));
+    assert_eq!(normalize_path(&long).unwrap_err(), VfsError::InvalidPath);
+    // Parts that collapse away do not count against the limit.
+    let collapsing = format!("/{}/../ok", "a".repeat(MAX_PATH - 8));
+    assert_eq!(normal(&collapsing), "/ok");
+}
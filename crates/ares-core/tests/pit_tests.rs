@@ -0,0 +1,39 @@
+use ares_core::interrupts::pit::{
+    divisor_for, frequency_for, MAX_DIVISOR, MAX_FREQUENCY_HZ, MIN_DIVISOR, MIN_FREQUENCY_HZ, PIT_CLOCK_HZ,
+};
+
+#[test]
+fn common_rates_round_to_nearest() {
+    // 1193182 / 100 = 11931.82, so 11932 is closer than 11931.
+    assert_eq!(divisor_for(100), 11932);
+    assert_eq!(frequency_for(11932), 100);
+    assert_eq!(divisor_for(1000), 1193);
+    assert_eq!(frequency_for(1193), 1000);
+    // 1193182 / 400000 = 2.98 rounds up.
+    assert_eq!(divisor_for(400_000), 3);
+}
+
+#[test]
+fn slow_requests_clamp_to_floor() {
+    assert_eq!(divisor_for(0), MAX_DIVISOR);
+    assert_eq!(divisor_for(1), MAX_DIVISOR);
+    assert_eq!(divisor_for(MIN_FREQUENCY_HZ), MAX_DIVISOR);
+    assert_eq!(frequency_for(MAX_DIVISOR), MIN_FREQUENCY_HZ);
+    // 19 Hz is the first rate the divisor can still express.
+    assert!(divisor_for(19) < MAX_DIVISOR);
+}
+
+#[test]
+fn fast_requests_clamp_to_ceiling() {
+    assert_eq!(divisor_for(MAX_FREQUENCY_HZ), MIN_DIVISOR);
+    assert_eq!(divisor_for(PIT_CLOCK_HZ * 2), MIN_DIVISOR);
+    assert_eq!(divisor_for(u32::MAX), MIN_DIVISOR);
+    assert_eq!(frequency_for(MIN_DIVISOR), MAX_FREQUENCY_HZ);
+    // Half the clock still needs divisor 2.
+    assert_eq!(divisor_for(PIT_CLOCK_HZ / 2), 2);
+}
+
+#[test]
+fn zero_divisor_reads_as_one() {
+    assert_eq!(frequency_for(0), PIT_CLOCK_HZ);
+}
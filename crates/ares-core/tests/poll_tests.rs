@@ -0,0 +1,90 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use ares_core::drivers::poll::{poll_until, Backoff, PollClock, BACKOFF_CAP_MS};
+
+struct WallClock(Instant);
+
+impl PollClock for WallClock {
+    fn now_ms(&self) -> u64 {
+        self.0.elapsed().as_millis() as u64
+    }
+
+    fn pause(&self, ms: u64) {
+        if ms == 0 {
+            std::thread::yield_now();
+        } else {
+            std::thread::sleep(Duration::from_millis(ms));
+        }
+    }
+}
+
+/// Time only moves when someone pauses; records every pause asked for.
+struct FakeClock {
+    now: Cell<u64>,
+    pauses: Cell<Vec<u64>>,
+}
+
+impl PollClock for FakeClock {
+    fn now_ms(&self) -> u64 {
+        self.now.get()
+    }
+
+    fn pause(&self, ms: u64) {
+        self.now.set(self.now.get() + ms.max(1));
+        let mut pauses = self.pauses.take();
+        pauses.push(ms);
+        self.pauses.set(pauses);
+    }
+}
+
+#[test]
+fn backoff_doubles_up_to_cap() {
+    let mut backoff = Backoff::new(8);
+    let delays: Vec<u64> = (0..7).map(|_| backoff.next_delay()).collect();
+    assert_eq!(delays, [0, 1, 2, 4, 8, 8, 8]);
+}
+
+#[test]
+fn never_ready_times_out_near_the_deadline() {
+    let clock = WallClock(Instant::now());
+    let mut reads = 0u32;
+    let started = Instant::now();
+    let result: Option<()> = poll_until(&clock, 50, || {
+        reads += 1;
+        None
+    });
+    let elapsed = started.elapsed();
+
+    assert_eq!(result, None);
+    assert!(elapsed >= Duration::from_millis(50), "gave up after {:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(500), "overran to {:?}", elapsed);
+    // Backing off means a handful of reads, not a spin budget's worth.
+    assert!(reads < 50, "{} status reads", reads);
+}
+
+#[test]
+fn ready_device_returns_without_pausing() {
+    let clock = FakeClock { now: Cell::new(0), pauses: Cell::new(Vec::new()) };
+    assert_eq!(poll_until(&clock, 100, || Some(7)), Some(7));
+    assert!(clock.pauses.take().is_empty());
+}
+
+#[test]
+fn last_pause_is_clipped_to_the_deadline() {
+    let clock = FakeClock { now: Cell::new(0), pauses: Cell::new(Vec::new()) };
+    let mut reads = 0;
+    let result = poll_until(&clock, 20, || {
+        reads += 1;
+        if clock.now_ms() >= 20 {
+            Some(reads)
+        } else {
+            None
+        }
+    });
+    // Ready exactly at the deadline still counts.
+    assert!(result.is_some());
+    let pauses = clock.pauses.take();
+    assert!(pauses.iter().all(|&ms| ms <= BACKOFF_CAP_MS));
+    assert_eq!(pauses, [0, 1, 2, 4, 8, 4]);
+}
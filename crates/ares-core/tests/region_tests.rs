@@ -0,0 +1,115 @@
+use ares_core::mem::region::{Region, RegionError, RegionList};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Perm {
+    ReadWrite,
+    ReadOnly,
+}
+
+fn spans(list: &RegionList<Perm>) -> Vec<(usize, usize, Perm)> {
+    list.iter().map(|region| (region.start, region.end, region.attrs)).collect()
+}
+
+fn remove(list: &mut RegionList<Perm>, start: usize, end: usize) -> Result<Vec<(usize, usize)>, RegionError> {
+    let mut pieces = Vec::new();
+    list.remove(start, end, |piece| pieces.push((piece.start, piece.end)))?;
+    Ok(pieces)
+}
+
+#[test]
+fn adjacent_regions_with_equal_attrs_merge() {
+    let mut list = RegionList::new();
+    list.insert(Region::new(0x1000, 0x1000, Perm::ReadWrite)).unwrap();
+    list.insert(Region::new(0x3000, 0x1000, Perm::ReadWrite)).unwrap();
+    assert_eq!(list.len(), 2);
+
+    // Filling the gap joins all three into one.
+    list.insert(Region::new(0x2000, 0x1000, Perm::ReadWrite)).unwrap();
+    assert_eq!(spans(&list), vec![(0x1000, 0x4000, Perm::ReadWrite)]);
+}
+
+#[test]
+fn different_attrs_stay_separate() {
+    let mut list = RegionList::new();
+    list.insert(Region::new(0x1000, 0x1000, Perm::ReadWrite)).unwrap();
+    list.insert(Region::new(0x2000, 0x1000, Perm::ReadOnly)).unwrap();
+    assert_eq!(
+        spans(&list),
+        vec![(0x1000, 0x2000, Perm::ReadWrite), (0x2000, 0x3000, Perm::ReadOnly)]
+    );
+}
+
+#[test]
+fn overlapping_and_empty_inserts_are_rejected() {
+    let mut list = RegionList::new();
+    list.insert(Region::new(0x1000, 0x2000, Perm::ReadWrite)).unwrap();
+    assert_eq!(list.insert(Region::new(0x2000, 0x1000, Perm::ReadWrite)), Err(RegionError::Overlap));
+    assert_eq!(list.insert(Region::new(0x0800, 0x1000, Perm::ReadOnly)), Err(RegionError::Overlap));
+    assert_eq!(list.insert(Region::new(0x5000, 0, Perm::ReadWrite)), Err(RegionError::Empty));
+    assert_eq!(list.len(), 1);
+}
+
+#[test]
+fn freeing_the_middle_splits_the_region() {
+    let mut list = RegionList::new();
+    list.insert(Region::new(0x1000, 0x3000, Perm::ReadWrite)).unwrap();
+
+    assert_eq!(remove(&mut list, 0x2000, 0x3000), Ok(vec![(0x2000, 0x3000)]));
+    assert_eq!(
+        spans(&list),
+        vec![(0x1000, 0x2000, Perm::ReadWrite), (0x3000, 0x4000, Perm::ReadWrite)]
+    );
+    assert!(list.find(0x2800).is_none());
+    assert_eq!(list.find(0x3800).map(|region| region.start), Some(0x3000));
+}
+
+#[test]
+fn freeing_across_regions_trims_each_and_skips_gaps() {
+    let mut list = RegionList::new();
+    list.insert(Region::new(0x1000, 0x1000, Perm::ReadWrite)).unwrap();
+    list.insert(Region::new(0x2000, 0x1000, Perm::ReadOnly)).unwrap();
+    list.insert(Region::new(0x5000, 0x2000, Perm::ReadWrite)).unwrap();
+
+    assert_eq!(
+        remove(&mut list, 0x1800, 0x6000),
+        Ok(vec![(0x1800, 0x2000), (0x2000, 0x3000), (0x5000, 0x6000)])
+    );
+    assert_eq!(
+        spans(&list),
+        vec![(0x1000, 0x1800, Perm::ReadWrite), (0x6000, 0x7000, Perm::ReadWrite)]
+    );
+}
+
+#[test]
+fn refilling_a_freed_hole_merges_again() {
+    let mut list = RegionList::new();
+    list.insert(Region::new(0x1000, 0x3000, Perm::ReadWrite)).unwrap();
+    remove(&mut list, 0x2000, 0x3000).unwrap();
+    list.insert(Region::new(0x2000, 0x1000, Perm::ReadWrite)).unwrap();
+    assert_eq!(spans(&list), vec![(0x1000, 0x4000, Perm::ReadWrite)]);
+}
+
+#[test]
+fn freeing_unmapped_or_empty_ranges_fails() {
+    let mut list = RegionList::new();
+    list.insert(Region::new(0x1000, 0x1000, Perm::ReadWrite)).unwrap();
+    assert_eq!(remove(&mut list, 0x4000, 0x5000), Err(RegionError::NotMapped));
+    assert_eq!(remove(&mut list, 0x1000, 0x1000), Err(RegionError::Empty));
+    assert_eq!(remove(&mut list, 0x0000, 0x4000), Ok(vec![(0x1000, 0x2000)]));
+    assert!(list.is_empty());
+}
+
+#[test]
+fn list_grows_past_its_first_allocation() {
+    let mut list = RegionList::new();
+    for i in 0..32 {
+        // Alternate attributes so nothing merges.
+        let perm = if i % 2 == 0 { Perm::ReadWrite } else { Perm::ReadOnly };
+        list.insert(Region::new(0x1000 * (32 - i), 0x1000, perm)).unwrap();
+    }
+    assert_eq!(list.len(), 32);
+    let starts: Vec<usize> = list.iter().map(|region| region.start).collect();
+    assert!(starts.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(list.drain().count(), 32);
+    assert!(list.is_empty());
+}
@@ -0,0 +1,88 @@
+use ares_core::drivers::scancode::{KeyCode, ScancodeDecoder};
+
+fn feed_all(decoder: &mut ScancodeDecoder, scancodes: &[u8]) -> Vec<u8> {
+    scancodes
+        .iter()
+        .flat_map(|&code| decoder.feed(code).as_slice().to_vec())
+        .collect()
+}
+
+#[test]
+fn extended_up_arrow_emits_csi_a() {
+    let mut decoder = ScancodeDecoder::new();
+    assert!(decoder.feed(0xE0).is_empty());
+    assert_eq!(decoder.feed(0x48).as_slice(), b"\x1b[A");
+    // The release (E0 C8) produces nothing.
+    assert!(feed_all(&mut decoder, &[0xE0, 0xC8]).is_empty());
+}
+
+#[test]
+fn extended_prefix_only_applies_to_next_code() {
+    let mut decoder = ScancodeDecoder::new();
+    // 0x48 without the prefix is keypad 8, which the base map ignores;
+    // 0x4B after E0 is Left.
+    assert_eq!(feed_all(&mut decoder, &[0xE0, 0x4B, 0x1E]), b"\x1b[Da");
+}
+
+#[test]
+fn editing_keys_map_to_xterm_sequences() {
+    let mut decoder = ScancodeDecoder::new();
+    assert_eq!(feed_all(&mut decoder, &[0xE0, 0x47]), KeyCode::Home.sequence());
+    assert_eq!(feed_all(&mut decoder, &[0xE0, 0x4F]), KeyCode::End.sequence());
+    assert_eq!(feed_all(&mut decoder, &[0xE0, 0x53]), b"\x1b[3~");
+}
+
+#[test]
+fn function_keys_map_to_xterm_sequences() {
+    let mut decoder = ScancodeDecoder::new();
+    assert_eq!(decoder.feed(0x3B).as_slice(), b"\x1bOP");
+    assert_eq!(decoder.feed(0x3F).as_slice(), b"\x1b[15~");
+    assert_eq!(decoder.feed(0x58).as_slice(), b"\x1b[24~");
+}
+
+#[test]
+fn fake_shift_does_not_latch_shift() {
+    let mut decoder = ScancodeDecoder::new();
+    assert_eq!(feed_all(&mut decoder, &[0xE0, 0x2A, 0x1E]), b"a");
+    assert_eq!(feed_all(&mut decoder, &[0x2A, 0x1E, 0xAA, 0x1E]), b"Aa");
+}
+
+#[test]
+fn pause_sequence_is_swallowed() {
+    let mut decoder = ScancodeDecoder::new();
+    assert_eq!(feed_all(&mut decoder, &[0xE1, 0x1D, 0x45, 0xE1, 0x9D, 0xC5, 0x1E]), b"a");
+}
+
+#[test]
+fn ctrl_c_yields_etx() {
+    let mut decoder = ScancodeDecoder::new();
+    assert!(decoder.feed(0x1D).is_empty());
+    assert_eq!(decoder.feed(0x2E).as_slice(), &[0x03]);
+    // Releasing ctrl (0x9D) returns letters to normal.
+    assert_eq!(feed_all(&mut decoder, &[0x9D, 0x2E]), b"c");
+}
+
+#[test]
+fn ctrl_masks_letters_regardless_of_shift() {
+    let mut decoder = ScancodeDecoder::new();
+    assert_eq!(feed_all(&mut decoder, &[0x1D, 0x1E, 0x2A, 0x2C, 0xAA, 0x20]), &[0x01, 0x1A, 0x04]);
+    // Right ctrl arrives as E0 1D / E0 9D.
+    assert_eq!(feed_all(&mut decoder, &[0x9D, 0xE0, 0x1D, 0x20, 0xE0, 0x9D, 0x20]), &[0x04, b'd']);
+}
+
+#[test]
+fn state_survives_a_round_trip_through_bits() {
+    let mut decoder = ScancodeDecoder::new();
+    // Shift and caps lock held, ctrl down, and halfway through Pause.
+    for &code in &[0x2A, 0x3A, 0x1D, 0xE1] {
+        decoder.feed(code);
+    }
+    let mut restored = ScancodeDecoder::from_bits(decoder.to_bits());
+    assert_eq!(restored.to_bits(), decoder.to_bits());
+    assert_eq!(feed_all(&mut restored, &[0x1D, 0x45, 0x9D, 0x1E]), b"a");
+
+    let mut extended = ScancodeDecoder::from_bits(ScancodeDecoder::new().to_bits());
+    extended.feed(0xE0);
+    let mut restored = ScancodeDecoder::from_bits(extended.to_bits());
+    assert_eq!(restored.feed(0x48).as_slice(), KeyCode::Up.sequence());
+}
@@ -0,0 +1,84 @@
+use ares_core::drivers::screen::{cell, Screen};
+
+const ATTR: u8 = 0x0F;
+const W: usize = 6;
+const H: usize = 3;
+
+fn text(screen: &Screen<W, H>, row: usize) -> String {
+    screen.row_cells(row).iter().map(|c| (c & 0xFF) as u8 as char).collect()
+}
+
+fn grid(screen: &Screen<W, H>) -> [String; H] {
+    [text(screen, 0), text(screen, 1), text(screen, 2)]
+}
+
+fn write(screen: &mut Screen<W, H>, bytes: &[u8]) -> Vec<String> {
+    let mut scrolled = Vec::new();
+    screen.write(bytes, |row| scrolled.push(row.iter().map(|c| (c & 0xFF) as u8 as char).collect()));
+    scrolled
+}
+
+#[test]
+fn plain_text_lands_in_order() {
+    let mut screen = Screen::<W, H>::new(ATTR);
+    assert!(write(&mut screen, b"ab\ncd").is_empty());
+    assert_eq!(grid(&screen), ["ab    ", "cd    ", "      "]);
+    assert_eq!((screen.row, screen.col), (1, 2));
+    assert_eq!(screen.row_cells(0)[0], cell(b'a', ATTR));
+    assert_eq!(screen.take_dirty(), Some((0, 1)));
+    assert_eq!(screen.take_dirty(), None);
+}
+
+#[test]
+fn long_lines_wrap_at_the_edge() {
+    let mut screen = Screen::<W, H>::new(ATTR);
+    write(&mut screen, b"abcdef");
+    // A full row leaves the cursor past the edge until the next byte.
+    assert_eq!((screen.row, screen.col), (0, W));
+    write(&mut screen, b"g");
+    assert_eq!(grid(&screen), ["abcdef", "g     ", "      "]);
+    assert_eq!((screen.row, screen.col), (1, 1));
+}
+
+#[test]
+fn many_newlines_scroll_rows_out_oldest_first() {
+    let mut screen = Screen::<W, H>::new(ATTR);
+    let scrolled = write(&mut screen, b"1\n2\n3\n4\n5");
+    assert_eq!(scrolled, ["1     ", "2     "]);
+    assert_eq!(grid(&screen), ["3     ", "4     ", "5     "]);
+    assert_eq!((screen.row, screen.col), (2, 1));
+    assert_eq!(screen.take_dirty(), Some((0, 2)));
+}
+
+#[test]
+fn carriage_return_and_tabs() {
+    let mut screen = Screen::<W, H>::new(ATTR);
+    write(&mut screen, b"abc\rX");
+    assert_eq!(text(&screen, 0), "Xbc   ");
+    // The next tab stop past column 5 is off a six-wide row.
+    write(&mut screen, b"\n\tY");
+    assert_eq!(text(&screen, 1), "      ");
+    assert_eq!(text(&screen, 2), "Y     ");
+}
+
+#[test]
+fn attribute_and_clear() {
+    let mut screen = Screen::<W, H>::new(ATTR);
+    screen.attr = 0x1E;
+    write(&mut screen, b"a");
+    assert_eq!(screen.row_cells(0)[0], cell(b'a', 0x1E));
+    screen.take_dirty();
+    screen.attr = ATTR;
+    screen.clear();
+    assert_eq!(grid(&screen), ["      ", "      ", "      "]);
+    assert_eq!(screen.row_cells(0)[0], cell(b' ', ATTR));
+    assert_eq!((screen.row, screen.col), (0, 0));
+    assert_eq!(screen.take_dirty(), Some((0, H - 1)));
+}
+
+#[test]
+fn move_to_clamps() {
+    let mut screen = Screen::<W, H>::new(ATTR);
+    screen.move_to(10, 10);
+    assert_eq!((screen.row, screen.col), (H - 1, W - 1));
+}
@@ -0,0 +1,112 @@
+#![cfg(debug_assertions)]
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use ares_core::sync::spinlock::{set_owner_hook, set_report_hook, Contention, LockOwner, SpinLock};
+
+#[derive(Debug)]
+struct Report {
+    waiter_line: u32,
+    held_line: Option<u32>,
+    holder: Option<LockOwner>,
+    reentrant: bool,
+}
+
+static REPORTS: Mutex<Vec<Report>> = Mutex::new(Vec::new());
+static NEXT_PID: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    static PID: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Every test thread gets its own pid, handed out on first use.
+fn thread_owner() -> LockOwner {
+    let pid = PID.with(|pid| {
+        if pid.get() == 0 {
+            pid.set(NEXT_PID.fetch_add(1, Ordering::Relaxed));
+        }
+        pid.get()
+    });
+    LockOwner { pid, cpu: 0 }
+}
+
+/// Record the report, then unwind out of a re-entrant lock, which would
+/// otherwise spin forever.
+fn record(contention: &Contention) {
+    REPORTS.lock().unwrap().push(Report {
+        waiter_line: contention.waiter.line(),
+        held_line: contention.held_at.map(|site| site.line()),
+        holder: contention.holder,
+        reentrant: contention.reentrant,
+    });
+    if contention.reentrant {
+        panic!("re-entrant spinlock");
+    }
+}
+
+fn install() {
+    set_owner_hook(thread_owner);
+    set_report_hook(record);
+}
+
+#[test]
+fn reentrant_lock_is_reported() {
+    install();
+    let lock = Arc::new(SpinLock::new(0u32));
+    let shared = Arc::clone(&lock);
+    let (me, held_line, waiter_line) = thread::spawn(move || {
+        let held_line = line!() + 1;
+        let _outer = shared.lock();
+        let me = thread_owner();
+        let waiter_line = line!() + 1;
+        let _inner = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| shared.lock()));
+        assert!(_inner.is_err(), "second lock must not succeed");
+        (me, held_line, waiter_line)
+    })
+    .join()
+    .unwrap();
+
+    let reports = REPORTS.lock().unwrap();
+    let report = reports
+        .iter()
+        .find(|report| report.reentrant && report.holder == Some(me))
+        .expect("re-entrant attempt was not reported");
+    assert_eq!(report.waiter_line, waiter_line);
+    assert_eq!(report.held_line, Some(held_line));
+    drop(reports);
+
+    // The outer guard was dropped normally, so the lock is free again.
+    assert!(lock.try_lock().is_some());
+}
+
+#[test]
+fn contention_between_owners_is_not_reentrant() {
+    install();
+    let lock = Arc::new(SpinLock::new(0u32));
+    let guard = lock.lock();
+    let holder = thread_owner();
+
+    let shared = Arc::clone(&lock);
+    let waiter = thread::spawn(move || {
+        *shared.lock() += 1;
+    });
+    // Hold the lock until the waiter has spun long enough to complain.
+    while !REPORTS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|report| report.holder == Some(holder))
+    {
+        thread::yield_now();
+    }
+    drop(guard);
+    waiter.join().unwrap();
+
+    let reports = REPORTS.lock().unwrap();
+    let report = reports.iter().find(|report| report.holder == Some(holder)).unwrap();
+    assert!(!report.reentrant);
+    assert_eq!(*lock.lock(), 1);
+}
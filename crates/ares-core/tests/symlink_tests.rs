@@ -0,0 +1,65 @@
+use ares_core::vfs::symlink::{SymlinkTable, MAX_LINK_DEPTH, MAX_SYMLINKS};
+use ares_core::vfs::VfsError;
+
+const CHAIN: [&str; MAX_LINK_DEPTH + 2] = ["/l0", "/l1", "/l2", "/l3", "/l4", "/l5", "/l6", "/l7", "/l8", "/l9"];
+
+#[test]
+fn plain_paths_resolve_to_themselves() {
+    let table = SymlinkTable::new();
+    assert_eq!(table.resolve("/dev/console"), Ok("/dev/console"));
+    assert_eq!(table.readlink("/dev/console"), None);
+}
+
+#[test]
+fn chains_are_followed() {
+    let mut table = SymlinkTable::new();
+    table.insert("/dev/stdout", "/dev/tty").unwrap();
+    table.insert("/dev/tty", "/dev/console").unwrap();
+    assert_eq!(table.readlink("/dev/stdout"), Some("/dev/tty"));
+    assert_eq!(table.resolve("/dev/stdout"), Ok("/dev/console"));
+}
+
+#[test]
+fn insert_replaces_existing_target() {
+    let mut table = SymlinkTable::new();
+    table.insert("/a", "/b").unwrap();
+    table.insert("/a", "/c").unwrap();
+    assert_eq!(table.resolve("/a"), Ok("/c"));
+    assert!(table.remove("/a"));
+    assert!(!table.remove("/a"));
+    assert_eq!(table.resolve("/a"), Ok("/a"));
+}
+
+#[test]
+fn cycles_are_rejected() {
+    let mut table = SymlinkTable::new();
+    table.insert("/self", "/self").unwrap();
+    table.insert("/ping", "/pong").unwrap();
+    table.insert("/pong", "/ping").unwrap();
+    assert_eq!(table.resolve("/self"), Err(VfsError::TooManyLinks));
+    assert_eq!(table.resolve("/ping"), Err(VfsError::TooManyLinks));
+}
+
+#[test]
+fn depth_limit_is_exact() {
+    let mut table = SymlinkTable::new();
+    for pair in CHAIN.windows(2).take(MAX_LINK_DEPTH) {
+        table.insert(pair[0], pair[1]).unwrap();
+    }
+    assert_eq!(table.resolve(CHAIN[0]), Ok(CHAIN[MAX_LINK_DEPTH]));
+
+    table.insert(CHAIN[MAX_LINK_DEPTH], CHAIN[MAX_LINK_DEPTH + 1]).unwrap();
+    assert_eq!(table.resolve(CHAIN[0]), Err(VfsError::TooManyLinks));
+}
+
+#[test]
+fn full_table_reports_no_space() {
+    let mut table = SymlinkTable::new();
+    let names: Vec<&'static str> = (0..=MAX_SYMLINKS)
+        .map(|i| &*Box::leak(format!("/link{i}").into_boxed_str()))
+        .collect();
+    for name in &names[..MAX_SYMLINKS] {
+        table.insert(name, "/target").unwrap();
+    }
+    assert_eq!(table.insert(names[MAX_SYMLINKS], "/target"), Err(VfsError::NoSpace));
+}
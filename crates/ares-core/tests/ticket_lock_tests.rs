@@ -0,0 +1,43 @@
+use std::sync::Arc;
+use std::thread;
+
+use ares_core::sync::ticket::TicketLock;
+
+#[test]
+fn lock_and_try_lock() {
+    let lock = TicketLock::new(5u32);
+    {
+        let mut guard = lock.lock();
+        *guard += 1;
+        assert!(lock.try_lock().is_none());
+        assert_eq!(lock.queued(), 1);
+    }
+    assert_eq!(lock.queued(), 0);
+    let guard = lock.try_lock().expect("lock should be free");
+    assert_eq!(*guard, 6);
+}
+
+#[test]
+fn waiters_acquire_in_request_order() {
+    const WAITERS: usize = 6;
+    let lock = Arc::new(TicketLock::new(Vec::new()));
+
+    let held = lock.lock();
+    let mut handles = Vec::new();
+    for id in 0..WAITERS {
+        let shared = Arc::clone(&lock);
+        handles.push(thread::spawn(move || {
+            shared.lock().push(id);
+        }));
+        // Don't start the next waiter until this one holds its ticket.
+        while lock.queued() as usize != id + 2 {
+            thread::yield_now();
+        }
+    }
+    drop(held);
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(*lock.lock(), (0..WAITERS).collect::<Vec<_>>());
+}
@@ -99,3 +99,69 @@ fn scratch_overflow_is_unsupported() {
     let err = file.write_at(0, &big).unwrap_err();
     assert_eq!(err, VfsError::Unsupported);
 }
+
+const MULTI_SECTORS: u64 = 3;
+
+fn multi_sector_file(dev: &'static MemBlockDevice) -> &'static AtaScratchFile {
+    unsafe { AtaScratchFile::init_with_sectors(dev, 1, MULTI_SECTORS, "scratch") }
+}
+
+#[test]
+fn multi_sector_size() {
+    let _guard = SCRATCH_GUARD.lock().unwrap();
+    let file = multi_sector_file(fresh_device());
+    assert_eq!(file.size().unwrap(), MULTI_SECTORS * BLOCK_SIZE as u64);
+}
+
+#[test]
+fn multi_sector_write_across_boundary() {
+    let _guard = SCRATCH_GUARD.lock().unwrap();
+    let dev = fresh_device();
+    let file = multi_sector_file(dev);
+
+    let start = BLOCK_SIZE as u64 - 4;
+    file.write_at(start, b"boundary").unwrap();
+
+    let mut buf = [0u8; 8];
+    file.read_at(start, &mut buf).unwrap();
+    assert_eq!(&buf, b"boundary");
+
+    // The file starts at LBA 1, so the write straddles disk sectors 1 and 2.
+    let mut disk = [0u8; BLOCK_SIZE * 4];
+    dev.read_blocks(0, &mut disk).unwrap();
+    assert_eq!(&disk[BLOCK_SIZE * 2 - 4..BLOCK_SIZE * 2 + 4], b"boundary");
+    assert!(disk[..BLOCK_SIZE].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn multi_sector_spanning_write_preserves_neighbours() {
+    let _guard = SCRATCH_GUARD.lock().unwrap();
+    let dev = fresh_device();
+    let file = multi_sector_file(dev);
+
+    file.write_at(0, &[0x11; BLOCK_SIZE * 3]).unwrap();
+    // Tail of sector 0, all of sector 1, head of sector 2.
+    let payload = [0x22u8; BLOCK_SIZE + 20];
+    file.write_at(BLOCK_SIZE as u64 - 10, &payload).unwrap();
+
+    let mut all = [0u8; BLOCK_SIZE * 3];
+    file.read_at(0, &mut all).unwrap();
+    assert!(all[..BLOCK_SIZE - 10].iter().all(|&b| b == 0x11));
+    assert!(all[BLOCK_SIZE - 10..BLOCK_SIZE * 2 + 10].iter().all(|&b| b == 0x22));
+    assert!(all[BLOCK_SIZE * 2 + 10..].iter().all(|&b| b == 0x11));
+}
+
+#[test]
+fn multi_sector_bounds_checks() {
+    let _guard = SCRATCH_GUARD.lock().unwrap();
+    let file = multi_sector_file(fresh_device());
+    let size = MULTI_SECTORS * BLOCK_SIZE as u64;
+
+    let mut single = [0u8; 1];
+    assert_eq!(file.read_at(size - 1, &mut single).unwrap(), 1);
+    assert_eq!(file.read_at(size, &mut single).unwrap_err(), VfsError::InvalidOffset);
+    assert_eq!(file.write_at(size, &single).unwrap_err(), VfsError::InvalidOffset);
+
+    let two = [0u8; 2];
+    assert_eq!(file.write_at(size - 1, &two).unwrap_err(), VfsError::Unsupported);
+}
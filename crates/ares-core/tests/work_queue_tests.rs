@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use ares_core::interrupts::work::{WorkItem, WorkQueue};
+
+static LOG: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn record(data: usize) {
+    LOG.lock().unwrap().push(data);
+}
+
+fn count(_data: usize) {
+    CALLS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+fn items_run_in_queue_order() {
+    LOG.lock().unwrap().clear();
+    let mut queue = WorkQueue::<4>::new();
+    for data in [10, 20, 30] {
+        assert!(queue.push(WorkItem::new(record, data)).is_ok());
+    }
+    assert_eq!(queue.len(), 3);
+    while let Some(item) = queue.pop() {
+        item.run();
+    }
+    assert!(queue.is_empty());
+    assert_eq!(*LOG.lock().unwrap(), vec![10, 20, 30]);
+}
+
+#[test]
+fn full_queue_hands_the_item_back() {
+    let mut queue = WorkQueue::<2>::new();
+    assert!(queue.push(WorkItem::new(count, 1)).is_ok());
+    assert!(queue.push(WorkItem::new(count, 2)).is_ok());
+    let rejected = queue.push(WorkItem::new(count, 3)).expect_err("third push should fail");
+    assert_eq!(rejected.data, 3);
+    assert_eq!(queue.len(), 2);
+}
+
+#[test]
+fn ring_wraps_after_draining() {
+    let mut queue = WorkQueue::<3>::new();
+    for round in 0..5 {
+        assert!(queue.push(WorkItem::new(count, round * 2)).is_ok());
+        assert!(queue.push(WorkItem::new(count, round * 2 + 1)).is_ok());
+        assert_eq!(queue.pop().map(|item| item.data), Some(round * 2));
+        assert_eq!(queue.pop().map(|item| item.data), Some(round * 2 + 1));
+        assert!(queue.pop().is_none());
+    }
+    let before = CALLS.load(Ordering::SeqCst);
+    assert!(queue.push(WorkItem::new(count, 0)).is_ok());
+    queue.pop().unwrap().run();
+    assert_eq!(CALLS.load(Ordering::SeqCst), before + 1);
+}
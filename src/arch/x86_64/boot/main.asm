@@ -89,6 +89,9 @@ _entry:
    mov   ss, ax                              ; therefore 16 bytes after the first
    mov   esp, _stack
 
+   call  check_cpu                            ; stops with a message if this CPU
+                                              ; cannot run in long mode
+
    mov   eax, pdpt
    or    eax, 3                               ; present + writable
    mov   [pml4], eax                          ; Pml4[0] -> Pdpt
@@ -126,9 +129,9 @@ _entry:
    or    eax, 1 << 5
    mov   cr4, eax
 
-   mov   ecx, 0xC0000080                      ; enable long mode
+   mov   ecx, 0xC0000080                      ; enable long mode and NX
    rdmsr
-   or    eax, 1 << 8
+   or    eax, (1 << 8) | (1 << 11)
    wrmsr
 
    mov   eax, cr0                             ; enable paging
@@ -178,6 +181,59 @@ _entry:
 [EXTERN pd]
 [EXTERN pt]
 
+;
+; Long mode needs CPUID, PAE and the LM bit. Without them the switch above
+; faults with nothing on screen, so check first and, if anything is
+; missing, write a line straight into VGA text memory and stop.
+;
+
+check_cpu:
+   pushfd                                     ; CPUID exists if EFLAGS.ID
+   pop   eax                                  ; (bit 21) can be flipped
+   mov   ecx, eax
+   xor   eax, 1 << 21
+   push  eax
+   popfd
+   pushfd
+   pop   eax
+   push  ecx                                  ; put the original flags back
+   popfd
+   cmp   eax, ecx
+   je    .unsupported
+
+   mov   eax, 0x80000000                      ; is the extended feature leaf there?
+   cpuid
+   cmp   eax, 0x80000001
+   jb    .unsupported
+
+   mov   eax, 0x80000001
+   cpuid
+   test  edx, 1 << 29                         ; long mode
+   jz    .unsupported
+
+   mov   eax, 1
+   cpuid
+   test  edx, 1 << 6                          ; PAE
+   jz    .unsupported
+   ret
+
+.unsupported:
+   mov   esi, cpu_unsupported_msg
+   mov   edi, 0xB8000
+.print:
+   mov   al, [esi]
+   test  al, al
+   jz    .halt
+   mov   ah, 0x4F                             ; white on red
+   mov   [edi], ax
+   inc   esi
+   add   edi, 2
+   jmp   .print
+.halt:
+   cli
+   hlt
+   jmp   .halt
+
 gdt_tab:
    DQ    0x0000000000000000
    DQ    0x00CF9A000000FFFF
@@ -205,4 +261,7 @@ gdt_3:
 multiboot_magic:    DD 0
 multiboot_info:     DQ 0
 
+cpu_unsupported_msg:
+   DB    "Ares: CPU not supported (needs CPUID, PAE and 64-bit long mode)", 0
+
 [SECTION .note.GNU-stack noalloc noexec nowrite]
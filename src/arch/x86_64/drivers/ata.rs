@@ -1,10 +1,15 @@
 use core::hint::spin_loop;
 use core::sync::atomic::{compiler_fence, Ordering};
 
-use crate::drivers::{BlockDevice, Driver, DriverError, DriverKind};
+use crate::arch::x86_64::kernel::timer;
+use crate::drivers::ata_identify::{AtaGeometry, IDENTIFY_WORDS, LBA28_LIMIT};
+use crate::drivers::poll::{poll_until, PollClock};
+use crate::drivers::{check_block_range, BlockDevice, Driver, DriverError, DriverKind};
 use crate::klog;
+use crate::process;
 
 use super::super::io::{inb, insw, outb, outsw};
+use crate::sync::mutex::Mutex;
 use crate::sync::spinlock::SpinLock;
 
 const PRIMARY_IO_BASE: u16 = 0x1F0;
@@ -38,10 +43,19 @@ const CMD_CACHE_FLUSH: u8   = 0xE7;
 
 const SECTOR_BYTES: usize = 512;
 
+/// How long a command may take to raise DRQ (or drop it again).
+const DRQ_TIMEOUT_MS: u64 = 1_000;
+const FLUSH_TIMEOUT_MS: u64 = 2_000;
+/// Before the scheduler runs there is no clock to wait on, so a timeout
+/// becomes this many status reads per millisecond.
+const SPINS_PER_MS: u64 = 100;
+
 pub struct AtaPrimaryMaster;
 
 static ATA_PRIMARY: AtaPrimaryMaster = AtaPrimaryMaster;
-static ATA_LOCK: SpinLock<()> = SpinLock::new(());
+// A sleeping lock: the holder may back off in `wait_until` for a while.
+static ATA_LOCK: Mutex<()> = Mutex::new(());
+static GEOMETRY: SpinLock<Option<AtaGeometry>> = SpinLock::new(None);
 
 impl AtaPrimaryMaster {
     const fn io_base(&self) -> u16 {
@@ -61,14 +75,30 @@ impl AtaPrimaryMaster {
         }
     }
 
-    fn wait_until(&self, mask: u8, value: u8, timeout: usize) -> Result<(), DriverError> {
-        for _ in 0..timeout {
+    /// Wait for BSY to clear and `status & mask == value`, failing with
+    /// `IoError` on ERR, DF or once `timeout_ms` runs out. From process
+    /// context with the timer ticking the wait yields and sleeps between
+    /// reads; otherwise, as early in boot or under the test harness, it
+    /// spins.
+    fn wait_until(&self, mask: u8, value: u8, timeout_ms: u64) -> Result<(), DriverError> {
+        let check = || {
             let status = unsafe { inb(self.io_base() + REG_STATUS) };
-            if status & STATUS_BSY == 0 && status & mask == value {
-                if status & STATUS_ERR != 0 || status & STATUS_DF != 0 {
-                    return Err(DriverError::IoError);
-                }
-                return Ok(());
+            if status & STATUS_BSY != 0 || status & mask != value {
+                return None;
+            }
+            if status & STATUS_ERR != 0 || status & STATUS_DF != 0 {
+                return Some(Err(DriverError::IoError));
+            }
+            Some(Ok(()))
+        };
+
+        if process::current_pid().is_some() && timer::is_ticking() {
+            return poll_until(&SchedulerClock, timeout_ms, check).unwrap_or(Err(DriverError::IoError));
+        }
+
+        for _ in 0..timeout_ms.saturating_mul(SPINS_PER_MS) {
+            if let Some(result) = check() {
+                return result;
             }
             spin_loop();
         }
@@ -83,7 +113,7 @@ impl AtaPrimaryMaster {
         }
     }
 
-    fn issue_identify(&self) -> Result<(), DriverError> {
+    fn issue_identify(&self) -> Result<AtaGeometry, DriverError> {
         self.select_drive(0);
         self.wait_400ns();
 
@@ -109,18 +139,17 @@ impl AtaPrimaryMaster {
             return Err(DriverError::IoError);
         }
 
-        self.wait_until(STATUS_DRQ, STATUS_DRQ, 100_000)?;
+        self.wait_until(STATUS_DRQ, STATUS_DRQ, DRQ_TIMEOUT_MS)?;
 
-        // Drain the IDENTIFY data (256 words) into a scratch buffer.
-        let mut scratch = [0u16; 256];
+        let mut words = [0u16; IDENTIFY_WORDS];
         unsafe {
             insw(
                 self.io_base() + REG_DATA,
-                scratch.as_mut_ptr(),
-                scratch.len(),
+                words.as_mut_ptr(),
+                words.len(),
             );
         }
-        Ok(())
+        Ok(AtaGeometry::parse(&words))
     }
 
     fn pio_read_sector(&self, lba: u64, buffer: &mut [u8; SECTOR_BYTES]) -> Result<(), DriverError> {
@@ -136,7 +165,7 @@ impl AtaPrimaryMaster {
             outb(self.io_base() + REG_COMMAND, CMD_READ_SECTORS);
         }
 
-        self.wait_until(STATUS_DRQ, STATUS_DRQ, 100_000)?;
+        self.wait_until(STATUS_DRQ, STATUS_DRQ, DRQ_TIMEOUT_MS)?;
 
         unsafe {
             let ptr = buffer.as_mut_ptr() as *mut u16;
@@ -164,7 +193,7 @@ impl AtaPrimaryMaster {
 
         // Device should become ready to accept data
         // Wait: BSY=0 and DRQ=1; bail if ERR/DF
-        self.wait_until(STATUS_DRQ, STATUS_DRQ, 100_000)?;
+        self.wait_until(STATUS_DRQ, STATUS_DRQ, DRQ_TIMEOUT_MS)?;
 
         // Push 512 bytes (256 words) to the data port
         unsafe {
@@ -174,7 +203,7 @@ impl AtaPrimaryMaster {
         compiler_fence(Ordering::SeqCst);
 
         // Finalize: wait for BSY=0 and DRQ=0 (transfer complete)
-        self.wait_until(STATUS_DRQ, 0, 100_000)?;
+        self.wait_until(STATUS_DRQ, 0, DRQ_TIMEOUT_MS)?;
         // Check for error bits one last time
         let st = unsafe { inb(self.io_base() + REG_STATUS) };
         if st & (STATUS_ERR | STATUS_DF) != 0 {
@@ -190,7 +219,7 @@ impl AtaPrimaryMaster {
         }
 
         // Wait until BSY=0; ERR/DF clear
-        self.wait_until(0, 0, 200_000)?;
+        self.wait_until(0, 0, FLUSH_TIMEOUT_MS)?;
 
         let st = unsafe { inb(self.io_base() + REG_STATUS) };
 
@@ -203,6 +232,21 @@ impl AtaPrimaryMaster {
 
 }
 
+/// Timer ticks for the deadline; the scheduler fills the pauses.
+struct SchedulerClock;
+
+impl PollClock for SchedulerClock {
+    fn now_ms(&self) -> u64 {
+        timer::ticks_to_ms(timer::ticks())
+    }
+
+    fn pause(&self, ms: u64) {
+        if ms == 0 || timer::sleep_ms(ms).is_err() {
+            process::yield_now();
+        }
+    }
+}
+
 impl Driver for AtaPrimaryMaster {
     fn name(&self) -> &'static str {
         "ata0-master"
@@ -216,8 +260,14 @@ impl Driver for AtaPrimaryMaster {
         let _guard = ATA_LOCK.lock();
 
         match self.issue_identify() {
-            Ok(()) => {
-                klog!("[ata] primary master ready\n");
+            Ok(geometry) => {
+                klog!(
+                    "[ata] primary master ready: \"{}\" {} sectors ({} MiB)\n",
+                    geometry.model(),
+                    geometry.sectors(),
+                    geometry.sectors() * SECTOR_BYTES as u64 / (1024 * 1024)
+                );
+                *GEOMETRY.lock() = Some(geometry);
                 Ok(())
             }
             Err(DriverError::Unsupported) => {
@@ -238,17 +288,15 @@ impl BlockDevice for AtaPrimaryMaster {
     }
 
     fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), DriverError> {
-        let _guard = ATA_LOCK.lock();
-
-        if buf.len() % SECTOR_BYTES != 0 {
-            return Err(DriverError::Unsupported);
-        }
-
-        let sectors = buf.len() / SECTOR_BYTES;
+        // Reject past-the-end requests before touching the ports; the drive
+        // would never raise DRQ and `wait_until` would sit out its timeout.
+        let sectors = check_block_range(self, lba, buf.len())?;
         if sectors == 0 {
             return Ok(());
         }
 
+        let _guard = ATA_LOCK.lock();
+
         for (index, chunk) in buf.chunks_mut(SECTOR_BYTES).enumerate() {
             let mut sector = [0u8; SECTOR_BYTES];
             self.pio_read_sector(lba + index as u64, &mut sector)?;
@@ -263,14 +311,11 @@ impl BlockDevice for AtaPrimaryMaster {
     }
 
     fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), DriverError> {
-        let _guard = ATA_LOCK.lock();
-
-        if buf.len() % SECTOR_BYTES != 0 {
-            return Err(DriverError::Unsupported);
-        }
-        let sectors = buf.len() / SECTOR_BYTES;
+        let sectors = check_block_range(self, lba, buf.len())?;
         if sectors == 0 { return Ok(()); }
 
+        let _guard = ATA_LOCK.lock();
+
         for (i, chunk) in buf.chunks(SECTOR_BYTES).enumerate() {
             // SAFETY: chunk is exactly 512 bytes
             let mut sector = [0u8; SECTOR_BYTES];
@@ -283,6 +328,21 @@ impl BlockDevice for AtaPrimaryMaster {
         Ok(())
     }
 
+    /// Sectors reachable by this driver. Only 28-bit commands are issued, so
+    /// a larger disk is capped at `LBA28_LIMIT`. Unknown until `init` has
+    /// identified the drive.
+    fn capacity_sectors(&self) -> u64 {
+        match *GEOMETRY.lock() {
+            Some(geometry) => geometry.sectors().min(LBA28_LIMIT),
+            None => u64::MAX,
+        }
+    }
+
+}
+
+/// What IDENTIFY reported, once `init` has run against a present drive.
+pub fn geometry() -> Option<AtaGeometry> {
+    *GEOMETRY.lock()
 }
 
 pub fn driver() -> &'static AtaPrimaryMaster {
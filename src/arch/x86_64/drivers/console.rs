@@ -37,6 +37,20 @@ pub fn write_at(row: usize, col: usize, byte: u8, attr: u8) {
     }
 }
 
+pub fn read_row(row: usize) -> [u16; WIDTH] {
+    let mut cells = [0u16; WIDTH];
+    unsafe {
+        core::ptr::copy_nonoverlapping(VGA_BUFFER.add(row * WIDTH), cells.as_mut_ptr(), WIDTH);
+    }
+    cells
+}
+
+pub fn write_row(row: usize, cells: &[u16; WIDTH]) {
+    unsafe {
+        core::ptr::copy_nonoverlapping(cells.as_ptr(), VGA_BUFFER.add(row * WIDTH), WIDTH);
+    }
+}
+
 pub fn clear_row(row: usize) {
     for col in 0..WIDTH {
         write_at(row, col, b' ', DEFAULT_ATTR);
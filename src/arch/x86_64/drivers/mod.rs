@@ -2,3 +2,4 @@ pub mod console;
 pub mod serial;
 pub mod keyboard;
 pub mod ata;
+pub mod rtc;
@@ -0,0 +1,111 @@
+//! The CMOS real-time clock, read once at boot to learn the wall time.
+//! Registers are read until two passes agree, so a value is never taken
+//! while the chip is half way through an update.
+
+use core::hint::spin_loop;
+
+use crate::arch::x86_64::io::{inb, outb};
+use crate::time::civil::DateTime;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+/// Keeps NMIs masked while a register is selected.
+const NMI_DISABLE: u8 = 0x80;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+
+const STATUS_A_UPDATING: u8 = 0x80;
+const STATUS_B_24_HOUR: u8 = 0x02;
+const STATUS_B_BINARY: u8 = 0x04;
+const HOUR_PM: u8 = 0x80;
+
+/// Reads before giving up on a clock that never settles.
+const MAX_ATTEMPTS: usize = 8;
+const UPDATE_SPIN_LIMIT: usize = 100_000;
+
+fn read_register(reg: u8) -> u8 {
+    unsafe {
+        outb(CMOS_ADDRESS, NMI_DISABLE | reg);
+        inb(CMOS_DATA)
+    }
+}
+
+fn wait_for_update() -> bool {
+    for _ in 0..UPDATE_SPIN_LIMIT {
+        if read_register(REG_STATUS_A) & STATUS_A_UPDATING == 0 {
+            return true;
+        }
+        spin_loop();
+    }
+    false
+}
+
+fn read_raw() -> Option<[u8; 6]> {
+    if !wait_for_update() {
+        return None;
+    }
+    Some([
+        read_register(REG_SECONDS),
+        read_register(REG_MINUTES),
+        read_register(REG_HOURS),
+        read_register(REG_DAY),
+        read_register(REG_MONTH),
+        read_register(REG_YEAR),
+    ])
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// The current date and time as the RTC keeps it, read as UTC. `None` if
+/// the clock does not answer or holds an impossible date.
+pub fn read() -> Option<DateTime> {
+    let mut last = read_raw()?;
+    let mut settled = None;
+    for _ in 0..MAX_ATTEMPTS {
+        let next = read_raw()?;
+        if next == last {
+            settled = Some(next);
+            break;
+        }
+        last = next;
+    }
+    let [seconds, minutes, hours, day, month, year] = settled?;
+
+    let status_b = read_register(REG_STATUS_B);
+    let decode = |value: u8| if status_b & STATUS_B_BINARY != 0 { value } else { from_bcd(value) };
+    let pm = hours & HOUR_PM != 0;
+    let mut hour = decode(hours & !HOUR_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12-hour mode: 12 AM is midnight and 12 PM is noon.
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+
+    // The chip only keeps two year digits; everything it could mean for
+    // this kernel is in this century.
+    let stamp = DateTime {
+        year: 2000 + decode(year) as u16,
+        month: decode(month),
+        day: decode(day),
+        hour,
+        minute: decode(minutes),
+        second: decode(seconds),
+    };
+    if stamp.is_valid() {
+        Some(stamp)
+    } else {
+        None
+    }
+}
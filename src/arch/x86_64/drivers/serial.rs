@@ -1,7 +1,11 @@
 use core::hint::spin_loop;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use crate::arch::x86_64::io::{inb, outb};
+use crate::arch::x86_64::kernel::interrupts;
+use crate::arch::x86_64::kernel::interrupts::InterruptFrame;
+use crate::drivers::byte_ring::ByteRing;
+use crate::process::{self, WaitChannel};
 
 const COM1_PORT: u16 = 0x3F8;
 
@@ -14,7 +18,29 @@ const LINE_STATUS: u16 = COM1_PORT + 5;
 
 const SERIAL_SPIN_LIMIT: usize = 100_000;
 
+/// `INTERRUPT_ENABLE` bit for "received data available".
+const IER_RECEIVED: u8 = 0x01;
+/// `MODEM_CONTROL` bit that wires the transmitter back to the receiver.
+const MCR_LOOPBACK: u8 = 0x10;
+/// `LINE_STATUS` bit set while the receive buffer holds a byte.
+const LSR_DATA_READY: u8 = 0x01;
+
+/// Bytes the receive ring holds before the oldest is overwritten.
+pub const BUFFER_SIZE: usize = 256;
+
 static SERIAL_ENABLED: AtomicBool = AtomicBool::new(true);
+/// Filled by the IRQ handler and drained by `read`.
+static RING: ByteRing<BUFFER_SIZE> = ByteRing::new();
+static RECEIVING: AtomicBool = AtomicBool::new(false);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SerialStats {
+    /// Received bytes waiting to be read.
+    pub queued: usize,
+    /// Oldest bytes discarded because the ring was full.
+    pub dropped: u64,
+}
 
 pub(crate) fn init() {
     unsafe {
@@ -65,3 +91,95 @@ fn transmit(byte: u8) {
 fn is_transmit_empty() -> bool {
     unsafe { inb(LINE_STATUS) & 0x20 != 0 }
 }
+
+/// Install the IRQ4 handler and ask the UART to raise it for every received
+/// byte. `init` runs before the IDT exists, so this is a separate step.
+pub fn enable_receive() {
+    if RECEIVING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    interrupts::register_handler(interrupts::vectors::COM1, serial_handler);
+    unsafe {
+        // Anything that arrived before now was never going to be read.
+        while inb(LINE_STATUS) & LSR_DATA_READY != 0 {
+            inb(DATA);
+        }
+        outb(INTERRUPT_ENABLE, IER_RECEIVED);
+    }
+    interrupts::enable_vector(interrupts::vectors::COM1);
+}
+
+pub fn has_input() -> bool {
+    !RING.is_empty()
+}
+
+pub fn stats() -> SerialStats {
+    SerialStats {
+        queued: RING.len(),
+        dropped: DROPPED.load(Ordering::Relaxed),
+    }
+}
+
+/// Never blocks; returns 0 when nothing has been received.
+pub fn read(buf: &mut [u8]) -> usize {
+    let mut count = 0;
+    while count < buf.len() {
+        match RING.pop() {
+            Some(byte) => {
+                buf[count] = byte;
+                count += 1;
+            }
+            None => break,
+        }
+    }
+    count
+}
+
+/// The UART keeps IRQ4 raised until its receive FIFO is empty, so the
+/// handler drains it here. Waking readers waits for the EOI.
+fn serial_handler(_frame: &mut InterruptFrame) {
+    let mut received = false;
+    while unsafe { inb(LINE_STATUS) } & LSR_DATA_READY != 0 {
+        let byte = unsafe { inb(DATA) };
+        if !RING.push(byte) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+        received = true;
+    }
+    // With the queue full the wake runs inline; order does not matter for it.
+    if received && !interrupts::defer(wake_readers, 0) {
+        wake_readers(0);
+    }
+}
+
+/// Inline or deferred, this runs on top of whatever the IRQ interrupted,
+/// which may hold the process table, so the wake only tries for it and
+/// otherwise waits for the next IRQ exit. With the retry queue full too, a
+/// reader left asleep is woken by the next byte.
+fn wake_readers(_: usize) {
+    if process::try_wake_channel(WaitChannel::SerialInput).is_none() {
+        let _ = interrupts::defer_retry(wake_readers, 0);
+    }
+}
+
+/// Route transmitted bytes straight back into the receiver instead of out
+/// of the port, so a test can talk to itself. Everything `klog` writes
+/// meanwhile is received too and never reaches the host.
+#[cfg(kernel_test)]
+pub fn set_loopback(enabled: bool) {
+    unsafe {
+        let control = inb(MODEM_CONTROL);
+        let control = if enabled {
+            control | MCR_LOOPBACK
+        } else {
+            control & !MCR_LOOPBACK
+        };
+        outb(MODEM_CONTROL, control);
+    }
+}
+
+/// Send one byte exactly as given, without `write_byte`'s CRLF handling.
+#[cfg(kernel_test)]
+pub fn transmit_raw(byte: u8) {
+    transmit(byte);
+}
@@ -0,0 +1,161 @@
+//! Local APIC register access and its timer. The registers live in one
+//! 4 KiB MMIO page at the physical address held in `IA32_APIC_BASE`
+//! (normally `0xFEE0_0000`), above the 1 GiB the boot page tables
+//! direct-map, so `init` maps that page uncached into the kernel half before
+//! anything reads it. The timer counts down from a programmed value at the
+//! bus clock over a divider; that clock differs between machines, so
+//! `calibrate` measures it against the PIT.
+
+use core::ptr;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::cpu::{self, feature};
+use super::paging::{
+    self, MapError, FLAG_CACHE_DISABLE, FLAG_NO_EXECUTE, FLAG_WRITABLE, FLAG_WRITE_THROUGH,
+};
+use super::interrupts::vectors;
+use super::{mmu, msr, pit};
+
+pub const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Register offsets within the MMIO page.
+pub mod reg {
+    pub const ID: usize = 0x20;
+    pub const VERSION: usize = 0x30;
+    pub const EOI: usize = 0xB0;
+    pub const SPURIOUS: usize = 0xF0;
+    pub const LVT_TIMER: usize = 0x320;
+    pub const TIMER_INITIAL: usize = 0x380;
+    pub const TIMER_CURRENT: usize = 0x390;
+    pub const TIMER_DIVIDE: usize = 0x3E0;
+}
+
+/// Spurious-vector register bit that turns the APIC on in software.
+const SPURIOUS_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+/// Divide configuration 0b0011: the timer runs at bus clock / 16.
+const TIMER_DIVIDE_16: u32 = 0x3;
+/// Length of the PIT window `calibrate` counts over.
+const CALIBRATE_MS: u32 = 10;
+
+/// Kernel virtual address of the register page, or 0 before `init`.
+static REGISTERS: AtomicU64 = AtomicU64::new(0);
+
+/// CPUID reports an on-chip APIC.
+pub fn is_present() -> bool {
+    cpu::features().has_edx(feature::edx::APIC)
+}
+
+/// Map the register page and switch the APIC on, with the timer masked and
+/// stray interrupts sent to `vectors::SPURIOUS`. Returns false, leaving
+/// every register read as `None`, when CPUID reports no LAPIC or the page
+/// could not be mapped.
+pub fn init() -> bool {
+    if REGISTERS.load(Ordering::Acquire) != 0 {
+        return true;
+    }
+    if !is_present() {
+        return false;
+    }
+
+    let mut base = unsafe { msr::read(IA32_APIC_BASE) };
+    if base & APIC_BASE_ENABLE == 0 {
+        base |= APIC_BASE_ENABLE;
+        unsafe { msr::write(IA32_APIC_BASE, base) };
+    }
+    let phys = base & APIC_BASE_ADDR_MASK;
+    let virt = mmu::phys_to_virt(phys);
+    let flags = FLAG_WRITABLE | FLAG_CACHE_DISABLE | FLAG_WRITE_THROUGH | FLAG_NO_EXECUTE;
+    // User address spaces copy the kernel's upper-half entries, so mapping
+    // into the live tables once covers every process.
+    let pml4 = unsafe { mmu::read_cr3() };
+    match paging::map_page(pml4, virt, phys, flags) {
+        Ok(()) => mmu::flush_tlb(virt),
+        // Inside the boot direct map already.
+        Err(MapError::AlreadyMapped) => {}
+        Err(_) => return false,
+    }
+    REGISTERS.store(virt, Ordering::Release);
+
+    unsafe {
+        write(reg::LVT_TIMER, LVT_MASKED);
+        write(reg::SPURIOUS, SPURIOUS_ENABLE | vectors::SPURIOUS as u32);
+    }
+    true
+}
+
+pub fn is_mapped() -> bool {
+    REGISTERS.load(Ordering::Acquire) != 0
+}
+
+pub fn read(reg: usize) -> Option<u32> {
+    match REGISTERS.load(Ordering::Acquire) {
+        0 => None,
+        base => Some(unsafe { ptr::read_volatile((base + reg as u64) as *const u32) }),
+    }
+}
+
+/// This CPU's APIC id, from bits 24..32 of the ID register.
+pub fn id() -> Option<u32> {
+    read(reg::ID).map(|value| value >> 24)
+}
+
+/// # Safety
+/// `reg` must be a writable register and `value` valid for it.
+pub unsafe fn write(reg: usize, value: u32) {
+    let base = REGISTERS.load(Ordering::Acquire);
+    if base != 0 {
+        ptr::write_volatile((base + reg as u64) as *mut u32, value);
+    }
+}
+
+/// Acknowledge the interrupt in service. Spurious interrupts must not be
+/// acknowledged.
+pub fn eoi() {
+    unsafe { write(reg::EOI, 0) };
+}
+
+/// Timer counts, at the divide-by-16 rate `start_timer` uses, in one period
+/// of `hz`. Measured by letting the timer run down from its maximum across
+/// a PIT-timed window, so interrupts should be off. `None` without a mapped
+/// APIC or if the timer did not move.
+pub fn calibrate(hz: u32) -> Option<u32> {
+    if !is_mapped() || hz == 0 {
+        return None;
+    }
+    unsafe {
+        write(reg::TIMER_DIVIDE, TIMER_DIVIDE_16);
+        write(reg::LVT_TIMER, LVT_MASKED);
+        write(reg::TIMER_INITIAL, u32::MAX);
+    }
+    pit::wait_polled(CALIBRATE_MS);
+    let remaining = read(reg::TIMER_CURRENT)?;
+    unsafe { write(reg::TIMER_INITIAL, 0) };
+
+    let elapsed = (u32::MAX - remaining) as u64;
+    let per_period = elapsed * 1000 / (CALIBRATE_MS as u64 * hz as u64);
+    match per_period {
+        0 => None,
+        count => Some(count.min(u32::MAX as u64) as u32),
+    }
+}
+
+/// Fire `vector` every `count` timer ticks (see `calibrate`) until
+/// `stop_timer`.
+pub fn start_timer(vector: u8, count: u32) {
+    unsafe {
+        write(reg::TIMER_DIVIDE, TIMER_DIVIDE_16);
+        write(reg::LVT_TIMER, LVT_TIMER_PERIODIC | vector as u32);
+        write(reg::TIMER_INITIAL, count);
+    }
+}
+
+pub fn stop_timer() {
+    unsafe {
+        write(reg::LVT_TIMER, LVT_MASKED);
+        write(reg::TIMER_INITIAL, 0);
+    }
+}
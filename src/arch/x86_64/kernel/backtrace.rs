@@ -0,0 +1,90 @@
+//! Register snapshot and frame-pointer stack walk for the panic dump. The
+//! kernel is built with `-C force-frame-pointers=yes`, so each frame opens
+//! with the caller's rbp at `[rbp]` and the return address at `[rbp + 8]`.
+//! Functions built without frame pointers (parts of `core`) leave rbp alone
+//! and simply do not show up.
+
+use super::mmu::{read_cr2, read_cr3};
+
+/// Furthest apart two frames of one walk may be. A saved rbp further up than
+/// this is not a frame on the same stack, so the walk stops there.
+const MAX_FRAME_BYTES: u64 = 64 * 1024;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Registers {
+    pub rsp: u64,
+    pub rbp: u64,
+    pub rflags: u64,
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+}
+
+/// Registers as they are in the calling function.
+#[inline(always)]
+pub fn capture() -> Registers {
+    let rsp: u64;
+    let rbp: u64;
+    let rflags: u64;
+    let cr0: u64;
+    unsafe {
+        core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("pushfq; pop {}", out(reg) rflags, options(nomem, preserves_flags));
+        core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        Registers {
+            rsp,
+            rbp,
+            rflags,
+            cr0,
+            cr2: read_cr2(),
+            cr3: read_cr3(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Frame {
+    /// Where this frame saved its caller's rbp.
+    pub rbp: u64,
+    pub return_address: u64,
+}
+
+/// Frames from `rbp` outwards, innermost first.
+pub fn frames(rbp: u64, max: usize) -> Frames {
+    Frames { rbp, remaining: max }
+}
+
+pub struct Frames {
+    rbp: u64,
+    remaining: usize,
+}
+
+impl Iterator for Frames {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if self.remaining == 0 || self.rbp == 0 || !self.rbp.is_multiple_of(8) {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let rbp = self.rbp;
+        let (saved_rbp, return_address) = unsafe {
+            let slot = rbp as *const u64;
+            (slot.read_volatile(), slot.add(1).read_volatile())
+        };
+        if return_address == 0 {
+            self.rbp = 0;
+            return None;
+        }
+
+        // Stacks grow down, so the caller's frame must sit above this one.
+        self.rbp = if saved_rbp > rbp && saved_rbp - rbp <= MAX_FRAME_BYTES {
+            saved_rbp
+        } else {
+            0
+        };
+        Some(Frame { rbp, return_address })
+    }
+}
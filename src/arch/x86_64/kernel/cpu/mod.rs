@@ -1,5 +1,7 @@
 mod cpuid;
 
+use crate::cpu::support::{CpuidWords, EXTENDED_FEATURE_LEAF};
+
 /// Enable SSE and related state-management instructions before using them.
 pub unsafe fn enable_sse() {
     let mut cr0: u64;
@@ -30,6 +32,23 @@ pub fn cpuid_ecx(eax: u32, ecx: u32) -> CpuidResult {
     cpuid::cpuid_ecx(eax, ecx)
 }
 
+/// The running CPU's Local APIC id. Reads 0 when there is no LAPIC or its
+/// registers are not mapped yet, which is also the boot CPU's id on a
+/// uniprocessor machine.
+pub fn apic_id() -> u32 {
+    super::apic::id().unwrap_or(0)
+}
+
+/// Time-stamp counter. Only good for comparing two reads on one CPU.
+pub fn read_tsc() -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe {
+        core::arch::asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    }
+    (u64::from(high) << 32) | u64::from(low)
+}
+
 pub fn highest_basic_leaf() -> u32 {
     cpuid(0).eax
 }
@@ -47,6 +66,31 @@ pub fn vendor_string() -> [u8; 12] {
     vendor
 }
 
+/// The CPUID words `cpu::support` checks. An extended leaf the CPU does
+/// not have is not read, and reports no features.
+pub fn support_words() -> CpuidWords {
+    let max_extended_leaf = highest_extended_leaf();
+    let extended_edx = if max_extended_leaf >= EXTENDED_FEATURE_LEAF {
+        cpuid(EXTENDED_FEATURE_LEAF).edx
+    } else {
+        0
+    };
+    CpuidWords {
+        basic_edx: cpuid(1).edx,
+        max_extended_leaf,
+        extended_edx,
+    }
+}
+
+/// Interrupts off and `hlt` for good.
+pub fn halt_forever() -> ! {
+    loop {
+        unsafe {
+            core::arch::asm!("cli; hlt", options(nomem, nostack));
+        }
+    }
+}
+
 pub struct Features {
     pub ecx: u32,
     pub edx: u32,
@@ -82,9 +82,17 @@ pub fn init() {
     }
 }
 
+/// The stack `syscall_entry` switches to. SYSCALL, unlike an interrupt
+/// from ring 3, does not load `rsp0` from the TSS, so the stub reads this
+/// copy instead. One CPU runs user code, so one slot is enough.
+#[no_mangle]
+static mut SYSCALL_KERNEL_RSP: u64 = 0;
+
+/// Where traps and syscalls from ring 3 start their kernel stack.
 pub fn set_kernel_stack(stack_top: u64) {
     unsafe {
         TSS.0.rsp[0] = stack_top;
+        SYSCALL_KERNEL_RSP = stack_top;
     }
 }
 
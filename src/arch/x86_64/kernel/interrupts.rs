@@ -1,13 +1,21 @@
 #![allow(dead_code)]
 
 use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
+use crate::interrupts::eoi;
+use crate::interrupts::work::{WorkFn, WorkItem, WorkQueue};
 use crate::klog;
+use crate::process::Pid;
+use crate::sync::spinlock::SpinLock;
 mod stubs;
+use super::apic;
+use super::gdt;
 use super::mmu;
+use super::paging;
 use arch::x86_64::qemu;
 
-type InterruptHandler = fn(&mut InterruptFrame);
+pub type InterruptHandler = fn(&mut InterruptFrame);
 
 #[repr(C, packed)]
 #[derive(Clone, Copy)]
@@ -195,6 +203,7 @@ struct AlignedIdt([IdtEntry; IDT_ENTRIES]);
 
 static mut IDT: AlignedIdt = AlignedIdt([IdtEntry::missing(); IDT_ENTRIES]);
 static mut HANDLERS: [InterruptHandler; IDT_ENTRIES] = [default_handler; IDT_ENTRIES];
+static COUNTS: [AtomicU64; IDT_ENTRIES] = [const { AtomicU64::new(0) }; IDT_ENTRIES];
 
 #[link_section = ".data"]
 static mut IDTR: Idtr = Idtr { limit: 0, base: 0 };
@@ -251,6 +260,9 @@ extern "C" {
     fn irq_13();
     fn irq_14();
     fn irq_15();
+
+    fn lapic_timer();
+    fn lapic_spurious();
 }
 
 const GDT_KERNEL_CODE: u16 = 0x08;
@@ -283,10 +295,185 @@ pub fn init() {
     klog::writeln("[interrupts] IDT loaded");
 }
 
-pub fn register_handler(vector: u8, handler: InterruptHandler) {
+/// Install `handler` for `vector` and return the one it replaced, so a
+/// caller hooking a vector temporarily can put the old handler back.
+pub fn register_handler(vector: u8, handler: InterruptHandler) -> InterruptHandler {
+    unsafe { core::mem::replace(&mut HANDLERS[vector as usize], handler) }
+}
+
+/// Put the default (log and exit) handler back on `vector`, returning the
+/// handler that was installed.
+pub fn unregister_handler(vector: u8) -> InterruptHandler {
+    register_handler(vector, default_handler)
+}
+
+/// Per-vector delivery counts, copied out at the time of the call.
+pub struct InterruptStats {
+    counts: [u64; IDT_ENTRIES],
+}
+
+impl InterruptStats {
+    pub fn count(&self, vector: u8) -> u64 {
+        self.counts[vector as usize]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// `(vector, count)` for every vector that has fired at least once.
+    pub fn fired(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count != 0)
+            .map(|(vector, &count)| (vector as u8, count))
+    }
+}
+
+pub fn stats() -> InterruptStats {
+    let mut counts = [0u64; IDT_ENTRIES];
+    for (slot, counter) in counts.iter_mut().zip(COUNTS.iter()) {
+        *slot = counter.load(Ordering::Relaxed);
+    }
+    InterruptStats { counts }
+}
+
+pub fn dump_stats() {
+    for (vector, count) in stats().fired() {
+        klog!("[interrupts] vector {:3} count={}\n", vector, count);
+    }
+}
+
+/// Bottom-half items that can be waiting at once.
+pub const MAX_DEFERRED: usize = 64;
+
+static DEFERRED: SpinLock<WorkQueue<MAX_DEFERRED>> = SpinLock::new(WorkQueue::new());
+/// Work put off by `defer_retry`, moved into `DEFERRED` when the next drain
+/// starts.
+static RETRY: SpinLock<WorkQueue<MAX_DEFERRED>> = SpinLock::new(WorkQueue::new());
+/// Items pushed to either queue and not yet popped, so an IRQ exit with
+/// nothing deferred can skip the queue locks.
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+/// Set while an IRQ exit is running deferred work with interrupts enabled.
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+const RFLAGS_IF: u64 = 1 << 9;
+
+fn interrupts_enabled() -> bool {
+    let rflags: u64;
     unsafe {
-        HANDLERS[vector as usize] = handler;
+        core::arch::asm!("pushfq; pop {}", out(reg) rflags, options(nomem, preserves_flags));
+    }
+    rflags & RFLAGS_IF != 0
+}
+
+/// Run `f` with interrupts off, then put them back as they were. The
+/// deferred queues are taken this way, because `SpinLock` does not mask
+/// interrupts and an IRQ must never find one held by the code it interrupted.
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let enabled = interrupts_enabled();
+    disable();
+    let result = f();
+    if enabled {
+        enable();
+    }
+    result
+}
+
+/// Queue `func(data)` to run once the current IRQ has been acknowledged.
+/// Returns false when the queue is full. The caller then has to drop the
+/// work or do it inline, which runs it ahead of everything still queued.
+pub fn defer(func: WorkFn, data: usize) -> bool {
+    let queued = without_interrupts(|| DEFERRED.lock().push(WorkItem::new(func, data)).is_ok());
+    if queued {
+        QUEUED.fetch_add(1, Ordering::AcqRel);
+    }
+    queued
+}
+
+/// Queue `func(data)` for the next IRQ exit rather than the drain in
+/// progress. For deferred work that found a lock held: the holder is the
+/// code the drain is running on top of, so it cannot let go until the
+/// drain returns, and retrying within the drain would spin forever.
+pub fn defer_retry(func: WorkFn, data: usize) -> bool {
+    let queued = without_interrupts(|| RETRY.lock().push(WorkItem::new(func, data)).is_ok());
+    if queued {
+        QUEUED.fetch_add(1, Ordering::AcqRel);
     }
+    queued
+}
+
+/// Items queued by `defer` or `defer_retry` and not yet run.
+pub fn deferred_pending() -> usize {
+    without_interrupts(|| DEFERRED.lock().len() + RETRY.lock().len())
+}
+
+/// Whether deferred work is running, possibly underneath the current
+/// handler. Work done inline while this is set can overlap an item.
+pub fn draining() -> bool {
+    DRAINING.load(Ordering::Acquire)
+}
+
+/// Move work waiting for this drain to the back of the main queue. Must be
+/// called with interrupts off, like `pop_deferred`.
+fn promote_retries() {
+    let mut retry = RETRY.lock();
+    let mut deferred = DEFERRED.lock();
+    while deferred.len() < MAX_DEFERRED {
+        match retry.pop() {
+            Some(item) => {
+                let _ = deferred.push(item);
+            }
+            None => break,
+        }
+    }
+}
+
+/// Must be called with interrupts off, so an IRQ's `defer` never finds the
+/// lock held by the code it interrupted.
+fn pop_deferred() -> Option<WorkItem> {
+    let item = DEFERRED.lock().pop();
+    if item.is_some() {
+        QUEUED.fetch_sub(1, Ordering::AcqRel);
+    }
+    item
+}
+
+/// Run queued work in order until the queue is empty and return how many
+/// items ran, all with interrupts off. The lock is dropped around each
+/// item, so work may defer more work; work retried with `defer_retry`
+/// waits for the next call. Tests use this to stand in for an IRQ exit.
+pub fn run_deferred() -> usize {
+    without_interrupts(|| {
+        promote_retries();
+        let mut ran = 0;
+        while let Some(item) = pop_deferred() {
+            item.run();
+            ran += 1;
+        }
+        ran
+    })
+}
+
+/// The IRQ exit path: after the EOI, run queued work with interrupts
+/// enabled so a long item does not hold off the next IRQ. Each item is
+/// popped with interrupts off. An IRQ that lands during an item queues its
+/// own work and returns without draining, because the outer drain is still
+/// going and picks it up. Only one drain runs at a time.
+fn drain_deferred() {
+    if QUEUED.load(Ordering::Acquire) == 0 || DRAINING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    promote_retries();
+    while let Some(item) = pop_deferred() {
+        enable();
+        item.run();
+        disable();
+    }
+    // Still with interrupts off, so nothing can be queued between the last
+    // empty pop and the flag going down.
+    DRAINING.store(false, Ordering::Release);
 }
 
 pub fn enable() {
@@ -309,15 +496,24 @@ pub fn disable_irq(line: u8) {
     unsafe { pic::mask(line); }
 }
 
+/// The 16 vectors the remapped PICs deliver on. Anything else (LAPIC
+/// vectors included) has no PIC mask bit to change.
+fn pic_line(vector: u8) -> Option<u8> {
+    match vector.checked_sub(PIC_MASTER_OFFSET) {
+        Some(line) if line < 16 => Some(line),
+        _ => None,
+    }
+}
+
 pub fn enable_vector(vector: u8) {
-    if vector >= PIC_MASTER_OFFSET {
-        enable_irq(vector - PIC_MASTER_OFFSET);
+    if let Some(line) = pic_line(vector) {
+        enable_irq(line);
     }
 }
 
 pub fn disable_vector(vector: u8) {
-    if vector >= PIC_MASTER_OFFSET {
-        disable_irq(vector - PIC_MASTER_OFFSET);
+    if let Some(line) = pic_line(vector) {
+        disable_irq(line);
     }
 }
 
@@ -326,15 +522,82 @@ fn default_handler(frame: &mut InterruptFrame) {
     qemu::exit_failure();
 }
 
+/// Exit statuses given to a user process killed by a CPU exception. They
+/// follow the shell's `128 + signal` convention for the signal Linux would
+/// deliver: SIGILL for #UD, SIGSEGV for #GP and #PF.
+pub mod fault_exit {
+    pub const INVALID_OPCODE: i32 = 128 + 4;
+    pub const GENERAL_PROTECTION: i32 = 128 + 11;
+    pub const PAGE_FAULT: i32 = 128 + 11;
+}
+
+/// True when the exception was raised by ring-3 code. The RPL bits of the
+/// saved CS are ignored so both 0x18 and 0x1B match.
+fn from_user(frame: &InterruptFrame) -> bool {
+    (frame.cs as u16) & !0x3 == gdt::USER_CODE_SELECTOR
+}
+
+/// Dump the faulting process and terminate it with `status`. Only called for
+/// user-mode faults; the kernel carries on with the next runnable task.
+fn kill_faulting_process(tag: &str, status: i32) -> ! {
+    use crate::process;
+
+    match process::current_pid() {
+        Some(pid) => {
+            if let Ok(()) = process::dump_process(pid) {
+                klog!("[{}] dumped process {}\n", tag, pid);
+            }
+            klog!("[{}] terminating pid {} with status {}\n", tag, pid, status);
+            process::exit_current(status)
+        }
+        None => {
+            klog!("[{}] user fault with no current process\n", tag);
+            qemu::exit_failure()
+        }
+    }
+}
+
+/// A page fault's CR2 and decoded error code. `present` distinguishes a
+/// permission violation from a missing page; `write` and `instruction` say
+/// what the access was.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PageFault {
+    pub addr: u64,
+    pub rip: u64,
+    pub present: bool,
+    pub write: bool,
+    pub user: bool,
+    pub reserved: bool,
+    pub instruction: bool,
+}
+
+impl PageFault {
+    fn decode(addr: u64, rip: u64, err: u64) -> Self {
+        Self {
+            addr,
+            rip,
+            present: (err & 1) != 0,
+            write: (err & 2) != 0,
+            user: (err & 4) != 0,
+            reserved: (err & 8) != 0,
+            instruction: (err & 16) != 0,
+        }
+    }
+}
+
+/// The most recent page fault that killed a user process, and its pid.
+static LAST_USER_FAULT: SpinLock<Option<(Pid, PageFault)>> = SpinLock::new(None);
+
+/// Return and clear the last fatal user page fault, so a caller can check
+/// which protection a process tripped over.
+pub fn take_last_user_fault() -> Option<(Pid, PageFault)> {
+    LAST_USER_FAULT.lock().take()
+}
+
 fn page_fault_handler(frame: &mut InterruptFrame) {
     let fault_addr = unsafe { mmu::read_cr2() };
     let err = frame.err_code;
-
-    let present = (err & 1) != 0;
-    let write = (err & 2) != 0;
-    let user = (err & 4) != 0;
-    let reserved = (err & 8) != 0;
-    let instruction = (err & 16) != 0;
+    let fault = PageFault::decode(fault_addr, frame.rip, err);
 
     klog!(
         "[page_fault] addr=0x{:016X} err=0x{:X} rip=0x{:016X} cs=0x{:X} present={} write={} user={} reserved={} instruction={}\n",
@@ -342,13 +605,26 @@ fn page_fault_handler(frame: &mut InterruptFrame) {
         err,
         frame.rip,
         frame.cs,
-        present,
-        write,
-        user,
-        reserved,
-        instruction
+        fault.present,
+        fault.write,
+        fault.user,
+        fault.reserved,
+        fault.instruction
     );
 
+    if fault.present && fault.write && !fault.reserved {
+        let cr3 = unsafe { mmu::read_cr3() } & !0xFFF;
+        if paging::resolve_cow_fault(cr3, fault_addr) {
+            return;
+        }
+    }
+
+    if from_user(frame) {
+        if let Some(pid) = crate::process::current_pid() {
+            *LAST_USER_FAULT.lock() = Some((pid, fault));
+        }
+        kill_faulting_process("page_fault", fault_exit::PAGE_FAULT);
+    }
     qemu::exit_failure();
 }
 
@@ -366,6 +642,10 @@ fn general_protection_handler(frame: &mut InterruptFrame) {
         frame.err_code
     );
 
+    if from_user(frame) {
+        kill_faulting_process("gpf", fault_exit::GENERAL_PROTECTION);
+    }
+
     if let Some(pid) = pid {
         if let Ok(()) = process::dump_process(pid) {
             klog!("[gpf] dumped process {}\n", pid);
@@ -401,7 +681,20 @@ fn invalid_opcode_handler(frame: &mut InterruptFrame) {
 
     let pid = process::current_pid();
     let rip = frame.rip;
-    let bytes = unsafe { slice::from_raw_parts(rip as *const u8, 16) };
+    let user = from_user(frame);
+    let mut bytes = [0u8; 16];
+    if user {
+        // User text may be unmapped or end short of 16 bytes; go through the
+        // checked copy rather than dereferencing rip directly.
+        let copied = process::current_address_space()
+            .map(|space| process::copy_from_user(&space, &mut bytes, rip).is_ok())
+            .unwrap_or(false);
+        if !copied {
+            klog!("[invop] instruction bytes at 0x{:016X} unreadable\n", rip);
+        }
+    } else {
+        bytes.copy_from_slice(unsafe { slice::from_raw_parts(rip as *const u8, 16) });
+    }
 
     klog!(
         "[invop] pid={:?} rip=0x{:016X} cs=0x{:X} rflags=0x{:016X} bytes={:02X?}\n",
@@ -412,6 +705,10 @@ fn invalid_opcode_handler(frame: &mut InterruptFrame) {
         bytes
     );
 
+    if user {
+        kill_faulting_process("invop", fault_exit::INVALID_OPCODE);
+    }
+
     if let Some(pid) = pid {
         if let Ok(()) = process::dump_process(pid) {
             klog!("[invop] dumped process {}\n", pid);
@@ -428,12 +725,32 @@ extern "C" fn isr_handler(frame: &mut InterruptFrame) {
 
 #[no_mangle]
 extern "C" fn irq_handler(frame: &mut InterruptFrame) {
+    let irq = (frame.int_no as u8).wrapping_sub(PIC_MASTER_OFFSET);
+    // Reading the ISR costs two port round trips, so only IRQ7 and IRQ15,
+    // which can be spurious, pay for it.
+    let isr = if eoi::may_be_spurious(irq) { unsafe { pic::read_isr() } } else { 0 };
+    if !eoi::is_spurious(irq, isr) {
+        dispatch(frame);
+    }
+    pic::send_eoi(eoi::eoi_for(irq, isr));
+    drain_deferred();
+}
+
+#[no_mangle]
+extern "C" fn lapic_irq_handler(frame: &mut InterruptFrame) {
     dispatch(frame);
-    pic::send_eoi(frame.int_no as u8);
+    // A spurious interrupt was never put in service, so it takes no EOI.
+    if frame.int_no as u8 != vectors::SPURIOUS {
+        apic::eoi();
+    }
+    drain_deferred();
 }
 
+fn spurious_handler(_frame: &mut InterruptFrame) {}
+
 fn dispatch(frame: &mut InterruptFrame) {
     let vector = frame.int_no as usize;
+    COUNTS[vector].fetch_add(1, Ordering::Relaxed);
 
     let handler = unsafe { HANDLERS[vector] };
     handler(frame);
@@ -467,6 +784,10 @@ unsafe fn setup_idt() {
         IDT.0[index].set_handler(*handler, GDT_KERNEL_CODE, IDT_TYPE_ATTR, 0);
     }
 
+    IDT.0[vectors::LAPIC_TIMER as usize].set_handler(lapic_timer, GDT_KERNEL_CODE, IDT_TYPE_ATTR, 0);
+    IDT.0[vectors::SPURIOUS as usize].set_handler(lapic_spurious, GDT_KERNEL_CODE, IDT_TYPE_ATTR, 0);
+    register_handler(vectors::SPURIOUS, spurious_handler);
+
     IDTR.limit = (size_of::<IdtEntry>() * IDT_ENTRIES - 1) as u16;
     IDTR.base = core::ptr::addr_of!(IDT.0) as u64;
 }
@@ -477,6 +798,7 @@ unsafe fn load_idt() {
 
 mod pic {
     use super::klog;
+    use crate::interrupts::eoi::Eoi;
     use crate::arch::x86_64::io::{inb, outb};
 
     const PIC1: u16 = 0x20;
@@ -487,6 +809,7 @@ mod pic {
     const PIC2_DATA: u16 = PIC2 + 1;
 
     const PIC_EOI: u8 = 0x20;
+    const OCW3_READ_ISR: u8 = 0x0B;
 
     const ICW1_INIT: u8 = 0x10;
     const ICW1_ICW4: u8 = 0x01;
@@ -520,13 +843,25 @@ mod pic {
         klog::writeln("[interrupts] PIC remapped");
     }
 
-    pub(super) fn send_eoi(vector: u8) {
+    /// In-service registers of both chips, master in the low byte.
+    pub(super) unsafe fn read_isr() -> u16 {
+        outb(PIC1_CMD, OCW3_READ_ISR);
+        outb(PIC2_CMD, OCW3_READ_ISR);
+        let master = inb(PIC1_CMD) as u16;
+        let slave = inb(PIC2_CMD) as u16;
+        (slave << 8) | master
+    }
+
+    pub(super) fn send_eoi(eoi: Eoi) {
         unsafe {
-            if vector >= 40 {
-                outb(PIC2_CMD, PIC_EOI);
+            match eoi {
+                Eoi::None => {}
+                Eoi::Master => outb(PIC1_CMD, PIC_EOI),
+                Eoi::Both => {
+                    outb(PIC2_CMD, PIC_EOI);
+                    outb(PIC1_CMD, PIC_EOI);
+                }
             }
-
-            outb(PIC1_CMD, PIC_EOI);
         }
     }
 
@@ -74,6 +74,16 @@ global_asm!(r#"
         jmp irq_common
     .endm
 
+    .macro lapic name, vector
+        .globl \name
+        .type \name, @function
+    \name:
+        cli
+        push 0
+        push \vector
+        jmp lapic_common
+    .endm
+
     .globl idt_stub_load
     .type idt_stub_load, @function
 idt_stub_load:
@@ -130,6 +140,9 @@ idt_stub_load:
     irq      14,  46
     irq      15,  47
 
+    lapic lapic_timer,    0xF0
+    lapic lapic_spurious, 0xFF
+
     .globl isr_common
     .type isr_common, @function
 isr_common:
@@ -190,5 +203,35 @@ irq_common:
     sti
     iretq
 
+    .globl lapic_common
+    .type lapic_common, @function
+lapic_common:
+    push_all
+
+    mov ax, ds
+    push rax
+
+    mov ax, 0x10
+    mov ds, ax
+    mov es, ax
+    mov fs, ax
+    mov gs, ax
+
+    mov rdi, rsp
+    call lapic_irq_handler
+
+    pop rbx
+    mov ds, bx
+    mov es, bx
+    mov fs, bx
+    mov gs, bx
+
+    pop_all
+
+    add rsp, 16
+
+    sti
+    iretq
+
     .section .note.GNU-stack,"",@progbits
 "#);
@@ -1,13 +1,17 @@
 #![allow(dead_code)]
 
 use crate::arch::x86_64::kernel::mmu;
+use crate::boot;
 use crate::klog;
 use crate::sync::spinlock::SpinLock;
 use crate::mem::heap;
+use crate::mem::mmap::{self, MmapEntry, MmapTotals};
 
 const MAX_REGIONS: usize = 128;
 const PAGE_SIZE: u64 = 4096;
 const RESERVED_END: u64 = 0x0010_0000; // keep first 1 MiB reserved (legacy floor)
+const MAX_SHARED_FRAMES: usize = 256;
+const MAX_RESERVED_REGIONS: usize = 64;
 
 pub const FRAME_SIZE: u64 = PAGE_SIZE;
 
@@ -106,10 +110,20 @@ struct MemoryMap {
     regions: [MemoryRegion; MAX_REGIONS],
     count: usize,
 }
+/// Bump allocator over the usable regions, handing out frames in ascending
+/// address order. Single frames that are freed go on an intrusive list (the
+/// next pointer lives in the freed frame itself) and are handed out again
+/// before the bump pointer moves; contiguous runs always come fresh.
+#[derive(Copy, Clone)]
 struct FrameAllocator {
     current: u64,
     end: u64,
     region_index: usize,
+    reserve_limit: u64,
+    free_head: u64,
+    free_listed: usize,
+    used: usize,
+    peak_used: usize,
 }
 impl FrameAllocator {
     const fn new() -> Self {
@@ -117,17 +131,30 @@ impl FrameAllocator {
             current: 0,
             end: 0,
             region_index: 0,
+            reserve_limit: 0,
+            free_head: 0,
+            free_listed: 0,
+            used: 0,
+            peak_used: 0,
         }
     }
 
-    fn init_from_map(&mut self, map: &MemoryMap) {
+    fn init_from_map(&mut self, map: &MemoryMap, reserve_limit: u64) {
         self.region_index = 0;
         self.current = 0;
         self.end = 0;
+        self.reserve_limit = reserve_limit;
         self.advance_to_next_region(map);
     }
 
     fn allocate(&mut self, map: &MemoryMap) -> Option<Frame> {
+        if self.free_head != 0 {
+            let frame = self.free_head;
+            self.free_head = unsafe { (mmu::phys_to_virt(frame) as *const u64).read() };
+            self.free_listed -= 1;
+            self.note_allocated(1);
+            return Some(Frame { start: frame });
+        }
         loop {
             if self.current >= self.end {
                 self.advance_to_next_region(map);
@@ -143,16 +170,68 @@ impl FrameAllocator {
                 continue;
             }
 
+            self.note_allocated(1);
             return Some(Frame { start: frame });
         }
     }
 
-    fn free(&mut self, _frame: Frame) {
-        // no-op for bump allocator
+    /// Take `count` physically adjacent frames, ending at or below `limit`,
+    /// from the first region at or after the cursor with room for all of
+    /// them. The skipped tail of an earlier region is abandoned, as the bump
+    /// pointer cannot go back. When nothing fits the allocator is left
+    /// untouched.
+    fn allocate_contiguous(&mut self, map: &MemoryMap, count: usize, limit: u64) -> Option<Frame> {
+        let bytes = (count as u64).checked_mul(PAGE_SIZE)?;
+        let mut probe = *self;
+        loop {
+            if probe.current == 0 && probe.current < probe.end {
+                // Frame 0 is never handed out; `allocate` skips it too.
+                probe.current = PAGE_SIZE;
+            }
+            let fits_below = probe.current.checked_add(bytes).is_some_and(|end| end <= limit);
+            if probe.current < probe.end && probe.end - probe.current >= bytes && fits_below {
+                let start = probe.current;
+                probe.current += bytes;
+                *self = probe;
+                self.note_allocated(count);
+                return Some(Frame { start });
+            }
+            probe.advance_to_next_region(map);
+            if probe.current >= probe.end {
+                return None;
+            }
+        }
+    }
+
+    fn free(&mut self, frame: Frame) {
+        if frame.start == 0 {
+            return;
+        }
+        unsafe { (mmu::phys_to_virt(frame.start) as *mut u64).write(self.free_head) };
+        self.free_head = frame.start;
+        self.free_listed += 1;
+        self.used = self.used.saturating_sub(1);
+    }
+
+    fn note_allocated(&mut self, count: usize) {
+        self.used += count;
+        self.peak_used = self.peak_used.max(self.used);
+    }
+
+    /// Frames that can still be handed out: the freed list plus whatever
+    /// the bump pointer has not reached. Region tails skipped by a
+    /// contiguous request are neither used nor free.
+    fn free_frames(&self, map: &MemoryMap) -> usize {
+        let mut bytes = self.end.saturating_sub(self.current);
+        for region in map.regions[self.region_index.min(map.count)..map.count].iter() {
+            let start = align_up_u64(region.base.max(self.reserve_limit), PAGE_SIZE);
+            bytes = bytes.saturating_add(region.end().saturating_sub(start));
+        }
+        (bytes / PAGE_SIZE) as usize + self.free_listed
     }
 
     fn advance_to_next_region(&mut self, map: &MemoryMap) {
-        let reserve_limit = reserved_limit();
+        let reserve_limit = self.reserve_limit;
         while self.region_index < map.count {
             let region = map.regions[self.region_index];
             self.region_index += 1;
@@ -204,7 +283,48 @@ impl MemoryMap {
     }
 }
 
+/// Regions the firmware reported but that are not ours to allocate from:
+/// reserved, ACPI, and bad memory. Kept only for reporting.
+struct ReservedMap {
+    entries: [MmapEntry; MAX_RESERVED_REGIONS],
+    count: usize,
+    /// Totals over the whole map, usable entries included, and counting
+    /// reserved entries that did not fit in `entries`.
+    totals: MmapTotals,
+}
+
+impl ReservedMap {
+    const fn new() -> Self {
+        Self {
+            entries: [MmapEntry {
+                base: 0,
+                length: 0,
+                entry_type: 0,
+            }; MAX_RESERVED_REGIONS],
+            count: 0,
+            totals: MmapTotals::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.count = 0;
+        self.totals = MmapTotals::new();
+    }
+
+    fn add_entry(&mut self, entry: MmapEntry) {
+        if self.count < MAX_RESERVED_REGIONS {
+            self.entries[self.count] = entry;
+            self.count += 1;
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &MmapEntry> {
+        self.entries[..self.count].iter()
+    }
+}
+
 static PHYS_MEMORY_MAP: SpinLock<MemoryMap> = SpinLock::new(MemoryMap::new());
+static RESERVED_MAP: SpinLock<ReservedMap> = SpinLock::new(ReservedMap::new());
 static FRAME_ALLOCATOR: SpinLock<FrameAllocator> = SpinLock::new(FrameAllocator::new());
 
 #[repr(C)]
@@ -220,22 +340,25 @@ struct MemoryMapTagHeader {
     entry_version: u32,
 }
 
-#[repr(C)]
-struct MemoryMapEntry {
-    base_addr: u64,
-    length: u64,
-    entry_type: u32,
-    _reserved: u32,
-}
-
 const TAG_TYPE_END: u32 = 0;
 const TAG_TYPE_MMAP: u32 = 6;
-const MEMORY_TYPE_AVAILABLE: u32 = 1;
 
+/// `region_count` and `total_bytes` cover usable memory only;
+/// `total_installed` also counts reserved, ACPI, and bad regions.
 #[derive(Copy, Clone)]
 pub struct MemorySummary {
     pub region_count: usize,
     pub total_bytes: u64,
+    pub reserved_count: usize,
+    pub reserved_bytes: u64,
+    pub total_installed: u64,
+    pub largest_contiguous: u64,
+    /// Frames the allocator can still hand out.
+    pub free_frames: usize,
+    /// Frames allocated and not yet freed.
+    pub used_frames: usize,
+    /// Highest `used_frames` seen since boot.
+    pub peak_used: usize,
 }
 
 pub fn init(multiboot_info_addr: usize) {
@@ -249,6 +372,18 @@ pub fn init(multiboot_info_addr: usize) {
         summary.region_count,
         summary.total_bytes / 1024
     );
+    klog!(
+        "[phys] installed {:>6} KiB, {} reserved region(s) {:>6} KiB, largest usable {:>6} KiB\n",
+        summary.total_installed / 1024,
+        summary.reserved_count,
+        summary.reserved_bytes / 1024,
+        summary.largest_contiguous / 1024
+    );
+    klog!(
+        "[phys] frames free={} used={}\n",
+        summary.free_frames,
+        summary.used_frames
+    );
 
     for_each_region(|region| {
         klog!(
@@ -258,6 +393,15 @@ pub fn init(multiboot_info_addr: usize) {
             region.page_count()
         );
     });
+
+    for_each_reserved_region(|entry| {
+        klog!(
+            "[phys] {}: base=0x{:016X} len=0x{:016X}\n",
+            entry.type_name(),
+            entry.base,
+            entry.length
+        );
+    });
 }
 
 pub fn for_each_region<F>(mut f: F)
@@ -270,15 +414,35 @@ where
     }
 }
 
+/// Visit the regions that are not usable RAM, in firmware order.
+pub fn for_each_reserved_region<F>(mut f: F)
+where
+    F: FnMut(&MmapEntry),
+{
+    let reserved = RESERVED_MAP.lock();
+    for entry in reserved.iter() {
+        f(entry);
+    }
+}
+
 pub fn summary() -> MemorySummary {
     let map = PHYS_MEMORY_MAP.lock();
     let mut total = 0u64;
     for region in map.iter() {
         total = total.saturating_add(region.length);
     }
+    let totals = RESERVED_MAP.lock().totals;
+    let allocator = FRAME_ALLOCATOR.lock();
     MemorySummary {
         region_count: map.count,
         total_bytes: total,
+        reserved_count: totals.reserved_regions,
+        reserved_bytes: totals.reserved_bytes,
+        total_installed: totals.total_installed,
+        largest_contiguous: totals.largest_contiguous,
+        free_frames: allocator.free_frames(&map),
+        used_frames: allocator.used,
+        peak_used: allocator.peak_used,
     }
 }
 
@@ -289,43 +453,139 @@ pub fn allocate_frame() -> Option<Frame> {
     frame
 }
 
+/// Allocate `count` physically contiguous frames. The result always covers
+/// exactly `count` frames; if no region has that much room left, `None` is
+/// returned and no frames are consumed.
 pub fn allocate_frames(count: usize) -> Option<FrameRange> {
+    allocate_frames_below(count, u64::MAX)
+}
+
+/// `allocate_frames`, for a run that must end at or below physical address
+/// `limit`. A run past it is never taken, so nothing needs handing back.
+pub fn allocate_frames_below(count: usize, limit: u64) -> Option<FrameRange> {
+    let map_guard = PHYS_MEMORY_MAP.lock();
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    allocate_range(&mut allocator, &map_guard, count, limit)
+}
+
+fn allocate_range(allocator: &mut FrameAllocator, map: &MemoryMap, count: usize, limit: u64) -> Option<FrameRange> {
     if count == 0 {
         return None;
     }
+    let start = allocator.allocate_contiguous(map, count, limit)?;
+    Some(FrameRange { start, count })
+}
 
-    let map_guard = PHYS_MEMORY_MAP.lock();
+/// A private allocator over a synthetic memory map, so tests can exercise
+/// region boundaries without consuming real frames. Addresses are never
+/// touched.
+#[cfg(kernel_test)]
+pub struct ScratchFrames {
+    map: MemoryMap,
+    allocator: FrameAllocator,
+}
+
+#[cfg(kernel_test)]
+impl ScratchFrames {
+    pub fn new(regions: &[MemoryRegion]) -> Self {
+        let mut map = MemoryMap::new();
+        for region in regions {
+            map.add_region(*region);
+        }
+        let mut allocator = FrameAllocator::new();
+        allocator.init_from_map(&map, RESERVED_END);
+        Self { map, allocator }
+    }
+
+    pub fn allocate_frame(&mut self) -> Option<Frame> {
+        self.allocator.allocate(&self.map)
+    }
+
+    pub fn allocate_frames(&mut self, count: usize) -> Option<FrameRange> {
+        allocate_range(&mut self.allocator, &self.map, count, u64::MAX)
+    }
+
+    pub fn allocate_frames_below(&mut self, count: usize, limit: u64) -> Option<FrameRange> {
+        allocate_range(&mut self.allocator, &self.map, count, limit)
+    }
+}
+
+pub fn free_frame(frame: Frame) {
     let mut allocator = FRAME_ALLOCATOR.lock();
+    allocator.free(frame);
+}
 
-    let first = allocator.allocate(&map_guard)?;
-    let mut last = first;
+/// Reference counts for frames mapped by more than one address space. A frame
+/// with no entry has a single owner, so only shared frames take a slot.
+struct RefcountTable {
+    entries: [(u64, usize); MAX_SHARED_FRAMES],
+}
 
-    for _ in 1..count {
-        match allocator.allocate(&map_guard) {
-            Some(next) if next.start == last.start + FRAME_SIZE => {
-                last = next;
+impl RefcountTable {
+    const fn new() -> Self {
+        Self {
+            entries: [(0, 0); MAX_SHARED_FRAMES],
+        }
+    }
+
+    fn get(&self, frame: Frame) -> usize {
+        self.entries
+            .iter()
+            .find(|(start, count)| *count != 0 && *start == frame.start)
+            .map_or(1, |(_, count)| *count)
+    }
+
+    fn increment(&mut self, frame: Frame) -> bool {
+        if let Some(entry) = self.entries.iter_mut().find(|(start, count)| *count != 0 && *start == frame.start) {
+            entry.1 += 1;
+            return true;
+        }
+        match self.entries.iter_mut().find(|(_, count)| *count == 0) {
+            Some(slot) => {
+                *slot = (frame.start, 2);
+                true
             }
-            Some(_) | None => {
-                // Out-of-line allocation; we can't rewind the bump pointer,
-                // so just report the contiguous sequence obtained so far.
-                let span_frames = ((last.start - first.start) / FRAME_SIZE) as usize + 1;
-                return Some(FrameRange {
-                    start: first,
-                    count: span_frames,
-                });
+            None => false,
+        }
+    }
+
+    fn decrement(&mut self, frame: Frame) -> usize {
+        match self.entries.iter_mut().find(|(start, count)| *count != 0 && *start == frame.start) {
+            Some(entry) => {
+                entry.1 -= 1;
+                let remaining = entry.1;
+                if remaining == 1 {
+                    // Back to a single owner; drop the slot.
+                    entry.1 = 0;
+                }
+                remaining
             }
+            None => 0,
         }
     }
+}
+
+static FRAME_REFCOUNTS: SpinLock<RefcountTable> = SpinLock::new(RefcountTable::new());
 
-    Some(FrameRange {
-        start: first,
-        count,
-    })
+/// Record one more mapping of `frame`. Returns `false` when the table of
+/// shared frames is full, in which case the caller must not share it.
+pub fn share_frame(frame: Frame) -> bool {
+    FRAME_REFCOUNTS.lock().increment(frame)
 }
 
-pub fn free_frame(frame: Frame) {
-    let mut allocator = FRAME_ALLOCATOR.lock();
-    allocator.free(frame);
+/// Number of address spaces currently mapping `frame`.
+pub fn frame_refcount(frame: Frame) -> usize {
+    FRAME_REFCOUNTS.lock().get(frame)
+}
+
+/// Drop one mapping of `frame`, freeing it once nobody maps it. Returns the
+/// references left.
+pub fn release_frame(frame: Frame) -> usize {
+    let remaining = FRAME_REFCOUNTS.lock().decrement(frame);
+    if remaining == 0 {
+        free_frame(frame);
+    }
+    remaining
 }
 
 pub fn frame_size() -> u64 {
@@ -338,7 +598,9 @@ unsafe fn parse(multiboot_info_addr: usize) {
     let end = multiboot_info_addr + total_size;
 
     let mut map = PHYS_MEMORY_MAP.lock();
+    let mut reserved = RESERVED_MAP.lock();
     map.clear();
+    reserved.clear();
 
     while current < end {
         let header = &*(current as *const TagHeader);
@@ -347,31 +609,32 @@ unsafe fn parse(multiboot_info_addr: usize) {
         }
 
         if header.tag_type == TAG_TYPE_MMAP {
-            parse_memory_map_tag(current as *const MemoryMapTagHeader, &mut map);
+            parse_memory_map_tag(current as *const MemoryMapTagHeader, &mut map, &mut reserved);
         }
 
         current = align_up(current + header.size as usize, 8);
     }
 
-    FRAME_ALLOCATOR.lock().init_from_map(&map);
+    FRAME_ALLOCATOR.lock().init_from_map(&map, reserved_limit());
 }
 
-unsafe fn parse_memory_map_tag(ptr: *const MemoryMapTagHeader, map: &mut MemoryMap) {
-    let tag = &*ptr;
-    let entry_size = tag.entry_size as usize;
-    let entries_start = (ptr as usize) + core::mem::size_of::<MemoryMapTagHeader>();
-    let entries_end = (ptr as usize) + tag.header.size as usize;
+unsafe fn parse_memory_map_tag(ptr: *const MemoryMapTagHeader, map: &mut MemoryMap, reserved: &mut ReservedMap) {
+    let size = (*ptr).header.size as usize;
+    let tag = core::slice::from_raw_parts(ptr as *const u8, size);
 
-    let mut current = entries_start;
-    while current + entry_size <= entries_end {
-        let entry = &*(current as *const MemoryMapEntry);
-        if entry.entry_type == MEMORY_TYPE_AVAILABLE && entry.length > 0 {
+    for entry in mmap::entries(tag) {
+        if entry.length == 0 {
+            continue;
+        }
+        reserved.totals.add(&entry);
+        if entry.is_available() {
             map.add_region(MemoryRegion {
-                base: entry.base_addr,
+                base: entry.base,
                 length: entry.length,
             });
+        } else {
+            reserved.add_entry(entry);
         }
-        current += entry_size;
     }
 }
 
@@ -411,7 +674,7 @@ fn reserved_limit() -> u64 {
         kernel_end
     );
 
-    let (heap_start_virt, heap_end_virt) = heap::bounds();
+    let (heap_start_virt, heap_end_virt) = heap::early_bounds();
     let heap_end_phys = if heap_end_virt >= mmu::KERNEL_LINK_BASE as usize {
         heap_end_virt as u64 - mmu::KERNEL_LINK_BASE
     } else {
@@ -425,7 +688,14 @@ fn reserved_limit() -> u64 {
         heap_end_phys
     );
 
-    let limit = core::cmp::max(core::cmp::max(RESERVED_END, kernel_end), heap_end_phys);
+    // Boot modules sit just past the kernel; keeping the floor above them
+    // stops their frames from being handed out before kmain mounts them.
+    let modules_end = boot::modules().end();
+    if modules_end != 0 {
+        klog!("[phys] boot modules end phys=0x{:X}\n", modules_end);
+    }
+
+    let limit = RESERVED_END.max(kernel_end).max(heap_end_phys).max(modules_end);
     align_up_u64(limit, PAGE_SIZE)
 }
 
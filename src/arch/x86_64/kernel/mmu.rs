@@ -1,3 +1,5 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
 pub(crate) unsafe fn read_cr2() -> u64 {
     let value: u64;
     core::arch::asm!("mov {}, cr2", out(reg) value, options(nomem, preserves_flags));
@@ -14,8 +16,23 @@ pub(crate) unsafe fn write_cr3(value: u64) {
     core::arch::asm!("mov cr3, {}", in(reg) value, options(nostack, preserves_flags));
 }
 
+/// Drop any cached translation for the page containing `addr` on this CPU.
+pub(crate) fn flush_tlb(addr: u64) {
+    unsafe {
+        core::arch::asm!("invlpg [{}]", in(reg) addr, options(nostack, preserves_flags));
+    }
+}
+
+/// Drop every non-global cached translation by reloading cr3.
+pub(crate) fn flush_tlb_all() {
+    unsafe { write_cr3(read_cr3()) }
+}
+
 pub(crate) const KERNEL_VMA_BASE: u64 = 0xFFFF_8000_0000_0000;
 pub(crate) const KERNEL_LINK_BASE: u64 = 0xFFFF_FFFF_8000_0000;
+/// Physical memory below this is reachable at `KERNEL_VMA_BASE + phys`;
+/// the boot page tables map the first 1 GiB there and nothing more.
+pub(crate) const DIRECT_MAP_LIMIT: u64 = 1 << 30;
 
 pub(crate) fn phys_to_virt(phys: u64) -> u64 {
     phys + KERNEL_VMA_BASE
@@ -24,3 +41,53 @@ pub(crate) fn phys_to_virt(phys: u64) -> u64 {
 pub(crate) fn virt_to_phys(virt: u64) -> u64 {
     virt - KERNEL_VMA_BASE
 }
+
+/// Set once SSE2 is enabled; until then `zero_page` sticks to `rep stosq`.
+static NT_ZERO: AtomicBool = AtomicBool::new(false);
+
+/// Let `zero_page` use non-temporal `movnti` stores (an SSE2 instruction).
+/// Call only after `cpu::enable_sse`, on a CPU whose CPUID reports SSE2.
+pub(crate) fn enable_nt_zero() {
+    NT_ZERO.store(true, Ordering::Release);
+}
+
+/// Clear the 4 KiB frame at `phys` through the physical-memory alias.
+/// With SSE2 the stores are non-temporal `movnti`s from a general-purpose
+/// register, so a freshly handed-out frame does not push the caller's
+/// working set out of the cache; otherwise one `rep stosq` covers the page.
+/// No XMM register is touched: the kernel is built without SSE and does
+/// not save a user's XMM state on entry.
+pub(crate) fn zero_page(phys: u64) {
+    let virt = phys_to_virt(phys & !0xFFF);
+    unsafe {
+        if NT_ZERO.load(Ordering::Acquire) {
+            core::arch::asm!(
+                "2:",
+                "movnti [{ptr}], {zero}",
+                "movnti [{ptr} + 8], {zero}",
+                "movnti [{ptr} + 16], {zero}",
+                "movnti [{ptr} + 24], {zero}",
+                "movnti [{ptr} + 32], {zero}",
+                "movnti [{ptr} + 40], {zero}",
+                "movnti [{ptr} + 48], {zero}",
+                "movnti [{ptr} + 56], {zero}",
+                "add {ptr}, 64",
+                "dec {count:e}",
+                "jnz 2b",
+                "sfence",
+                ptr = inout(reg) virt => _,
+                count = inout(reg) 4096u32 / 64 => _,
+                zero = in(reg) 0u64,
+                options(nostack),
+            );
+        } else {
+            core::arch::asm!(
+                "rep stosq",
+                inout("rdi") virt => _,
+                inout("rcx") 4096u64 / 8 => _,
+                in("rax") 0u64,
+                options(nostack, preserves_flags),
+            );
+        }
+    }
+}
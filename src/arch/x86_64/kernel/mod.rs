@@ -1,3 +1,5 @@
+pub mod apic;
+pub mod backtrace;
 pub mod cpu;
 pub mod gdt;
 pub mod interrupts;
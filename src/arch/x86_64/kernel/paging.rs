@@ -1,9 +1,12 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use crate::klog;
 use crate::mem::phys;
 
 use super::mmu;
 
 pub const PAGE_SIZE: usize = 4096;
+pub const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
 const PAGE_TABLE_ENTRIES: usize = 512;
 const ENTRY_ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
 
@@ -13,12 +16,17 @@ pub const FLAG_USER: u64 = 1 << 2;
 pub const FLAG_WRITE_THROUGH: u64 = 1 << 3;
 pub const FLAG_CACHE_DISABLE: u64 = 1 << 4;
 pub const FLAG_HUGE: u64 = 1 << 7;
+/// Software bit (ignored by the MMU) marking a read-only PTE whose frame is
+/// shared copy-on-write; a write fault on it gets a private copy.
+pub const FLAG_COW: u64 = 1 << 9;
 pub const FLAG_NO_EXECUTE: u64 = 1 << 63;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum MapError {
     OutOfMemory,
     AlreadyMapped,
+    NotMapped,
+    Misaligned,
 }
 
 type PageTable = [u64; PAGE_TABLE_ENTRIES];
@@ -31,10 +39,8 @@ fn table_from_phys(phys: u64) -> &'static mut PageTable {
 fn allocate_table() -> Result<(u64, &'static mut PageTable), MapError> {
     let frame = phys::allocate_frame().ok_or(MapError::OutOfMemory)?;
     let phys = frame.start();
+    mmu::zero_page(phys);
     let table = table_from_phys(phys);
-    for entry in table.iter_mut() {
-        *entry = 0;
-    }
     klog!(
         "[paging] allocate_table frame=0x{:016X} virt=0x{:016X}\n",
         phys,
@@ -189,6 +195,75 @@ pub fn unmap_page(pml4_phys: u64, virt_addr: u64) {
 
     let pte = &mut pt[pt_index(virt_addr)];
     *pte = 0;
+    flush_if_active(pml4_phys, virt_addr);
+}
+
+/// Map a 2 MiB page with a single PD entry. `virt_addr` and `frame_phys`
+/// must both be 2 MiB aligned, and nothing may be mapped in that slot yet,
+/// including a page table of 4 KiB pages.
+pub fn map_huge_page(
+    pml4_phys: u64,
+    virt_addr: u64,
+    frame_phys: u64,
+    flags: u64,
+) -> Result<(), MapError> {
+    let mask = HUGE_PAGE_SIZE as u64 - 1;
+    if virt_addr & mask != 0 || frame_phys & mask != 0 {
+        return Err(MapError::Misaligned);
+    }
+
+    let user = flags & FLAG_USER != 0;
+
+    let pml4 = table_from_phys(pml4_phys);
+    let pdpt = ensure_table(&mut pml4[pml4_index(virt_addr)], user)?;
+    let pdpte = &mut pdpt[pdpt_index(virt_addr)];
+    if *pdpte & FLAG_PRESENT != 0 && *pdpte & FLAG_HUGE != 0 {
+        return Err(MapError::AlreadyMapped);
+    }
+    let pd = ensure_table(pdpte, user)?;
+
+    let pde = &mut pd[pd_index(virt_addr)];
+    if *pde & FLAG_PRESENT != 0 {
+        return Err(MapError::AlreadyMapped);
+    }
+
+    *pde = frame_phys | flags | FLAG_PRESENT | FLAG_HUGE;
+    klog!(
+        "[paging] map_huge_page virt=0x{:016X} frame=0x{:016X} pde=0x{:016X}\n",
+        virt_addr,
+        frame_phys,
+        *pde
+    );
+    Ok(())
+}
+
+/// Remove a 2 MiB mapping made by `map_huge_page`. Does nothing if the slot
+/// holds 4 KiB pages or is empty.
+pub fn unmap_huge_page(pml4_phys: u64, virt_addr: u64) {
+    if virt_addr & (HUGE_PAGE_SIZE as u64 - 1) != 0 {
+        return;
+    }
+
+    let pml4 = table_from_phys(pml4_phys);
+    let pml4e = pml4[pml4_index(virt_addr)];
+    if pml4e & FLAG_PRESENT == 0 {
+        return;
+    }
+    let pdpt = table_from_phys(pml4e & ENTRY_ADDR_MASK);
+
+    let pdpte = pdpt[pdpt_index(virt_addr)];
+    if pdpte & FLAG_PRESENT == 0 || pdpte & FLAG_HUGE != 0 {
+        return;
+    }
+    let pd = table_from_phys(pdpte & ENTRY_ADDR_MASK);
+
+    let pde = &mut pd[pd_index(virt_addr)];
+    if *pde & FLAG_PRESENT == 0 || *pde & FLAG_HUGE == 0 {
+        return;
+    }
+    *pde = 0;
+    // invlpg on any address inside a large page drops the whole entry.
+    flush_if_active(pml4_phys, virt_addr);
 }
 
 pub fn translate(pml4_phys: u64, virt_addr: u64) -> Option<u64> {
@@ -231,3 +306,310 @@ pub fn translate(pml4_phys: u64, virt_addr: u64) -> Option<u64> {
     let offset = virt_addr & 0xFFF;
     Some(base + offset)
 }
+
+/// The 4 KiB leaf entry for `virt_addr`, if every level above it is present.
+fn leaf_entry(pml4_phys: u64, virt_addr: u64) -> Option<&'static mut u64> {
+    let pml4 = table_from_phys(pml4_phys);
+    let pml4e = pml4[pml4_index(virt_addr)];
+    if pml4e & FLAG_PRESENT == 0 {
+        return None;
+    }
+    let pdpt = table_from_phys(pml4e & ENTRY_ADDR_MASK);
+    let pdpte = pdpt[pdpt_index(virt_addr)];
+    if pdpte & FLAG_PRESENT == 0 || pdpte & FLAG_HUGE != 0 {
+        return None;
+    }
+    let pd = table_from_phys(pdpte & ENTRY_ADDR_MASK);
+    let pde = pd[pd_index(virt_addr)];
+    if pde & FLAG_PRESENT == 0 || pde & FLAG_HUGE != 0 {
+        return None;
+    }
+    let pt = table_from_phys(pde & ENTRY_ADDR_MASK);
+    Some(&mut pt[pt_index(virt_addr)])
+}
+
+/// Flags of the 4 KiB page mapping `virt_addr`, without the frame address.
+pub fn page_flags(pml4_phys: u64, virt_addr: u64) -> Option<u64> {
+    let pte = leaf_entry(pml4_phys, virt_addr & !0xFFF)?;
+    if *pte & FLAG_PRESENT == 0 {
+        return None;
+    }
+    Some(*pte & !ENTRY_ADDR_MASK)
+}
+
+fn is_active(pml4_phys: u64) -> bool {
+    unsafe { mmu::read_cr3() & ENTRY_ADDR_MASK == pml4_phys & ENTRY_ADDR_MASK }
+}
+
+/// Invalidate `virt_addr` after its PTE changed. Other address spaces are
+/// not cached while inactive, so only the live cr3 needs it.
+fn flush_if_active(pml4_phys: u64, virt_addr: u64) {
+    if is_active(pml4_phys) {
+        mmu::flush_tlb(virt_addr);
+    }
+}
+
+/// Forget every cached translation of `pml4_phys` if it is the live address
+/// space, for use after tearing down many mappings at once.
+pub fn flush_address_space(pml4_phys: u64) {
+    if is_active(pml4_phys) {
+        mmu::flush_tlb_all();
+    }
+}
+
+/// Map the page at `virt_addr` in `src_pml4` into `dst_pml4` at the same
+/// address, sharing the frame. Writable pages become read-only + `FLAG_COW`
+/// in both spaces so the first write from either side takes a private copy;
+/// read-only pages are simply shared.
+pub fn share_cow(src_pml4: u64, dst_pml4: u64, virt_addr: u64) -> Result<(), MapError> {
+    let pte = leaf_entry(src_pml4, virt_addr).ok_or(MapError::NotMapped)?;
+    if *pte & FLAG_PRESENT == 0 {
+        return Err(MapError::NotMapped);
+    }
+    let frame_phys = *pte & ENTRY_ADDR_MASK;
+    let mut flags = *pte & !ENTRY_ADDR_MASK;
+    if flags & (FLAG_WRITABLE | FLAG_COW) != 0 {
+        flags = (flags & !FLAG_WRITABLE) | FLAG_COW;
+    }
+
+    if !phys::share_frame(phys::Frame::containing(frame_phys)) {
+        return Err(MapError::OutOfMemory);
+    }
+    if let Err(err) = map_page(dst_pml4, virt_addr, frame_phys, flags) {
+        phys::release_frame(phys::Frame::containing(frame_phys));
+        return Err(err);
+    }
+    *pte = frame_phys | flags;
+    flush_if_active(src_pml4, virt_addr);
+    Ok(())
+}
+
+/// Physical address of the shared all-zero frame, or 0 until first use.
+static ZERO_FRAME: AtomicU64 = AtomicU64::new(0);
+
+/// The frame every demand-zero page maps until it is first written. It is
+/// allocated and cleared on first use and holds a reference of its own, so
+/// it is never freed and a write fault always copies it.
+pub fn zero_frame() -> Option<phys::Frame> {
+    let current = ZERO_FRAME.load(Ordering::Acquire);
+    if current != 0 {
+        return Some(phys::Frame::containing(current));
+    }
+    let frame = phys::allocate_frame()?;
+    mmu::zero_page(frame.start());
+    match ZERO_FRAME.compare_exchange(0, frame.start(), Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => Some(frame),
+        Err(winner) => {
+            phys::free_frame(frame);
+            Some(phys::Frame::containing(winner))
+        }
+    }
+}
+
+pub fn is_zero_frame(phys_addr: u64) -> bool {
+    let zero = ZERO_FRAME.load(Ordering::Acquire);
+    zero != 0 && phys_addr & ENTRY_ADDR_MASK == zero
+}
+
+/// Map `virt_addr` onto the shared zero frame. A writable request becomes
+/// read-only + `FLAG_COW`, so nothing is allocated until the first write
+/// takes a private copy in `resolve_cow_fault`.
+pub fn map_zero_page(pml4_phys: u64, virt_addr: u64, flags: u64) -> Result<(), MapError> {
+    let frame = zero_frame().ok_or(MapError::OutOfMemory)?;
+    if !phys::share_frame(frame) {
+        return Err(MapError::OutOfMemory);
+    }
+    let flags = if flags & FLAG_WRITABLE != 0 {
+        (flags & !FLAG_WRITABLE) | FLAG_COW
+    } else {
+        flags
+    };
+    if let Err(err) = map_page(pml4_phys, virt_addr, frame.start(), flags) {
+        phys::release_frame(frame);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Resolve a write fault on a copy-on-write page. Returns `false` when the
+/// page at `fault_addr` is not COW, leaving the fault to the caller. The
+/// last owner of a frame gets write access back without copying.
+pub fn resolve_cow_fault(pml4_phys: u64, fault_addr: u64) -> bool {
+    let virt_addr = fault_addr & !0xFFF;
+    let pte = match leaf_entry(pml4_phys, virt_addr) {
+        Some(pte) if *pte & FLAG_PRESENT != 0 && *pte & FLAG_COW != 0 => pte,
+        _ => return false,
+    };
+    let old = phys::Frame::containing(*pte & ENTRY_ADDR_MASK);
+    let flags = (*pte & !ENTRY_ADDR_MASK & !FLAG_COW) | FLAG_WRITABLE;
+
+    if phys::frame_refcount(old) <= 1 {
+        *pte = old.start() | flags;
+    } else {
+        let copy = match phys::allocate_frame() {
+            Some(frame) => frame,
+            None => return false,
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                mmu::phys_to_virt(old.start()) as *const u8,
+                mmu::phys_to_virt(copy.start()) as *mut u8,
+                PAGE_SIZE,
+            );
+        }
+        *pte = copy.start() | flags;
+        phys::release_frame(old);
+    }
+    klog!(
+        "[paging] cow resolved virt=0x{:016X} pte=0x{:016X}\n",
+        virt_addr,
+        *pte
+    );
+    flush_if_active(pml4_phys, virt_addr);
+    true
+}
+
+const PERMISSION_FLAGS: u64 = FLAG_WRITABLE | FLAG_USER | FLAG_NO_EXECUTE;
+
+/// A run of virtually contiguous pages sharing the same effective
+/// permissions. `flags` holds only `FLAG_WRITABLE`, `FLAG_USER` and
+/// `FLAG_NO_EXECUTE`; `phys` is the frame behind `start`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MappedRange {
+    pub start: u64,
+    pub end: u64,
+    pub phys: u64,
+    pub flags: u64,
+}
+
+impl MappedRange {
+    pub fn pages(&self) -> u64 {
+        (self.end - self.start) / PAGE_SIZE as u64
+    }
+}
+
+/// Combine a parent's effective permissions with one more level: writable
+/// and user access need every level to allow them, NX at any level wins.
+fn combine_flags(parent: u64, entry: u64) -> u64 {
+    let allow = parent & entry & (FLAG_WRITABLE | FLAG_USER);
+    let deny = (parent | entry) & FLAG_NO_EXECUTE;
+    allow | deny
+}
+
+fn canonical(addr: u64) -> u64 {
+    if addr & (1 << 47) != 0 {
+        addr | 0xFFFF_0000_0000_0000
+    } else {
+        addr
+    }
+}
+
+/// Effective permission bits for `virt_addr` after combining every level of
+/// the walk, or `None` if it is not mapped.
+pub fn region_flags(pml4_phys: u64, virt_addr: u64) -> Option<u64> {
+    let mut flags = PERMISSION_FLAGS & !FLAG_NO_EXECUTE;
+    let mut table = table_from_phys(pml4_phys);
+    let indices = [
+        pml4_index(virt_addr),
+        pdpt_index(virt_addr),
+        pd_index(virt_addr),
+        pt_index(virt_addr),
+    ];
+    for (level, index) in indices.iter().enumerate() {
+        let entry = table[*index];
+        if entry & FLAG_PRESENT == 0 {
+            return None;
+        }
+        flags = combine_flags(flags, entry);
+        if level == 3 || (level > 0 && entry & FLAG_HUGE != 0) {
+            return Some(flags);
+        }
+        table = table_from_phys(entry & ENTRY_ADDR_MASK);
+    }
+    None
+}
+
+/// Call `f` for every present mapping in `pml4_phys`, in address order,
+/// merging neighbouring pages whose permissions match.
+pub fn for_each_mapping<F>(pml4_phys: u64, mut f: F)
+where
+    F: FnMut(MappedRange),
+{
+    let mut pending: Option<MappedRange> = None;
+    let mut emit = |start: u64, size: u64, phys: u64, flags: u64| {
+        if let Some(range) = pending.as_mut() {
+            if range.end == start && range.flags == flags {
+                range.end += size;
+                return;
+            }
+            f(*range);
+        }
+        pending = Some(MappedRange {
+            start,
+            end: start + size,
+            phys,
+            flags,
+        });
+    };
+
+    let root = PERMISSION_FLAGS & !FLAG_NO_EXECUTE;
+    let pml4 = table_from_phys(pml4_phys);
+    for (i4, pml4e) in pml4.iter().enumerate() {
+        if pml4e & FLAG_PRESENT == 0 {
+            continue;
+        }
+        let flags4 = combine_flags(root, *pml4e);
+        let pdpt = table_from_phys(pml4e & ENTRY_ADDR_MASK);
+        for (i3, pdpte) in pdpt.iter().enumerate() {
+            if pdpte & FLAG_PRESENT == 0 {
+                continue;
+            }
+            let virt3 = canonical(((i4 as u64) << 39) | ((i3 as u64) << 30));
+            let flags3 = combine_flags(flags4, *pdpte);
+            if pdpte & FLAG_HUGE != 0 {
+                emit(virt3, 1 << 30, pdpte & ENTRY_ADDR_MASK, flags3);
+                continue;
+            }
+            let pd = table_from_phys(pdpte & ENTRY_ADDR_MASK);
+            for (i2, pde) in pd.iter().enumerate() {
+                if pde & FLAG_PRESENT == 0 {
+                    continue;
+                }
+                let virt2 = virt3 | ((i2 as u64) << 21);
+                let flags2 = combine_flags(flags3, *pde);
+                if pde & FLAG_HUGE != 0 {
+                    emit(virt2, HUGE_PAGE_SIZE as u64, pde & ENTRY_ADDR_MASK, flags2);
+                    continue;
+                }
+                let pt = table_from_phys(pde & ENTRY_ADDR_MASK);
+                for (i1, pte) in pt.iter().enumerate() {
+                    if pte & FLAG_PRESENT == 0 {
+                        continue;
+                    }
+                    let virt1 = virt2 | ((i1 as u64) << 12);
+                    emit(virt1, PAGE_SIZE as u64, pte & ENTRY_ADDR_MASK, combine_flags(flags2, *pte));
+                }
+            }
+        }
+    }
+
+    if let Some(range) = pending {
+        f(range);
+    }
+}
+
+/// Log every mapped range of `pml4_phys` with its permissions.
+pub fn dump_mappings(pml4_phys: u64) {
+    klog!("[paging] mappings pml4=0x{:016X}\n", pml4_phys);
+    for_each_mapping(pml4_phys, |range| {
+        klog!(
+            "           0x{:016X}-0x{:016X} phys=0x{:016X} R{}{}{}\n",
+            range.start,
+            range.end,
+            range.phys,
+            if range.flags & FLAG_WRITABLE != 0 { "W" } else { "-" },
+            if range.flags & FLAG_USER != 0 { "U" } else { "-" },
+            if range.flags & FLAG_NO_EXECUTE != 0 { "-" } else { "X" }
+        );
+    });
+}
@@ -1,24 +1,38 @@
-use crate::arch::x86_64::io::outb;
-
-const PIT_CLOCK_OSC: u32 = 1_193_182;
-
-pub(crate) fn init_frequency(hz: u32) {
-    assert!(hz > 0, "PIT frequency must be greater than zero");
-
-    let mut divisor = PIT_CLOCK_OSC / hz;
-    if divisor == 0 {
-        divisor = 1;
-    }
-    if divisor > u16::MAX as u32 {
-        divisor = u16::MAX as u32;
-    }
+use crate::arch::x86_64::io::{inb, outb};
+use crate::interrupts::pit::{divisor_for, MAX_DIVISOR, PIT_CLOCK_HZ};
 
+/// Program channel 0 as a square wave at the rate closest to `hz` and
+/// return the divisor written.
+pub(crate) fn init_frequency(hz: u32) -> u16 {
+    let divisor = divisor_for(hz);
     let low = (divisor & 0xFF) as u8;
-    let high = ((divisor >> 8) & 0xFF) as u8;
+    let high = (divisor >> 8) as u8;
 
     unsafe {
         outb(0x43, 0x36);
         outb(0x40, low);
         outb(0x40, high);
     }
+    divisor
+}
+
+/// Spin for about `ms` milliseconds (at most ~54) by running channel 2 as a
+/// one-shot and polling its output bit on port 0x61. Needs no interrupts,
+/// so other clocks can be calibrated against it before the tick is running.
+pub(crate) fn wait_polled(ms: u32) {
+    let count = (PIT_CLOCK_HZ as u64 * ms as u64 / 1000).clamp(1, MAX_DIVISOR as u64) as u16;
+    unsafe {
+        // Gate channel 2 on with the speaker output disconnected.
+        let gate = (inb(0x61) & !0x02) | 0x01;
+        outb(0x61, gate & !0x01);
+        outb(0x43, 0xB0); // channel 2, lobyte/hibyte, mode 0
+        outb(0x42, (count & 0xFF) as u8);
+        outb(0x42, (count >> 8) as u8);
+        // Counting starts on the gate's rising edge.
+        outb(0x61, gate);
+        while inb(0x61) & 0x20 == 0 {
+            core::hint::spin_loop();
+        }
+        outb(0x61, gate & !0x01);
+    }
 }
@@ -4,12 +4,17 @@ extern crate alloc;
 mod entry;
 
 use alloc::vec;
+use alloc::vec::Vec;
 use crate::drivers::DriverError;
 use crate::klog;
+use crate::mem::{heap, phys};
+use crate::power;
 use crate::process;
-use crate::process::{FileIoError, ProcessError, SeekFrom};
-use crate::vfs::VfsError;
+use crate::process::{FileIoError, OpenOptions, ProcessError, SeekFrom};
+use crate::timer;
+use crate::vfs::{VfsError, VfsFileStat};
 use core::str;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use super::msr;
 
 pub mod nr {
@@ -17,9 +22,292 @@ pub mod nr {
     pub const WRITE: u64 = 1;
     pub const OPEN: u64 = 2;
     pub const CLOSE: u64 = 3;
+    pub const STAT: u64 = 4;  // matches Linux stat
+    pub const FSTAT: u64 = 5; // matches Linux fstat
+    pub const POLL: u64 = 7;  // matches Linux poll
     pub const SEEK: u64 = 8;
+    pub const IOCTL: u64 = 16; // matches Linux ioctl
+    pub const WRITEV: u64 = 20; // matches Linux writev
     pub const YIELD: u64 = 24; // matches Linux sched_yield
+    pub const SPAWN: u64 = 59; // execve's slot, but creates a child instead of replacing
     pub const EXIT: u64 = 60;  // matches Linux exit
+    pub const WAITPID: u64 = 61; // Linux wait4 without options/rusage
+    pub const KILL: u64 = 62;    // Linux kill without the signal number
+    pub const GETDENTS: u64 = 78; // matches Linux getdents
+    pub const SYSINFO: u64 = 99; // Linux sysinfo slot, ares layout (see `SysInfo`)
+    pub const SETPGID: u64 = 109; // matches Linux setpgid
+    pub const GETPGID: u64 = 121; // matches Linux getpgid
+    pub const REBOOT: u64 = 169; // Linux reboot slot, no magic or command arguments
+    pub const GETPROCS: u64 = 500; // ares only, past the end of Linux's table
+    pub const POWEROFF: u64 = 501; // ares only
+    pub const SYSTRACE: u64 = 502; // ares only
+
+    /// OR'd into a syscall number to request `-errno` returns instead of the
+    /// legacy `u64::MAX - n` sentinels. Mirrors Linux's x32 marker bit.
+    pub const NEG_ERRNO_FLAG: u64 = 0x4000_0000;
+
+    /// Flags accepted by `open` in `rdx`. Values match Linux.
+    pub mod open_flags {
+        /// Mask for the access mode in the low two bits (`O_ACCMODE`).
+        /// Only the permission check at open time reads it; descriptors
+        /// are not yet limited to the mode they were opened with.
+        pub const ACCESS_MODE: u64 = 0o3;
+        pub const READ_ONLY: u64 = 0o0;
+        pub const WRITE_ONLY: u64 = 0o1;
+        pub const READ_WRITE: u64 = 0o2;
+        /// Create the file if it does not exist (`O_CREAT`).
+        pub const CREATE: u64 = 0o100;
+        /// Every write goes to the end of the file (`O_APPEND`).
+        pub const APPEND: u64 = 0o2000;
+    }
+
+    /// Bits for `PollFd::events`/`revents`. Values match Linux.
+    pub mod poll_events {
+        /// Data can be read without blocking (`POLLIN`).
+        pub const IN: u16 = 0x001;
+        /// Data can be written without blocking (`POLLOUT`).
+        pub const OUT: u16 = 0x004;
+        /// The fd is not open (`POLLNVAL`). Reported whether asked for or not.
+        pub const NVAL: u16 = 0x020;
+    }
+
+    /// Error numbers reported as `-errno` when `NEG_ERRNO_FLAG` is set.
+    /// Values match Linux so C code can reuse its `<errno.h>`.
+    pub mod errno {
+        pub const EPERM: i64 = 1;
+        pub const ENOENT: i64 = 2;
+        pub const ESRCH: i64 = 3;
+        pub const EIO: i64 = 5;
+        pub const EBADF: i64 = 9;
+        pub const ECHILD: i64 = 10;
+        pub const ENOMEM: i64 = 12;
+        pub const EACCES: i64 = 13;
+        pub const EFAULT: i64 = 14;
+        pub const EBUSY: i64 = 16;
+        pub const EINVAL: i64 = 22;
+        pub const EMFILE: i64 = 24;
+        pub const ENOSPC: i64 = 28;
+        pub const ENOSYS: i64 = 38;
+        pub const ELOOP: i64 = 40;
+        /// Largest errno the kernel will ever report; anything in
+        /// `-MAX_ERRNO..0` is an error under the negative convention.
+        pub const MAX_ERRNO: i64 = 4095;
+    }
+}
+
+/// Layout of the records `getdents` packs into the caller's buffer:
+/// `{ size: u16, name_len: u8, attr: u8, name: [u8; name_len] }`, little
+/// endian, no padding. `size` covers the whole record.
+pub mod dirent {
+    pub const HEADER_LEN: usize = 4;
+}
+
+/// What `fstat`/`stat` copy into the caller's buffer.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Stat {
+    pub size: u64,
+    pub mode: u32,
+    pub block_size: u32,
+}
+
+impl From<VfsFileStat> for Stat {
+    fn from(stat: VfsFileStat) -> Self {
+        Self {
+            size: stat.size,
+            mode: stat.mode,
+            block_size: stat.block_size,
+        }
+    }
+}
+
+impl Stat {
+    fn to_bytes(self) -> [u8; core::mem::size_of::<Stat>()] {
+        let mut out = [0u8; core::mem::size_of::<Stat>()];
+        out[0..8].copy_from_slice(&self.size.to_le_bytes());
+        out[8..12].copy_from_slice(&self.mode.to_le_bytes());
+        out[12..16].copy_from_slice(&self.block_size.to_le_bytes());
+        out
+    }
+}
+
+/// What `sysinfo` copies into the caller's buffer: process counts by state
+/// from `process::scheduler_stats`, plus free heap and usable physical
+/// memory in bytes. All fields little endian, no padding.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct SysInfo {
+    pub procs_total: u32,
+    pub procs_ready: u32,
+    pub procs_running: u32,
+    pub procs_blocked: u32,
+    pub procs_zombie: u32,
+    pub _reserved: u32,
+    pub cpu_slices: u64,
+    pub heap_free: u64,
+    pub phys_total: u64,
+}
+
+impl SysInfo {
+    fn collect() -> Self {
+        let stats = process::scheduler_stats();
+        Self {
+            procs_total: stats.total as u32,
+            procs_ready: stats.ready as u32,
+            procs_running: stats.running as u32,
+            procs_blocked: stats.blocked as u32,
+            procs_zombie: stats.zombie as u32,
+            _reserved: 0,
+            cpu_slices: stats.total_slices,
+            heap_free: heap::remaining_bytes() as u64,
+            phys_total: phys::summary().total_bytes,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; core::mem::size_of::<SysInfo>()] {
+        let mut out = [0u8; core::mem::size_of::<SysInfo>()];
+        out[0..4].copy_from_slice(&self.procs_total.to_le_bytes());
+        out[4..8].copy_from_slice(&self.procs_ready.to_le_bytes());
+        out[8..12].copy_from_slice(&self.procs_running.to_le_bytes());
+        out[12..16].copy_from_slice(&self.procs_blocked.to_le_bytes());
+        out[16..20].copy_from_slice(&self.procs_zombie.to_le_bytes());
+        out[24..32].copy_from_slice(&self.cpu_slices.to_le_bytes());
+        out[32..40].copy_from_slice(&self.heap_free.to_le_bytes());
+        out[40..48].copy_from_slice(&self.phys_total.to_le_bytes());
+        out
+    }
+}
+
+/// Values of `ProcInfo::state`.
+pub mod proc_state {
+    pub const READY: u32 = 0;
+    pub const RUNNING: u32 = 1;
+    pub const BLOCKED: u32 = 2;
+    pub const ZOMBIE: u32 = 3;
+}
+
+pub const PROC_NAME_LEN: usize = 16;
+
+/// One record of the array `getprocs` writes. `parent` is 0 for a process
+/// without one; `name` is truncated and NUL padded. Little endian, no
+/// padding.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct ProcInfo {
+    pub pid: u32,
+    pub parent: u32,
+    pub state: u32,
+    pub _reserved: u32,
+    pub cpu_slices: u64,
+    pub name: [u8; PROC_NAME_LEN],
+}
+
+impl ProcInfo {
+    pub const SIZE: usize = core::mem::size_of::<ProcInfo>();
+
+    fn from_snapshot(snapshot: &process::ProcessSnapshot) -> Self {
+        let mut name = [0u8; PROC_NAME_LEN];
+        let bytes = snapshot.name().as_bytes();
+        let len = bytes.len().min(PROC_NAME_LEN);
+        name[..len].copy_from_slice(&bytes[..len]);
+        Self {
+            pid: snapshot.pid(),
+            parent: snapshot.parent().unwrap_or(0),
+            state: match snapshot.state() {
+                process::ProcessState::Ready => proc_state::READY,
+                process::ProcessState::Running => proc_state::RUNNING,
+                process::ProcessState::Blocked => proc_state::BLOCKED,
+                process::ProcessState::Zombie => proc_state::ZOMBIE,
+            },
+            _reserved: 0,
+            cpu_slices: snapshot.cpu_slices(),
+            name,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; ProcInfo::SIZE] {
+        let mut out = [0u8; ProcInfo::SIZE];
+        out[0..4].copy_from_slice(&self.pid.to_le_bytes());
+        out[4..8].copy_from_slice(&self.parent.to_le_bytes());
+        out[8..12].copy_from_slice(&self.state.to_le_bytes());
+        out[16..24].copy_from_slice(&self.cpu_slices.to_le_bytes());
+        out[24..40].copy_from_slice(&self.name);
+        out
+    }
+
+    /// The name up to its first NUL.
+    pub fn name(&self) -> &[u8] {
+        let len = self.name.iter().position(|b| *b == 0).unwrap_or(PROC_NAME_LEN);
+        &self.name[..len]
+    }
+}
+
+/// Most fds a single `poll` call will look at.
+pub const MAX_POLL_FDS: usize = 64;
+
+/// One entry of the array `poll` reads and updates, laid out like Linux's
+/// `struct pollfd`. A negative `fd` is skipped and gets `revents` 0.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: u16,
+    pub revents: u16,
+}
+
+impl PollFd {
+    pub const SIZE: usize = core::mem::size_of::<PollFd>();
+
+    pub const fn new(fd: i32, events: u16) -> Self {
+        Self { fd, events, revents: 0 }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            fd: i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            events: u16::from_le_bytes([bytes[4], bytes[5]]),
+            revents: 0,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; PollFd::SIZE] {
+        let mut out = [0u8; PollFd::SIZE];
+        out[0..4].copy_from_slice(&self.fd.to_le_bytes());
+        out[4..6].copy_from_slice(&self.events.to_le_bytes());
+        out[6..8].copy_from_slice(&self.revents.to_le_bytes());
+        out
+    }
+}
+
+/// Most fragments a single `writev` takes.
+pub const MAX_IOVECS: usize = 64;
+
+/// One fragment of a `writev`, laid out like Linux's `struct iovec`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct IoVec {
+    pub base: u64,
+    pub len: u64,
+}
+
+impl IoVec {
+    pub const SIZE: usize = core::mem::size_of::<IoVec>();
+
+    pub fn new(bytes: &[u8]) -> Self {
+        Self {
+            base: bytes.as_ptr() as u64,
+            len: bytes.len() as u64,
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let word = |at: usize| {
+            let mut raw = [0u8; 8];
+            raw.copy_from_slice(&bytes[at..at + 8]);
+            u64::from_le_bytes(raw)
+        };
+        Self { base: word(0), len: word(8) }
+    }
 }
 
 pub mod fd {
@@ -53,6 +341,14 @@ const ERR_INVAL: u64 = u64::MAX - 3;
 const ERR_NOENT: u64 = u64::MAX - 4;
 const ERR_NOMEM: u64 = u64::MAX - 5;
 const ERR_IO: u64 = u64::MAX - 6;
+const ERR_CHILD: u64 = u64::MAX - 7;
+const ERR_ACCES: u64 = u64::MAX - 8;
+const ERR_LOOP: u64 = u64::MAX - 9;
+const ERR_NOSPC: u64 = u64::MAX - 10;
+const ERR_SRCH: u64 = u64::MAX - 11;
+const ERR_PERM: u64 = u64::MAX - 12;
+const ERR_BUSY: u64 = u64::MAX - 13;
+const ERR_MFILE: u64 = u64::MAX - 14;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum SysError {
@@ -63,14 +359,103 @@ pub enum SysError {
     NoEntry,
     NoMemory,
     Io,
+    NoChild,
+    PermissionDenied,
+    Loop,
+    NoSpace,
+    NoProcess,
+    NotPermitted,
+    Busy,
+    TooManyFiles,
+}
+
+impl SysError {
+    pub fn errno(self) -> i64 {
+        match self {
+            SysError::BadFileDescriptor => nr::errno::EBADF,
+            SysError::Fault => nr::errno::EFAULT,
+            SysError::NoSys => nr::errno::ENOSYS,
+            SysError::InvalidArgument => nr::errno::EINVAL,
+            SysError::NoEntry => nr::errno::ENOENT,
+            SysError::NoMemory => nr::errno::ENOMEM,
+            SysError::Io => nr::errno::EIO,
+            SysError::NoChild => nr::errno::ECHILD,
+            SysError::PermissionDenied => nr::errno::EACCES,
+            SysError::Loop => nr::errno::ELOOP,
+            SysError::NoSpace => nr::errno::ENOSPC,
+            SysError::NoProcess => nr::errno::ESRCH,
+            SysError::NotPermitted => nr::errno::EPERM,
+            SysError::Busy => nr::errno::EBUSY,
+            SysError::TooManyFiles => nr::errno::EMFILE,
+        }
+    }
+
+    fn from_errno(errno: i64) -> Option<Self> {
+        match errno {
+            nr::errno::EBADF => Some(SysError::BadFileDescriptor),
+            nr::errno::EFAULT => Some(SysError::Fault),
+            nr::errno::ENOSYS => Some(SysError::NoSys),
+            nr::errno::EINVAL => Some(SysError::InvalidArgument),
+            nr::errno::ENOENT => Some(SysError::NoEntry),
+            nr::errno::ENOMEM => Some(SysError::NoMemory),
+            nr::errno::EIO => Some(SysError::Io),
+            nr::errno::ECHILD => Some(SysError::NoChild),
+            nr::errno::EACCES => Some(SysError::PermissionDenied),
+            nr::errno::ELOOP => Some(SysError::Loop),
+            nr::errno::ENOSPC => Some(SysError::NoSpace),
+            nr::errno::ESRCH => Some(SysError::NoProcess),
+            nr::errno::EPERM => Some(SysError::NotPermitted),
+            nr::errno::EBUSY => Some(SysError::Busy),
+            nr::errno::EMFILE => Some(SysError::TooManyFiles),
+            _ => None,
+        }
+    }
 }
 
 pub type SysResult<T> = Result<T, SysError>;
 
+/// How a syscall reports failure in `rax`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ErrorConvention {
+    /// Legacy `u64::MAX - n` sentinels (`ERR_BADF`, `ERR_FAULT`, ...).
+    Sentinel,
+    /// Linux-style `-errno`; selected with `nr::NEG_ERRNO_FLAG`.
+    NegativeErrno,
+}
+
+impl ErrorConvention {
+    fn from_number(number: u64) -> Self {
+        if number & nr::NEG_ERRNO_FLAG != 0 {
+            ErrorConvention::NegativeErrno
+        } else {
+            ErrorConvention::Sentinel
+        }
+    }
+
+    fn translate(self, value: u64) -> u64 {
+        match self {
+            ErrorConvention::Sentinel => value,
+            ErrorConvention::NegativeErrno => match decode_ret(value, ErrorConvention::Sentinel) {
+                Ok(value) => value,
+                Err(err) => encode_error_neg(err),
+            },
+        }
+    }
+}
+
 extern "C" {
     fn syscall_entry();
 }
 
+/// The user rsp between `syscall` and `syscall_entry` moving it onto the
+/// kernel stack.
+#[no_mangle]
+static mut SYSCALL_USER_RSP: u64 = 0;
+
+/// What `syscall_entry` saves, lowest address first. It sits on the
+/// calling process's kernel stack, so a handler that sleeps leaves it where
+/// it is until the process runs again. Everything except `rax` is restored
+/// on the way out, `rcx` and `r11` as `sysretq` needs them.
 #[repr(C)]
 pub struct SyscallFrame {
     pub r9: u64,
@@ -80,8 +465,12 @@ pub struct SyscallFrame {
     pub rsi: u64,
     pub rdi: u64,
     pub rax: u64,
+    /// User `rip`, from `rcx`.
     pub rip: u64,
+    /// User `rflags`, from `r11`.
     pub rflags: u64,
+    pub rbp: u64,
+    pub rsp: u64,
 }
 
 impl SyscallFrame {
@@ -96,6 +485,8 @@ impl SyscallFrame {
             rax: 0,
             rip: 0,
             rflags: 0,
+            rbp: 0,
+            rsp: 0,
         }
     }
 }
@@ -106,20 +497,95 @@ pub extern "C" fn syscall_trampoline(frame: *mut SyscallFrame) -> u64 {
     dispatch(frame)
 }
 
+/// Syscall numbers below this get an invocation counter. The largest in use
+/// is `nr::SYSTRACE`.
+pub const COUNTED_SYSCALLS: usize = 512;
+
+static TRACE: AtomicBool = AtomicBool::new(false);
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_CALLS: AtomicU64 = AtomicU64::new(0);
+static CALL_COUNTS: [AtomicU64; COUNTED_SYSCALLS] = [NO_CALLS; COUNTED_SYSCALLS];
+
+/// Log every syscall's number, arguments and return value. Returns the
+/// previous setting.
+pub fn set_trace(enabled: bool) -> bool {
+    TRACE.swap(enabled, Ordering::AcqRel)
+}
+
+pub fn trace_enabled() -> bool {
+    TRACE.load(Ordering::Acquire)
+}
+
+/// How many times syscall `number` has been dispatched since boot, whether
+/// or not it succeeded. Unknown numbers are counted too; numbers past
+/// `COUNTED_SYSCALLS` read as 0.
+pub fn call_count(number: u64) -> u64 {
+    CALL_COUNTS
+        .get(number as usize)
+        .map_or(0, |count| count.load(Ordering::Relaxed))
+}
+
 fn dispatch(frame: &mut SyscallFrame) -> u64 {
-    match frame.rax {
+    let convention = ErrorConvention::from_number(frame.rax);
+    let number = frame.rax & !nr::NEG_ERRNO_FLAG;
+    if let Some(count) = CALL_COUNTS.get(number as usize) {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+    // Captured up front: the handler may overwrite the frame.
+    let args = [frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8, frame.r9];
+    let ret = match number {
         nr::READ => sys_read(frame.rdi, frame.rsi, frame.rdx),
         nr::WRITE => sys_write(frame.rdi, frame.rsi, frame.rdx),
+        nr::WRITEV => sys_writev(frame.rdi, frame.rsi, frame.rdx),
         nr::OPEN => sys_open(frame.rdi, frame.rsi, frame.rdx),
         nr::CLOSE => sys_close(frame.rdi),
+        nr::STAT => sys_stat(frame.rdi, frame.rsi, frame.rdx),
+        nr::FSTAT => sys_fstat(frame.rdi, frame.rsi),
         nr::SEEK => sys_seek(frame.rdi, frame.rsi, frame.rdx),
         nr::YIELD => sys_yield(),
+        nr::SPAWN => sys_spawn(frame.rdi, frame.rsi, frame.rdx),
         nr::EXIT => sys_exit(frame.rdi),
+        nr::WAITPID => sys_waitpid(frame.rdi, frame.rsi),
+        nr::KILL => sys_kill(frame.rdi),
+        nr::SETPGID => sys_setpgid(frame.rdi, frame.rsi),
+        nr::GETPGID => sys_getpgid(frame.rdi),
+        nr::GETDENTS => sys_getdents(frame.rdi, frame.rsi, frame.rdx),
+        nr::IOCTL => sys_ioctl(frame.rdi, frame.rsi, frame.rdx),
+        nr::SYSINFO => sys_sysinfo(frame.rdi),
+        nr::GETPROCS => sys_getprocs(frame.rdi, frame.rsi),
+        nr::POLL => sys_poll(frame.rdi, frame.rsi, frame.rdx),
+        nr::REBOOT => sys_reboot(),
+        nr::POWEROFF => sys_poweroff(),
+        nr::SYSTRACE => sys_systrace(frame.rdi),
         _ => ERR_NOSYS,
+    };
+    let ret = convention.translate(ret);
+    if trace_enabled() {
+        klog!(
+            "[systrace] pid={:?} nr={} args=[0x{:X}, 0x{:X}, 0x{:X}, 0x{:X}, 0x{:X}, 0x{:X}] -> 0x{:X}\n",
+            process::current_pid(),
+            number,
+            args[0],
+            args[1],
+            args[2],
+            args[3],
+            args[4],
+            args[5],
+            ret
+        );
     }
+    ret
 }
 
-fn decode_ret(value: u64) -> SysResult<u64> {
+fn decode_ret(value: u64, convention: ErrorConvention) -> SysResult<u64> {
+    if convention == ErrorConvention::NegativeErrno {
+        let signed = value as i64;
+        if (-nr::errno::MAX_ERRNO..0).contains(&signed) {
+            return Err(SysError::from_errno(-signed).unwrap_or(SysError::Io));
+        }
+        return Ok(value);
+    }
+
     match value {
         ERR_BADF => Err(SysError::BadFileDescriptor),
         ERR_FAULT => Err(SysError::Fault),
@@ -128,6 +594,14 @@ fn decode_ret(value: u64) -> SysResult<u64> {
         ERR_NOENT => Err(SysError::NoEntry),
         ERR_NOMEM => Err(SysError::NoMemory),
         ERR_IO => Err(SysError::Io),
+        ERR_CHILD => Err(SysError::NoChild),
+        ERR_ACCES => Err(SysError::PermissionDenied),
+        ERR_LOOP => Err(SysError::Loop),
+        ERR_NOSPC => Err(SysError::NoSpace),
+        ERR_SRCH => Err(SysError::NoProcess),
+        ERR_PERM => Err(SysError::NotPermitted),
+        ERR_BUSY => Err(SysError::Busy),
+        ERR_MFILE => Err(SysError::TooManyFiles),
         other => Ok(other),
     }
 }
@@ -141,9 +615,21 @@ fn encode_error(err: SysError) -> u64 {
         SysError::NoEntry => ERR_NOENT,
         SysError::NoMemory => ERR_NOMEM,
         SysError::Io => ERR_IO,
+        SysError::NoChild => ERR_CHILD,
+        SysError::PermissionDenied => ERR_ACCES,
+        SysError::Loop => ERR_LOOP,
+        SysError::NoSpace => ERR_NOSPC,
+        SysError::NoProcess => ERR_SRCH,
+        SysError::NotPermitted => ERR_PERM,
+        SysError::Busy => ERR_BUSY,
+        SysError::TooManyFiles => ERR_MFILE,
     }
 }
 
+fn encode_error_neg(err: SysError) -> u64 {
+    (-err.errno()) as u64
+}
+
 fn decode_seek(offset: u64, whence: u64) -> SysResult<SeekFrom> {
     match whence {
         0 => {
@@ -159,52 +645,70 @@ fn decode_seek(offset: u64, whence: u64) -> SysResult<SeekFrom> {
     }
 }
 
+/// Where a `Current` or `End` seek lands from `origin`. `decode_seek` only
+/// sees the raw offset, so a delta that would take the target below zero or
+/// past `u64::MAX` is caught here, before the handle sees it.
+fn checked_seek_target(pos: SeekFrom, origin: u64) -> SysResult<u64> {
+    match pos {
+        SeekFrom::Start(target) => Ok(target),
+        SeekFrom::Current(delta) | SeekFrom::End(delta) => {
+            origin.checked_add_signed(delta).ok_or(SysError::InvalidArgument)
+        }
+    }
+}
+
 fn map_file_io_error(err: FileIoError) -> SysError {
     match err {
         FileIoError::Driver(DriverError::Unsupported) => SysError::InvalidArgument,
         FileIoError::Driver(DriverError::IoError) => SysError::Io,
         FileIoError::Driver(DriverError::RegistryFull) => SysError::NoMemory,
         FileIoError::Driver(DriverError::InitFailed) => SysError::Io,
+        FileIoError::Driver(DriverError::NotFound) => SysError::NoEntry,
+        FileIoError::Driver(DriverError::Busy) => SysError::Busy,
+        FileIoError::Driver(DriverError::NoSpace) => SysError::NoSpace,
+        FileIoError::Driver(DriverError::DuplicateName) => SysError::InvalidArgument,
         FileIoError::Vfs(VfsError::Unsupported) => SysError::InvalidArgument,
         FileIoError::Vfs(VfsError::InvalidOffset) => SysError::InvalidArgument,
         FileIoError::Vfs(VfsError::Io) => SysError::Io,
+        FileIoError::Vfs(VfsError::NotFound) => SysError::NoEntry,
+        FileIoError::Vfs(VfsError::PermissionDenied) => SysError::PermissionDenied,
+        FileIoError::Vfs(VfsError::NoSpace) => SysError::NoSpace,
+        FileIoError::Vfs(VfsError::TooManyLinks) => SysError::Loop,
+        FileIoError::Vfs(VfsError::InvalidPath) => SysError::InvalidArgument,
     }
 }
 
-fn sys_open(path_ptr: u64, path_len: u64, _flags: u64) -> u64 {
+/// Map a failed user-memory copy onto `rax`: bad or unmapped memory is
+/// `ERR_FAULT`, a string with no terminator `ERR_INVAL`.
+fn user_copy_error(err: ProcessError) -> u64 {
+    match err {
+        ProcessError::InvalidUserPointer | ProcessError::UserMemoryNotPresent => ERR_FAULT,
+        ProcessError::UnterminatedString => ERR_INVAL,
+        _ => ERR_FAULT,
+    }
+}
+
+/// Copy a `(ptr, len)` path out of the caller's address space, stopping at
+/// the first NUL. Errors come back already encoded for `rax`.
+fn copy_user_path(path_ptr: u64, path_len: u64) -> Result<Vec<u8>, u64> {
     if path_ptr == 0 || path_len == 0 {
-        return ERR_INVAL;
+        return Err(ERR_INVAL);
     }
 
     let address_space = match process::current_address_space() {
         Some(space) => space,
-        None => return ERR_BADF,
+        None => return Err(ERR_BADF),
     };
 
-    let buffer = match process::read_user_buffer(&address_space, path_ptr, path_len as usize) {
-        Ok(buf) => buf,
-        Err(err) => {
-            return match err {
-                ProcessError::InvalidUserPointer | ProcessError::UserMemoryNotPresent => ERR_FAULT,
-                _ => {
-                    klog!(
-                        "[syscall] open copy_from_user failed ptr=0x{:016X} len={} err {:?}\n",
-                        path_ptr,
-                        path_len,
-                        err
-                    );
-                    ERR_FAULT
-                }
-            };
-        }
-    };
+    process::read_user_str(&address_space, path_ptr, path_len as usize).map_err(user_copy_error)
+}
 
-    let trimmed_len = match buffer.iter().position(|&b| b == 0) {
-        Some(pos) => pos,
-        None => buffer.len(),
+fn sys_open(path_ptr: u64, path_len: u64, flags: u64) -> u64 {
+    let buffer = match copy_user_path(path_ptr, path_len) {
+        Ok(buf) => buf,
+        Err(code) => return code,
     };
-    let path_slice = &buffer[..trimmed_len];
-    let path_str = match str::from_utf8(path_slice) {
+    let path_str = match str::from_utf8(&buffer) {
         Ok(s) => s,
         Err(_) => return ERR_INVAL,
     };
@@ -214,11 +718,17 @@ fn sys_open(path_ptr: u64, path_len: u64, _flags: u64) -> u64 {
         None => return ERR_BADF,
     };
 
-    match process::open_path(current_pid, path_str) {
+    let options = OpenOptions {
+        write: flags & nr::open_flags::ACCESS_MODE != nr::open_flags::READ_ONLY,
+        create: flags & nr::open_flags::CREATE != 0,
+        append: flags & nr::open_flags::APPEND != 0,
+    };
+    match process::open_path_as(current_pid, path_str, options) {
         Ok(fd) => fd as u64,
-        Err(ProcessError::NoFreeFileDescriptors) => encode_error(SysError::NoMemory),
+        Err(ProcessError::NoFreeFileDescriptors) => encode_error(SysError::TooManyFiles),
         Err(ProcessError::PathNotFound) => encode_error(SysError::NoEntry),
         Err(ProcessError::InvalidFileDescriptor) => encode_error(SysError::BadFileDescriptor),
+        Err(ProcessError::Vfs(err)) => encode_error(map_file_io_error(FileIoError::Vfs(err))),
         Err(err) => {
             klog!("[syscall] open failed pid {} path {:?} err {:?}\n", current_pid, path_str, err);
             encode_error(SysError::BadFileDescriptor)
@@ -226,6 +736,239 @@ fn sys_open(path_ptr: u64, path_len: u64, _flags: u64) -> u64 {
     }
 }
 
+fn copy_stat_to_user(stat: VfsFileStat, stat_ptr: u64) -> u64 {
+    let address_space = match process::current_address_space() {
+        Some(space) => space,
+        None => return ERR_BADF,
+    };
+    let bytes = Stat::from(stat).to_bytes();
+    match process::copy_to_user(&address_space, stat_ptr, &bytes) {
+        Ok(()) => 0,
+        Err(err) => {
+            klog!("[syscall] stat copy_to_user failed ptr=0x{:016X} err {:?}\n", stat_ptr, err);
+            ERR_FAULT
+        }
+    }
+}
+
+fn sys_fstat(fd: u64, stat_ptr: u64) -> u64 {
+    if stat_ptr == 0 {
+        return ERR_FAULT;
+    }
+
+    let current_pid = match process::current_pid() {
+        Some(pid) => pid,
+        None => return ERR_BADF,
+    };
+
+    match process::with_fd_mut(current_pid, fd as usize, |descriptor| descriptor.stat()) {
+        Ok(Ok(stat)) => copy_stat_to_user(stat, stat_ptr),
+        Ok(Err(err)) => encode_error(map_file_io_error(err)),
+        Err(ProcessError::InvalidFileDescriptor) => encode_error(SysError::BadFileDescriptor),
+        Err(err) => {
+            klog!("[syscall] fstat failed pid {} fd {} err {:?}\n", current_pid, fd, err);
+            encode_error(SysError::BadFileDescriptor)
+        }
+    }
+}
+
+fn sys_sysinfo(info_ptr: u64) -> u64 {
+    if info_ptr == 0 {
+        return ERR_FAULT;
+    }
+    let address_space = match process::current_address_space() {
+        Some(space) => space,
+        None => return ERR_BADF,
+    };
+    let bytes = SysInfo::collect().to_bytes();
+    match process::copy_to_user(&address_space, info_ptr, &bytes) {
+        Ok(()) => 0,
+        Err(err) => {
+            klog!("[syscall] sysinfo copy_to_user failed ptr=0x{:016X} err {:?}\n", info_ptr, err);
+            ERR_FAULT
+        }
+    }
+}
+
+fn sys_getprocs(buf_ptr: u64, len: u64) -> u64 {
+    if buf_ptr == 0 {
+        return ERR_FAULT;
+    }
+    let address_space = match process::current_address_space() {
+        Some(space) => space,
+        None => return ERR_BADF,
+    };
+
+    let capacity = len as usize / ProcInfo::SIZE;
+    let mut records: Vec<u8> = Vec::new();
+    for snapshot in process::snapshot_all().iter().take(capacity) {
+        records.extend_from_slice(&ProcInfo::from_snapshot(snapshot).to_bytes());
+    }
+    match process::copy_to_user(&address_space, buf_ptr, &records) {
+        Ok(()) => (records.len() / ProcInfo::SIZE) as u64,
+        Err(err) => {
+            klog!("[syscall] getprocs copy_to_user failed ptr=0x{:016X} err {:?}\n", buf_ptr, err);
+            ERR_FAULT
+        }
+    }
+}
+
+/// Fill in `revents` for every entry and return how many are non-zero.
+fn poll_scan(pid: process::Pid, fds: &mut [PollFd]) -> usize {
+    let mut ready = 0;
+    for entry in fds.iter_mut() {
+        entry.revents = 0;
+        if entry.fd < 0 {
+            continue;
+        }
+        let events = entry.events;
+        entry.revents = match process::with_fd_mut(pid, entry.fd as usize, |descriptor| {
+            let mut revents = 0;
+            if events & nr::poll_events::IN != 0 && descriptor.can_read() {
+                revents |= nr::poll_events::IN;
+            }
+            if events & nr::poll_events::OUT != 0 && descriptor.can_write() {
+                revents |= nr::poll_events::OUT;
+            }
+            revents
+        }) {
+            Ok(revents) => revents,
+            Err(_) => nr::poll_events::NVAL,
+        };
+        if entry.revents != 0 {
+            ready += 1;
+        }
+    }
+    ready
+}
+
+fn sys_poll(fds_ptr: u64, nfds: u64, timeout_ms: u64) -> u64 {
+    if nfds > MAX_POLL_FDS as u64 {
+        return ERR_INVAL;
+    }
+    if fds_ptr == 0 && nfds != 0 {
+        return ERR_FAULT;
+    }
+    let address_space = match process::current_address_space() {
+        Some(space) => space,
+        None => return ERR_BADF,
+    };
+    let current_pid = match process::current_pid() {
+        Some(pid) => pid,
+        None => return ERR_BADF,
+    };
+
+    let bytes = match process::read_user_buffer(&address_space, fds_ptr, nfds as usize * PollFd::SIZE) {
+        Ok(bytes) => bytes,
+        Err(_) => return ERR_FAULT,
+    };
+    let mut fds: Vec<PollFd> = bytes.chunks_exact(PollFd::SIZE).map(PollFd::from_bytes).collect();
+
+    // A negative timeout waits forever; zero checks once and returns.
+    let deadline = match timeout_ms as i64 {
+        ms if ms < 0 => None,
+        ms => Some(timer::ticks().saturating_add(timer::ms_to_ticks(ms as u64))),
+    };
+    let expired = |deadline: Option<u64>| deadline.is_some_and(|tick| timer::ticks() >= tick);
+
+    let ready = loop {
+        let ready = poll_scan(current_pid, &mut fds);
+        if ready > 0 || expired(deadline) {
+            break ready;
+        }
+        if let Some(tick) = deadline {
+            process::arm_poll_deadline(tick);
+        }
+        // Readiness that changed between the scan and the block found no
+        // poller to wake; look again once we are marked blocked.
+        let mut probe = fds.clone();
+        let blocked = process::block_current_then(process::WaitChannel::Poll, || {
+            if poll_scan(current_pid, &mut probe) > 0 || expired(deadline) {
+                process::wake_channel(process::WaitChannel::Poll);
+            }
+        });
+        if blocked.is_err() {
+            return ERR_BADF;
+        }
+    };
+
+    let mut out = Vec::with_capacity(fds.len() * PollFd::SIZE);
+    for entry in fds.iter() {
+        out.extend_from_slice(&entry.to_bytes());
+    }
+    match process::copy_to_user(&address_space, fds_ptr, &out) {
+        Ok(()) => ready as u64,
+        Err(err) => {
+            klog!("[syscall] poll copy_to_user failed ptr=0x{:016X} err {:?}\n", fds_ptr, err);
+            ERR_FAULT
+        }
+    }
+}
+
+fn sys_ioctl(fd: u64, cmd: u64, arg: u64) -> u64 {
+    if cmd > u32::MAX as u64 {
+        return ERR_INVAL;
+    }
+    let cmd = cmd as u32;
+
+    let current_pid = match process::current_pid() {
+        Some(pid) => pid,
+        None => return ERR_BADF,
+    };
+
+    match process::with_fd_mut(current_pid, fd as usize, |descriptor| descriptor.ioctl(cmd, arg)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => encode_error(map_file_io_error(err)),
+        Err(ProcessError::InvalidFileDescriptor) => encode_error(SysError::BadFileDescriptor),
+        Err(err) => {
+            klog!("[syscall] ioctl failed pid {} fd {} err {:?}\n", current_pid, fd, err);
+            encode_error(SysError::BadFileDescriptor)
+        }
+    }
+}
+
+fn sys_stat(path_ptr: u64, path_len: u64, stat_ptr: u64) -> u64 {
+    if stat_ptr == 0 {
+        return ERR_FAULT;
+    }
+
+    let buffer = match copy_user_path(path_ptr, path_len) {
+        Ok(buf) => buf,
+        Err(code) => return code,
+    };
+    let path_str = match str::from_utf8(&buffer) {
+        Ok(s) => s,
+        Err(_) => return ERR_INVAL,
+    };
+
+    let current_pid = match process::current_pid() {
+        Some(pid) => pid,
+        None => return ERR_BADF,
+    };
+
+    let fd = match process::open_path(current_pid, path_str) {
+        Ok(fd) => fd,
+        Err(ProcessError::NoFreeFileDescriptors) => return encode_error(SysError::TooManyFiles),
+        Err(ProcessError::PathNotFound) => return encode_error(SysError::NoEntry),
+        Err(ProcessError::Vfs(err)) => return encode_error(map_file_io_error(FileIoError::Vfs(err))),
+        Err(err) => {
+            klog!("[syscall] stat open failed pid {} path {:?} err {:?}\n", current_pid, path_str, err);
+            return encode_error(SysError::NoEntry);
+        }
+    };
+
+    let result = process::with_fd_mut(current_pid, fd, |descriptor| descriptor.stat());
+    if let Err(err) = process::close_fd(current_pid, fd) {
+        klog!("[syscall] stat close failed pid {} fd {} err {:?}\n", current_pid, fd, err);
+    }
+
+    match result {
+        Ok(Ok(stat)) => copy_stat_to_user(stat, stat_ptr),
+        Ok(Err(err)) => encode_error(map_file_io_error(err)),
+        Err(_) => encode_error(SysError::BadFileDescriptor),
+    }
+}
+
 fn sys_close(fd: u64) -> u64 {
     let current_pid = match process::current_pid() {
         Some(pid) => pid,
@@ -253,9 +996,11 @@ fn sys_seek(fd: u64, offset: u64, whence: u64) -> u64 {
         Err(err) => return encode_error(err),
     };
 
-    match process::with_fd_mut(current_pid, fd as usize, |descriptor| descriptor.seek(seek_from)) {
-        Ok(Ok(new_offset)) => new_offset,
-        Ok(Err(err)) => {
+    let result = process::with_fd_mut(current_pid, fd as usize, |descriptor| {
+        if let Some(origin) = descriptor.seek_origin(seek_from).map_err(map_file_io_error)? {
+            checked_seek_target(seek_from, origin)?;
+        }
+        descriptor.seek(seek_from).map_err(|err| {
             let sys_err = map_file_io_error(err);
             klog!(
                 "[syscall] seek: device error {:?} (fd={} offset={} whence={})\n",
@@ -264,8 +1009,13 @@ fn sys_seek(fd: u64, offset: u64, whence: u64) -> u64 {
                 offset,
                 whence
             );
-            encode_error(sys_err)
-        }
+            sys_err
+        })
+    });
+
+    match result {
+        Ok(Ok(new_offset)) => new_offset,
+        Ok(Err(err)) => encode_error(err),
         Err(ProcessError::InvalidFileDescriptor) => encode_error(SysError::BadFileDescriptor),
         Err(err) => {
             klog!("[syscall] seek failed pid {} fd {} err {:?}\n", current_pid, fd, err);
@@ -274,6 +1024,12 @@ fn sys_seek(fd: u64, offset: u64, whence: u64) -> u64 {
     }
 }
 
+/// Most `read` stages in a kernel buffer at once. A longer read goes round
+/// again while each chunk comes back full and the fd is still ready, so an
+/// endless source like `/dev/zero` fills the whole user buffer without a
+/// kernel copy the size of the request, and nothing ever blocks twice.
+const READ_CHUNK: usize = 64 * 1024;
+
 fn sys_read(fd: u64, buf_ptr: u64, len: u64) -> u64 {
     if len == 0 {
         return 0;
@@ -293,7 +1049,7 @@ fn sys_read(fd: u64, buf_ptr: u64, len: u64) -> u64 {
         }
     };
 
-    let mut kernel_buffer = vec![0u8; len];
+    let mut kernel_buffer = vec![0u8; len.min(READ_CHUNK)];
 
     let current_pid = match process::current_pid() {
         Some(pid) => pid,
@@ -303,29 +1059,106 @@ fn sys_read(fd: u64, buf_ptr: u64, len: u64) -> u64 {
         }
     };
 
-    match process::with_fd_mut(current_pid, fd as usize, |descriptor| descriptor.read(&mut kernel_buffer)) {
-        Ok(Ok(count)) => {
-            let count = count as usize;
-            if let Err(err) = process::copy_to_user(&address_space, buf_ptr, &kernel_buffer[..count]) {
-                return match err {
-                    ProcessError::InvalidUserPointer | ProcessError::UserMemoryNotPresent => ERR_FAULT,
-                    _ => {
-                        klog!(
-                            "[syscall] read copy_to_user failed pid {} fd {} err {:?}\n",
-                            current_pid,
-                            fd,
-                            err
-                        );
-                        ERR_FAULT
-                    }
-                };
+    let mut total = 0;
+    while total < len {
+        let chunk = (len - total).min(kernel_buffer.len());
+        let buffer = &mut kernel_buffer[..chunk];
+        let result = process::with_fd_mut(current_pid, fd as usize, |descriptor| {
+            descriptor.read(buffer).map(|count| (count, descriptor.can_read()))
+        });
+        let (count, ready) = match result {
+            Ok(Ok(read)) => read,
+            // Bytes already handed over win over a later failure.
+            Ok(Err(_)) | Err(_) if total > 0 => break,
+            Ok(Err(err)) => return encode_error(map_file_io_error(err)),
+            Err(ProcessError::InvalidFileDescriptor) => return encode_error(SysError::BadFileDescriptor),
+            Err(err) => {
+                klog!("[syscall] read failed pid {} fd {} err {:?}\n", current_pid, fd, err);
+                return encode_error(SysError::BadFileDescriptor);
             }
-            count as u64
+        };
+
+        let dest = buf_ptr + total as u64;
+        if let Err(err) = process::copy_to_user(&address_space, dest, &kernel_buffer[..count]) {
+            return match err {
+                ProcessError::InvalidUserPointer | ProcessError::UserMemoryNotPresent => ERR_FAULT,
+                _ => {
+                    klog!(
+                        "[syscall] read copy_to_user failed pid {} fd {} err {:?}\n",
+                        current_pid,
+                        fd,
+                        err
+                    );
+                    ERR_FAULT
+                }
+            };
+        }
+        total += count;
+        if count < chunk || !ready {
+            break;
+        }
+    }
+    total as u64
+}
+
+fn sys_getdents(fd: u64, buf_ptr: u64, len: u64) -> u64 {
+    if buf_ptr == 0 {
+        return ERR_FAULT;
+    }
+
+    let len = len as usize;
+
+    let address_space = match process::current_address_space() {
+        Some(space) => space,
+        None => return ERR_BADF,
+    };
+
+    let current_pid = match process::current_pid() {
+        Some(pid) => pid,
+        None => return ERR_BADF,
+    };
+
+    let mut kernel_buffer = vec![0u8; len];
+    let mut used = 0usize;
+    let mut truncated = false;
+
+    let result = process::with_fd_mut(current_pid, fd as usize, |descriptor| {
+        descriptor.read_dir(|entry| {
+            let name = entry.name();
+            let record = dirent::HEADER_LEN + name.len();
+            if used + record > kernel_buffer.len() {
+                truncated = true;
+                return false;
+            }
+            let out = &mut kernel_buffer[used..used + record];
+            out[0..2].copy_from_slice(&(record as u16).to_le_bytes());
+            out[2] = name.len() as u8;
+            out[3] = entry.attr;
+            out[dirent::HEADER_LEN..].copy_from_slice(name);
+            used += record;
+            true
+        })
+    });
+
+    match result {
+        Ok(Ok(_)) => {
+            if used == 0 && truncated {
+                // The next record alone is larger than the caller's buffer.
+                return ERR_INVAL;
+            }
+            if used == 0 {
+                return 0;
+            }
+            if let Err(err) = process::copy_to_user(&address_space, buf_ptr, &kernel_buffer[..used]) {
+                klog!("[syscall] getdents copy_to_user failed pid {} fd {} err {:?}\n", current_pid, fd, err);
+                return ERR_FAULT;
+            }
+            used as u64
         }
         Ok(Err(err)) => encode_error(map_file_io_error(err)),
         Err(ProcessError::InvalidFileDescriptor) => encode_error(SysError::BadFileDescriptor),
         Err(err) => {
-            klog!("[syscall] read failed pid {} fd {} err {:?}\n", current_pid, fd, err);
+            klog!("[syscall] getdents failed pid {} fd {} err {:?}\n", current_pid, fd, err);
             encode_error(SysError::BadFileDescriptor)
         }
     }
@@ -395,11 +1228,242 @@ fn sys_write(fd: u64, buf_ptr: u64, len: u64) -> u64 {
     }
 }
 
+/// Gather every fragment into one kernel buffer and hand it to the fd in a
+/// single call, so a burst of small pieces reaches a char device as one
+/// write (atomic up to `ATOMIC_WRITE_MAX`) instead of one per fragment.
+/// Most `writev` gathers into one kernel buffer. A longer vector is a short
+/// write, as Linux does past `MAX_RW_COUNT`, and the caller goes round again.
+pub const WRITEV_MAX: usize = 64 * 1024;
+
+fn sys_writev(fd: u64, iov_ptr: u64, iovcnt: u64) -> u64 {
+    if iovcnt > MAX_IOVECS as u64 {
+        return ERR_INVAL;
+    }
+    if iovcnt == 0 {
+        return 0;
+    }
+    if iov_ptr == 0 {
+        return ERR_FAULT;
+    }
+    let address_space = match process::current_address_space() {
+        Some(space) => space,
+        None => return ERR_BADF,
+    };
+    let current_pid = match process::current_pid() {
+        Some(pid) => pid,
+        None => return ERR_BADF,
+    };
+
+    let table = match process::read_user_buffer(&address_space, iov_ptr, iovcnt as usize * IoVec::SIZE) {
+        Ok(bytes) => bytes,
+        Err(_) => return ERR_FAULT,
+    };
+    let iovecs: Vec<IoVec> = table.chunks_exact(IoVec::SIZE).map(IoVec::from_bytes).collect();
+    let total = iovecs
+        .iter()
+        .try_fold(0usize, |sum, iov| sum.checked_add(iov.len as usize));
+    let total = match total {
+        Some(total) => total,
+        None => return ERR_INVAL,
+    };
+
+    // The lengths are the caller's to choose, so only the first
+    // `WRITEV_MAX` bytes are gathered and the rest is left for a retry.
+    let mut kernel_buffer = vec![0u8; total.min(WRITEV_MAX)];
+    let mut filled = 0;
+    for iov in iovecs.iter().filter(|iov| iov.len != 0) {
+        if filled == kernel_buffer.len() {
+            break;
+        }
+        let end = (filled + iov.len as usize).min(kernel_buffer.len());
+        let fragment = &mut kernel_buffer[filled..end];
+        if iov.base == 0 || process::copy_from_user(&address_space, fragment, iov.base).is_err() {
+            return ERR_FAULT;
+        }
+        filled = end;
+    }
+    if kernel_buffer.is_empty() {
+        return 0;
+    }
+
+    match process::with_fd_mut(current_pid, fd as usize, |descriptor| descriptor.write_all(&kernel_buffer)) {
+        Ok(Ok(count)) => count as u64,
+        Ok(Err(err)) => encode_error(map_file_io_error(err)),
+        Err(ProcessError::InvalidFileDescriptor) => encode_error(SysError::BadFileDescriptor),
+        Err(err) => {
+            klog!("[syscall] writev failed pid {} fd {} err {:?}\n", current_pid, fd, err);
+            encode_error(SysError::BadFileDescriptor)
+        }
+    }
+}
+
+/// Collect a NULL-terminated array of C string pointers. A null `argv_ptr`
+/// is an empty list.
+fn copy_user_argv(argv_ptr: u64) -> Result<Vec<Vec<u8>>, u64> {
+    let mut args = Vec::new();
+    if argv_ptr == 0 {
+        return Ok(args);
+    }
+
+    let address_space = process::current_address_space().ok_or(ERR_BADF)?;
+    loop {
+        let mut word = [0u8; 8];
+        let slot = argv_ptr + (args.len() * 8) as u64;
+        process::copy_from_user(&address_space, &mut word, slot).map_err(|_| ERR_FAULT)?;
+        let ptr = u64::from_le_bytes(word);
+        if ptr == 0 {
+            return Ok(args);
+        }
+        if args.len() == process::MAX_ARGS {
+            return Err(ERR_INVAL);
+        }
+        let arg = process::read_user_cstr(&address_space, ptr, process::MAX_ARG_LEN + 1)
+            .map_err(user_copy_error)?;
+        args.push(arg);
+    }
+}
+
+fn sys_spawn(path_ptr: u64, path_len: u64, argv_ptr: u64) -> u64 {
+    let buffer = match copy_user_path(path_ptr, path_len) {
+        Ok(buf) => buf,
+        Err(code) => return code,
+    };
+    let path_str = match str::from_utf8(&buffer) {
+        Ok(s) => s,
+        Err(_) => return ERR_INVAL,
+    };
+    let args = match copy_user_argv(argv_ptr) {
+        Ok(args) => args,
+        Err(code) => return code,
+    };
+    let argv: Vec<&[u8]> = args.iter().map(|arg| arg.as_slice()).collect();
+
+    // Each spawned child is named after its program's basename.
+    let name = path_str.rsplit('/').next().unwrap_or(path_str);
+
+    match process::spawn_user_process_with_args(name, path_str, &argv) {
+        Ok(pid) => pid as u64,
+        Err(ProcessError::PathNotFound) => encode_error(SysError::NoEntry),
+        Err(ProcessError::InvalidElf) | Err(ProcessError::ArgumentListTooLong) => {
+            encode_error(SysError::InvalidArgument)
+        }
+        Err(ProcessError::UserImageIo) => encode_error(SysError::Io),
+        Err(ProcessError::InvalidUserPointer) | Err(ProcessError::UserMemoryNotPresent) => {
+            encode_error(SysError::Fault)
+        }
+        Err(err) => {
+            klog!("[syscall] spawn failed path {:?} err {:?}\n", path_str, err);
+            encode_error(SysError::NoMemory)
+        }
+    }
+}
+
+/// `pid` is -1 for any child or a specific child pid. The exit status is
+/// written to `status_ptr` as an `i32` unless the pointer is null.
+fn sys_waitpid(pid: u64, status_ptr: u64) -> u64 {
+    let target = match pid as i64 {
+        -1 => None,
+        value if value > 0 && value <= process::Pid::MAX as i64 => Some(value as process::Pid),
+        _ => return ERR_INVAL,
+    };
+
+    let (child, code) = match process::wait_for_child(target) {
+        Ok(result) => result,
+        Err(ProcessError::NoChildren) | Err(ProcessError::ChildNotFound) => return ERR_CHILD,
+        Err(err) => {
+            klog!("[syscall] waitpid failed target {:?} err {:?}\n", target, err);
+            return ERR_BADF;
+        }
+    };
+
+    if status_ptr != 0 {
+        let address_space = match process::current_address_space() {
+            Some(space) => space,
+            None => return ERR_BADF,
+        };
+        if let Err(err) = process::copy_to_user(&address_space, status_ptr, &code.to_le_bytes()) {
+            klog!("[syscall] waitpid copy_to_user failed ptr=0x{:016X} err {:?}\n", status_ptr, err);
+            return ERR_FAULT;
+        }
+    }
+
+    child as u64
+}
+
+/// Errors from the pid and group calls below.
+fn map_process_error(call: &str, err: ProcessError) -> u64 {
+    match err {
+        ProcessError::ProcessNotFound => ERR_SRCH,
+        ProcessError::NotPermitted => ERR_PERM,
+        other => {
+            klog!("[syscall] {} failed err {:?}\n", call, other);
+            ERR_IO
+        }
+    }
+}
+
+fn sys_kill(pid: u64) -> u64 {
+    match process::kill(pid as i64) {
+        Ok(_) => 0,
+        Err(err) => map_process_error("kill", err),
+    }
+}
+
+fn sys_setpgid(pid: u64, pgid: u64) -> u64 {
+    if pid > u64::from(process::Pid::MAX) || pgid > u64::from(process::Pid::MAX) {
+        return ERR_INVAL;
+    }
+    match process::set_pgid(pid as process::Pid, pgid as process::Pid) {
+        Ok(()) => 0,
+        Err(err) => map_process_error("setpgid", err),
+    }
+}
+
+fn sys_getpgid(pid: u64) -> u64 {
+    if pid > u64::from(process::Pid::MAX) {
+        return ERR_INVAL;
+    }
+    match process::get_pgid(pid as process::Pid) {
+        Ok(pgid) => u64::from(pgid),
+        Err(err) => map_process_error("getpgid", err),
+    }
+}
+
 fn sys_yield() -> u64 {
     process::yield_now();
     0
 }
 
+/// Reboot, power-off and tracing need an effective uid of root.
+fn caller_privileged() -> bool {
+    process::current_credentials().map_or(false, |credentials| credentials.is_privileged())
+}
+
+fn sys_reboot() -> u64 {
+    if !caller_privileged() {
+        klog!("[syscall] reboot refused pid={:?}\n", process::current_pid());
+        return ERR_ACCES;
+    }
+    power::reboot()
+}
+
+fn sys_poweroff() -> u64 {
+    if !caller_privileged() {
+        klog!("[syscall] poweroff refused pid={:?}\n", process::current_pid());
+        return ERR_ACCES;
+    }
+    power::poweroff()
+}
+
+/// Turn tracing on (`enabled != 0`) or off and return the old setting as
+/// 0 or 1. Root only, since the log shows every process's arguments.
+fn sys_systrace(enabled: u64) -> u64 {
+    if !caller_privileged() {
+        return ERR_ACCES;
+    }
+    set_trace(enabled != 0) as u64
+}
+
 fn sys_exit(code: u64) -> u64 {
     klog!("[syscall] exit pid={:?} code={}\n", process::current_pid(), code);
     let status = (code & 0xFFFF_FFFF) as i32;
@@ -412,7 +1476,18 @@ pub fn write(fd: u64, bytes: &[u8]) -> SysResult<usize> {
     frame.rdi = fd;
     frame.rsi = bytes.as_ptr() as u64;
     frame.rdx = bytes.len() as u64;
-    decode_ret(dispatch(&mut frame)).map(|value| value as usize)
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|value| value as usize)
+}
+
+/// Write `parts` to `fd` as one request, in order.
+pub fn writev(fd: u64, parts: &[&[u8]]) -> SysResult<usize> {
+    let iovecs: Vec<IoVec> = parts.iter().map(|part| IoVec::new(part)).collect();
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::WRITEV;
+    frame.rdi = fd;
+    frame.rsi = iovecs.as_ptr() as u64;
+    frame.rdx = iovecs.len() as u64;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|value| value as usize)
 }
 
 pub fn read(fd: u64, buf: &mut [u8]) -> SysResult<usize> {
@@ -421,23 +1496,148 @@ pub fn read(fd: u64, buf: &mut [u8]) -> SysResult<usize> {
     frame.rdi = fd;
     frame.rsi = buf.as_mut_ptr() as u64;
     frame.rdx = buf.len() as u64;
-    decode_ret(dispatch(&mut frame)).map(|value| value as usize)
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|value| value as usize)
+}
+
+pub fn ioctl(fd: u64, cmd: u32, arg: u64) -> SysResult<u64> {
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::IOCTL;
+    frame.rdi = fd;
+    frame.rsi = cmd as u64;
+    frame.rdx = arg;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel)
+}
+
+pub fn getdents(fd: u64, buf: &mut [u8]) -> SysResult<usize> {
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::GETDENTS;
+    frame.rdi = fd;
+    frame.rsi = buf.as_mut_ptr() as u64;
+    frame.rdx = buf.len() as u64;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|value| value as usize)
 }
 
 pub fn open(path: &str) -> SysResult<usize> {
+    open_with(path, 0)
+}
+
+/// `open` with explicit `nr::open_flags`.
+pub fn open_with(path: &str, flags: u64) -> SysResult<usize> {
     let mut frame = SyscallFrame::empty();
     frame.rax = nr::OPEN;
     frame.rdi = path.as_ptr() as u64;
     frame.rsi = path.len() as u64;
-    frame.rdx = 0;
-    decode_ret(dispatch(&mut frame)).map(|value| value as usize)
+    frame.rdx = flags;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|value| value as usize)
+}
+
+pub fn fstat(fd: u64) -> SysResult<Stat> {
+    let mut stat = Stat::default();
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::FSTAT;
+    frame.rdi = fd;
+    frame.rsi = &mut stat as *mut Stat as u64;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|_| stat)
+}
+
+pub fn stat(path: &str) -> SysResult<Stat> {
+    let mut stat = Stat::default();
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::STAT;
+    frame.rdi = path.as_ptr() as u64;
+    frame.rsi = path.len() as u64;
+    frame.rdx = &mut stat as *mut Stat as u64;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|_| stat)
+}
+
+pub fn sysinfo() -> SysResult<SysInfo> {
+    let mut info = SysInfo::default();
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::SYSINFO;
+    frame.rdi = &mut info as *mut SysInfo as u64;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|_| info)
+}
+
+/// Fill `procs` with one record per process, returning how many were written.
+pub fn getprocs(procs: &mut [ProcInfo]) -> SysResult<usize> {
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::GETPROCS;
+    frame.rdi = procs.as_mut_ptr() as u64;
+    frame.rsi = (procs.len() * ProcInfo::SIZE) as u64;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|value| value as usize)
+}
+
+/// Wait until one of `fds` is ready or `timeout_ms` passes (negative waits
+/// forever), filling in each `revents`. Returns how many entries are ready.
+pub fn poll(fds: &mut [PollFd], timeout_ms: i64) -> SysResult<usize> {
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::POLL;
+    frame.rdi = fds.as_mut_ptr() as u64;
+    frame.rsi = fds.len() as u64;
+    frame.rdx = timeout_ms as u64;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|value| value as usize)
+}
+
+/// Spawn `path` as a child of the caller, passing `argv` on its stack.
+pub fn spawn(path: &str, argv: &[&str]) -> SysResult<process::Pid> {
+    let strings: Vec<Vec<u8>> = argv
+        .iter()
+        .map(|arg| {
+            let mut bytes = Vec::with_capacity(arg.len() + 1);
+            bytes.extend_from_slice(arg.as_bytes());
+            bytes.push(0);
+            bytes
+        })
+        .collect();
+    let mut pointers: Vec<u64> = strings.iter().map(|arg| arg.as_ptr() as u64).collect();
+    pointers.push(0);
+
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::SPAWN;
+    frame.rdi = path.as_ptr() as u64;
+    frame.rsi = path.len() as u64;
+    frame.rdx = pointers.as_ptr() as u64;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|value| value as process::Pid)
+}
+
+/// Reap `pid`, or any child when `None`, returning its pid and exit status.
+pub fn waitpid(pid: Option<process::Pid>) -> SysResult<(process::Pid, i32)> {
+    let mut status = 0i32;
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::WAITPID;
+    frame.rdi = pid.map(|pid| pid as u64).unwrap_or(u64::MAX);
+    frame.rsi = &mut status as *mut i32 as u64;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|child| (child as process::Pid, status))
+}
+
+/// End `pid`, or every process in group `-pid`, or the caller's group for 0.
+pub fn kill(pid: i64) -> SysResult<()> {
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::KILL;
+    frame.rdi = pid as u64;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|_| ())
+}
+
+pub fn setpgid(pid: process::Pid, pgid: process::Pid) -> SysResult<()> {
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::SETPGID;
+    frame.rdi = u64::from(pid);
+    frame.rsi = u64::from(pgid);
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|_| ())
+}
+
+pub fn getpgid(pid: process::Pid) -> SysResult<process::Pid> {
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::GETPGID;
+    frame.rdi = u64::from(pid);
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|pgid| pgid as process::Pid)
 }
 
 pub fn close(fd: u64) -> SysResult<()> {
     let mut frame = SyscallFrame::empty();
     frame.rax = nr::CLOSE;
     frame.rdi = fd;
-    decode_ret(dispatch(&mut frame)).map(|_| ())
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|_| ())
 }
 
 pub fn seek(fd: u64, offset: i64, whence: SeekWhence) -> SysResult<u64> {
@@ -446,7 +1646,24 @@ pub fn seek(fd: u64, offset: i64, whence: SeekWhence) -> SysResult<u64> {
     frame.rdi = fd;
     frame.rsi = offset as u64;
     frame.rdx = whence.as_raw();
-    decode_ret(dispatch(&mut frame))
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel)
+}
+
+/// Issue a syscall by number with up to three arguments and hand back the raw
+/// `rax` value. `number` may carry `nr::NEG_ERRNO_FLAG`.
+pub fn raw(number: u64, arg0: u64, arg1: u64, arg2: u64) -> u64 {
+    let mut frame = SyscallFrame::empty();
+    frame.rax = number;
+    frame.rdi = arg0;
+    frame.rsi = arg1;
+    frame.rdx = arg2;
+    dispatch(&mut frame)
+}
+
+/// Decode a value returned by `raw`, honouring whichever convention the
+/// syscall number selected.
+pub fn decode_raw(number: u64, value: u64) -> SysResult<u64> {
+    decode_ret(value, ErrorConvention::from_number(number))
 }
 
 pub fn yield_now() {
@@ -455,6 +1672,28 @@ pub fn yield_now() {
     let _ = dispatch(&mut frame);
 }
 
+/// Only returns if the caller is not privileged.
+pub fn reboot() -> SysResult<()> {
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::REBOOT;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|_| ())
+}
+
+/// Only returns if the caller is not privileged.
+pub fn poweroff() -> SysResult<()> {
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::POWEROFF;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|_| ())
+}
+
+/// Switch syscall tracing on or off, returning whether it was on.
+pub fn systrace(enabled: bool) -> SysResult<bool> {
+    let mut frame = SyscallFrame::empty();
+    frame.rax = nr::SYSTRACE;
+    frame.rdi = enabled as u64;
+    decode_ret(dispatch(&mut frame), ErrorConvention::Sentinel).map(|previous| previous != 0)
+}
+
 pub fn exit(status: i32) -> ! {
     let mut frame = SyscallFrame::empty();
     frame.rax = nr::EXIT;
@@ -6,11 +6,18 @@ global_asm!(r#"
     .section .text
 
     .extern syscall_trampoline
+    .extern SYSCALL_KERNEL_RSP
+    .extern SYSCALL_USER_RSP
 
     .globl syscall_entry
     .type syscall_entry, @function
 syscall_entry:
+    // Interrupts stay off (IA32_FMASK) until the user rsp is stored on the
+    // process's own kernel stack; the scratch slot is only live until then.
     swapgs
+    mov [rip + SYSCALL_USER_RSP], rsp
+    mov rsp, [rip + SYSCALL_KERNEL_RSP]
+    push qword ptr [rip + SYSCALL_USER_RSP]
     push rbp
     mov rbp, rsp
 
@@ -25,16 +32,29 @@ syscall_entry:
     push r8
     push r9
 
+    // Eleven pushes from a 16-byte aligned top: pad before the call.
     mov rdi, rsp
+    sub rsp, 8
     call syscall_trampoline
-
-    add rsp, 8*7
+    add rsp, 8
+
+    // The handler may have slept with interrupts on. None may arrive once
+    // rsp points back at the user stack.
+    cli
+    pop r9
+    pop r8
+    pop r10
+    pop rdx
+    pop rsi
+    pop rdi
+    add rsp, 8          // rax: the return value replaces it
     pop rcx
     pop r11
 
     pop rbp
+    pop rsp
     swapgs
-    sysret
+    sysretq
 
     .section .note.GNU-stack,"",@progbits
 "#);
@@ -1,36 +1,304 @@
 #![allow(dead_code)]
 
-use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 
+use crate::interrupts::pit::frequency_for;
 use crate::klog;
-use crate::process;
-use super::{interrupts, pit};
+use crate::process::{self, ProcessError, WaitChannel};
+use crate::sync::spinlock::SpinLock;
+use super::interrupts::vectors;
+use super::{apic, interrupts, pit};
 
 const DEFAULT_FREQUENCY_HZ: u32 = 100;
 const PREEMPT_SLICE_TICKS: u64 = 1;
+/// Kernel timers that can be pending at once.
+pub const MAX_TIMERS: usize = 32;
 
 static TICK_COUNT: AtomicU64 = AtomicU64::new(0);
 static FREQUENCY_HZ: AtomicU32 = AtomicU32::new(0);
+static LAPIC_SOURCE: AtomicBool = AtomicBool::new(false);
+/// Set once a tick source is programmed with its handler installed.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// The interrupt that drives `timer_handler`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TickSource {
+    Pit,
+    Lapic,
+}
 
 pub fn init() {
     init_with_frequency(DEFAULT_FREQUENCY_HZ);
 }
 
+/// Tick at `hz` from the Local APIC timer, or from the PIT when there is
+/// no LAPIC or its timer cannot be calibrated.
 pub fn init_with_frequency(hz: u32) {
+    if start_lapic_timer(hz).is_some() {
+        return;
+    }
+    klog!("[timer] local APIC timer unavailable; using the PIT\n");
+    interrupts::register_handler(vectors::PIT, timer_handler);
+    interrupts::enable_vector(vectors::PIT);
+    set_frequency(hz);
+    RUNNING.store(true, Ordering::Release);
+}
+
+pub fn source() -> TickSource {
+    if LAPIC_SOURCE.load(Ordering::Relaxed) {
+        TickSource::Lapic
+    } else {
+        TickSource::Pit
+    }
+}
+
+/// Move the tick onto the LAPIC timer in periodic mode at `hz`, masking the
+/// PIT line. Returns the rate, or `None` (changing nothing) when
+/// `apic::calibrate` cannot measure the timer.
+pub fn start_lapic_timer(hz: u32) -> Option<u32> {
+    let count = apic::calibrate(hz)?;
+    interrupts::register_handler(vectors::LAPIC_TIMER, timer_handler);
+    interrupts::disable_vector(vectors::PIT);
+    apic::start_timer(vectors::LAPIC_TIMER, count);
+    LAPIC_SOURCE.store(true, Ordering::Relaxed);
     FREQUENCY_HZ.store(hz, Ordering::Relaxed);
-    interrupts::register_handler(interrupts::vectors::PIT, timer_handler);
-    interrupts::enable_vector(interrupts::vectors::PIT);
-    pit::init_frequency(hz);
-    klog!("[timer] PIT set to {} Hz\n", hz);
+    RUNNING.store(true, Ordering::Release);
+    klog!("[timer] LAPIC timer set to {} Hz (initial count {})\n", hz, count);
+    Some(hz)
+}
+
+/// Undo `start_lapic_timer` so a test leaves the harness without a tick,
+/// converting ticks at `previous_hz` again, the `frequency()` read before
+/// the start.
+#[cfg(kernel_test)]
+pub fn stop_lapic_timer(previous_hz: u32) {
+    apic::stop_timer();
+    interrupts::unregister_handler(vectors::LAPIC_TIMER);
+    LAPIC_SOURCE.store(false, Ordering::Relaxed);
+    FREQUENCY_HZ.store(previous_hz, Ordering::Relaxed);
+    RUNNING.store(false, Ordering::Release);
+}
+
+/// Change the tick rate and return the rate achieved. The LAPIC timer is
+/// recalibrated for exactly `hz`; the PIT lands on the nearest rate its
+/// divisor allows (clamped to 18 Hz..1.19 MHz). Tick-to-time conversions
+/// use it from then on; ticks already counted are not rescaled.
+pub fn set_frequency(hz: u32) -> u32 {
+    if source() == TickSource::Lapic {
+        return start_lapic_timer(hz).unwrap_or_else(frequency);
+    }
+    let divisor = pit::init_frequency(hz);
+    let actual = frequency_for(divisor);
+    FREQUENCY_HZ.store(actual, Ordering::Relaxed);
+    klog!("[timer] PIT set to {} Hz (requested {}, divisor {})\n", actual, hz, divisor);
+    actual
 }
 
 pub fn ticks() -> u64 {
     TICK_COUNT.load(Ordering::Relaxed)
 }
 
+/// Whether real ticks are arriving: a source is programmed and has fired
+/// at least once, so interrupts have been on since. Until then anything
+/// waiting on `ticks()` or `sleep_ms` would wait forever, and should spin.
+pub fn is_ticking() -> bool {
+    RUNNING.load(Ordering::Acquire) && ticks() > 0
+}
+
+/// Programmed tick rate, or the default before `init` has run.
+pub fn frequency() -> u32 {
+    match FREQUENCY_HZ.load(Ordering::Relaxed) {
+        0 => DEFAULT_FREQUENCY_HZ,
+        hz => hz,
+    }
+}
+
+pub fn ticks_to_ms(ticks: u64) -> u64 {
+    ticks.saturating_mul(1000) / frequency() as u64
+}
+
+/// Ticks covering at least `ms` milliseconds, rounded up so a timeout
+/// never fires early.
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    let hz = frequency() as u64;
+    ms.saturating_mul(hz).saturating_add(999) / 1000
+}
+
+/// The test harness runs with the PIT masked; this stands in for timer
+/// interrupts so time-based bookkeeping can be exercised.
+#[cfg(kernel_test)]
+pub fn advance_ticks(count: u64) {
+    for _ in 0..count {
+        let tick = TICK_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+        run_expired(tick);
+        process::watchdog_tick(tick);
+    }
+}
+
+/// Runs in interrupt context with the timer's `data`: it must not sleep and
+/// should be short.
+pub type TimerCallback = fn(usize);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TimerHandle(u64);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TimerError {
+    /// All `MAX_TIMERS` slots are pending.
+    TableFull,
+}
+
+#[derive(Copy, Clone)]
+struct Timer {
+    id: u64,
+    deadline: u64,
+    callback: TimerCallback,
+    data: usize,
+}
+
+/// Pending timers sorted by deadline, earliest first; equal deadlines keep
+/// the order they were added in. Each tick only looks at the front.
+struct TimerList {
+    entries: [Option<Timer>; MAX_TIMERS],
+    len: usize,
+    next_id: u64,
+}
+
+impl TimerList {
+    const fn new() -> Self {
+        Self {
+            entries: [None; MAX_TIMERS],
+            len: 0,
+            next_id: 1,
+        }
+    }
+
+    fn insert(&mut self, deadline: u64, callback: TimerCallback, data: usize) -> Result<TimerHandle, TimerError> {
+        if self.len == MAX_TIMERS {
+            return Err(TimerError::TableFull);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut index = self.len;
+        while index > 0 && self.entries[index - 1].map_or(false, |timer| timer.deadline > deadline) {
+            self.entries[index] = self.entries[index - 1];
+            index -= 1;
+        }
+        self.entries[index] = Some(Timer { id, deadline, callback, data });
+        self.len += 1;
+        Ok(TimerHandle(id))
+    }
+
+    fn remove_at(&mut self, index: usize) -> Option<Timer> {
+        let timer = self.entries[index].take();
+        for slot in index..self.len - 1 {
+            self.entries[slot] = self.entries[slot + 1];
+        }
+        self.len -= 1;
+        self.entries[self.len] = None;
+        timer
+    }
+
+    fn cancel(&mut self, handle: TimerHandle) -> bool {
+        let found = self.entries[..self.len]
+            .iter()
+            .position(|timer| timer.map_or(false, |timer| timer.id == handle.0));
+        match found {
+            Some(index) => self.remove_at(index).is_some(),
+            None => false,
+        }
+    }
+
+    fn pop_expired(&mut self, now: u64) -> Option<Timer> {
+        match self.entries[0] {
+            Some(timer) if timer.deadline <= now => self.remove_at(0),
+            _ => None,
+        }
+    }
+}
+
+static TIMERS: SpinLock<TimerList> = SpinLock::new(TimerList::new());
+
+/// Call `callback(data)` from the timer interrupt once `ticks()` reaches
+/// `deadline`. A deadline already in the past fires on the next tick.
+pub fn add_timer(deadline: u64, callback: TimerCallback, data: usize) -> Result<TimerHandle, TimerError> {
+    TIMERS.lock().insert(deadline, callback, data)
+}
+
+/// Drop a pending timer. Returns false if it already fired or was
+/// cancelled.
+pub fn cancel(handle: TimerHandle) -> bool {
+    TIMERS.lock().cancel(handle)
+}
+
+pub fn pending_timers() -> usize {
+    TIMERS.lock().len
+}
+
+/// Fire every timer due at `now`. Callbacks run with the list unlocked so
+/// they can add or cancel timers. If process context holds the list when
+/// the tick lands, the expired timers wait for the next tick.
+fn run_expired(now: u64) {
+    loop {
+        let timer = match TIMERS.try_lock() {
+            Some(mut timers) => timers.pop_expired(now),
+            None => return,
+        };
+        match timer {
+            Some(timer) => (timer.callback)(timer.data),
+            None => return,
+        }
+    }
+}
+
+/// Sequence number of the next `sleep_ms`. Numbers are never reused, so a
+/// wake re-armed past its sleeper's return finds nobody on the channel.
+static NEXT_SLEEP: AtomicU64 = AtomicU64::new(1);
+
+/// Runs in the timer interrupt. If the interrupted code holds the process
+/// table the wake is put off to the next tick rather than spinning on it.
+fn wake_sleeper(sleep: usize) {
+    if process::try_wake_channel(WaitChannel::Sleep(sleep as u64)).is_none() {
+        let _ = add_timer(ticks().saturating_add(1), wake_sleeper, sleep);
+    }
+}
+
+/// Block the current process for at least `ms` milliseconds.
+pub fn sleep_ms(ms: u64) -> Result<(), ProcessError> {
+    let deadline = ticks().saturating_add(ms_to_ticks(ms).max(1));
+    let sleep = NEXT_SLEEP.fetch_add(1, Ordering::Relaxed);
+    let channel = WaitChannel::Sleep(sleep);
+    while ticks() < deadline {
+        let mut armed = None;
+        // Armed after we are marked blocked, so a tick that lands first
+        // still wakes us. Without a free slot we wake ourselves and give up.
+        process::block_current_then(channel, || {
+            armed = add_timer(deadline, wake_sleeper, sleep as usize).ok();
+            if armed.is_none() {
+                process::wake_channel(channel);
+            }
+        })?;
+        match armed {
+            Some(handle) => {
+                cancel(handle);
+            }
+            None => {
+                klog!("[timer] sleep_ms: timer table full\n");
+                return Err(ProcessError::AllocationFailed);
+            }
+        }
+    }
+    Ok(())
+}
+
 fn timer_handler(frame: &mut interrupts::InterruptFrame) {
     let tick = TICK_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
-    if tick % PREEMPT_SLICE_TICKS == 0 {
+    process::expire_poll_deadline(tick);
+    run_expired(tick);
+    process::watchdog_tick(tick);
+    // Deferred work underneath us is IRQ-exit code, not a task to switch
+    // away from; the slice is taken at the next tick after it finishes.
+    if tick % PREEMPT_SLICE_TICKS == 0 && !interrupts::draining() {
         // klog!("[timer] Prescaler tick: {}\n", tick);
         process::request_preempt(frame);
     }
@@ -1,4 +1,5 @@
 pub mod kernel;
 pub mod drivers;
 pub mod io;
+pub mod power;
 pub mod qemu;
@@ -0,0 +1,63 @@
+use core::hint::spin_loop;
+use klog;
+
+use super::io::{inb, outb, outw};
+
+/// ACPI PM1a control ports QEMU wires up: 0x604 on the q35 and newer i440fx
+/// machine types, 0xB004 on older Bochs-derived ones. Writing SLP_EN with
+/// sleep type 0 selects S5 (soft off).
+const QEMU_ACPI_PM1A_CNT: u16 = 0x604;
+const BOCHS_ACPI_PM1A_CNT: u16 = 0xB004;
+const ACPI_SLP_EN_S5: u16 = 0x2000;
+
+const KBC_STATUS: u16 = 0x64;
+const KBC_COMMAND: u16 = 0x64;
+const KBC_INPUT_FULL: u8 = 1 << 1;
+/// Pulse the CPU reset line.
+const KBC_PULSE_RESET: u8 = 0xFE;
+
+pub fn poweroff() -> ! {
+    klog!("[power] ACPI soft-off\n");
+    unsafe {
+        outw(QEMU_ACPI_PM1A_CNT, ACPI_SLP_EN_S5);
+        outw(BOCHS_ACPI_PM1A_CNT, ACPI_SLP_EN_S5);
+    }
+    klog!("[power] ACPI soft-off ignored, forcing a triple fault\n");
+    triple_fault()
+}
+
+pub fn reboot() -> ! {
+    klog!("[power] resetting through the keyboard controller\n");
+    unsafe {
+        for _ in 0..0x10000 {
+            if inb(KBC_STATUS) & KBC_INPUT_FULL == 0 {
+                break;
+            }
+            spin_loop();
+        }
+        outb(KBC_COMMAND, KBC_PULSE_RESET);
+    }
+    for _ in 0..0x10000 {
+        spin_loop();
+    }
+    klog!("[power] controller reset ignored, forcing a triple fault\n");
+    triple_fault()
+}
+
+/// Load an empty IDT and raise an exception: with no handler for it, or for
+/// the double fault that follows, the CPU shuts down and the machine resets.
+/// Under `qemu -no-reboot` that ends the emulator instead.
+pub fn triple_fault() -> ! {
+    #[repr(C, packed)]
+    struct NullIdtr {
+        limit: u16,
+        base: u64,
+    }
+    let idtr = NullIdtr { limit: 0, base: 0 };
+    unsafe {
+        core::arch::asm!("cli", "lidt [{}]", "int3", in(reg) &idtr, options(nostack));
+    }
+    loop {
+        spin_loop();
+    }
+}
@@ -0,0 +1,94 @@
+#![allow(dead_code)]
+
+//! What the bootloader handed over besides memory: the boot modules listed
+//! in the multiboot2 info block. `init` copies their addresses out before
+//! the frame allocator starts, so `mem::phys` can keep them from being
+//! handed out, and `modules()` gives kmain the first one to mount as an
+//! initrd.
+
+pub mod multiboot;
+
+use self::multiboot::BootModule;
+use crate::klog;
+use crate::sync::spinlock::SpinLock;
+
+/// Modules remembered; any past this are logged and ignored.
+pub const MAX_MODULES: usize = 8;
+
+/// The boot modules, in the order the bootloader listed them. Names point
+/// into the info block, which nothing reclaims.
+#[derive(Copy, Clone)]
+pub struct Modules {
+    entries: [BootModule<'static>; MAX_MODULES],
+    count: usize,
+}
+
+impl Modules {
+    const fn new() -> Self {
+        Self {
+            entries: [BootModule {
+                start: 0,
+                end: 0,
+                name: "",
+            }; MAX_MODULES],
+            count: 0,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BootModule<'static>> {
+        self.entries[..self.count].iter()
+    }
+
+    pub fn first(&self) -> Option<&BootModule<'static>> {
+        self.iter().next()
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Physical address just past the highest module, or 0 with none.
+    pub fn end(&self) -> u64 {
+        self.iter().map(|module| module.end).max().unwrap_or(0)
+    }
+}
+
+static MODULES: SpinLock<Modules> = SpinLock::new(Modules::new());
+
+/// Read the module tags out of the multiboot2 info block. Runs before
+/// `mem::phys::init`, which reserves everything below `modules().end()`.
+///
+/// # Safety
+/// `multiboot_info_addr` must point at the multiboot2 info block, which
+/// must stay where it is for the life of the kernel.
+pub unsafe fn init(multiboot_info_addr: usize) {
+    let total_size = *(multiboot_info_addr as *const u32) as usize;
+    let info: &'static [u8] = core::slice::from_raw_parts(multiboot_info_addr as *const u8, total_size);
+
+    let mut table = MODULES.lock();
+    *table = Modules::new();
+    for module in multiboot::modules(info) {
+        if table.count == MAX_MODULES {
+            klog!("[boot] module '{}' ignored: table full\n", module.name);
+            continue;
+        }
+        klog!(
+            "[boot] module '{}' phys=0x{:X}-0x{:X} ({} bytes)\n",
+            module.name,
+            module.start,
+            module.end,
+            module.len()
+        );
+        let slot = table.count;
+        table.entries[slot] = module;
+        table.count += 1;
+    }
+}
+
+pub fn modules() -> Modules {
+    *MODULES.lock()
+}
@@ -0,0 +1,106 @@
+#![allow(dead_code)]
+
+//! Walking the tags of a multiboot2 info block held as a byte slice, and
+//! decoding boot module tags (type 3). The block starts with its total size
+//! and a reserved word; each tag follows on an 8-byte boundary and the list
+//! stops at an end tag or at the block's stated size, whichever is first.
+
+pub const INFO_HEADER_LEN: usize = 8;
+pub const TAG_HEADER_LEN: usize = 8;
+
+pub const TAG_TYPE_END: u32 = 0;
+pub const TAG_TYPE_CMDLINE: u32 = 1;
+pub const TAG_TYPE_MODULE: u32 = 3;
+pub const TAG_TYPE_MMAP: u32 = 6;
+
+/// Bytes of a module tag before its name: header, `mod_start`, `mod_end`.
+pub const MODULE_HEADER_LEN: usize = 16;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Tag<'a> {
+    pub tag_type: u32,
+    /// The whole tag, header included, cut to the tag's `size`.
+    pub bytes: &'a [u8],
+}
+
+/// A file the bootloader loaded next to the kernel, such as an initrd.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BootModule<'a> {
+    /// Physical address of the first byte.
+    pub start: u64,
+    /// Physical address one past the last byte.
+    pub end: u64,
+    /// The string given after the module path in the bootloader config,
+    /// usually the module's name. Empty when there is none or it is not
+    /// UTF-8.
+    pub name: &'a str,
+}
+
+impl BootModule<'_> {
+    pub fn len(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Every tag in `info`, in order, not including the end tag. A tag whose
+/// size is smaller than its header or runs past the block ends the walk.
+pub fn tags(info: &[u8]) -> impl Iterator<Item = Tag<'_>> + '_ {
+    let end = if info.len() >= INFO_HEADER_LEN {
+        (read_u32(info, 0) as usize).min(info.len())
+    } else {
+        0
+    };
+    let mut offset = INFO_HEADER_LEN;
+    core::iter::from_fn(move || {
+        if end.saturating_sub(offset) < TAG_HEADER_LEN {
+            return None;
+        }
+        let tag_type = read_u32(info, offset);
+        let size = read_u32(info, offset + 4) as usize;
+        if tag_type == TAG_TYPE_END || size < TAG_HEADER_LEN || size > end - offset {
+            offset = end;
+            return None;
+        }
+        let tag = Tag {
+            tag_type,
+            bytes: &info[offset..offset + size],
+        };
+        offset = align_up(offset + size, 8);
+        Some(tag)
+    })
+}
+
+/// Decode a module tag. `None` for another tag type or one too short to
+/// hold the two addresses.
+pub fn module(tag: Tag<'_>) -> Option<BootModule<'_>> {
+    if tag.tag_type != TAG_TYPE_MODULE || tag.bytes.len() < MODULE_HEADER_LEN {
+        return None;
+    }
+    let string = &tag.bytes[MODULE_HEADER_LEN..];
+    let terminator = string.iter().position(|&b| b == 0).unwrap_or(string.len());
+    Some(BootModule {
+        start: u64::from(read_u32(tag.bytes, 8)),
+        end: u64::from(read_u32(tag.bytes, 12)),
+        name: core::str::from_utf8(&string[..terminator]).unwrap_or(""),
+    })
+}
+
+/// The module tags of `info`, in the order the bootloader listed them.
+pub fn modules(info: &[u8]) -> impl Iterator<Item = BootModule<'_>> + '_ {
+    tags(info).filter_map(module)
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    let mask = align - 1;
+    (value + mask) & !mask
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut raw = [0u8; 4];
+    raw.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(raw)
+}
@@ -0,0 +1,66 @@
+#![allow(dead_code)]
+
+//! The kernel command line from the multiboot2 info block: space-separated
+//! words, either bare flags (`poweroff`) or `key=value` pairs (`systrace=1`).
+
+const TAG_TYPE_END: u32 = 0;
+const TAG_TYPE_CMDLINE: u32 = 1;
+
+#[repr(C)]
+struct TagHeader {
+    tag_type: u32,
+    size: u32,
+}
+
+/// Find the command-line tag. `None` when GRUB passed nothing.
+///
+/// # Safety
+/// `multiboot_info_addr` must point at the multiboot2 info block.
+pub unsafe fn from_multiboot(multiboot_info_addr: usize) -> Option<&'static str> {
+    let total_size = *(multiboot_info_addr as *const u32) as usize;
+    let mut current = multiboot_info_addr + core::mem::size_of::<u32>() * 2;
+    let end = multiboot_info_addr + total_size;
+
+    while current < end {
+        let header = &*(current as *const TagHeader);
+        if header.tag_type == TAG_TYPE_END {
+            break;
+        }
+        if header.tag_type == TAG_TYPE_CMDLINE {
+            let data_ptr = current + core::mem::size_of::<TagHeader>();
+            let len = header.size as usize - core::mem::size_of::<TagHeader>();
+            if len == 0 {
+                return None;
+            }
+            let bytes = core::slice::from_raw_parts(data_ptr as *const u8, len);
+            let terminator = bytes.iter().position(|&b| b == 0).unwrap_or(len);
+            if terminator == 0 {
+                return None;
+            }
+            let slice = &bytes[..terminator];
+            return core::str::from_utf8(slice).ok();
+        }
+        current = align_up(current + header.size as usize, 8);
+    }
+    None
+}
+
+pub fn has_flag(cmdline: &str, flag: &str) -> bool {
+    cmdline.split_ascii_whitespace().any(|part| part == flag)
+}
+
+/// The value of the first non-empty `key=value` word.
+pub fn value<'a>(cmdline: &'a str, key: &str) -> Option<&'a str> {
+    cmdline.split_ascii_whitespace().find_map(|part| {
+        let rest = part.strip_prefix(key)?;
+        match rest.strip_prefix('=') {
+            Some(value) if !value.is_empty() => Some(value),
+            _ => None,
+        }
+    })
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    let mask = align - 1;
+    (value + mask) & !mask
+}
@@ -0,0 +1,73 @@
+//! Per-CPU storage. A `CpuLocal<T>` holds one `T` for each possible CPU and
+//! hands the caller the slot for the CPU it is running on. Slots are dense:
+//! each CPU claims the next free one with `claim_slot` as it comes up, so
+//! APIC ids, which firmware may number sparsely, never index the array.
+//! Only the boot CPU runs today, so everything lands in slot 0; state that
+//! will differ between CPUs once more are started lives here rather than in
+//! a plain global.
+
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use super::apic_id;
+
+/// Highest number of CPUs with a slot. `claim_slot` refuses any more.
+pub const MAX_CPUS: usize = 8;
+
+/// xAPIC ids are 8 bits wide.
+const APIC_IDS: usize = 256;
+
+/// Slot plus one for each APIC id; 0 while the id has none.
+static SLOT_OF: [AtomicU8; APIC_IDS] = [const { AtomicU8::new(0) }; APIC_IDS];
+static SLOTS_CLAIMED: AtomicUsize = AtomicUsize::new(0);
+
+/// Give the CPU with APIC `id` the next free slot, or return the one it
+/// already has. `None` when every slot is taken or the id is out of range;
+/// bring-up must then park that CPU rather than let it run kernel code.
+pub fn claim_slot(id: u32) -> Option<usize> {
+    let entry = SLOT_OF.get(id as usize)?;
+    if let Some(slot) = slot_from_entry(entry.load(Ordering::Acquire)) {
+        return Some(slot);
+    }
+    let slot = SLOTS_CLAIMED
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |claimed| {
+            (claimed < MAX_CPUS).then_some(claimed + 1)
+        })
+        .ok()?;
+    entry.store(slot as u8 + 1, Ordering::Release);
+    Some(slot)
+}
+
+/// The slot the CPU with APIC `id` claimed, if any.
+pub fn slot_of(id: u32) -> Option<usize> {
+    slot_from_entry(SLOT_OF.get(id as usize)?.load(Ordering::Acquire))
+}
+
+/// The calling CPU's slot. The boot CPU runs before it can claim one, so an
+/// id without a slot reads as slot 0.
+pub fn current_slot() -> usize {
+    slot_of(apic_id()).unwrap_or(0)
+}
+
+fn slot_from_entry(entry: u8) -> Option<usize> {
+    (entry != 0).then(|| entry as usize - 1)
+}
+
+pub struct CpuLocal<T> {
+    slots: [T; MAX_CPUS],
+}
+
+impl<T> CpuLocal<T> {
+    pub const fn new(slots: [T; MAX_CPUS]) -> Self {
+        Self { slots }
+    }
+
+    /// The calling CPU's slot.
+    pub fn get(&self) -> &T {
+        self.for_slot(current_slot())
+    }
+
+    /// Slots come from `claim_slot`, so they are always below `MAX_CPUS`.
+    pub fn for_slot(&self, slot: usize) -> &T {
+        &self.slots[slot]
+    }
+}
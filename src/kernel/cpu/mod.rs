@@ -1,7 +1,33 @@
 #![allow(dead_code)]
 
+mod local;
+pub mod support;
+
+pub use self::local::{claim_slot, CpuLocal, MAX_CPUS};
+
 #[cfg(target_arch = "x86_64")]
 pub use crate::arch::x86_64::kernel::cpu::*;
 
 #[cfg(not(target_arch = "x86_64"))]
 compile_error!("cpuid module is not implemented for this architecture");
+
+use crate::klog;
+
+/// Stop here, with a message saying why, if the CPU lacks a feature the
+/// kernel depends on. Runs before paging or the heap are touched, so the
+/// failure is a log line instead of a fault somewhere later in boot.
+pub fn require() {
+    let words = support_words();
+    let Some(missing) = support::missing_feature(&words) else {
+        return;
+    };
+    klog!(
+        "[cpu] CPU not supported: {} is missing (leaf 1 edx=0x{:08X}, max extended leaf=0x{:08X}, extended edx=0x{:08X})\n",
+        missing.name(),
+        words.basic_edx,
+        words.max_extended_leaf,
+        words.extended_edx
+    );
+    klog!("[cpu] halting\n");
+    halt_forever();
+}
@@ -0,0 +1,93 @@
+#![allow(dead_code)]
+
+//! What the panic handler prints: the message, who was running, the
+//! registers at the panic and the return addresses found by walking the
+//! frame-pointer chain. Nothing here allocates or waits on a lock, since the
+//! panic may have come from inside the heap or with the process table held.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+#[cfg(target_arch = "x86_64")]
+use crate::arch::x86_64::kernel::backtrace::{self, Registers};
+
+#[cfg(not(target_arch = "x86_64"))]
+compile_error!("panic dump not available for this architecture");
+
+use crate::process;
+
+/// Frames printed before the walk gives up.
+pub const MAX_FRAMES: usize = 16;
+
+/// Write the whole dump to `out`, one `[kpanic]` line at a time.
+pub fn write_dump(out: &mut dyn Write, info: &PanicInfo, regs: &Registers) -> fmt::Result {
+    writeln!(out, "[kpanic] Kernel panic!")?;
+    writeln!(out, "[kpanic] {}", info)?;
+
+    match process::current_pid() {
+        Some(pid) => {
+            let name = process::try_process_name(pid);
+            let name = name.as_ref().map_or("?", process::ProcessName::as_str);
+            writeln!(out, "[kpanic] pid {} ({})", pid, name)?;
+        }
+        None => writeln!(out, "[kpanic] no current process (boot context)")?,
+    }
+
+    writeln!(
+        out,
+        "[kpanic] rsp=0x{:016X} rbp=0x{:016X} rflags=0x{:016X}",
+        regs.rsp, regs.rbp, regs.rflags
+    )?;
+    writeln!(
+        out,
+        "[kpanic] cr0=0x{:016X} cr2=0x{:016X} cr3=0x{:016X}",
+        regs.cr0, regs.cr2, regs.cr3
+    )?;
+
+    writeln!(out, "[kpanic] backtrace:")?;
+    let mut walked = 0;
+    for (index, frame) in backtrace::frames(regs.rbp, MAX_FRAMES).enumerate() {
+        writeln!(
+            out,
+            "[kpanic]   #{:<2} rbp=0x{:016X} ret=0x{:016X}",
+            index, frame.rbp, frame.return_address
+        )?;
+        walked += 1;
+    }
+    if walked == 0 {
+        writeln!(out, "[kpanic]   (no frames)")?;
+    }
+    Ok(())
+}
+
+/// The test build can let one task panic on purpose: its dump is logged as
+/// usual and then the task exits with `PANIC_EXIT_CODE` instead of halting
+/// the machine, so the test can look at what was printed.
+#[cfg(kernel_test)]
+pub mod expect {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use crate::process::{self, Pid};
+
+    pub const PANIC_EXIT_CODE: i32 = 101;
+
+    static SURVIVOR: AtomicU32 = AtomicU32::new(0);
+
+    pub fn panic_in(pid: Pid) {
+        SURVIVOR.store(pid, Ordering::SeqCst);
+    }
+
+    /// Called at the end of the panic handler; only returns when the
+    /// panicking task was not expected to panic.
+    pub fn exit_if_expected() {
+        let Some(pid) = process::current_pid() else {
+            return;
+        };
+        if SURVIVOR
+            .compare_exchange(pid, 0, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            process::exit_current(PANIC_EXIT_CODE);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+#![allow(dead_code)]
+
+//! Byte-at-a-time decoder for the subset of ANSI/VT100 escape sequences the
+//! console understands. It knows nothing about VGA memory; callers feed bytes
+//! and apply the returned `AnsiAction`.
+
+const ESC: u8 = 0x1B;
+const MAX_PARAMS: usize = 4;
+
+/// ANSI colour index (black, red, green, yellow, blue, magenta, cyan, white)
+/// to the matching VGA palette entry.
+const ANSI_TO_VGA: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AnsiAction {
+    /// Byte is not part of an escape sequence and should be handled normally.
+    Print(u8),
+    /// Byte was swallowed by an escape sequence still in progress, or by one
+    /// the console does not support.
+    None,
+    /// New VGA attribute byte from an SGR sequence.
+    SetAttr(u8),
+    /// Absolute cursor position, zero-based.
+    CursorTo { row: usize, col: usize },
+    CursorUp(usize),
+    CursorDown(usize),
+    CursorForward(usize),
+    CursorBack(usize),
+    /// `ESC[2J`: blank the whole screen and home the cursor.
+    ClearScreen,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+}
+
+pub struct AnsiParser {
+    state: State,
+    default_attr: u8,
+    params: [u16; MAX_PARAMS],
+    count: usize,
+    private: bool,
+}
+
+impl AnsiParser {
+    pub const fn new(default_attr: u8) -> Self {
+        Self {
+            state: State::Ground,
+            default_attr,
+            params: [0; MAX_PARAMS],
+            count: 0,
+            private: false,
+        }
+    }
+
+    /// Feed one byte. `attr` is the attribute currently in effect so SGR
+    /// sequences can change only the foreground or background half.
+    pub fn feed(&mut self, byte: u8, attr: u8) -> AnsiAction {
+        match self.state {
+            State::Ground => {
+                if byte == ESC {
+                    self.state = State::Escape;
+                    AnsiAction::None
+                } else {
+                    AnsiAction::Print(byte)
+                }
+            }
+            State::Escape => {
+                if byte == b'[' {
+                    self.params = [0; MAX_PARAMS];
+                    self.count = 0;
+                    self.private = false;
+                    self.state = State::Csi;
+                } else {
+                    // Two-byte escapes (ESC 7, ESC c, ...) are not supported.
+                    self.state = State::Ground;
+                }
+                AnsiAction::None
+            }
+            State::Csi => self.feed_csi(byte, attr),
+        }
+    }
+
+    fn feed_csi(&mut self, byte: u8, attr: u8) -> AnsiAction {
+        match byte {
+            b'0'..=b'9' => {
+                if self.count == 0 {
+                    self.count = 1;
+                }
+                if let Some(param) = self.params.get_mut(self.count - 1) {
+                    *param = param.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                }
+                AnsiAction::None
+            }
+            b';' => {
+                if self.count == 0 {
+                    self.count = 1;
+                }
+                self.count += 1;
+                AnsiAction::None
+            }
+            // Private markers and intermediates: remember them so the final
+            // byte is ignored rather than misread as a standard command.
+            0x20..=0x2F | b'<'..=b'?' => {
+                self.private = true;
+                AnsiAction::None
+            }
+            0x40..=0x7E => {
+                self.state = State::Ground;
+                if self.private {
+                    AnsiAction::None
+                } else {
+                    self.dispatch(byte, attr)
+                }
+            }
+            _ => AnsiAction::None,
+        }
+    }
+
+    fn param(&self, index: usize) -> u16 {
+        if index < self.count.min(MAX_PARAMS) {
+            self.params[index]
+        } else {
+            0
+        }
+    }
+
+    /// Cursor counts treat a missing or zero parameter as 1.
+    fn count_param(&self, index: usize) -> usize {
+        self.param(index).max(1) as usize
+    }
+
+    fn dispatch(&self, final_byte: u8, attr: u8) -> AnsiAction {
+        match final_byte {
+            b'm' => AnsiAction::SetAttr(self.apply_sgr(attr)),
+            b'H' | b'f' => AnsiAction::CursorTo {
+                row: self.count_param(0) - 1,
+                col: self.count_param(1) - 1,
+            },
+            b'A' => AnsiAction::CursorUp(self.count_param(0)),
+            b'B' => AnsiAction::CursorDown(self.count_param(0)),
+            b'C' => AnsiAction::CursorForward(self.count_param(0)),
+            b'D' => AnsiAction::CursorBack(self.count_param(0)),
+            // Only the whole-screen form; erasing above or below the cursor
+            // is not supported.
+            b'J' if self.param(0) == 2 => AnsiAction::ClearScreen,
+            _ => AnsiAction::None,
+        }
+    }
+
+    fn apply_sgr(&self, mut attr: u8) -> u8 {
+        if self.count == 0 {
+            return self.default_attr;
+        }
+
+        for &code in &self.params[..self.count.min(MAX_PARAMS)] {
+            match code {
+                0 => attr = self.default_attr,
+                code @ 30..=37 => attr = (attr & 0xF0) | ANSI_TO_VGA[(code - 30) as usize],
+                39 => attr = (attr & 0xF0) | (self.default_attr & 0x0F),
+                code @ 40..=47 => attr = (attr & 0x0F) | (ANSI_TO_VGA[(code - 40) as usize] << 4),
+                49 => attr = (attr & 0x0F) | (self.default_attr & 0xF0),
+                _ => {}
+            }
+        }
+        attr
+    }
+}
@@ -3,12 +3,17 @@ use super::{register_block, register_char, CharDevice, Driver, DriverError, Driv
 
 use super::console;
 use super::keyboard;
+use super::serial;
+use super::tty;
 use crate::arch::x86_64::drivers::ata;
 struct NullDevice;
 struct ZeroDevice;
+/// Reads like `/dev/zero`, but every write fails as if the disk were full.
+struct FullDevice;
 
 static NULL_DRIVER: NullDevice = NullDevice;
 static ZERO_DRIVER: ZeroDevice = ZeroDevice;
+static FULL_DRIVER: FullDevice = FullDevice;
 
 impl Driver for NullDevice {
     fn name(&self) -> &'static str {
@@ -50,9 +55,7 @@ impl Driver for ZeroDevice {
 
 impl CharDevice for ZeroDevice {
     fn read(&self, buf: &mut [u8]) -> Result<usize, DriverError> {
-        for byte in buf.iter_mut() {
-            *byte = 0;
-        }
+        buf.fill(0);
         Ok(buf.len())
     }
 
@@ -61,6 +64,31 @@ impl CharDevice for ZeroDevice {
     }
 }
 
+impl Driver for FullDevice {
+    fn name(&self) -> &'static str {
+        "full"
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Char
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+impl CharDevice for FullDevice {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, DriverError> {
+        buf.fill(0);
+        Ok(buf.len())
+    }
+
+    fn write(&self, _buf: &[u8]) -> Result<usize, DriverError> {
+        Err(DriverError::NoSpace)
+    }
+}
+
 pub fn register() {
     if let Err(err) = register_char(console::driver()) {
         klog!("[driver] failed to register console: {:?}\n", err);
@@ -68,6 +96,12 @@ pub fn register() {
     if let Err(err) = register_char(keyboard::driver()) {
         klog!("[driver] failed to register keyboard: {:?}\n", err);
     }
+    if let Err(err) = register_char(tty::driver()) {
+        klog!("[driver] failed to register tty: {:?}\n", err);
+    }
+    if let Err(err) = register_char(serial::driver()) {
+        klog!("[driver] failed to register serial: {:?}\n", err);
+    }
     if let Err(err) = register_block(ata::driver()) {
         klog!("[driver] failed to register ata primary: {:?}\n", err);
     }
@@ -77,4 +111,7 @@ pub fn register() {
     if let Err(err) = register_char(&ZERO_DRIVER) {
         klog!("[driver] failed to register zero device: {:?}\n", err);
     }
+    if let Err(err) = register_char(&FULL_DRIVER) {
+        klog!("[driver] failed to register full device: {:?}\n", err);
+    }
 }
@@ -1,6 +1,12 @@
-use crate::drivers::{CharDevice, Driver, DriverError, DriverKind};
+use crate::drivers::ansi::{AnsiAction, AnsiParser};
+use crate::drivers::screen::Screen;
+use crate::drivers::{ioctl, CharDevice, Driver, DriverError, DriverKind};
+use crate::mem::heap;
 use crate::sync::spinlock::SpinLock;
 
+use core::alloc::Layout;
+use core::ptr;
+
 #[cfg(target_arch = "x86_64")]
 use crate::arch::x86_64::drivers::console as arch;
 
@@ -9,17 +15,121 @@ compile_error!("Console driver is only implemented for x86_64");
 
 pub struct Console;
 
+/// Rows kept after they scroll off the top of the screen.
+pub const SCROLLBACK_ROWS: usize = 200;
+
+type Row = [u16; arch::WIDTH];
+
+/// Ring of rows that have scrolled off screen, plus a copy of the live
+/// screen taken while the user is looking at history. Both buffers come from
+/// the kernel heap on first use; if that fails scrollback is simply off.
+struct Scrollback {
+    rows: *mut Row,
+    live: *mut Row,
+    head: usize,
+    len: usize,
+    offset: usize,
+}
+
+unsafe impl Send for Scrollback {}
+
+impl Scrollback {
+    const fn new() -> Self {
+        Self {
+            rows: ptr::null_mut(),
+            live: ptr::null_mut(),
+            head: 0,
+            len: 0,
+            offset: 0,
+        }
+    }
+
+    fn ensure_storage(&mut self) -> bool {
+        if !self.rows.is_null() {
+            return true;
+        }
+        let rows = Layout::array::<Row>(SCROLLBACK_ROWS).ok();
+        let live = Layout::array::<Row>(arch::HEIGHT).ok();
+        let (Some(rows), Some(live)) = (rows, live) else {
+            return false;
+        };
+        unsafe {
+            let rows_ptr = heap::allocate(rows) as *mut Row;
+            if rows_ptr.is_null() {
+                return false;
+            }
+            let live_ptr = heap::allocate(live) as *mut Row;
+            if live_ptr.is_null() {
+                heap::deallocate(rows_ptr as *mut u8, rows);
+                return false;
+            }
+            self.rows = rows_ptr;
+            self.live = live_ptr;
+        }
+        true
+    }
+
+    fn push(&mut self, row: &Row) {
+        if !self.ensure_storage() {
+            return;
+        }
+        let slot = (self.head + self.len) % SCROLLBACK_ROWS;
+        unsafe { self.rows.add(slot).write(*row) };
+        if self.len == SCROLLBACK_ROWS {
+            self.head = (self.head + 1) % SCROLLBACK_ROWS;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    /// `index` 0 is the oldest retained row.
+    fn get(&self, index: usize) -> Option<Row> {
+        if index >= self.len {
+            return None;
+        }
+        let slot = (self.head + index) % SCROLLBACK_ROWS;
+        Some(unsafe { *self.rows.add(slot) })
+    }
+
+    fn save_live(&mut self) {
+        for row in 0..arch::HEIGHT {
+            unsafe { self.live.add(row).write(arch::read_row(row)) };
+        }
+    }
+
+    fn restore_live(&mut self) {
+        for row in 0..arch::HEIGHT {
+            arch::write_row(row, unsafe { &*self.live.add(row) });
+        }
+    }
+
+    /// Paint the screen as if the view were `offset` rows above live.
+    fn repaint(&self) {
+        let first = self.len - self.offset;
+        for row in 0..arch::HEIGHT {
+            let line = first + row;
+            let cells = match self.get(line) {
+                Some(cells) => cells,
+                None => unsafe { *self.live.add(line - self.len) },
+            };
+            arch::write_row(row, &cells);
+        }
+    }
+}
+
+/// `screen` is the authoritative copy of the live display; video memory is
+/// only written from it, a batch of rows at a time.
 struct ConsoleState {
-    row: usize,
-    col: usize,
-    attr: u8,
+    screen: Screen<{ arch::WIDTH }, { arch::HEIGHT }>,
+    ansi: AnsiParser,
+    scrollback: Scrollback,
 }
 
 static CONSOLE: Console = Console;
 static STATE: SpinLock<ConsoleState> = SpinLock::new(ConsoleState {
-    row: 0,
-    col: 0,
-    attr: arch::DEFAULT_ATTR,
+    screen: Screen::new(arch::DEFAULT_ATTR),
+    ansi: AnsiParser::new(arch::DEFAULT_ATTR),
+    scrollback: Scrollback::new(),
 });
 
 impl Console {
@@ -41,9 +151,8 @@ impl Driver for Console {
         let mut state = STATE.lock();
         arch::init();
         arch::clear_screen();
-        state.row = 0;
-        state.col = 0;
-        arch::set_cursor(state.row, state.col);
+        state.screen.clear();
+        flush(&mut state.screen);
         Ok(())
     }
 }
@@ -53,45 +162,113 @@ impl CharDevice for Console {
         Ok(0)
     }
 
+    /// Lay the whole buffer out on the shadow screen first, then copy the
+    /// changed rows to video memory and move the cursor once.
     fn write(&self, buf: &[u8]) -> Result<usize, DriverError> {
-        let mut state = STATE.lock();
+        let mut guard = STATE.lock();
+        snap_to_live(&mut guard);
+        let state = &mut *guard;
+        let (screen, ansi, scrollback) = (&mut state.screen, &mut state.ansi, &mut state.scrollback);
+        let mut scrolled = |row: &Row| scrollback.push(row);
         for &byte in buf {
-            match byte {
-                b'\n' => new_line(&mut state),
-                b'\r' => state.col = 0,
-                b'\t' => {
-                    let next_tab = (state.col / 8 + 1) * 8;
-                    if next_tab >= arch::WIDTH {
-                        new_line(&mut state);
-                    } else {
-                        state.col = next_tab;
-                    }
-                }
-                byte => put_char(&mut state, byte),
+            match ansi.feed(byte, screen.attr) {
+                AnsiAction::Print(byte) => screen.put(byte, &mut scrolled),
+                AnsiAction::None => {}
+                action => apply_ansi(screen, action),
             }
         }
+        flush(screen);
         Ok(buf.len())
     }
+
+    fn ioctl(&self, cmd: u32, arg: u64) -> Result<u64, DriverError> {
+        match cmd {
+            ioctl::CONSOLE_CLEAR => {
+                clear();
+                Ok(0)
+            }
+            ioctl::CONSOLE_SET_CURSOR => {
+                set_cursor(((arg >> 16) & 0xFFFF) as usize, (arg & 0xFFFF) as usize);
+                Ok(0)
+            }
+            _ => Err(DriverError::Unsupported),
+        }
+    }
 }
 
-fn put_char(state: &mut ConsoleState, byte: u8) {
-    if state.col >= arch::WIDTH {
-        new_line(state);
+fn apply_ansi(screen: &mut Screen<{ arch::WIDTH }, { arch::HEIGHT }>, action: AnsiAction) {
+    match action {
+        AnsiAction::SetAttr(attr) => screen.attr = attr,
+        AnsiAction::CursorTo { row, col } => screen.move_to(row, col),
+        AnsiAction::CursorUp(n) => screen.move_to(screen.row.saturating_sub(n), screen.col),
+        AnsiAction::CursorDown(n) => screen.move_to(screen.row.saturating_add(n), screen.col),
+        AnsiAction::CursorForward(n) => screen.move_to(screen.row, screen.col.saturating_add(n)),
+        AnsiAction::CursorBack(n) => screen.move_to(screen.row, screen.col.saturating_sub(n)),
+        AnsiAction::ClearScreen => screen.clear(),
+        AnsiAction::Print(_) | AnsiAction::None => {}
     }
+}
 
-    arch::write_at(state.row, state.col, byte, state.attr);
-    state.col += 1;
-    arch::set_cursor(state.row, state.col);
+/// Copy the rows the shadow screen changed to video memory and place the
+/// cursor.
+fn flush(screen: &mut Screen<{ arch::WIDTH }, { arch::HEIGHT }>) {
+    if let Some((first, last)) = screen.take_dirty() {
+        for row in first..=last {
+            arch::write_row(row, screen.row_cells(row));
+        }
+    }
+    arch::set_cursor(screen.row, screen.col);
 }
 
-fn new_line(state: &mut ConsoleState) {
-    state.col = 0;
-    state.row += 1;
-    if state.row >= arch::HEIGHT {
-        arch::scroll_up();
-        state.row = arch::HEIGHT - 1;
+fn snap_to_live(state: &mut ConsoleState) {
+    if state.scrollback.offset == 0 {
+        return;
     }
-    arch::set_cursor(state.row, state.col);
+    state.scrollback.offset = 0;
+    state.scrollback.restore_live();
+    arch::set_cursor(state.screen.row, state.screen.col);
+}
+
+/// Move the view `lines` rows back into history (negative moves towards the
+/// live screen). The view is clamped to the retained rows; any write snaps it
+/// back to live output.
+pub fn scroll_view(lines: isize) {
+    let mut state = STATE.lock();
+    let sb = &mut state.scrollback;
+    if sb.rows.is_null() {
+        return;
+    }
+
+    let target = (sb.offset as isize + lines).clamp(0, sb.len as isize) as usize;
+    if target == sb.offset {
+        return;
+    }
+    if sb.offset == 0 {
+        sb.save_live();
+    }
+    sb.offset = target;
+    if target == 0 {
+        sb.restore_live();
+        let (row, col) = (state.screen.row, state.screen.col);
+        arch::set_cursor(row, col);
+    } else {
+        sb.repaint();
+    }
+}
+
+/// Rows currently held in scrollback.
+pub fn scrollback_len() -> usize {
+    STATE.lock().scrollback.len
+}
+
+/// Text of scrollback row `index` (0 is the oldest), without attributes.
+pub fn scrollback_line(index: usize) -> Option<[u8; arch::WIDTH]> {
+    let cells = STATE.lock().scrollback.get(index)?;
+    let mut text = [0u8; arch::WIDTH];
+    for (out, cell) in text.iter_mut().zip(cells.iter()) {
+        *out = (*cell & 0xFF) as u8;
+    }
+    Some(text)
 }
 
 pub fn driver() -> &'static dyn CharDevice {
@@ -102,10 +279,26 @@ pub fn write_bytes(buf: &[u8]) -> Result<usize, DriverError> {
     driver().write(buf)
 }
 
+/// Blank the screen and home the cursor. `ESC[2J` in the write path and
+/// the `CONSOLE_CLEAR` ioctl both end up in `Screen::clear`.
 pub fn clear() {
     let mut state = STATE.lock();
+    state.scrollback.offset = 0;
     arch::clear_screen();
-    state.row = 0;
-    state.col = 0;
-    arch::set_cursor(state.row, state.col);
+    state.screen.clear();
+    flush(&mut state.screen);
+}
+
+/// Place the cursor, clamped to the screen, returning to the live view first.
+pub fn set_cursor(row: usize, col: usize) {
+    let mut state = STATE.lock();
+    snap_to_live(&mut state);
+    state.screen.move_to(row, col);
+    flush(&mut state.screen);
+}
+
+/// Cursor position as `(row, col)`.
+pub fn cursor() -> (usize, usize) {
+    let state = STATE.lock();
+    (state.screen.row, state.screen.col)
 }
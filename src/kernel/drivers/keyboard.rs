@@ -1,8 +1,14 @@
+//! The keyboard as a raw byte stream: whatever the IRQ path has decoded,
+//! with no echo and no line editing. `drivers::tty` builds the terminal on
+//! top of it.
+
 use crate::drivers::{CharDevice, Driver, DriverError, DriverKind};
 use crate::process::{self, WaitChannel};
 
 #[cfg(target_arch = "x86_64")]
 use crate::arch::x86_64::drivers::keyboard as arch;
+#[cfg(target_arch = "x86_64")]
+pub use crate::arch::x86_64::drivers::keyboard::KeyboardStats;
 
 #[cfg(not(target_arch = "x86_64"))]
 compile_error!("Keyboard driver is only implemented for x86_64");
@@ -34,25 +40,64 @@ impl Driver for Keyboard {
 
 impl CharDevice for Keyboard {
     fn read(&self, buf: &mut [u8]) -> Result<usize, DriverError> {
-        if buf.is_empty() {
-            return Ok(0);
-        }
-
-        loop {
-            let count = arch::read(buf);
-            if count > 0 {
-                return Ok(count);
-            }
-
-            if let Err(_err) = process::block_current(WaitChannel::KeyboardInput) {
-                return Err(DriverError::IoError);
-            }
-        }
+        read_blocking(buf)
     }
 
     fn write(&self, _buf: &[u8]) -> Result<usize, DriverError> {
         Err(DriverError::Unsupported)
     }
+
+    fn can_read(&self) -> bool {
+        arch::has_input()
+    }
+
+    fn can_write(&self) -> bool {
+        false
+    }
+}
+
+/// Take whatever bytes are queued without waiting. Returns 0 when there is
+/// nothing yet, for pollers that must not sleep.
+pub fn try_read(buf: &mut [u8]) -> usize {
+    arch::read(buf)
+}
+
+pub fn has_input() -> bool {
+    arch::has_input()
+}
+
+/// Sleep on `WaitChannel::KeyboardInput` until input is ready, then read it.
+/// Only an empty `buf` returns 0.
+pub fn read_blocking(buf: &mut [u8]) -> Result<usize, DriverError> {
+    if buf.is_empty() {
+        return Ok(0);
+    }
+
+    loop {
+        let count = try_read(buf);
+        if count > 0 {
+            return Ok(count);
+        }
+        wait_for_input()?;
+    }
+}
+
+/// Sleep on `WaitChannel::KeyboardInput` until the IRQ path queues a byte.
+/// Callers check for input first and call again if it is still not there.
+pub fn wait_for_input() -> Result<(), DriverError> {
+    // A key that lands between the caller's empty read and the block would
+    // have found nobody to wake; check again once we are marked blocked.
+    process::block_current_then(WaitChannel::KeyboardInput, || {
+        if arch::has_input() {
+            process::wake_channel(WaitChannel::KeyboardInput);
+        }
+    })
+    .map_err(|_| DriverError::IoError)
+}
+
+/// Queue depth and how many bytes the raw ring has dropped since boot.
+pub fn stats() -> KeyboardStats {
+    arch::stats()
 }
 
 pub fn driver() -> &'static dyn CharDevice {
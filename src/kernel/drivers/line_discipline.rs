@@ -0,0 +1,92 @@
+#![allow(dead_code)]
+
+//! Canonical-mode line editing for terminal input. Bytes are collected until
+//! a newline arrives; only completed lines are handed to readers. Echo output
+//! is returned through a callback so the caller decides where it goes.
+
+pub const LINE_MAX: usize = 256;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+const ERASE_ECHO: &[u8] = b"\x08 \x08";
+
+pub struct LineDiscipline {
+    buffer: [u8; LINE_MAX],
+    len: usize,
+    /// Bytes at the front of `buffer` that belong to finished lines.
+    committed: usize,
+}
+
+impl LineDiscipline {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; LINE_MAX],
+            len: 0,
+            committed: 0,
+        }
+    }
+
+    /// Process one input byte, calling `echo` with whatever should appear on
+    /// the terminal as a result.
+    pub fn input<F>(&mut self, byte: u8, mut echo: F)
+    where
+        F: FnMut(&[u8]),
+    {
+        match byte {
+            BACKSPACE | DELETE => {
+                if self.len > self.committed {
+                    self.len -= 1;
+                    echo(ERASE_ECHO);
+                }
+            }
+            b'\n' | b'\r' => {
+                // Always leave room for the terminator; an over-long line is
+                // cut short rather than losing the newline.
+                if self.len == LINE_MAX {
+                    self.len -= 1;
+                }
+                self.buffer[self.len] = b'\n';
+                self.len += 1;
+                self.committed = self.len;
+                echo(b"\n");
+            }
+            byte => {
+                if self.len + 1 < LINE_MAX {
+                    self.buffer[self.len] = byte;
+                    self.len += 1;
+                    echo(&[byte]);
+                }
+            }
+        }
+    }
+
+    pub fn has_line(&self) -> bool {
+        self.committed > 0
+    }
+
+    /// Copy completed input into `out`, returning the byte count. Returns 0
+    /// while the current line is still being edited.
+    pub fn read(&mut self, out: &mut [u8]) -> usize {
+        let count = out.len().min(self.committed);
+        if count == 0 {
+            return 0;
+        }
+        out[..count].copy_from_slice(&self.buffer[..count]);
+        self.buffer.copy_within(count..self.len, 0);
+        self.len -= count;
+        self.committed -= count;
+        count
+    }
+
+    /// Discard everything, finished lines included.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.committed = 0;
+    }
+}
+
+impl Default for LineDiscipline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
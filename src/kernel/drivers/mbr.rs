@@ -0,0 +1,96 @@
+#![allow(dead_code)]
+
+//! Registers each partition found on a disk as a block device of its own,
+//! named `<prefix>p<n>` with `n` counting from 1.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::format;
+
+use super::partition::{self, Partition, PartitionEntry, PartitionTable, GPT_HEADER_LBA, SECTOR_SIZE};
+use super::{register_block, BlockDevice, DriverError};
+use crate::klog;
+
+/// GPT allows 128 entries; stop looking well before that on a small disk.
+const MAX_GPT_ENTRIES: u32 = 32;
+
+/// Scan `device` for an MBR or GPT and register its partitions. Returns how
+/// many were registered; an unpartitioned disk registers none.
+pub fn scan(device: &'static dyn BlockDevice, prefix: &str) -> Result<usize, DriverError> {
+    if device.block_size() != SECTOR_SIZE {
+        return Err(DriverError::Unsupported);
+    }
+
+    let mut sector = [0u8; SECTOR_SIZE];
+    device.read_blocks(0, &mut sector)?;
+
+    let mut registered = 0;
+    match partition::parse_mbr(&sector) {
+        PartitionTable::None => {
+            klog!("[part] {}: no partition table\n", device.name());
+        }
+        PartitionTable::Mbr(entries) => {
+            for (index, entry) in entries.iter().enumerate() {
+                if let Some(entry) = entry {
+                    register(device, prefix, index + 1, *entry)?;
+                    registered += 1;
+                }
+            }
+        }
+        PartitionTable::Gpt => {
+            registered = scan_gpt(device, prefix)?;
+        }
+    }
+    Ok(registered)
+}
+
+fn scan_gpt(device: &'static dyn BlockDevice, prefix: &str) -> Result<usize, DriverError> {
+    let mut sector = [0u8; SECTOR_SIZE];
+    device.read_blocks(GPT_HEADER_LBA, &mut sector)?;
+    let header = match partition::parse_gpt_header(&sector) {
+        Some(header) => header,
+        None => {
+            klog!("[part] {}: protective MBR but no GPT header\n", device.name());
+            return Ok(0);
+        }
+    };
+
+    let entry_size = header.entry_size as usize;
+    let per_sector = SECTOR_SIZE / entry_size;
+    let count = header.entry_count.min(MAX_GPT_ENTRIES) as usize;
+    let mut registered = 0;
+    let mut loaded_lba = None;
+
+    for index in 0..count {
+        let lba = header.entries_lba + (index / per_sector) as u64;
+        if loaded_lba != Some(lba) {
+            device.read_blocks(lba, &mut sector)?;
+            loaded_lba = Some(lba);
+        }
+        let offset = (index % per_sector) * entry_size;
+        if let Some(entry) = partition::parse_gpt_entry(&sector[offset..offset + entry_size]) {
+            register(device, prefix, index + 1, entry)?;
+            registered += 1;
+        }
+    }
+    Ok(registered)
+}
+
+fn register(
+    device: &'static dyn BlockDevice,
+    prefix: &str,
+    number: usize,
+    entry: PartitionEntry,
+) -> Result<(), DriverError> {
+    let name: &'static str = Box::leak(format!("{prefix}p{number}").into_boxed_str());
+    let part: &'static Partition = Box::leak(Box::new(Partition::new(name, device, entry.start_lba, entry.blocks)));
+    klog!(
+        "[part] {} start_lba={} blocks={} type=0x{:02X}\n",
+        name,
+        entry.start_lba,
+        entry.blocks,
+        entry.kind
+    );
+    register_block(part)
+}
@@ -3,10 +3,24 @@
 use crate::klog;
 use crate::mem::heap;
 use crate::sync::spinlock::SpinLock;
+use crate::vfs::handle::SeekFrom;
 
 use core::alloc::Layout;
 use core::{ptr, slice};
 
+pub mod ansi;
+pub mod ata_identify;
+pub mod byte_ring;
+pub mod line_discipline;
+pub mod mbr;
+pub mod partition;
+pub mod pipe;
+pub mod poll;
+pub mod ramdisk;
+pub mod scancode;
+pub mod screen;
+pub mod serial;
+pub mod tty;
 pub mod console;
 pub mod keyboard;
 
@@ -22,6 +36,14 @@ pub enum DriverError {
     InitFailed,
     Unsupported,
     IoError,
+    /// No registered device has that name.
+    NotFound,
+    /// A process still has the device open.
+    Busy,
+    /// The device has no room for the data.
+    NoSpace,
+    /// A registered device, of either kind, already has that name.
+    DuplicateName,
 }
 
 pub trait Driver: Send + Sync {
@@ -39,11 +61,111 @@ pub trait BlockDevice: Driver {
     fn flush(&self) -> Result<(), DriverError> {
         Ok(())
     }
+
+    /// Number of blocks on the device, or `u64::MAX` when the size is not
+    /// known.
+    fn capacity_sectors(&self) -> u64 {
+        u64::MAX
+    }
 }
 
+/// Check a request of `len` bytes at `lba` before it reaches the hardware.
+/// A length that is not whole blocks is `Unsupported`; a range that ends
+/// past `capacity_sectors()` is `IoError`. Returns the block count.
+pub fn check_block_range<D: BlockDevice + ?Sized>(device: &D, lba: u64, len: usize) -> Result<u64, DriverError> {
+    let block_size = device.block_size();
+    if block_size == 0 || !len.is_multiple_of(block_size) {
+        return Err(DriverError::Unsupported);
+    }
+    let blocks = (len / block_size) as u64;
+    match lba.checked_add(blocks) {
+        Some(end) if end <= device.capacity_sectors() => Ok(blocks),
+        _ => Err(DriverError::IoError),
+    }
+}
+
+/// Largest write a `CharDevice` must take in one piece.
+pub const ATOMIC_WRITE_MAX: usize = 512;
+
+/// A `write` of at most `ATOMIC_WRITE_MAX` bytes is atomic: it is taken
+/// whole or not at all, and no other writer's bytes land in the middle of
+/// it. Longer writes may be split or come back short.
 pub trait CharDevice: Driver {
     fn read(&self, buf: &mut [u8]) -> Result<usize, DriverError>;
     fn write(&self, buf: &[u8]) -> Result<usize, DriverError>;
+
+    /// Push out anything `write` has accepted but not yet delivered. Called
+    /// when an fd is closed, including the closes at process exit.
+    fn flush(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+
+    /// Called by the opening process when a path under `/dev` names the
+    /// device. Descriptors set up at spawn do not call it.
+    fn open(&self) {}
+
+    /// Out-of-band control request; `cmd` is one of the `ioctl` constants.
+    fn ioctl(&self, _cmd: u32, _arg: u64) -> Result<u64, DriverError> {
+        Err(DriverError::Unsupported)
+    }
+
+    /// Whether `read` would return without sleeping. Devices that never
+    /// block keep the default.
+    fn can_read(&self) -> bool {
+        true
+    }
+
+    /// Whether `write` would return without sleeping.
+    fn can_write(&self) -> bool {
+        true
+    }
+
+    /// Reposition a device that keeps its own cursor and return the new
+    /// offset. Streams such as the console and keyboard keep the default.
+    fn seek(&self, _pos: SeekFrom) -> Result<u64, DriverError> {
+        Err(DriverError::Unsupported)
+    }
+}
+
+/// Write all of `buf` in `ATOMIC_WRITE_MAX` pieces, so each piece keeps
+/// the atomic-write guarantee even when `buf` is longer. A short write is
+/// continued from where it stopped. Returns the bytes written, which is
+/// less than `buf.len()` only if the device stops taking data.
+pub fn write_all<D: CharDevice + ?Sized>(device: &D, buf: &[u8]) -> Result<usize, DriverError> {
+    let mut written = 0;
+    while written < buf.len() {
+        let end = buf.len().min(written + ATOMIC_WRITE_MAX);
+        match device.write(&buf[written..end]) {
+            Ok(0) => break,
+            Ok(count) => written += count,
+            Err(err) if written == 0 => return Err(err),
+            Err(_) => break,
+        }
+    }
+    Ok(written)
+}
+
+/// Command numbers for `CharDevice::ioctl`. The high byte names the device
+/// family so a command sent to the wrong device is rejected, not misread.
+pub mod ioctl {
+    /// Blank the screen and home the cursor. `arg` is ignored.
+    pub const CONSOLE_CLEAR: u32 = 0x4301;
+    /// Move the cursor to `arg >> 16` (row), `arg & 0xFFFF` (column),
+    /// both zero-based and clamped to the screen.
+    pub const CONSOLE_SET_CURSOR: u32 = 0x4302;
+    /// `arg` non-zero selects canonical (line-edited) input, zero raw.
+    pub const TTY_SET_CANONICAL: u32 = 0x5401;
+    /// Returns 1 in canonical mode, 0 in raw mode.
+    pub const TTY_GET_CANONICAL: u32 = 0x5402;
+    /// `arg` non-zero echoes typed input to the console, zero keeps quiet.
+    pub const TTY_SET_ECHO: u32 = 0x5403;
+    /// Returns 1 when input is echoed, 0 when not.
+    pub const TTY_GET_ECHO: u32 = 0x5404;
+    /// Make group `arg` the foreground group. The caller must be in the
+    /// terminal's session and `arg` a live group of it.
+    pub const TTY_SET_FOREGROUND: u32 = 0x5405;
+    /// Returns the foreground group, or 0 when no session owns the terminal.
+    pub const TTY_GET_FOREGROUND: u32 = 0x5406;
 }
 
 #[derive(Copy, Clone)]
@@ -87,6 +209,14 @@ impl DriverSlot {
             _ => None,
         }
     }
+
+    fn shutdown(&self) {
+        match self {
+            DriverSlot::Empty => {}
+            DriverSlot::Block(dev) => dev.shutdown(),
+            DriverSlot::Char(dev) => dev.shutdown(),
+        }
+    }
 }
 
 struct DriverRegistry {
@@ -114,7 +244,12 @@ impl DriverRegistry {
         self.insert(DriverSlot::Char(device))
     }
 
+    /// Names are unique across block and char devices, so a name lookup or
+    /// `unregister_by_name` always means one device.
     fn insert(&mut self, slot: DriverSlot) -> Result<(), DriverError> {
+        if slot.name().is_some_and(|name| self.position(name).is_some()) {
+            return Err(DriverError::DuplicateName);
+        }
         self.ensure_capacity(1)?;
         unsafe {
             self.slots.add(self.len).write(slot);
@@ -123,6 +258,22 @@ impl DriverRegistry {
         Ok(())
     }
 
+    fn position(&self, name: &str) -> Option<usize> {
+        self.iter().position(|slot| slot.name() == Some(name))
+    }
+
+    /// Take the slot at `index` out, keeping the others in registration
+    /// order.
+    fn remove(&mut self, index: usize) -> DriverSlot {
+        let slot = self.iter().nth(index).copied().unwrap_or(DriverSlot::Empty);
+        unsafe {
+            ptr::copy(self.slots.add(index + 1), self.slots.add(index), self.len - index - 1);
+            self.slots.add(self.len - 1).write(DriverSlot::empty());
+        }
+        self.len -= 1;
+        slot
+    }
+
     fn ensure_capacity(&mut self, additional: usize) -> Result<(), DriverError> {
         let required = self.len.checked_add(additional).ok_or(DriverError::RegistryFull)?;
         if required <= self.capacity {
@@ -200,7 +351,12 @@ pub fn init() {
     klog!("[driver] registry ready\n");
 }
 
+/// A name that is already taken is refused with `DuplicateName` before
+/// `init` runs, so registering the same device twice does not reset it. The
+/// registry checks again when inserting, in case a device of that name was
+/// added while `init` ran.
 pub fn register_block(device: &'static dyn BlockDevice) -> Result<(), DriverError> {
+    reject_duplicate(device.name())?;
     device.init().map_err(|err| {
         klog!("[driver] block device '{}' init failed: {:?}\n", device.name(), err);
         DriverError::InitFailed
@@ -211,7 +367,9 @@ pub fn register_block(device: &'static dyn BlockDevice) -> Result<(), DriverErro
     Ok(())
 }
 
+/// Same duplicate-name rules as `register_block`.
 pub fn register_char(device: &'static dyn CharDevice) -> Result<(), DriverError> {
+    reject_duplicate(device.name())?;
     device.init().map_err(|_| DriverError::InitFailed)?;
     let mut registry = REGISTRY.lock();
     registry.register_char(device)?;
@@ -219,6 +377,54 @@ pub fn register_char(device: &'static dyn CharDevice) -> Result<(), DriverError>
     Ok(())
 }
 
+fn reject_duplicate(name: &str) -> Result<(), DriverError> {
+    if REGISTRY.lock().position(name).is_some() {
+        klog!("[driver] '{}' is already registered\n", name);
+        return Err(DriverError::DuplicateName);
+    }
+    Ok(())
+}
+
+/// Remove the device called `name` from the registry and shut it down. A
+/// char device that some process still has open is refused with `Busy`.
+pub fn unregister_by_name(name: &str) -> Result<(), DriverError> {
+    // `char_device_in_use` takes the process table, so it runs before
+    // REGISTRY is taken and the two locks never nest.
+    if let Some(dev) = char_device_by_name(name) {
+        if crate::process::char_device_in_use(dev) {
+            klog!("[driver] '{}' is still open, not unregistering\n", name);
+            return Err(DriverError::Busy);
+        }
+    }
+    let slot = {
+        let mut registry = REGISTRY.lock();
+        let index = registry.position(name).ok_or(DriverError::NotFound)?;
+        registry.remove(index)
+    };
+    slot.shutdown();
+    klog!("[driver] unregistered '{}'\n", name);
+    Ok(())
+}
+
+/// Empty the registry, shutting devices down newest first. Meant for the
+/// reboot and power-off paths, so open descriptors are not consulted.
+pub fn shutdown_all() {
+    loop {
+        let slot = {
+            let mut registry = REGISTRY.lock();
+            if registry.len == 0 {
+                break;
+            }
+            let last = registry.len - 1;
+            registry.remove(last)
+        };
+        if let Some(name) = slot.name() {
+            klog!("[driver] shutting down '{}'\n", name);
+        }
+        slot.shutdown();
+    }
+}
+
 pub fn list_drivers() {
     let registry = REGISTRY.lock();
     for slot in registry.iter() {
@@ -0,0 +1,187 @@
+#![allow(dead_code)]
+
+//! Partitions as block devices of their own, plus MBR and GPT table
+//! parsing. A `Partition` adds its start LBA to every request and refuses
+//! anything that would run past its end, so filesystems can be mounted at
+//! LBA 0 of the partition rather than at a hand-picked offset on the disk.
+
+use crate::drivers::{check_block_range, BlockDevice, Driver, DriverError, DriverKind};
+
+pub const SECTOR_SIZE: usize = 512;
+pub const MBR_ENTRIES: usize = 4;
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_ENTRY_LEN: usize = 16;
+const MBR_TYPE_EMPTY: u8 = 0x00;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+pub const GPT_HEADER_LBA: u64 = 1;
+
+/// A region of a parent device, `start_lba..start_lba + blocks`.
+pub struct Partition {
+    name: &'static str,
+    parent: &'static dyn BlockDevice,
+    start_lba: u64,
+    blocks: u64,
+}
+
+impl Partition {
+    pub const fn new(name: &'static str, parent: &'static dyn BlockDevice, start_lba: u64, blocks: u64) -> Self {
+        Self {
+            name,
+            parent,
+            start_lba,
+            blocks,
+        }
+    }
+
+    pub fn start_lba(&self) -> u64 {
+        self.start_lba
+    }
+
+    pub fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    /// Parent LBA for a request of `len` bytes at partition LBA `lba`.
+    fn translate(&self, lba: u64, len: usize) -> Result<u64, DriverError> {
+        check_block_range(self, lba, len)?;
+        self.start_lba.checked_add(lba).ok_or(DriverError::IoError)
+    }
+}
+
+impl Driver for Partition {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Block
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+impl BlockDevice for Partition {
+    fn block_size(&self) -> usize {
+        self.parent.block_size()
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), DriverError> {
+        let lba = self.translate(lba, buf.len())?;
+        self.parent.read_blocks(lba, buf)
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), DriverError> {
+        let lba = self.translate(lba, buf.len())?;
+        self.parent.write_blocks(lba, buf)
+    }
+
+    fn flush(&self) -> Result<(), DriverError> {
+        self.parent.flush()
+    }
+
+    fn capacity_sectors(&self) -> u64 {
+        self.blocks
+    }
+}
+
+/// One used slot from a partition table.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PartitionEntry {
+    pub start_lba: u64,
+    pub blocks: u64,
+    /// MBR partition type byte; 0 for GPT entries.
+    pub kind: u8,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PartitionTable {
+    /// No boot signature: the disk is not partitioned.
+    None,
+    Mbr([Option<PartitionEntry>; MBR_ENTRIES]),
+    /// Protective MBR; the real table is the GPT starting at LBA 1.
+    Gpt,
+}
+
+/// Read the partition table out of sector 0.
+pub fn parse_mbr(sector: &[u8]) -> PartitionTable {
+    if sector.len() < SECTOR_SIZE || sector[510..512] != MBR_SIGNATURE {
+        return PartitionTable::None;
+    }
+
+    let mut entries = [None; MBR_ENTRIES];
+    for (index, slot) in entries.iter_mut().enumerate() {
+        let raw = &sector[MBR_TABLE_OFFSET + index * MBR_ENTRY_LEN..][..MBR_ENTRY_LEN];
+        let kind = raw[4];
+        let start = le_u32(&raw[8..12]) as u64;
+        let blocks = le_u32(&raw[12..16]) as u64;
+        if kind == MBR_TYPE_GPT_PROTECTIVE {
+            return PartitionTable::Gpt;
+        }
+        if kind != MBR_TYPE_EMPTY && blocks != 0 {
+            *slot = Some(PartitionEntry {
+                start_lba: start,
+                blocks,
+                kind,
+            });
+        }
+    }
+    PartitionTable::Mbr(entries)
+}
+
+/// Where the GPT keeps its partition entries.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct GptHeader {
+    pub entries_lba: u64,
+    pub entry_count: u32,
+    pub entry_size: u32,
+}
+
+pub fn parse_gpt_header(sector: &[u8]) -> Option<GptHeader> {
+    if sector.len() < 92 || &sector[0..8] != GPT_SIGNATURE {
+        return None;
+    }
+    let header = GptHeader {
+        entries_lba: le_u64(&sector[72..80]),
+        entry_count: le_u32(&sector[80..84]),
+        entry_size: le_u32(&sector[84..88]),
+    };
+    if header.entry_size < 128 || header.entry_size as usize > SECTOR_SIZE {
+        return None;
+    }
+    Some(header)
+}
+
+/// Decode one GPT entry; unused entries (all-zero type GUID) are `None`.
+pub fn parse_gpt_entry(raw: &[u8]) -> Option<PartitionEntry> {
+    if raw.len() < 48 || raw[0..16].iter().all(|&b| b == 0) {
+        return None;
+    }
+    let first = le_u64(&raw[32..40]);
+    let last = le_u64(&raw[40..48]);
+    if last < first {
+        return None;
+    }
+    Some(PartitionEntry {
+        start_lba: first,
+        blocks: last - first + 1,
+        kind: 0,
+    })
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    let mut raw = [0u8; 4];
+    raw.copy_from_slice(&bytes[..4]);
+    u32::from_le_bytes(raw)
+}
+
+fn le_u64(bytes: &[u8]) -> u64 {
+    let mut raw = [0u8; 8];
+    raw.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(raw)
+}
@@ -0,0 +1,183 @@
+#![allow(dead_code)]
+
+//! Anonymous in-kernel pipes. Each pipe is a fixed ring buffer with a read
+//! end and a write end, both exposed as char devices so they slot into the
+//! fd table. Readers sleep while the buffer is empty and writers while it is
+//! full; both wake on `WaitChannel::Token` keyed by the pipe's address.
+//! Pipes are leaked like tmpfs nodes, so the `&'static` ends stay valid.
+
+use alloc::boxed::Box;
+
+use crate::drivers::{CharDevice, Driver, DriverError, DriverKind, ATOMIC_WRITE_MAX};
+use crate::sync::condvar::CondVar;
+use crate::sync::spinlock::SpinLock;
+
+/// At least `ATOMIC_WRITE_MAX`, or an atomic write could never fit.
+pub const PIPE_CAPACITY: usize = ATOMIC_WRITE_MAX;
+
+struct Ring {
+    data: [u8; PIPE_CAPACITY],
+    head: usize,
+    len: usize,
+}
+
+impl Ring {
+    const fn new() -> Self {
+        Self {
+            data: [0; PIPE_CAPACITY],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn pop(&mut self, out: &mut [u8]) -> usize {
+        let count = out.len().min(self.len);
+        for byte in out[..count].iter_mut() {
+            *byte = self.data[self.head];
+            self.head = (self.head + 1) % PIPE_CAPACITY;
+        }
+        self.len -= count;
+        count
+    }
+
+    fn push(&mut self, input: &[u8]) -> usize {
+        let count = input.len().min(PIPE_CAPACITY - self.len);
+        for byte in &input[..count] {
+            self.data[(self.head + self.len) % PIPE_CAPACITY] = *byte;
+            self.len += 1;
+        }
+        count
+    }
+}
+
+pub struct Pipe {
+    ring: SpinLock<Ring>,
+}
+
+impl Pipe {
+    fn condvar(&self) -> CondVar {
+        CondVar::for_object(self)
+    }
+
+    fn readable(&self) -> bool {
+        self.ring.lock().len > 0
+    }
+
+    fn writable(&self) -> bool {
+        self.ring.lock().len < PIPE_CAPACITY
+    }
+}
+
+pub struct PipeReader {
+    pipe: &'static Pipe,
+}
+
+pub struct PipeWriter {
+    pipe: &'static Pipe,
+}
+
+impl Driver for PipeReader {
+    fn name(&self) -> &'static str {
+        "pipe"
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Char
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+impl Driver for PipeWriter {
+    fn name(&self) -> &'static str {
+        "pipe"
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Char
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+impl CharDevice for PipeReader {
+    /// Block until at least one byte is buffered, then take as many as fit.
+    fn read(&self, buf: &mut [u8]) -> Result<usize, DriverError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let pipe = self.pipe;
+        let mut ring = pipe.ring.lock();
+        loop {
+            let count = ring.pop(buf);
+            if count > 0 {
+                drop(ring);
+                pipe.condvar().notify_all();
+                return Ok(count);
+            }
+            ring = pipe.condvar().wait_with(ring).map_err(|_| DriverError::IoError)?;
+        }
+    }
+
+    fn write(&self, _buf: &[u8]) -> Result<usize, DriverError> {
+        Err(DriverError::Unsupported)
+    }
+
+    fn can_read(&self) -> bool {
+        self.pipe.readable()
+    }
+
+    fn can_write(&self) -> bool {
+        false
+    }
+}
+
+impl CharDevice for PipeWriter {
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, DriverError> {
+        Err(DriverError::Unsupported)
+    }
+
+    /// Block until there is room, then buffer as much as fits. A write of
+    /// up to `ATOMIC_WRITE_MAX` bytes waits until all of it fits, so it is
+    /// never interleaved with another writer's; longer writes take what
+    /// room there is and may come back short.
+    fn write(&self, buf: &[u8]) -> Result<usize, DriverError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let needed = if buf.len() <= ATOMIC_WRITE_MAX { buf.len() } else { 1 };
+        let pipe = self.pipe;
+        let mut ring = pipe.ring.lock();
+        loop {
+            let count = if PIPE_CAPACITY - ring.len >= needed { ring.push(buf) } else { 0 };
+            if count > 0 {
+                drop(ring);
+                pipe.condvar().notify_all();
+                return Ok(count);
+            }
+            ring = pipe.condvar().wait_with(ring).map_err(|_| DriverError::IoError)?;
+        }
+    }
+
+    fn can_read(&self) -> bool {
+        false
+    }
+
+    fn can_write(&self) -> bool {
+        self.pipe.writable()
+    }
+}
+
+/// Allocate a new pipe and return its read and write ends.
+pub fn create() -> (&'static dyn CharDevice, &'static dyn CharDevice) {
+    let pipe: &'static Pipe = Box::leak(Box::new(Pipe {
+        ring: SpinLock::new(Ring::new()),
+    }));
+    let reader: &'static PipeReader = Box::leak(Box::new(PipeReader { pipe }));
+    let writer: &'static PipeWriter = Box::leak(Box::new(PipeWriter { pipe }));
+    (reader, writer)
+}
@@ -0,0 +1,67 @@
+#![allow(dead_code)]
+
+//! Waiting on a device register against a deadline. Between reads the
+//! caller's `PollClock` pauses for a delay that starts at zero and doubles
+//! up to a cap, so a slow device costs a few yields and short sleeps, not a
+//! core spinning flat out. The clock is a trait so the kernel can back it
+//! with the timer tick and the scheduler while the host tests use `std`.
+
+/// First non-zero pause, in milliseconds.
+pub const BACKOFF_START_MS: u64 = 1;
+/// Longest pause between two reads.
+pub const BACKOFF_CAP_MS: u64 = 8;
+
+pub trait PollClock {
+    /// Milliseconds on a clock that never goes backwards.
+    fn now_ms(&self) -> u64;
+
+    /// Give the CPU away for about `ms` milliseconds. Zero means yield once
+    /// and come straight back.
+    fn pause(&self, ms: u64);
+}
+
+/// Pause lengths for successive retries: 0, 1, 2, 4, ... up to `cap_ms`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Backoff {
+    next_ms: u64,
+    cap_ms: u64,
+}
+
+impl Backoff {
+    pub const fn new(cap_ms: u64) -> Self {
+        Self { next_ms: 0, cap_ms }
+    }
+
+    pub fn next_delay(&mut self) -> u64 {
+        let delay = self.next_ms;
+        self.next_ms = if delay == 0 {
+            BACKOFF_START_MS.min(self.cap_ms)
+        } else {
+            delay.saturating_mul(2).min(self.cap_ms)
+        };
+        delay
+    }
+}
+
+/// Call `poll` until it gives `Some`, backing off between attempts. `None`
+/// once `timeout_ms` has passed without an answer; `poll` always runs at
+/// least once, and once more after the last pause, so a device that comes
+/// ready just before the deadline is still seen.
+pub fn poll_until<C, T, F>(clock: &C, timeout_ms: u64, mut poll: F) -> Option<T>
+where
+    C: PollClock + ?Sized,
+    F: FnMut() -> Option<T>,
+{
+    let deadline = clock.now_ms().saturating_add(timeout_ms);
+    let mut backoff = Backoff::new(BACKOFF_CAP_MS);
+    loop {
+        if let Some(value) = poll() {
+            return Some(value);
+        }
+        let now = clock.now_ms();
+        if now >= deadline {
+            return None;
+        }
+        clock.pause(backoff.next_delay().min(deadline - now));
+    }
+}
@@ -0,0 +1,84 @@
+#![allow(dead_code)]
+
+//! A block device over a run of memory, such as a boot module the
+//! bootloader loaded as an initrd. Reads and writes are plain copies under
+//! a lock; nothing is flushed anywhere, so writes last until reboot.
+
+use alloc::boxed::Box;
+
+use crate::arch::x86_64::kernel::mmu;
+use crate::boot::multiboot::BootModule;
+use crate::drivers::{check_block_range, register_block, BlockDevice, Driver, DriverError, DriverKind};
+use crate::sync::spinlock::SpinLock;
+
+pub const BLOCK_SIZE: usize = 512;
+
+pub struct RamDisk {
+    name: &'static str,
+    bytes: SpinLock<&'static mut [u8]>,
+    blocks: u64,
+}
+
+impl RamDisk {
+    /// Whole blocks of `bytes`; a partial block at the end is left out.
+    pub fn new(name: &'static str, bytes: &'static mut [u8]) -> Self {
+        let blocks = (bytes.len() / BLOCK_SIZE) as u64;
+        Self {
+            name,
+            bytes: SpinLock::new(bytes),
+            blocks,
+        }
+    }
+}
+
+impl Driver for RamDisk {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Block
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), DriverError> {
+        check_block_range(self, lba, buf.len())?;
+        let offset = lba as usize * BLOCK_SIZE;
+        buf.copy_from_slice(&self.bytes.lock()[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), DriverError> {
+        check_block_range(self, lba, buf.len())?;
+        let offset = lba as usize * BLOCK_SIZE;
+        self.bytes.lock()[offset..offset + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn capacity_sectors(&self) -> u64 {
+        self.blocks
+    }
+}
+
+/// Register `module` as a block device called `name`, reading it through
+/// the physical-memory alias. The module's frames must already be kept
+/// from the frame allocator, as `mem::phys` does for every boot module.
+pub fn register_module(name: &'static str, module: &BootModule<'_>) -> Result<&'static RamDisk, DriverError> {
+    if (module.len() as usize) < BLOCK_SIZE {
+        return Err(DriverError::Unsupported);
+    }
+    let bytes =
+        unsafe { core::slice::from_raw_parts_mut(mmu::phys_to_virt(module.start) as *mut u8, module.len() as usize) };
+    let disk: &'static RamDisk = Box::leak(Box::new(RamDisk::new(name, bytes)));
+    register_block(disk)?;
+    Ok(disk)
+}
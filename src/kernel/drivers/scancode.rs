@@ -0,0 +1,359 @@
+#![allow(dead_code)]
+
+//! PS/2 scan code set 1 decoder. Turns the raw bytes read from port 0x60 into
+//! the bytes a terminal would deliver: ASCII for printable keys and xterm
+//! escape sequences for cursor, editing and function keys.
+
+const EXTENDED_PREFIX: u8 = 0xE0;
+const PAUSE_PREFIX: u8 = 0xE1;
+const RELEASE_BIT: u8 = 0x80;
+const CTRL: u8 = 0x1D;
+
+const MAX_SEQUENCE: usize = 8;
+
+const BIT_SHIFT: u32 = 1 << 0;
+const BIT_CTRL: u32 = 1 << 1;
+const BIT_CAPS_LOCK: u32 = 1 << 2;
+const BIT_EXTENDED: u32 = 1 << 3;
+const SKIP_SHIFT: u32 = 8;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum KeyCode {
+    Up,
+    Down,
+    Right,
+    Left,
+    Home,
+    End,
+    Insert,
+    Delete,
+    PageUp,
+    PageDown,
+    /// Function key, 1 through 12.
+    F(u8),
+}
+
+impl KeyCode {
+    /// The sequence xterm sends for this key.
+    pub fn sequence(self) -> &'static [u8] {
+        match self {
+            KeyCode::Up => b"\x1b[A",
+            KeyCode::Down => b"\x1b[B",
+            KeyCode::Right => b"\x1b[C",
+            KeyCode::Left => b"\x1b[D",
+            KeyCode::Home => b"\x1b[H",
+            KeyCode::End => b"\x1b[F",
+            KeyCode::Insert => b"\x1b[2~",
+            KeyCode::Delete => b"\x1b[3~",
+            KeyCode::PageUp => b"\x1b[5~",
+            KeyCode::PageDown => b"\x1b[6~",
+            KeyCode::F(1) => b"\x1bOP",
+            KeyCode::F(2) => b"\x1bOQ",
+            KeyCode::F(3) => b"\x1bOR",
+            KeyCode::F(4) => b"\x1bOS",
+            KeyCode::F(5) => b"\x1b[15~",
+            KeyCode::F(6) => b"\x1b[17~",
+            KeyCode::F(7) => b"\x1b[18~",
+            KeyCode::F(8) => b"\x1b[19~",
+            KeyCode::F(9) => b"\x1b[20~",
+            KeyCode::F(10) => b"\x1b[21~",
+            KeyCode::F(11) => b"\x1b[23~",
+            KeyCode::F(12) => b"\x1b[24~",
+            KeyCode::F(_) => b"",
+        }
+    }
+}
+
+/// Bytes produced by a single scancode; empty for modifiers, releases and
+/// prefixes.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KeyBytes {
+    bytes: [u8; MAX_SEQUENCE],
+    len: usize,
+}
+
+impl KeyBytes {
+    const fn empty() -> Self {
+        Self {
+            bytes: [0; MAX_SEQUENCE],
+            len: 0,
+        }
+    }
+
+    fn byte(byte: u8) -> Self {
+        let mut out = Self::empty();
+        out.bytes[0] = byte;
+        out.len = 1;
+        out
+    }
+
+    fn sequence(seq: &[u8]) -> Self {
+        let mut out = Self::empty();
+        let len = seq.len().min(MAX_SEQUENCE);
+        out.bytes[..len].copy_from_slice(&seq[..len]);
+        out.len = len;
+        out
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+pub struct ScancodeDecoder {
+    shift: bool,
+    ctrl: bool,
+    caps_lock: bool,
+    extended: bool,
+    skip: u8,
+}
+
+impl ScancodeDecoder {
+    pub const fn new() -> Self {
+        Self {
+            shift: false,
+            ctrl: false,
+            caps_lock: false,
+            extended: false,
+            skip: 0,
+        }
+    }
+
+    /// Pack the modifier and prefix state into one word, so it can sit in
+    /// an atomic between scancodes rather than behind a lock.
+    pub const fn to_bits(&self) -> u32 {
+        let mut bits = (self.skip as u32) << SKIP_SHIFT;
+        if self.shift {
+            bits |= BIT_SHIFT;
+        }
+        if self.ctrl {
+            bits |= BIT_CTRL;
+        }
+        if self.caps_lock {
+            bits |= BIT_CAPS_LOCK;
+        }
+        if self.extended {
+            bits |= BIT_EXTENDED;
+        }
+        bits
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self {
+            shift: bits & BIT_SHIFT != 0,
+            ctrl: bits & BIT_CTRL != 0,
+            caps_lock: bits & BIT_CAPS_LOCK != 0,
+            extended: bits & BIT_EXTENDED != 0,
+            skip: (bits >> SKIP_SHIFT) as u8,
+        }
+    }
+
+    pub fn feed(&mut self, scancode: u8) -> KeyBytes {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return KeyBytes::empty();
+        }
+
+        match scancode {
+            EXTENDED_PREFIX => {
+                self.extended = true;
+                return KeyBytes::empty();
+            }
+            PAUSE_PREFIX => {
+                // Pause/Break sends E1 1D 45 E1 9D C5 and has no release.
+                self.skip = 2;
+                return KeyBytes::empty();
+            }
+            _ => {}
+        }
+
+        let extended = core::mem::replace(&mut self.extended, false);
+        let released = scancode & RELEASE_BIT != 0;
+        let code = scancode & !RELEASE_BIT;
+
+        if extended {
+            // Right ctrl is E0 1D; treat it like the left one.
+            if code == CTRL {
+                self.ctrl = !released;
+                return KeyBytes::empty();
+            }
+            if released {
+                return KeyBytes::empty();
+            }
+            return match extended_key(code) {
+                Some(Extended::Key(key)) => KeyBytes::sequence(key.sequence()),
+                Some(Extended::Byte(byte)) => KeyBytes::byte(byte),
+                None => KeyBytes::empty(),
+            };
+        }
+
+        if released {
+            match code {
+                0x2A | 0x36 => self.shift = false,
+                CTRL => self.ctrl = false,
+                _ => {}
+            }
+            return KeyBytes::empty();
+        }
+
+        if let Some(key) = function_key(code) {
+            return KeyBytes::sequence(key.sequence());
+        }
+
+        match self.translate(code) {
+            Some(byte) => KeyBytes::byte(byte),
+            None => KeyBytes::empty(),
+        }
+    }
+
+    fn translate(&mut self, scancode: u8) -> Option<u8> {
+        match scancode {
+            0x2A | 0x36 => {
+                self.shift = true;
+                None
+            }
+            CTRL => {
+                self.ctrl = true;
+                None
+            }
+            0x3A => {
+                self.caps_lock = !self.caps_lock;
+                None
+            }
+            0x01 => Some(0x1B), // escape
+            0x1C => Some(b'\n'),
+            0x0E => Some(0x08), // backspace
+            0x0F => Some(b'\t'),
+            0x39 => Some(b' '),
+            0x10..=0x19 | 0x1E..=0x26 | 0x2C..=0x32 => {
+                let letter = map_letter(scancode, self.shift, self.caps_lock)?;
+                if self.ctrl {
+                    // Ctrl-A = 0x01 ... Ctrl-Z = 0x1A, regardless of case.
+                    Some(letter & 0x1F)
+                } else {
+                    Some(letter)
+                }
+            }
+            _ => map_symbol(scancode, self.shift),
+        }
+    }
+}
+
+impl Default for ScancodeDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Extended {
+    Key(KeyCode),
+    Byte(u8),
+}
+
+fn extended_key(scancode: u8) -> Option<Extended> {
+    let key = match scancode {
+        0x48 => KeyCode::Up,
+        0x50 => KeyCode::Down,
+        0x4D => KeyCode::Right,
+        0x4B => KeyCode::Left,
+        0x47 => KeyCode::Home,
+        0x4F => KeyCode::End,
+        0x52 => KeyCode::Insert,
+        0x53 => KeyCode::Delete,
+        0x49 => KeyCode::PageUp,
+        0x51 => KeyCode::PageDown,
+        0x1C => return Some(Extended::Byte(b'\n')), // keypad enter
+        0x35 => return Some(Extended::Byte(b'/')),  // keypad slash
+        // E0 2A / E0 36 are the "fake shift" codes some keys emit; ignore
+        // them along with right alt and the Windows keys.
+        _ => return None,
+    };
+    Some(Extended::Key(key))
+}
+
+fn function_key(scancode: u8) -> Option<KeyCode> {
+    match scancode {
+        0x3B..=0x44 => Some(KeyCode::F(scancode - 0x3B + 1)),
+        0x57 => Some(KeyCode::F(11)),
+        0x58 => Some(KeyCode::F(12)),
+        _ => None,
+    }
+}
+
+fn map_letter(scancode: u8, shift: bool, caps: bool) -> Option<u8> {
+    let letter = match scancode {
+        0x10 => b'q',
+        0x11 => b'w',
+        0x12 => b'e',
+        0x13 => b'r',
+        0x14 => b't',
+        0x15 => b'y',
+        0x16 => b'u',
+        0x17 => b'i',
+        0x18 => b'o',
+        0x19 => b'p',
+        0x1E => b'a',
+        0x1F => b's',
+        0x20 => b'd',
+        0x21 => b'f',
+        0x22 => b'g',
+        0x23 => b'h',
+        0x24 => b'j',
+        0x25 => b'k',
+        0x26 => b'l',
+        0x2C => b'z',
+        0x2D => b'x',
+        0x2E => b'c',
+        0x2F => b'v',
+        0x30 => b'b',
+        0x31 => b'n',
+        0x32 => b'm',
+        _ => return None,
+    };
+
+    let use_shift = shift ^ caps;
+    let ch = if use_shift {
+        letter.to_ascii_uppercase()
+    } else {
+        letter
+    };
+
+    Some(ch)
+}
+
+fn map_symbol(scancode: u8, shift: bool) -> Option<u8> {
+    let byte = match scancode {
+        0x02 => if shift { b'!' } else { b'1' },
+        0x03 => if shift { b'@' } else { b'2' },
+        0x04 => if shift { b'#' } else { b'3' },
+        0x05 => if shift { b'$' } else { b'4' },
+        0x06 => if shift { b'%' } else { b'5' },
+        0x07 => if shift { b'^' } else { b'6' },
+        0x08 => if shift { b'&' } else { b'7' },
+        0x09 => if shift { b'*' } else { b'8' },
+        0x0A => if shift { b'(' } else { b'9' },
+        0x0B => if shift { b')' } else { b'0' },
+        0x0C => if shift { b'_' } else { b'-' },
+        0x0D => if shift { b'+' } else { b'=' },
+        0x1A => if shift { b'{' } else { b'[' },
+        0x1B => if shift { b'}' } else { b']' },
+        0x27 => if shift { b':' } else { b';' },
+        0x28 => if shift { b'"' } else { b'\'' },
+        0x29 => if shift { b'~' } else { b'`' },
+        0x2B => if shift { b'|' } else { b'\\' },
+        0x33 => if shift { b'<' } else { b',' },
+        0x34 => if shift { b'>' } else { b'.' },
+        0x35 => if shift { b'?' } else { b'/' },
+        _ => 0,
+    };
+
+    if byte == 0 {
+        None
+    } else {
+        Some(byte)
+    }
+}
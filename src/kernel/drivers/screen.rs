@@ -0,0 +1,111 @@
+#![allow(dead_code)]
+
+//! Text-mode screen layout kept in ordinary memory. Writes, wrapping and
+//! scrolling all happen on this shadow grid; the driver then copies only the
+//! rows that changed to video memory and moves the cursor once. Cells use
+//! the VGA encoding: character in the low byte, attribute in the high byte.
+
+pub const fn cell(byte: u8, attr: u8) -> u16 {
+    ((attr as u16) << 8) | byte as u16
+}
+
+const TAB_WIDTH: usize = 8;
+
+pub struct Screen<const W: usize, const H: usize> {
+    rows: [[u16; W]; H],
+    pub row: usize,
+    pub col: usize,
+    pub attr: u8,
+    /// First and last row touched since the last `take_dirty`.
+    dirty: Option<(usize, usize)>,
+}
+
+impl<const W: usize, const H: usize> Screen<W, H> {
+    pub const fn new(attr: u8) -> Self {
+        Self {
+            rows: [[cell(b' ', attr); W]; H],
+            row: 0,
+            col: 0,
+            attr,
+            dirty: None,
+        }
+    }
+
+    pub fn row_cells(&self, row: usize) -> &[u16; W] {
+        &self.rows[row]
+    }
+
+    /// Blank every cell with the current attribute and home the cursor.
+    pub fn clear(&mut self) {
+        let blank = cell(b' ', self.attr);
+        for row in self.rows.iter_mut() {
+            row.fill(blank);
+        }
+        self.row = 0;
+        self.col = 0;
+        self.mark(0, H - 1);
+    }
+
+    /// Lay out `bytes` as plain text: `\n` starts a line, `\r` returns to
+    /// column 0, `\t` advances to the next multiple of eight, and everything
+    /// else is printed, wrapping at the right edge. Rows pushed off the top
+    /// are passed to `scrolled`, oldest first.
+    pub fn write<F: FnMut(&[u16; W])>(&mut self, bytes: &[u8], mut scrolled: F) {
+        for &byte in bytes {
+            self.put(byte, &mut scrolled);
+        }
+    }
+
+    pub fn put<F: FnMut(&[u16; W])>(&mut self, byte: u8, scrolled: &mut F) {
+        match byte {
+            b'\n' => self.new_line(scrolled),
+            b'\r' => self.col = 0,
+            b'\t' => {
+                let next_tab = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                if next_tab >= W {
+                    self.new_line(scrolled);
+                } else {
+                    self.col = next_tab;
+                }
+            }
+            byte => {
+                if self.col >= W {
+                    self.new_line(scrolled);
+                }
+                self.rows[self.row][self.col] = cell(byte, self.attr);
+                self.mark(self.row, self.row);
+                self.col += 1;
+            }
+        }
+    }
+
+    pub fn new_line<F: FnMut(&[u16; W])>(&mut self, scrolled: &mut F) {
+        self.col = 0;
+        self.row += 1;
+        if self.row >= H {
+            scrolled(&self.rows[0]);
+            self.rows.copy_within(1.., 0);
+            self.rows[H - 1] = [cell(b' ', self.attr); W];
+            self.row = H - 1;
+            self.mark(0, H - 1);
+        }
+    }
+
+    /// Place the cursor, clamped to the grid.
+    pub fn move_to(&mut self, row: usize, col: usize) {
+        self.row = row.min(H - 1);
+        self.col = col.min(W - 1);
+    }
+
+    /// Rows changed since the last call, as an inclusive range.
+    pub fn take_dirty(&mut self) -> Option<(usize, usize)> {
+        self.dirty.take()
+    }
+
+    fn mark(&mut self, first: usize, last: usize) {
+        self.dirty = Some(match self.dirty {
+            Some((lo, hi)) => (lo.min(first), hi.max(last)),
+            None => (first, last),
+        });
+    }
+}
@@ -0,0 +1,98 @@
+//! COM1 as a char device, `/dev/serial`. Writes go out of the port as
+//! `klog` does; reads take bytes the IRQ4 handler has buffered, with no echo
+//! and no line editing, so a terminal on the other end of the wire can
+//! drive the kernel.
+
+use crate::drivers::{CharDevice, Driver, DriverError, DriverKind};
+use crate::process::{self, WaitChannel};
+
+#[cfg(target_arch = "x86_64")]
+use crate::arch::x86_64::drivers::serial as arch;
+#[cfg(target_arch = "x86_64")]
+pub use crate::arch::x86_64::drivers::serial::SerialStats;
+
+#[cfg(not(target_arch = "x86_64"))]
+compile_error!("Serial driver is only implemented for x86_64");
+
+pub struct Serial;
+
+static SERIAL: Serial = Serial;
+
+impl Serial {
+    pub fn instance() -> &'static Serial {
+        &SERIAL
+    }
+}
+
+impl Driver for Serial {
+    fn name(&self) -> &'static str {
+        "serial"
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Char
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        arch::enable_receive();
+        Ok(())
+    }
+}
+
+impl CharDevice for Serial {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, DriverError> {
+        read_blocking(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, DriverError> {
+        for &byte in buf {
+            arch::write_byte(byte);
+        }
+        Ok(buf.len())
+    }
+
+    fn can_read(&self) -> bool {
+        arch::has_input()
+    }
+}
+
+/// Take whatever bytes have been received without waiting. Returns 0 when
+/// there is nothing yet.
+pub fn try_read(buf: &mut [u8]) -> usize {
+    arch::read(buf)
+}
+
+pub fn has_input() -> bool {
+    arch::has_input()
+}
+
+/// Sleep on `WaitChannel::SerialInput` until a byte arrives, then read.
+/// Only an empty `buf` returns 0.
+pub fn read_blocking(buf: &mut [u8]) -> Result<usize, DriverError> {
+    if buf.is_empty() {
+        return Ok(0);
+    }
+
+    loop {
+        let count = try_read(buf);
+        if count > 0 {
+            return Ok(count);
+        }
+        // Recheck once blocked, for a byte that lands before the block.
+        process::block_current_then(WaitChannel::SerialInput, || {
+            if arch::has_input() {
+                process::wake_channel(WaitChannel::SerialInput);
+            }
+        })
+        .map_err(|_| DriverError::IoError)?;
+    }
+}
+
+/// Queue depth and how many received bytes have been dropped since boot.
+pub fn stats() -> SerialStats {
+    arch::stats()
+}
+
+pub fn driver() -> &'static dyn CharDevice {
+    Serial::instance()
+}
@@ -0,0 +1,245 @@
+//! The terminal: keyboard bytes in, console bytes out, with echo and line
+//! editing in between. `/dev/tty` is this device, and so is every new
+//! process's stdin. In canonical mode (the default) typed bytes go through a
+//! `LineDiscipline` and readers get whole lines; in raw mode they get bytes
+//! as typed. Echo is a separate switch and applies to both.
+//!
+//! The terminal belongs to at most one session: the first session leader to
+//! open `/dev/tty` makes it that session's controlling terminal, with the
+//! leader's group in the foreground. A read from another group of that
+//! session fails with `IoError` instead of taking the foreground's input.
+//! Processes outside the session read it as if nobody owned it. When the
+//! session leader exits the terminal is free again.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::drivers::line_discipline::LineDiscipline;
+use crate::drivers::{console, ioctl, keyboard, CharDevice, Driver, DriverError, DriverKind};
+use crate::process::{self, Pid};
+use crate::sync::spinlock::SpinLock;
+
+pub struct Tty;
+
+static TTY: Tty = Tty;
+static CANONICAL: AtomicBool = AtomicBool::new(true);
+static ECHO: AtomicBool = AtomicBool::new(true);
+static LINE: SpinLock<LineDiscipline> = SpinLock::new(LineDiscipline::new());
+static CONTROL: SpinLock<Control> = SpinLock::new(Control::new());
+
+/// Who the terminal belongs to. Both are `None` until a session leader
+/// opens it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Control {
+    pub session: Option<Pid>,
+    pub foreground: Option<Pid>,
+}
+
+impl Control {
+    const fn new() -> Self {
+        Self {
+            session: None,
+            foreground: None,
+        }
+    }
+}
+
+impl Tty {
+    pub fn instance() -> &'static Tty {
+        &TTY
+    }
+}
+
+impl Driver for Tty {
+    fn name(&self) -> &'static str {
+        "tty"
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Char
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+impl CharDevice for Tty {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, DriverError> {
+        read_blocking(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, DriverError> {
+        console::write_bytes(buf)
+    }
+
+    fn open(&self) {
+        attach_current();
+    }
+
+    fn ioctl(&self, cmd: u32, arg: u64) -> Result<u64, DriverError> {
+        match cmd {
+            ioctl::TTY_SET_CANONICAL => {
+                set_canonical(arg != 0);
+                Ok(0)
+            }
+            ioctl::TTY_GET_CANONICAL => Ok(is_canonical() as u64),
+            ioctl::TTY_SET_ECHO => {
+                set_echo(arg != 0);
+                Ok(0)
+            }
+            ioctl::TTY_GET_ECHO => Ok(is_echo() as u64),
+            ioctl::TTY_SET_FOREGROUND => {
+                if arg > u64::from(Pid::MAX) {
+                    return Err(DriverError::Unsupported);
+                }
+                set_foreground(arg as Pid).map(|()| 0)
+            }
+            ioctl::TTY_GET_FOREGROUND => Ok(control().foreground.map_or(0, u64::from)),
+            _ => Err(DriverError::Unsupported),
+        }
+    }
+
+    /// Raw mode is readable once any byte is queued; canonical mode only
+    /// once a whole line has been typed.
+    fn can_read(&self) -> bool {
+        if is_canonical() {
+            let mut line = LINE.lock();
+            pump(&mut line);
+            line.has_line()
+        } else {
+            keyboard::has_input()
+        }
+    }
+}
+
+/// Read whatever input is ready without waiting: a finished line in
+/// canonical mode, queued bytes in raw mode. Returns 0 when there is
+/// nothing yet, for pollers that must not sleep.
+pub fn try_read(buf: &mut [u8]) -> usize {
+    if is_canonical() {
+        let mut line = LINE.lock();
+        pump(&mut line);
+        return line.read(buf);
+    }
+    let count = keyboard::try_read(buf);
+    if count > 0 && is_echo() {
+        let _ = console::write_bytes(&buf[..count]);
+    }
+    count
+}
+
+/// Sleep until input is ready, then read it. Only an empty `buf` returns 0.
+/// A background group of the owning session gets `IoError`.
+pub fn read_blocking(buf: &mut [u8]) -> Result<usize, DriverError> {
+    if buf.is_empty() {
+        return Ok(0);
+    }
+    if !caller_may_read() {
+        return Err(DriverError::IoError);
+    }
+
+    loop {
+        let count = try_read(buf);
+        if count > 0 {
+            return Ok(count);
+        }
+        keyboard::wait_for_input()?;
+    }
+}
+
+/// Move everything the keyboard has queued through the line discipline,
+/// echoing as it goes when echo is on.
+fn pump(line: &mut LineDiscipline) {
+    let echo = is_echo();
+    let mut byte = [0u8; 1];
+    while keyboard::try_read(&mut byte) > 0 {
+        line.input(byte[0], |bytes| {
+            if echo {
+                let _ = console::write_bytes(bytes);
+            }
+        });
+    }
+}
+
+/// Switch between raw reads (every byte as it arrives) and canonical reads
+/// (edited, delivered a line at a time). Leaving canonical mode drops any
+/// partially typed line.
+pub fn set_canonical(enabled: bool) {
+    let was = CANONICAL.swap(enabled, Ordering::AcqRel);
+    if was && !enabled {
+        LINE.lock().clear();
+    }
+}
+
+pub fn is_canonical() -> bool {
+    CANONICAL.load(Ordering::Acquire)
+}
+
+pub fn set_echo(enabled: bool) {
+    ECHO.store(enabled, Ordering::Release);
+}
+
+pub fn is_echo() -> bool {
+    ECHO.load(Ordering::Acquire)
+}
+
+pub fn control() -> Control {
+    *CONTROL.lock()
+}
+
+/// Make the calling process's session the owner if it is a session leader
+/// and nobody owns the terminal yet. Anyone else opens it without taking it.
+fn attach_current() {
+    let Some(pid) = process::current_pid() else {
+        return;
+    };
+    let (Ok(sid), Ok(pgid)) = (process::get_sid(pid), process::get_pgid(pid)) else {
+        return;
+    };
+    let mut control = CONTROL.lock();
+    if sid == pid && control.session.is_none() {
+        *control = Control {
+            session: Some(sid),
+            foreground: Some(pgid),
+        };
+    }
+}
+
+/// Hand the foreground to group `pgid`. Only a member of the owning session
+/// may, and only to a live group of that session.
+pub fn set_foreground(pgid: Pid) -> Result<(), DriverError> {
+    let sid = process::get_sid(0).map_err(|_| DriverError::Unsupported)?;
+    if control().session != Some(sid) || !process::group_in_session(pgid, sid) {
+        return Err(DriverError::Unsupported);
+    }
+    let mut control = CONTROL.lock();
+    // The session may have ended while the table was being checked.
+    if control.session != Some(sid) {
+        return Err(DriverError::Unsupported);
+    }
+    control.foreground = Some(pgid);
+    Ok(())
+}
+
+/// Free the terminal if `sid` owned it. Called when a session leader exits.
+pub fn session_ended(sid: Pid) {
+    let mut control = CONTROL.lock();
+    if control.session == Some(sid) {
+        *control = Control::new();
+    }
+}
+
+fn caller_may_read() -> bool {
+    let control = control();
+    let Some(session) = control.session else {
+        return true;
+    };
+    match (process::get_sid(0), process::get_pgid(0)) {
+        (Ok(sid), Ok(pgid)) => sid != session || control.foreground == Some(pgid),
+        _ => true,
+    }
+}
+
+pub fn driver() -> &'static dyn CharDevice {
+    Tty::instance()
+}
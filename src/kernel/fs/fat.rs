@@ -1,9 +1,14 @@
 #![allow(dead_code)]
 
-use crate::drivers::BlockDevice;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::drivers::{self, BlockDevice};
 use crate::klog;
-use crate::sync::spinlock::SpinLock;
-use crate::vfs::{VfsError, VfsFile, VfsResult};
+use super::fat_time::FatTimestamp;
+use crate::sync::mutex::Mutex;
+use crate::vfs::mount::FileSystem;
+use crate::vfs::{attr, mode, VfsDirEntry, VfsError, VfsFile, VfsFileStat, VfsResult};
 
 use crate::mem::heap;
 use core::alloc::Layout;
@@ -20,6 +25,72 @@ pub enum FatError {
     InvalidPath,
     NotFound,
     Io,
+    /// Every slot in the volume table is taken by another name.
+    TooManyVolumes,
+    /// A cluster chain loops, or links to a cluster outside the data region.
+    CorruptChain,
+}
+
+impl From<FatError> for VfsError {
+    fn from(err: FatError) -> Self {
+        match err {
+            FatError::NotMounted | FatError::InvalidPath | FatError::NotFound => VfsError::NotFound,
+            FatError::Io | FatError::CorruptChain => VfsError::Io,
+            FatError::TooManyVolumes => VfsError::NoSpace,
+        }
+    }
+}
+
+/// The boot sector fields later LBA arithmetic depends on.
+struct Bpb {
+    sectors_per_cluster: u8,
+    reserved_sectors: u16,
+    num_fats: u8,
+    sectors_per_fat: u16,
+    root_dir_sectors: u32,
+    /// 0 when neither the 16- nor the 32-bit count is filled in.
+    total_sectors: u32,
+}
+
+impl Bpb {
+    /// Catch a blank or non-FAT boot sector before its values are used as
+    /// divisors and offsets.
+    fn validate(&self) -> Result<(), &'static str> {
+        if self.sectors_per_cluster == 0 || !self.sectors_per_cluster.is_power_of_two() {
+            return Err("sectors per cluster is not a power of two");
+        }
+        if self.reserved_sectors == 0 {
+            return Err("no reserved sectors for the boot sector");
+        }
+        if self.num_fats != 1 && self.num_fats != 2 {
+            return Err("FAT count is not 1 or 2");
+        }
+        if self.sectors_per_fat == 0 {
+            return Err("FAT has no sectors");
+        }
+        let metadata = self.reserved_sectors as u64
+            + self.num_fats as u64 * self.sectors_per_fat as u64
+            + self.root_dir_sectors as u64;
+        if self.total_sectors != 0 && metadata >= self.total_sectors as u64 {
+            return Err("FATs and root directory do not fit in the volume");
+        }
+        Ok(())
+    }
+
+    /// Data clusters the volume can address, numbered from 2: what fits in
+    /// the sectors after the metadata, when the sector count is recorded,
+    /// and never more than the FAT has entries for.
+    fn cluster_count(&self) -> u32 {
+        let by_fat = (self.sectors_per_fat as u32 * SECTOR_SIZE as u32 / 2).saturating_sub(2);
+        if self.total_sectors == 0 {
+            return by_fat;
+        }
+        let metadata = self.reserved_sectors as u32
+            + self.num_fats as u32 * self.sectors_per_fat as u32
+            + self.root_dir_sectors;
+        let by_size = self.total_sectors.saturating_sub(metadata) / self.sectors_per_cluster as u32;
+        by_size.min(by_fat)
+    }
 }
 
 struct FatVolume {
@@ -36,9 +107,24 @@ struct FatVolume {
     root_dir_sectors: u32,
     data_lba: u64,
     bytes_per_cluster: usize,
+    cluster_count: u32,
 }
 
 impl FatVolume {
+    /// Whether both describe the same volume: one device, one start, and
+    /// the same geometry, so either can serve the other's files.
+    fn same_as(&self, other: &FatVolume) -> bool {
+        core::ptr::addr_eq(self.device, other.device)
+            && self.start_lba == other.start_lba
+            && self.bytes_per_sector == other.bytes_per_sector
+            && self.sectors_per_cluster == other.sectors_per_cluster
+            && self.reserved_sectors == other.reserved_sectors
+            && self.num_fats == other.num_fats
+            && self.root_entries == other.root_entries
+            && self.sectors_per_fat == other.sectors_per_fat
+            && self.cluster_count == other.cluster_count
+    }
+
     fn load(device: &'static dyn BlockDevice, start_lba: u64) -> Result<Self, FatError> {
         klog!("[fat] load volume start_lba={} device='{}'\n", start_lba, device.name());
         let mut sector = [0u8; SECTOR_SIZE];
@@ -61,12 +147,29 @@ impl FatVolume {
         let num_fats = sector[16];
         let root_entries = u16::from_le_bytes([sector[17], sector[18]]);
         let sectors_per_fat = u16::from_le_bytes([sector[22], sector[23]]);
+        let total_sectors = match u16::from_le_bytes([sector[19], sector[20]]) {
+            0 => u32::from_le_bytes([sector[32], sector[33], sector[34], sector[35]]),
+            small => small as u32,
+        };
 
         let fat_lba = start_lba + reserved_sectors as u64;
         let root_dir_lba = fat_lba + (num_fats as u64 * sectors_per_fat as u64);
         let root_dir_sectors = ((root_entries as u32 * 32) + (bytes_per_sector as u32 - 1)) / bytes_per_sector as u32;
         let data_lba = root_dir_lba + root_dir_sectors as u64;
 
+        let bpb = Bpb {
+            sectors_per_cluster,
+            reserved_sectors,
+            num_fats,
+            sectors_per_fat,
+            root_dir_sectors,
+            total_sectors,
+        };
+        if let Err(reason) = bpb.validate() {
+            klog!("[fat] rejecting volume: {}\n", reason);
+            return Err(FatError::Io);
+        }
+
         klog!(
             "[fat] bpb bytes_per_sector={} spc={} reserved={} fats={} root_entries={} spf={}\n",
             bytes_per_sector,
@@ -91,56 +194,88 @@ impl FatVolume {
             root_dir_sectors,
             data_lba,
             bytes_per_cluster: bytes_per_sector * sectors_per_cluster as usize,
+            cluster_count: bpb.cluster_count(),
         })
     }
 
-    fn find_root_file(&self, path: &str) -> Result<(u16, u32), FatError> {
-        let short_name = format_short_name(path).ok_or(FatError::InvalidPath)?;
-        klog!("[fat] find_root_file path='{}' short={:02X?}\n", path, short_name);
+    /// Read the raw 32-byte slot `index` of a directory. `dir_cluster == 0`
+    /// addresses the fixed root directory region.
+    fn dir_slot(&self, dir_cluster: u16, index: u64) -> Result<Option<[u8; 32]>, FatError> {
+        let byte_offset = index * 32;
+        let lba = if dir_cluster == 0 {
+            if index >= self.root_entries as u64 {
+                return Ok(None);
+            }
+            self.root_dir_lba + byte_offset / self.bytes_per_sector as u64
+        } else {
+            match self.cluster_for_offset(dir_cluster, byte_offset)? {
+                Some((cluster, within)) => {
+                    self.cluster_to_lba(cluster) + within / self.bytes_per_sector as u64
+                }
+                None => return Ok(None),
+            }
+        };
 
-        let entries_per_sector = self.bytes_per_sector / 32;
-        let mut sector_buffer = [0u8; SECTOR_SIZE];
+        let mut sector = [0u8; SECTOR_SIZE];
+        self.read_sector(lba, &mut sector)?;
+        let offset = (byte_offset % self.bytes_per_sector as u64) as usize;
+        let mut slot = [0u8; 32];
+        slot.copy_from_slice(&sector[offset..offset + 32]);
+        Ok(Some(slot))
+    }
 
-        for sector_index in 0..self.root_dir_sectors {
-            let lba = self.root_dir_lba + sector_index as u64;
-            klog!("[fat] scanning root sector {} lba={}\n", sector_index, lba);
-            self.read_sector(lba, &mut sector_buffer)?;
+    /// Find the next live entry at or after slot `index`, skipping deleted
+    /// slots, long-name fragments, volume labels and the `.`/`..` links.
+    fn next_dir_entry(&self, dir_cluster: u16, mut index: u64) -> Result<Option<(DirEntry, u64)>, FatError> {
+        while let Some(slot) = self.dir_slot(dir_cluster, index)? {
+            index += 1;
+            let first = slot[0];
+            if first == 0x00 {
+                break;
+            }
+            if first == 0xE5 || first == b'.' || slot[11] == 0x0F || slot[11] & 0x08 != 0 {
+                continue;
+            }
+            return Ok(Some((DirEntry::from_slot(&slot), index)));
+        }
+        Ok(None)
+    }
 
-            for entry_index in 0..entries_per_sector {
-                let offset = entry_index * 32;
-                let entry = &sector_buffer[offset..offset + 32];
-                let first = entry[0];
-                if first == 0x00 {
-                    klog!("[fat] directory terminator reached\n");
-                    return Err(FatError::NotFound);
-                }
-                if first == 0xE5 || entry[11] == 0x0F {
-                    continue;
-                }
-                if entry[11] & 0x08 != 0 || entry[11] & 0x10 != 0 {
-                    continue;
-                }
-                if entry[..SHORT_NAME_LEN] != short_name {
-                    continue;
-                }
+    fn find_entry(&self, dir_cluster: u16, component: &str) -> Result<DirEntry, FatError> {
+        let short_name = format_short_name(component).ok_or(FatError::InvalidPath)?;
+        klog!("[fat] find_entry dir={} name='{}' short={:02X?}\n", dir_cluster, component, short_name);
 
-                let start_cluster = u16::from_le_bytes([entry[26], entry[27]]);
-                let size = u32::from_le_bytes([entry[28], entry[29], entry[30], entry[31]]);
+        let mut index = 0;
+        while let Some((entry, next)) = self.next_dir_entry(dir_cluster, index)? {
+            if entry.short_name == short_name {
                 klog!(
-                    "[fat] found entry sector={} index={} cluster={} size={}\n",
-                    sector_index,
-                    entry_index,
-                    start_cluster,
-                    size
+                    "[fat] found entry slot={} cluster={} size={} attr=0x{:02X}\n",
+                    index,
+                    entry.cluster,
+                    entry.size,
+                    entry.attr
                 );
-                return Ok((start_cluster, size));
+                return Ok(entry);
             }
+            index = next;
         }
 
-        klog!("[fat] file '{}' not found in root\n", path);
+        klog!("[fat] '{}' not found in dir {}\n", component, dir_cluster);
         Err(FatError::NotFound)
     }
 
+    /// Walk `path` (relative to the volume root) one component at a time.
+    fn lookup(&self, path: &str) -> Result<DirEntry, FatError> {
+        let mut entry = DirEntry::root();
+        for component in path.split('/').filter(|part| !part.is_empty()) {
+            if !entry.is_dir() {
+                return Err(FatError::NotFound);
+            }
+            entry = self.find_entry(entry.cluster, component)?;
+        }
+        Ok(entry)
+    }
+
     fn read_sector(&self, lba: u64, buffer: &mut [u8; SECTOR_SIZE]) -> Result<(), FatError> {
         self.device
             .read_blocks(lba, buffer)
@@ -156,6 +291,12 @@ impl FatVolume {
         lba
     }
 
+    fn is_data_cluster(&self, cluster: u16) -> bool {
+        cluster >= 2 && (cluster as u32) < self.cluster_count + 2
+    }
+
+    /// The cluster after `cluster` in its chain, or `None` at the end. A
+    /// link to a reserved or out-of-range cluster is `CorruptChain`.
     fn next_cluster(&self, cluster: u16) -> Result<Option<u16>, FatError> {
         let fat_offset = cluster as usize * 2;
         let fat_sector = fat_offset / self.bytes_per_sector;
@@ -178,8 +319,10 @@ impl FatVolume {
 
         if entry >= FAT16_END {
             Ok(None)
-        } else {
+        } else if self.is_data_cluster(entry) {
             Ok(Some(entry))
+        } else {
+            Err(FatError::CorruptChain)
         }
     }
 
@@ -188,9 +331,20 @@ impl FatVolume {
             return Ok(None);
         }
 
+        if !self.is_data_cluster(start_cluster) {
+            return Err(FatError::CorruptChain);
+        }
+
+        // No chain is longer than the volume, so more hops than that means
+        // it loops back on itself.
         let cluster_bytes = self.bytes_per_cluster as u64;
         let mut cluster = start_cluster;
+        let mut hops = 0u32;
         while offset >= cluster_bytes {
+            hops += 1;
+            if hops >= self.cluster_count {
+                return Err(FatError::CorruptChain);
+            }
             match self.next_cluster(cluster)? {
                 Some(next) => {
                     cluster = next;
@@ -255,10 +409,142 @@ impl FatVolume {
     }
 }
 
+#[derive(Copy, Clone)]
+struct DirEntry {
+    short_name: [u8; SHORT_NAME_LEN],
+    attr: u8,
+    cluster: u16,
+    size: u32,
+    created: Option<FatTimestamp>,
+    modified: Option<FatTimestamp>,
+}
+
+impl DirEntry {
+    /// The root directory has no entry of its own, so no timestamps either.
+    fn root() -> Self {
+        Self {
+            short_name: [b' '; SHORT_NAME_LEN],
+            attr: attr::DIRECTORY,
+            cluster: 0,
+            size: 0,
+            created: None,
+            modified: None,
+        }
+    }
+
+    fn from_slot(slot: &[u8; 32]) -> Self {
+        let mut short_name = [0u8; SHORT_NAME_LEN];
+        short_name.copy_from_slice(&slot[..SHORT_NAME_LEN]);
+        Self {
+            short_name,
+            attr: slot[11],
+            cluster: u16::from_le_bytes([slot[26], slot[27]]),
+            size: u32::from_le_bytes([slot[28], slot[29], slot[30], slot[31]]),
+            created: FatTimestamp::decode(
+                u16::from_le_bytes([slot[16], slot[17]]),
+                u16::from_le_bytes([slot[14], slot[15]]),
+            ),
+            modified: FatTimestamp::decode(
+                u16::from_le_bytes([slot[24], slot[25]]),
+                u16::from_le_bytes([slot[22], slot[23]]),
+            ),
+        }
+    }
+
+    /// `(created, modified)` as Unix seconds, 0 for a stamp that is unset.
+    fn unix_times(&self) -> (u64, u64) {
+        let unix = |stamp: Option<FatTimestamp>| stamp.map_or(0, |stamp| stamp.to_unix());
+        (unix(self.created), unix(self.modified))
+    }
+
+    fn is_dir(&self) -> bool {
+        self.attr & attr::DIRECTORY != 0
+    }
+
+    /// Render the 8.3 name as `NAME.EXT`, dropping padding.
+    fn display_name(&self, out: &mut [u8; 12]) -> usize {
+        let base = trim_padding(&self.short_name[..8]);
+        let ext = trim_padding(&self.short_name[8..]);
+        out[..base.len()].copy_from_slice(base);
+        let mut len = base.len();
+        if !ext.is_empty() {
+            out[len] = b'.';
+            len += 1;
+            out[len..len + ext.len()].copy_from_slice(ext);
+            len += ext.len();
+        }
+        len
+    }
+
+    fn to_vfs(self) -> VfsDirEntry {
+        let mut name = [0u8; 12];
+        let len = self.display_name(&mut name);
+        VfsDirEntry::new(&name[..len], self.attr, self.size as u64)
+    }
+}
+
+fn trim_padding(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().rposition(|&b| b != b' ').map_or(0, |pos| pos + 1);
+    &bytes[..end]
+}
+
+pub struct FatDir {
+    volume: &'static FatVolume,
+    start_cluster: u16,
+    times: (u64, u64),
+}
+
+impl VfsFile for FatDir {
+    fn name(&self) -> &'static str {
+        "fat-dir"
+    }
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn flush(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> VfsResult<u64> {
+        Ok(0)
+    }
+
+    fn is_dir(&self) -> bool {
+        true
+    }
+
+    fn stat(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            size: 0,
+            mode: mode::DIR | mode::READ | mode::EXEC,
+            block_size: self.volume.bytes_per_cluster as u32,
+            created: self.times.0,
+            modified: self.times.1,
+            accessed: 0,
+        })
+    }
+
+    fn read_dir(&self, cursor: u64) -> VfsResult<Option<(VfsDirEntry, u64)>> {
+        match self.volume.next_dir_entry(self.start_cluster, cursor) {
+            Ok(Some((entry, next))) => Ok(Some((entry.to_vfs(), next))),
+            Ok(None) => Ok(None),
+            Err(_) => Err(VfsError::Io),
+        }
+    }
+}
+
 pub struct FatFile {
     volume: &'static FatVolume,
     start_cluster: u16,
     size: u32,
+    times: (u64, u64),
+    attr: u8,
 }
 
 impl VfsFile for FatFile {
@@ -315,16 +601,120 @@ impl VfsFile for FatFile {
     fn size(&self) -> VfsResult<u64> {
         Ok(self.size as u64)
     }
+
+    fn attributes(&self) -> u8 {
+        self.attr
+    }
+
+    fn stat(&self) -> VfsResult<VfsFileStat> {
+        // The FAT layer has no write path, so every file is read-only.
+        Ok(VfsFileStat {
+            size: self.size as u64,
+            mode: mode::FILE | mode::READ,
+            block_size: self.volume.bytes_per_cluster as u32,
+            created: self.times.0,
+            modified: self.times.1,
+            accessed: 0,
+        })
+    }
+}
+
+pub const MAX_FAT_VOLUMES: usize = 4;
+pub const MAX_VOLUME_NAME: usize = 16;
+/// Name of the volume mounted at boot. `/bin/<name>` and `/fat/<path>`
+/// paths that do not start with another volume's name resolve against it.
+pub const DEFAULT_VOLUME: &str = "boot";
+
+/// A loaded volume under its mount name. Volumes are leaked on mount so the
+/// files opened on them keep a stable reference even if the name is later
+/// mounted over. Mounting a volume that was loaded before reuses it.
+#[derive(Copy, Clone)]
+struct MountedVolume {
+    name: [u8; MAX_VOLUME_NAME],
+    name_len: usize,
+    volume: &'static FatVolume,
+}
+
+impl MountedVolume {
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+}
+
+struct VolumeTable {
+    slots: [Option<MountedVolume>; MAX_FAT_VOLUMES],
+    /// Every volume leaked so far, mounted or not.
+    loaded: Vec<&'static FatVolume>,
+}
+
+impl VolumeTable {
+    const fn new() -> Self {
+        Self {
+            slots: [None; MAX_FAT_VOLUMES],
+            loaded: Vec::new(),
+        }
+    }
+
+    /// The leaked copy of `volume`, leaking one only if none matches.
+    fn intern(&mut self, volume: FatVolume) -> Result<&'static FatVolume, FatError> {
+        if let Some(&loaded) = self.loaded.iter().find(|loaded| loaded.same_as(&volume)) {
+            return Ok(loaded);
+        }
+        let leaked = leak(volume)?;
+        self.loaded.push(leaked);
+        Ok(leaked)
+    }
+
+    fn find(&self, name: &str) -> Option<&'static FatVolume> {
+        self.slots
+            .iter()
+            .flatten()
+            .find(|mounted| mounted.name() == name)
+            .map(|mounted| mounted.volume)
+    }
+
+    /// Put `volume` under `name`, replacing a volume already mounted there.
+    fn insert(&mut self, name: &str, volume: &'static FatVolume) -> Result<(), FatError> {
+        let mut entry = MountedVolume {
+            name: [0; MAX_VOLUME_NAME],
+            name_len: name.len(),
+            volume,
+        };
+        entry.name[..name.len()].copy_from_slice(name.as_bytes());
+
+        let slot = match self.slots.iter().position(|slot| matches!(slot, Some(mounted) if mounted.name() == name)) {
+            Some(index) => index,
+            None => self
+                .slots
+                .iter()
+                .position(|slot| slot.is_none())
+                .ok_or(FatError::TooManyVolumes)?,
+        };
+        self.slots[slot] = Some(entry);
+        Ok(())
+    }
 }
 
-static FAT_VOLUME: SpinLock<Option<FatVolume>> = SpinLock::new(None);
+fn valid_volume_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= MAX_VOLUME_NAME && !name.contains('/')
+}
+
+// Sleeps rather than spins: lookups go to disk with the lock held.
+static FAT_VOLUMES: Mutex<VolumeTable> = Mutex::new(VolumeTable::new());
 
-pub fn mount(device: &'static dyn BlockDevice, start_lba: u64) -> Result<(), FatError> {
+/// Load the FAT volume starting at `start_lba` on `device` and mount it as
+/// `name`, replacing any volume of that name. The first mount also attaches
+/// the filesystem at `MOUNT_POINT`.
+pub fn mount(name: &str, device: &'static dyn BlockDevice, start_lba: u64) -> Result<(), FatError> {
     klog!(
-        "[fat] mount request device='{}' start_lba={}\n",
+        "[fat] mount request name='{}' device='{}' start_lba={}\n",
+        name,
         device.name(),
         start_lba
     );
+    if !valid_volume_name(name) {
+        return Err(FatError::InvalidPath);
+    }
 
     let volume = match FatVolume::load(device, start_lba) {
         Ok(volume) => volume,
@@ -333,39 +723,94 @@ pub fn mount(device: &'static dyn BlockDevice, start_lba: u64) -> Result<(), Fat
             return Err(err);
         }
     };
-    let mut slot = FAT_VOLUME.lock();
-    *slot = Some(volume);
-    klog!("[fat] mounted at LBA {}\n", start_lba);
+    {
+        let mut volumes = FAT_VOLUMES.lock();
+        let volume = volumes.intern(volume)?;
+        volumes.insert(name, volume)?;
+    }
+    if let Err(err) = crate::vfs::mount::mount(MOUNT_POINT, &FAT_FS) {
+        klog!("[fat] could not attach at {}: {:?}\n", MOUNT_POINT, err);
+    }
+    klog!("[fat] mounted '{}' at LBA {}\n", name, start_lba);
     Ok(())
 }
 
-pub fn open_file(path: &str) -> Result<&'static dyn VfsFile, FatError> {
-    let trimmed = path.trim_matches('/');
-    if trimmed.is_empty() {
-        return Err(FatError::InvalidPath);
+/// Mount the volume on a registered block device, e.g. a partition
+/// registered by `drivers::mbr::scan`.
+pub fn mount_named(name: &str, device_name: &str, start_lba: u64) -> Result<(), FatError> {
+    let device = drivers::block_device_by_name(device_name).ok_or(FatError::NotFound)?;
+    mount(name, device, start_lba)
+}
+
+pub fn is_mounted(name: &str) -> bool {
+    FAT_VOLUMES.lock().find(name).is_some()
+}
+
+/// How many volumes have been leaked since boot, mounted or not.
+#[cfg(kernel_test)]
+pub fn loaded_volumes() -> usize {
+    FAT_VOLUMES.lock().loaded.len()
+}
+
+pub const MOUNT_POINT: &str = "/fat";
+
+/// Mount-table view of every volume; `mount` attaches it at `MOUNT_POINT`.
+/// `<volume>/<path>` opens `path` on that volume and anything else opens
+/// on `DEFAULT_VOLUME`.
+pub struct FatFs;
+
+static FAT_FS: FatFs = FatFs;
+
+impl FileSystem for FatFs {
+    fn open(&'static self, path: &str) -> VfsResult<&'static dyn VfsFile> {
+        let trimmed = path.trim_start_matches('/');
+        let (first, rest) = match trimmed.find('/') {
+            Some(split) => (&trimmed[..split], &trimmed[split + 1..]),
+            None => (trimmed, ""),
+        };
+        if !first.is_empty() && first != DEFAULT_VOLUME && is_mounted(first) {
+            return open_file(first, rest).map_err(VfsError::from);
+        }
+        open_file(DEFAULT_VOLUME, path).map_err(VfsError::from)
     }
+}
 
-    klog!("[fat] open_file path='{}' trimmed='{}'\n", path, trimmed);
+/// Open a file or directory on volume `name` by path relative to its root.
+/// An empty path (or `/`) opens the root directory.
+pub fn open_file(name: &str, path: &str) -> Result<&'static dyn VfsFile, FatError> {
+    let trimmed = path.trim_matches('/');
+    klog!("[fat] open_file volume='{}' path='{}' trimmed='{}'\n", name, path, trimmed);
 
-    let (volume_ptr, entry) = {
-        let guard = FAT_VOLUME.lock();
-        let volume = guard.as_ref().ok_or(FatError::NotMounted)?;
+    let (volume_ref, entry) = {
+        let table = FAT_VOLUMES.lock();
+        let volume = table.find(name).ok_or(FatError::NotMounted)?;
         klog!("[fat] open_file volume OK data_lba={} root_dir_sectors={}\n", volume.data_lba, volume.root_dir_sectors);
-        let info = match volume.find_root_file(trimmed) {
-            Ok(info) => info,
+        let entry = match volume.lookup(trimmed) {
+            Ok(entry) => entry,
             Err(err) => {
-                klog!("[fat] open_file find_root_file error {:?}\n", err);
+                klog!("[fat] open_file lookup error {:?}\n", err);
                 return Err(err);
             }
         };
-        (volume as *const FatVolume, info)
+        (volume, entry)
     };
 
-    let volume_ref = unsafe { &*volume_ptr };
+    if entry.is_dir() {
+        klog!("[fat] open_file directory cluster={}\n", entry.cluster);
+        let dir = FatDir {
+            volume: volume_ref,
+            start_cluster: entry.cluster,
+            times: entry.unix_times(),
+        };
+        return leak(dir).map(|dir| dir as &'static dyn VfsFile);
+    }
+
     let file = FatFile {
         volume: volume_ref,
-        start_cluster: entry.0,
-        size: entry.1,
+        start_cluster: entry.cluster,
+        size: entry.size,
+        times: entry.unix_times(),
+        attr: entry.attr,
     };
 
     klog!(
@@ -374,13 +819,81 @@ pub fn open_file(path: &str) -> Result<&'static dyn VfsFile, FatError> {
         file.size
     );
 
-    let layout = Layout::new::<FatFile>();
-    let raw = unsafe { heap::allocate(layout) } as *mut FatFile;
+    leak(file).map(|file| file as &'static dyn VfsFile)
+}
+
+/// Visit everything below `start_dir` on volume `name`. The visitor gets
+/// each entry's path from the volume root (`/DOCS/README.TXT`) and its
+/// directory entry, a directory before anything inside it; returning
+/// `false` stops the walk. Directories still to be listed wait on a heap
+/// stack, not the kernel stack, so depth costs no recursion. A directory
+/// whose cluster has already been entered (a corrupt image can link a
+/// child back to an ancestor) is visited but not entered again. Returns
+/// the number of entries visited.
+pub fn walk<F>(name: &str, start_dir: &str, mut visit: F) -> Result<usize, FatError>
+where
+    F: FnMut(&str, &VfsDirEntry) -> bool,
+{
+    let trimmed = start_dir.trim_matches('/');
+    let (volume, start) = {
+        let table = FAT_VOLUMES.lock();
+        let volume = table.find(name).ok_or(FatError::NotMounted)?;
+        (volume, volume.lookup(trimmed)?)
+    };
+    if !start.is_dir() {
+        return Err(FatError::InvalidPath);
+    }
+
+    let mut root_prefix = String::new();
+    if !trimmed.is_empty() {
+        root_prefix.push('/');
+        root_prefix.push_str(trimmed);
+    }
+    let mut pending: Vec<(u16, String)> = Vec::new();
+    let mut entered: Vec<u16> = Vec::new();
+    pending.push((start.cluster, root_prefix));
+    entered.push(start.cluster);
+
+    let mut visited = 0;
+    while let Some((cluster, prefix)) = pending.pop() {
+        let mut subdirs = Vec::new();
+        let mut index = 0;
+        while let Some((entry, next)) = volume.next_dir_entry(cluster, index)? {
+            index = next;
+            let mut short = [0u8; 12];
+            let len = entry.display_name(&mut short);
+            let mut path = prefix.clone();
+            path.push('/');
+            path.extend(short[..len].iter().map(|&byte| byte as char));
+
+            visited += 1;
+            if !visit(&path, &entry.to_vfs()) {
+                return Ok(visited);
+            }
+            if !entry.is_dir() {
+                continue;
+            }
+            if entered.contains(&entry.cluster) || !volume.is_data_cluster(entry.cluster) {
+                klog!("[fat] walk: not entering {} again (cluster {})\n", path, entry.cluster);
+                continue;
+            }
+            entered.push(entry.cluster);
+            subdirs.push((entry.cluster, path));
+        }
+        // Reversed so subdirectories are walked in listing order.
+        pending.extend(subdirs.into_iter().rev());
+    }
+    Ok(visited)
+}
+
+fn leak<T>(value: T) -> Result<&'static T, FatError> {
+    let layout = Layout::new::<T>();
+    let raw = unsafe { heap::allocate(layout) } as *mut T;
     if raw.is_null() {
         return Err(FatError::Io);
     }
     unsafe {
-        raw.write(file);
+        raw.write(value);
         Ok(&*raw)
     }
 }
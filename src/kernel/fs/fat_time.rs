@@ -0,0 +1,82 @@
+#![allow(dead_code)]
+
+//! FAT directory entries carry packed 16-bit date and time words: the date
+//! counts years from 1980, and the time only has room for seconds / 2. This
+//! unpacks them into a `FatTimestamp` and converts between that and Unix
+//! seconds through `time::civil`. The format has no time zone, so stamps
+//! are read as UTC.
+
+use crate::time::civil::DateTime;
+
+/// Unix time of 1980-01-01 00:00:00, the earliest stamp FAT can hold.
+pub const FAT_EPOCH_UNIX: u64 = 315_532_800;
+/// The 7-bit year field runs out at the end of this year.
+pub const FAT_MAX_YEAR: u16 = 1980 + 127;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FatTimestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl FatTimestamp {
+    /// Unpack a directory entry's date and time words. A zero date means
+    /// the writer never set one; that and out-of-range fields give `None`.
+    pub fn decode(date: u16, time: u16) -> Option<Self> {
+        let stamp = Self {
+            year: 1980 + (date >> 9),
+            month: ((date >> 5) & 0x0F) as u8,
+            day: (date & 0x1F) as u8,
+            hour: (time >> 11) as u8,
+            minute: ((time >> 5) & 0x3F) as u8,
+            second: ((time & 0x1F) * 2) as u8,
+        };
+        if stamp.is_valid() {
+            Some(stamp)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (1980..=FAT_MAX_YEAR).contains(&self.year) && self.civil().is_valid()
+    }
+
+    pub fn to_unix(&self) -> u64 {
+        self.civil().to_unix()
+    }
+
+    /// `None` for times before 1980 or after 2107, which FAT cannot store.
+    pub fn from_unix(secs: u64) -> Option<Self> {
+        if secs < FAT_EPOCH_UNIX {
+            return None;
+        }
+        let civil = DateTime::from_unix(secs)?;
+        if civil.year > FAT_MAX_YEAR {
+            return None;
+        }
+        Some(Self {
+            year: civil.year,
+            month: civil.month,
+            day: civil.day,
+            hour: civil.hour,
+            minute: civil.minute,
+            second: civil.second,
+        })
+    }
+
+    fn civil(&self) -> DateTime {
+        DateTime {
+            year: self.year,
+            month: self.month,
+            day: self.day,
+            hour: self.hour,
+            minute: self.minute,
+            second: self.second,
+        }
+    }
+}
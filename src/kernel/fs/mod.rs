@@ -1 +1,4 @@
 pub mod fat;
+pub mod fat_time;
+pub mod procfs;
+pub mod tmpfs;
@@ -0,0 +1,227 @@
+//! Read-only view of kernel state as files, mounted at `/proc`. Nothing is
+//! stored: every read formats the text afresh from the process table or the
+//! memory allocators, so two reads of one file can disagree. The tree is
+//!
+//! - `/proc/meminfo`: heap and physical frame totals.
+//! - `/proc/<pid>/status`: one `Key:\tvalue` line per field of the process
+//!   snapshot.
+//!
+//! Per-pid nodes are allocated on the first open of each path and handed
+//! out again on every later one. They are never freed, like FAT's files.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use crate::mem::{heap, phys};
+use crate::process::{self, Pid, ProcessSnapshot};
+use crate::sync::spinlock::SpinLock;
+use crate::vfs::mount::{self, FileSystem};
+use crate::vfs::{attr, mode, VfsDirEntry, VfsError, VfsFile, VfsFileStat, VfsResult};
+
+pub const MOUNT_POINT: &str = "/proc";
+
+const BLOCK_SIZE: u32 = 512;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Node {
+    Root,
+    MemInfo,
+    ProcessDir(Pid),
+    Status(Pid),
+}
+
+impl Node {
+    /// Text of a file node, or `None` for directories and exited pids.
+    fn render(&self) -> Option<String> {
+        let mut text = String::new();
+        let written = match *self {
+            Node::MemInfo => write_meminfo(&mut text),
+            Node::Status(pid) => write_status(&mut text, &process::get_process(pid)?),
+            Node::Root | Node::ProcessDir(_) => return None,
+        };
+        written.ok().map(|()| text)
+    }
+
+    fn process_exists(&self) -> bool {
+        match *self {
+            Node::ProcessDir(pid) | Node::Status(pid) => process::get_process(pid).is_some(),
+            Node::Root | Node::MemInfo => true,
+        }
+    }
+}
+
+static ROOT: Node = Node::Root;
+static MEMINFO: Node = Node::MemInfo;
+
+/// Every per-pid node leaked so far.
+static PID_NODES: SpinLock<Vec<&'static Node>> = SpinLock::new(Vec::new());
+
+/// The leaked copy of `node`, leaking one only the first time it is asked
+/// for, so reopening a file costs nothing.
+fn intern(node: Node) -> &'static Node {
+    let mut nodes = PID_NODES.lock();
+    if let Some(&cached) = nodes.iter().find(|&&cached| *cached == node) {
+        return cached;
+    }
+    let leaked: &'static Node = Box::leak(Box::new(node));
+    nodes.push(leaked);
+    leaked
+}
+
+impl VfsFile for Node {
+    fn name(&self) -> &'static str {
+        match self {
+            Node::Root => "proc",
+            Node::MemInfo => "meminfo",
+            Node::ProcessDir(_) => "pid",
+            Node::Status(_) => "status",
+        }
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if self.is_dir() {
+            return Err(VfsError::Unsupported);
+        }
+        // The process may have been reaped since the open.
+        let text = self.render().ok_or(VfsError::NotFound)?;
+        let bytes = text.as_bytes();
+        if offset >= bytes.len() as u64 {
+            return Ok(0);
+        }
+        let start = offset as usize;
+        let count = buf.len().min(bytes.len() - start);
+        buf[..count].copy_from_slice(&bytes[start..start + count]);
+        Ok(count)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn flush(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    /// The length the text would have if rendered now.
+    fn size(&self) -> VfsResult<u64> {
+        if self.is_dir() {
+            return Ok(0);
+        }
+        self.render()
+            .map(|text| text.len() as u64)
+            .ok_or(VfsError::NotFound)
+    }
+
+    fn is_dir(&self) -> bool {
+        matches!(self, Node::Root | Node::ProcessDir(_))
+    }
+
+    fn attributes(&self) -> u8 {
+        attr::READ_ONLY
+    }
+
+    fn stat(&self) -> VfsResult<VfsFileStat> {
+        let mode = if self.is_dir() {
+            mode::DIR | mode::READ | mode::EXEC
+        } else {
+            mode::FILE | mode::READ
+        };
+        Ok(VfsFileStat {
+            size: self.size()?,
+            mode,
+            block_size: BLOCK_SIZE,
+            created: 0,
+            modified: 0,
+            accessed: 0,
+        })
+    }
+
+    /// The root lists `meminfo` and then one directory per process in table
+    /// order, so a pid that exits between calls shifts the ones after it.
+    fn read_dir(&self, cursor: u64) -> VfsResult<Option<(VfsDirEntry, u64)>> {
+        match *self {
+            Node::Root => {
+                if cursor == 0 {
+                    return Ok(Some((VfsDirEntry::new(b"meminfo", attr::READ_ONLY, 0), 1)));
+                }
+                let snapshots = process::snapshot_all();
+                let Some(snapshot) = snapshots.get(cursor as usize - 1) else {
+                    return Ok(None);
+                };
+                let mut name = String::new();
+                write!(name, "{}", snapshot.pid()).map_err(|_| VfsError::Io)?;
+                let entry = VfsDirEntry::new(name.as_bytes(), attr::DIRECTORY | attr::READ_ONLY, 0);
+                Ok(Some((entry, cursor + 1)))
+            }
+            Node::ProcessDir(_) if cursor == 0 => {
+                Ok(Some((VfsDirEntry::new(b"status", attr::READ_ONLY, 0), 1)))
+            }
+            Node::ProcessDir(_) => Ok(None),
+            Node::MemInfo | Node::Status(_) => Err(VfsError::Unsupported),
+        }
+    }
+}
+
+fn write_status(out: &mut dyn Write, snapshot: &ProcessSnapshot) -> fmt::Result {
+    let credentials = snapshot.credentials();
+    writeln!(out, "Name:\t{}", snapshot.name())?;
+    writeln!(out, "Pid:\t{}", snapshot.pid())?;
+    writeln!(out, "Tgid:\t{}", snapshot.tgid())?;
+    writeln!(out, "PPid:\t{}", snapshot.parent().unwrap_or(0))?;
+    writeln!(out, "Pgid:\t{}", snapshot.pgid())?;
+    writeln!(out, "Sid:\t{}", snapshot.sid())?;
+    writeln!(out, "State:\t{}", process::state_name(snapshot.state()))?;
+    writeln!(out, "Uid:\t{}\t{}", credentials.real_uid(), credentials.effective_uid())?;
+    writeln!(out, "Gid:\t{}\t{}", credentials.real_gid(), credentials.effective_gid())?;
+    writeln!(out, "Idle:\t{}", snapshot.is_idle() as u8)?;
+    writeln!(out, "Slices:\t{}", snapshot.cpu_slices())?;
+    writeln!(out, "CpuMs:\t{}", snapshot.cpu_time_ms())
+}
+
+fn write_meminfo(out: &mut dyn Write) -> fmt::Result {
+    let (start, end) = heap::bounds();
+    let heap = heap::stats();
+    let memory = phys::summary();
+    let frame = phys::frame_size();
+    writeln!(out, "HeapTotal:\t{}", end - start)?;
+    writeln!(out, "HeapFree:\t{}", heap.remaining)?;
+    writeln!(out, "HeapPeak:\t{}", heap.peak_used)?;
+    writeln!(out, "HeapLargestFree:\t{}", heap.largest_free)?;
+    writeln!(out, "PhysTotal:\t{}", memory.total_bytes)?;
+    writeln!(out, "PhysFree:\t{}", memory.free_frames as u64 * frame)?;
+    writeln!(out, "PhysUsed:\t{}", memory.used_frames as u64 * frame)?;
+    writeln!(out, "PhysReserved:\t{}", memory.reserved_bytes)
+}
+
+pub struct ProcFs;
+
+static PROCFS: ProcFs = ProcFs;
+
+impl FileSystem for ProcFs {
+    fn open(&'static self, path: &str) -> VfsResult<&'static dyn VfsFile> {
+        let mut parts = path.split('/').filter(|part| !part.is_empty());
+        let node = match (parts.next(), parts.next()) {
+            (None, _) => return Ok(&ROOT),
+            (Some("meminfo"), None) => return Ok(&MEMINFO),
+            (Some(first), second) => {
+                let pid: Pid = first.parse().map_err(|_| VfsError::NotFound)?;
+                match second {
+                    None => Node::ProcessDir(pid),
+                    Some("status") => Node::Status(pid),
+                    Some(_) => return Err(VfsError::NotFound),
+                }
+            }
+        };
+        if parts.next().is_some() || !node.process_exists() {
+            return Err(VfsError::NotFound);
+        }
+        Ok(intern(node))
+    }
+}
+
+/// Attach the filesystem at `MOUNT_POINT`. Safe to call again.
+pub fn init() -> VfsResult<()> {
+    mount::mount(MOUNT_POINT, &PROCFS)
+}
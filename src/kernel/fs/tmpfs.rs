@@ -0,0 +1,331 @@
+#![allow(dead_code)]
+
+//! Writable in-memory filesystem. Files are heap-backed byte vectors that
+//! grow as they are written; directories hold a list of child nodes. Nodes
+//! are never freed, so the `&'static` handles given to the VFS stay valid.
+//! Files keep Unix-second stamps from `time::now()`: `modified` moves on
+//! write and truncate, `accessed` on read.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::sync::spinlock::SpinLock;
+use crate::time;
+use crate::vfs::mount::{self, FileSystem};
+use crate::vfs::{attr, mode, VfsDirEntry, VfsError, VfsFile, VfsFileStat, VfsResult, DIR_NAME_MAX};
+
+pub const MOUNT_POINT: &str = "/tmp";
+
+const BLOCK_SIZE: u32 = 512;
+
+#[derive(Copy, Clone)]
+enum Node {
+    File(&'static TmpFile),
+    Dir(&'static TmpDir),
+}
+
+impl Node {
+    fn name(&self) -> &'static str {
+        match self {
+            Node::File(file) => file.name,
+            Node::Dir(dir) => dir.name,
+        }
+    }
+
+    fn as_vfs(&self) -> &'static dyn VfsFile {
+        match *self {
+            Node::File(file) => file,
+            Node::Dir(dir) => dir,
+        }
+    }
+}
+
+pub struct TmpFile {
+    name: &'static str,
+    data: SpinLock<Vec<u8>>,
+    created: u64,
+    modified: AtomicU64,
+    accessed: AtomicU64,
+    /// Set by a change to the data, cleared by `flush`.
+    dirty: AtomicBool,
+}
+
+impl TmpFile {
+    fn new(name: &'static str) -> Self {
+        let now = time::now();
+        Self {
+            name,
+            data: SpinLock::new(Vec::new()),
+            created: now,
+            modified: AtomicU64::new(now),
+            accessed: AtomicU64::new(now),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    fn touch_modified(&self) {
+        self.modified.store(time::now(), Ordering::Relaxed);
+        self.dirty.store(true, Ordering::Release);
+    }
+}
+
+pub struct TmpDir {
+    name: &'static str,
+    entries: SpinLock<Vec<Node>>,
+}
+
+impl TmpDir {
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            entries: SpinLock::new(Vec::new()),
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<Node> {
+        self.entries.lock().iter().copied().find(|node| node.name() == name)
+    }
+
+    /// Return the child called `name`, inserting the node built by `make`
+    /// when there is none. The lookup and insert share one lock so two
+    /// creators cannot both add the same name.
+    fn find_or_insert<F>(&self, name: &str, make: F) -> Node
+    where
+        F: FnOnce(&'static str) -> Node,
+    {
+        let mut entries = self.entries.lock();
+        if let Some(node) = entries.iter().copied().find(|node| node.name() == name) {
+            return node;
+        }
+        let node = make(Box::leak(String::from(name).into_boxed_str()));
+        entries.push(node);
+        node
+    }
+}
+
+impl VfsFile for TmpDir {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn flush(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> VfsResult<u64> {
+        Ok(0)
+    }
+
+    fn is_dir(&self) -> bool {
+        true
+    }
+
+    fn stat(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            size: 0,
+            mode: mode::DIR | mode::READ | mode::WRITE | mode::EXEC,
+            block_size: BLOCK_SIZE,
+            created: 0,
+            modified: 0,
+            accessed: 0,
+        })
+    }
+
+    fn read_dir(&self, cursor: u64) -> VfsResult<Option<(VfsDirEntry, u64)>> {
+        let node = match self.entries.lock().get(cursor as usize) {
+            Some(node) => *node,
+            None => return Ok(None),
+        };
+        let entry = match node {
+            Node::File(file) => VfsDirEntry::new(file.name.as_bytes(), 0, file.data.lock().len() as u64),
+            Node::Dir(dir) => VfsDirEntry::new(dir.name.as_bytes(), attr::DIRECTORY, 0),
+        };
+        Ok(Some((entry, cursor + 1)))
+    }
+}
+
+impl VfsFile for TmpFile {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let data = self.data.lock();
+        if offset >= data.len() as u64 {
+            return Ok(0);
+        }
+        let start = offset as usize;
+        let count = buf.len().min(data.len() - start);
+        buf[..count].copy_from_slice(&data[start..start + count]);
+        self.accessed.store(time::now(), Ordering::Relaxed);
+        Ok(count)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .filter(|end| *end <= isize::MAX as u64)
+            .ok_or(VfsError::InvalidOffset)? as usize;
+        let mut data = self.data.lock();
+        if end > data.len() {
+            // Writing past the end leaves a zero-filled gap, as on disk.
+            let extra = end - data.len();
+            data.try_reserve(extra).map_err(|_| VfsError::NoSpace)?;
+            data.resize(end, 0);
+        }
+        data[offset as usize..end].copy_from_slice(buf);
+        self.touch_modified();
+        Ok(buf.len())
+    }
+
+    /// The data already lives in memory, so there is nothing to write back;
+    /// flushing only marks the file clean.
+    fn flush(&self) -> VfsResult<()> {
+        self.dirty.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+
+    fn size(&self) -> VfsResult<u64> {
+        Ok(self.data.lock().len() as u64)
+    }
+
+    fn stat(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            size: self.size()?,
+            mode: mode::FILE | mode::READ | mode::WRITE,
+            block_size: BLOCK_SIZE,
+            created: self.created,
+            modified: self.modified.load(Ordering::Relaxed),
+            accessed: self.accessed.load(Ordering::Relaxed),
+        })
+    }
+
+    fn truncate(&self, len: u64) -> VfsResult<()> {
+        if len > isize::MAX as u64 {
+            return Err(VfsError::InvalidOffset);
+        }
+        let len = len as usize;
+        let mut data = self.data.lock();
+        if len > data.len() {
+            let extra = len - data.len();
+            data.try_reserve(extra).map_err(|_| VfsError::NoSpace)?;
+        }
+        data.resize(len, 0);
+        self.touch_modified();
+        Ok(())
+    }
+}
+
+pub struct TmpFs {
+    root: TmpDir,
+}
+
+impl TmpFs {
+    pub const fn new() -> Self {
+        Self {
+            root: TmpDir::new("tmp"),
+        }
+    }
+
+    fn root(&'static self) -> &'static TmpDir {
+        &self.root
+    }
+
+    /// Walk every component of `path` as a directory.
+    fn dir(&'static self, path: &str) -> VfsResult<&'static TmpDir> {
+        let mut dir = self.root();
+        for component in path.split('/').filter(|part| !part.is_empty()) {
+            dir = match dir.find(component) {
+                Some(Node::Dir(child)) => child,
+                Some(Node::File(_)) => return Err(VfsError::Unsupported),
+                None => return Err(VfsError::NotFound),
+            };
+        }
+        Ok(dir)
+    }
+
+    fn lookup(&'static self, path: &str) -> VfsResult<Node> {
+        match split_parent(path)? {
+            None => Ok(Node::Dir(self.root())),
+            Some((parent, name)) => self.dir(parent)?.find(name).ok_or(VfsError::NotFound),
+        }
+    }
+
+    /// Create a directory at `path`. An existing directory is returned as is.
+    pub fn mkdir(&'static self, path: &str) -> VfsResult<&'static dyn VfsFile> {
+        let (parent, name) = split_parent(path)?.ok_or(VfsError::Unsupported)?;
+        let node = self.dir(parent)?.find_or_insert(name, |name| {
+            Node::Dir(Box::leak(Box::new(TmpDir::new(name))))
+        });
+        match node {
+            Node::Dir(dir) => Ok(dir),
+            Node::File(_) => Err(VfsError::Unsupported),
+        }
+    }
+}
+
+impl Default for TmpFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for TmpFs {
+    fn open(&'static self, path: &str) -> VfsResult<&'static dyn VfsFile> {
+        self.lookup(path).map(|node| node.as_vfs())
+    }
+
+    fn create(&'static self, path: &str) -> VfsResult<&'static dyn VfsFile> {
+        let (parent, name) = match split_parent(path)? {
+            Some(parts) => parts,
+            None => return Ok(self.root()),
+        };
+        let node = self.dir(parent)?.find_or_insert(name, |name| {
+            Node::File(Box::leak(Box::new(TmpFile::new(name))))
+        });
+        Ok(node.as_vfs())
+    }
+}
+
+/// Split `path` into its parent directory and final component, rejecting
+/// names that could not be listed back. `None` means the root itself.
+fn split_parent(path: &str) -> VfsResult<Option<(&str, &str)>> {
+    let trimmed = path.trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    let (parent, name) = match trimmed.rfind('/') {
+        Some(index) => (&trimmed[..index], &trimmed[index + 1..]),
+        None => ("", trimmed),
+    };
+    if name.is_empty() || name == "." || name == ".." || name.len() > DIR_NAME_MAX {
+        return Err(VfsError::Unsupported);
+    }
+    Ok(Some((parent, name)))
+}
+
+static TMPFS: TmpFs = TmpFs::new();
+
+/// Attach the tmpfs at `MOUNT_POINT`. Safe to call again; the tree survives.
+pub fn init() -> VfsResult<()> {
+    mount::mount(MOUNT_POINT, &TMPFS)
+}
+
+/// Create a directory on the mounted tmpfs; `path` is relative to its root.
+pub fn mkdir(path: &str) -> VfsResult<&'static dyn VfsFile> {
+    TMPFS.mkdir(path)
+}
@@ -1,5 +1,9 @@
 #![allow(dead_code)]
 
+pub mod eoi;
+pub mod pit;
+pub mod work;
+
 #[cfg(target_arch = "x86_64")]
 pub use crate::arch::x86_64::kernel::interrupts::*;
 
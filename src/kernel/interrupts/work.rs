@@ -0,0 +1,79 @@
+#![allow(dead_code)]
+
+//! Bottom-half work items. An IRQ handler does the minimum with the device
+//! (e.g. reads the scancode) and queues the rest as a `WorkItem`; the queue
+//! is drained once the interrupt has been acknowledged. The queue is a plain
+//! ring with no locking of its own, so the kernel wraps one in a `SpinLock`
+//! and the host tests drive it directly.
+
+/// Deferred work receives the `data` it was queued with.
+pub type WorkFn = fn(usize);
+
+#[derive(Copy, Clone)]
+pub struct WorkItem {
+    pub func: WorkFn,
+    pub data: usize,
+}
+
+impl WorkItem {
+    pub const fn new(func: WorkFn, data: usize) -> Self {
+        Self { func, data }
+    }
+
+    pub fn run(self) {
+        (self.func)(self.data)
+    }
+}
+
+/// Fixed-capacity FIFO of `WorkItem`s. Pushing never allocates, so it is
+/// safe from interrupt context.
+pub struct WorkQueue<const N: usize> {
+    items: [Option<WorkItem>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> WorkQueue<N> {
+    pub const fn new() -> Self {
+        Self {
+            items: [None; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Queue `item` behind everything already pending. A full queue hands
+    /// it back so the caller can run it inline rather than lose it.
+    pub fn push(&mut self, item: WorkItem) -> Result<(), WorkItem> {
+        if self.len == N {
+            return Err(item);
+        }
+        self.items[(self.head + self.len) % N] = Some(item);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<WorkItem> {
+        if self.len == 0 {
+            return None;
+        }
+        let item = self.items[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        item
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<const N: usize> Default for WorkQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -5,14 +5,36 @@ use crate::arch::x86_64::drivers::serial;
 compile_error!("klog serial backend not implemented for this architecture");
 
 use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::timer;
+
+static TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+/// Whether the last byte written was a newline, so the next one starts a
+/// line and gets the timestamp prefix.
+static AT_LINE_START: AtomicBool = AtomicBool::new(true);
 
 pub fn init() {
     serial::init();
 }
 
+/// Prefix every line with `[uptime_ms]` from the timer tick count.
+pub fn set_timestamps(enabled: bool) {
+    TIMESTAMPS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn timestamps_enabled() -> bool {
+    TIMESTAMPS.load(Ordering::Relaxed)
+}
+
 pub fn write_bytes(bytes: &[u8]) {
+    let timestamps = timestamps_enabled();
     for &byte in bytes {
-        serial::write_byte(byte);
+        if timestamps && AT_LINE_START.load(Ordering::Relaxed) {
+            let _ = write!(RawWriter, "[{}] ", timer::ticks_to_ms(timer::ticks()));
+        }
+        emit(byte);
+        AT_LINE_START.store(byte == b'\n', Ordering::Relaxed);
     }
 }
 
@@ -36,7 +58,14 @@ macro_rules! klog {
     };
 }
 
-struct SerialWriter;
+fn emit(byte: u8) {
+    serial::write_byte(byte);
+    #[cfg(kernel_test)]
+    capture::record(byte);
+}
+
+/// `fmt::Write` onto the log, for code that formats in several steps.
+pub struct SerialWriter;
 
 impl Write for SerialWriter {
     fn write_str(&mut self, s: &str) -> fmt::Result {
@@ -44,3 +73,62 @@ impl Write for SerialWriter {
         Ok(())
     }
 }
+
+/// Writes straight to the backend, bypassing line-start tracking.
+struct RawWriter;
+
+impl Write for RawWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            emit(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Lets kernel tests see what was logged. Output still goes to serial.
+#[cfg(kernel_test)]
+pub mod capture {
+    use crate::sync::spinlock::SpinLock;
+
+    const CAPTURE_BYTES: usize = 2048;
+
+    struct Capture {
+        active: bool,
+        buf: [u8; CAPTURE_BYTES],
+        len: usize,
+    }
+
+    static CAPTURE: SpinLock<Capture> = SpinLock::new(Capture {
+        active: false,
+        buf: [0; CAPTURE_BYTES],
+        len: 0,
+    });
+
+    pub fn start() {
+        let mut capture = CAPTURE.lock();
+        capture.active = true;
+        capture.len = 0;
+    }
+
+    /// Stop capturing and copy what was recorded into `out`.
+    pub fn finish(out: &mut [u8]) -> usize {
+        let mut capture = CAPTURE.lock();
+        capture.active = false;
+        let count = capture.len.min(out.len());
+        out[..count].copy_from_slice(&capture.buf[..count]);
+        count
+    }
+
+    pub(super) fn record(byte: u8) {
+        // try_lock: a log line from an interrupt while the test holds the
+        // lock is simply not captured.
+        if let Some(mut capture) = CAPTURE.try_lock() {
+            if capture.active && capture.len < CAPTURE_BYTES {
+                let len = capture.len;
+                capture.buf[len] = byte;
+                capture.len += 1;
+            }
+        }
+    }
+}
@@ -5,14 +5,19 @@ extern crate alloc;
 #[path = "../arch/mod.rs"]
 pub mod arch;
 
+mod boot;
+mod cmdline;
+mod crash;
 mod interrupts;
 mod klog;
 mod drivers;
 mod fs;
 mod mem;
+mod power;
 mod syscall;
 mod sync;
 mod timer;
+mod time;
 mod cpu;
 mod vfs;
 pub mod user;
@@ -33,6 +38,12 @@ use crate::mem::heap;
 #[cfg(not(kernel_test))]
 use crate::mem::heap::HeapBox;
 const FAT_START_LBA: u64 = 4096;
+/// FAT volume name, and block device name, of the first boot module.
+#[cfg(not(kernel_test))]
+const INITRD_VOLUME: &str = "initrd";
+/// What runs as init from the initrd when the command line names nothing.
+#[cfg(not(kernel_test))]
+const INITRD_INIT: &str = "/fat/initrd/INIT";
 #[cfg(not(kernel_test))]
 use crate::vfs::ata::AtaScratchFile;
 #[cfg(not(kernel_test))]
@@ -43,14 +54,22 @@ pub extern "C" fn kmain(multiboot_info: *const c_void, multiboot_magic: u32) ->
     let info_addr = multiboot_info as usize;
 
     klog::init();
+    cpu::require();
+    #[cfg(debug_assertions)]
+    sync::install_lock_debug();
     klog!("[kmain] multiboot magic: 0x{:08X}
 ", multiboot_magic);
     klog!("[kmain] multiboot info ptr: 0x{:016X}
 ", info_addr);
 
     interrupts::init();
+    unsafe { boot::init(info_addr) };
     mem::phys::init(info_addr);
-    heap::init();
+    heap::init_from_phys();
+    if !arch::x86_64::kernel::apic::init() {
+        klog!("[kmain] no local APIC; running as cpu 0\n");
+    }
+    cpu::claim_slot(cpu::apic_id());
 
     #[cfg(not(kernel_test))]
     {
@@ -67,6 +86,7 @@ pub extern "C" fn kmain(multiboot_info: *const c_void, multiboot_magic: u32) ->
         arch::x86_64::kernel::gdt::init();
 
         drivers::init();
+        time::init();
 
         let vendor_raw = cpu::vendor_string();
         let vendor = str::from_utf8(&vendor_raw).unwrap_or("unknown");
@@ -80,6 +100,7 @@ pub extern "C" fn kmain(multiboot_info: *const c_void, multiboot_magic: u32) ->
 
         if features.has_edx(cpu::feature::edx::SSE) && features.has_edx(cpu::feature::edx::SSE2) {
             unsafe { cpu::enable_sse(); }
+            arch::x86_64::kernel::mmu::enable_nt_zero();
             klog::writeln("[kmain] SSE/SSE2 enabled");
         } else {
             klog::writeln("[kmain] SSE/SSE2 unavailable");
@@ -89,8 +110,23 @@ pub extern "C" fn kmain(multiboot_info: *const c_void, multiboot_magic: u32) ->
             klog::writeln("[kmain] AVX supported");
         }
 
+        let cmdline = unsafe { cmdline::from_multiboot(info_addr) };
+        if cmdline.and_then(|cmdline| cmdline::value(cmdline, "systrace")) == Some("1") {
+            syscall::set_trace(true);
+            klog!("[kmain] syscall tracing enabled\n");
+        }
+
         drivers::register_builtin();
         drivers::list_drivers();
+        if let Err(err) = fs::tmpfs::init() {
+            klog!("[tmpfs] mount failed: {:?}\n", err);
+        }
+        if let Err(err) = fs::procfs::init() {
+            klog!("[procfs] mount failed: {:?}\n", err);
+        }
+        if let Err(err) = vfs::symlink::symlink("/dev/stdout", "/dev/console") {
+            klog!("[vfs] /dev/stdout link failed: {:?}\n", err);
+        }
         klog!("[vfs] probing for block device 'ata0-master'\n");
         match drivers::block_device_by_name("ata0-master") {
             Some(ata_dev) => {
@@ -99,15 +135,22 @@ pub extern "C" fn kmain(multiboot_info: *const c_void, multiboot_magic: u32) ->
                     let file = AtaScratchFile::init(ata_dev, 2048, "ata0-scratch");
                     klog!("[vfs] scratch file '{}' mounted at LBA {}\n", file.name(), 2048);
                 }
-                match fs::fat::mount(ata_dev, FAT_START_LBA) {
-                    Ok(()) => klog!("[fat] mounted volume at LBA {}\n", FAT_START_LBA),
-                    Err(err) => klog!("[fat] mount failed: {:?}\n", err),
+                let partitions = drivers::mbr::scan(ata_dev, "ata0").unwrap_or(0);
+                if partitions > 0 && fs::fat::mount_named(fs::fat::DEFAULT_VOLUME, "ata0p1", 0).is_ok() {
+                    klog!("[fat] mounted volume on ata0p1\n");
+                } else {
+                    // Unpartitioned image: the volume sits at a fixed offset.
+                    match fs::fat::mount(fs::fat::DEFAULT_VOLUME, ata_dev, FAT_START_LBA) {
+                        Ok(()) => klog!("[fat] mounted volume at LBA {}\n", FAT_START_LBA),
+                        Err(err) => klog!("[fat] mount failed: {:?}\n", err),
+                    }
                 }
             }
             None => {
                 klog!("[vfs] ata0-master unavailable; scratch file not initialised\n");
             }
         }
+        let initrd = mount_initrd();
         process::init().expect("process init");
         syscall::init();
         let banner = b"[ares] Booting Ares kernel\n";
@@ -124,6 +167,14 @@ pub extern "C" fn kmain(multiboot_info: *const c_void, multiboot_magic: u32) ->
     }
     let after = heap::remaining_bytes();
     klog!("[heap] remaining before={} after={}\n", before, after);
+    let stats = heap::stats();
+    klog!(
+        "[heap] stats remaining={} peak_used={} free_regions={} largest_free={}\n",
+        stats.remaining,
+        stats.peak_used,
+        stats.free_regions,
+        stats.largest_free
+    );
 
     unsafe {
         let layout = Layout::from_size_align(64, 16).unwrap();
@@ -138,15 +189,12 @@ pub extern "C" fn kmain(multiboot_info: *const c_void, multiboot_magic: u32) ->
     }
 
         timer::init();
+        klog::set_timestamps(true);
 
-    process::spawn_kernel_process("init", init_shell_task).expect("spawn init");
-/*
-        if let Err(err) = process::spawn_user_process("hello", "/bin/hello") {
-            klog!("[kmain] failed to spawn user process: {:?}\n", err);
-        } else {
-            klog!("[kmain] started user process '/bin/hello'\n");
-        }
-*/
+        let init_path = cmdline
+            .and_then(|cmdline| cmdline::value(cmdline, "init"))
+            .or(if initrd { Some(INITRD_INIT) } else { None });
+        process::spawn_init(init_path, init_shell_task).expect("spawn init");
         interrupts::enable();
 
 
@@ -154,10 +202,47 @@ pub extern "C" fn kmain(multiboot_info: *const c_void, multiboot_magic: u32) ->
     }
 }
 
+/// Mount the first boot module as the FAT volume `INITRD_VOLUME`, so init
+/// can come from it when there is no disk. False when there is no module or
+/// it does not hold a FAT volume.
+#[cfg(not(kernel_test))]
+fn mount_initrd() -> bool {
+    let modules = boot::modules();
+    let Some(module) = modules.first() else {
+        return false;
+    };
+    let disk = match drivers::ramdisk::register_module(INITRD_VOLUME, module) {
+        Ok(disk) => disk,
+        Err(err) => {
+            klog!("[initrd] module '{}' not usable as a disk: {:?}\n", module.name, err);
+            return false;
+        }
+    };
+    match fs::fat::mount(INITRD_VOLUME, disk, 0) {
+        Ok(()) => {
+            klog!("[initrd] module '{}' mounted at /fat/{}\n", module.name, INITRD_VOLUME);
+            true
+        }
+        Err(err) => {
+            klog!("[initrd] mount failed: {:?}\n", err);
+            false
+        }
+    }
+}
+
+/// Fallback init: open the terminal, which makes it init's controlling
+/// tty, and hand each finished line straight back to it.
 extern "C" fn init_shell_task() -> ! {
+    let tty = match syscall::open("/dev/tty") {
+        Ok(fd) => fd as u64,
+        Err(err) => {
+            klog!("[shell] open /dev/tty failed: {:?}; using stdin\n", err);
+            syscall::fd::STDIN
+        }
+    };
     let mut input_buf = [0u8; 64];
     loop {
-        let count = match syscall::read(syscall::fd::STDIN, &mut input_buf) {
+        let count = match syscall::read(tty, &mut input_buf) {
             Ok(count) => count,
             Err(err) => {
                 klog!("[shell] read error: {:?}\n", err);
@@ -166,13 +251,13 @@ extern "C" fn init_shell_task() -> ! {
             }
         };
 
+        // The tty blocks until a line is ready, so 0 only means an empty read.
         if count == 0 {
-            process::yield_now();
             continue;
         }
         if count <= input_buf.len() {
             let slice = &input_buf[..count];
-            if let Err(err) = syscall::write(syscall::fd::STDOUT, slice) {
+            if let Err(err) = syscall::write(tty, slice) {
                 klog!("[shell] write error: {:?}\n", err);
             }
         }
@@ -278,8 +363,11 @@ fn vfs_smoke_checks() {
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    klog::writeln("[kpanic] Kernel panic!");
-    klog!("[kpanic] {}\n", info);
+    let regs = arch::x86_64::kernel::backtrace::capture();
+    let _ = crash::write_dump(&mut klog::SerialWriter, info, &regs);
+
+    #[cfg(kernel_test)]
+    crash::expect::exit_if_expected();
 
     loop {
         spin_loop();
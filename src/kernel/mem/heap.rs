@@ -1,198 +1,42 @@
 #![allow(dead_code, non_snake_case)]
 
 use core::alloc::Layout;
-use core::mem::{align_of, size_of};
-use core::ptr::{self, copy, null_mut, NonNull};
+use core::ptr::{self, copy, NonNull};
 use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
+#[cfg(target_arch = "x86_64")]
+use crate::arch::x86_64::kernel::mmu;
 use crate::interrupts;
 use crate::klog;
+use crate::mem::phys;
 use crate::sync::spinlock::SpinLock;
 
-pub const HEAP_SIZE: usize = 8 * 1024 * 1024; // 8 MiB temporary heap
+use super::free_list::LinkedListAllocator;
+pub use super::free_list::HeapStats;
 
-pub(crate) static mut HEAP_SPACE: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
-static ALLOCATOR: SpinLock<LinkedListAllocator> = SpinLock::new(LinkedListAllocator::new());
-
-pub struct KernelAllocator;
-
-struct ListNode {
-    size: usize,
-    next: Option<&'static mut ListNode>,
-}
-
-impl ListNode {
-    const fn new(size: usize) -> Self {
-        Self { size, next: None }
-    }
-
-    fn start_addr(&self) -> usize {
-        self as *const Self as usize
-    }
-
-    fn end_addr(&self) -> usize {
-        self.start_addr() + self.size
-    }
-}
-
-struct LinkedListAllocator {
-    head: ListNode,
-}
-
-impl LinkedListAllocator {
-    const fn new() -> Self {
-        Self {
-            head: ListNode::new(0),
-        }
-    }
-
-    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
-        self.head.next = None;
-        self.insert_region(heap_start, heap_size);
-    }
-
-    fn min_region_size() -> usize {
-        size_of::<ListNode>()
-    }
-
-    fn remaining(&self) -> usize {
-        let mut total = 0;
-        let mut current = &self.head;
-        while let Some(node) = current.next.as_deref() {
-            total += node.size;
-            current = node;
-        }
-        total
-    }
-
-    unsafe fn allocate(&mut self, layout: Layout) -> *mut u8 {
-        let size = layout.size().max(Self::min_region_size());
-        let align = layout.align().max(align_of::<ListNode>());
-
-        let mut current = &mut self.head;
-        while let Some(region) = current.next.as_mut() {
-            let alloc_start = align_up(region.start_addr(), align);
-            let alloc_end = match alloc_start.checked_add(size) {
-                Some(end) => end,
-                None => return null_mut(),
-            };
-
-            if alloc_end > region.end_addr() {
-                current = current.next.as_mut().unwrap();
-                continue;
-            }
-
-            let next = region.next.take();
-            let region_start = region.start_addr();
-            let region_size = region.size;
-
-            current.next = next;
-
-            let excess_before = alloc_start - region_start;
-            if excess_before >= Self::min_region_size() {
-                self.insert_region(region_start, excess_before);
-            }
-
-            let excess_after = region_start + region_size - alloc_end;
-            if excess_after >= Self::min_region_size() {
-                self.insert_region(alloc_end, excess_after);
-            }
-            crate::klog!(
-                "[heap] allocate size={} align={} -> ptr=0x{:016X} remaining={}\n",
-                size,
-                align,
-                alloc_start,
-                self.remaining()
-            );
-            return alloc_start as *mut u8;
-        }
-
-        null_mut()
-    }
-
-    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
-        let size = layout.size().max(Self::min_region_size());
-        self.insert_region(ptr as usize, size);
-    }
+/// Bytes `init_from_phys` takes from physical memory for the heap. Set
+/// `ARES_HEAP_MIB` in the environment of the kernel build to change it.
+pub const HEAP_SIZE: usize = match option_env!("ARES_HEAP_MIB") {
+    Some(mib) => parse_mib(mib),
+    None => 32,
+} * 1024
+    * 1024;
 
-    unsafe fn insert_region(&mut self, addr: usize, size: usize) {
-        let align = align_of::<ListNode>();
-        let start = align_up(addr, align);
-        let end = match addr.checked_add(size) {
-            Some(end) => end,
-            None => return,
-        };
+/// The heap in the kernel image, used only when no physical range can be
+/// had for it.
+pub const EARLY_HEAP_SIZE: usize = 1024 * 1024;
 
-        if start >= end {
-            return;
-        }
-
-        let size = end - start;
-        if size < Self::min_region_size() {
-            return;
-        }
-
-        let mut current = &mut self.head;
-        while let Some(next) = current.next.as_ref() {
-            if next.start_addr() >= start {
-                break;
-            }
-            current = current.next.as_mut().unwrap();
-        }
-
-        let mut node = ListNode::new(size);
-        node.next = current.next.take();
-
-        let node_ptr = start as *mut ListNode;
-        node_ptr.write(node);
-        current.next = Some(&mut *node_ptr);
-
-        self.merge_with_next(node_ptr);
-        self.merge_with_previous(node_ptr);
-    }
-
-    unsafe fn merge_with_next(&mut self, node_ptr: *mut ListNode) {
-        let node = &mut *node_ptr;
-        loop {
-            let node_end = node.end_addr();
-            let next = match node.next.as_mut() {
-                Some(next) => next,
-                None => break,
-            };
-
-            if node_end != next.start_addr() {
-                break;
-            }
-
-            let next_next = next.next.take();
-            node.size += next.size;
-            node.next = next_next;
-        }
-    }
-
-    unsafe fn merge_with_previous(&mut self, node_ptr: *mut ListNode) {
-        let mut current = &mut self.head;
-        while let Some(next) = current.next.as_mut() {
-            let next_ptr = &mut **next as *mut ListNode;
-            if next_ptr == node_ptr {
-                if current.size != 0 && current.end_addr() == (*node_ptr).start_addr() {
-                    let node = &mut *node_ptr;
-                    let next_next = node.next.take();
-                    current.size += node.size;
-                    current.next = next_next;
-                    let current_ptr = current as *mut ListNode;
-                    self.merge_with_next(current_ptr);
-                }
-                break;
-            }
-            current = current.next.as_mut().unwrap();
-        }
-    }
-}
+pub(crate) static mut EARLY_HEAP: [u8; EARLY_HEAP_SIZE] = [0; EARLY_HEAP_SIZE];
+/// Start and end of the range the allocator was initialised over.
+static BOUNDS: SpinLock<(usize, usize)> = SpinLock::new((0, 0));
+static ALLOCATOR: SpinLock<LinkedListAllocator> = SpinLock::new(LinkedListAllocator::new());
+/// Copy of the allocator's free total, stored while the lock is held, so
+/// `remaining_bytes` and the out-of-memory path can read it without taking
+/// the lock (which the failing caller may already hold).
+static FREE_BYTES: AtomicUsize = AtomicUsize::new(0);
 
-fn align_up(addr: usize, align: usize) -> usize {
-    (addr + align - 1) & !(align - 1)
-}
+pub struct KernelAllocator;
 
 fn allocation_failed(layout: Layout, remaining: usize) -> ! {
     interrupts::disable();
@@ -207,39 +51,131 @@ fn allocation_failed(layout: Layout, remaining: usize) -> ! {
     }
 }
 
-pub fn init() {
-    let heap_start = core::ptr::addr_of_mut!(HEAP_SPACE) as *mut u8 as usize;
-    let heap_size = HEAP_SIZE;
-    unsafe {
-        ALLOCATOR.lock().init(heap_start, heap_size);
+/// Hand `start..start + size` to the allocator as the whole heap.
+///
+/// # Safety
+/// The range must be mapped, writable, used for nothing else, and never
+/// given back. Call once, before anything allocates.
+pub unsafe fn init(start: usize, size: usize) {
+    let mut allocator = ALLOCATOR.lock();
+    allocator.init(start, size);
+    publish_free(&allocator);
+    *BOUNDS.lock() = (start, start + size);
+    klog!("[heap] allocator ready at 0x{:016X} ({} bytes)\n", start, size);
+}
+
+/// Carve `HEAP_SIZE` bytes out of physical memory for the heap, or fall
+/// back to the static `EARLY_HEAP` when `phys` has no run that large inside
+/// the direct map. Call after `phys::init`.
+pub fn init_from_phys() {
+    match carve(HEAP_SIZE) {
+        Some((start, size)) => unsafe { init(start, size) },
+        None => {
+            klog!("[heap] no {} byte physical range; using the early heap\n", HEAP_SIZE);
+            init_early();
+        }
     }
-    klog!("[heap] allocator ready ({} bytes)\n", HEAP_SIZE);
 }
 
+/// Use the static array in the kernel image as the heap.
+pub fn init_early() {
+    let start = core::ptr::addr_of_mut!(EARLY_HEAP) as *mut u8 as usize;
+    unsafe { init(start, EARLY_HEAP_SIZE) }
+}
+
+/// Take `size` bytes (rounded up to whole frames) of contiguous physical
+/// memory and return where it sits in the direct map. The frames are never
+/// freed. `None` when no free run lies wholly inside the direct map.
+pub fn carve(size: usize) -> Option<(usize, usize)> {
+    let frames = size.div_ceil(phys::FRAME_SIZE as usize);
+    let range = phys::allocate_frames_below(frames, mmu::DIRECT_MAP_LIMIT)?;
+    let start = range.start().start();
+    let bytes = frames as u64 * phys::FRAME_SIZE;
+    Some((mmu::phys_to_virt(start) as usize, bytes as usize))
+}
+
+/// The static early heap, which `phys` keeps out of the frame pool.
+pub fn early_bounds() -> (usize, usize) {
+    let start = core::ptr::addr_of!(EARLY_HEAP) as usize;
+    (start, start + EARLY_HEAP_SIZE)
+}
+
+/// The range the heap is running over; `(0, 0)` before `init`.
 pub fn bounds() -> (usize, usize) {
-    let start = core::ptr::addr_of!(HEAP_SPACE) as usize;
-    (start, start + HEAP_SIZE)
+    *BOUNDS.lock()
+}
+
+/// `ARES_HEAP_MIB` as a number. A value that is not a positive decimal
+/// stops the build.
+const fn parse_mib(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    assert!(!bytes.is_empty(), "ARES_HEAP_MIB is empty");
+    let mut value = 0usize;
+    let mut index = 0;
+    while index < bytes.len() {
+        let digit = bytes[index];
+        assert!(digit.is_ascii_digit(), "ARES_HEAP_MIB must be a decimal number of MiB");
+        value = value * 10 + (digit - b'0') as usize;
+        index += 1;
+    }
+    assert!(value > 0, "ARES_HEAP_MIB must be at least 1");
+    value
 }
 
+fn publish_free(allocator: &LinkedListAllocator) {
+    FREE_BYTES.store(allocator.remaining(), Ordering::Relaxed);
+}
+
+/// Free heap bytes as of the last allocate or free. Takes no lock.
 pub fn remaining_bytes() -> usize {
-    let allocator = ALLOCATOR.lock();
-    allocator.remaining()
+    FREE_BYTES.load(Ordering::Relaxed)
+}
+
+/// Current free bytes, the peak usage since boot, and how the free space is
+/// split up.
+pub fn stats() -> HeapStats {
+    ALLOCATOR.lock().stats()
 }
 
 pub unsafe fn allocate(layout: Layout) -> *mut u8 {
-    ALLOCATOR.lock().allocate(layout)
+    let mut allocator = ALLOCATOR.lock();
+    let ptr = allocator.allocate(layout);
+    if !ptr.is_null() {
+        publish_free(&allocator);
+        klog!(
+            "[heap] allocate size={} align={} -> ptr=0x{:016X} remaining={}\n",
+            layout.size(),
+            layout.align(),
+            ptr as usize,
+            allocator.remaining()
+        );
+    }
+    ptr
 }
 
 pub unsafe fn deallocate(ptr: *mut u8, layout: Layout) {
-    ALLOCATOR.lock().deallocate(ptr, layout)
+    let mut allocator = ALLOCATOR.lock();
+    allocator.deallocate(ptr, layout);
+    publish_free(&allocator);
+}
+
+/// How many bytes at the returned pointer `allocate(layout)` really owns.
+pub fn reserved_size(layout: Layout) -> usize {
+    LinkedListAllocator::reserved_size(layout)
+}
+
+/// Free `len` bytes at `ptr`, which may be only part of an allocation.
+///
+/// # Safety
+/// The bytes must belong to a live allocation and not be touched again.
+pub unsafe fn release(ptr: *mut u8, len: usize) {
+    let mut allocator = ALLOCATOR.lock();
+    allocator.release(ptr as usize, len);
+    publish_free(&allocator);
 }
 
 pub fn handle_alloc_error(layout: Layout) -> ! {
-    let remaining = {
-        let allocator = ALLOCATOR.lock();
-        allocator.remaining()
-    };
-    allocation_failed(layout, remaining)
+    allocation_failed(layout, remaining_bytes())
 }
 
 unsafe fn layout_from_size_align(size: usize, align: usize) -> Option<Layout> {
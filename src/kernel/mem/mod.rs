@@ -1,2 +1,5 @@
+pub mod free_list;
 pub mod heap;
+pub mod mmap;
 pub mod phys;
+pub mod region;
@@ -0,0 +1,25 @@
+#![allow(dead_code)]
+
+//! Halting and restarting the machine. Both paths shut the registered
+//! drivers down before handing over to the architecture.
+
+use crate::drivers;
+use crate::klog;
+
+#[cfg(target_arch = "x86_64")]
+use crate::arch::x86_64::power as arch;
+
+#[cfg(not(target_arch = "x86_64"))]
+compile_error!("power control not available for this architecture");
+
+pub fn poweroff() -> ! {
+    klog!("[power] powering off\n");
+    drivers::shutdown_all();
+    arch::poweroff()
+}
+
+pub fn reboot() -> ! {
+    klog!("[power] rebooting\n");
+    drivers::shutdown_all();
+    arch::reboot()
+}
@@ -2,15 +2,21 @@
 
 extern crate alloc;
 
+use alloc::collections::VecDeque;
 use alloc::vec;
 use alloc::vec::Vec;
 
-use crate::drivers::{console, keyboard, CharDevice, DriverError};
+use crate::cpu::{CpuLocal, MAX_CPUS};
+use crate::drivers::{self, console, pipe, tty, CharDevice, DriverError};
 use crate::klog;
+use crate::mem::region::{Region, RegionError, RegionList};
 use crate::mem::{heap, phys};
-use crate::sync::spinlock::SpinLock;
+use crate::sync::ticket::TicketLock;
+use crate::timer;
 use crate::user::{self, Credentials};
-use crate::vfs::{VfsError, VfsFile};
+use crate::vfs::{mode, VfsDirEntry, VfsError, VfsFileStat};
+
+pub use crate::vfs::handle::{SeekFrom, VfsHandle};
 
 #[cfg(target_arch = "x86_64")]
 use crate::arch::x86_64::kernel::interrupts::InterruptFrame;
@@ -23,8 +29,9 @@ use crate::arch::x86_64::kernel::{
 };
 
 use core::alloc::Layout;
-use core::array;
-use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use core::{ptr, slice};
 
 pub type Pid = u32;
@@ -33,26 +40,65 @@ pub const STDIN_FD: usize = 0;
 pub const STDOUT_FD: usize = 1;
 pub const STDERR_FD: usize = 2;
 pub const SCRATCH_FD: usize = 3;
-const MAX_FDS: usize = 16;
-const KERNEL_STACK_SIZE: usize = 16 * 1024;
+/// Descriptors a process may hold until `set_fd_limit` changes it.
+pub const DEFAULT_FD_LIMIT: usize = 64;
+/// Highest limit `set_fd_limit` accepts.
+pub const MAX_FD_LIMIT: usize = 1024;
+pub const KERNEL_STACK_SIZE: usize = 16 * 1024;
+/// Pattern kept in the lowest words of every kernel stack. Stacks come from
+/// the heap with nothing mapped out below them, so an overflow shows up as
+/// a clobbered canary rather than a fault.
+const STACK_CANARY: u64 = 0x57AC_C0DE_CA9A_12E5;
+const STACK_CANARY_WORDS: usize = 4;
+/// Word kept just above the `process_exit` return slot at the top of every
+/// kernel stack. A task that writes past its outermost frame hits it.
+const EXIT_CANARY: u64 = 0xE417_FA11_C0DE_5AFE;
+pub const MAX_ARGS: usize = 16;
+pub const MAX_ARG_LEN: usize = 256;
+/// Longest process name kept. Longer names are cut at a char boundary.
+pub const PROCESS_NAME_MAX: usize = 32;
 
 type ProcessEntry = extern "C" fn() -> !;
 
-#[derive(Debug, Copy, Clone)]
-pub enum SeekFrom {
-    Start(u64),
-    Current(i64),
-    End(i64),
+/// A process name held inline, so one built at run time, like a spawned
+/// program's basename, lives and dies with its process.
+#[derive(Clone, Copy)]
+pub struct ProcessName {
+    bytes: [u8; PROCESS_NAME_MAX],
+    len: usize,
 }
 
-#[derive(Clone, Copy, Debug)]
+impl ProcessName {
+    pub fn new(name: &str) -> Self {
+        let mut len = name.len().min(PROCESS_NAME_MAX);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut bytes = [0u8; PROCESS_NAME_MAX];
+        bytes[..len].copy_from_slice(&name.as_bytes()[..len]);
+        Self { bytes, len }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ever filled from a `&str` cut at a char boundary.
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl fmt::Display for ProcessName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum MemoryRegionKind {
     Stack,
     Heap,
     Other,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct MemoryPermissions {
     read: bool,
     write: bool,
@@ -89,14 +135,26 @@ impl MemoryPermissions {
     }
 }
 
-#[derive(Clone, Copy)]
-struct MemoryRegion {
-    base: *mut u8,
-    layout: Layout,
+/// What a tracked range holds. Neighbouring ranges merge only when both
+/// fields match.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct RegionAttrs {
     kind: MemoryRegionKind,
     permissions: MemoryPermissions,
 }
 
+type MemoryRegion = Region<RegionAttrs>;
+
+impl From<RegionError> for ProcessError {
+    fn from(err: RegionError) -> Self {
+        match err {
+            RegionError::NoMemory => ProcessError::AllocationFailed,
+            RegionError::NotMapped => ProcessError::MemoryRegionNotFound,
+            RegionError::Empty | RegionError::Overlap => ProcessError::InvalidMemoryRange,
+        }
+    }
+}
+
 #[repr(C)]
 pub struct Context {
     pub r15: u64,
@@ -199,14 +257,27 @@ extern "C" {
 }
 
 extern "C" fn process_exit() -> ! {
-    klog!("[process] process exited unexpectedly\n");
-    exit_current(-1)
+    let pid = current_pid().expect("process_exit without a current process");
+    exit_current(returned_exit_code(pid))
+}
+
+/// Status for a task whose entry function returned into `process_exit`:
+/// `STACK_SMASH_STATUS` if it clobbered the word above its return slot on
+/// the way, -1 otherwise.
+pub fn returned_exit_code(pid: Pid) -> i32 {
+    if exit_frame_intact(pid).unwrap_or(true) {
+        klog!("[process] process exited unexpectedly\n");
+        -1
+    } else {
+        klog!("[process] pid {} smashed its stack: exit canary clobbered\n", pid);
+        STACK_SMASH_STATUS
+    }
 }
 
 extern "C" fn idle_task() -> ! {
     loop {
         if NEED_RESCHED.swap(false, Ordering::AcqRel) {
-            if schedule_internal() {
+            if schedule_internal(SwitchReason::Preempt) {
                 continue;
             }
         }
@@ -220,61 +291,6 @@ pub enum FileDescriptor {
     Vfs(VfsHandle),
 }
 
-pub struct VfsHandle {
-    file: &'static dyn VfsFile,
-    offset: u64,
-}
-
-impl VfsHandle {
-    pub fn new(file: &'static dyn VfsFile) -> Self {
-        Self { file, offset: 0 }
-    }
-
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, VfsError> {
-        let count = self.file.read_at(self.offset, buf)?;
-        self.offset = self.offset.saturating_add(count as u64);
-        Ok(count)
-    }
-
-    fn write(&mut self, buf: &[u8]) -> Result<usize, VfsError> {
-        let count = self.file.write_at(self.offset, buf)?;
-        self.offset = self.offset.saturating_add(count as u64);
-        Ok(count)
-    }
-
-    fn flush(&self) -> Result<(), VfsError> {
-        self.file.flush()
-    }
-
-    fn seek(&mut self, pos: SeekFrom) -> Result<u64, VfsError> {
-        let size = self.file.size()?;
-        let new_offset = match pos {
-            SeekFrom::Start(pos) => pos,
-            SeekFrom::Current(delta) => {
-                let base = self.offset as i128 + delta as i128;
-                if base < 0 {
-                    return Err(VfsError::InvalidOffset);
-                }
-                base as u64
-            }
-            SeekFrom::End(delta) => {
-                let base = size as i128 + delta as i128;
-                if base < 0 {
-                    return Err(VfsError::InvalidOffset);
-                }
-                base as u64
-            }
-        };
-
-        if new_offset > size {
-            return Err(VfsError::InvalidOffset);
-        }
-
-        self.offset = new_offset;
-        Ok(new_offset)
-    }
-}
-
 #[derive(Debug)]
 pub enum FileIoError {
     Driver(DriverError),
@@ -308,6 +324,15 @@ impl FileDescriptor {
         }
     }
 
+    /// Write as much of `buf` as the target takes, continuing short char
+    /// device writes. Char devices get it in `ATOMIC_WRITE_MAX` pieces.
+    pub fn write_all(&mut self, buf: &[u8]) -> Result<usize, FileIoError> {
+        match self {
+            FileDescriptor::Char(device) => drivers::write_all(*device, buf).map_err(FileIoError::from),
+            FileDescriptor::Vfs(handle) => handle.write(buf).map_err(FileIoError::from),
+        }
+    }
+
     pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, FileIoError> {
         match self {
             FileDescriptor::Char(device) => device.read(buf).map_err(FileIoError::from),
@@ -317,17 +342,81 @@ impl FileDescriptor {
 
     pub fn flush(&mut self) -> Result<(), FileIoError> {
         match self {
-            FileDescriptor::Char(_) => Ok(()),
+            FileDescriptor::Char(device) => device.flush().map_err(FileIoError::from),
             FileDescriptor::Vfs(handle) => handle.flush().map_err(FileIoError::from),
         }
     }
 
     pub fn seek(&mut self, pos: SeekFrom) -> Result<u64, FileIoError> {
         match self {
-            FileDescriptor::Char(_) => Err(FileIoError::Driver(DriverError::Unsupported)),
+            FileDescriptor::Char(device) => device.seek(pos).map_err(FileIoError::from),
             FileDescriptor::Vfs(handle) => handle.seek(pos).map_err(FileIoError::from),
         }
     }
+
+    /// Where a relative seek counts from: the cursor for `Current`, the file
+    /// size for `End`. `None` for `Start` and for char devices, which bound
+    /// their own seeks.
+    pub fn seek_origin(&self, pos: SeekFrom) -> Result<Option<u64>, FileIoError> {
+        match (self, pos) {
+            (FileDescriptor::Vfs(handle), SeekFrom::Current(_)) => Ok(Some(handle.offset())),
+            (FileDescriptor::Vfs(handle), SeekFrom::End(_)) => {
+                handle.file().size().map(Some).map_err(FileIoError::from)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Char devices have no backing size, so they report 0 and `mode::CHAR`.
+    pub fn stat(&self) -> Result<VfsFileStat, FileIoError> {
+        match self {
+            FileDescriptor::Char(_) => Ok(VfsFileStat {
+                size: 0,
+                mode: mode::CHAR | mode::READ | mode::WRITE,
+                block_size: 1,
+                created: 0,
+                modified: 0,
+                accessed: 0,
+            }),
+            FileDescriptor::Vfs(handle) => handle.file().stat().map_err(FileIoError::from),
+        }
+    }
+
+    pub fn can_read(&self) -> bool {
+        match self {
+            FileDescriptor::Char(device) => device.can_read(),
+            FileDescriptor::Vfs(handle) => handle.file().can_read(),
+        }
+    }
+
+    pub fn can_write(&self) -> bool {
+        match self {
+            FileDescriptor::Char(device) => device.can_write(),
+            FileDescriptor::Vfs(handle) => handle.file().can_write(),
+        }
+    }
+
+    /// Forward a control request to the underlying char device. Files have
+    /// no control commands.
+    pub fn ioctl(&self, cmd: u32, arg: u64) -> Result<u64, FileIoError> {
+        match self {
+            FileDescriptor::Char(device) => device.ioctl(cmd, arg).map_err(FileIoError::from),
+            FileDescriptor::Vfs(_) => Err(FileIoError::Driver(DriverError::Unsupported)),
+        }
+    }
+
+    /// Hand directory entries to `emit` starting at the handle's cursor.
+    /// Enumeration stops when `emit` returns `false`; that entry is not
+    /// consumed, so the next call resumes with it. Returns entries accepted.
+    pub fn read_dir<F>(&mut self, emit: F) -> Result<usize, FileIoError>
+    where
+        F: FnMut(&VfsDirEntry) -> bool,
+    {
+        match self {
+            FileDescriptor::Char(_) => Err(FileIoError::Driver(DriverError::Unsupported)),
+            FileDescriptor::Vfs(handle) => handle.read_dir(emit).map_err(FileIoError::from),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -341,16 +430,27 @@ pub enum ProcessState {
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum WaitChannel {
     KeyboardInput,
+    SerialInput,
     ChildAny,
     Child(Pid),
+    /// Opaque key chosen by a `sync::condvar` user.
+    Token(usize),
+    /// One `timer::sleep_ms` call, by sequence number.
+    Sleep(u64),
+    /// A `poll` caller. Every event wakes it so it can recheck its fds.
+    Poll,
 }
 
 impl WaitChannel {
     fn matches_event(self, event: WaitChannel) -> bool {
         match (self, event) {
             (WaitChannel::KeyboardInput, WaitChannel::KeyboardInput) => true,
+            (WaitChannel::SerialInput, WaitChannel::SerialInput) => true,
             (WaitChannel::ChildAny, WaitChannel::Child(_)) => true,
             (WaitChannel::Child(wait_pid), WaitChannel::Child(event_pid)) => wait_pid == event_pid,
+            (WaitChannel::Token(wait), WaitChannel::Token(event)) => wait == event,
+            (WaitChannel::Sleep(wait), WaitChannel::Sleep(event)) => wait == event,
+            (WaitChannel::Poll, _) => true,
             _ => false,
         }
     }
@@ -362,8 +462,15 @@ impl WaitChannel {
 
 pub struct Process {
     pid: Pid,
+    /// Thread group leader. Equal to `pid` for ordinary processes; threads
+    /// created by `spawn_thread` share the leader's address space and fds.
+    tgid: Pid,
     parent: Option<Pid>,
-    name: &'static str,
+    /// Process group, for job control and group-wide `kill`.
+    pgid: Pid,
+    /// Session the group belongs to. Fixed at spawn.
+    sid: Pid,
+    name: ProcessName,
     credentials: Credentials,
     address_space: AddressSpace,
     state: ProcessState,
@@ -372,11 +479,18 @@ pub struct Process {
     is_idle: bool,
     preempt_return: Option<u64>,
     cpu_slices: u64,
-    fds: [Option<FileDescriptor>; MAX_FDS],
+    /// Timer ticks spent running, not counting the current stint.
+    cpu_ticks: u64,
+    /// Tick at which the process was last switched in, while it runs.
+    run_started: Option<u64>,
+    /// Grows on demand up to `fd_limit`; a closed fd leaves a `None` hole
+    /// that the next open reuses.
+    fds: Vec<Option<FileDescriptor>>,
+    fd_limit: usize,
     context: Context,
     stack_ptr: *mut u8,
     stack_layout: Option<Layout>,
-    regions: MemoryRegionList,
+    regions: RegionList<RegionAttrs>,
     user_stack: Option<UserStack>,
     user_entry: Option<u64>,
 }
@@ -384,43 +498,23 @@ pub struct Process {
 impl Process {
     fn new_kernel(
         pid: Pid,
-        name: &'static str,
+        name: &str,
         parent: Option<Pid>,
         entry: ProcessEntry,
         is_idle: bool,
         credentials: Credentials,
     ) -> Result<Self, ProcessError> {
-        let layout = Layout::from_size_align(KERNEL_STACK_SIZE, 16).map_err(|_| {
-            klog!("[process] Process::new_user layout creation failed size={} align=16\n", KERNEL_STACK_SIZE);
-            ProcessError::StackAllocationFailed
-        })?;
-        let stack_ptr = unsafe { heap::allocate(layout) };
-        if stack_ptr.is_null() {
-            klog!("[process] Process::new_user heap allocation returned null\n");
-            return Err(ProcessError::StackAllocationFailed);
-        }
-
-        let stack_top = unsafe { stack_ptr.add(KERNEL_STACK_SIZE) } as u64;
-        let mut aligned_top = stack_top & !0xFu64;
-
-        unsafe {
-            aligned_top = aligned_top.saturating_sub(8);
-            (aligned_top as *mut u64).write(process_exit as u64);
-        }
-
-        let mut context = Context::new();
-        context.rsp = aligned_top;
-        context.rbp = aligned_top;
-        context.rip = entry as u64;
-
-        let fds: [Option<FileDescriptor>; MAX_FDS] = array::from_fn(|_| None);
+        let (stack_ptr, layout, context) = kernel_stack_for(entry)?;
 
         let address_space = AddressSpace::kernel();
 
         let mut process = Self {
             pid,
+            tgid: pid,
             parent,
-            name,
+            pgid: pid,
+            sid: pid,
+            name: ProcessName::new(name),
             credentials,
             address_space,
             state: ProcessState::Ready,
@@ -429,11 +523,14 @@ impl Process {
             is_idle,
             preempt_return: None,
             cpu_slices: 0,
-            fds,
+            cpu_ticks: 0,
+            run_started: None,
+            fds: Vec::new(),
+            fd_limit: DEFAULT_FD_LIMIT,
             context,
             stack_ptr,
             stack_layout: Some(layout),
-            regions: MemoryRegionList::new(),
+            regions: RegionList::new(),
             user_stack: None,
             user_entry: None,
         };
@@ -442,28 +539,24 @@ impl Process {
         process.set_fd(STDOUT_FD, FileDescriptor::Char(console_device))?;
         process.set_fd(STDERR_FD, FileDescriptor::Char(console_device))?;
 
-        let keyboard_device = keyboard::driver();
-        process.set_fd(STDIN_FD, FileDescriptor::Char(keyboard_device))?;
+        let tty_device = tty::driver();
+        process.set_fd(STDIN_FD, FileDescriptor::Char(tty_device))?;
 
         if let Some(file) = crate::vfs::ata::AtaScratchFile::get() {
             process.set_fd(SCRATCH_FD, FileDescriptor::Vfs(VfsHandle::new(file)))?;
         }
 
-        process.regions.register(MemoryRegion {
-            base: stack_ptr,
-            layout,
-            kind: MemoryRegionKind::Stack,
-            permissions: MemoryPermissions::read_write(),
-        })?;
+        process.regions.insert(stack_region(stack_ptr, layout))?;
 
         Ok(process)
     }
 
     fn new_user(
         pid: Pid,
-        name: &'static str,
+        name: &str,
         parent: Option<Pid>,
-        path: &'static str,
+        path: &str,
+        argv: &[&[u8]],
         credentials: Credentials,
     ) -> Result<Self, ProcessError> {
         klog!(
@@ -492,7 +585,7 @@ impl Process {
             data.len()
         );
 
-        let (address_space, user_stack) = create_default_user_address_space()?;
+        let (address_space, user_stack) = create_default_user_address_space(&image)?;
 
         klog!(
             "[process] Process::new_user address space cr3=0x{:016X} stack_top=0x{:016X} size={}\n",
@@ -509,6 +602,13 @@ impl Process {
         map_user_segments(&address_space, &image, &data)?;
         klog!("[process] Process::new_user segments mapped pid={}\n", pid);
 
+        let user_rsp = push_user_args(&address_space, user_stack.top(), argv)?;
+        klog!(
+            "[process] Process::new_user argv pushed argc={} rsp=0x{:016X}\n",
+            argv.len(),
+            user_rsp
+        );
+
         klog!(
             "[process] Process::new_user heap remaining after segments={}\n",
             heap::remaining_bytes()
@@ -544,6 +644,7 @@ impl Process {
             );
             return Err(ProcessError::StackAllocationFailed);
         }
+        write_stack_canary(stack_ptr);
 
         let remaining_after = heap::remaining_bytes();
         klog!(
@@ -566,7 +667,7 @@ impl Process {
         context.rbp = aligned_top;
         context.rip = usermode::trampoline() as usize as u64;
         context.r15 = image.entry;
-        context.r14 = user_stack.top();
+        context.r14 = user_rsp;
 
         klog!(
             "[process] Process::new_user context prepared rsp=0x{:016X} rip=0x{:016X} entry=0x{:016X}\n",
@@ -575,24 +676,22 @@ impl Process {
             image.entry
         );
 
-        unsafe {
-            aligned_top = aligned_top.saturating_sub(8);
-            (aligned_top as *mut u64).write(process_exit as u64);
-            context.rsp = aligned_top;
-            context.rbp = aligned_top;
-        }
+        aligned_top = push_exit_frame(stack_top);
+        context.rsp = aligned_top;
+        context.rbp = aligned_top;
 
         klog!(
             "[process] Process::new_user stack sentinel pushed new_rsp=0x{:016X}\n",
             context.rsp
         );
 
-        let fds: [Option<FileDescriptor>; MAX_FDS] = array::from_fn(|_| None);
-
         let mut process = Self {
             pid,
+            tgid: pid,
             parent,
-            name,
+            pgid: pid,
+            sid: pid,
+            name: ProcessName::new(name),
             credentials,
             address_space,
             state: ProcessState::Ready,
@@ -601,21 +700,19 @@ impl Process {
             is_idle: false,
             preempt_return: None,
             cpu_slices: 0,
-            fds,
+            cpu_ticks: 0,
+            run_started: None,
+            fds: Vec::new(),
+            fd_limit: DEFAULT_FD_LIMIT,
             context,
             stack_ptr,
             stack_layout: Some(layout),
-            regions: MemoryRegionList::new(),
+            regions: RegionList::new(),
             user_stack: Some(user_stack),
             user_entry: Some(image.entry),
         };
 
-        process.regions.register(MemoryRegion {
-            base: stack_ptr,
-            layout,
-            kind: MemoryRegionKind::Stack,
-            permissions: MemoryPermissions::read_write(),
-        })?;
+        process.regions.insert(stack_region(stack_ptr, layout))?;
 
         klog!("[process] Process::new_user kernel stack region registered pid={}\n", pid);
 
@@ -623,8 +720,8 @@ impl Process {
         process.set_fd(STDOUT_FD, FileDescriptor::Char(console_device))?;
         process.set_fd(STDERR_FD, FileDescriptor::Char(console_device))?;
 
-        let keyboard_device = keyboard::driver();
-        process.set_fd(STDIN_FD, FileDescriptor::Char(keyboard_device))?;
+        let tty_device = tty::driver();
+        process.set_fd(STDIN_FD, FileDescriptor::Char(tty_device))?;
 
         if let Some(file) = crate::vfs::ata::AtaScratchFile::get() {
             process.set_fd(SCRATCH_FD, FileDescriptor::Vfs(VfsHandle::new(file)))?;
@@ -635,12 +732,100 @@ impl Process {
         Ok(process)
     }
 
+    /// A new thread in `leader`'s group: its own kernel stack and context,
+    /// everything else borrowed from the leader. File descriptors stay in the
+    /// leader's table, so the thread's own table is left empty.
+    fn new_thread(pid: Pid, leader: &Process, parent: Option<Pid>, entry: ProcessEntry) -> Result<Self, ProcessError> {
+        let (stack_ptr, layout, context) = kernel_stack_for(entry)?;
+
+        let mut process = Self {
+            pid,
+            tgid: leader.tgid,
+            parent,
+            pgid: leader.pgid,
+            sid: leader.sid,
+            name: leader.name,
+            credentials: leader.credentials,
+            address_space: leader.address_space,
+            state: ProcessState::Ready,
+            wait_channel: None,
+            exit_code: None,
+            is_idle: false,
+            preempt_return: None,
+            cpu_slices: 0,
+            cpu_ticks: 0,
+            run_started: None,
+            fds: Vec::new(),
+            fd_limit: leader.fd_limit,
+            context,
+            stack_ptr,
+            stack_layout: Some(layout),
+            regions: RegionList::new(),
+            user_stack: leader.user_stack,
+            user_entry: None,
+        };
+
+        process.regions.insert(stack_region(stack_ptr, layout))?;
+
+        Ok(process)
+    }
+
     pub fn pid(&self) -> Pid {
         self.pid
     }
 
-    pub fn name(&self) -> &'static str {
-        self.name
+    pub fn tgid(&self) -> Pid {
+        self.tgid
+    }
+
+    pub fn pgid(&self) -> Pid {
+        self.pgid
+    }
+
+    pub fn sid(&self) -> Pid {
+        self.sid
+    }
+
+    /// Ticks of CPU time including a stint still in progress.
+    fn cpu_ticks_at(&self, now: u64) -> u64 {
+        let running = self.run_started.map(|start| now.saturating_sub(start)).unwrap_or(0);
+        self.cpu_ticks.saturating_add(running)
+    }
+
+    /// False once anything has written over `EXIT_CANARY`, the word above
+    /// the `process_exit` return slot at the top of the stack.
+    fn exit_frame_intact(&self) -> bool {
+        if self.stack_ptr.is_null() {
+            return true;
+        }
+        let top = self.stack_ptr as u64 + KERNEL_STACK_SIZE as u64;
+        unsafe { (exit_canary_slot(top) as *const u64).read() == EXIT_CANARY }
+    }
+
+    /// Where a trap or syscall from ring 3 starts on this process's kernel
+    /// stack: just under the exit frame, 16-byte aligned. Nothing else is
+    /// live on the stack while the process runs user code. `None` without
+    /// a stack of its own.
+    fn trap_stack_top(&self) -> Option<u64> {
+        if self.stack_ptr.is_null() {
+            return None;
+        }
+        let top = self.stack_ptr as u64 + KERNEL_STACK_SIZE as u64;
+        Some(exit_canary_slot(top) - 16)
+    }
+
+    /// False once anything has written over the canary words at the stack
+    /// base, as a task that overran its stack would.
+    fn stack_intact(&self) -> bool {
+        if self.stack_ptr.is_null() {
+            return true;
+        }
+        let base = self.stack_ptr as *const u64;
+        (0..STACK_CANARY_WORDS).all(|word| unsafe { base.add(word).read() } == STACK_CANARY)
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
     }
 
     pub fn state(&self) -> ProcessState {
@@ -700,29 +885,43 @@ impl Process {
     }
 
     fn set_fd(&mut self, index: usize, descriptor: FileDescriptor) -> Result<(), ProcessError> {
-        if index >= MAX_FDS {
+        if index >= self.fd_limit {
             return Err(ProcessError::InvalidFileDescriptor);
         }
+        if index >= self.fds.len() {
+            self.grow_fds(index + 1)?;
+        }
         self.fds[index] = Some(descriptor);
         Ok(())
     }
 
+    /// Lowest free fd below the limit, growing the table when every slot is
+    /// taken and the limit allows one more.
     fn allocate_fd_slot(&mut self, descriptor: FileDescriptor) -> Result<usize, ProcessError> {
-        for (index, slot) in self.fds.iter_mut().enumerate() {
-            if slot.is_none() {
-                *slot = Some(descriptor);
-                return Ok(index);
+        let index = match self.fds.iter().take(self.fd_limit).position(|slot| slot.is_none()) {
+            Some(index) => index,
+            None if self.fds.len() < self.fd_limit => {
+                self.grow_fds(self.fds.len() + 1)?;
+                self.fds.len() - 1
             }
-        }
-        Err(ProcessError::NoFreeFileDescriptors)
+            None => return Err(ProcessError::NoFreeFileDescriptors),
+        };
+        self.fds[index] = Some(descriptor);
+        Ok(index)
+    }
+
+    fn grow_fds(&mut self, len: usize) -> Result<(), ProcessError> {
+        self.fds
+            .try_reserve(len - self.fds.len())
+            .map_err(|_| ProcessError::AllocationFailed)?;
+        self.fds.resize_with(len, || None);
+        Ok(())
     }
 
     fn release_fd_slot(&mut self, index: usize) -> Result<FileDescriptor, ProcessError> {
-        if index >= MAX_FDS {
-            return Err(ProcessError::InvalidFileDescriptor);
-        }
-        self.fds[index]
-            .take()
+        self.fds
+            .get_mut(index)
+            .and_then(Option::take)
             .ok_or(ProcessError::InvalidFileDescriptor)
     }
 
@@ -744,12 +943,13 @@ impl Process {
         if ptr.is_null() {
             return Err(ProcessError::AllocationFailed);
         }
-        self.regions.register(MemoryRegion {
-            base: ptr,
-            layout,
-            kind,
-            permissions,
-        })?;
+        let region = Region::new(ptr as usize, heap::reserved_size(layout), RegionAttrs { kind, permissions });
+        if let Err(err) = self.regions.insert(region) {
+            unsafe {
+                heap::deallocate(ptr, layout);
+            }
+            return Err(err.into());
+        }
         Ok(ptr)
     }
 
@@ -757,24 +957,41 @@ impl Process {
         self.allocate_region_with_permissions(layout, kind, MemoryPermissions::read_write())
     }
 
-    fn release_region(&mut self, ptr: *mut u8) -> Result<(), ProcessError> {
-        if let Some(region) = self.regions.remove_by_ptr(ptr) {
-            if !region.base.is_null() {
-                unsafe {
-                    heap::deallocate(region.base, region.layout);
-                }
-            }
-            Ok(())
-        } else {
-            Err(ProcessError::MemoryRegionNotFound)
-        }
+    /// Free `len` bytes at `ptr` from the process's heap-backed regions,
+    /// splitting any region the range only partly covers. Stack pages are
+    /// owned by `stack_layout` and cannot be released this way.
+    fn release_region(&mut self, ptr: *mut u8, len: usize) -> Result<usize, ProcessError> {
+        let start = ptr as usize;
+        let end = start.checked_add(len).ok_or(ProcessError::InvalidMemoryRange)?;
+        let touches_stack = self
+            .regions
+            .iter()
+            .any(|region| region.attrs.kind == MemoryRegionKind::Stack && region.start < end && start < region.end);
+        if touches_stack {
+            return Err(ProcessError::InvalidMemoryRange);
+        }
+        let released = self.regions.remove(start, end, |piece| unsafe {
+            heap::release(piece.start as *mut u8, piece.len());
+        })?;
+        Ok(released)
     }
 }
 
+fn stack_region(stack_ptr: *mut u8, layout: Layout) -> MemoryRegion {
+    Region::new(
+        stack_ptr as usize,
+        layout.size(),
+        RegionAttrs {
+            kind: MemoryRegionKind::Stack,
+            permissions: MemoryPermissions::read_write(),
+        },
+    )
+}
+
 impl Drop for Process {
     fn drop(&mut self) {
         for region in self.regions.drain() {
-            match region.kind {
+            match region.attrs.kind {
                 MemoryRegionKind::Stack => {
                     if let Some(layout) = self.stack_layout.take() {
                         unsafe {
@@ -783,7 +1000,7 @@ impl Drop for Process {
                     }
                 }
                 _ => unsafe {
-                    heap::deallocate(region.base, region.layout);
+                    heap::release(region.start as *mut u8, region.len());
                 },
             }
         }
@@ -800,147 +1017,91 @@ pub enum ProcessError {
     StackAllocationFailed,
     NotInitialized,
     MemoryRegionNotFound,
+    /// An empty, wrapping or overlapping range, or one that covers a stack.
+    InvalidMemoryRange,
     IdleAlreadyExists,
     NoChildren,
     ChildNotFound,
     NoFreeFileDescriptors,
+    /// A descriptor limit of 0 or above `MAX_FD_LIMIT`.
+    InvalidFdLimit,
     AddressSpaceAllocationFailed,
     InvalidUserPointer,
     UserMemoryNotPresent,
     InvalidElf,
     UserImageIo,
+    ArgumentListTooLong,
+    /// `read_user_cstr` reached its limit without finding a NUL.
+    UnterminatedString,
+    /// The caller may not act on that process or group.
+    NotPermitted,
+    /// Opening a filesystem path failed; carries the filesystem's reason.
+    Vfs(VfsError),
 }
 
-struct MemoryRegionList {
-    regions: *mut MemoryRegion,
-    len: usize,
-    capacity: usize,
+/// Why the scheduler switched away from a process.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SwitchReason {
+    Yield,
+    Preempt,
+    Block,
+    Exit,
 }
 
-impl MemoryRegionList {
-    const fn new() -> Self {
-        Self {
-            regions: ptr::null_mut(),
-            len: 0,
-            capacity: 0,
-        }
-    }
-
-    fn register(&mut self, region: MemoryRegion) -> Result<(), ProcessError> {
-        self.ensure_capacity(1)?;
-        unsafe {
-            self.regions.add(self.len).write(region);
-        }
-        self.len += 1;
-        Ok(())
-    }
+/// One context switch seen by the trace. `from` is 0 when the switch left
+/// the boot context rather than a process.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SwitchRecord {
+    pub from: Pid,
+    pub to: Pid,
+    pub tick: u64,
+    pub reason: SwitchReason,
+}
 
-    fn remove_by_ptr(&mut self, ptr: *mut u8) -> Option<MemoryRegion> {
-        for index in 0..self.len {
-            unsafe {
-                let entry_ptr = self.regions.add(index);
-                if (*entry_ptr).base == ptr {
-                    let removed = entry_ptr.read();
-                    if index != self.len - 1 {
-                        let last = self.regions.add(self.len - 1).read();
-                        self.regions.add(index).write(last);
-                    }
-                    self.len -= 1;
-                    return Some(removed);
-                }
-            }
-        }
-        None
-    }
+impl SwitchRecord {
+    const EMPTY: Self = Self {
+        from: 0,
+        to: 0,
+        tick: 0,
+        reason: SwitchReason::Yield,
+    };
+}
 
-    fn iter(&self) -> core::slice::Iter<'_, MemoryRegion> {
-        self.as_slice().iter()
-    }
+/// Switches the trace keeps; once full, each new one drops the oldest.
+pub const SWITCH_TRACE_LEN: usize = 64;
 
-    fn drain(&mut self) -> DrainMemoryRegions {
-        DrainMemoryRegions { list: self }
-    }
+static SWITCH_TRACE_ON: AtomicBool = AtomicBool::new(false);
 
-    fn as_slice(&self) -> &[MemoryRegion] {
-        if self.len == 0 {
-            &[]
-        } else {
-            unsafe { slice::from_raw_parts(self.regions, self.len) }
-        }
-    }
+/// Ring of the latest `SWITCH_TRACE_LEN` switches, filled under the table
+/// lock by `mark_switch` while tracing is on.
+struct SwitchTrace {
+    records: [SwitchRecord; SWITCH_TRACE_LEN],
+    next: usize,
+    len: usize,
+}
 
-    fn as_slice_mut(&mut self) -> &mut [MemoryRegion] {
-        if self.len == 0 {
-            &mut []
-        } else {
-            unsafe { slice::from_raw_parts_mut(self.regions, self.len) }
+impl SwitchTrace {
+    const fn new() -> Self {
+        Self {
+            records: [SwitchRecord::EMPTY; SWITCH_TRACE_LEN],
+            next: 0,
+            len: 0,
         }
     }
 
-    fn ensure_capacity(&mut self, additional: usize) -> Result<(), ProcessError> {
-        let required = self.len.checked_add(additional).ok_or(ProcessError::AllocationFailed)?;
-        if required <= self.capacity {
-            return Ok(());
-        }
-
-        let mut new_capacity = if self.capacity == 0 { 4 } else { self.capacity };
-        while new_capacity < required {
-            new_capacity = new_capacity.checked_mul(2).ok_or(ProcessError::AllocationFailed)?;
-        }
-
-        let layout = Layout::array::<MemoryRegion>(new_capacity).map_err(|_| ProcessError::AllocationFailed)?;
-        let new_ptr = unsafe { heap::allocate(layout) } as *mut MemoryRegion;
-        if new_ptr.is_null() {
-            return Err(ProcessError::AllocationFailed);
-        }
-
-        unsafe {
-            if self.len > 0 {
-                ptr::copy(self.regions, new_ptr, self.len);
-            }
-        }
-
-        if self.capacity != 0 {
-            let old_layout = Layout::array::<MemoryRegion>(self.capacity).map_err(|_| ProcessError::AllocationFailed)?;
-            unsafe {
-                heap::deallocate(self.regions as *mut u8, old_layout);
-            }
-        }
-
-        self.regions = new_ptr;
-        self.capacity = new_capacity;
-        Ok(())
+    fn push(&mut self, record: SwitchRecord) {
+        self.records[self.next] = record;
+        self.next = (self.next + 1) % SWITCH_TRACE_LEN;
+        self.len = (self.len + 1).min(SWITCH_TRACE_LEN);
     }
-}
 
-impl Drop for MemoryRegionList {
-    fn drop(&mut self) {
-        if self.capacity != 0 && !self.regions.is_null() {
-            if let Ok(layout) = Layout::array::<MemoryRegion>(self.capacity) {
-                unsafe {
-                    heap::deallocate(self.regions as *mut u8, layout);
-                }
-            }
-        }
-        self.regions = ptr::null_mut();
+    fn drain(&mut self) -> Vec<SwitchRecord> {
+        let start = (self.next + SWITCH_TRACE_LEN - self.len) % SWITCH_TRACE_LEN;
+        let records = (0..self.len)
+            .map(|i| self.records[(start + i) % SWITCH_TRACE_LEN])
+            .collect();
         self.len = 0;
-        self.capacity = 0;
-    }
-}
-
-struct DrainMemoryRegions<'a> {
-    list: &'a mut MemoryRegionList,
-}
-
-impl<'a> Iterator for DrainMemoryRegions<'a> {
-    type Item = MemoryRegion;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.list.len == 0 {
-            return None;
-        }
-        self.list.len -= 1;
-        unsafe { Some(self.list.regions.add(self.list.len).read()) }
+        records
     }
 }
 
@@ -948,10 +1109,14 @@ struct ProcessTable {
     entries: *mut Process,
     len: usize,
     capacity: usize,
+    /// Table indices of `Ready` processes in the order they will run. The
+    /// idle task is never queued; it runs only when this is empty.
+    run_queue: VecDeque<usize>,
     next_pid: Pid,
     init_pid: Option<Pid>,
     idle_pid: Option<Pid>,
     initialized: bool,
+    switch_trace: SwitchTrace,
 }
 
 unsafe impl Send for ProcessTable {}
@@ -962,16 +1127,18 @@ impl ProcessTable {
             entries: ptr::null_mut(),
             len: 0,
             capacity: 0,
+            run_queue: VecDeque::new(),
             next_pid: 1,
             init_pid: None,
             idle_pid: None,
             initialized: false,
+            switch_trace: SwitchTrace::new(),
         }
     }
 
     fn spawn_kernel_process(
         &mut self,
-        name: &'static str,
+        name: &str,
         parent: Option<Pid>,
         entry: ProcessEntry,
         is_idle: bool,
@@ -985,7 +1152,8 @@ impl ProcessTable {
             Credentials::root()
         };
 
-        let process = Process::new_kernel(pid, name, parent, entry, is_idle, credentials)?;
+        let mut process = Process::new_kernel(pid, name, parent, entry, is_idle, credentials)?;
+        self.inherit_group(&mut process);
         self.push(process)?;
         if is_idle {
             self.idle_pid = Some(pid);
@@ -997,9 +1165,10 @@ impl ProcessTable {
 
     fn spawn_user_process(
         &mut self,
-        name: &'static str,
+        name: &str,
         parent: Option<Pid>,
-        path: &'static str,
+        path: &str,
+        argv: &[&[u8]],
     ) -> Result<Pid, ProcessError> {
         let pid = self.allocate_pid()?;
         klog!(
@@ -1026,7 +1195,8 @@ impl ProcessTable {
             credentials.is_privileged()
         );
 
-        let process = Process::new_user(pid, name, parent, path, credentials)?;
+        let mut process = Process::new_user(pid, name, parent, path, argv, credentials)?;
+        self.inherit_group(&mut process);
         klog!(
             "[process] table.spawn_user_process new_user constructed pid={} state={:?}\n",
             pid,
@@ -1037,6 +1207,32 @@ impl ProcessTable {
         Ok(pid)
     }
 
+    /// Put a new process in its parent's group and session. One with no
+    /// parent keeps the fresh group and session named after itself.
+    fn inherit_group(&self, process: &mut Process) {
+        if let Some(parent) = process.parent.and_then(|pid| self.get(pid)) {
+            process.pgid = parent.pgid;
+            process.sid = parent.sid;
+        }
+    }
+
+    /// Whether any live process is in group `pgid` of session `sid`.
+    fn group_exists(&self, pgid: Pid, sid: Pid) -> bool {
+        self.slice()
+            .iter()
+            .any(|p| p.pgid == pgid && p.sid == sid && p.state != ProcessState::Zombie)
+    }
+
+    fn spawn_thread(&mut self, creator: Pid, entry: ProcessEntry) -> Result<Pid, ProcessError> {
+        let pid = self.allocate_pid()?;
+        let process = {
+            let leader = self.get(creator).ok_or(ProcessError::ProcessNotFound)?;
+            Process::new_thread(pid, leader, Some(creator), entry)?
+        };
+        self.push(process)?;
+        Ok(pid)
+    }
+
     fn allocate_pid(&mut self) -> Result<Pid, ProcessError> {
         let pid = self.next_pid;
         self.next_pid = self.next_pid.checked_add(1).ok_or(ProcessError::TooManyProcesses)?;
@@ -1045,20 +1241,31 @@ impl ProcessTable {
 
     fn push(&mut self, process: Process) -> Result<(), ProcessError> {
         self.ensure_capacity(1)?;
+        let ready = process.state == ProcessState::Ready && !process.is_idle;
         unsafe {
             self.entries.add(self.len).write(process);
         }
+        if ready {
+            self.run_queue.push_back(self.len);
+        }
         self.len += 1;
         Ok(())
     }
 
     fn remove_index(&mut self, index: usize) -> Process {
         assert!(index < self.len);
+        self.dequeue(index);
         unsafe {
             let removed = self.entries.add(index).read();
-            if index != self.len - 1 {
-                let moved = self.entries.add(self.len - 1).read();
+            let last = self.len - 1;
+            if index != last {
+                let moved = self.entries.add(last).read();
                 self.entries.add(index).write(moved);
+                for queued in self.run_queue.iter_mut() {
+                    if *queued == last {
+                        *queued = index;
+                    }
+                }
             }
             self.len -= 1;
             if Some(removed.pid) == self.idle_pid {
@@ -1067,10 +1274,46 @@ impl ProcessTable {
             if Some(removed.pid) == self.init_pid {
                 self.init_pid = None;
             }
+            let cr3 = removed.address_space.cr3();
+            if !self.slice().iter().any(|process| process.address_space.cr3() == cr3) {
+                release_user_address_space(removed.address_space, removed.user_stack);
+            }
             removed
         }
     }
 
+    /// The process whose descriptor table `pid` uses: its thread group
+    /// leader, or `pid` itself once the leader has been reaped.
+    fn fd_owner_mut(&mut self, pid: Pid) -> Option<&mut Process> {
+        let tgid = self.get(pid)?.tgid;
+        let owner = if self.find_index_by_pid(tgid).is_some() { tgid } else { pid };
+        self.get_mut(owner)
+    }
+
+    /// Whether a member of thread group `tgid` other than `pid` has not
+    /// exited yet.
+    fn group_has_live_member(&self, tgid: Pid, pid: Pid) -> bool {
+        self.slice()
+            .iter()
+            .any(|p| p.pid != pid && p.tgid == tgid && p.state != ProcessState::Zombie)
+    }
+
+    /// Empty the descriptor table `pid` used, unless another live member of
+    /// its thread group still shares it. Called once `pid` is a zombie. The
+    /// leader is not reaped while its threads run, so the last one out still
+    /// finds the table in the leader's entry.
+    fn take_exit_descriptors(&mut self, pid: Pid) -> Vec<Option<FileDescriptor>> {
+        let tgid = match self.get(pid) {
+            Some(process) => process.tgid,
+            None => return Vec::new(),
+        };
+        let shared = self.group_has_live_member(tgid, pid);
+        match self.fd_owner_mut(pid) {
+            Some(owner) if !shared => core::mem::take(&mut owner.fds),
+            _ => Vec::new(),
+        }
+    }
+
     fn ensure_capacity(&mut self, additional: usize) -> Result<(), ProcessError> {
         let required = self.len.checked_add(additional).ok_or(ProcessError::TooManyProcesses)?;
         if required <= self.capacity {
@@ -1105,9 +1348,27 @@ impl ProcessTable {
 
         self.entries = new_ptr;
         self.capacity = new_capacity;
+
+        // Keep the queue able to hold every process so the scheduler never
+        // allocates while switching.
+        let queued = self.run_queue.len();
+        self.run_queue.reserve(new_capacity - queued);
         Ok(())
     }
 
+    /// Put a process that just became `Ready` at the back of the queue.
+    fn enqueue(&mut self, index: usize) {
+        if !self.slice()[index].is_idle {
+            self.run_queue.push_back(index);
+        }
+    }
+
+    /// Drop a process from the queue when it leaves `Ready` other than by
+    /// being picked to run.
+    fn dequeue(&mut self, index: usize) {
+        self.run_queue.retain(|&queued| queued != index);
+    }
+
     fn slice(&self) -> &[Process] {
         if self.len == 0 {
             &[]
@@ -1156,8 +1417,13 @@ impl ProcessTable {
                         continue;
                     }
                 }
+                // A leader holds its group's descriptor table, so it waits
+                // for the rest of the group before it can be reaped.
+                let (pid, tgid) = ((*entry_ptr).pid, (*entry_ptr).tgid);
+                if pid == tgid && self.group_has_live_member(tgid, pid) {
+                    continue;
+                }
 
-                let pid = (*entry_ptr).pid;
                 let code = (*entry_ptr).exit_code.unwrap_or(0);
                 let process = self.remove_index(index);
                 drop(process);
@@ -1167,38 +1433,72 @@ impl ProcessTable {
         None
     }
 
-    fn next_ready_index(&self, start: Option<usize>) -> Option<usize> {
-        if self.len == 0 {
-            return None;
+    /// Choose the process to run after `current`. A still-running current
+    /// process goes to the back of the queue first, so a lone task keeps the
+    /// CPU and several ready tasks take strict turns. Falls back to idle.
+    fn pick_next(&mut self, current: Option<usize>) -> Option<usize> {
+        if let Some(idx) = current {
+            let process = &self.slice()[idx];
+            if process.state == ProcessState::Running && !process.is_idle {
+                self.run_queue.push_back(idx);
+            }
         }
 
-        let slice = self.slice();
-        let mut index = start.map(|i| (i + 1) % self.len).unwrap_or(0);
-        let mut inspected = 0;
-        let mut idle_candidate = None;
-        while inspected < self.len {
-            let process = &slice[index];
-            match process.state {
-                ProcessState::Ready => {
-                    if process.is_idle {
-                        if idle_candidate.is_none() {
-                            idle_candidate = Some(index);
-                        }
-                    } else {
-                        return Some(index);
-                    }
+        if let Some(next) = self.run_queue.pop_front() {
+            return Some(next);
+        }
+
+        let idle = self.find_index_by_pid(self.idle_pid?)?;
+        match self.slice()[idle].state {
+            ProcessState::Ready | ProcessState::Running => Some(idle),
+            _ => None,
+        }
+    }
+
+    /// State changes for handing the CPU from `current` to `next`.
+    /// `reason` is what the caller asked for; a process that is leaving
+    /// because it blocked or exited is recorded as such instead.
+    fn mark_switch(&mut self, current: Option<usize>, next: usize, reason: SwitchReason) {
+        let now = timer::ticks();
+        LAST_SWITCH_TICK.store(now, Ordering::Relaxed);
+        if SWITCH_TRACE_ON.load(Ordering::Relaxed) {
+            let from = current.and_then(|idx| self.slice().get(idx));
+            let record = SwitchRecord {
+                from: from.map_or(0, |process| process.pid),
+                to: self.slice()[next].pid,
+                tick: now,
+                reason: match from.map(|process| process.state) {
+                    Some(ProcessState::Blocked) => SwitchReason::Block,
+                    Some(ProcessState::Zombie) => SwitchReason::Exit,
+                    _ => reason,
+                },
+            };
+            self.switch_trace.push(record);
+        }
+        let slice = self.slice_mut();
+        if let Some(idx) = current {
+            if let Some(process) = slice.get_mut(idx) {
+                if let Some(start) = process.run_started.take() {
+                    process.cpu_ticks = process.cpu_ticks.saturating_add(now.saturating_sub(start));
                 }
-                ProcessState::Running => {
-                    if process.is_idle && idle_candidate.is_none() {
-                        idle_candidate = Some(index);
-                    }
+                if process.state == ProcessState::Running {
+                    process.state = ProcessState::Ready;
+                    //klog!("[process] schedule_internal demoted pid={} -> Ready\n", process.pid);
                 }
-                _ => {}
             }
-            index = (index + 1) % self.len;
-            inspected += 1;
         }
-        idle_candidate
+
+        if let Some(process) = slice.get_mut(next) {
+            process.state = ProcessState::Running;
+            process.cpu_slices = process.cpu_slices.saturating_add(1);
+            process.run_started = Some(now);
+            klog!(
+                "[sched] promote pid={} slices={} kind={:?}\n",
+                process.pid,
+                process.cpu_slices,
+                process.address_space.kind()
+            );
+        }
     }
 
     fn get_mut(&mut self, pid: Pid) -> Option<&mut Process> {
@@ -1236,9 +1536,23 @@ impl Drop for ProcessTable {
     }
 }
 
-static PROCESS_TABLE: SpinLock<ProcessTable> = SpinLock::new(ProcessTable::new());
-static CURRENT_PID: AtomicU32 = AtomicU32::new(0);
-static mut BOOT_CONTEXT: Context = Context::new();
+/// Where a CPU's pre-scheduler state is saved by its first switch into a
+/// process. Only the CPU owning the slot ever touches it.
+struct BootContext(UnsafeCell<Context>);
+
+unsafe impl Sync for BootContext {}
+
+impl BootContext {
+    const fn new() -> Self {
+        Self(UnsafeCell::new(Context::new()))
+    }
+}
+
+// Ticket lock: the timer IRQ and every task contend for the table, and a
+// test-and-set lock can starve one of them indefinitely.
+static PROCESS_TABLE: TicketLock<ProcessTable> = TicketLock::new(ProcessTable::new());
+static CURRENT_PID: CpuLocal<AtomicU32> = CpuLocal::new([const { AtomicU32::new(0) }; MAX_CPUS]);
+static BOOT_CONTEXT: CpuLocal<BootContext> = CpuLocal::new([const { BootContext::new() }; MAX_CPUS]);
 static NEED_RESCHED: AtomicBool = AtomicBool::new(false);
 
 pub fn init() -> Result<(), ProcessError> {
@@ -1262,9 +1576,23 @@ pub fn spawn_kernel_process(name: &'static str, entry: ProcessEntry) -> Result<P
     Ok(pid)
 }
 
-pub fn spawn_user_process(name: &'static str, path: &'static str) -> Result<Pid, ProcessError> {
+pub fn spawn_user_process(name: &str, path: &str) -> Result<Pid, ProcessError> {
+    spawn_user_process_with_args(name, path, &[])
+}
+
+/// Spawn a user process whose initial stack carries `argv` in the SysV
+/// layout: `argc`, the argument pointers, a NULL, and an empty environment.
+pub fn spawn_user_process_with_args(
+    name: &str,
+    path: &str,
+    argv: &[&[u8]],
+) -> Result<Pid, ProcessError> {
     klog!("[process] spawn_user_process enter name='{}' path='{}'\n", name, path);
 
+    if argv.len() > MAX_ARGS {
+        return Err(ProcessError::ArgumentListTooLong);
+    }
+
     let mut table = PROCESS_TABLE.lock();
     if !table.initialized {
         klog!("[process] spawn_user_process aborted: table not initialised\n");
@@ -1280,11 +1608,47 @@ pub fn spawn_user_process(name: &'static str, path: &'static str) -> Result<Pid,
         table.init_pid
     );
 
-    let pid = table.spawn_user_process(name, parent, path)?;
+    let pid = table.spawn_user_process(name, parent, path, argv)?;
     klog!("[process] spawn_user_process success pid={} name='{}' path='{}'\n", pid, name, path);
     Ok(pid)
 }
 
+/// Start the first userspace process. `path` is usually the `init=` word of
+/// the kernel command line. When it is missing, or the program fails to
+/// load, `fallback` runs as a kernel task instead, so the machine always
+/// boots into something.
+pub fn spawn_init(path: Option<&str>, fallback: ProcessEntry) -> Result<Pid, ProcessError> {
+    if let Some(path) = path {
+        match spawn_user_process("init", path) {
+            Ok(pid) => {
+                let mut table = PROCESS_TABLE.lock();
+                if table.init_pid.is_none() {
+                    table.init_pid = Some(pid);
+                }
+                klog!("[process] init is '{}' (pid {})\n", path, pid);
+                return Ok(pid);
+            }
+            Err(err) => klog!("[process] init '{}' failed to load: {:?}; using the built-in shell\n", path, err),
+        }
+    }
+    spawn_kernel_process("init", fallback)
+}
+
+/// Start `entry` as a thread of the calling process: a separate kernel stack
+/// and context scheduled on its own, sharing the caller's address space,
+/// credentials and file descriptors. The caller is its parent, so
+/// `wait_for_child` joins it.
+pub fn spawn_thread(entry: ProcessEntry) -> Result<Pid, ProcessError> {
+    let creator = current_pid().ok_or(ProcessError::ProcessNotFound)?;
+    let mut table = PROCESS_TABLE.lock();
+    if !table.initialized {
+        return Err(ProcessError::NotInitialized);
+    }
+    let pid = table.spawn_thread(creator, entry)?;
+    klog!("[process] spawned thread pid={} tgid={}\n", pid, table.get(pid).map(|p| p.tgid).unwrap_or(pid));
+    Ok(pid)
+}
+
 pub fn spawn_idle_process(name: &'static str, entry: ProcessEntry) -> Result<Pid, ProcessError> {
     let mut table = PROCESS_TABLE.lock();
     if !table.initialized {
@@ -1302,7 +1666,7 @@ pub fn start_scheduler() -> ! {
     klog!("[process] starting scheduler\n");
 
     loop {
-        if !schedule_internal() {
+        if !schedule_internal(SwitchReason::Yield) {
             klog!("[process] start_scheduler idle spin\n");
             core::hint::spin_loop();
         }
@@ -1313,45 +1677,218 @@ pub fn start_scheduler() -> ! {
 
 pub fn yield_now() {
     klog!("[process] yield_now invoked\n");
-    let _ = schedule_internal();
+    let _ = schedule_internal(SwitchReason::Yield);
 }
 
 fn reschedule() {
     klog!("[process] reschedule begin\n");
-    while !schedule_internal() {
+    // Callers have already marked the process blocked or exited, which the
+    // trace records in place of `Yield`.
+    while !schedule_internal(SwitchReason::Yield) {
         klog!("[process] reschedule retry\n");
         core::hint::spin_loop();
     }
     klog!("[process] reschedule complete\n");
 }
 
-pub fn block_current(channel: WaitChannel) -> Result<(), ProcessError> {
-    let pid = current_pid().ok_or(ProcessError::ProcessNotFound)?;
-    {
-        let mut table = PROCESS_TABLE.lock();
-        let process = table.get_mut(pid).ok_or(ProcessError::ProcessNotFound)?;
-        process.state = ProcessState::Blocked;
-        process.wait_channel = Some(channel);
-        process.preempt_return = None;
-    }
-    reschedule();
-    Ok(())
+/// Do everything a reschedule does except the context switch, and return the
+/// pid now marked running. The test harness never starts the scheduler, so
+/// this is how it observes run-queue order.
+#[cfg(kernel_test)]
+pub fn schedule_dry_run() -> Option<Pid> {
+    let pid = {
+        let mut table = PROCESS_TABLE.lock();
+        let current_index = current_pid().and_then(|pid| table.find_index_by_pid(pid));
+        let next_index = table.pick_next(current_index)?;
+        if current_index != Some(next_index) {
+            table.mark_switch(current_index, next_index, SwitchReason::Yield);
+        } else {
+            table.slice_mut()[next_index].state = ProcessState::Running;
+        }
+        table.slice()[next_index].pid
+    };
+    set_current_pid(pid);
+    Some(pid)
+}
+
+pub fn block_current(channel: WaitChannel) -> Result<(), ProcessError> {
+    block_current_then(channel, || {})
+}
+
+/// Mark the current process blocked on `channel`, run `before_switch`, then
+/// give up the CPU. A wakeup that lands after the state change but before
+/// the switch is not lost, which lets callers release a lock guarding the
+/// condition they are waiting on inside `before_switch`.
+pub fn block_current_then<F: FnOnce()>(channel: WaitChannel, before_switch: F) -> Result<(), ProcessError> {
+    let pid = current_pid().ok_or(ProcessError::ProcessNotFound)?;
+    {
+        let mut table = PROCESS_TABLE.lock();
+        let process = table.get_mut(pid).ok_or(ProcessError::ProcessNotFound)?;
+        process.state = ProcessState::Blocked;
+        process.wait_channel = Some(channel);
+        process.preempt_return = None;
+    }
+    before_switch();
+
+    let still_blocked = {
+        let mut table = PROCESS_TABLE.lock();
+        match table.find_index_by_pid(pid) {
+            Some(index) if table.slice()[index].state == ProcessState::Ready => {
+                // Woken before we switched away; take ourselves back off the
+                // run queue and keep going.
+                table.dequeue(index);
+                table.slice_mut()[index].state = ProcessState::Running;
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    };
+    if still_blocked {
+        reschedule();
+    }
+    Ok(())
+}
+
+/// Wake every process blocked on a channel matching `event`, returning how
+/// many were woken.
+pub fn wake_channel(event: WaitChannel) -> usize {
+    wake_matching(event, usize::MAX)
+}
+
+/// Wake the first process, in table order, blocked on a channel matching
+/// `event`. Returns false if nobody was waiting.
+pub fn wake_one(event: WaitChannel) -> bool {
+    wake_matching(event, 1) == 1
+}
+
+/// `wake_channel` for interrupt handlers: if the interrupted code holds the
+/// process table, nobody is woken and `None` comes back so the caller can
+/// try again later instead of spinning on a lock that cannot be released.
+pub fn try_wake_channel(event: WaitChannel) -> Option<usize> {
+    let mut table = PROCESS_TABLE.try_lock()?;
+    Some(wake_in(&mut table, event, usize::MAX))
+}
+
+fn wake_matching(event: WaitChannel, limit: usize) -> usize {
+    let mut table = PROCESS_TABLE.lock();
+    wake_in(&mut table, event, limit)
+}
+
+fn wake_in(table: &mut ProcessTable, event: WaitChannel, limit: usize) -> usize {
+    let mut woken = 0;
+    for index in 0..table.len {
+        if woken == limit {
+            break;
+        }
+        let process = &mut table.slice_mut()[index];
+        if process.state != ProcessState::Blocked {
+            continue;
+        }
+        if let Some(channel) = process.wait_channel {
+            if channel.matches_event(event) {
+                process.wait_channel = None;
+                process.state = ProcessState::Ready;
+                process.preempt_return = None;
+                table.enqueue(index);
+                // Pollers only eavesdrop; they must not use up a `wake_one`.
+                if channel != WaitChannel::Poll {
+                    woken += 1;
+                }
+            }
+        }
+    }
+    woken
+}
+
+/// Earliest tick at which a sleeping `poll` wants waking, or `u64::MAX`.
+static POLL_DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Ask the timer to wake `WaitChannel::Poll` sleepers once `tick` is
+/// reached. Only the earliest armed deadline is kept; sleepers woken early
+/// re-arm their own.
+pub fn arm_poll_deadline(tick: u64) {
+    POLL_DEADLINE.fetch_min(tick, Ordering::AcqRel);
+}
+
+/// Called from the timer interrupt with the current tick. The deadline is
+/// only cleared once the wake has gone through; if the interrupted code
+/// holds the process table it stays armed and the next tick tries again.
+pub fn expire_poll_deadline(now: u64) {
+    let deadline = POLL_DEADLINE.load(Ordering::Acquire);
+    if now < deadline {
+        return;
+    }
+    let Some(mut table) = PROCESS_TABLE.try_lock() else {
+        return;
+    };
+    // An earlier deadline armed meanwhile fails the swap; it has passed too,
+    // so the next tick takes it.
+    if POLL_DEADLINE
+        .compare_exchange(deadline, u64::MAX, Ordering::AcqRel, Ordering::Relaxed)
+        .is_ok()
+    {
+        wake_in(&mut table, WaitChannel::Poll, usize::MAX);
+    }
+}
+
+/// How long runnable processes may go without a context switch before the
+/// watchdog dumps the process table.
+pub const WATCHDOG_MS: u64 = 2000;
+
+/// Tick of the most recent context switch. `u64::MAX` until the first one,
+/// which keeps the watchdog quiet during boot.
+static LAST_SWITCH_TICK: AtomicU64 = AtomicU64::new(u64::MAX);
+/// Switch tick the watchdog last reported, so one stall is dumped once.
+static WATCHDOG_REPORTED: AtomicU64 = AtomicU64::new(u64::MAX);
+static WATCHDOG_FIRES: AtomicU64 = AtomicU64::new(0);
+
+/// Called from the timer interrupt. If nothing has been switched in for
+/// `WATCHDOG_MS` while a process sits ready, log the process table and the
+/// scheduler stats. A table locked by the interrupted code is left for the
+/// next tick.
+pub fn watchdog_tick(now: u64) {
+    let last = LAST_SWITCH_TICK.load(Ordering::Relaxed);
+    if now.saturating_sub(last) < timer::ms_to_ticks(WATCHDOG_MS)
+        || WATCHDOG_REPORTED.load(Ordering::Relaxed) == last
+    {
+        return;
+    }
+    let ready = match PROCESS_TABLE.try_lock() {
+        Some(table) => table
+            .slice()
+            .iter()
+            .filter(|process| process.state == ProcessState::Ready)
+            .count(),
+        None => return,
+    };
+    if ready == 0 {
+        return;
+    }
+    WATCHDOG_REPORTED.store(last, Ordering::Relaxed);
+    WATCHDOG_FIRES.fetch_add(1, Ordering::Relaxed);
+    klog!(
+        "[sched] watchdog: no context switch for {} ticks with {} ready\n",
+        now - last,
+        ready
+    );
+    dump_all_processes();
+}
+
+/// Stalls the watchdog has reported since boot.
+pub fn watchdog_fires() -> u64 {
+    WATCHDOG_FIRES.load(Ordering::Relaxed)
+}
+
+/// Turn the context-switch trace on or off, returning whether it was on.
+/// Off, it costs the scheduler one relaxed load per switch.
+pub fn set_switch_trace(enabled: bool) -> bool {
+    SWITCH_TRACE_ON.swap(enabled, Ordering::Relaxed)
 }
 
-pub fn wake_channel(event: WaitChannel) {
-    let mut table = PROCESS_TABLE.lock();
-    let slice = table.slice_mut();
-    for process in slice {
-        if process.state == ProcessState::Blocked {
-            if let Some(channel) = process.wait_channel {
-                if channel.matches_event(event) {
-                    process.wait_channel = None;
-                    process.state = ProcessState::Ready;
-                    process.preempt_return = None;
-                }
-            }
-        }
-    }
+/// Take the recorded switches, oldest first, leaving the trace empty.
+pub fn switch_trace() -> Vec<SwitchRecord> {
+    PROCESS_TABLE.lock().switch_trace.drain()
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -1420,7 +1957,8 @@ pub fn request_preempt(frame: &mut InterruptFrame) {
 #[no_mangle]
 pub extern "C" fn preempt_do_switch() -> u64 {
     NEED_RESCHED.store(false, Ordering::Release);
-    reschedule();
+    // With nothing else ready the preempted task simply carries on.
+    let _ = schedule_internal(SwitchReason::Preempt);
 
     let pid = current_pid().expect("preempted process missing current pid");
     let mut table = PROCESS_TABLE.lock();
@@ -1446,26 +1984,211 @@ pub fn exit_current(exit_code: i32) -> ! {
 
     klog!("[process] exit request for pid {} as {}", pid, exit_code);
 
-    let parent = {
+    exit_process(pid, exit_code).expect("current pid missing from table during exit");
+
+    reschedule();
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Turn `pid` into a zombie carrying `exit_code` and wake its parent. Does
+/// not switch away; `exit_current` does that for the running process.
+pub fn exit_process(pid: Pid, exit_code: i32) -> Result<(), ProcessError> {
+    let (parent, leader_parent, ended_session, mut descriptors) = {
         let mut table = PROCESS_TABLE.lock();
-        let process = table
-            .get_mut(pid)
-            .expect("current pid missing from table during exit");
+        let index = table.find_index_by_pid(pid).ok_or(ProcessError::ProcessNotFound)?;
+        table.dequeue(index);
+        let process = &mut table.slice_mut()[index];
         process.state = ProcessState::Zombie;
         process.wait_channel = None;
         process.exit_code = Some(exit_code);
         process.preempt_return = None;
-        process.parent
+        let parent = process.parent;
+        let tgid = process.tgid;
+        let ended_session = (process.sid == pid).then_some(pid);
+        // The last thread out of a group whose leader already exited frees
+        // the leader to be reaped, so its parent may be waiting on it.
+        let leader_parent = match table.get(tgid) {
+            Some(leader) if tgid != pid && leader.state == ProcessState::Zombie => {
+                leader.parent.filter(|_| !table.group_has_live_member(tgid, pid))
+            }
+            _ => None,
+        };
+        (parent, leader_parent, ended_session, table.take_exit_descriptors(pid))
     };
 
+    // Flushed outside the table lock: a block device flush may sleep.
+    for slot in descriptors.iter_mut() {
+        if let Some(descriptor) = slot.take() {
+            close_descriptor(descriptor);
+        }
+    }
+    if let Some(sid) = ended_session {
+        tty::session_ended(sid);
+    }
+
     if let Some(parent_pid) = parent {
         wake_channel(WaitChannel::Child(parent_pid));
     }
+    if let Some(parent_pid) = leader_parent {
+        wake_channel(WaitChannel::Child(parent_pid));
+    }
+    Ok(())
+}
 
-    reschedule();
-    loop {
-        core::hint::spin_loop();
+/// Exit status of a process ended by `kill`: 128 plus SIGKILL's number, as
+/// a shell would report it.
+pub const KILLED_STATUS: i32 = 128 + 9;
+
+/// Exit status of a task that returned with its exit canary overwritten:
+/// 128 plus SIGABRT, what a C program's stack protector would end with.
+pub const STACK_SMASH_STATUS: i32 = 128 + 6;
+
+/// Process group of `pid`, or of the caller when `pid` is 0.
+pub fn get_pgid(pid: Pid) -> Result<Pid, ProcessError> {
+    let pid = if pid == 0 { current_pid().ok_or(ProcessError::ProcessNotFound)? } else { pid };
+    let table = PROCESS_TABLE.lock();
+    table.get(pid).map(|process| process.pgid).ok_or(ProcessError::ProcessNotFound)
+}
+
+/// Session of `pid`, or of the caller when `pid` is 0.
+pub fn get_sid(pid: Pid) -> Result<Pid, ProcessError> {
+    let pid = if pid == 0 { current_pid().ok_or(ProcessError::ProcessNotFound)? } else { pid };
+    let table = PROCESS_TABLE.lock();
+    table.get(pid).map(|process| process.sid).ok_or(ProcessError::ProcessNotFound)
+}
+
+/// Whether a live process is in group `pgid` of session `sid`.
+pub fn group_in_session(pgid: Pid, sid: Pid) -> bool {
+    PROCESS_TABLE.lock().group_exists(pgid, sid)
+}
+
+/// Move `pid` into group `pgid` with `setpgid`'s rules; 0 for either means
+/// the caller and `pid` itself. Only the caller or one of its children in
+/// the same session may be moved, a session leader stays where it is, and
+/// joining needs a live group in that session unless `pgid == pid` starts
+/// a new one.
+pub fn set_pgid(pid: Pid, pgid: Pid) -> Result<(), ProcessError> {
+    let caller = current_pid().ok_or(ProcessError::ProcessNotFound)?;
+    let pid = if pid == 0 { caller } else { pid };
+    let pgid = if pgid == 0 { pid } else { pgid };
+
+    let mut table = PROCESS_TABLE.lock();
+    let caller_sid = table.get(caller).ok_or(ProcessError::ProcessNotFound)?.sid;
+    let target = table
+        .get(pid)
+        .filter(|process| process.state != ProcessState::Zombie)
+        .ok_or(ProcessError::ProcessNotFound)?;
+    if pid != caller && target.parent != Some(caller) {
+        return Err(ProcessError::ProcessNotFound);
+    }
+    let sid = target.sid;
+    if sid != caller_sid || sid == pid {
+        return Err(ProcessError::NotPermitted);
+    }
+    if pgid != pid && !table.group_exists(pgid, sid) {
+        return Err(ProcessError::NotPermitted);
+    }
+    if let Some(process) = table.get_mut(pid) {
+        process.pgid = pgid;
+    }
+    Ok(())
+}
+
+/// Terminate processes chosen the way `kill(2)` picks them: `target > 0`
+/// is one pid, `-pgid` is every member of that group, and 0 is the caller's
+/// own group. There are no signals yet, so each one exits with
+/// `KILLED_STATUS`. A non-root caller can only end processes with its real
+/// uid; others in the group are skipped. Returns how many were ended. If
+/// the caller is among them it exits last and this does not return. A
+/// victim that fails to exit does not stop the others; the first such
+/// error is returned once the rest have been ended.
+pub fn kill(target: i64) -> Result<usize, ProcessError> {
+    let caller = current_pid();
+    let victims: Vec<Pid> = {
+        let table = PROCESS_TABLE.lock();
+        let caller_process = caller.and_then(|pid| table.get(pid));
+        let group = match target {
+            0 => Some(caller_process.ok_or(ProcessError::ProcessNotFound)?.pgid),
+            t if t < 0 => {
+                let pgid = t.unsigned_abs();
+                if pgid > u64::from(Pid::MAX) {
+                    return Err(ProcessError::ProcessNotFound);
+                }
+                Some(pgid as Pid)
+            }
+            _ => None,
+        };
+        let selected = |process: &&Process| {
+            process.state != ProcessState::Zombie
+                && !process.is_idle
+                && match group {
+                    Some(pgid) => process.pgid == pgid,
+                    None => i64::from(process.pid) == target,
+                }
+        };
+        let candidates: Vec<&Process> = table.slice().iter().filter(selected).collect();
+        if candidates.is_empty() {
+            return Err(ProcessError::ProcessNotFound);
+        }
+        // Kernel code with no current process may end anything.
+        let credentials = caller_process.map(|process| process.credentials);
+        let allowed: Vec<Pid> = candidates
+            .iter()
+            .filter(|process| match credentials {
+                None => true,
+                Some(credentials) => {
+                    credentials.is_privileged() || credentials.real_uid() == process.credentials.real_uid()
+                }
+            })
+            .map(|process| process.pid)
+            .collect();
+        if allowed.is_empty() {
+            return Err(ProcessError::NotPermitted);
+        }
+        allowed
+    };
+
+    // One victim that cannot be ended, say because it was reaped meanwhile,
+    // must not spare the rest of the group or the caller.
+    let mut killed_self = false;
+    let mut ended = 0;
+    let mut first_error = None;
+    for &pid in victims.iter() {
+        if Some(pid) == caller {
+            killed_self = true;
+            continue;
+        }
+        klog!("[process] kill pid={} by {:?}\n", pid, caller);
+        match exit_process(pid, KILLED_STATUS) {
+            Ok(()) => ended += 1,
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
     }
+    if killed_self {
+        exit_current(KILLED_STATUS);
+    }
+    match first_error {
+        Some(err) => Err(err),
+        None => Ok(ended),
+    }
+}
+
+/// Whether `pid`'s kernel stack canary is still in place.
+pub fn stack_guard_intact(pid: Pid) -> Result<bool, ProcessError> {
+    let table = PROCESS_TABLE.lock();
+    let process = table.get(pid).ok_or(ProcessError::ProcessNotFound)?;
+    Ok(process.stack_intact())
+}
+
+/// Whether the canary above `pid`'s `process_exit` return slot survives.
+pub fn exit_frame_intact(pid: Pid) -> Result<bool, ProcessError> {
+    let table = PROCESS_TABLE.lock();
+    let process = table.get(pid).ok_or(ProcessError::ProcessNotFound)?;
+    Ok(process.exit_frame_intact())
 }
 
 pub fn wait_for_child(target: Option<Pid>) -> Result<(Pid, i32), ProcessError> {
@@ -1519,13 +2242,16 @@ pub fn allocate_for_process_with_permissions(
     process.allocate_region_with_permissions(layout, kind, permissions)
 }
 
-pub fn free_for_process(pid: Pid, ptr: *mut u8) -> Result<(), ProcessError> {
+/// Free `len` bytes at `ptr` that `allocate_for_process` handed to `pid`.
+/// The range may cover part of one allocation or span several; returns how
+/// many tracked bytes it released.
+pub fn free_for_process(pid: Pid, ptr: *mut u8, len: usize) -> Result<usize, ProcessError> {
     let mut table = PROCESS_TABLE.lock();
     let process = table.get_mut(pid).ok_or(ProcessError::ProcessNotFound)?;
-    process.release_region(ptr)
+    process.release_region(ptr, len)
 }
 
-fn schedule_internal() -> bool {
+fn schedule_internal(reason: SwitchReason) -> bool {
     //klog!("[process] schedule_internal enter\n");
 
     let (current_ctx, next_ctx, current_space, next_space, next_pid) = {
@@ -1540,7 +2266,7 @@ fn schedule_internal() -> bool {
         let current_index = current_pid.and_then(|pid| table.find_index_by_pid(pid));
         //klog!("[process] schedule_internal current_index={:?}\n", current_index);
 
-        let next_index = match table.next_ready_index(current_index) {
+        let next_index = match table.pick_next(current_index) {
             Some(idx) => idx,
             None => {
                 //klog!("[process] schedule_internal no ready process\n");
@@ -1553,6 +2279,8 @@ fn schedule_internal() -> bool {
         if let Some(idx) = current_index {
             if idx == next_index {
                 //klog!("[process] schedule_internal staying on same index={}\n", idx);
+                // It may have been popped while still marked Ready.
+                table.slice_mut()[idx].state = ProcessState::Running;
                 return false;
             }
         }
@@ -1565,32 +2293,28 @@ fn schedule_internal() -> bool {
         let next_space = slice[next_index].address_space;
 
         if let Some(idx) = current_index {
-            if let Some(process) = slice.get_mut(idx) {
-                if process.state == ProcessState::Running {
-                    process.state = ProcessState::Ready;
-                    //klog!("[process] schedule_internal demoted pid={} -> Ready\n", process.pid);
+            if let Some(process) = slice.get(idx) {
+                if !process.stack_intact() {
+                    panic!(
+                        "kernel stack overflow pid={} name='{}' base=0x{:016X}",
+                        process.pid,
+                        process.name,
+                        process.stack_ptr as u64
+                    );
                 }
             }
         }
 
-        if let Some(process) = slice.get_mut(next_index) {
-            process.state = ProcessState::Running;
-            process.cpu_slices = process.cpu_slices.saturating_add(1);
-            klog!(
-                "[sched] promote pid={} slices={} kind={:?}\n",
-                process.pid,
-                process.cpu_slices,
-                process.address_space.kind()
-            );
-        }
+        table.mark_switch(current_index, next_index, reason);
+        let slice = table.slice_mut();
 
         let next_pid = slice[next_index].pid;
         let next_ctx_ptr: *const Context = &slice[next_index].context;
 
         #[cfg(target_arch = "x86_64")]
         {
-            let kernel_rsp = slice[next_index].context.rsp;
-            gdt::set_kernel_stack(kernel_rsp);
+            let next = &slice[next_index];
+            gdt::set_kernel_stack(next.trap_stack_top().unwrap_or(next.context.rsp));
         }
 /*
         klog!(
@@ -1602,7 +2326,7 @@ fn schedule_internal() -> bool {
 */
         let current_ctx_ptr: *mut Context = match current_index {
             Some(idx) => &mut slice[idx].context as *mut Context,
-            None => ptr::addr_of_mut!(BOOT_CONTEXT),
+            None => BOOT_CONTEXT.get().0.get(),
         };
 
         if let Some(idx) = current_index {
@@ -1662,14 +2386,23 @@ pub fn get_process(pid: Pid) -> Option<ProcessSnapshot> {
     table.get(pid).map(ProcessSnapshot::from)
 }
 
+/// Snapshot every process in table order, including zombies.
+pub fn snapshot_all() -> Vec<ProcessSnapshot> {
+    let table = PROCESS_TABLE.lock();
+    table.slice().iter().map(ProcessSnapshot::from).collect()
+}
+
 pub fn scheduler_stats() -> SchedulerStats {
     let table = PROCESS_TABLE.lock();
     let mut stats = SchedulerStats::empty();
     stats.need_resched = NEED_RESCHED.load(Ordering::Acquire);
+    let now = timer::ticks();
+    let mut total_ticks = 0u64;
 
     for process in table.slice() {
         stats.total += 1;
         stats.total_slices = stats.total_slices.saturating_add(process.cpu_slices);
+        total_ticks = total_ticks.saturating_add(process.cpu_ticks_at(now));
         match process.state {
             ProcessState::Ready => stats.ready += 1,
             ProcessState::Running => stats.running += 1,
@@ -1678,35 +2411,46 @@ pub fn scheduler_stats() -> SchedulerStats {
         }
     }
 
+    stats.total_cpu_ms = timer::ticks_to_ms(total_ticks);
     stats
 }
 
 pub struct ProcessSnapshot {
     pid: Pid,
+    tgid: Pid,
     parent: Option<Pid>,
-    name: &'static str,
+    pgid: Pid,
+    sid: Pid,
+    name: ProcessName,
     state: ProcessState,
     cpu_slices: u64,
+    cpu_time_ms: u64,
    is_idle: bool,
    credentials: Credentials,
    address_space: AddressSpace,
    user_stack: Option<UserStack>,
     user_entry: Option<u64>,
+    kernel_stack_base: u64,
 }
 
 impl ProcessSnapshot {
     fn from(process: &Process) -> Self {
         Self {
             pid: process.pid,
+            tgid: process.tgid,
             parent: process.parent,
+            pgid: process.pgid,
+            sid: process.sid,
             name: process.name,
             state: process.state,
             cpu_slices: process.cpu_slices,
+            cpu_time_ms: timer::ticks_to_ms(process.cpu_ticks_at(timer::ticks())),
             is_idle: process.is_idle,
             credentials: process.credentials,
             address_space: process.address_space,
             user_stack: process.user_stack,
             user_entry: process.user_entry,
+            kernel_stack_base: process.stack_ptr as u64,
         }
     }
 
@@ -1714,12 +2458,24 @@ impl ProcessSnapshot {
         self.pid
     }
 
+    pub fn tgid(&self) -> Pid {
+        self.tgid
+    }
+
     pub fn parent(&self) -> Option<Pid> {
         self.parent
     }
 
-    pub fn name(&self) -> &'static str {
-        self.name
+    pub fn pgid(&self) -> Pid {
+        self.pgid
+    }
+
+    pub fn sid(&self) -> Pid {
+        self.sid
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
     }
 
     pub fn state(&self) -> ProcessState {
@@ -1730,6 +2486,10 @@ impl ProcessSnapshot {
         self.cpu_slices
     }
 
+    pub fn cpu_time_ms(&self) -> u64 {
+        self.cpu_time_ms
+    }
+
     pub fn is_idle(&self) -> bool {
         self.is_idle
     }
@@ -1749,6 +2509,10 @@ impl ProcessSnapshot {
     pub fn user_entry(&self) -> Option<u64> {
         self.user_entry
     }
+
+    pub fn kernel_stack_base(&self) -> u64 {
+        self.kernel_stack_base
+    }
 }
 
 pub struct SchedulerStats {
@@ -1758,6 +2522,7 @@ pub struct SchedulerStats {
     pub blocked: usize,
     pub zombie: usize,
     pub total_slices: u64,
+    pub total_cpu_ms: u64,
     pub need_resched: bool,
 }
 
@@ -1770,6 +2535,7 @@ impl SchedulerStats {
             blocked: 0,
             zombie: 0,
             total_slices: 0,
+            total_cpu_ms: 0,
             need_resched: false,
         }
     }
@@ -1782,14 +2548,21 @@ pub fn init_pid() -> Option<Pid> {
 
 pub fn current_pid() -> Option<Pid> {
 
-    match CURRENT_PID.load(Ordering::Acquire) {
+    match CURRENT_PID.get().load(Ordering::Acquire) {
         0 => None,
         pid => Some(pid as Pid),
     }
 }
 
 pub fn set_current_pid(pid: Pid) {
-    CURRENT_PID.store(pid, Ordering::Release);
+    CURRENT_PID.get().store(pid, Ordering::Release);
+}
+
+/// `pid`'s name, or `None` if it is gone or the process table is held. For
+/// the panic path, which must not wait on a lock.
+pub fn try_process_name(pid: Pid) -> Option<ProcessName> {
+    let table = PROCESS_TABLE.try_lock()?;
+    table.get(pid).map(|process| process.name)
 }
 
 pub fn current_credentials() -> Option<Credentials> {
@@ -1872,6 +2645,9 @@ fn copy_to_user_internal(
     let mut written = 0usize;
     while written < src.len() {
         let virt_addr = user_ptr + written as u64;
+        // Writes go through the physical alias, which the MMU never checks,
+        // so a shared copy-on-write page has to be split here by hand.
+        paging::resolve_cow_fault(address_space.cr3(), virt_addr);
         let phys = paging::translate(address_space.cr3(), virt_addr)
             .ok_or(ProcessError::UserMemoryNotPresent)?;
 
@@ -1955,13 +2731,60 @@ pub fn write_user_buffer(
     copy_to_user(address_space, user_ptr, data)
 }
 
+/// Copy a NUL-terminated string out of `address_space` one byte at a time,
+/// so each byte's page is checked before it is read and nothing past the
+/// NUL is touched. `max_len` counts the terminator; returns the bytes
+/// before it, or `UnterminatedString` if none turns up in time.
+pub fn read_user_cstr(
+    address_space: &AddressSpace,
+    user_ptr: u64,
+    max_len: usize,
+) -> Result<Vec<u8>, ProcessError> {
+    match read_user_until_nul(address_space, user_ptr, max_len)? {
+        (bytes, true) => Ok(bytes),
+        (_, false) => Err(ProcessError::UnterminatedString),
+    }
+}
+
+/// `read_user_cstr` for a `(ptr, len)` string: reads up to `len` bytes and
+/// stops early at a NUL, but does not require one.
+pub fn read_user_str(
+    address_space: &AddressSpace,
+    user_ptr: u64,
+    len: usize,
+) -> Result<Vec<u8>, ProcessError> {
+    read_user_until_nul(address_space, user_ptr, len).map(|(bytes, _)| bytes)
+}
+
+fn read_user_until_nul(
+    address_space: &AddressSpace,
+    user_ptr: u64,
+    max_len: usize,
+) -> Result<(Vec<u8>, bool), ProcessError> {
+    let mut out = Vec::new();
+    while out.len() < max_len {
+        let addr = user_ptr
+            .checked_add(out.len() as u64)
+            .ok_or(ProcessError::InvalidUserPointer)?;
+        let mut byte = [0u8; 1];
+        copy_from_user(address_space, &mut byte, addr)?;
+        if byte[0] == 0 {
+            return Ok((out, true));
+        }
+        out.push(byte[0]);
+    }
+    Ok((out, false))
+}
+
 #[cfg(target_arch = "x86_64")]
 pub fn create_user_address_space_with_stack(
     stack_pages: usize,
+    executable_stack: bool,
 ) -> Result<(AddressSpace, UserStack), ProcessError> {
     klog!(
-        "[process] create_user_address_space_with_stack stack_pages={} requested\n",
-        stack_pages
+        "[process] create_user_address_space_with_stack stack_pages={} executable={} requested\n",
+        stack_pages,
+        executable_stack
     );
 
     if stack_pages == 0 {
@@ -1983,14 +2806,14 @@ pub fn create_user_address_space_with_stack(
         address_space.kind()
     );
 
-    let mut current_top = user::space::stack_top();
+    let stack_top = user::space::stack_top();
     let stack_size = stack_pages
         .checked_mul(paging::PAGE_SIZE)
         .ok_or(ProcessError::AddressSpaceAllocationFailed)?;
 
     klog!(
         "[process] create_user_address_space_with_stack stack_top=0x{:016X} size={} bytes\n",
-        current_top,
+        stack_top,
         stack_size
     );
 
@@ -1999,24 +2822,27 @@ pub fn create_user_address_space_with_stack(
         heap::remaining_bytes()
     );
 
-    for _ in 0..stack_pages {
-        let frame = phys::allocate_frame().ok_or(ProcessError::AddressSpaceAllocationFailed)?;
-        current_top = current_top.saturating_sub(paging::PAGE_SIZE as u64);
+    // The whole stack comes from one physically contiguous run, mapped in
+    // order so the lowest stack page sits on the lowest frame.
+    let frames = phys::allocate_frames(stack_pages).ok_or(ProcessError::AddressSpaceAllocationFailed)?;
+    let mut flags = FLAG_WRITABLE | FLAG_USER;
+    if !executable_stack {
+        flags |= FLAG_NO_EXECUTE;
+    }
+    let stack_base = stack_top.saturating_sub(stack_size as u64);
+    for (index, frame) in frames.iter().enumerate() {
+        let virt = stack_base + (index * paging::PAGE_SIZE) as u64;
+        mmu::zero_page(frame.start());
         klog!(
             "[process] create_user_address_space_with_stack map stack page virt=0x{:016X} frame=0x{:016X}\n",
-            current_top,
+            virt,
             frame.start()
         );
-        paging::map_page(
-            pml4_phys,
-            current_top,
-            frame.start(),
-            FLAG_WRITABLE | FLAG_USER,
-        )
-        .map_err(|_| ProcessError::AddressSpaceAllocationFailed)?;
+        paging::map_page(pml4_phys, virt, frame.start(), flags)
+            .map_err(|_| ProcessError::AddressSpaceAllocationFailed)?;
     }
 
-    let user_stack = UserStack::new(user::space::stack_top(), stack_size);
+    let user_stack = UserStack::new(stack_top, stack_size);
     klog!(
         "[process] create_user_address_space_with_stack complete top=0x{:016X} size={}\n",
         user_stack.top(),
@@ -2030,12 +2856,154 @@ pub fn create_user_address_space_with_stack(
 }
 
 #[cfg(target_arch = "x86_64")]
-pub fn create_default_user_address_space() -> Result<(AddressSpace, UserStack), ProcessError> {
+/// Allocate a canaried kernel stack and a context that starts at `entry`,
+/// returning into `process_exit` if the entry ever does.
+fn kernel_stack_for(entry: ProcessEntry) -> Result<(*mut u8, Layout, Context), ProcessError> {
+    let layout = Layout::from_size_align(KERNEL_STACK_SIZE, 16).map_err(|_| {
+        klog!("[process] kernel stack layout creation failed size={} align=16\n", KERNEL_STACK_SIZE);
+        ProcessError::StackAllocationFailed
+    })?;
+    let stack_ptr = unsafe { heap::allocate(layout) };
+    if stack_ptr.is_null() {
+        klog!("[process] kernel stack heap allocation returned null\n");
+        return Err(ProcessError::StackAllocationFailed);
+    }
+    write_stack_canary(stack_ptr);
+
+    let stack_top = unsafe { stack_ptr.add(KERNEL_STACK_SIZE) } as u64;
+    let rsp = push_exit_frame(stack_top);
+
+    let mut context = Context::new();
+    context.rsp = rsp;
+    // The entry function saves this as its caller's rbp; zero is where
+    // backtraces stop.
+    context.rbp = 0;
+    context.rip = entry as u64;
+
+    Ok((stack_ptr, layout, context))
+}
+
+/// Give back what a user address space owns once nothing runs in it any
+/// more. Only the user stack is tracked today; ELF segment frames and the
+/// page tables themselves are still leaked. Frames shared copy-on-write
+/// are only freed by their last owner.
+fn release_user_address_space(address_space: AddressSpace, user_stack: Option<UserStack>) {
+    if !address_space.is_user() {
+        return;
+    }
+    let stack = match user_stack {
+        Some(stack) => stack,
+        None => return,
+    };
+
+    let used_before = phys::summary().used_frames;
+    let mut page = stack.base();
+    let mut freed = 0usize;
+    let mut returned = 0usize;
+    while page < stack.top() {
+        if let Some(phys) = paging::translate(address_space.cr3(), page) {
+            paging::unmap_page(address_space.cr3(), page);
+            if phys::release_frame(phys::Frame::containing(phys)) == 0 {
+                returned += 1;
+            }
+            freed += 1;
+        }
+        page += paging::PAGE_SIZE as u64;
+    }
+    paging::flush_address_space(address_space.cr3());
+    klog!(
+        "[process] released address space cr3=0x{:016X} stack_pages={}\n",
+        address_space.cr3(),
+        freed
+    );
+
+    // Frames still shared with another space stay allocated; every other
+    // one should show up in the allocator's count.
+    let dropped = used_before.saturating_sub(phys::summary().used_frames);
+    if dropped != returned {
+        klog!(
+            "[process] frame leak? cr3=0x{:016X} expected {} frame(s) back, allocator dropped {}\n",
+            address_space.cr3(),
+            returned,
+            dropped
+        );
+    }
+}
+
+/// Where `EXIT_CANARY` lives on a kernel stack ending at `stack_top`.
+fn exit_canary_slot(stack_top: u64) -> u64 {
+    (stack_top & !0xF) - 16
+}
+
+/// Seed the top of a fresh kernel stack: a spare word, `EXIT_CANARY`, then
+/// `process_exit` as the return address. Returns the starting `rsp`, which
+/// points at the return slot and leaves the entry function's stack aligned
+/// as if it had been called.
+fn push_exit_frame(stack_top: u64) -> u64 {
+    let canary = exit_canary_slot(stack_top);
+    let rsp = canary - 8;
+    unsafe {
+        ((canary + 8) as *mut u64).write(0);
+        (canary as *mut u64).write(EXIT_CANARY);
+        (rsp as *mut u64).write(process_exit as u64);
+    }
+    rsp
+}
+
+fn write_stack_canary(base: *mut u8) {
+    let base = base as *mut u64;
+    for word in 0..STACK_CANARY_WORDS {
+        unsafe {
+            base.add(word).write(STACK_CANARY);
+        }
+    }
+}
+
+/// Lay out `argv` at the top of a fresh user stack and return the initial
+/// `rsp`, which points at `argc` and is 16-byte aligned.
+fn push_user_args(address_space: &AddressSpace, stack_top: u64, argv: &[&[u8]]) -> Result<u64, ProcessError> {
+    let mut cursor = stack_top;
+    let mut pointers: Vec<u64> = Vec::with_capacity(argv.len());
+
+    for arg in argv {
+        if arg.len() > MAX_ARG_LEN {
+            return Err(ProcessError::ArgumentListTooLong);
+        }
+        cursor -= arg.len() as u64 + 1;
+        copy_to_user(address_space, cursor, arg)?;
+        copy_to_user(address_space, cursor + arg.len() as u64, &[0])?;
+        pointers.push(cursor);
+    }
+
+    // argc, argv[0..n], NULL, envp NULL.
+    let words = argv.len() + 3;
+    cursor = (cursor - (words * 8) as u64) & !0xFu64;
+
+    let mut block: Vec<u8> = Vec::with_capacity(words * 8);
+    block.extend_from_slice(&(argv.len() as u64).to_le_bytes());
+    for pointer in &pointers {
+        block.extend_from_slice(&pointer.to_le_bytes());
+    }
+    block.extend_from_slice(&0u64.to_le_bytes());
+    block.extend_from_slice(&0u64.to_le_bytes());
+    copy_to_user(address_space, cursor, &block)?;
+
+    Ok(cursor)
+}
+
+/// An address space with the default stack, executable only if `image`
+/// asked for it through `PT_GNU_STACK`.
+pub fn create_default_user_address_space(
+    image: &user::elf::ElfImage,
+) -> Result<(AddressSpace, UserStack), ProcessError> {
     klog!("[process] create_default_user_address_space enter\n");
-    create_user_address_space_with_stack(user::space::DEFAULT_STACK_PAGES)
+    create_user_address_space_with_stack(user::space::DEFAULT_STACK_PAGES, image.executable_stack)
 }
 
-fn map_user_segments(
+/// Map every PT_LOAD segment of `image` into `address_space`. Pages holding
+/// any file bytes get a private frame; pages that are purely BSS map the
+/// shared zero frame copy-on-write and cost nothing until written.
+pub fn map_user_segments(
     address_space: &AddressSpace,
     image: &user::elf::ElfImage,
     data: &[u8],
@@ -2057,13 +3025,25 @@ fn map_user_segments(
             segment.flags
         );
 
+        let flags = segment_page_flags(segment.flags);
+
+        let mut zero_pages = 0usize;
         let mut page = start;
         while page < end {
+            let seg_file_end = segment.vaddr + segment.filesz;
+            let copy_start = core::cmp::max(segment.vaddr, page);
+            let copy_end = core::cmp::min(seg_file_end, page + paging::PAGE_SIZE as u64);
+
+            if copy_end <= copy_start {
+                paging::map_zero_page(address_space.cr3(), page, flags).map_err(segment_map_error)?;
+                zero_pages += 1;
+                page = page.saturating_add(paging::PAGE_SIZE as u64);
+                continue;
+            }
+
             let frame = phys::allocate_frame().ok_or(ProcessError::AddressSpaceAllocationFailed)?;
+            mmu::zero_page(frame.start());
             let frame_ptr = mmu::phys_to_virt(frame.start()) as *mut u8;
-            unsafe {
-                ptr::write_bytes(frame_ptr, 0, paging::PAGE_SIZE);
-            }
 
             klog!(
                 "[process] map_user_segments map page virt=0x{:016X} frame=0x{:016X}\n",
@@ -2071,16 +3051,7 @@ fn map_user_segments(
                 frame.start()
             );
 
-            let mut flags = FLAG_USER;
-            if user::elf::segment_flags_writable(segment.flags) {
-                flags |= FLAG_WRITABLE;
-            }
-            if !user::elf::segment_flags_executable(segment.flags) {
-                flags |= FLAG_NO_EXECUTE;
-            }
-
-            paging::map_page(address_space.cr3(), page, frame.start(), flags)
-                .map_err(|_| ProcessError::AddressSpaceAllocationFailed)?;
+            paging::map_page(address_space.cr3(), page, frame.start(), flags).map_err(segment_map_error)?;
 
             klog!(
                 "[process] map_user_segments mapped virt=0x{:016X} -> phys=0x{:016X} flags=0x{:X}\n",
@@ -2089,42 +3060,37 @@ fn map_user_segments(
                 flags
             );
 
-            let seg_file_end = segment.vaddr + segment.filesz;
-            let copy_start = core::cmp::max(segment.vaddr, page);
-            let copy_end = core::cmp::min(seg_file_end, page + paging::PAGE_SIZE as u64);
-
-            if copy_end > copy_start {
-                let dst_offset = (copy_start - page) as usize;
-                let src_offset = (copy_start - segment.vaddr) as usize;
-                let len = (copy_end - copy_start) as usize;
-
-                let src_index = segment.offset as usize + src_offset;
-                if src_index + len > data.len() {
-                    return Err(ProcessError::InvalidElf);
-                }
+            let dst_offset = (copy_start - page) as usize;
+            let src_offset = (copy_start - segment.vaddr) as usize;
+            let len = (copy_end - copy_start) as usize;
 
-                unsafe {
-                    ptr::copy_nonoverlapping(
-                        data.as_ptr().add(src_index),
-                        frame_ptr.add(dst_offset),
-                        len,
-                    );
-                }
+            let src_index = segment.offset as usize + src_offset;
+            if src_index + len > data.len() {
+                return Err(ProcessError::InvalidElf);
+            }
 
-                klog!(
-                    "[process] map_user_segments copied len={} from file_offset=0x{:X} to virt=0x{:016X} pid_segment_base=0x{:016X}\n",
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    data.as_ptr().add(src_index),
+                    frame_ptr.add(dst_offset),
                     len,
-                    src_index,
-                    page + dst_offset as u64,
-                    segment.vaddr
                 );
             }
 
+            klog!(
+                "[process] map_user_segments copied len={} from file_offset=0x{:X} to virt=0x{:016X} pid_segment_base=0x{:016X}\n",
+                len,
+                src_index,
+                page + dst_offset as u64,
+                segment.vaddr
+            );
+
             page = page.saturating_add(paging::PAGE_SIZE as u64);
         }
 
         klog!(
-            "[process] map_user_segments after segment heap remaining={}\n",
+            "[process] map_user_segments after segment zero_pages={} heap remaining={}\n",
+            zero_pages,
             heap::remaining_bytes()
         );
     }
@@ -2133,6 +3099,35 @@ fn map_user_segments(
     Ok(())
 }
 
+/// Page flags for a segment with ELF `p_flags`: writable only with `PF_W`,
+/// no-execute without `PF_X`. The loader fills frames through the kernel's
+/// direct map, so a read-only segment never needs a writable user mapping,
+/// not even for the zeroed tail of its last page.
+fn segment_page_flags(p_flags: u32) -> u64 {
+    let mut flags = FLAG_USER;
+    if user::elf::segment_flags_writable(p_flags) {
+        flags |= FLAG_WRITABLE;
+    }
+    if !user::elf::segment_flags_executable(p_flags) {
+        flags |= FLAG_NO_EXECUTE;
+    }
+    flags
+}
+
+/// Each page gets the flags of one segment. A page already mapped means two
+/// segments share it, and whichever flags it kept would be wrong for one of
+/// them (a writable neighbour would open up the end of `.rodata`), so such
+/// an image is refused.
+fn segment_map_error(err: paging::MapError) -> ProcessError {
+    match err {
+        paging::MapError::AlreadyMapped => {
+            klog!("[process] map_user_segments: segments share a page\n");
+            ProcessError::InvalidElf
+        }
+        _ => ProcessError::AddressSpaceAllocationFailed,
+    }
+}
+
 fn align_down(value: u64, align: u64) -> u64 {
     value & !(align - 1)
 }
@@ -2145,38 +3140,137 @@ fn align_up(value: u64, align: u64) -> u64 {
     }
 }
 
+/// How `open_path_as` opens a path. The default is a plain read open of
+/// something that must already exist.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct OpenOptions {
+    /// The caller means to write. Checked against the file's permissions at
+    /// open time; the descriptor itself is not limited to reads or writes.
+    pub write: bool,
+    /// A missing file on a mounted filesystem is created empty.
+    pub create: bool,
+    /// Every write lands at the end of the file. Devices have no end to
+    /// append to and ignore it.
+    pub append: bool,
+}
+
+/// Open `path` for `pid` as `options` describes, checking the file against
+/// `pid`'s credentials, and install it in the lowest free fd.
+pub fn open_path_as(pid: Pid, path: &str, options: OpenOptions) -> Result<usize, ProcessError> {
+    let credentials = {
+        let table = PROCESS_TABLE.lock();
+        table.get(pid).ok_or(ProcessError::ProcessNotFound)?.credentials()
+    };
+    let mut descriptor = open_descriptor(path, options, credentials)?;
+    if options.append {
+        if let FileDescriptor::Vfs(handle) = &mut descriptor {
+            handle.set_append(true);
+        }
+    }
+    install_fd(pid, descriptor)
+}
+
 pub fn open_path(pid: Pid, path: &str) -> Result<usize, ProcessError> {
-    let descriptor = if path.starts_with("/fat/") {
-        let sub = &path[5..];
-        let file = crate::fs::fat::open_file(sub).map_err(|err| match err {
-            crate::fs::fat::FatError::NotMounted => ProcessError::PathNotFound,
-            crate::fs::fat::FatError::InvalidPath => ProcessError::PathNotFound,
-            crate::fs::fat::FatError::NotFound => ProcessError::PathNotFound,
-            crate::fs::fat::FatError::Io => ProcessError::AllocationFailed,
-        })?;
-        FileDescriptor::Vfs(VfsHandle::new(file))
-    } else {
-        match path {
-            "/scratch" => {
-                let file = crate::vfs::ata::AtaScratchFile::get().ok_or(ProcessError::PathNotFound)?;
-                FileDescriptor::Vfs(VfsHandle::new(file))
-            }
-            "/dev/console" => FileDescriptor::Char(console::driver()),
-            "/dev/null" => {
-                let dev = crate::drivers::char_device_by_name("null").ok_or(ProcessError::PathNotFound)?;
-                FileDescriptor::Char(dev)
-            }
-            "/dev/zero" => {
-                let dev = crate::drivers::char_device_by_name("zero").ok_or(ProcessError::PathNotFound)?;
-                FileDescriptor::Char(dev)
-            }
-            _ => return Err(ProcessError::PathNotFound),
+    open_path_as(pid, path, OpenOptions::default())
+}
+
+/// Like `open_path`, but a missing file on a mounted filesystem is created
+/// empty instead of reported as not found.
+pub fn create_path(pid: Pid, path: &str) -> Result<usize, ProcessError> {
+    let options = OpenOptions {
+        create: true,
+        ..OpenOptions::default()
+    };
+    open_path_as(pid, path, options)
+}
+
+/// `open_path`, or `create_path` when `create` is set, with the handle in
+/// append mode so every write lands at the end of the file. Devices have no
+/// end to append to and open as usual.
+pub fn open_path_append(pid: Pid, path: &str, create: bool) -> Result<usize, ProcessError> {
+    let options = OpenOptions {
+        create,
+        append: true,
+        ..OpenOptions::default()
+    };
+    open_path_as(pid, path, options)
+}
+
+/// Create a pipe and install both ends in `pid`'s fd table, returning
+/// `(read_fd, write_fd)`.
+pub fn open_pipe(pid: Pid) -> Result<(usize, usize), ProcessError> {
+    let (reader, writer) = pipe::create();
+    let read_fd = install_fd(pid, FileDescriptor::Char(reader))?;
+    match install_fd(pid, FileDescriptor::Char(writer)) {
+        Ok(write_fd) => Ok((read_fd, write_fd)),
+        Err(err) => {
+            let _ = close_fd(pid, read_fd);
+            Err(err)
+        }
+    }
+}
+
+fn open_descriptor(
+    path: &str,
+    options: OpenOptions,
+    credentials: Credentials,
+) -> Result<FileDescriptor, ProcessError> {
+    let normal = crate::vfs::normalize_path(path).map_err(ProcessError::Vfs)?;
+    let path = crate::vfs::symlink::resolve(&normal).map_err(ProcessError::Vfs)?;
+    if let Some((fs, rest)) = crate::vfs::mount::lookup(path) {
+        let file = if options.create { fs.create(rest) } else { fs.open(rest) };
+        let file = file.map_err(ProcessError::Vfs)?;
+        check_access(file, options, credentials).map_err(ProcessError::Vfs)?;
+        return Ok(FileDescriptor::Vfs(VfsHandle::new(file)));
+    }
+
+    let descriptor = match path {
+        "/scratch" => {
+            let file = crate::vfs::ata::AtaScratchFile::get().ok_or(ProcessError::PathNotFound)?;
+            FileDescriptor::Vfs(VfsHandle::new(file))
+        }
+        _ => {
+            // Everything under /dev is a registered char device of that name.
+            let name = path.strip_prefix("/dev/").ok_or(ProcessError::PathNotFound)?;
+            let dev = crate::drivers::char_device_by_name(name).ok_or(ProcessError::PathNotFound)?;
+            dev.open();
+            FileDescriptor::Char(dev)
         }
     };
+    Ok(descriptor)
+}
+
+/// The per-file permission check behind every open. For now the only rule
+/// is the read-only attribute: nobody but a privileged caller opens such a
+/// file for writing.
+fn check_access(
+    file: &dyn crate::vfs::VfsFile,
+    options: OpenOptions,
+    credentials: Credentials,
+) -> Result<(), VfsError> {
+    let protected = file.attributes() & crate::vfs::attr::READ_ONLY != 0;
+    if options.write && protected && !credentials.is_privileged() {
+        return Err(VfsError::PermissionDenied);
+    }
+    Ok(())
+}
+
+/// Whether any process has `device` open.
+pub fn char_device_in_use(device: &dyn CharDevice) -> bool {
+    let target = device as *const dyn CharDevice as *const u8;
+    let table = PROCESS_TABLE.lock();
+    table.slice().iter().any(|process| {
+        process.fds.iter().flatten().any(|descriptor| match descriptor.as_char() {
+            Some(dev) => ptr::eq(dev as *const dyn CharDevice as *const u8, target),
+            None => false,
+        })
+    })
+}
 
+fn install_fd(pid: Pid, descriptor: FileDescriptor) -> Result<usize, ProcessError> {
     let mut table = PROCESS_TABLE.lock();
     let process = table
-        .get_mut(pid)
+        .fd_owner_mut(pid)
         .ok_or(ProcessError::ProcessNotFound)?;
     process.allocate_fd_slot(descriptor)
 }
@@ -2185,16 +3279,38 @@ pub fn close_fd(pid: Pid, fd: usize) -> Result<(), ProcessError> {
     let descriptor = {
         let mut table = PROCESS_TABLE.lock();
         let process = table
-            .get_mut(pid)
+            .fd_owner_mut(pid)
             .ok_or(ProcessError::ProcessNotFound)?;
         process.release_fd_slot(fd)?
     };
 
-    let mut descriptor = descriptor;
+    close_descriptor(descriptor);
+    Ok(())
+}
+
+/// Cap how many descriptors `pid`'s fd table may hold. Threads share their
+/// leader's table and limit. Lowering the limit closes nothing: fds already
+/// at or above it stay usable, but no new fd is handed out there.
+pub fn set_fd_limit(pid: Pid, limit: usize) -> Result<(), ProcessError> {
+    if limit == 0 || limit > MAX_FD_LIMIT {
+        return Err(ProcessError::InvalidFdLimit);
+    }
+    let mut table = PROCESS_TABLE.lock();
+    let process = table.fd_owner_mut(pid).ok_or(ProcessError::ProcessNotFound)?;
+    process.fd_limit = limit;
+    Ok(())
+}
+
+pub fn fd_limit(pid: Pid) -> Result<usize, ProcessError> {
+    let mut table = PROCESS_TABLE.lock();
+    let process = table.fd_owner_mut(pid).ok_or(ProcessError::ProcessNotFound)?;
+    Ok(process.fd_limit)
+}
+
+fn close_descriptor(mut descriptor: FileDescriptor) {
     if let Err(err) = descriptor.flush() {
         klog!("[process] flush on close failed: {:?}\n", err);
     }
-    Ok(())
 }
 
 pub fn with_fd_mut<F, R>(pid: Pid, fd: usize, f: F) -> Result<R, ProcessError>
@@ -2204,7 +3320,7 @@ where
     let mut descriptor = {
         let mut table = PROCESS_TABLE.lock();
         let process = table
-            .get_mut(pid)
+            .fd_owner_mut(pid)
             .ok_or(ProcessError::ProcessNotFound)?;
         let slot = process
             .fds
@@ -2218,7 +3334,7 @@ where
     {
         let mut table = PROCESS_TABLE.lock();
         let process = table
-            .get_mut(pid)
+            .fd_owner_mut(pid)
             .ok_or(ProcessError::ProcessNotFound)?;
         let slot = process
             .fds
@@ -2242,7 +3358,7 @@ where
     Ok(f(process))
 }
 
-fn state_name(state: ProcessState) -> &'static str {
+pub fn state_name(state: ProcessState) -> &'static str {
     match state {
         ProcessState::Ready => "Ready",
         ProcessState::Running => "Running",
@@ -2277,20 +3393,22 @@ pub fn dump_all_processes() {
 
     let stats = scheduler_stats();
     klog!(
-        "[process] summary total={} ready={} running={} blocked={} zombie={} slices={} need_resched={}\n",
+        "[process] summary total={} ready={} running={} blocked={} zombie={} slices={} cpu_ms={} need_resched={}\n",
         stats.total,
         stats.ready,
         stats.running,
         stats.blocked,
         stats.zombie,
         stats.total_slices,
+        stats.total_cpu_ms,
         stats.need_resched
     );
 }
 
 fn dump_process_inner(process: &Process) {
-    klog!("[process] dump pid={} name='{}' state={} parent={:?}\n",
+    klog!("[process] dump pid={} tgid={} name='{}' state={} parent={:?}\n",
         process.pid,
+        process.tgid,
         process.name,
         state_name(process.state),
         process.parent);
@@ -2311,13 +3429,25 @@ fn dump_process_inner(process: &Process) {
     if let Some(entry) = process.user_entry {
         klog!("           user_entry=0x{:016X}\n", entry);
     }
+    #[cfg(target_arch = "x86_64")]
+    if process.address_space.is_user() {
+        let (mut ranges, mut pages) = (0usize, 0u64);
+        paging::for_each_mapping(process.address_space.cr3(), |range| {
+            if range.start < mmu::KERNEL_VMA_BASE {
+                ranges += 1;
+                pages += range.pages();
+            }
+        });
+        klog!("           user mappings ranges={} pages={}\n", ranges, pages);
+    }
     klog!(
-        "           wait={:?} exit_code={:?} idle={} preempt_ret={:?} slices={}\n",
+        "           wait={:?} exit_code={:?} idle={} preempt_ret={:?} slices={} cpu_ms={}\n",
         process.wait_channel,
         process.exit_code,
         process.is_idle,
         process.preempt_return,
-        process.cpu_slices
+        process.cpu_slices,
+        timer::ticks_to_ms(process.cpu_ticks_at(timer::ticks()))
     );
 
     klog!(
@@ -2347,8 +3477,8 @@ fn dump_process_inner(process: &Process) {
                     klog!(
                         "           fd {:>2}: VfsFile '{}' offset={}\n",
                         fd,
-                        handle.file.name(),
-                        handle.offset
+                        handle.file().name(),
+                        handle.offset()
                     );
                 }
             }
@@ -2356,20 +3486,20 @@ fn dump_process_inner(process: &Process) {
     }
 
     for region in process.regions.iter() {
-        let kind = match region.kind {
+        let kind = match region.attrs.kind {
             MemoryRegionKind::Stack => "stack",
             MemoryRegionKind::Heap => "heap",
             MemoryRegionKind::Other => "other",
         };
-        let read = if region.permissions.read() { 'r' } else { '-' };
-        let write = if region.permissions.write() { 'w' } else { '-' };
-        let exec = if region.permissions.execute() { 'x' } else { '-' };
+        let permissions = region.attrs.permissions;
+        let read = if permissions.read() { 'r' } else { '-' };
+        let write = if permissions.write() { 'w' } else { '-' };
+        let exec = if permissions.execute() { 'x' } else { '-' };
         klog!(
-            "           region {:>6} base=0x{:016X} size={:>6} align={} perms={}{}{}\n",
+            "           region {:>6} base=0x{:016X} size={:>6} perms={}{}{}\n",
             kind,
-            region.base as usize,
-            region.layout.size(),
-            region.layout.align(),
+            region.start,
+            region.len(),
             read,
             write,
             exec
@@ -0,0 +1,75 @@
+#![allow(dead_code)]
+
+//! Condition variables on top of the scheduler's wait channels. Waiters and
+//! notifiers agree on an opaque `usize` token; nothing is allocated per
+//! token, so any address or constant will do.
+
+use crate::process::{self, ProcessError, WaitChannel};
+use crate::sync::spinlock::SpinLockGuard;
+
+/// Block the current process until `token` is notified.
+pub fn wait(token: usize) -> Result<(), ProcessError> {
+    process::block_current(WaitChannel::Token(token))
+}
+
+/// Release `guard` and block until `token` is notified, then take the lock
+/// again. A notify issued between the unlock and the switch still wakes us.
+pub fn wait_with<'a, T>(token: usize, guard: SpinLockGuard<'a, T>) -> Result<SpinLockGuard<'a, T>, ProcessError> {
+    let lock = SpinLockGuard::spinlock(&guard);
+    process::block_current_then(WaitChannel::Token(token), move || drop(guard))?;
+    Ok(lock.lock())
+}
+
+/// Wake one process waiting on `token`. Returns false if there was none.
+pub fn notify(token: usize) -> bool {
+    process::wake_one(WaitChannel::Token(token))
+}
+
+/// Wake every process waiting on `token`, returning how many there were.
+pub fn notify_all(token: usize) -> usize {
+    process::wake_channel(WaitChannel::Token(token))
+}
+
+/// `notify_all` for interrupt context. `None` means the process table was
+/// held by the interrupted code and nothing was woken.
+pub fn try_notify_all(token: usize) -> Option<usize> {
+    process::try_wake_channel(WaitChannel::Token(token))
+}
+
+/// A token bundled with the operations on it, for callers that would rather
+/// keep a condition variable next to the data it guards.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CondVar {
+    token: usize,
+}
+
+impl CondVar {
+    pub const fn new(token: usize) -> Self {
+        Self { token }
+    }
+
+    /// Key the condition variable on the address of `object`.
+    pub fn for_object<T>(object: &T) -> Self {
+        Self::new(object as *const T as usize)
+    }
+
+    pub fn token(&self) -> usize {
+        self.token
+    }
+
+    pub fn wait(&self) -> Result<(), ProcessError> {
+        wait(self.token)
+    }
+
+    pub fn wait_with<'a, T>(&self, guard: SpinLockGuard<'a, T>) -> Result<SpinLockGuard<'a, T>, ProcessError> {
+        wait_with(self.token, guard)
+    }
+
+    pub fn notify(&self) -> bool {
+        notify(self.token)
+    }
+
+    pub fn notify_all(&self) -> usize {
+        notify_all(self.token)
+    }
+}
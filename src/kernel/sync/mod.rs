@@ -1 +1,37 @@
+pub mod condvar;
+pub mod mutex;
+pub mod semaphore;
 pub mod spinlock;
+pub mod ticket;
+
+#[cfg(debug_assertions)]
+use crate::klog;
+
+/// Have debug builds name the holder of a stuck or re-entered spinlock on
+/// the serial log. The kernel runs on one CPU, so the owner is the pid.
+#[cfg(debug_assertions)]
+pub fn install_lock_debug() {
+    spinlock::set_owner_hook(lock_owner);
+    spinlock::set_report_hook(report_contention);
+}
+
+#[cfg(debug_assertions)]
+fn lock_owner() -> spinlock::LockOwner {
+    spinlock::LockOwner {
+        pid: crate::process::current_pid().unwrap_or(0),
+        cpu: 0,
+    }
+}
+
+#[cfg(debug_assertions)]
+fn report_contention(contention: &spinlock::Contention) {
+    let what = if contention.reentrant { "re-entrant lock" } else { "lock held too long" };
+    klog!("[sync] {} at {} after {} spins\n", what, contention.waiter, contention.spins);
+    match (contention.holder, contention.held_at) {
+        (Some(holder), Some(site)) => {
+            klog!("[sync]   held by pid {} (cpu {}) since {}\n", holder.pid, holder.cpu, site)
+        }
+        (None, Some(site)) => klog!("[sync]   held since {}\n", site),
+        _ => klog!("[sync]   holder unknown\n"),
+    }
+}
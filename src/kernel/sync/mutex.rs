@@ -0,0 +1,112 @@
+#![allow(dead_code)]
+
+//! Sleeping mutual exclusion for longer critical sections. A contended
+//! `lock()` blocks the caller on a wait token derived from the mutex's
+//! address instead of spinning, and `unlock` wakes one waiter.
+//!
+//! Only use it from process context: blocking needs a current process to
+//! put to sleep, so an IRQ handler must stick to `SpinLock`. Before the
+//! scheduler is up there is nobody to block and a contended `lock()` falls
+//! back to spinning. Like `Semaphore`, the mutex must not move while anyone
+//! is waiting on it.
+
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+
+use crate::sync::condvar;
+use crate::sync::spinlock::SpinLock;
+
+struct State {
+    locked: bool,
+    waiters: usize,
+}
+
+pub struct Mutex<T> {
+    state: SpinLock<State>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: SpinLock::new(State {
+                locked: false,
+                waiters: 0,
+            }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    fn token(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let mut state = self.state.lock();
+        while state.locked {
+            state.waiters += 1;
+            state = match condvar::wait_with(self.token(), state) {
+                Ok(state) => state,
+                Err(_) => {
+                    spin_loop();
+                    self.state.lock()
+                }
+            };
+            state.waiters -= 1;
+        }
+        state.locked = true;
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let mut state = self.state.lock();
+        if state.locked {
+            return None;
+        }
+        state.locked = true;
+        Some(MutexGuard { mutex: self })
+    }
+
+    /// Processes currently blocked in `lock()`.
+    pub fn waiters(&self) -> usize {
+        self.state.lock().waiters
+    }
+
+    fn unlock(&self) {
+        let waiters = {
+            let mut state = self.state.lock();
+            state.locked = false;
+            state.waiters
+        };
+        if waiters > 0 {
+            condvar::notify(self.token());
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+impl<T> core::ops::Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> core::ops::DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
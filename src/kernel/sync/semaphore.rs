@@ -0,0 +1,58 @@
+#![allow(dead_code)]
+
+//! Counting semaphore. Waiters block on a wait token derived from the
+//! semaphore's address, so a semaphore must stay put (a `static` or a field
+//! of something that never moves) while anyone is waiting on it.
+
+use crate::process::ProcessError;
+use crate::sync::condvar;
+use crate::sync::spinlock::SpinLock;
+
+pub struct Semaphore {
+    count: SpinLock<usize>,
+}
+
+impl Semaphore {
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count: SpinLock::new(count),
+        }
+    }
+
+    fn token(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Take one unit, blocking the current process while the count is zero.
+    pub fn acquire(&self) -> Result<(), ProcessError> {
+        let mut count = self.count.lock();
+        while *count == 0 {
+            count = condvar::wait_with(self.token(), count)?;
+        }
+        *count -= 1;
+        Ok(())
+    }
+
+    /// Take one unit if one is available without blocking.
+    pub fn try_acquire(&self) -> bool {
+        let mut count = self.count.lock();
+        if *count == 0 {
+            return false;
+        }
+        *count -= 1;
+        true
+    }
+
+    /// Return one unit and wake a single waiter, if any.
+    pub fn release(&self) {
+        {
+            let mut count = self.count.lock();
+            *count += 1;
+        }
+        condvar::notify(self.token());
+    }
+
+    pub fn available(&self) -> usize {
+        *self.count.lock()
+    }
+}
@@ -4,9 +4,19 @@ use core::cell::UnsafeCell;
 use core::hint::spin_loop;
 use core::sync::atomic::{AtomicBool, Ordering};
 
+#[cfg(debug_assertions)]
+pub use self::debug::{set_owner_hook, set_report_hook, Contention, LockOwner};
+
 /// Simple spinlock for kernel structures.
+///
+/// Debug builds also remember who took the lock and where. A `lock()` that
+/// finds the lock held by its own caller, or that spins for longer than
+/// `SPIN_REPORT_THRESHOLD`, hands a `Contention` to the report hook and then
+/// carries on spinning.
 pub struct SpinLock<T> {
     locked: AtomicBool,
+    #[cfg(debug_assertions)]
+    holder: debug::Holder,
     value: UnsafeCell<T>,
 }
 
@@ -17,29 +27,44 @@ impl<T> SpinLock<T> {
     pub const fn new(value: T) -> Self {
         Self {
             locked: AtomicBool::new(false),
+            #[cfg(debug_assertions)]
+            holder: debug::Holder::new(),
             value: UnsafeCell::new(value),
         }
     }
 
+    #[cfg_attr(debug_assertions, track_caller)]
     pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        #[cfg(debug_assertions)]
+        let mut watch = debug::Watch::new();
+
         while self
             .locked
             .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
             .is_err()
         {
             while self.locked.load(Ordering::Relaxed) {
+                #[cfg(debug_assertions)]
+                watch.spin(&self.holder);
                 spin_loop();
             }
         }
 
+        #[cfg(debug_assertions)]
+        self.holder.acquired(watch.caller);
         SpinLockGuard { lock: self }
     }
 
+    #[cfg_attr(debug_assertions, track_caller)]
     pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
         self.locked
             .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
             .ok()
-            .map(|_| SpinLockGuard { lock: self })
+            .map(|_| {
+                #[cfg(debug_assertions)]
+                self.holder.acquired(core::panic::Location::caller());
+                SpinLockGuard { lock: self }
+            })
     }
 }
 
@@ -47,8 +72,18 @@ pub struct SpinLockGuard<'a, T> {
     lock: &'a SpinLock<T>,
 }
 
+impl<'a, T> SpinLockGuard<'a, T> {
+    /// The lock this guard holds, so a caller that has to drop the guard can
+    /// take the same lock again later.
+    pub fn spinlock(guard: &Self) -> &'a SpinLock<T> {
+        guard.lock
+    }
+}
+
 impl<T> Drop for SpinLockGuard<'_, T> {
     fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        self.lock.holder.released();
         self.lock.locked.store(false, Ordering::Release);
     }
 }
@@ -66,3 +101,170 @@ impl<T> core::ops::DerefMut for SpinLockGuard<'_, T> {
         unsafe { &mut *self.lock.value.get() }
     }
 }
+
+#[cfg(debug_assertions)]
+mod debug {
+    use core::panic::Location;
+    use core::ptr;
+    use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+    /// Spins a contended `lock()` waits before it reports the holder.
+    pub(super) const SPIN_REPORT_THRESHOLD: usize = 1 << 24;
+
+    const NO_OWNER: u64 = u64::MAX;
+
+    /// Whoever was running when a lock was taken, as told by the owner hook.
+    #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+    pub struct LockOwner {
+        pub pid: u32,
+        pub cpu: u32,
+    }
+
+    impl LockOwner {
+        fn pack(self) -> u64 {
+            (self.cpu as u64) << 32 | self.pid as u64
+        }
+
+        fn unpack(raw: u64) -> Option<Self> {
+            if raw == NO_OWNER {
+                return None;
+            }
+            Some(Self {
+                pid: raw as u32,
+                cpu: (raw >> 32) as u32,
+            })
+        }
+    }
+
+    /// What the report hook is told about a lock that will not come free.
+    #[derive(Copy, Clone, Debug)]
+    pub struct Contention {
+        /// Where the waiting `lock()` was called.
+        pub waiter: &'static Location<'static>,
+        /// Where the holder took the lock, if it has not been released since.
+        pub held_at: Option<&'static Location<'static>>,
+        /// The holder, when an owner hook is installed.
+        pub holder: Option<LockOwner>,
+        /// The waiter already holds this lock and can never get it.
+        pub reentrant: bool,
+        pub spins: usize,
+    }
+
+    static OWNER_HOOK: AtomicUsize = AtomicUsize::new(0);
+    static REPORT_HOOK: AtomicUsize = AtomicUsize::new(0);
+    // Set while a report runs, so a hook that takes the contended lock (the
+    // console, say) cannot recurse into another report.
+    static REPORTING: AtomicBool = AtomicBool::new(false);
+
+    /// Install the function that names the current owner. Without one,
+    /// re-entrant locking cannot be told apart from ordinary contention.
+    pub fn set_owner_hook(hook: fn() -> LockOwner) {
+        OWNER_HOOK.store(hook as usize, Ordering::Release);
+    }
+
+    pub fn set_report_hook(hook: fn(&Contention)) {
+        REPORT_HOOK.store(hook as usize, Ordering::Release);
+    }
+
+    fn current_owner() -> Option<LockOwner> {
+        match OWNER_HOOK.load(Ordering::Acquire) {
+            0 => None,
+            raw => {
+                let hook = unsafe { core::mem::transmute::<usize, fn() -> LockOwner>(raw) };
+                Some(hook())
+            }
+        }
+    }
+
+    fn report(contention: &Contention) {
+        let raw = REPORT_HOOK.load(Ordering::Acquire);
+        if raw == 0 || REPORTING.swap(true, Ordering::Acquire) {
+            return;
+        }
+        // Cleared on drop, so a hook that panics does not silence later reports.
+        struct Reporting;
+        impl Drop for Reporting {
+            fn drop(&mut self) {
+                REPORTING.store(false, Ordering::Release);
+            }
+        }
+        let _reporting = Reporting;
+        let hook = unsafe { core::mem::transmute::<usize, fn(&Contention)>(raw) };
+        hook(contention);
+    }
+
+    pub(super) struct Holder {
+        owner: AtomicU64,
+        location: AtomicPtr<Location<'static>>,
+    }
+
+    impl Holder {
+        pub(super) const fn new() -> Self {
+            Self {
+                owner: AtomicU64::new(NO_OWNER),
+                location: AtomicPtr::new(ptr::null_mut()),
+            }
+        }
+
+        pub(super) fn acquired(&self, caller: &'static Location<'static>) {
+            let owner = current_owner().map_or(NO_OWNER, LockOwner::pack);
+            self.owner.store(owner, Ordering::Relaxed);
+            self.location
+                .store(caller as *const Location<'static> as *mut _, Ordering::Relaxed);
+        }
+
+        pub(super) fn released(&self) {
+            self.owner.store(NO_OWNER, Ordering::Relaxed);
+            self.location.store(ptr::null_mut(), Ordering::Relaxed);
+        }
+
+        fn snapshot(&self) -> (Option<LockOwner>, Option<&'static Location<'static>>) {
+            let owner = LockOwner::unpack(self.owner.load(Ordering::Relaxed));
+            let location = self.location.load(Ordering::Relaxed);
+            (owner, unsafe { location.as_ref() })
+        }
+    }
+
+    /// Per-call spin bookkeeping for one contended `lock()`.
+    pub(super) struct Watch {
+        pub(super) caller: &'static Location<'static>,
+        me: Option<LockOwner>,
+        spins: usize,
+        reported: bool,
+    }
+
+    impl Watch {
+        #[track_caller]
+        pub(super) fn new() -> Self {
+            Self {
+                caller: Location::caller(),
+                me: None,
+                spins: 0,
+                reported: false,
+            }
+        }
+
+        pub(super) fn spin(&mut self, holder: &Holder) {
+            if self.reported {
+                return;
+            }
+            if self.spins == 0 {
+                self.me = current_owner();
+            }
+            self.spins += 1;
+
+            let (owner, held_at) = holder.snapshot();
+            let reentrant = self.me.is_some() && owner == self.me;
+            if reentrant || self.spins >= SPIN_REPORT_THRESHOLD {
+                self.reported = true;
+                report(&Contention {
+                    waiter: self.caller,
+                    held_at,
+                    holder: owner,
+                    reentrant,
+                    spins: self.spins,
+                });
+            }
+        }
+    }
+}
@@ -14,9 +14,61 @@ pub mod nr {
     pub const WRITE: u64 = 1;
     pub const OPEN: u64 = 2;
     pub const CLOSE: u64 = 3;
+    pub const STAT: u64 = 4;
+    pub const FSTAT: u64 = 5;
+    pub const POLL: u64 = 7;
     pub const SEEK: u64 = 8;
+    pub const IOCTL: u64 = 16;
+    pub const WRITEV: u64 = 20;
     pub const YIELD: u64 = 24;
+    pub const SPAWN: u64 = 59;
     pub const EXIT: u64 = 60;
+    pub const WAITPID: u64 = 61;
+    pub const KILL: u64 = 62;
+    pub const GETDENTS: u64 = 78;
+    pub const SYSINFO: u64 = 99;
+    pub const SETPGID: u64 = 109;
+    pub const GETPGID: u64 = 121;
+    pub const REBOOT: u64 = 169;
+    pub const GETPROCS: u64 = 500;
+    pub const POWEROFF: u64 = 501;
+    pub const SYSTRACE: u64 = 502;
+
+    pub const NEG_ERRNO_FLAG: u64 = 0x4000_0000;
+
+    pub mod open_flags {
+        pub const ACCESS_MODE: u64 = 0o3;
+        pub const READ_ONLY: u64 = 0o0;
+        pub const WRITE_ONLY: u64 = 0o1;
+        pub const READ_WRITE: u64 = 0o2;
+        pub const CREATE: u64 = 0o100;
+        pub const APPEND: u64 = 0o2000;
+    }
+
+    pub mod poll_events {
+        pub const IN: u16 = 0x001;
+        pub const OUT: u16 = 0x004;
+        pub const NVAL: u16 = 0x020;
+    }
+
+    pub mod errno {
+        pub const EPERM: i64 = 1;
+        pub const ENOENT: i64 = 2;
+        pub const ESRCH: i64 = 3;
+        pub const EIO: i64 = 5;
+        pub const EBADF: i64 = 9;
+        pub const ECHILD: i64 = 10;
+        pub const ENOMEM: i64 = 12;
+        pub const EACCES: i64 = 13;
+        pub const EFAULT: i64 = 14;
+        pub const EBUSY: i64 = 16;
+        pub const EINVAL: i64 = 22;
+        pub const EMFILE: i64 = 24;
+        pub const ENOSPC: i64 = 28;
+        pub const ENOSYS: i64 = 38;
+        pub const ELOOP: i64 = 40;
+        pub const MAX_ERRNO: i64 = 4095;
+    }
 }
 
 #[cfg(not(target_arch = "x86_64"))]
@@ -37,6 +89,14 @@ pub enum SysError {
     NoEntry,
     NoMemory,
     Io,
+    NoChild,
+    PermissionDenied,
+    Loop,
+    NoSpace,
+    NoProcess,
+    NotPermitted,
+    Busy,
+    TooManyFiles,
 }
 
 #[cfg(not(target_arch = "x86_64"))]
@@ -50,6 +110,107 @@ pub enum SeekWhence {
     End,
 }
 
+#[cfg(not(target_arch = "x86_64"))]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Stat {
+    pub size: u64,
+    pub mode: u32,
+    pub block_size: u32,
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct SysInfo {
+    pub procs_total: u32,
+    pub procs_ready: u32,
+    pub procs_running: u32,
+    pub procs_blocked: u32,
+    pub procs_zombie: u32,
+    pub _reserved: u32,
+    pub cpu_slices: u64,
+    pub heap_free: u64,
+    pub phys_total: u64,
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub mod proc_state {
+    pub const READY: u32 = 0;
+    pub const RUNNING: u32 = 1;
+    pub const BLOCKED: u32 = 2;
+    pub const ZOMBIE: u32 = 3;
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub const PROC_NAME_LEN: usize = 16;
+
+#[cfg(not(target_arch = "x86_64"))]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct ProcInfo {
+    pub pid: u32,
+    pub parent: u32,
+    pub state: u32,
+    pub _reserved: u32,
+    pub cpu_slices: u64,
+    pub name: [u8; PROC_NAME_LEN],
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+impl ProcInfo {
+    pub const SIZE: usize = core::mem::size_of::<ProcInfo>();
+
+    pub fn name(&self) -> &[u8] {
+        let len = self.name.iter().position(|b| *b == 0).unwrap_or(PROC_NAME_LEN);
+        &self.name[..len]
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub const MAX_POLL_FDS: usize = 64;
+
+#[cfg(not(target_arch = "x86_64"))]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: u16,
+    pub revents: u16,
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+impl PollFd {
+    pub const SIZE: usize = core::mem::size_of::<PollFd>();
+
+    pub const fn new(fd: i32, events: u16) -> Self {
+        Self { fd, events, revents: 0 }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub const MAX_IOVECS: usize = 64;
+
+#[cfg(not(target_arch = "x86_64"))]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct IoVec {
+    pub base: u64,
+    pub len: u64,
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+impl IoVec {
+    pub const SIZE: usize = core::mem::size_of::<IoVec>();
+
+    pub fn new(bytes: &[u8]) -> Self {
+        Self {
+            base: bytes.as_ptr() as u64,
+            len: bytes.len() as u64,
+        }
+    }
+}
+
 #[cfg(not(target_arch = "x86_64"))]
 pub fn init() {}
 
@@ -63,21 +224,116 @@ pub fn write(_fd: u64, bytes: &[u8]) -> SysResult<usize> {
     Ok(bytes.len())
 }
 
+#[cfg(not(target_arch = "x86_64"))]
+pub fn writev(_fd: u64, parts: &[&[u8]]) -> SysResult<usize> {
+    Ok(parts.iter().map(|part| part.len()).sum())
+}
+
 #[cfg(not(target_arch = "x86_64"))]
 pub fn open(_path: &str) -> SysResult<usize> {
     Ok(0)
 }
 
+#[cfg(not(target_arch = "x86_64"))]
+pub fn open_with(_path: &str, _flags: u64) -> SysResult<usize> {
+    Ok(0)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn spawn(_path: &str, _argv: &[&str]) -> SysResult<crate::process::Pid> {
+    Err(SysError::NoSys)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn waitpid(_pid: Option<crate::process::Pid>) -> SysResult<(crate::process::Pid, i32)> {
+    Err(SysError::NoChild)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn kill(_pid: i64) -> SysResult<()> {
+    Err(SysError::NoSys)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn setpgid(_pid: crate::process::Pid, _pgid: crate::process::Pid) -> SysResult<()> {
+    Err(SysError::NoSys)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn getpgid(_pid: crate::process::Pid) -> SysResult<crate::process::Pid> {
+    Err(SysError::NoSys)
+}
+
 #[cfg(not(target_arch = "x86_64"))]
 pub fn close(_fd: u64) -> SysResult<()> {
     Ok(())
 }
 
+#[cfg(not(target_arch = "x86_64"))]
+pub fn ioctl(_fd: u64, _cmd: u32, _arg: u64) -> SysResult<u64> {
+    Err(SysError::NoSys)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn getdents(_fd: u64, _buf: &mut [u8]) -> SysResult<usize> {
+    Ok(0)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn fstat(_fd: u64) -> SysResult<Stat> {
+    Ok(Stat::default())
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn stat(_path: &str) -> SysResult<Stat> {
+    Ok(Stat::default())
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn sysinfo() -> SysResult<SysInfo> {
+    Err(SysError::NoSys)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn getprocs(_procs: &mut [ProcInfo]) -> SysResult<usize> {
+    Err(SysError::NoSys)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn poll(_fds: &mut [PollFd], _timeout_ms: i64) -> SysResult<usize> {
+    Err(SysError::NoSys)
+}
+
 #[cfg(not(target_arch = "x86_64"))]
 pub fn seek(_fd: u64, _offset: i64, _whence: SeekWhence) -> SysResult<u64> {
     Ok(0)
 }
 
+#[cfg(not(target_arch = "x86_64"))]
+pub fn reboot() -> SysResult<()> {
+    Err(SysError::NoSys)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn poweroff() -> SysResult<()> {
+    Err(SysError::NoSys)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn systrace(_enabled: bool) -> SysResult<bool> {
+    Err(SysError::NoSys)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn set_trace(_enabled: bool) -> bool {
+    false
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn call_count(_number: u64) -> u64 {
+    0
+}
+
 #[cfg(not(target_arch = "x86_64"))]
 pub fn yield_now() {}
 
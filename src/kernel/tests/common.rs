@@ -1,12 +1,16 @@
 #![cfg(kernel_test)]
 
+use core::hint::spin_loop;
 use core::sync::atomic::{AtomicBool, Ordering};
 
-use crate::drivers::{BlockDevice, Driver, DriverError, DriverKind};
+use crate::drivers::{check_block_range, BlockDevice, Driver, DriverError, DriverKind};
 use crate::fs::fat;
+use crate::process::{self, Pid, ProcessState};
 use crate::sync::spinlock::SpinLock;
 use crate::vfs::ata::AtaScratchFile;
 
+use super::TestResult;
+
 pub struct TestBlockDevice<const N: usize> {
     name: &'static str,
     block_size: usize,
@@ -57,40 +61,28 @@ impl<const N: usize> BlockDevice for TestBlockDevice<N> {
     }
 
     fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), DriverError> {
-        if buf.len() % self.block_size() != 0 {
-            return Err(DriverError::Unsupported);
-        }
-        let offset = (lba as usize)
-            .checked_mul(self.block_size())
-            .ok_or(DriverError::IoError)?;
-        let end = offset + buf.len();
+        check_block_range(self, lba, buf.len())?;
+        let offset = lba as usize * self.block_size;
         let guard = self.storage.lock();
-        if end > guard.len() {
-            return Err(DriverError::IoError);
-        }
-        buf.copy_from_slice(&guard[offset..end]);
+        buf.copy_from_slice(&guard[offset..offset + buf.len()]);
         Ok(())
     }
 
     fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), DriverError> {
-        if buf.len() % self.block_size() != 0 {
-            return Err(DriverError::Unsupported);
-        }
-        let offset = (lba as usize)
-            .checked_mul(self.block_size())
-            .ok_or(DriverError::IoError)?;
-        let end = offset + buf.len();
+        check_block_range(self, lba, buf.len())?;
+        let offset = lba as usize * self.block_size;
         let mut guard = self.storage.lock();
-        if end > guard.len() {
-            return Err(DriverError::IoError);
-        }
-        guard[offset..end].copy_from_slice(buf);
+        guard[offset..offset + buf.len()].copy_from_slice(buf);
         Ok(())
     }
 
     fn flush(&self) -> Result<(), DriverError> {
         Ok(())
     }
+
+    fn capacity_sectors(&self) -> u64 {
+        (N / self.block_size) as u64
+    }
 }
 
 const BLOCK_SIZE: usize = 512;
@@ -117,6 +109,12 @@ pub fn init_scratch() {
     }
 }
 
+/// HELLO.TXT's stamps in `hello_image` as `(date, time)` words, packed as
+/// FAT stores them: 2024-02-29 08:00:00 and 2024-03-01 12:30:14.
+/// DOCS and the rest have none.
+pub const HELLO_CREATED: (u16, u16) = ((44 << 9) | (2 << 5) | 29, 8 << 11);
+pub const HELLO_MODIFIED: (u16, u16) = ((44 << 9) | (3 << 5) | 1, (12 << 11) | (30 << 5) | 7);
+
 pub fn mount_hello() -> Result<(), &'static str> {
     if FAT_READY
         .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
@@ -127,12 +125,12 @@ pub fn mount_hello() -> Result<(), &'static str> {
         FAT_DEVICE
             .load_image(&image)
             .map_err(|_| "fat image too large")?;
-        fat::mount(&FAT_DEVICE, 0).map_err(|_| "fat mount failed")?;
+        fat::mount(fat::DEFAULT_VOLUME, &FAT_DEVICE, 0).map_err(|_| "fat mount failed")?;
     }
     Ok(())
 }
 
-fn hello_image() -> [u8; BLOCK_SIZE * 10] {
+pub fn hello_image() -> [u8; BLOCK_SIZE * 10] {
     let mut image = [0u8; BLOCK_SIZE * 10];
 
     {
@@ -156,8 +154,10 @@ fn hello_image() -> [u8; BLOCK_SIZE * 10] {
         fat[1] = 0xFF;
         fat[2] = 0xFF;
         fat[3] = 0xFF;
-        let cluster2 = 2 * 2;
-        fat[cluster2..cluster2 + 2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        for cluster in 2..=5usize {
+            let offset = cluster * 2;
+            fat[offset..offset + 2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        }
     }
 
     {
@@ -166,6 +166,23 @@ fn hello_image() -> [u8; BLOCK_SIZE * 10] {
         root[11] = 0x20;
         root[26..28].copy_from_slice(&(2u16).to_le_bytes());
         root[28..32].copy_from_slice(&(5u32).to_le_bytes());
+        let (created_date, created_time) = HELLO_CREATED;
+        root[14..16].copy_from_slice(&created_time.to_le_bytes());
+        root[16..18].copy_from_slice(&created_date.to_le_bytes());
+        let (modified_date, modified_time) = HELLO_MODIFIED;
+        root[22..24].copy_from_slice(&modified_time.to_le_bytes());
+        root[24..26].copy_from_slice(&modified_date.to_le_bytes());
+
+        let docs = &mut root[32..64];
+        docs[0..11].copy_from_slice(b"DOCS       ");
+        docs[11] = 0x10;
+        docs[26..28].copy_from_slice(&(3u16).to_le_bytes());
+
+        let exit = &mut root[64..96];
+        exit[0..11].copy_from_slice(b"EXIT7      ");
+        exit[11] = 0x20;
+        exit[26..28].copy_from_slice(&(5u16).to_le_bytes());
+        exit[28..32].copy_from_slice(&(EXIT_ELF_LEN as u32).to_le_bytes());
     }
 
     {
@@ -173,5 +190,258 @@ fn hello_image() -> [u8; BLOCK_SIZE * 10] {
         data[..5].copy_from_slice(b"Hello");
     }
 
+    {
+        // DOCS/ at cluster 3: dot entries followed by README.TXT.
+        let dir = &mut image[BLOCK_SIZE * 4..BLOCK_SIZE * 5];
+        dir[0..11].copy_from_slice(b".          ");
+        dir[11] = 0x10;
+        dir[26..28].copy_from_slice(&(3u16).to_le_bytes());
+        dir[32..43].copy_from_slice(b"..         ");
+        dir[43] = 0x10;
+        let readme = &mut dir[64..96];
+        readme[0..11].copy_from_slice(b"README  TXT");
+        readme[11] = 0x21;
+        readme[26..28].copy_from_slice(&(4u16).to_le_bytes());
+        readme[28..32].copy_from_slice(&(6u32).to_le_bytes());
+    }
+
+    {
+        let data = &mut image[BLOCK_SIZE * 5..BLOCK_SIZE * 6];
+        data[..6].copy_from_slice(b"Readme");
+    }
+
+    {
+        let data = &mut image[BLOCK_SIZE * 6..BLOCK_SIZE * 7];
+        data[..EXIT_ELF_LEN].copy_from_slice(&exit_elf(EXIT7_CODE as u8));
+    }
+
     image
 }
+
+/// Exit status of `/fat/EXIT7`.
+pub const EXIT7_CODE: i32 = 7;
+
+pub const EXIT_ELF_LEN: usize = 132;
+pub const UD2_ELF_LEN: usize = 122;
+pub const STORE_ELF_LEN: usize = 126;
+pub const RODATA_ELF_LEN: usize = 208;
+pub const READ_REGS_ELF_LEN: usize = 268;
+
+pub const ELF_BASE: u64 = 0x40_0000;
+pub const ELF_CODE_OFFSET: usize = 120;
+
+const PF_X: u32 = 1;
+const PF_R: u32 = 4;
+
+/// Where `store_to_rodata_elf` puts its read-only data: eight bytes from
+/// the file, then zeroes to `RODATA_MEMSZ`, so the segment ends in a
+/// partly filled page followed by a page that is all zero fill.
+pub const RODATA_VADDR: u64 = ELF_BASE + 0x2000;
+pub const RODATA_MEMSZ: u64 = 0x1800;
+const RODATA_ELF_CODE_OFFSET: usize = 176;
+const RODATA_FILE_OFFSET: usize = 200;
+
+/// Smallest useful user program: one PT_LOAD segment at 0x400000 whose code
+/// is `mov edi, code; mov eax, 60; syscall`.
+pub fn exit_elf(code: u8) -> [u8; EXIT_ELF_LEN] {
+    let mut elf = [0u8; EXIT_ELF_LEN];
+    write_elf_headers(&mut elf, PF_R | PF_X);
+    elf[ELF_CODE_OFFSET..].copy_from_slice(&[
+        0xBF, code, 0x00, 0x00, 0x00, // mov edi, code
+        0xB8, 0x3C, 0x00, 0x00, 0x00, // mov eax, 60
+        0x0F, 0x05, // syscall
+    ]);
+    elf
+}
+
+/// A user program whose first instruction is `ud2`.
+pub fn ud2_elf() -> [u8; UD2_ELF_LEN] {
+    let mut elf = [0u8; UD2_ELF_LEN];
+    write_elf_headers(&mut elf, PF_R | PF_X);
+    elf[ELF_CODE_OFFSET..].copy_from_slice(&[0x0F, 0x0B]);
+    elf
+}
+
+/// A user program that stores a byte into its own read-only text, just past
+/// the store instruction (`ELF_BASE + ELF_CODE_OFFSET + 6`).
+pub fn store_to_text_elf() -> [u8; STORE_ELF_LEN] {
+    let mut elf = [0u8; STORE_ELF_LEN];
+    write_elf_headers(&mut elf, PF_R | PF_X);
+    elf[ELF_CODE_OFFSET..].copy_from_slice(&[
+        0x88, 0x05, 0x00, 0x00, 0x00, 0x00, // mov [rip + 0], al
+    ]);
+    elf
+}
+
+/// `ud2_elf` with its only segment mapped readable but not executable, so
+/// fetching the entry instruction faults before `ud2` can run.
+pub fn no_exec_elf() -> [u8; UD2_ELF_LEN] {
+    let mut elf = [0u8; UD2_ELF_LEN];
+    write_elf_headers(&mut elf, PF_R);
+    elf[ELF_CODE_OFFSET..].copy_from_slice(&[0x0F, 0x0B]);
+    elf
+}
+
+/// Exit status bits from `read_regs_elf`; 0 means everything survived.
+pub mod read_regs {
+    pub const BAD_RETURN: i32 = 1;
+    pub const ARGS_CLOBBERED: i32 = 2;
+    pub const CALLEE_SAVED_CLOBBERED: i32 = 4;
+    pub const WRONG_BYTE: i32 = 8;
+}
+
+/// A user program that fills rbx, rbp, r12, r8 and r10 with marker values,
+/// reads one byte from stdin into `[rsp - 64]` and exits with the
+/// `read_regs` bits for whatever came back wrong: `rax` not 1, any of
+/// rdi/rsi/rdx/r8/r10 changed, any of rbx/rbp/r12 changed, or a byte other
+/// than `a`.
+pub fn read_regs_elf() -> [u8; READ_REGS_ELF_LEN] {
+    let mut elf = [0u8; READ_REGS_ELF_LEN];
+    write_elf_headers(&mut elf, PF_R | PF_X);
+    elf[ELF_CODE_OFFSET..].copy_from_slice(&[
+        0xBB, 0x0B, 0x0B, 0x0B, 0x0B, // mov ebx, 0x0B0B0B0B
+        0xBD, 0x05, 0x05, 0x05, 0x05, // mov ebp, 0x05050505
+        0x41, 0xBC, 0x0C, 0x0C, 0x0C, 0x0C, // mov r12d, 0x0C0C0C0C
+        0x41, 0xB8, 0x08, 0x08, 0x08, 0x08, // mov r8d, 0x08080808
+        0x41, 0xBA, 0x0A, 0x0A, 0x0A, 0x0A, // mov r10d, 0x0A0A0A0A
+        0x48, 0x8D, 0x74, 0x24, 0xC0, // lea rsi, [rsp - 64]
+        0x31, 0xFF, // xor edi, edi
+        0xBA, 0x01, 0x00, 0x00, 0x00, // mov edx, 1
+        0x31, 0xC0, // xor eax, eax (read)
+        0x0F, 0x05, // syscall
+        0x31, 0xC9, // xor ecx, ecx
+        0x48, 0x83, 0xF8, 0x01, // cmp rax, 1
+        0x74, 0x03, // je +3
+        0x80, 0xC9, 0x01, // or cl, BAD_RETURN
+        0x4C, 0x8D, 0x5C, 0x24, 0xC0, // lea r11, [rsp - 64]
+        0x4C, 0x31, 0xDE, // xor rsi, r11
+        0x48, 0x09, 0xFE, // or rsi, rdi
+        0x48, 0xFF, 0xCA, // dec rdx
+        0x48, 0x09, 0xD6, // or rsi, rdx
+        0x49, 0x81, 0xF0, 0x08, 0x08, 0x08, 0x08, // xor r8, 0x08080808
+        0x4C, 0x09, 0xC6, // or rsi, r8
+        0x49, 0x81, 0xF2, 0x0A, 0x0A, 0x0A, 0x0A, // xor r10, 0x0A0A0A0A
+        0x4C, 0x09, 0xD6, // or rsi, r10
+        0x74, 0x03, // jz +3
+        0x80, 0xC9, 0x02, // or cl, ARGS_CLOBBERED
+        0x48, 0x81, 0xF3, 0x0B, 0x0B, 0x0B, 0x0B, // xor rbx, 0x0B0B0B0B
+        0x48, 0x81, 0xF5, 0x05, 0x05, 0x05, 0x05, // xor rbp, 0x05050505
+        0x48, 0x09, 0xEB, // or rbx, rbp
+        0x49, 0x81, 0xF4, 0x0C, 0x0C, 0x0C, 0x0C, // xor r12, 0x0C0C0C0C
+        0x4C, 0x09, 0xE3, // or rbx, r12
+        0x74, 0x03, // jz +3
+        0x80, 0xC9, 0x04, // or cl, CALLEE_SAVED_CLOBBERED
+        0x80, 0x7C, 0x24, 0xC0, 0x61, // cmp byte [rsp - 64], 'a'
+        0x74, 0x03, // je +3
+        0x80, 0xC9, 0x08, // or cl, WRONG_BYTE
+        0x89, 0xCF, // mov edi, ecx
+        0xB8, 0x3C, 0x00, 0x00, 0x00, // mov eax, 60
+        0x0F, 0x05, // syscall
+    ]);
+    elf
+}
+
+/// A user program with an R+X text segment and a separate read-only
+/// `.rodata` segment at `RODATA_VADDR`. It stores a byte into `.rodata`
+/// (`mov rax, RODATA_VADDR; mov [rax], al`) and would exit 0 if that store
+/// did not fault.
+pub fn store_to_rodata_elf() -> [u8; RODATA_ELF_LEN] {
+    let mut elf = [0u8; RODATA_ELF_LEN];
+    write_ehdr(&mut elf, RODATA_ELF_CODE_OFFSET, 2);
+    write_phdr(&mut elf[64..120], PF_R | PF_X, 0, ELF_BASE, RODATA_FILE_OFFSET as u64, RODATA_FILE_OFFSET as u64);
+    write_phdr(&mut elf[120..176], PF_R, RODATA_FILE_OFFSET as u64, RODATA_VADDR, 8, RODATA_MEMSZ);
+
+    let code = &mut elf[RODATA_ELF_CODE_OFFSET..RODATA_FILE_OFFSET];
+    code[0..2].copy_from_slice(&[0x48, 0xB8]); // mov rax, imm64
+    code[2..10].copy_from_slice(&RODATA_VADDR.to_le_bytes());
+    code[10..12].copy_from_slice(&[0x88, 0x00]); // mov [rax], al
+    code[12..24].copy_from_slice(&[
+        0xBF, 0x00, 0x00, 0x00, 0x00, // mov edi, 0
+        0xB8, 0x3C, 0x00, 0x00, 0x00, // mov eax, 60
+        0x0F, 0x05, // syscall
+    ]);
+    elf[RODATA_FILE_OFFSET..].copy_from_slice(b"rodata!\0");
+    elf
+}
+
+fn write_ehdr(elf: &mut [u8], entry_offset: usize, phnum: u16) {
+    elf[0..4].copy_from_slice(&[0x7F, b'E', b'L', b'F']);
+    elf[4] = 2; // ELFCLASS64
+    elf[5] = 1; // little endian
+    elf[6] = 1; // EV_CURRENT
+    elf[16..18].copy_from_slice(&2u16.to_le_bytes()); // ET_EXEC
+    elf[18..20].copy_from_slice(&0x3Eu16.to_le_bytes());
+    elf[20..24].copy_from_slice(&1u32.to_le_bytes());
+    elf[24..32].copy_from_slice(&(ELF_BASE + entry_offset as u64).to_le_bytes());
+    elf[32..40].copy_from_slice(&64u64.to_le_bytes());
+    elf[52..54].copy_from_slice(&64u16.to_le_bytes());
+    elf[54..56].copy_from_slice(&56u16.to_le_bytes());
+    elf[56..58].copy_from_slice(&phnum.to_le_bytes());
+}
+
+fn write_phdr(phdr: &mut [u8], p_flags: u32, offset: u64, vaddr: u64, filesz: u64, memsz: u64) {
+    phdr[0..4].copy_from_slice(&1u32.to_le_bytes()); // PT_LOAD
+    phdr[4..8].copy_from_slice(&p_flags.to_le_bytes());
+    phdr[8..16].copy_from_slice(&offset.to_le_bytes());
+    phdr[16..24].copy_from_slice(&vaddr.to_le_bytes());
+    phdr[24..32].copy_from_slice(&vaddr.to_le_bytes());
+    phdr[32..40].copy_from_slice(&filesz.to_le_bytes());
+    phdr[40..48].copy_from_slice(&memsz.to_le_bytes());
+    phdr[48..56].copy_from_slice(&0x1000u64.to_le_bytes());
+}
+
+/// ELF and program headers mapping all of `elf` at `ELF_BASE` with segment
+/// flags `p_flags`, and the entry point at `ELF_CODE_OFFSET`.
+fn write_elf_headers(elf: &mut [u8], p_flags: u32) {
+    let len = elf.len() as u64;
+    write_ehdr(elf, ELF_CODE_OFFSET, 1);
+    write_phdr(&mut elf[64..120], p_flags, 0, ELF_BASE, len, len);
+}
+
+/// Turn finished test tasks into zombies so they leave the run queue. Tests
+/// that really switch contexts would otherwise hand the CPU to a leftover
+/// spinning stub that never gives it back.
+pub fn retire(pids: &[Pid]) {
+    for &pid in pids {
+        let _ = process::exit_process(pid, 0);
+    }
+}
+
+/// Run `body` as a dormant kernel process. The leader's saved context becomes
+/// the test's continuation once it yields, so tasks spawned inside `body`
+/// really run and then switch back.
+pub fn with_leader(name: &'static str, body: fn(Pid) -> TestResult) -> TestResult {
+    extern "C" fn dormant() -> ! {
+        loop {
+            spin_loop();
+        }
+    }
+
+    let leader = process::spawn_kernel_process(name, dormant).map_err(|_| "spawn leader failed")?;
+    process::set_current_pid(leader);
+    let result = body(leader);
+    process::set_current_pid(0);
+    retire(&[leader]);
+    result
+}
+
+/// Yield until `child` has exited, then reap it and return its status.
+pub fn reap_child(child: Pid) -> Result<i32, &'static str> {
+    for _ in 0..16 {
+        match process::get_process(child) {
+            Some(snapshot) if snapshot.state() == ProcessState::Zombie => break,
+            Some(_) => process::yield_now(),
+            None => return Err("child vanished before it was reaped"),
+        }
+    }
+    let zombie = process::get_process(child).map(|snapshot| snapshot.state()) == Some(ProcessState::Zombie);
+    if !zombie {
+        retire(&[child]);
+        return Err("child did not exit");
+    }
+    let (pid, code) = process::wait_for_child(Some(child)).map_err(|_| "wait_for_child failed")?;
+    if pid != child {
+        return Err("reaped the wrong child");
+    }
+    Ok(code)
+}
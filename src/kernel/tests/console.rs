@@ -0,0 +1,178 @@
+#![cfg(kernel_test)]
+
+use core::fmt::Write;
+
+use super::common::with_leader;
+use super::{TestCase, TestResult};
+use crate::arch::x86_64::drivers::console as vga;
+use crate::drivers::{console, ioctl};
+use crate::syscall::{self, fd};
+
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("console.scrollback_retains_lines", scrollback_retains_lines),
+    TestCase::new("console.scroll_view_roundtrip", scroll_view_roundtrip),
+    TestCase::new("console.ioctl_clear", ioctl_clear),
+    TestCase::new("console.ioctl_set_cursor", ioctl_set_cursor),
+    TestCase::new("console.escape_clear_and_home", escape_clear_and_home),
+    TestCase::new("console.writev_fragments", writev_fragments),
+];
+
+const EXTRA_LINES: usize = 4;
+
+struct LineBuf {
+    buf: [u8; 16],
+    len: usize,
+}
+
+impl Write for LineBuf {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let bytes = s.as_bytes();
+        let end = self.len + bytes.len();
+        if end > self.buf.len() {
+            return Err(core::fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+fn write_numbered_lines(count: usize) -> TestResult {
+    for i in 0..count {
+        let mut line = LineBuf { buf: [0; 16], len: 0 };
+        writeln!(line, "line {:02}", i).map_err(|_| "format failed")?;
+        console::write_bytes(&line.buf[..line.len]).map_err(|_| "console write failed")?;
+    }
+    Ok(())
+}
+
+fn row_starts_with(row: &[u8], prefix: &[u8]) -> bool {
+    row.len() >= prefix.len() && &row[..prefix.len()] == prefix
+}
+
+fn scrollback_retains_lines() -> TestResult {
+    console::clear();
+    // HEIGHT - 1 lines fill the screen above the cursor row; the rest scroll off.
+    write_numbered_lines(vga::HEIGHT - 1 + EXTRA_LINES)?;
+
+    let len = console::scrollback_len();
+    if len < EXTRA_LINES {
+        return Err("scrollback did not capture scrolled rows");
+    }
+    for i in 0..EXTRA_LINES {
+        let row = console::scrollback_line(len - EXTRA_LINES + i).ok_or("scrollback row missing")?;
+        let mut expected = *b"line 00";
+        expected[5] = b'0' + (i / 10) as u8;
+        expected[6] = b'0' + (i % 10) as u8;
+        if !row_starts_with(&row, &expected) {
+            return Err("scrollback row out of order");
+        }
+    }
+    Ok(())
+}
+
+fn scroll_view_roundtrip() -> TestResult {
+    console::clear();
+    write_numbered_lines(vga::HEIGHT - 1 + EXTRA_LINES)?;
+    let live_top = vga::read_row(0);
+
+    console::scroll_view(EXTRA_LINES as isize);
+    let top = vga::read_row(0);
+    if (top[0] & 0xFF) as u8 != b'l' || (top[6] & 0xFF) as u8 != b'0' {
+        return Err("scrolled view should show line 00 at the top");
+    }
+
+    // Any output returns to the live screen before it is drawn.
+    console::write_bytes(b"").map_err(|_| "console write failed")?;
+    if vga::read_row(0) != live_top {
+        return Err("write did not snap back to live view");
+    }
+    Ok(())
+}
+
+fn screen_is_blank() -> bool {
+    (0..vga::HEIGHT).all(|row| vga::read_row(row).iter().all(|cell| (cell & 0xFF) as u8 == b' '))
+}
+
+fn ioctl_clear() -> TestResult {
+    write_numbered_lines(2)?;
+    let device = console::driver();
+    device.ioctl(ioctl::CONSOLE_CLEAR, 0).map_err(|_| "clear ioctl failed")?;
+    if !screen_is_blank() {
+        return Err("clear should blank the screen");
+    }
+    if console::cursor() != (0, 0) {
+        return Err("clear should home the cursor");
+    }
+    if device.ioctl(ioctl::TTY_GET_CANONICAL, 0).is_ok() {
+        return Err("console should reject tty ioctls");
+    }
+    Ok(())
+}
+
+fn ioctl_set_cursor() -> TestResult {
+    console::clear();
+    let device = console::driver();
+    device
+        .ioctl(ioctl::CONSOLE_SET_CURSOR, (5 << 16) | 10)
+        .map_err(|_| "set cursor ioctl failed")?;
+    if console::cursor() != (5, 10) {
+        return Err("cursor not moved");
+    }
+    console::write_bytes(b"X").map_err(|_| "console write failed")?;
+    if (vga::read_row(5)[10] & 0xFF) as u8 != b'X' {
+        return Err("text should land at the new cursor");
+    }
+
+    device
+        .ioctl(ioctl::CONSOLE_SET_CURSOR, (0xFFFF << 16) | 0xFFFF)
+        .map_err(|_| "set cursor ioctl failed")?;
+    if console::cursor() != (vga::HEIGHT - 1, vga::WIDTH - 1) {
+        return Err("cursor should clamp to the screen");
+    }
+    console::clear();
+    Ok(())
+}
+
+fn escape_clear_and_home() -> TestResult {
+    console::clear();
+    write_numbered_lines(3)?;
+    console::write_bytes(b"\x1b[2J").map_err(|_| "console write failed")?;
+    if !screen_is_blank() || console::cursor() != (0, 0) {
+        return Err("ESC[2J should clear like CONSOLE_CLEAR");
+    }
+
+    console::write_bytes(b"ab\x1b[H").map_err(|_| "console write failed")?;
+    if console::cursor() != (0, 0) {
+        return Err("ESC[H should home the cursor");
+    }
+    Ok(())
+}
+
+const FRAGMENTED: &[u8] = b"writev keeps every fragment: 0123456789 abcdefghijklmnopqrstuvwxyz";
+
+fn writev_fragments() -> TestResult {
+    with_leader("writev_leader", |_| {
+        let mut parts: [&[u8]; 32] = [&[]; 32];
+        let mut count = 0;
+        for (slot, chunk) in parts.iter_mut().zip(FRAGMENTED.chunks(3)) {
+            *slot = chunk;
+            count += 1;
+        }
+
+        console::clear();
+        let written = syscall::writev(fd::STDOUT, &parts[..count]).map_err(|_| "writev failed")?;
+        if written != FRAGMENTED.len() {
+            return Err("writev should take every fragment");
+        }
+        let row = vga::read_row(0);
+        if !row.iter().zip(FRAGMENTED).all(|(cell, byte)| (cell & 0xFF) as u8 == *byte) {
+            return Err("fragments missing or out of order");
+        }
+        if console::cursor() != (0, FRAGMENTED.len()) {
+            return Err("writev printed more than it was given");
+        }
+        console::clear();
+        Ok(())
+    })
+}
@@ -0,0 +1,49 @@
+#![cfg(kernel_test)]
+
+use core::array;
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::{TestCase, TestResult};
+use crate::arch::x86_64::kernel::apic;
+use crate::cpu::{self, CpuLocal};
+use crate::process;
+
+pub const TESTS: &[TestCase] = &[TestCase::new("cpu.local_is_cpu0_slot", local_is_cpu0_slot)];
+
+fn local_is_cpu0_slot() -> TestResult {
+    if apic::is_present() {
+        if !apic::is_mapped() {
+            return Err("LAPIC present but its registers were not mapped at boot");
+        }
+        if apic::id() != Some(0) {
+            return Err("the boot CPU should have APIC id 0");
+        }
+    }
+    if cpu::apic_id() != 0 {
+        return Err("apic_id should be 0 on a uniprocessor boot");
+    }
+
+    let local: CpuLocal<AtomicU32> = CpuLocal::new(array::from_fn(|i| AtomicU32::new(i as u32 * 10)));
+    // Claiming again hands back the slot taken at boot.
+    if cpu::claim_slot(cpu::apic_id()) != Some(0) {
+        return Err("the boot CPU should have claimed slot 0");
+    }
+    if !ptr::eq(local.get(), local.for_slot(0)) {
+        return Err("get() did not pick the cpu-0 slot");
+    }
+    local.get().store(99, Ordering::Relaxed);
+    if local.for_slot(0).load(Ordering::Relaxed) != 99 || local.for_slot(1).load(Ordering::Relaxed) != 10 {
+        return Err("a store through get() reached the wrong slot");
+    }
+
+    // The current pid is per-CPU now; on one CPU it behaves as before.
+    let saved = process::current_pid();
+    process::set_current_pid(42);
+    let seen = process::current_pid();
+    process::set_current_pid(saved.unwrap_or(0));
+    if seen != Some(42) {
+        return Err("current_pid did not read back from this CPU's slot");
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+#![cfg(kernel_test)]
+
+use super::{TestCase, TestResult};
+use crate::crash::expect::{self, PANIC_EXIT_CODE};
+use crate::klog::capture;
+use crate::process::{self, KERNEL_STACK_SIZE};
+use crate::tests::common::with_leader;
+
+pub const TESTS: &[TestCase] = &[TestCase::new("crash.panic_dump", panic_dump)];
+
+/// Lowest address of the higher-half kernel image.
+const KERNEL_TEXT_BASE: u64 = 0xFFFF_FFFF_8000_0000;
+
+extern "C" fn panicker() -> ! {
+    deliberate_panic()
+}
+
+#[inline(never)]
+fn deliberate_panic() -> ! {
+    panic!("deliberate test panic");
+}
+
+fn panic_dump() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("panic_leader", |_| {
+        let child = process::spawn_kernel_process("panicker", panicker).map_err(|_| "spawn panicker failed")?;
+        let stack = process::get_process(child).ok_or("panicker missing")?.kernel_stack_base();
+
+        expect::panic_in(child);
+        capture::start();
+        let waited = process::wait_for_child(Some(child));
+        let mut buf = [0u8; 2048];
+        let len = capture::finish(&mut buf);
+
+        let (_, code) = waited.map_err(|_| "reaping the panicked task failed")?;
+        if code != PANIC_EXIT_CODE {
+            return Err("panicked task did not exit through the handler");
+        }
+        let text = core::str::from_utf8(&buf[..len]).map_err(|_| "dump is not utf8")?;
+        if !text.contains("deliberate test panic") || !text.contains("(panicker)") {
+            return Err("dump lacks the message or the task name");
+        }
+        if !text.contains("rsp=0x") || !text.contains("cr3=0x") {
+            return Err("dump lacks the registers");
+        }
+        check_frames(text, stack..stack + KERNEL_STACK_SIZE as u64)
+    })
+}
+
+/// Every frame must sit on the task's own kernel stack and return into the
+/// kernel image, and the walk must get past the panic handler itself.
+fn check_frames(text: &str, stack: core::ops::Range<u64>) -> TestResult {
+    let mut frames = 0;
+    for line in text.lines().filter(|line| line.starts_with("[kpanic]   #")) {
+        let rbp = hex_after(line, "rbp=0x").ok_or("frame line without rbp")?;
+        let ret = hex_after(line, "ret=0x").ok_or("frame line without a return address")?;
+        if !stack.contains(&rbp) {
+            return Err("frame outside the panicking task's stack");
+        }
+        if ret < KERNEL_TEXT_BASE {
+            return Err("return address outside the kernel image");
+        }
+        frames += 1;
+    }
+    if frames < 2 {
+        return Err("backtrace stopped at the panic handler");
+    }
+    Ok(())
+}
+
+fn hex_after(line: &str, marker: &str) -> Option<u64> {
+    let start = line.find(marker)? + marker.len();
+    let digits = line[start..].split(' ').next()?;
+    u64::from_str_radix(digits, 16).ok()
+}
@@ -0,0 +1,184 @@
+#![cfg(kernel_test)]
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{TestCase, TestResult};
+use crate::arch::x86_64::kernel::paging::{self, FLAG_NO_EXECUTE};
+use crate::process;
+use crate::user::elf::{self, ElfError};
+use crate::user::space::{self, USER_ADDR_LIMIT};
+
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("elf.accepts_disjoint_segments", accepts_disjoint_segments),
+    TestCase::new("elf.rejects_segment_past_file", rejects_segment_past_file),
+    TestCase::new("elf.rejects_segment_outside_user_space", rejects_segment_outside_user_space),
+    TestCase::new("elf.rejects_filesz_over_memsz", rejects_filesz_over_memsz),
+    TestCase::new("elf.rejects_overlapping_segments", rejects_overlapping_segments),
+    TestCase::new("elf.gnu_stack_flags", gnu_stack_flags),
+    TestCase::new("elf.nx_stack_mapping", nx_stack_mapping),
+    TestCase::new("elf.rejects_shared_segment_page", rejects_shared_segment_page),
+];
+
+const HEADER_LEN: usize = 64;
+const PHDR_LEN: usize = 56;
+const BASE: u64 = 0x40_0000;
+const PT_GNU_STACK: u32 = 0x6474_E551;
+
+struct Load {
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+    memsz: u64,
+}
+
+/// A minimal x86_64 executable with one PT_LOAD per `loads`, padded to
+/// `file_len` bytes.
+fn image(loads: &[Load], file_len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; file_len.max(HEADER_LEN + loads.len() * PHDR_LEN)];
+    bytes[0..4].copy_from_slice(&[0x7F, b'E', b'L', b'F']);
+    bytes[4] = 2; // ELFCLASS64
+    bytes[5] = 1; // little endian
+    bytes[6] = 1;
+    bytes[16..18].copy_from_slice(&2u16.to_le_bytes());
+    bytes[18..20].copy_from_slice(&0x3Eu16.to_le_bytes());
+    bytes[24..32].copy_from_slice(&BASE.to_le_bytes());
+    bytes[32..40].copy_from_slice(&(HEADER_LEN as u64).to_le_bytes());
+    bytes[54..56].copy_from_slice(&(PHDR_LEN as u16).to_le_bytes());
+    bytes[56..58].copy_from_slice(&(loads.len() as u16).to_le_bytes());
+
+    for (index, load) in loads.iter().enumerate() {
+        let phdr = &mut bytes[HEADER_LEN + index * PHDR_LEN..][..PHDR_LEN];
+        phdr[0..4].copy_from_slice(&1u32.to_le_bytes()); // PT_LOAD
+        phdr[4..8].copy_from_slice(&5u32.to_le_bytes());
+        phdr[8..16].copy_from_slice(&load.offset.to_le_bytes());
+        phdr[16..24].copy_from_slice(&load.vaddr.to_le_bytes());
+        phdr[32..40].copy_from_slice(&load.filesz.to_le_bytes());
+        phdr[40..48].copy_from_slice(&load.memsz.to_le_bytes());
+        phdr[48..56].copy_from_slice(&0x1000u64.to_le_bytes());
+    }
+    bytes
+}
+
+fn expect(bytes: &[u8], want: ElfError) -> TestResult {
+    match elf::parse(bytes) {
+        Err(err) if err == want => Ok(()),
+        Err(_) => Err("parse failed with the wrong error"),
+        Ok(_) => Err("malformed image was accepted"),
+    }
+}
+
+fn accepts_disjoint_segments() -> TestResult {
+    let bytes = image(
+        &[
+            Load { offset: 0, vaddr: BASE, filesz: 0x100, memsz: 0x100 },
+            Load { offset: 0x100, vaddr: BASE + 0x100, filesz: 0x40, memsz: 0x2000 },
+        ],
+        0x200,
+    );
+    let parsed = elf::parse(&bytes).map_err(|_| "valid image rejected")?;
+    if parsed.segments.len() != 2 {
+        return Err("both segments should be kept");
+    }
+    Ok(())
+}
+
+fn rejects_segment_past_file() -> TestResult {
+    let bytes = image(&[Load { offset: 0x180, vaddr: BASE, filesz: 0x100, memsz: 0x100 }], 0x200);
+    expect(&bytes, ElfError::SegmentOutOfFile)?;
+    let bytes = image(&[Load { offset: u64::MAX, vaddr: BASE, filesz: 2, memsz: 2 }], 0x200);
+    expect(&bytes, ElfError::SegmentOutOfFile)
+}
+
+fn rejects_segment_outside_user_space() -> TestResult {
+    let bytes = image(&[Load { offset: 0, vaddr: USER_ADDR_LIMIT - 0x10, filesz: 0, memsz: 0x20 }], 0x200);
+    expect(&bytes, ElfError::SegmentOutsideUserSpace)?;
+    let bytes = image(&[Load { offset: 0, vaddr: u64::MAX - 8, filesz: 0, memsz: 0x20 }], 0x200);
+    expect(&bytes, ElfError::SegmentOutsideUserSpace)
+}
+
+fn rejects_filesz_over_memsz() -> TestResult {
+    let bytes = image(&[Load { offset: 0, vaddr: BASE, filesz: 0x100, memsz: 0x80 }], 0x200);
+    expect(&bytes, ElfError::SegmentFileSizeTooLarge)
+}
+
+fn rejects_overlapping_segments() -> TestResult {
+    let bytes = image(
+        &[
+            Load { offset: 0, vaddr: BASE, filesz: 0x100, memsz: 0x1000 },
+            Load { offset: 0x100, vaddr: BASE + 0xFFF, filesz: 0x10, memsz: 0x10 },
+        ],
+        0x200,
+    );
+    expect(&bytes, ElfError::OverlappingSegments)
+}
+
+/// `image` with one more program header: a `PT_GNU_STACK` carrying `flags`.
+fn with_gnu_stack(loads: &[Load], flags: u32) -> Vec<u8> {
+    let mut bytes = image(loads, HEADER_LEN + (loads.len() + 1) * PHDR_LEN);
+    bytes[56..58].copy_from_slice(&((loads.len() + 1) as u16).to_le_bytes());
+    let phdr = &mut bytes[HEADER_LEN + loads.len() * PHDR_LEN..][..PHDR_LEN];
+    phdr[0..4].copy_from_slice(&PT_GNU_STACK.to_le_bytes());
+    phdr[4..8].copy_from_slice(&flags.to_le_bytes());
+    bytes
+}
+
+fn gnu_stack_flags() -> TestResult {
+    let load = [Load { offset: 0, vaddr: BASE, filesz: 0x100, memsz: 0x100 }];
+    let absent = elf::parse(&image(&load, 0x200)).map_err(|_| "image without PT_GNU_STACK rejected")?;
+    if absent.executable_stack {
+        return Err("stack should default to non-executable");
+    }
+    let nx = elf::parse(&with_gnu_stack(&load, 0x6)).map_err(|_| "PF_R|PF_W stack rejected")?;
+    if nx.executable_stack {
+        return Err("PT_GNU_STACK without PF_X marked the stack executable");
+    }
+    let exec = elf::parse(&with_gnu_stack(&load, 0x7)).map_err(|_| "PF_X stack rejected")?;
+    if !exec.executable_stack {
+        return Err("PT_GNU_STACK with PF_X was ignored");
+    }
+    if exec.segments.len() != 1 {
+        return Err("PT_GNU_STACK should not become a segment");
+    }
+    Ok(())
+}
+
+fn stack_page_flags(executable: bool) -> Result<u64, &'static str> {
+    let (address_space, stack) = process::create_user_address_space_with_stack(1, executable)
+        .map_err(|_| "address space creation failed")?;
+    let page = space::stack_top() - stack.size() as u64;
+    paging::page_flags(address_space.cr3(), page).ok_or("stack page not mapped")
+}
+
+fn nx_stack_mapping() -> TestResult {
+    let load = [Load { offset: 0, vaddr: BASE, filesz: 0x100, memsz: 0x100 }];
+    let parsed = elf::parse(&with_gnu_stack(&load, 0x6)).map_err(|_| "PF_R|PF_W stack rejected")?;
+    if stack_page_flags(parsed.executable_stack)? & FLAG_NO_EXECUTE == 0 {
+        return Err("NX stack request left the stack executable");
+    }
+    if stack_page_flags(true)? & FLAG_NO_EXECUTE != 0 {
+        return Err("executable stack was mapped no-execute");
+    }
+    Ok(())
+}
+
+fn rejects_shared_segment_page() -> TestResult {
+    // Disjoint as far as the parser cares, but both land in BASE's page.
+    let bytes = image(
+        &[
+            Load { offset: 0, vaddr: BASE, filesz: 0x100, memsz: 0x100 },
+            Load { offset: 0x100, vaddr: BASE + 0x800, filesz: 0x100, memsz: 0x100 },
+        ],
+        0x200,
+    );
+    let parsed = elf::parse(&bytes).map_err(|_| "disjoint segments rejected by the parser")?;
+    let (address_space, _) =
+        process::create_default_user_address_space(&parsed).map_err(|_| "address space creation failed")?;
+    match process::map_user_segments(&address_space, &parsed, &bytes) {
+        Err(process::ProcessError::InvalidElf) => Ok(()),
+        Err(_) => Err("shared page failed with the wrong error"),
+        Ok(()) => Err("two segments were mapped into one page"),
+    }
+}
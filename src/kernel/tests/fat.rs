@@ -1,16 +1,25 @@
 #![cfg(kernel_test)]
 
 use super::{TestCase, TestResult};
-use crate::tests::common::{mount_hello};
+use crate::fs::fat::{FatError, DEFAULT_VOLUME};
+use crate::tests::common::{hello_image, mount_hello, TestBlockDevice};
+use crate::vfs::mount;
+use crate::vfs::VfsFile;
 
 pub const TESTS: &[TestCase] = &[
     TestCase::new("fat.read_hello", read_hello),
     TestCase::new("fat.read_beyond_end", read_beyond_end),
+    TestCase::new("fat.reject_blank_bpb", reject_blank_bpb),
+    TestCase::new("fat.two_volumes", two_volumes),
+    TestCase::new("fat.remount_reuses_volume", remount_reuses_volume),
+    TestCase::new("fat.timestamps", timestamps),
+    TestCase::new("fat.cyclic_chain", cyclic_chain),
+    TestCase::new("fat.walk_tree", walk_tree),
 ];
 
 fn read_hello() -> TestResult {
     mount_hello()?;
-    let file = crate::fs::fat::open_file("HELLO.TXT").map_err(|_| "open HELLO failed")?;
+    let file = crate::fs::fat::open_file(DEFAULT_VOLUME, "HELLO.TXT").map_err(|_| "open HELLO failed")?;
     let mut buf = [0u8; 32];
     let count = file.read_at(0, &mut buf).map_err(|_| "read failed")?;
     if count == 0 {
@@ -25,7 +34,7 @@ fn read_hello() -> TestResult {
 
 fn read_beyond_end() -> TestResult {
     mount_hello()?;
-    let file = crate::fs::fat::open_file("HELLO.TXT").map_err(|_| "open HELLO failed")?;
+    let file = crate::fs::fat::open_file(DEFAULT_VOLUME, "HELLO.TXT").map_err(|_| "open HELLO failed")?;
     let mut buf = [0u8; 16];
     let count = file
         .read_at(1024, &mut buf)
@@ -35,3 +44,226 @@ fn read_beyond_end() -> TestResult {
     }
     Ok(())
 }
+
+static BLANK_DEVICE: TestBlockDevice<{ 512 * 4 }> = TestBlockDevice::new("test-blank", 512);
+
+fn reject_blank_bpb() -> TestResult {
+    mount_hello()?;
+    match crate::fs::fat::mount(DEFAULT_VOLUME, &BLANK_DEVICE, 0) {
+        Err(FatError::Io) => {}
+        Err(_) => return Err("blank volume failed with the wrong error"),
+        Ok(()) => return Err("blank volume mounted"),
+    }
+    // The volume already mounted under that name must still be the one in use.
+    crate::fs::fat::open_file(DEFAULT_VOLUME, "HELLO.TXT").map_err(|_| "previous volume lost")?;
+    Ok(())
+}
+
+const SECOND_VOLUME: &str = "second";
+static SECOND_DEVICE: TestBlockDevice<{ 512 * 10 }> = TestBlockDevice::new("test-fat2", 512);
+
+/// The hello image with its first file renamed to WORLD.TXT and holding
+/// "World", so reads show which volume answered.
+fn mount_second() -> TestResult {
+    let mut image = hello_image();
+    let root = 512 * 2;
+    image[root..root + 11].copy_from_slice(b"WORLD   TXT");
+    image[512 * 3..512 * 3 + 5].copy_from_slice(b"World");
+    SECOND_DEVICE.reset();
+    SECOND_DEVICE.load_image(&image).map_err(|_| "second image too large")?;
+    crate::fs::fat::mount(SECOND_VOLUME, &SECOND_DEVICE, 0).map_err(|_| "second mount failed")
+}
+
+fn remount_reuses_volume() -> TestResult {
+    mount_second()?;
+    let loaded = crate::fs::fat::loaded_volumes();
+    mount_second()?;
+    if crate::fs::fat::loaded_volumes() != loaded {
+        return Err("remounting the same volume leaked another copy");
+    }
+    let world = crate::fs::fat::open_file(SECOND_VOLUME, "WORLD.TXT").map_err(|_| "open WORLD failed")?;
+    if &read_five(world)? != b"World" {
+        return Err("remounted volume returned the wrong contents");
+    }
+    Ok(())
+}
+
+fn read_five(file: &'static dyn VfsFile) -> Result<[u8; 5], &'static str> {
+    let mut buf = [0u8; 5];
+    let count = file.read_at(0, &mut buf).map_err(|_| "read failed")?;
+    if count != buf.len() {
+        return Err("short read");
+    }
+    Ok(buf)
+}
+
+fn two_volumes() -> TestResult {
+    mount_hello()?;
+    mount_second()?;
+
+    let hello = crate::fs::fat::open_file(DEFAULT_VOLUME, "HELLO.TXT").map_err(|_| "open HELLO failed")?;
+    let world = crate::fs::fat::open_file(SECOND_VOLUME, "WORLD.TXT").map_err(|_| "open WORLD failed")?;
+    if &read_five(hello)? != b"Hello" || &read_five(world)? != b"World" {
+        return Err("volumes returned the wrong contents");
+    }
+    if crate::fs::fat::open_file(DEFAULT_VOLUME, "WORLD.TXT").is_ok() {
+        return Err("second volume leaked into the first");
+    }
+
+    // Through the mount table: a leading volume name picks that volume,
+    // anything else goes to the boot volume.
+    let (fs, rest) = mount::lookup("/fat/second/WORLD.TXT").ok_or("/fat not mounted")?;
+    if &read_five(fs.open(rest).map_err(|_| "open /fat/second/WORLD.TXT failed")?)? != b"World" {
+        return Err("/fat/second did not reach the second volume");
+    }
+    let (fs, rest) = mount::lookup("/fat/HELLO.TXT").ok_or("/fat not mounted")?;
+    if &read_five(fs.open(rest).map_err(|_| "open /fat/HELLO.TXT failed")?)? != b"Hello" {
+        return Err("/fat/HELLO.TXT did not reach the boot volume");
+    }
+
+    // Remounting the name keeps already open files on the old volume.
+    mount_second()?;
+    if &read_five(world)? != b"World" {
+        return Err("open file lost its volume on remount");
+    }
+    Ok(())
+}
+
+fn timestamps() -> TestResult {
+    mount_hello()?;
+    let file = crate::fs::fat::open_file(DEFAULT_VOLUME, "HELLO.TXT").map_err(|_| "open HELLO failed")?;
+    let stat = file.stat().map_err(|_| "stat HELLO failed")?;
+    // 2024-02-29 08:00:00 and 2024-03-01 12:30:14 UTC.
+    if stat.created != 1_709_193_600 || stat.modified != 1_709_296_214 {
+        return Err("HELLO.TXT timestamps not decoded");
+    }
+
+    let docs = crate::fs::fat::open_file(DEFAULT_VOLUME, "DOCS").map_err(|_| "open DOCS failed")?;
+    let stat = docs.stat().map_err(|_| "stat DOCS failed")?;
+    if stat.created != 0 || stat.modified != 0 {
+        return Err("an unset stamp should read as 0");
+    }
+    Ok(())
+}
+
+static CYCLIC_DEVICE: TestBlockDevice<{ 512 * 10 }> = TestBlockDevice::new("test-fat-cyclic", 512);
+
+/// HELLO.TXT's cluster links back to itself while its size claims 4 KiB,
+/// more than the seven data clusters of a 10-sector volume.
+fn cyclic_chain() -> TestResult {
+    let mut image = hello_image();
+    image[19..21].copy_from_slice(&10u16.to_le_bytes());
+    image[512 + 4..512 + 6].copy_from_slice(&2u16.to_le_bytes());
+    let size = 512 * 2 + 28;
+    image[size..size + 4].copy_from_slice(&4096u32.to_le_bytes());
+    CYCLIC_DEVICE.reset();
+    CYCLIC_DEVICE.load_image(&image).map_err(|_| "cyclic image too large")?;
+    crate::fs::fat::mount("cyclic", &CYCLIC_DEVICE, 0).map_err(|_| "cyclic mount failed")?;
+
+    let file = crate::fs::fat::open_file("cyclic", "HELLO.TXT").map_err(|_| "open HELLO failed")?;
+    if &read_five(file)? != b"Hello" {
+        return Err("first cluster unreadable");
+    }
+    let mut buf = [0u8; 4096];
+    if file.read_at(0, &mut buf).is_ok() {
+        return Err("read followed a looping chain");
+    }
+    Ok(())
+}
+
+static TREE_DEVICE: TestBlockDevice<{ 512 * 10 }> = TestBlockDevice::new("test-fat-tree", 512);
+
+fn put_entry(slot: &mut [u8], name: &[u8; 11], attr: u8, cluster: u16, size: u32) {
+    slot[0..11].copy_from_slice(name);
+    slot[11] = attr;
+    slot[26..28].copy_from_slice(&cluster.to_le_bytes());
+    slot[28..32].copy_from_slice(&size.to_le_bytes());
+}
+
+/// The hello image with two more levels under DOCS: DOCS/NOTES (cluster 6)
+/// holds TODO.TXT and DEEP (cluster 7), which holds LEAF.TXT and LOOP, a
+/// directory entry pointing back at DOCS. Files share data clusters; the
+/// walk never reads them.
+fn mount_tree() -> TestResult {
+    let mut image = hello_image();
+    for cluster in [6usize, 7] {
+        image[512 + cluster * 2..512 + cluster * 2 + 2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+    }
+    let docs = 512 * 4;
+    put_entry(&mut image[docs + 96..docs + 128], b"NOTES      ", 0x10, 6, 0);
+
+    let notes = 512 * 7;
+    put_entry(&mut image[notes..notes + 32], b".          ", 0x10, 6, 0);
+    put_entry(&mut image[notes + 32..notes + 64], b"..         ", 0x10, 3, 0);
+    put_entry(&mut image[notes + 64..notes + 96], b"TODO    TXT", 0x20, 4, 6);
+    put_entry(&mut image[notes + 96..notes + 128], b"DEEP       ", 0x10, 7, 0);
+
+    let deep = 512 * 8;
+    put_entry(&mut image[deep..deep + 32], b".          ", 0x10, 7, 0);
+    put_entry(&mut image[deep + 32..deep + 64], b"..         ", 0x10, 6, 0);
+    put_entry(&mut image[deep + 64..deep + 96], b"LEAF    TXT", 0x20, 4, 6);
+    put_entry(&mut image[deep + 96..deep + 128], b"LOOP       ", 0x10, 3, 0);
+
+    TREE_DEVICE.reset();
+    TREE_DEVICE.load_image(&image).map_err(|_| "tree image too large")?;
+    crate::fs::fat::mount("tree", &TREE_DEVICE, 0).map_err(|_| "tree mount failed")
+}
+
+const TREE_PATHS: [&str; 9] = [
+    "/HELLO.TXT",
+    "/DOCS",
+    "/EXIT7",
+    "/DOCS/README.TXT",
+    "/DOCS/NOTES",
+    "/DOCS/NOTES/TODO.TXT",
+    "/DOCS/NOTES/DEEP",
+    "/DOCS/NOTES/DEEP/LEAF.TXT",
+    "/DOCS/NOTES/DEEP/LOOP",
+];
+
+fn walk_tree() -> TestResult {
+    mount_tree()?;
+
+    let mut seen = [0usize; TREE_PATHS.len()];
+    let mut unexpected = false;
+    let visited = crate::fs::fat::walk("tree", "/", |path, entry| {
+        match TREE_PATHS.iter().position(|expected| *expected == path) {
+            Some(index) => seen[index] += 1,
+            None => unexpected = true,
+        }
+        // Paths end in the entry's own name.
+        unexpected |= !path.as_bytes().ends_with(entry.name());
+        true
+    })
+    .map_err(|_| "walk failed")?;
+    if unexpected {
+        return Err("walk produced a path that is not in the tree");
+    }
+    if visited != TREE_PATHS.len() || seen.iter().any(|count| *count != 1) {
+        return Err("every entry should be visited exactly once");
+    }
+
+    // Starting lower down keeps the path from the volume root.
+    let mut paths = 0;
+    let mut prefixed = true;
+    crate::fs::fat::walk("tree", "DOCS/NOTES", |path, _| {
+        paths += 1;
+        prefixed &= path.starts_with("/DOCS/NOTES/");
+        true
+    })
+    .map_err(|_| "subtree walk failed")?;
+    if paths != 4 || !prefixed {
+        return Err("subtree walk should see four entries under /DOCS/NOTES");
+    }
+
+    // The visitor can stop the walk.
+    let stopped = crate::fs::fat::walk("tree", "", |_, _| false).map_err(|_| "stopped walk failed")?;
+    if stopped != 1 {
+        return Err("returning false should stop after one entry");
+    }
+
+    match crate::fs::fat::walk("tree", "HELLO.TXT", |_, _| true) {
+        Err(FatError::InvalidPath) => Ok(()),
+        _ => Err("walking a file should fail"),
+    }
+}
@@ -0,0 +1,124 @@
+#![cfg(kernel_test)]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::{TestCase, TestResult};
+use crate::interrupts::{self, vectors, InterruptFrame};
+
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("interrupts.breakpoint_counted", breakpoint_counted),
+    TestCase::new("interrupts.handler_restore", handler_restore),
+    TestCase::new("interrupts.retry_waits_for_next_drain", retry_waits_for_next_drain),
+];
+
+fn ignore_breakpoint(_frame: &mut InterruptFrame) {}
+
+fn breakpoint() {
+    unsafe {
+        core::arch::asm!("int3", options(nomem, nostack));
+    }
+}
+
+fn breakpoint_counted() -> TestResult {
+    let previous = interrupts::register_handler(vectors::BREAKPOINT, ignore_breakpoint);
+
+    let before = interrupts::stats();
+    for _ in 0..3 {
+        breakpoint();
+    }
+    let after = interrupts::stats();
+
+    if after.count(vectors::BREAKPOINT) != before.count(vectors::BREAKPOINT) + 3 {
+        return Err("breakpoint count should rise by three");
+    }
+    if after.count(vectors::PAGE_FAULT) != before.count(vectors::PAGE_FAULT) {
+        return Err("unrelated vector counted");
+    }
+    if !after.fired().any(|(vector, _)| vector == vectors::BREAKPOINT) {
+        return Err("breakpoint missing from fired vectors");
+    }
+    interrupts::register_handler(vectors::BREAKPOINT, previous);
+    Ok(())
+}
+
+static OUTER_HITS: AtomicU32 = AtomicU32::new(0);
+static INNER_HITS: AtomicU32 = AtomicU32::new(0);
+
+fn outer_breakpoint(_frame: &mut InterruptFrame) {
+    OUTER_HITS.fetch_add(1, Ordering::SeqCst);
+}
+
+fn inner_breakpoint(_frame: &mut InterruptFrame) {
+    INNER_HITS.fetch_add(1, Ordering::SeqCst);
+}
+
+fn hits() -> (u32, u32) {
+    (OUTER_HITS.load(Ordering::SeqCst), INNER_HITS.load(Ordering::SeqCst))
+}
+
+fn handler_restore() -> TestResult {
+    OUTER_HITS.store(0, Ordering::SeqCst);
+    INNER_HITS.store(0, Ordering::SeqCst);
+
+    let original = interrupts::register_handler(vectors::BREAKPOINT, outer_breakpoint);
+    breakpoint();
+    if hits() != (1, 0) {
+        return Err("outer handler should run first");
+    }
+
+    let saved = interrupts::register_handler(vectors::BREAKPOINT, inner_breakpoint);
+    breakpoint();
+    if hits() != (1, 1) {
+        return Err("inner handler should replace the outer one");
+    }
+
+    // Reinstalling what register_handler returned brings the outer back.
+    interrupts::register_handler(vectors::BREAKPOINT, saved);
+    breakpoint();
+    if hits() != (2, 1) {
+        return Err("restored handler should be the outer one");
+    }
+
+    let removed = interrupts::unregister_handler(vectors::BREAKPOINT);
+    interrupts::register_handler(vectors::BREAKPOINT, removed);
+    breakpoint();
+    if hits() != (3, 1) {
+        return Err("unregister should hand back the outer handler");
+    }
+
+    interrupts::register_handler(vectors::BREAKPOINT, original);
+    Ok(())
+}
+
+static RETRY_RUNS: AtomicU32 = AtomicU32::new(0);
+
+/// Fails the first time, like a wake that finds the process table held.
+fn retry_once(_: usize) {
+    if RETRY_RUNS.fetch_add(1, Ordering::SeqCst) == 0 {
+        interrupts::defer_retry(retry_once, 0);
+    }
+}
+
+fn retry_waits_for_next_drain() -> TestResult {
+    interrupts::run_deferred();
+    RETRY_RUNS.store(0, Ordering::SeqCst);
+
+    if !interrupts::defer(retry_once, 0) {
+        return Err("defer on an empty queue failed");
+    }
+    let first = interrupts::run_deferred();
+    let runs = RETRY_RUNS.load(Ordering::SeqCst);
+    let pending = interrupts::deferred_pending();
+    let second = interrupts::run_deferred();
+
+    if first != 1 || runs != 1 {
+        return Err("a retried item ran again in the same drain");
+    }
+    if pending != 1 {
+        return Err("the retried item should be pending between drains");
+    }
+    if second != 1 || interrupts::deferred_pending() != 0 {
+        return Err("the next drain should run the retried item");
+    }
+    Ok(())
+}
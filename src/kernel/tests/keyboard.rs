@@ -0,0 +1,185 @@
+#![cfg(kernel_test)]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::common::{retire, with_leader};
+use super::{TestCase, TestResult};
+use crate::arch::x86_64::drivers::keyboard as arch;
+use crate::drivers::keyboard;
+use crate::interrupts;
+use crate::process::{self, Pid, ProcessState};
+
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("keyboard.try_read_empty", try_read_empty),
+    TestCase::new("keyboard.blocking_read_wakes", blocking_read_wakes),
+    TestCase::new("keyboard.overflow_drops_oldest", overflow_drops_oldest),
+    TestCase::new("keyboard.decode_is_deferred", decode_is_deferred),
+    TestCase::new("keyboard.full_queue_drops_scancode", full_queue_drops_scancode),
+];
+
+// Set 1 make/break codes for 'a'.
+const SCANCODE_A: u8 = 0x1E;
+const SCANCODE_A_RELEASE: u8 = 0x9E;
+
+fn drain() {
+    let mut byte = [0u8; 1];
+    while keyboard::try_read(&mut byte) > 0 {}
+}
+
+fn try_read_empty() -> TestResult {
+    drain();
+    let mut buf = [0u8; 4];
+    let count = keyboard::try_read(&mut buf);
+    if count != 0 {
+        return Err("try_read on an empty queue should return 0");
+    }
+    Ok(())
+}
+
+/// Byte the reader task received, or `NOT_READ`.
+static RECEIVED: AtomicU32 = AtomicU32::new(NOT_READ);
+const NOT_READ: u32 = u32::MAX;
+
+extern "C" fn reader_task() -> ! {
+    let mut byte = [0u8; 1];
+    let value = match keyboard::driver().read(&mut byte) {
+        Ok(1) => byte[0] as u32,
+        _ => NOT_READ - 1,
+    };
+    RECEIVED.store(value, Ordering::SeqCst);
+    process::exit_current(0)
+}
+
+fn state_of(pid: Pid) -> Option<ProcessState> {
+    process::get_process(pid).map(|snapshot| snapshot.state())
+}
+
+fn blocking_read_wakes() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    drain();
+    RECEIVED.store(NOT_READ, Ordering::SeqCst);
+
+    with_leader("kbd_leader", |_| {
+        let reader = process::spawn_kernel_process("kbd_reader", reader_task).map_err(|_| "spawn reader failed")?;
+        for _ in 0..8 {
+            if state_of(reader) == Some(ProcessState::Blocked) {
+                break;
+            }
+            process::yield_now();
+        }
+        if state_of(reader) != Some(ProcessState::Blocked) || RECEIVED.load(Ordering::SeqCst) != NOT_READ {
+            retire(&[reader]);
+            return Err("reader should sleep while the queue is empty");
+        }
+
+        arch::inject_scancode(SCANCODE_A);
+        arch::inject_scancode(SCANCODE_A_RELEASE);
+        if state_of(reader) != Some(ProcessState::Ready) {
+            retire(&[reader]);
+            return Err("keystroke did not wake the reader");
+        }
+        for _ in 0..8 {
+            if state_of(reader) == Some(ProcessState::Zombie) {
+                break;
+            }
+            process::yield_now();
+        }
+        retire(&[reader]);
+        if RECEIVED.load(Ordering::SeqCst) != b'a' as u32 {
+            return Err("reader did not receive the injected key");
+        }
+        Ok(())
+    })
+}
+
+// Set 1 make codes for '1' through '0'.
+const DIGIT_SCANCODES: [u8; 10] = [0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B];
+const DIGITS: &[u8; 10] = b"1234567890";
+
+fn overflow_drops_oldest() -> TestResult {
+    drain();
+    let before = keyboard::stats().dropped;
+
+    // Repeated make codes read as typematic repeat, one byte each.
+    let pushed = arch::BUFFER_SIZE + 10;
+    for i in 0..pushed {
+        arch::inject_scancode(DIGIT_SCANCODES[i % 10]);
+    }
+    let stats = keyboard::stats();
+
+    let mut oldest_kept = true;
+    let mut byte = [0u8; 1];
+    for i in (pushed - arch::BUFFER_SIZE)..pushed {
+        if keyboard::try_read(&mut byte) != 1 || byte[0] != DIGITS[i % 10] {
+            oldest_kept = false;
+            break;
+        }
+    }
+    drain();
+
+    if stats.queued != arch::BUFFER_SIZE {
+        return Err("a full ring should hold BUFFER_SIZE bytes");
+    }
+    if stats.dropped - before != 10 {
+        return Err("each overflowing byte should count one drop");
+    }
+    if !oldest_kept {
+        return Err("the ring should keep the newest bytes in order");
+    }
+    Ok(())
+}
+
+fn decode_is_deferred() -> TestResult {
+    drain();
+    interrupts::run_deferred();
+
+    arch::inject_scancode_deferred(SCANCODE_A);
+    let pending = interrupts::deferred_pending();
+    let early = keyboard::stats().queued;
+    let ran = interrupts::run_deferred();
+    let mut byte = [0u8; 1];
+    let count = keyboard::try_read(&mut byte);
+    arch::inject_scancode(SCANCODE_A_RELEASE);
+    drain();
+
+    if pending != 1 || early != 0 {
+        return Err("the IRQ half should only queue the decode");
+    }
+    if ran != 1 || interrupts::deferred_pending() != 0 {
+        return Err("draining should run the one queued item");
+    }
+    if count != 1 || byte[0] != b'a' {
+        return Err("the drained work did not deliver the byte");
+    }
+    Ok(())
+}
+
+fn full_queue_drops_scancode() -> TestResult {
+    drain();
+    interrupts::run_deferred();
+
+    // Each press decodes to a byte; the release that would overflow the
+    // queue must not be decoded ahead of them.
+    for _ in 0..interrupts::MAX_DEFERRED {
+        arch::inject_scancode_deferred(SCANCODE_A);
+    }
+    let before = keyboard::stats().dropped;
+    arch::inject_scancode_deferred(SCANCODE_A_RELEASE);
+    let dropped = keyboard::stats().dropped - before;
+    let early = keyboard::stats().queued;
+    interrupts::run_deferred();
+    let queued = keyboard::stats().queued;
+    arch::inject_scancode(SCANCODE_A_RELEASE);
+    drain();
+
+    if dropped != 1 {
+        return Err("a scancode past a full queue should count one drop");
+    }
+    if early != 0 {
+        return Err("the overflowing scancode was decoded inline");
+    }
+    if queued != interrupts::MAX_DEFERRED {
+        return Err("every queued press should decode in order");
+    }
+    Ok(())
+}
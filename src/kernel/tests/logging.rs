@@ -0,0 +1,53 @@
+#![cfg(kernel_test)]
+
+use super::{TestCase, TestResult};
+use crate::klog::{self, capture};
+use crate::timer;
+
+pub const TESTS: &[TestCase] = &[TestCase::new("klog.timestamps_monotonic", timestamps_monotonic)];
+
+/// Split `[ms] text` into the timestamp and the text.
+fn parse_line(line: &[u8]) -> Option<(u64, &[u8])> {
+    if line.first() != Some(&b'[') {
+        return None;
+    }
+    let close = line.iter().position(|&b| b == b']')?;
+    let digits = &line[1..close];
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let ms = digits.iter().fold(0u64, |acc, &d| acc * 10 + (d - b'0') as u64);
+    line.get(close + 2..).map(|rest| (ms, rest))
+}
+
+fn timestamps_monotonic() -> TestResult {
+    // Writes must not start mid-line, or the first prefix would be skipped.
+    crate::klog!("\n");
+    klog::set_timestamps(true);
+    capture::start();
+    crate::klog!("first line\n");
+    timer::advance_ticks(3);
+    crate::klog!("second ");
+    crate::klog!("line\n");
+    let mut buf = [0u8; 128];
+    let len = capture::finish(&mut buf);
+    klog::set_timestamps(false);
+
+    let mut lines = buf[..len].split(|&b| b == b'\n').filter(|line| !line.is_empty());
+    let (first_ms, first) = lines.next().and_then(parse_line).ok_or("first line missing its timestamp")?;
+    let (second_ms, second) = lines.next().and_then(parse_line).ok_or("second line missing its timestamp")?;
+    if first != b"first line" {
+        return Err("first line text mangled");
+    }
+    // Two writes, one line: the prefix only appears at the real line start.
+    if second != b"second line" {
+        return Err("timestamp inserted mid-line");
+    }
+    if second_ms <= first_ms {
+        return Err("timestamps should increase with the tick count");
+    }
+    if lines.next().is_some() {
+        return Err("unexpected extra output");
+    }
+    Ok(())
+}
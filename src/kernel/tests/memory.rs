@@ -1,9 +1,36 @@
 #![cfg(kernel_test)]
 
+use alloc::vec;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+
 use super::{TestCase, TestResult};
+use crate::arch::x86_64::kernel::cpu;
+use crate::arch::x86_64::kernel::mmu;
+use crate::arch::x86_64::kernel::paging::{
+    self, MapError, MappedRange, FLAG_COW, FLAG_NO_EXECUTE, FLAG_USER, FLAG_WRITABLE,
+};
+use crate::mem::free_list::LinkedListAllocator;
 use crate::mem::heap::{self, HeapBox};
+use crate::mem::phys::{self, Frame, MemoryRegion, ScratchFrames, FRAME_SIZE};
+use crate::process::{self, AddressSpace, AddressSpaceKind};
+use crate::user::elf::{ElfImage, ElfSegment};
+use crate::klog;
 
-pub const TESTS: &[TestCase] = &[TestCase::new("memory.heap_allocation", heap_allocation)];
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("memory.heap_allocation", heap_allocation),
+    TestCase::new("memory.contiguous_frames", contiguous_frames),
+    TestCase::new("memory.contiguous_across_gap", contiguous_across_gap),
+    TestCase::new("memory.contiguous_below_limit", contiguous_below_limit),
+    TestCase::new("memory.frame_stats_roundtrip", frame_stats_roundtrip),
+    TestCase::new("memory.cow_shared_until_write", cow_shared_until_write),
+    TestCase::new("memory.bss_maps_zero_page", bss_maps_zero_page),
+    TestCase::new("memory.unmap_flushes_tlb", unmap_flushes_tlb),
+    TestCase::new("memory.huge_page_translate", huge_page_translate),
+    TestCase::new("memory.walk_mappings", walk_mappings),
+    TestCase::new("memory.zero_page_clears_frame", zero_page_clears_frame),
+    TestCase::new("memory.carved_heap_fills_its_range", carved_heap_fills_its_range),
+];
 
 fn heap_allocation() -> TestResult {
     let before = heap::remaining_bytes();
@@ -22,3 +49,483 @@ fn heap_allocation() -> TestResult {
     }
     Ok(())
 }
+
+// Synthetic regions well above the reserved floor; nothing here is touched.
+const REGION_A: u64 = 0x4000_0000;
+const REGION_B: u64 = 0x5000_0000;
+
+fn contiguous_frames() -> TestResult {
+    let mut frames = ScratchFrames::new(&[MemoryRegion {
+        base: REGION_A,
+        length: 8 * FRAME_SIZE,
+    }]);
+    let range = frames.allocate_frames(3).ok_or("contiguous allocation failed")?;
+    if range.count() != 3 || range.iter().count() != 3 {
+        return Err("range did not cover every requested frame");
+    }
+    for (index, frame) in range.iter().enumerate() {
+        if frame.start() != REGION_A + index as u64 * FRAME_SIZE {
+            return Err("frames not in ascending contiguous order");
+        }
+    }
+    let next = frames.allocate_frame().ok_or("single frame after range failed")?;
+    if next.start() != REGION_A + 3 * FRAME_SIZE {
+        return Err("allocation order not deterministic");
+    }
+    Ok(())
+}
+
+fn contiguous_across_gap() -> TestResult {
+    // Two four-frame regions with a hole between them: five frames would
+    // have to straddle the gap, so the request must fail outright.
+    let mut frames = ScratchFrames::new(&[
+        MemoryRegion {
+            base: REGION_A,
+            length: 4 * FRAME_SIZE,
+        },
+        MemoryRegion {
+            base: REGION_B,
+            length: 4 * FRAME_SIZE,
+        },
+    ]);
+    if frames.allocate_frames(5).is_some() {
+        return Err("contiguous request spanning a gap succeeded");
+    }
+    let first = frames.allocate_frame().ok_or("failed request consumed frames")?;
+    if first.start() != REGION_A {
+        return Err("failed request moved the allocator");
+    }
+    let rest = frames.allocate_frames(3).ok_or("remaining frames unavailable")?;
+    if rest.start().start() != REGION_A + FRAME_SIZE {
+        return Err("range did not use the rest of the first region");
+    }
+    let second = frames.allocate_frames(4).ok_or("second region unavailable")?;
+    if second.start().start() != REGION_B {
+        return Err("range did not move on to the next region");
+    }
+    Ok(())
+}
+
+fn contiguous_below_limit() -> TestResult {
+    // The region runs past the limit: a run that would cross it is refused
+    // without taking anything, and one that ends on it is fine.
+    let mut frames = ScratchFrames::new(&[MemoryRegion {
+        base: REGION_A,
+        length: 8 * FRAME_SIZE,
+    }]);
+    let limit = REGION_A + 4 * FRAME_SIZE;
+    if frames.allocate_frames_below(5, limit).is_some() {
+        return Err("run crossing the limit was handed out");
+    }
+    let range = frames.allocate_frames_below(4, limit).ok_or("run ending at the limit refused")?;
+    if range.start().start() != REGION_A {
+        return Err("refused request moved the allocator");
+    }
+    if frames.allocate_frames_below(1, limit).is_some() {
+        return Err("frame past the limit was handed out");
+    }
+    Ok(())
+}
+
+const STAT_BATCH: usize = 8;
+
+fn frame_stats_roundtrip() -> TestResult {
+    let before = phys::summary();
+    let mut frames = Vec::new();
+    for _ in 0..STAT_BATCH {
+        frames.push(phys::allocate_frame().ok_or("frame allocation failed")?);
+    }
+
+    let during = phys::summary();
+    if during.used_frames != before.used_frames + STAT_BATCH {
+        return Err("used_frames did not grow by the batch");
+    }
+    if during.free_frames + STAT_BATCH != before.free_frames {
+        return Err("free_frames did not shrink by the batch");
+    }
+    if during.peak_used < during.used_frames {
+        return Err("peak below current use");
+    }
+
+    for frame in frames.iter() {
+        phys::free_frame(*frame);
+    }
+    let after = phys::summary();
+    if after.used_frames != before.used_frames || after.free_frames != before.free_frames {
+        return Err("counts did not return after freeing the batch");
+    }
+    if after.peak_used != during.peak_used {
+        return Err("peak should not fall when frames are freed");
+    }
+
+    // Freed frames are handed out again before fresh ones.
+    let again = phys::allocate_frame().ok_or("reallocation failed")?;
+    if !frames.contains(&again) {
+        return Err("freed frame was not reused");
+    }
+    phys::free_frame(again);
+    Ok(())
+}
+
+const COW_VIRT: u64 = 0x0040_0000;
+
+fn read_byte(space: &AddressSpace) -> Result<u8, &'static str> {
+    let mut byte = [0u8; 1];
+    process::copy_from_user(space, &mut byte, COW_VIRT).map_err(|_| "read from user page failed")?;
+    Ok(byte[0])
+}
+
+fn cow_shared_until_write() -> TestResult {
+    let parent_pml4 = paging::clone_kernel_pml4().map_err(|_| "parent pml4 allocation failed")?;
+    let child_pml4 = paging::clone_kernel_pml4().map_err(|_| "child pml4 allocation failed")?;
+    let parent = AddressSpace::with_cr3(parent_pml4, AddressSpaceKind::User);
+    let child = AddressSpace::with_cr3(child_pml4, AddressSpaceKind::User);
+
+    let frame = phys::allocate_frame().ok_or("frame allocation failed")?;
+    paging::map_page(parent_pml4, COW_VIRT, frame.start(), FLAG_WRITABLE | FLAG_USER)
+        .map_err(|_| "map parent page failed")?;
+    process::copy_to_user(&parent, COW_VIRT, &[0xAA]).map_err(|_| "seed write failed")?;
+
+    paging::share_cow(parent_pml4, child_pml4, COW_VIRT).map_err(|_| "share_cow failed")?;
+    if paging::translate(child_pml4, COW_VIRT) != Some(frame.start()) {
+        return Err("child does not map the parent's frame");
+    }
+    if phys::frame_refcount(frame) != 2 {
+        return Err("shared frame refcount is not 2");
+    }
+    for pml4 in [parent_pml4, child_pml4].iter() {
+        let flags = paging::page_flags(*pml4, COW_VIRT).ok_or("shared page missing")?;
+        if flags & FLAG_WRITABLE != 0 || flags & FLAG_COW == 0 {
+            return Err("shared page not read-only copy-on-write");
+        }
+    }
+
+    process::copy_to_user(&child, COW_VIRT, &[0x55]).map_err(|_| "child write failed")?;
+    let child_frame = paging::translate(child_pml4, COW_VIRT).ok_or("child page vanished")?;
+    if child_frame == frame.start() {
+        return Err("child write did not take a private copy");
+    }
+    if read_byte(&parent)? != 0xAA || read_byte(&child)? != 0x55 {
+        return Err("parent and child did not diverge");
+    }
+    if phys::frame_refcount(Frame::containing(child_frame)) != 1 || phys::frame_refcount(frame) != 1 {
+        return Err("refcounts not back to single owners");
+    }
+
+    // The parent is now the sole owner and keeps its frame.
+    process::copy_to_user(&parent, COW_VIRT, &[0x11]).map_err(|_| "parent write failed")?;
+    if paging::translate(parent_pml4, COW_VIRT) != Some(frame.start()) {
+        return Err("sole owner was copied instead of made writable");
+    }
+    let flags = paging::page_flags(parent_pml4, COW_VIRT).ok_or("parent page missing")?;
+    if flags & FLAG_WRITABLE == 0 || flags & FLAG_COW != 0 {
+        return Err("sole owner did not regain write access");
+    }
+    if read_byte(&child)? != 0x55 {
+        return Err("parent write leaked into the child");
+    }
+    Ok(())
+}
+
+const BSS_VIRT: u64 = 0x0060_0000;
+const BSS_PAGES: u64 = 64;
+/// The page holding file bytes plus a PDPT, PD and PT for the segment, which
+/// sits inside one 2 MiB slot.
+const BSS_FRAMES_MAX: usize = 4;
+
+/// Unmap `pages` pages from `virt` and drop this space's reference to each.
+fn release_pages(pml4: u64, virt: u64, pages: u64) {
+    for page in 0..pages {
+        let addr = virt + page * FRAME_SIZE;
+        if let Some(phys) = paging::translate(pml4, addr) {
+            paging::unmap_page(pml4, addr);
+            phys::release_frame(Frame::containing(phys));
+        }
+    }
+    paging::flush_address_space(pml4);
+}
+
+fn bss_maps_zero_page() -> TestResult {
+    let zero_frame = paging::zero_frame().ok_or("no zero frame")?;
+    let zero = zero_frame.start();
+    let zero_refs = phys::frame_refcount(zero_frame);
+    let pml4 = paging::clone_kernel_pml4().map_err(|_| "pml4 allocation failed")?;
+    let space = AddressSpace::with_cr3(pml4, AddressSpaceKind::User);
+    let before = phys::summary();
+    let data = [0x5Au8; 16];
+    let image = ElfImage {
+        entry: BSS_VIRT,
+        segments: vec![ElfSegment {
+            vaddr: BSS_VIRT,
+            filesz: data.len() as u64,
+            memsz: BSS_PAGES * FRAME_SIZE,
+            offset: 0,
+            flags: 0x6, // PF_R | PF_W
+            align: FRAME_SIZE,
+        }],
+        executable_stack: false,
+    };
+    process::map_user_segments(&space, &image, &data).map_err(|_| "map_user_segments failed")?;
+    let mapped = phys::summary();
+    if mapped.used_frames - before.used_frames > BSS_FRAMES_MAX {
+        return Err("bss pages took frames of their own");
+    }
+
+    let first = paging::translate(pml4, BSS_VIRT).ok_or("file page missing")?;
+    if first == zero {
+        return Err("page with file bytes should have its own frame");
+    }
+    let mut distinct = Vec::new();
+    for page in 1..BSS_PAGES {
+        let virt = BSS_VIRT + page * FRAME_SIZE;
+        let phys = paging::translate(pml4, virt).ok_or("bss page missing")?;
+        if !distinct.contains(&phys) {
+            distinct.push(phys);
+        }
+        let flags = paging::page_flags(pml4, virt).ok_or("bss page missing")?;
+        if flags & FLAG_WRITABLE != 0 || flags & FLAG_COW == 0 {
+            return Err("bss page should be read-only copy-on-write");
+        }
+    }
+    if distinct.len() != 1 || distinct[0] != zero {
+        return Err("bss pages should all share the zero frame");
+    }
+
+    let target = BSS_VIRT + 10 * FRAME_SIZE;
+    process::copy_to_user(&space, target + 8, &[0x77]).map_err(|_| "bss write failed")?;
+    let private = paging::translate(pml4, target).ok_or("written page missing")?;
+    if private == zero {
+        return Err("write did not take a private frame");
+    }
+    let mut page = [0xFFu8; 16];
+    process::copy_from_user(&space, &mut page, target).map_err(|_| "bss read failed")?;
+    if page[8] != 0x77 || page.iter().enumerate().any(|(i, b)| i != 8 && *b != 0) {
+        return Err("private copy is not zero apart from the write");
+    }
+    let untouched = BSS_VIRT + 11 * FRAME_SIZE;
+    if paging::translate(pml4, untouched) != Some(zero) {
+        return Err("neighbouring bss page lost the zero frame");
+    }
+    let mut word = [0xFFu8; 8];
+    process::copy_from_user(&space, &mut word, untouched + 8).map_err(|_| "zero read failed")?;
+    if word != [0u8; 8] {
+        return Err("zero frame was dirtied");
+    }
+
+    // Page tables are not reclaimed; every data frame, including the private
+    // copy, should be.
+    release_pages(pml4, BSS_VIRT, BSS_PAGES);
+    let after = phys::summary();
+    if after.used_frames + 1 != mapped.used_frames {
+        return Err("releasing the segment did not return its frames");
+    }
+    if phys::frame_refcount(zero_frame) != zero_refs {
+        return Err("zero frame references were not dropped");
+    }
+    Ok(())
+}
+
+// Lower-half slot in the kernel's own tables that nothing else maps.
+const SCRATCH_VIRT: u64 = 0x0000_5000_0000_0000;
+
+fn unmap_flushes_tlb() -> TestResult {
+    let cr3 = AddressSpace::kernel().cr3();
+    let first = phys::allocate_frame().ok_or("first frame allocation failed")?;
+    let second = phys::allocate_frame().ok_or("second frame allocation failed")?;
+    let page = SCRATCH_VIRT as *mut u64;
+
+    paging::map_page(cr3, SCRATCH_VIRT, first.start(), FLAG_WRITABLE).map_err(|_| "map first frame failed")?;
+    unsafe { page.write_volatile(0x1111) };
+
+    paging::unmap_page(cr3, SCRATCH_VIRT);
+    if paging::translate(cr3, SCRATCH_VIRT).is_some() {
+        return Err("translate still resolves after unmap");
+    }
+
+    // Remap onto a different frame. A stale TLB entry would keep reading
+    // the first frame through the same address.
+    paging::map_page(cr3, SCRATCH_VIRT, second.start(), FLAG_WRITABLE).map_err(|_| "map second frame failed")?;
+    unsafe { page.write_volatile(0x2222) };
+    let seen_first = unsafe { (mmu::phys_to_virt(first.start()) as *const u64).read_volatile() };
+    paging::unmap_page(cr3, SCRATCH_VIRT);
+
+    if seen_first != 0x1111 {
+        return Err("write after remap landed in the unmapped frame");
+    }
+    if paging::translate(cr3, SCRATCH_VIRT).is_some() {
+        return Err("mapping survived the second unmap");
+    }
+    Ok(())
+}
+
+fn huge_page_translate() -> TestResult {
+    // The space is never loaded, so the frame need not be ours.
+    const HUGE_VIRT: u64 = 0x0000_0000_4000_0000;
+    const HUGE_FRAME: u64 = 0x0000_0000_0060_0000;
+
+    let pml4 = paging::clone_kernel_pml4().map_err(|_| "pml4 allocation failed")?;
+    if paging::map_huge_page(pml4, HUGE_VIRT + 0x1000, HUGE_FRAME, FLAG_WRITABLE)
+        != Err(MapError::Misaligned)
+    {
+        return Err("misaligned huge page accepted");
+    }
+    paging::map_huge_page(pml4, HUGE_VIRT, HUGE_FRAME, FLAG_WRITABLE | FLAG_USER)
+        .map_err(|_| "map_huge_page failed")?;
+
+    let last = paging::HUGE_PAGE_SIZE as u64 - 1;
+    for offset in [0u64, 0x1_2345, last].iter() {
+        if paging::translate(pml4, HUGE_VIRT + offset) != Some(HUGE_FRAME + offset) {
+            return Err("address inside huge page did not translate through the PD");
+        }
+    }
+    if paging::translate(pml4, HUGE_VIRT + last + 1).is_some() {
+        return Err("huge page extends past 2 MiB");
+    }
+    if paging::map_page(pml4, HUGE_VIRT + 0x1000, HUGE_FRAME, FLAG_WRITABLE) != Err(MapError::AlreadyMapped) {
+        return Err("4 KiB page mapped over a huge page");
+    }
+
+    paging::unmap_huge_page(pml4, HUGE_VIRT);
+    if paging::translate(pml4, HUGE_VIRT + 0x1_2345).is_some() {
+        return Err("huge page still mapped after unmap");
+    }
+    Ok(())
+}
+
+fn walk_mappings() -> TestResult {
+    // Frames are never touched, only recorded in the tables.
+    const TEXT: u64 = 0x0040_0000;
+    const DATA: u64 = 0x0060_0000;
+    const FRAME: u64 = 0x0010_0000;
+
+    let pml4 = paging::clone_kernel_pml4().map_err(|_| "pml4 allocation failed")?;
+    for page in 0..2u64 {
+        paging::map_page(pml4, TEXT + page * FRAME_SIZE, FRAME + page * FRAME_SIZE, FLAG_USER)
+            .map_err(|_| "map text failed")?;
+    }
+    for page in 0..3u64 {
+        let flags = FLAG_USER | FLAG_WRITABLE | FLAG_NO_EXECUTE;
+        paging::map_page(pml4, DATA + page * FRAME_SIZE, FRAME + (8 + page) * FRAME_SIZE, flags)
+            .map_err(|_| "map data failed")?;
+    }
+
+    let mut seen: Vec<MappedRange> = Vec::new();
+    paging::for_each_mapping(pml4, |range| {
+        if range.start < mmu::KERNEL_VMA_BASE {
+            seen.push(range);
+        }
+    });
+    let expected = [
+        MappedRange {
+            start: TEXT,
+            end: TEXT + 2 * FRAME_SIZE,
+            phys: FRAME,
+            flags: FLAG_USER,
+        },
+        MappedRange {
+            start: DATA,
+            end: DATA + 3 * FRAME_SIZE,
+            phys: FRAME + 8 * FRAME_SIZE,
+            flags: FLAG_USER | FLAG_WRITABLE | FLAG_NO_EXECUTE,
+        },
+    ];
+    if seen.as_slice() != expected {
+        return Err("walker did not report exactly the mapped ranges");
+    }
+
+    if paging::region_flags(pml4, TEXT + 0x10) != Some(FLAG_USER) {
+        return Err("text region flags wrong");
+    }
+    if paging::region_flags(pml4, DATA + 2 * FRAME_SIZE) != Some(FLAG_USER | FLAG_WRITABLE | FLAG_NO_EXECUTE) {
+        return Err("data region flags wrong");
+    }
+    if paging::region_flags(pml4, DATA + 3 * FRAME_SIZE).is_some() {
+        return Err("unmapped address reported flags");
+    }
+    Ok(())
+}
+
+const ZERO_ROUNDS: u64 = 16;
+
+fn zero_page_clears_frame() -> TestResult {
+    let frame = phys::allocate_frame().ok_or("no free frame")?;
+    let bytes = mmu::phys_to_virt(frame.start()) as *mut u8;
+    let dirty = |value: u8| unsafe { core::ptr::write_bytes(bytes, value, FRAME_SIZE as usize) };
+
+    dirty(0xA5);
+    mmu::zero_page(frame.start());
+    let page = unsafe { core::slice::from_raw_parts(bytes, FRAME_SIZE as usize) };
+    let leftover = page.iter().position(|&byte| byte != 0);
+
+    // Timing only goes to the log: under emulation the counter says little.
+    let mut helper_cycles = 0;
+    let mut loop_cycles = 0;
+    for _ in 0..ZERO_ROUNDS {
+        dirty(0x5A);
+        let start = cpu::read_tsc();
+        mmu::zero_page(frame.start());
+        helper_cycles += cpu::read_tsc().wrapping_sub(start);
+
+        dirty(0x5A);
+        let start = cpu::read_tsc();
+        for offset in 0..FRAME_SIZE as usize {
+            unsafe { bytes.add(offset).write_volatile(0) };
+        }
+        loop_cycles += cpu::read_tsc().wrapping_sub(start);
+    }
+    klog!(
+        "[test] zero_page {} cycles/page, byte loop {} cycles/page\n",
+        helper_cycles / ZERO_ROUNDS,
+        loop_cycles / ZERO_ROUNDS
+    );
+
+    phys::free_frame(frame);
+    match leftover {
+        Some(_) => Err("zero_page left a non-zero byte"),
+        None => Ok(()),
+    }
+}
+
+const CARVED_HEAP: usize = 64 * 1024;
+const CARVED_BLOCK: usize = 4096;
+
+/// An allocator over a range from `heap::carve`, set up the way
+/// `heap::init_from_phys` sets up the real one, gives out every byte of
+/// the range and then fails.
+fn carved_heap_fills_its_range() -> TestResult {
+    let (start, size) = heap::carve(CARVED_HEAP).ok_or("no physical range for the heap")?;
+    let mut allocator = LinkedListAllocator::new();
+    unsafe { allocator.init(start, size) };
+
+    let block = Layout::from_size_align(CARVED_BLOCK, 8).unwrap();
+    let mut blocks = 0;
+    let mut in_range = true;
+    loop {
+        let ptr = unsafe { allocator.allocate(block) };
+        if ptr.is_null() {
+            break;
+        }
+        let addr = ptr as usize;
+        in_range &= addr >= start && addr + CARVED_BLOCK <= start + size;
+        // Touch every byte so an unmapped or read-only page would fault.
+        unsafe { core::ptr::write_bytes(ptr, 0x5A, CARVED_BLOCK) };
+        blocks += 1;
+    }
+    let remaining = allocator.remaining();
+
+    let first = mmu::virt_to_phys(start as u64);
+    for index in 0..size as u64 / FRAME_SIZE {
+        phys::free_frame(Frame::containing(first + index * FRAME_SIZE));
+    }
+
+    if size != CARVED_HEAP {
+        return Err("a whole number of frames should be carved exactly");
+    }
+    if !in_range {
+        return Err("an allocation fell outside the carved range");
+    }
+    if blocks != CARVED_HEAP / CARVED_BLOCK || remaining != 0 {
+        return Err("the heap should satisfy allocations up to its full size");
+    }
+    Ok(())
+}
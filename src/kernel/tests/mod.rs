@@ -1,13 +1,29 @@
 #![cfg(kernel_test)]
 
 use crate::arch::x86_64::qemu;
+use crate::cmdline;
 use crate::klog;
+use crate::power;
 
 mod common;
+mod console;
+mod cpu;
+mod crash;
+mod elf;
+mod interrupts;
+mod keyboard;
+mod logging;
 mod memory;
 mod process;
+mod procfs;
+mod serial;
 mod vfs;
 mod fat;
+mod syscall;
+mod sync;
+mod timer;
+mod tmpfs;
+mod tty;
 
 pub type TestResult = Result<(), &'static str>;
 
@@ -29,13 +45,29 @@ impl TestCase {
 
 const SUITES: &[(&str, &[TestCase])] = &[
     ("memory", memory::TESTS),
+    ("interrupts", interrupts::TESTS),
+    ("cpu", cpu::TESTS),
+    ("klog", logging::TESTS),
     ("process", process::TESTS),
+    ("sync", sync::TESTS),
     ("vfs", vfs::TESTS),
     ("fat", fat::TESTS),
+    ("tmpfs", tmpfs::TESTS),
+    ("procfs", procfs::TESTS),
+    ("elf", elf::TESTS),
+    ("syscall", syscall::TESTS),
+    ("console", console::TESTS),
+    ("keyboard", keyboard::TESTS),
+    ("serial", serial::TESTS),
+    ("tty", tty::TESTS),
+    ("timer", timer::TESTS),
+    ("crash", crash::TESTS),
 ];
 
 pub fn run(multiboot_info_addr: usize) -> ! {
-    let filter = unsafe { command_line_filter(multiboot_info_addr) };
+    let cmdline = unsafe { cmdline::from_multiboot(multiboot_info_addr) };
+    let filter = cmdline.and_then(|cmdline| cmdline::value(cmdline, "test"));
+    let poweroff = cmdline.map_or(false, |cmdline| cmdline::has_flag(cmdline, "poweroff"));
 
     match filter {
         Some(f) => klog!("[test] kernel test harness starting (filter='{f}')\n"),
@@ -67,7 +99,12 @@ pub fn run(multiboot_info_addr: usize) -> ! {
         }
     }
 
-    if failures == 0 {
+    if failures == 0 && poweroff {
+        // QEMU exits 0 on ACPI soft-off, unlike exit_success's 1, so the
+        // runner can tell the power path really ran.
+        klog!("[test] all passed, powering off\n");
+        power::poweroff();
+    } else if failures == 0 {
         klog!("[test] all passed\n");
         qemu::exit_success();
     } else {
@@ -87,61 +124,3 @@ fn should_run(name: &str, filter: Option<&'static str>) -> bool {
     }
 }
 
-unsafe fn command_line_filter(multiboot_info_addr: usize) -> Option<&'static str> {
-    let cmdline = parse_cmdline(multiboot_info_addr)?;
-    extract_filter(cmdline)
-}
-
-fn extract_filter(cmdline: &'static str) -> Option<&'static str> {
-    for part in cmdline.split_ascii_whitespace() {
-        if let Some(value) = part.strip_prefix("test=") {
-            if !value.is_empty() {
-                return Some(value);
-            }
-        }
-    }
-    None
-}
-
-unsafe fn parse_cmdline(multiboot_info_addr: usize) -> Option<&'static str> {
-    const TAG_TYPE_END: u32 = 0;
-    const TAG_TYPE_CMDLINE: u32 = 1;
-
-    let total_size = *(multiboot_info_addr as *const u32) as usize;
-    let mut current = multiboot_info_addr + core::mem::size_of::<u32>() * 2;
-    let end = multiboot_info_addr + total_size;
-
-    while current < end {
-        let header = &*(current as *const TagHeader);
-        if header.tag_type == TAG_TYPE_END {
-            break;
-        }
-        if header.tag_type == TAG_TYPE_CMDLINE {
-            let data_ptr = current + core::mem::size_of::<TagHeader>();
-            let len = header.size as usize - core::mem::size_of::<TagHeader>();
-            if len == 0 {
-                return None;
-            }
-            let bytes = core::slice::from_raw_parts(data_ptr as *const u8, len);
-            let terminator = bytes.iter().position(|&b| b == 0).unwrap_or(len);
-            if terminator == 0 {
-                return None;
-            }
-            let slice = &bytes[..terminator];
-            return core::str::from_utf8(slice).ok();
-        }
-        current = align_up(current + header.size as usize, 8);
-    }
-    None
-}
-
-fn align_up(value: usize, align: usize) -> usize {
-    let mask = align - 1;
-    (value + mask) & !mask
-}
-
-#[repr(C)]
-struct TagHeader {
-    tag_type: u32,
-    size: u32,
-}
@@ -1,12 +1,49 @@
 #![cfg(kernel_test)]
 
 use core::hint::spin_loop;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 
+use super::common::{
+    exit_elf, no_exec_elf, read_regs, read_regs_elf, reap_child, retire, store_to_rodata_elf, store_to_text_elf, ud2_elf,
+    with_leader, ELF_BASE, ELF_CODE_OFFSET, EXIT7_CODE, RODATA_MEMSZ, RODATA_VADDR,
+};
 use super::{TestCase, TestResult};
-use crate::process::{self, AddressSpaceKind};
+use crate::arch::x86_64::drivers::keyboard as arch_keyboard;
+use crate::arch::x86_64::kernel::gdt;
+use crate::arch::x86_64::kernel::interrupts::{self, fault_exit};
+use crate::arch::x86_64::kernel::paging::{self, FLAG_NO_EXECUTE, FLAG_WRITABLE};
+use crate::cmdline;
+use crate::drivers::{keyboard, tty};
+use crate::fs::tmpfs;
+use crate::mem::heap;
+use crate::process::{self, AddressSpaceKind, MemoryRegionKind, Pid, ProcessError, ProcessState};
+use crate::syscall::{self, SysError};
+use crate::vfs::mount::{self, FileSystem};
+use crate::vfs::{VfsError, VfsFile, VfsResult};
+use crate::timer;
 use crate::user;
 
-pub const TESTS: &[TestCase] = &[TestCase::new("process.spawn_snapshot", spawn_snapshot)];
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("process.spawn_snapshot", spawn_snapshot),
+    TestCase::new("process.stack_overrun_trips_canary", stack_overrun_trips_canary),
+    TestCase::new("process.exit_canary_catches_smash", exit_canary_catches_smash),
+    TestCase::new("process.round_robin_order", round_robin_order),
+    TestCase::new("process.switch_trace_records_yields", switch_trace_records_yields),
+    TestCase::new("process.cpu_time_accounting", cpu_time_accounting),
+    TestCase::new("process.watchdog_reports_stall", watchdog_reports_stall),
+    TestCase::new("process.threads_share_state", threads_share_state),
+    TestCase::new("process.user_fault_exit", user_fault_exit),
+    TestCase::new("process.write_protect_fault", write_protect_fault),
+    TestCase::new("process.no_execute_fault", no_execute_fault),
+    TestCase::new("process.rodata_write_fault", rodata_write_fault),
+    TestCase::new("process.blocking_read_keeps_registers", blocking_read_keeps_registers),
+    TestCase::new("process.exit_flushes_descriptors", exit_flushes_descriptors),
+    TestCase::new("process.last_thread_flushes_shared_fds", last_thread_flushes_shared_fds),
+    TestCase::new("process.fd_table_grows", fd_table_grows),
+    TestCase::new("process.group_kill", group_kill),
+    TestCase::new("process.partial_region_free", partial_region_free),
+    TestCase::new("process.init_from_cmdline", init_from_cmdline),
+];
 
 fn spawn_snapshot() -> TestResult {
     process::init().map_err(|_| "process init failed")?;
@@ -40,5 +77,755 @@ fn spawn_snapshot() -> TestResult {
     if snapshot.user_entry().is_some() {
         return Err("kernel task should not have user entry");
     }
+    retire(&[pid]);
     Ok(())
 }
+
+fn stack_overrun_trips_canary() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+
+    extern "C" fn stub() -> ! {
+        loop {
+            spin_loop();
+        }
+    }
+
+    let pid = process::spawn_kernel_process("deep_task", stub).map_err(|_| "spawn failed")?;
+    if !process::stack_guard_intact(pid).map_err(|_| "guard lookup failed")? {
+        return Err("fresh stack should have an intact canary");
+    }
+
+    // Stand in for a task whose frames ran past the bottom of its stack.
+    let base = process::get_process(pid).ok_or("snapshot missing")?.kernel_stack_base() as *mut u64;
+    let saved = unsafe { base.read() };
+    unsafe { base.write(0) };
+    let intact = process::stack_guard_intact(pid).map_err(|_| "guard lookup failed")?;
+    unsafe { base.write(saved) };
+    retire(&[pid]);
+
+    if intact {
+        return Err("overrun did not trip the canary");
+    }
+    Ok(())
+}
+
+/// Set by the test before it lets `returning_task` run: whether to
+/// clobber the exit canary on the way out.
+static SMASH_ON_RETURN: AtomicU32 = AtomicU32::new(0);
+
+/// Falls off the end of its outermost frame into `process_exit`, the way a
+/// returning entry function would, after writing over the word above the
+/// return slot if asked to.
+extern "C" fn returning_task() -> ! {
+    let pid = process::current_pid().expect("returning_task without a pid");
+    let base = process::get_process(pid).expect("returning_task missing").kernel_stack_base();
+    let canary = ((base + process::KERNEL_STACK_SIZE as u64) & !0xF) - 16;
+    let return_slot = canary - 8;
+    if SMASH_ON_RETURN.load(Ordering::SeqCst) != 0 {
+        unsafe { (canary as *mut u64).write(0x4141_4141_4141_4141) };
+    }
+    unsafe {
+        core::arch::asm!("mov rsp, {slot}", "ret", slot = in(reg) return_slot, options(noreturn));
+    }
+}
+
+fn exit_canary_catches_smash() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("smash_leader", |_| {
+        SMASH_ON_RETURN.store(0, Ordering::SeqCst);
+        let clean = process::spawn_kernel_process("clean_return", returning_task).map_err(|_| "spawn failed")?;
+        let clean_code = reap_child(clean)?;
+
+        SMASH_ON_RETURN.store(1, Ordering::SeqCst);
+        let smashed = process::spawn_kernel_process("smash_return", returning_task).map_err(|_| "spawn failed")?;
+        let smashed_code = reap_child(smashed)?;
+        SMASH_ON_RETURN.store(0, Ordering::SeqCst);
+
+        if clean_code != -1 {
+            return Err("a task returning with its canary intact should exit with -1");
+        }
+        if smashed_code != process::STACK_SMASH_STATUS {
+            return Err("clobbered exit canary was not caught on exit");
+        }
+        Ok(())
+    })
+}
+
+fn round_robin_order() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+
+    extern "C" fn stub() -> ! {
+        loop {
+            spin_loop();
+        }
+    }
+
+    let tasks = [
+        process::spawn_kernel_process("rr_a", stub).map_err(|_| "spawn a failed")?,
+        process::spawn_kernel_process("rr_b", stub).map_err(|_| "spawn b failed")?,
+        process::spawn_kernel_process("rr_c", stub).map_err(|_| "spawn c failed")?,
+    ];
+
+    // Tasks left behind by earlier tests share the queue; only the relative
+    // order of ours matters, and each must come round exactly once per lap.
+    let mut order = [0u32; 9];
+    let mut seen = 0;
+    let mut picks = 0;
+    while seen < order.len() {
+        picks += 1;
+        if picks > 1024 {
+            process::set_current_pid(0);
+            return Err("tasks stopped being scheduled");
+        }
+        let pid = process::schedule_dry_run().ok_or("nothing runnable")?;
+        if tasks.contains(&pid) {
+            order[seen] = pid;
+            seen += 1;
+        }
+    }
+    process::set_current_pid(0);
+    retire(&tasks);
+
+    for (slot, pid) in order.iter().enumerate() {
+        if *pid != tasks[slot % tasks.len()] {
+            return Err("ready tasks did not run in strict round-robin order");
+        }
+    }
+    Ok(())
+}
+
+/// Yield once, so the trace sees this task leave both ways, then exit.
+extern "C" fn trace_yielder() -> ! {
+    process::yield_now();
+    process::exit_current(0)
+}
+
+fn switch_trace_records_yields() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("trace_leader", |leader| {
+        let was_on = process::set_switch_trace(true);
+        let _ = process::switch_trace();
+        let spawned = process::spawn_kernel_process("trace_child", trace_yielder).map_err(|_| "spawn failed");
+        let reaped = spawned.and_then(reap_child);
+        let trace = process::switch_trace();
+        process::set_switch_trace(was_on);
+        let child = spawned?;
+        if reaped? != 0 {
+            return Err("traced child exited with the wrong status");
+        }
+
+        // Real switches chain: each one leaves the process the last entered.
+        if !trace.windows(2).all(|pair| pair[0].to == pair[1].from) {
+            return Err("trace records do not chain from one switch to the next");
+        }
+        if !trace.windows(2).all(|pair| pair[0].tick <= pair[1].tick) {
+            return Err("trace ticks went backwards");
+        }
+        let left = |pid: Pid, reason: process::SwitchReason| {
+            trace.iter().any(|record| record.from == pid && record.reason == reason)
+        };
+        if !left(leader, process::SwitchReason::Yield) {
+            return Err("the leader's yield was not traced");
+        }
+        if !left(child, process::SwitchReason::Yield) {
+            return Err("the child's yield was not traced");
+        }
+        if !left(child, process::SwitchReason::Exit) {
+            return Err("the child's exit was not traced as an exit");
+        }
+        Ok(())
+    })
+}
+
+fn cpu_time_accounting() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+
+    extern "C" fn stub() -> ! {
+        loop {
+            spin_loop();
+        }
+    }
+
+    let busy = process::spawn_kernel_process("cpu_busy", stub).map_err(|_| "spawn busy failed")?;
+    let light = process::spawn_kernel_process("cpu_light", stub).map_err(|_| "spawn light failed")?;
+
+    // The busy task keeps the CPU for a whole slice; the light one gives it
+    // back after a single tick, as a task that keeps blocking would.
+    let mut turns = 0;
+    let mut picks = 0;
+    while turns < 6 {
+        picks += 1;
+        if picks > 1024 {
+            process::set_current_pid(0);
+            return Err("tasks stopped being scheduled");
+        }
+        let pid = process::schedule_dry_run().ok_or("nothing runnable")?;
+        if pid == busy {
+            timer::advance_ticks(5);
+            turns += 1;
+        } else if pid == light {
+            timer::advance_ticks(1);
+            turns += 1;
+        }
+    }
+    process::set_current_pid(0);
+    retire(&[busy, light]);
+
+    let busy_ms = process::get_process(busy).ok_or("busy missing")?.cpu_time_ms();
+    let light_ms = process::get_process(light).ok_or("light missing")?.cpu_time_ms();
+    if light_ms == 0 {
+        return Err("light task accrued no CPU time");
+    }
+    if busy_ms <= light_ms {
+        return Err("CPU-bound task should accrue more time");
+    }
+    if process::scheduler_stats().total_cpu_ms < busy_ms + light_ms {
+        return Err("scheduler stats should include both tasks");
+    }
+    Ok(())
+}
+
+static THREAD_HITS: AtomicU32 = AtomicU32::new(0);
+
+extern "C" fn bump_and_exit() -> ! {
+    THREAD_HITS.fetch_add(1, Ordering::SeqCst);
+    process::exit_current(0)
+}
+
+fn threads_share_state() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("thread_leader", run_threads)
+}
+
+fn run_threads(leader: Pid) -> TestResult {
+    THREAD_HITS.store(0, Ordering::SeqCst);
+    let first = process::spawn_thread(bump_and_exit).map_err(|_| "spawn first thread failed")?;
+    let second = process::spawn_thread(bump_and_exit).map_err(|_| "spawn second thread failed")?;
+
+    let leader_cr3 = process::get_process(leader).ok_or("leader missing")?.address_space().cr3();
+    for pid in [first, second] {
+        let snapshot = process::get_process(pid).ok_or("thread missing")?;
+        if snapshot.tgid() != leader || snapshot.address_space().cr3() != leader_cr3 {
+            return Err("thread should share the leader's group and address space");
+        }
+    }
+
+    // A descriptor opened through a thread lands in the shared table.
+    let fd = process::open_path(first, "/dev/null").map_err(|_| "open via thread failed")?;
+    process::close_fd(leader, fd).map_err(|_| "thread fd not visible to leader")?;
+
+    for _ in 0..8 {
+        if THREAD_HITS.load(Ordering::SeqCst) == 2 {
+            break;
+        }
+        process::yield_now();
+    }
+    if THREAD_HITS.load(Ordering::SeqCst) != 2 {
+        return Err("both threads should have run");
+    }
+
+    for pid in [first, second] {
+        let (reaped, code) = process::wait_for_child(Some(pid)).map_err(|_| "join failed")?;
+        if reaped != pid || code != 0 {
+            return Err("thread exit status mismatch");
+        }
+    }
+    Ok(())
+}
+
+/// Put `elf` at `path` on tmpfs, ready for `spawn_user_process`.
+fn install_user_program(path: &str, elf: &[u8]) -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    // Ring 3 needs the user segments and a TSS for the trap back in.
+    gdt::init();
+    tmpfs::init().map_err(|_| "tmpfs mount failed")?;
+    let (fs, rest) = mount::lookup(path).ok_or("tmpfs not mounted")?;
+    let file = fs.create(rest).map_err(|_| "create user program failed")?;
+    file.write_at(0, elf).map_err(|_| "write user program failed")?;
+    Ok(())
+}
+
+/// Spawn `path` as a child of the current leader, yield until it dies and
+/// reap it. Returns the child's pid and exit status.
+fn run_user_child(name: &'static str, path: &str) -> Result<(Pid, i32), &'static str> {
+    let child = process::spawn_user_process(name, path).map_err(|_| "spawn user child failed")?;
+    Ok((child, reap_child(child)?))
+}
+
+fn user_fault_exit() -> TestResult {
+    install_user_program("/tmp/ud2", &ud2_elf())?;
+    with_leader("fault_parent", |_| {
+        let (_, code) = run_user_child("ud2", "/tmp/ud2")?;
+        if code != fault_exit::INVALID_OPCODE {
+            return Err("parent should see the invalid-opcode exit status");
+        }
+        Ok(())
+    })
+}
+
+// Set 1 make/break codes for 'a'.
+const SCANCODE_A: u8 = 0x1E;
+const SCANCODE_A_RELEASE: u8 = 0x9E;
+
+/// A user process that sleeps inside `read` is switched out with its
+/// syscall frame on its kernel stack, and must come back to user mode with
+/// the byte count in rax and every other register as it left them.
+fn blocking_read_keeps_registers() -> TestResult {
+    install_user_program("/tmp/rdregs", &read_regs_elf())?;
+    let canonical = tty::is_canonical();
+    tty::set_canonical(false);
+    let mut byte = [0u8; 1];
+    while keyboard::try_read(&mut byte) > 0 {}
+
+    let result = with_leader("rdregs_parent", |_| {
+        let child = process::spawn_user_process("rdregs", "/tmp/rdregs").map_err(|_| "spawn user child failed")?;
+        for _ in 0..8 {
+            if process::get_process(child).map(|snapshot| snapshot.state()) == Some(ProcessState::Blocked) {
+                break;
+            }
+            process::yield_now();
+        }
+        if process::get_process(child).map(|snapshot| snapshot.state()) != Some(ProcessState::Blocked) {
+            retire(&[child]);
+            return Err("child should sleep in read while stdin is empty");
+        }
+
+        arch_keyboard::inject_scancode(SCANCODE_A);
+        arch_keyboard::inject_scancode(SCANCODE_A_RELEASE);
+        match reap_child(child)? {
+            0 => Ok(()),
+            code if code & read_regs::BAD_RETURN != 0 => Err("read returned the wrong count after the switch"),
+            code if code & read_regs::ARGS_CLOBBERED != 0 => Err("argument registers changed across the syscall"),
+            code if code & read_regs::CALLEE_SAVED_CLOBBERED != 0 => Err("rbx/rbp/r12 changed across the syscall"),
+            code if code & read_regs::WRONG_BYTE != 0 => Err("the injected byte did not reach the user buffer"),
+            _ => Err("child exited with an unexpected status"),
+        }
+    });
+
+    tty::set_canonical(canonical);
+    result
+}
+
+fn write_protect_fault() -> TestResult {
+    install_user_program("/tmp/wp", &store_to_text_elf())?;
+    with_leader("wp_parent", |_| {
+        interrupts::take_last_user_fault();
+        let (child, code) = run_user_child("wp", "/tmp/wp")?;
+        if code != fault_exit::PAGE_FAULT {
+            return Err("store to read-only text should end in a page fault");
+        }
+        let (pid, fault) = interrupts::take_last_user_fault().ok_or("no user page fault recorded")?;
+        if pid != child || fault.addr != ELF_BASE + ELF_CODE_OFFSET as u64 + 6 {
+            return Err("fault recorded for the wrong process or address");
+        }
+        if !(fault.present && fault.write && fault.user) || fault.instruction {
+            return Err("expected a write-protection fault on a present page");
+        }
+        Ok(())
+    })
+}
+
+fn no_execute_fault() -> TestResult {
+    install_user_program("/tmp/nx", &no_exec_elf())?;
+    with_leader("nx_parent", |_| {
+        interrupts::take_last_user_fault();
+        let (child, code) = run_user_child("nx", "/tmp/nx")?;
+        if code != fault_exit::PAGE_FAULT {
+            return Err("executing a no-execute page should end in a page fault, not #UD");
+        }
+        let (pid, fault) = interrupts::take_last_user_fault().ok_or("no user page fault recorded")?;
+        let entry = ELF_BASE + ELF_CODE_OFFSET as u64;
+        if pid != child || fault.addr != entry || fault.rip != entry {
+            return Err("fault recorded for the wrong process or address");
+        }
+        if !(fault.present && fault.instruction && fault.user) || fault.write {
+            return Err("expected an instruction-fetch fault on a present page");
+        }
+        Ok(())
+    })
+}
+
+fn rodata_write_fault() -> TestResult {
+    install_user_program("/tmp/rodata", &store_to_rodata_elf())?;
+    with_leader("rodata_parent", |_| {
+        interrupts::take_last_user_fault();
+        let child = process::spawn_user_process("rodata", "/tmp/rodata").map_err(|_| "spawn user child failed")?;
+
+        // The partly filled first page and the zero-fill page after it.
+        let cr3 = process::get_process(child).ok_or("child missing")?.address_space().cr3();
+        let mut page = RODATA_VADDR;
+        while page < RODATA_VADDR + RODATA_MEMSZ {
+            let flags = paging::page_flags(cr3, page).ok_or("rodata page not mapped")?;
+            if flags & FLAG_WRITABLE != 0 || flags & FLAG_NO_EXECUTE == 0 {
+                retire(&[child]);
+                return Err("rodata page should be read-only and no-execute");
+            }
+            page += paging::PAGE_SIZE as u64;
+        }
+
+        let code = reap_child(child)?;
+        if code != fault_exit::PAGE_FAULT {
+            return Err("store to .rodata should end in a page fault");
+        }
+        let (pid, fault) = interrupts::take_last_user_fault().ok_or("no user page fault recorded")?;
+        if pid != child || fault.addr != RODATA_VADDR {
+            return Err("fault recorded for the wrong process or address");
+        }
+        if !(fault.present && fault.write && fault.user) || fault.instruction {
+            return Err("expected a write-protection fault on a present page");
+        }
+        Ok(())
+    })
+}
+
+fn watchdog_reports_stall() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+
+    extern "C" fn stub() -> ! {
+        loop {
+            spin_loop();
+        }
+    }
+
+    let first = process::spawn_kernel_process("wd_a", stub).map_err(|_| "spawn a failed")?;
+    let second = process::spawn_kernel_process("wd_b", stub).map_err(|_| "spawn b failed")?;
+    // One switch to start the clock, then stop scheduling with a task ready.
+    process::schedule_dry_run().ok_or("nothing runnable")?;
+    let before = process::watchdog_fires();
+    let limit = timer::ms_to_ticks(process::WATCHDOG_MS);
+
+    timer::advance_ticks(limit - 1);
+    let early = process::watchdog_fires() != before;
+    timer::advance_ticks(1);
+    let fired = process::watchdog_fires() == before + 1;
+    timer::advance_ticks(limit);
+    let repeated = process::watchdog_fires() != before + 1;
+
+    process::set_current_pid(0);
+    retire(&[first, second]);
+
+    if early {
+        return Err("watchdog fired before the limit");
+    }
+    if !fired {
+        return Err("watchdog missed a stalled scheduler");
+    }
+    if repeated {
+        return Err("one stall should be reported once");
+    }
+    Ok(())
+}
+
+/// A file that holds writes back until `flush`, counting bytes rather than
+/// storing them.
+struct WriteBackFile {
+    pending: AtomicU64,
+    committed: AtomicU64,
+}
+
+static WRITE_BACK: WriteBackFile = WriteBackFile {
+    pending: AtomicU64::new(0),
+    committed: AtomicU64::new(0),
+};
+
+impl VfsFile for WriteBackFile {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Ok(0)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.pending.fetch_add(buf.len() as u64, Ordering::SeqCst);
+        Ok(buf.len())
+    }
+
+    fn flush(&self) -> VfsResult<()> {
+        let pending = self.pending.swap(0, Ordering::SeqCst);
+        self.committed.fetch_add(pending, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn size(&self) -> VfsResult<u64> {
+        Ok(self.committed.load(Ordering::SeqCst))
+    }
+}
+
+struct WriteBackFs;
+
+static WRITE_BACK_FS: WriteBackFs = WriteBackFs;
+
+impl FileSystem for WriteBackFs {
+    fn open(&'static self, path: &str) -> VfsResult<&'static dyn VfsFile> {
+        match path {
+            "log" => Ok(&WRITE_BACK),
+            _ => Err(VfsError::NotFound),
+        }
+    }
+}
+
+fn exit_flushes_descriptors() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    WRITE_BACK.pending.store(0, Ordering::SeqCst);
+    WRITE_BACK.committed.store(0, Ordering::SeqCst);
+    mount::mount("/writeback", &WRITE_BACK_FS).map_err(|_| "mount failed")?;
+    let result = with_leader("exit_flush", run_exit_flush);
+    mount::unmount("/writeback");
+    result
+}
+
+fn run_exit_flush(leader: Pid) -> TestResult {
+    extern "C" fn idle_thread() -> ! {
+        loop {
+            spin_loop();
+        }
+    }
+
+    let thread = process::spawn_thread(idle_thread).map_err(|_| "spawn thread failed")?;
+    let fd = process::open_path(leader, "/writeback/log").map_err(|_| "open /writeback/log failed")?;
+    process::with_fd_mut(leader, fd, |descriptor| descriptor.write(b"unflushed"))
+        .map_err(|_| "fd vanished")?
+        .map_err(|_| "write failed")?;
+
+    // The leader is still alive, so the shared table must survive the thread.
+    process::exit_process(thread, 0).map_err(|_| "thread exit failed")?;
+    process::wait_for_child(Some(thread)).map_err(|_| "join failed")?;
+    if WRITE_BACK.committed.load(Ordering::SeqCst) != 0 {
+        return Err("thread exit flushed the shared table");
+    }
+    process::with_fd_mut(leader, fd, |_| ()).map_err(|_| "thread exit closed the shared table")?;
+
+    process::exit_process(leader, 0).map_err(|_| "leader exit failed")?;
+    if WRITE_BACK.committed.load(Ordering::SeqCst) != b"unflushed".len() as u64 {
+        return Err("exit left buffered writes unflushed");
+    }
+    if process::with_fd_mut(leader, fd, |_| ()).is_ok() {
+        return Err("descriptor still open after exit");
+    }
+    Ok(())
+}
+
+fn last_thread_flushes_shared_fds() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    WRITE_BACK.pending.store(0, Ordering::SeqCst);
+    WRITE_BACK.committed.store(0, Ordering::SeqCst);
+    mount::mount("/writeback", &WRITE_BACK_FS).map_err(|_| "mount failed")?;
+    let result = with_leader("group_parent", run_last_thread_flush);
+    mount::unmount("/writeback");
+    result
+}
+
+/// The group leader exits first, while its thread still runs; the thread
+/// is the one that ends the shared table.
+fn run_last_thread_flush(parent: Pid) -> TestResult {
+    extern "C" fn idle_task() -> ! {
+        loop {
+            spin_loop();
+        }
+    }
+
+    let leader = process::spawn_kernel_process("group_leader", idle_task).map_err(|_| "spawn leader failed")?;
+    process::set_current_pid(leader);
+    let thread = process::spawn_thread(idle_task);
+    process::set_current_pid(parent);
+    let thread = thread.map_err(|_| "spawn thread failed")?;
+
+    let fd = process::open_path(leader, "/writeback/log").map_err(|_| "open /writeback/log failed")?;
+    process::with_fd_mut(leader, fd, |descriptor| descriptor.write(b"unflushed"))
+        .map_err(|_| "fd vanished")?
+        .map_err(|_| "write failed")?;
+
+    process::exit_process(leader, 0).map_err(|_| "leader exit failed")?;
+    if WRITE_BACK.committed.load(Ordering::SeqCst) != 0 {
+        return Err("leader exit flushed a table its thread still uses");
+    }
+    process::with_fd_mut(thread, fd, |_| ()).map_err(|_| "leader exit closed the thread's table")?;
+
+    process::exit_process(thread, 0).map_err(|_| "thread exit failed")?;
+    if WRITE_BACK.committed.load(Ordering::SeqCst) != b"unflushed".len() as u64 {
+        return Err("the last thread out left buffered writes unflushed");
+    }
+    // The thread is the leader's child; collect it in the leader's name.
+    process::set_current_pid(leader);
+    let joined = process::wait_for_child(Some(thread));
+    process::set_current_pid(parent);
+    joined.map_err(|_| "join thread failed")?;
+    let (reaped, _) = process::wait_for_child(Some(leader)).map_err(|_| "reap leader failed")?;
+    if reaped != leader {
+        return Err("reaped the wrong child");
+    }
+    Ok(())
+}
+
+fn fd_table_grows() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("many_fds", run_fd_table_grows)
+}
+
+fn run_fd_table_grows(leader: Pid) -> TestResult {
+    const OPENED: usize = 40;
+
+    let mut fds = [0usize; OPENED];
+    for slot in fds.iter_mut() {
+        *slot = process::open_path(leader, "/dev/null").map_err(|_| "open past the old 16-fd cap failed")?;
+    }
+    if fds.iter().enumerate().any(|(i, &fd)| i > 0 && fd != fds[i - 1] + 1) {
+        return Err("new fds were not handed out in order");
+    }
+    for &fd in fds.iter() {
+        let written = process::with_fd_mut(leader, fd, |descriptor| descriptor.write(b"x"))
+            .map_err(|_| "high fd vanished")?
+            .map_err(|_| "write to high fd failed")?;
+        if written != 1 {
+            return Err("write to high fd came back short");
+        }
+    }
+
+    // The soft limit stops growth but leaves what is open alone.
+    let highest = fds[OPENED - 1];
+    process::set_fd_limit(leader, highest + 1).map_err(|_| "set_fd_limit failed")?;
+    if !matches!(process::open_path(leader, "/dev/null"), Err(ProcessError::NoFreeFileDescriptors)) {
+        return Err("open past the soft limit was not refused");
+    }
+    if !matches!(process::set_fd_limit(leader, 0), Err(ProcessError::InvalidFdLimit)) {
+        return Err("a zero fd limit was accepted");
+    }
+
+    for &fd in fds.iter() {
+        process::close_fd(leader, fd).map_err(|_| "close of high fd failed")?;
+    }
+    if process::with_fd_mut(leader, highest, |_| ()).is_ok() {
+        return Err("closed fd still usable");
+    }
+    let reused = process::open_path(leader, "/dev/null").map_err(|_| "open after closing failed")?;
+    if reused != fds[0] {
+        return Err("lowest free fd was not reused");
+    }
+    process::close_fd(leader, reused).map_err(|_| "close failed")?;
+    process::set_fd_limit(leader, process::DEFAULT_FD_LIMIT).map_err(|_| "restoring the limit failed")
+}
+
+fn group_kill() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("group_leader", run_group_kill)
+}
+
+fn run_group_kill(leader: Pid) -> TestResult {
+    extern "C" fn member() -> ! {
+        loop {
+            spin_loop();
+        }
+    }
+
+    let first = process::spawn_kernel_process("group_a", member).map_err(|_| "spawn first failed")?;
+    let second = process::spawn_kernel_process("group_b", member).map_err(|_| "spawn second failed")?;
+    let bystander = process::spawn_kernel_process("group_c", member).map_err(|_| "spawn bystander failed")?;
+    let result = (|| -> TestResult {
+        let snapshot = process::get_process(first).ok_or("first child missing")?;
+        if snapshot.pgid() != leader || snapshot.sid() != leader {
+            return Err("children should start in the parent's group and session");
+        }
+        if syscall::setpgid(leader, leader) != Err(SysError::NotPermitted) {
+            return Err("a session leader should not change group");
+        }
+
+        syscall::setpgid(first, first).map_err(|_| "new group failed")?;
+        syscall::setpgid(second, first).map_err(|_| "joining the group failed")?;
+        if syscall::getpgid(second) != Ok(first) || syscall::getpgid(bystander) != Ok(leader) {
+            return Err("getpgid does not match setpgid");
+        }
+
+        syscall::kill(-(first as i64)).map_err(|_| "group kill failed")?;
+        for pid in [first, second] {
+            if process::get_process(pid).map(|p| p.state()) != Some(ProcessState::Zombie) {
+                return Err("a group member survived the kill");
+            }
+            let (reaped, code) = process::wait_for_child(Some(pid)).map_err(|_| "reap failed")?;
+            if reaped != pid || code != process::KILLED_STATUS {
+                return Err("killed member should exit with KILLED_STATUS");
+            }
+        }
+        if process::get_process(bystander).map(|p| p.state()) == Some(ProcessState::Zombie) {
+            return Err("kill reached outside the group");
+        }
+        if syscall::kill(-(first as i64)) != Err(SysError::NoProcess) {
+            return Err("an emptied group should report ESRCH");
+        }
+        Ok(())
+    })();
+    retire(&[first, second, bystander]);
+    result
+}
+
+fn partial_region_free() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+
+    extern "C" fn stub() -> ! {
+        loop {
+            spin_loop();
+        }
+    }
+
+    let pid = process::spawn_kernel_process("region_owner", stub).map_err(|_| "spawn failed")?;
+    let result = (|| -> TestResult {
+        let layout = core::alloc::Layout::from_size_align(256, 16).map_err(|_| "bad layout")?;
+        let ptr = process::allocate_for_process(pid, layout, MemoryRegionKind::Heap)
+            .map_err(|_| "allocate_for_process failed")?;
+        let middle = ptr.wrapping_add(64);
+
+        let free_before = heap::remaining_bytes();
+        if process::free_for_process(pid, middle, 64).map_err(|_| "middle free failed")? != 64 {
+            return Err("freeing the middle should release 64 bytes");
+        }
+        if heap::remaining_bytes() != free_before + 64 {
+            return Err("the middle should go back to the heap");
+        }
+        if !matches!(process::free_for_process(pid, middle, 64), Err(ProcessError::MemoryRegionNotFound)) {
+            return Err("a freed hole should no longer be tracked");
+        }
+        // The rest is two regions now; one call spanning the hole frees both.
+        if process::free_for_process(pid, ptr, 256).map_err(|_| "spanning free failed")? != 192 {
+            return Err("the pieces either side of the hole should total 192 bytes");
+        }
+        Ok(())
+    })();
+    retire(&[pid]);
+    result
+}
+
+fn init_from_cmdline() -> TestResult {
+    install_user_program("/tmp/init", &exit_elf(EXIT7_CODE as u8))?;
+    with_leader("boot", |_| {
+        extern "C" fn builtin_shell() -> ! {
+            loop {
+                spin_loop();
+            }
+        }
+
+        let kind_of = |pid: Pid| process::get_process(pid).map(|p| p.address_space().kind());
+
+        let line = "console=ttyS0 init=/tmp/init quiet";
+        let init = process::spawn_init(cmdline::value(line, "init"), builtin_shell)
+            .map_err(|_| "spawn_init failed")?;
+        if kind_of(init) != Some(AddressSpaceKind::User) {
+            retire(&[init]);
+            return Err("init= should start the user program");
+        }
+        if reap_child(init)? != EXIT7_CODE {
+            return Err("the init program did not run to its exit");
+        }
+
+        let missing = process::spawn_init(Some("/tmp/no-such-init"), builtin_shell)
+            .map_err(|_| "fallback failed")?;
+        let absent = process::spawn_init(cmdline::value("quiet", "init"), builtin_shell)
+            .map_err(|_| "default failed")?;
+        let kinds = (kind_of(missing), kind_of(absent));
+        retire(&[missing, absent]);
+        if kinds != (Some(AddressSpaceKind::Kernel), Some(AddressSpaceKind::Kernel)) {
+            return Err("without a loadable init= the built-in shell should run");
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,117 @@
+#![cfg(kernel_test)]
+
+use alloc::format;
+use core::ptr;
+
+use super::common::{retire, with_leader};
+use super::{TestCase, TestResult};
+use crate::fs::procfs;
+use crate::process::{self, Pid};
+use crate::syscall;
+use crate::vfs::mount;
+use crate::vfs::VfsFile;
+
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("procfs.status_names_the_process", status_names_the_process),
+    TestCase::new("procfs.meminfo_reports_the_heap", meminfo_reports_the_heap),
+    TestCase::new("procfs.reopen_reuses_node", reopen_reuses_node),
+];
+
+fn setup() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    procfs::init().map_err(|_| "procfs mount failed")
+}
+
+/// Read the whole of `path` through a descriptor, as a user program would.
+fn read_file(path: &str, buf: &mut [u8]) -> Result<usize, &'static str> {
+    let fd = syscall::open(path).map_err(|_| "open failed")? as u64;
+    let mut total = 0;
+    let result = loop {
+        match syscall::read(fd, &mut buf[total..]) {
+            Ok(0) => break Ok(total),
+            Ok(count) => total += count,
+            Err(_) => break Err("read failed"),
+        }
+        if total == buf.len() {
+            break Err("file larger than the buffer");
+        }
+    };
+    syscall::close(fd).map_err(|_| "close failed")?;
+    result
+}
+
+/// The value of the `key:\t` line in `text`.
+fn field<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+    text.lines().find_map(|line| line.strip_prefix(key)?.strip_prefix(":\t"))
+}
+
+extern "C" fn idle_task() -> ! {
+    loop {
+        process::yield_now();
+    }
+}
+
+fn status_names_the_process() -> TestResult {
+    setup()?;
+    with_leader("procfs_reader", |_| {
+        let child: Pid = process::spawn_kernel_process("proc_target", idle_task).map_err(|_| "spawn failed")?;
+        let path = format!("/proc/{}/status", child);
+        let mut buf = [0u8; 512];
+        let read = read_file(&path, &mut buf);
+        retire(&[child]);
+
+        let count = read?;
+        let text = core::str::from_utf8(&buf[..count]).map_err(|_| "status is not utf8")?;
+        if field(text, "Pid").and_then(|pid| pid.parse::<Pid>().ok()) != Some(child) {
+            return Err("status should carry the task's pid");
+        }
+        if field(text, "Name") != Some("proc_target") {
+            return Err("status should carry the task's name");
+        }
+
+        // The text is generated on each read, so it follows the exit.
+        let count = read_file(&path, &mut buf)?;
+        let text = core::str::from_utf8(&buf[..count]).map_err(|_| "status is not utf8")?;
+        if field(text, "State") != Some("Zombie") {
+            return Err("an exited task should read back as a zombie");
+        }
+        Ok(())
+    })
+}
+
+fn meminfo_reports_the_heap() -> TestResult {
+    setup()?;
+    with_leader("procfs_meminfo", |_| {
+        let mut buf = [0u8; 512];
+        let count = read_file("/proc/meminfo", &mut buf)?;
+        let text = core::str::from_utf8(&buf[..count]).map_err(|_| "meminfo is not utf8")?;
+        let total = field(text, "HeapTotal").and_then(|value| value.parse::<usize>().ok());
+        let free = field(text, "HeapFree").and_then(|value| value.parse::<usize>().ok());
+        match (total, free) {
+            (Some(total), Some(free)) if total > 0 && free <= total => Ok(()),
+            _ => Err("meminfo should report a heap with free bytes within its size"),
+        }
+    })
+}
+
+/// Open `path` straight from the filesystem and return the node's address.
+fn open_node(path: &str) -> Result<*const u8, &'static str> {
+    let (fs, rest) = mount::lookup(path).ok_or("no filesystem for the path")?;
+    let file: &'static dyn VfsFile = fs.open(rest).map_err(|_| "open failed")?;
+    Ok(file as *const dyn VfsFile as *const u8)
+}
+
+fn reopen_reuses_node() -> TestResult {
+    setup()?;
+    with_leader("procfs_reopen", |leader| {
+        let status = format!("/proc/{}/status", leader);
+        let dir = format!("/proc/{}", leader);
+        if !ptr::eq(open_node(&status)?, open_node(&status)?) {
+            return Err("reopening a status file allocated a new node");
+        }
+        if ptr::eq(open_node(&status)?, open_node(&dir)?) {
+            return Err("a pid's directory and status file share a node");
+        }
+        Ok(())
+    })
+}
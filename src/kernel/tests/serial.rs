@@ -0,0 +1,51 @@
+#![cfg(kernel_test)]
+
+use core::hint::spin_loop;
+
+use super::{TestCase, TestResult};
+use crate::arch::x86_64::drivers::serial as arch;
+use crate::arch::x86_64::kernel::interrupts::{self, vectors};
+use crate::drivers::serial;
+
+pub const TESTS: &[TestCase] = &[TestCase::new("serial.loopback_byte_is_received", loopback_byte_is_received)];
+
+/// Not ASCII, so a stray `klog` line caught by the loopback cannot be
+/// mistaken for it.
+const PROBE: u8 = 0xA5;
+const RECEIVE_WAIT_SPINS: usize = 10_000_000;
+
+fn loopback_byte_is_received() -> TestResult {
+    arch::enable_receive();
+    let mut buf = [0u8; 16];
+    while serial::try_read(&mut buf) > 0 {}
+    let fired = interrupts::stats().count(vectors::COM1);
+
+    // Nothing may log between here and the end of loopback: it would be
+    // received instead of reaching the host.
+    arch::set_loopback(true);
+    arch::transmit_raw(PROBE);
+    interrupts::enable();
+    let mut spins = 0;
+    while !serial::has_input() && spins < RECEIVE_WAIT_SPINS {
+        spin_loop();
+        spins += 1;
+    }
+    interrupts::disable();
+    arch::set_loopback(false);
+
+    if interrupts::stats().count(vectors::COM1) == fired {
+        return Err("IRQ4 never fired for the received byte");
+    }
+    let mut seen = false;
+    loop {
+        let count = serial::try_read(&mut buf);
+        if count == 0 {
+            break;
+        }
+        seen |= buf[..count].contains(&PROBE);
+    }
+    if !seen {
+        return Err("the transmitted byte was not readable");
+    }
+    Ok(())
+}
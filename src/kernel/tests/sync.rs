@@ -0,0 +1,222 @@
+#![cfg(kernel_test)]
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use super::common::with_leader;
+use super::{TestCase, TestResult};
+use crate::process::{self, Pid};
+use crate::sync::condvar;
+use crate::sync::mutex::Mutex;
+use crate::sync::semaphore::Semaphore;
+
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("sync.condvar_notify_all", condvar_notify_all),
+    TestCase::new("sync.semaphore_mutual_exclusion", semaphore_mutual_exclusion),
+    TestCase::new("sync.mutex_blocks_waiters", mutex_blocks_waiters),
+];
+
+const WAKE_TOKEN: usize = 0xC0DE_0001;
+
+static WOKEN: AtomicU32 = AtomicU32::new(0);
+
+extern "C" fn wait_then_count() -> ! {
+    let _ = condvar::wait(WAKE_TOKEN);
+    WOKEN.fetch_add(1, Ordering::SeqCst);
+    process::exit_current(0)
+}
+
+fn condvar_notify_all() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("condvar_leader", run_condvar)
+}
+
+fn run_condvar(_leader: Pid) -> TestResult {
+    WOKEN.store(0, Ordering::SeqCst);
+    let first = process::spawn_kernel_process("cv_wait_a", wait_then_count).map_err(|_| "spawn waiter failed")?;
+    let second = process::spawn_kernel_process("cv_wait_b", wait_then_count).map_err(|_| "spawn waiter failed")?;
+
+    // Let both waiters run up to the wait.
+    process::yield_now();
+    for pid in [first, second] {
+        let snapshot = process::get_process(pid).ok_or("waiter missing")?;
+        if snapshot.state() != process::ProcessState::Blocked {
+            return Err("waiter should be blocked on the token");
+        }
+    }
+    if WOKEN.load(Ordering::SeqCst) != 0 {
+        return Err("waiter ran past the condvar without a notify");
+    }
+
+    if condvar::notify(WAKE_TOKEN + 1) {
+        return Err("notify on an unrelated token woke someone");
+    }
+    let woken = condvar::notify_all(WAKE_TOKEN);
+    if woken != 2 {
+        return Err("notify_all should wake both waiters");
+    }
+
+    for _ in 0..8 {
+        if WOKEN.load(Ordering::SeqCst) == 2 {
+            break;
+        }
+        process::yield_now();
+    }
+    if WOKEN.load(Ordering::SeqCst) != 2 {
+        return Err("both waiters should have resumed");
+    }
+
+    for pid in [first, second] {
+        process::wait_for_child(Some(pid)).map_err(|_| "reap waiter failed")?;
+    }
+    Ok(())
+}
+
+const ROUNDS: u32 = 4;
+
+static MUTEX: Semaphore = Semaphore::new(1);
+static IN_SECTION: AtomicBool = AtomicBool::new(false);
+static OVERLAPPED: AtomicBool = AtomicBool::new(false);
+static SHARED: AtomicU32 = AtomicU32::new(0);
+static FINISHED: AtomicU32 = AtomicU32::new(0);
+
+extern "C" fn bump_under_semaphore() -> ! {
+    for _ in 0..ROUNDS {
+        if MUTEX.acquire().is_err() {
+            break;
+        }
+        if IN_SECTION.swap(true, Ordering::SeqCst) {
+            OVERLAPPED.store(true, Ordering::SeqCst);
+        }
+        // Read-modify-write with a yield in the middle: without the
+        // semaphore the other task would interleave and lose an update.
+        let value = SHARED.load(Ordering::SeqCst);
+        process::yield_now();
+        SHARED.store(value + 1, Ordering::SeqCst);
+        IN_SECTION.store(false, Ordering::SeqCst);
+        MUTEX.release();
+    }
+    FINISHED.fetch_add(1, Ordering::SeqCst);
+    process::exit_current(0)
+}
+
+fn semaphore_mutual_exclusion() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("semaphore_leader", run_semaphore)
+}
+
+fn run_semaphore(_leader: Pid) -> TestResult {
+    SHARED.store(0, Ordering::SeqCst);
+    FINISHED.store(0, Ordering::SeqCst);
+    OVERLAPPED.store(false, Ordering::SeqCst);
+
+    let first = process::spawn_kernel_process("sem_task_a", bump_under_semaphore).map_err(|_| "spawn task failed")?;
+    let second = process::spawn_kernel_process("sem_task_b", bump_under_semaphore).map_err(|_| "spawn task failed")?;
+
+    for _ in 0..64 {
+        if FINISHED.load(Ordering::SeqCst) == 2 {
+            break;
+        }
+        process::yield_now();
+    }
+    if FINISHED.load(Ordering::SeqCst) != 2 {
+        return Err("semaphore tasks did not finish");
+    }
+    if OVERLAPPED.load(Ordering::SeqCst) {
+        return Err("two tasks were inside the critical section at once");
+    }
+    if SHARED.load(Ordering::SeqCst) != 2 * ROUNDS {
+        return Err("shared counter lost an update");
+    }
+    if MUTEX.available() != 1 {
+        return Err("semaphore count should be back to one");
+    }
+
+    for pid in [first, second] {
+        process::wait_for_child(Some(pid)).map_err(|_| "reap task failed")?;
+    }
+    Ok(())
+}
+
+const HOLD_YIELDS: u64 = 4;
+// Each round the holder yields HOLD_YIELDS times, plus a slice or two to be
+// woken and to exit. A waiter that polled the lock would get a slice on
+// every one of the holder's yields as well.
+const SLICE_BUDGET: u64 = (ROUNDS as u64) * (HOLD_YIELDS + 2) + 4;
+
+static SLEEPY: Mutex<u32> = Mutex::new(0);
+static SAW_BLOCKED: AtomicBool = AtomicBool::new(false);
+static SLICES_A: AtomicU32 = AtomicU32::new(0);
+static SLICES_B: AtomicU32 = AtomicU32::new(0);
+
+fn bump_under_mutex(slices: &AtomicU32) -> ! {
+    for _ in 0..ROUNDS {
+        let mut value = SLEEPY.lock();
+        let seen = *value;
+        for _ in 0..HOLD_YIELDS {
+            process::yield_now();
+        }
+        if SLEEPY.waiters() > 0 {
+            SAW_BLOCKED.store(true, Ordering::SeqCst);
+        }
+        *value = seen + 1;
+        drop(value);
+        process::yield_now();
+    }
+    let used = process::current_pid()
+        .and_then(process::get_process)
+        .map_or(u64::MAX, |snapshot| snapshot.cpu_slices());
+    slices.store(used.min(u32::MAX as u64) as u32, Ordering::SeqCst);
+    FINISHED.fetch_add(1, Ordering::SeqCst);
+    process::exit_current(0)
+}
+
+extern "C" fn mutex_task_a() -> ! {
+    bump_under_mutex(&SLICES_A)
+}
+
+extern "C" fn mutex_task_b() -> ! {
+    bump_under_mutex(&SLICES_B)
+}
+
+fn mutex_blocks_waiters() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("mutex_leader", run_mutex)
+}
+
+fn run_mutex(_leader: Pid) -> TestResult {
+    *SLEEPY.lock() = 0;
+    FINISHED.store(0, Ordering::SeqCst);
+    SAW_BLOCKED.store(false, Ordering::SeqCst);
+
+    let first = process::spawn_kernel_process("mutex_task_a", mutex_task_a).map_err(|_| "spawn task failed")?;
+    let second = process::spawn_kernel_process("mutex_task_b", mutex_task_b).map_err(|_| "spawn task failed")?;
+
+    for _ in 0..256 {
+        if FINISHED.load(Ordering::SeqCst) == 2 {
+            break;
+        }
+        process::yield_now();
+    }
+    if FINISHED.load(Ordering::SeqCst) != 2 {
+        return Err("mutex tasks did not finish");
+    }
+    if *SLEEPY.lock() != 2 * ROUNDS {
+        return Err("mutex lost an update");
+    }
+    if !SAW_BLOCKED.load(Ordering::SeqCst) {
+        return Err("contending task never blocked on the mutex");
+    }
+    for slices in [&SLICES_A, &SLICES_B] {
+        if slices.load(Ordering::SeqCst) as u64 > SLICE_BUDGET {
+            return Err("waiter burned slices instead of sleeping");
+        }
+    }
+    if SLEEPY.waiters() != 0 {
+        return Err("mutex still has waiters");
+    }
+
+    for pid in [first, second] {
+        process::wait_for_child(Some(pid)).map_err(|_| "reap task failed")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,710 @@
+#![cfg(kernel_test)]
+
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::{TestCase, TestResult};
+use crate::arch::x86_64::kernel::gdt;
+use crate::drivers::{ioctl, tty};
+use crate::fs::procfs;
+use crate::process::{self, AddressSpace, ProcessError};
+use crate::syscall::{self, dirent, nr, proc_state, PollFd, ProcInfo, SysError};
+use crate::mem::phys;
+use crate::user::{self, Credentials};
+use crate::tests::common::{mount_hello, reap_child, retire, with_leader, EXIT7_CODE};
+use crate::vfs::{attr, mode, VfsError};
+
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("syscall.neg_errno_badf", neg_errno_badf),
+    TestCase::new("syscall.sentinel_badf", sentinel_badf),
+    TestCase::new("syscall.getdents_root", getdents_root),
+    TestCase::new("syscall.getdents_resume", getdents_resume),
+    TestCase::new("syscall.getdents_subdir", getdents_subdir),
+    TestCase::new("syscall.fstat_hello", fstat_hello),
+    TestCase::new("syscall.seek_checks_deltas", seek_checks_deltas),
+    TestCase::new("syscall.stat_paths", stat_paths),
+    TestCase::new("syscall.open_missing_fat", open_missing_fat),
+    TestCase::new("syscall.open_unmapped_path", open_unmapped_path),
+    TestCase::new("syscall.ioctl_tty_mode", ioctl_tty_mode),
+    TestCase::new("syscall.sysinfo_counts", sysinfo_counts),
+    TestCase::new("syscall.getprocs_lists_tasks", getprocs_lists_tasks),
+    TestCase::new("syscall.poll_pipe_wakes", poll_pipe_wakes),
+    TestCase::new("syscall.spawn_exit7", spawn_exit7),
+    TestCase::new("syscall.spawn_rejects_paths", spawn_rejects_paths),
+    TestCase::new("syscall.waitpid_any_twice", waitpid_any_twice),
+    TestCase::new("syscall.power_needs_root", power_needs_root),
+    TestCase::new("syscall.trace_counts_calls", trace_counts_calls),
+    TestCase::new("syscall.read_only_fat_needs_root", read_only_fat_needs_root),
+    TestCase::new("syscall.writev_caps_huge_lengths", writev_caps_huge_lengths),
+];
+
+fn with_syscall_ctx(body: fn() -> TestResult) -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+
+    extern "C" fn dormant() -> ! {
+        loop {
+            spin_loop();
+        }
+    }
+
+    let pid = process::spawn_kernel_process("syscall_ctx", dormant)
+        .map_err(|_| "spawn syscall ctx failed")?;
+    process::set_current_pid(pid);
+    let result = body();
+    process::set_current_pid(0);
+    result
+}
+
+fn neg_errno_badf() -> TestResult {
+    with_syscall_ctx(|| {
+        let number = nr::CLOSE | nr::NEG_ERRNO_FLAG;
+        let ret = syscall::raw(number, 99, 0, 0);
+        if ret as i64 != -nr::errno::EBADF {
+            return Err("close(99) did not return -EBADF");
+        }
+        match syscall::decode_raw(number, ret) {
+            Err(SysError::BadFileDescriptor) => {}
+            _ => return Err("-EBADF did not decode to BadFileDescriptor"),
+        }
+
+        let number = nr::WRITE | nr::NEG_ERRNO_FLAG;
+        let msg = b"[test] neg errno\n";
+        let ret = syscall::raw(number, 42, msg.as_ptr() as u64, msg.len() as u64);
+        if ret as i64 != -nr::errno::EBADF {
+            return Err("write(42) did not return -EBADF");
+        }
+        Ok(())
+    })
+}
+
+fn sentinel_badf() -> TestResult {
+    with_syscall_ctx(|| {
+        let ret = syscall::raw(nr::CLOSE, 99, 0, 0);
+        if ret != u64::MAX {
+            return Err("legacy close(99) did not return ERR_BADF");
+        }
+        match syscall::close(99) {
+            Err(SysError::BadFileDescriptor) => Ok(()),
+            _ => Err("legacy close(99) did not decode to BadFileDescriptor"),
+        }
+    })
+}
+
+struct Dirent<'a> {
+    attr: u8,
+    name: &'a [u8],
+}
+
+fn decode_dirents<'a>(buf: &'a [u8], out: &mut [Option<Dirent<'a>>]) -> Result<usize, &'static str> {
+    let mut offset = 0;
+    let mut count = 0;
+    while offset < buf.len() {
+        if buf.len() - offset < dirent::HEADER_LEN {
+            return Err("truncated dirent header");
+        }
+        let size = u16::from_le_bytes([buf[offset], buf[offset + 1]]) as usize;
+        let name_len = buf[offset + 2] as usize;
+        if size != dirent::HEADER_LEN + name_len || offset + size > buf.len() {
+            return Err("malformed dirent size");
+        }
+        let slot = out.get_mut(count).ok_or("too many dirents")?;
+        *slot = Some(Dirent {
+            attr: buf[offset + 3],
+            name: &buf[offset + dirent::HEADER_LEN..offset + size],
+        });
+        count += 1;
+        offset += size;
+    }
+    Ok(count)
+}
+
+fn getdents_root() -> TestResult {
+    mount_hello()?;
+    with_syscall_ctx(|| {
+        let fd = syscall::open("/fat").map_err(|_| "open /fat failed")? as u64;
+        let mut buf = [0u8; 64];
+        let used = syscall::getdents(fd, &mut buf).map_err(|_| "getdents failed")?;
+
+        let mut entries: [Option<Dirent>; 4] = [None, None, None, None];
+        let count = decode_dirents(&buf[..used], &mut entries)?;
+        if count != 3 {
+            return Err("expected three root entries");
+        }
+        let hello = entries[0].as_ref().ok_or("missing first entry")?;
+        if hello.name != b"HELLO.TXT" || hello.attr & attr::DIRECTORY != 0 {
+            return Err("first entry should be HELLO.TXT");
+        }
+        let docs = entries[1].as_ref().ok_or("missing second entry")?;
+        if docs.name != b"DOCS" || docs.attr & attr::DIRECTORY == 0 {
+            return Err("second entry should be DOCS/");
+        }
+        let exit = entries[2].as_ref().ok_or("missing third entry")?;
+        if exit.name != b"EXIT7" {
+            return Err("third entry should be EXIT7");
+        }
+
+        let again = syscall::getdents(fd, &mut buf).map_err(|_| "second getdents failed")?;
+        syscall::close(fd).map_err(|_| "close /fat failed")?;
+        if again != 0 {
+            return Err("exhausted directory should return 0");
+        }
+        Ok(())
+    })
+}
+
+fn getdents_resume() -> TestResult {
+    mount_hello()?;
+    with_syscall_ctx(|| {
+        let fd = syscall::open("/fat/").map_err(|_| "open /fat/ failed")? as u64;
+
+        let mut tiny = [0u8; 4];
+        match syscall::getdents(fd, &mut tiny) {
+            Err(SysError::InvalidArgument) => {}
+            _ => return Err("undersized buffer should be rejected"),
+        }
+
+        // Room for "HELLO.TXT" (13 bytes) but not "DOCS" as well.
+        let mut buf = [0u8; 14];
+        let mut names = [[0u8; 16]; 2];
+        for (round, name) in names.iter_mut().enumerate() {
+            let used = syscall::getdents(fd, &mut buf).map_err(|_| "getdents failed")?;
+            let mut entries: [Option<Dirent>; 1] = [None];
+            if decode_dirents(&buf[..used], &mut entries)? != 1 {
+                return Err(if round == 0 { "first call should yield one entry" } else { "second call should yield one entry" });
+            }
+            let entry = entries[0].as_ref().ok_or("missing entry")?;
+            name[..entry.name.len()].copy_from_slice(entry.name);
+        }
+        syscall::close(fd).map_err(|_| "close failed")?;
+
+        if &names[0][..9] != b"HELLO.TXT" || &names[1][..4] != b"DOCS" {
+            return Err("cursor did not resume after first entry");
+        }
+        Ok(())
+    })
+}
+
+fn getdents_subdir() -> TestResult {
+    mount_hello()?;
+    with_syscall_ctx(|| {
+        let fd = syscall::open("/fat/DOCS").map_err(|_| "open /fat/DOCS failed")? as u64;
+        let mut buf = [0u8; 64];
+        let used = syscall::getdents(fd, &mut buf).map_err(|_| "getdents failed")?;
+        syscall::close(fd).map_err(|_| "close failed")?;
+
+        let mut entries: [Option<Dirent>; 2] = [None, None];
+        if decode_dirents(&buf[..used], &mut entries)? != 1 {
+            return Err("DOCS should list only README.TXT");
+        }
+        let readme = entries[0].as_ref().ok_or("missing entry")?;
+        if readme.name != b"README.TXT" || readme.attr & attr::READ_ONLY == 0 {
+            return Err("unexpected DOCS entry");
+        }
+
+        let fd = syscall::open("/fat/DOCS/README.TXT").map_err(|_| "open README failed")? as u64;
+        let read = syscall::read(fd, &mut buf).map_err(|_| "read README failed")?;
+        syscall::close(fd).map_err(|_| "close README failed")?;
+        if &buf[..read] != b"Readme" {
+            return Err("README contents mismatch");
+        }
+        Ok(())
+    })
+}
+
+fn fstat_hello() -> TestResult {
+    mount_hello()?;
+    with_syscall_ctx(|| {
+        let fd = syscall::open("/fat/HELLO.TXT").map_err(|_| "open HELLO failed")? as u64;
+        let stat = syscall::fstat(fd);
+        syscall::close(fd).map_err(|_| "close HELLO failed")?;
+        let stat = stat.map_err(|_| "fstat failed")?;
+        if stat.size != 5 {
+            return Err("HELLO.TXT should report size 5");
+        }
+        if stat.mode & mode::TYPE_MASK != mode::FILE || stat.mode & mode::WRITE != 0 {
+            return Err("HELLO.TXT should be a read-only regular file");
+        }
+        if stat.block_size == 0 {
+            return Err("block size missing");
+        }
+
+        let stat = syscall::fstat(syscall::fd::STDOUT).map_err(|_| "fstat stdout failed")?;
+        if stat.size != 0 || stat.mode & mode::TYPE_MASK != mode::CHAR {
+            return Err("stdout should be a zero-sized char device");
+        }
+        Ok(())
+    })
+}
+
+fn seek_checks_deltas() -> TestResult {
+    mount_hello()?;
+    with_syscall_ctx(|| {
+        let fd = syscall::open("/fat/HELLO.TXT").map_err(|_| "open HELLO failed")? as u64;
+        let result = seek_deltas(fd);
+        syscall::close(fd).map_err(|_| "close HELLO failed")?;
+        result
+    })
+}
+
+fn seek_deltas(fd: u64) -> TestResult {
+    use crate::syscall::SeekWhence;
+
+    if syscall::seek(fd, 2, SeekWhence::Set) != Ok(2) {
+        return Err("seek to 2 failed");
+    }
+    // -10 goes over the wire as u64::MAX - 9 and runs off the front.
+    if syscall::seek(fd, -10, SeekWhence::Cur) != Err(SysError::InvalidArgument) {
+        return Err("wrapping Cur delta was not refused");
+    }
+    if syscall::seek(fd, i64::MIN, SeekWhence::End) != Err(SysError::InvalidArgument) {
+        return Err("End delta below zero was not refused");
+    }
+    if syscall::seek(fd, 0, SeekWhence::Cur) != Ok(2) {
+        return Err("refused seek moved the cursor");
+    }
+
+    if syscall::seek(fd, -2, SeekWhence::End) != Ok(3) {
+        return Err("backward End seek landed wrong");
+    }
+    let mut buf = [0u8; 8];
+    let read = syscall::read(fd, &mut buf).map_err(|_| "read after End seek failed")?;
+    if &buf[..read] != b"lo" {
+        return Err("read after End seek returned the wrong bytes");
+    }
+    Ok(())
+}
+
+fn stat_paths() -> TestResult {
+    mount_hello()?;
+    with_syscall_ctx(|| {
+        let probe = syscall::open("/dev/null").map_err(|_| "open /dev/null failed")?;
+        syscall::close(probe as u64).map_err(|_| "close /dev/null failed")?;
+
+        let stat = syscall::stat("/fat/DOCS/README.TXT").map_err(|_| "stat README failed")?;
+        if stat.size != 6 {
+            return Err("README.TXT should report size 6");
+        }
+        let stat = syscall::stat("/fat/DOCS").map_err(|_| "stat DOCS failed")?;
+        if stat.mode & mode::TYPE_MASK != mode::DIR {
+            return Err("DOCS should stat as a directory");
+        }
+        match syscall::stat("/fat/MISSING.TXT") {
+            Err(SysError::NoEntry) => {}
+            _ => return Err("missing path should report NoEntry"),
+        }
+        // stat must not leak the descriptor it opened internally.
+        let fd = syscall::open("/dev/null").map_err(|_| "open /dev/null failed")?;
+        syscall::close(fd as u64).map_err(|_| "close /dev/null failed")?;
+        if fd != probe {
+            return Err("stat leaked a file descriptor");
+        }
+        Ok(())
+    })
+}
+
+fn open_missing_fat() -> TestResult {
+    mount_hello()?;
+    with_syscall_ctx(|| {
+        match process::open_path(process::current_pid().unwrap_or(0), "/fat/NOPE.TXT") {
+            Err(process::ProcessError::Vfs(VfsError::NotFound)) => {}
+            _ => return Err("open_path should report Vfs(NotFound)"),
+        }
+        match syscall::open("/fat/NOPE.TXT") {
+            Err(SysError::NoEntry) => {}
+            Err(SysError::BadFileDescriptor) => return Err("missing file reported as bad fd"),
+            _ => return Err("missing FAT file should report NoEntry"),
+        }
+        let path = b"/fat/DOCS/NOPE.TXT";
+        let number = nr::OPEN | nr::NEG_ERRNO_FLAG;
+        let ret = syscall::raw(number, path.as_ptr() as u64, path.len() as u64, 0);
+        if ret as i64 != -nr::errno::ENOENT {
+            return Err("missing FAT file should return -ENOENT");
+        }
+        Ok(())
+    })
+}
+
+fn open_unmapped_path() -> TestResult {
+    mount_hello()?;
+    with_syscall_ctx(|| {
+        // Kernel tasks read paths straight out of kernel memory, so borrow a
+        // user process for the duration of the checks.
+        let parent = process::current_pid().ok_or("no current pid")?;
+        let child = syscall::spawn("/fat/EXIT7", &[]).map_err(|_| "spawn EXIT7 failed")?;
+        let space = process::get_process(child).ok_or("child missing")?.address_space();
+        process::set_current_pid(child);
+        let result = open_from_user_space(&space);
+        process::set_current_pid(parent);
+
+        process::exit_process(child, EXIT7_CODE).map_err(|_| "exit_process failed")?;
+        process::wait_for_child(Some(child)).map_err(|_| "wait_for_child failed")?;
+        result
+    })
+}
+
+// Between the EXIT7 image and its stack; nothing maps it.
+const UNMAPPED_USER: u64 = 0x1000_0000;
+
+fn open_from_user_space(space: &AddressSpace) -> TestResult {
+    let number = nr::OPEN | nr::NEG_ERRNO_FLAG;
+    if syscall::raw(number, UNMAPPED_USER, 8, 0) as i64 != -nr::errno::EFAULT {
+        return Err("open of an unmapped path should return -EFAULT");
+    }
+    match process::read_user_cstr(space, UNMAPPED_USER, 8) {
+        Err(ProcessError::UserMemoryNotPresent) => {}
+        _ => return Err("read_user_cstr should fault on an unmapped page"),
+    }
+
+    // The page above the stack is unmapped. A path ending at the top of
+    // the stack still opens because the copy stops at the NUL.
+    let top = user::space::stack_top();
+    let path = b"/fat/HELLO.TXT\0";
+    let at = top - path.len() as u64;
+    process::copy_to_user(space, at, path).map_err(|_| "seed path failed")?;
+    match process::read_user_cstr(space, at, 64) {
+        Ok(bytes) if bytes == b"/fat/HELLO.TXT" => {}
+        _ => return Err("read_user_cstr should stop at the NUL"),
+    }
+    let fd = syscall::raw(number, at, 64, 0) as i64;
+    if fd < 0 {
+        return Err("open of a path at the top of the stack failed");
+    }
+    syscall::close(fd as u64).map_err(|_| "close failed")?;
+
+    if !matches!(process::read_user_cstr(space, at, 4), Err(ProcessError::UnterminatedString)) {
+        return Err("a string longer than max_len should be unterminated");
+    }
+    let tail = top - 4;
+    process::copy_to_user(space, tail, b"/fat").map_err(|_| "seed tail failed")?;
+    match process::read_user_cstr(space, tail, 64) {
+        Err(ProcessError::UserMemoryNotPresent) => {}
+        _ => return Err("a string running off the stack should fault"),
+    }
+    if syscall::raw(number, tail, 64, 0) as i64 != -nr::errno::EFAULT {
+        return Err("open of a path running off the stack should return -EFAULT");
+    }
+    Ok(())
+}
+
+fn ioctl_tty_mode() -> TestResult {
+    let original = tty::is_canonical();
+    let result = with_syscall_ctx(|| {
+        let stdin = syscall::fd::STDIN;
+        syscall::ioctl(stdin, ioctl::TTY_SET_CANONICAL, 1).map_err(|_| "set canonical failed")?;
+        if !tty::is_canonical() {
+            return Err("tty should be canonical");
+        }
+        syscall::ioctl(stdin, ioctl::TTY_SET_CANONICAL, 0).map_err(|_| "set raw failed")?;
+        if tty::is_canonical() {
+            return Err("tty should be raw");
+        }
+        if syscall::ioctl(stdin, ioctl::TTY_GET_CANONICAL, 0) != Ok(0) {
+            return Err("getter should report raw mode");
+        }
+
+        // Commands belong to one device; files and bad fds are rejected.
+        match syscall::ioctl(syscall::fd::STDOUT, ioctl::TTY_SET_CANONICAL, 1) {
+            Err(SysError::InvalidArgument) => {}
+            _ => return Err("console should reject tty ioctls"),
+        }
+        match syscall::ioctl(99, ioctl::TTY_GET_CANONICAL, 0) {
+            Err(SysError::BadFileDescriptor) => Ok(()),
+            _ => Err("ioctl on a closed fd should report BadFileDescriptor"),
+        }
+    });
+    tty::set_canonical(original);
+    result
+}
+
+fn spawn_exit7() -> TestResult {
+    mount_hello()?;
+    // Ring 3 needs the user segments and a TSS for the trap back in.
+    gdt::init();
+    with_syscall_ctx(|| {
+        let parent = process::current_pid().ok_or("no current pid")?;
+        let child = syscall::spawn("/fat/EXIT7", &["exit7", "-v"]).map_err(|_| "spawn EXIT7 failed")?;
+
+        let snapshot = process::get_process(child).ok_or("child missing")?;
+        if snapshot.parent() != Some(parent) || snapshot.name() != "EXIT7" {
+            return Err("child should be named after its binary and parented to the caller");
+        }
+        if snapshot.user_entry() != Some(0x40_0078) {
+            return Err("child entry does not match the ELF header");
+        }
+
+        // Let the child run to its own `exit(7)`.
+        if reap_child(child)? != EXIT7_CODE {
+            return Err("wait_for_child returned the wrong status");
+        }
+        Ok(())
+    })
+}
+
+fn spawn_rejects_paths() -> TestResult {
+    mount_hello()?;
+    with_syscall_ctx(|| {
+        match syscall::spawn("/fat/MISSING", &[]) {
+            Err(SysError::NoEntry) => {}
+            _ => return Err("missing binary should report NoEntry"),
+        }
+        match syscall::spawn("/dev/null", &[]) {
+            Err(SysError::NoEntry) => {}
+            _ => return Err("paths outside mounted filesystems should be refused"),
+        }
+        procfs::init().map_err(|_| "procfs mount failed")?;
+        match syscall::spawn("/proc/meminfo", &[]) {
+            Err(SysError::NoEntry) => {}
+            _ => return Err("mounts other than /fat and /tmp should be refused"),
+        }
+        match syscall::spawn("/fat/HELLO.TXT", &[]) {
+            Err(SysError::InvalidArgument) => Ok(()),
+            _ => Err("non-ELF file should be rejected"),
+        }
+    })
+}
+
+fn waitpid_any_twice() -> TestResult {
+    mount_hello()?;
+    with_syscall_ctx(|| {
+        let first = syscall::spawn("/fat/EXIT7", &[]).map_err(|_| "first spawn failed")?;
+        let second = syscall::spawn("/fat/EXIT7", &[]).map_err(|_| "second spawn failed")?;
+        process::exit_process(first, 1).map_err(|_| "exit first failed")?;
+        process::exit_process(second, 2).map_err(|_| "exit second failed")?;
+
+        let mut seen = [false; 2];
+        for _ in 0..2 {
+            let (pid, code) = syscall::waitpid(None).map_err(|_| "waitpid(-1) failed")?;
+            let index = if pid == first {
+                0
+            } else if pid == second {
+                1
+            } else {
+                return Err("waitpid returned a stranger");
+            };
+            if seen[index] || code != index as i32 + 1 {
+                return Err("waitpid reaped a child twice or with the wrong status");
+            }
+            seen[index] = true;
+        }
+
+        match syscall::waitpid(None) {
+            Err(SysError::NoChild) => {}
+            _ => return Err("waitpid with no children should report NoChild"),
+        }
+        let number = nr::WAITPID | nr::NEG_ERRNO_FLAG;
+        if syscall::raw(number, first as u64, 0, 0) as i64 != -nr::errno::ECHILD {
+            return Err("reaped pid should give -ECHILD");
+        }
+        Ok(())
+    })
+}
+
+fn sysinfo_counts() -> TestResult {
+    with_syscall_ctx(|| {
+        extern "C" fn idle_task() -> ! {
+            loop {
+                spin_loop();
+            }
+        }
+
+        let before = syscall::sysinfo().map_err(|_| "sysinfo failed")?;
+        let first = process::spawn_kernel_process("sysinfo_a", idle_task).map_err(|_| "spawn a failed")?;
+        let second = process::spawn_kernel_process("sysinfo_b", idle_task).map_err(|_| "spawn b failed")?;
+        let after = syscall::sysinfo().map_err(|_| "sysinfo failed")?;
+        retire(&[first, second]);
+
+        if after.procs_total != before.procs_total + 2 {
+            return Err("process total did not grow by the spawned tasks");
+        }
+        if after.procs_ready != before.procs_ready + 2 {
+            return Err("spawned tasks not counted as ready");
+        }
+        let states = after.procs_ready + after.procs_running + after.procs_blocked + after.procs_zombie;
+        if states != after.procs_total {
+            return Err("state counts do not add up to the total");
+        }
+        if after.heap_free == 0 || after.phys_total != phys::summary().total_bytes {
+            return Err("memory figures not filled in");
+        }
+        Ok(())
+    })
+}
+
+/// Write end of the pipe `poll_pipe_wakes` hands to its writer thread.
+static PIPE_WRITE_FD: AtomicU64 = AtomicU64::new(0);
+
+extern "C" fn pipe_writer() -> ! {
+    let fd = PIPE_WRITE_FD.load(Ordering::SeqCst);
+    let code = match syscall::write(fd, b"p") {
+        Ok(1) => 0,
+        _ => 1,
+    };
+    process::exit_current(code)
+}
+
+fn poll_pipe_wakes() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("poll_leader", |leader| {
+        let (read_fd, write_fd) = process::open_pipe(leader).map_err(|_| "open_pipe failed")?;
+        PIPE_WRITE_FD.store(write_fd as u64, Ordering::SeqCst);
+
+        let mut fds = [PollFd::new(read_fd as i32, nr::poll_events::IN), PollFd::new(99, nr::poll_events::IN)];
+        if syscall::poll(&mut fds[..1], 0) != Ok(0) || fds[0].revents != 0 {
+            return Err("empty pipe polled as readable");
+        }
+        if syscall::poll(&mut fds[1..], 0) != Ok(1) || fds[1].revents != nr::poll_events::NVAL {
+            return Err("closed fd was not reported as NVAL");
+        }
+
+        // The writer shares our fd table; it only runs once poll blocks.
+        let writer = process::spawn_thread(pipe_writer).map_err(|_| "spawn writer failed")?;
+        let ready = syscall::poll(&mut fds[..1], -1);
+        retire(&[writer]);
+        if ready != Ok(1) || fds[0].revents != nr::poll_events::IN {
+            return Err("poll did not report the written pipe as readable");
+        }
+
+        let mut byte = [0u8; 1];
+        if syscall::read(read_fd as u64, &mut byte) != Ok(1) || byte[0] != b'p' {
+            return Err("pipe did not deliver the written byte");
+        }
+        Ok(())
+    })
+}
+
+fn getprocs_lists_tasks() -> TestResult {
+    with_syscall_ctx(|| {
+        extern "C" fn idle_task() -> ! {
+            loop {
+                spin_loop();
+            }
+        }
+
+        let ready = process::spawn_kernel_process("ps_ready", idle_task).map_err(|_| "spawn ready failed")?;
+        let exited = process::spawn_kernel_process("ps_exited", idle_task).map_err(|_| "spawn exited failed")?;
+        process::exit_process(exited, 0).map_err(|_| "exit task failed")?;
+
+        let mut procs = [ProcInfo::default(); 64];
+        let count = syscall::getprocs(&mut procs).map_err(|_| "getprocs failed")?;
+        retire(&[ready]);
+        for pid in [ready, exited] {
+            process::wait_for_child(Some(pid)).map_err(|_| "reap task failed")?;
+        }
+        let procs = &procs[..count];
+
+        let find = |pid| procs.iter().find(|info| info.pid == pid);
+        match find(ready) {
+            Some(info) if info.state == proc_state::READY && info.name() == b"ps_ready" => {}
+            _ => return Err("ready task missing or wrong"),
+        }
+        match find(exited) {
+            Some(info) if info.state == proc_state::ZOMBIE && info.name() == b"ps_exited" => {}
+            _ => return Err("exited task not listed as a zombie"),
+        }
+
+        // A buffer with room for one record gets exactly one.
+        let mut one = [ProcInfo::default(); 1];
+        if syscall::getprocs(&mut one) != Ok(1) || one[0].pid == 0 {
+            return Err("getprocs ignored the buffer size");
+        }
+        Ok(())
+    })
+}
+
+fn power_needs_root() -> TestResult {
+    with_leader("power_user", |pid| {
+        process::with_process_mut(pid, |process| process.set_credentials(Credentials::new(1000, 1000)))
+            .map_err(|_| "could not drop privileges")?;
+        // A privileged caller would never get these results back.
+        match syscall::poweroff() {
+            Err(SysError::PermissionDenied) => {}
+            _ => return Err("unprivileged poweroff was not refused"),
+        }
+        match syscall::reboot() {
+            Err(SysError::PermissionDenied) => {}
+            _ => return Err("unprivileged reboot was not refused"),
+        }
+        let raw = syscall::raw(nr::POWEROFF | nr::NEG_ERRNO_FLAG, 0, 0, 0);
+        match syscall::decode_raw(nr::POWEROFF | nr::NEG_ERRNO_FLAG, raw) {
+            Err(SysError::PermissionDenied) => Ok(()),
+            _ => Err("unprivileged poweroff should report -EACCES"),
+        }
+    })
+}
+
+fn trace_counts_calls() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("systrace", |pid| {
+        let counted = [nr::OPEN, nr::WRITE, nr::CLOSE, nr::SYSTRACE];
+        let before = counted.map(syscall::call_count);
+
+        let was_on = syscall::systrace(true).map_err(|_| "enabling trace failed")?;
+        let fd = syscall::open("/dev/null").map_err(|_| "open /dev/null failed")?;
+        syscall::write(fd as u64, b"traced").map_err(|_| "write failed")?;
+        syscall::write(fd as u64, b"twice").map_err(|_| "write failed")?;
+        syscall::close(fd as u64).map_err(|_| "close failed")?;
+        syscall::systrace(was_on).map_err(|_| "restoring trace failed")?;
+
+        let after = counted.map(syscall::call_count);
+        let expected = [1, 2, 1, 2];
+        for i in 0..counted.len() {
+            if after[i] - before[i] != expected[i] {
+                return Err("per-syscall counter did not match the calls made");
+            }
+        }
+
+        process::with_process_mut(pid, |process| process.set_credentials(Credentials::new(1000, 1000)))
+            .map_err(|_| "could not drop privileges")?;
+        match syscall::systrace(true) {
+            Err(SysError::PermissionDenied) => Ok(()),
+            _ => Err("unprivileged systrace was not refused"),
+        }
+    })
+}
+
+fn read_only_fat_needs_root() -> TestResult {
+    mount_hello()?;
+    // DOCS/README.TXT carries the FAT read-only attribute; HELLO.TXT does not.
+    const LOCKED: &str = "/fat/DOCS/README.TXT";
+    with_leader("fat_writer", |pid| {
+        let fd = syscall::open_with(LOCKED, nr::open_flags::WRITE_ONLY)
+            .map_err(|_| "root could not open a read-only file for write")?;
+        syscall::close(fd as u64).map_err(|_| "close failed")?;
+
+        process::with_process_mut(pid, |process| process.set_credentials(Credentials::new(1000, 1000)))
+            .map_err(|_| "could not drop privileges")?;
+        let fd = syscall::open(LOCKED).map_err(|_| "reading a read-only file should stay allowed")?;
+        syscall::close(fd as u64).map_err(|_| "close failed")?;
+        let fd = syscall::open_with("/fat/HELLO.TXT", nr::open_flags::READ_WRITE)
+            .map_err(|_| "a file without the attribute should open for write")?;
+        syscall::close(fd as u64).map_err(|_| "close failed")?;
+
+        match syscall::open_with(LOCKED, nr::open_flags::WRITE_ONLY) {
+            Err(SysError::PermissionDenied) => {}
+            _ => return Err("unprivileged write open of a read-only file was not refused"),
+        }
+        match syscall::open_with(LOCKED, nr::open_flags::READ_WRITE) {
+            Err(SysError::PermissionDenied) => Ok(()),
+            _ => Err("unprivileged read-write open of a read-only file was not refused"),
+        }
+    })
+}
+
+fn writev_caps_huge_lengths() -> TestResult {
+    with_leader("writev_cap", |_| {
+        let fd = syscall::open("/dev/null").map_err(|_| "open /dev/null failed")?;
+        // Enough real bytes behind the pointer for the capped copy, and a
+        // length far beyond anything the heap could hold.
+        let backing = alloc::vec![0x5Au8; syscall::WRITEV_MAX];
+        let iov = syscall::IoVec {
+            base: backing.as_ptr() as u64,
+            len: 1 << 40,
+        };
+        let written = syscall::raw(nr::WRITEV, fd as u64, &iov as *const syscall::IoVec as u64, 1);
+        syscall::close(fd as u64).map_err(|_| "close failed")?;
+        if written != syscall::WRITEV_MAX as u64 {
+            return Err("a huge writev should be a short write of WRITEV_MAX bytes");
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,186 @@
+#![cfg(kernel_test)]
+
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use super::common::{retire, with_leader};
+use super::{TestCase, TestResult};
+use crate::arch::x86_64::kernel::apic;
+use crate::arch::x86_64::kernel::interrupts::{self, vectors};
+use crate::process::{self, Pid, ProcessState};
+use crate::timer::{self, TickSource};
+
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("timer.fire_in_deadline_order", fire_in_deadline_order),
+    TestCase::new("timer.lapic_preempts", lapic_preempts),
+    TestCase::new("timer.sleep_wakes_at_deadline", sleep_wakes_at_deadline),
+];
+
+const CANCELLED: usize = 99;
+
+static FIRED: [AtomicUsize; 4] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+static FIRED_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn record(id: usize) {
+    let slot = FIRED_COUNT.fetch_add(1, Ordering::Relaxed);
+    if slot < FIRED.len() {
+        FIRED[slot].store(id, Ordering::Relaxed);
+    }
+}
+
+fn fire_in_deadline_order() -> TestResult {
+    FIRED_COUNT.store(0, Ordering::Relaxed);
+    let pending = timer::pending_timers();
+    let now = timer::ticks();
+
+    // Added out of order; they must fire by deadline.
+    timer::add_timer(now + 3, record, 3).map_err(|_| "add third failed")?;
+    timer::add_timer(now + 1, record, 1).map_err(|_| "add first failed")?;
+    let doomed = timer::add_timer(now + 2, record, CANCELLED).map_err(|_| "add cancelled failed")?;
+    timer::add_timer(now + 2, record, 2).map_err(|_| "add second failed")?;
+    if !timer::cancel(doomed) {
+        return Err("cancel of a pending timer failed");
+    }
+    if timer::cancel(doomed) {
+        return Err("a timer was cancelled twice");
+    }
+
+    timer::advance_ticks(1);
+    if FIRED_COUNT.load(Ordering::Relaxed) != 1 {
+        return Err("only the first timer should have fired");
+    }
+    timer::advance_ticks(2);
+    if FIRED_COUNT.load(Ordering::Relaxed) != 3 {
+        return Err("not every timer fired");
+    }
+    for (slot, want) in [1, 2, 3].iter().enumerate() {
+        if FIRED[slot].load(Ordering::Relaxed) != *want {
+            return Err("timers fired out of deadline order");
+        }
+    }
+    if timer::pending_timers() != pending {
+        return Err("fired timers were left pending");
+    }
+    Ok(())
+}
+
+static BOUNCES: AtomicU64 = AtomicU64::new(0);
+
+/// Spins allowed while waiting for the LAPIC timer before giving up.
+const LAPIC_WAIT_SPINS: u64 = 200_000_000;
+
+fn lapic_preempts() -> TestResult {
+    if !apic::is_mapped() {
+        // No LAPIC: `timer::init` stays on the PIT and there is nothing to test.
+        return Ok(());
+    }
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("lapic", |_| {
+        // Counts each time it gets the CPU, then hands it straight back.
+        extern "C" fn bouncer() -> ! {
+            loop {
+                BOUNCES.fetch_add(1, Ordering::Relaxed);
+                process::yield_now();
+            }
+        }
+
+        let other = process::spawn_kernel_process("bouncer", bouncer).map_err(|_| "spawn bouncer failed")?;
+        // One voluntary round trip leaves the leader Running, which is what
+        // `request_preempt` looks for.
+        process::yield_now();
+        let result = wait_for_preemption(other);
+        retire(&[other]);
+        result
+    })
+}
+
+fn wait_for_preemption(other: Pid) -> TestResult {
+    let bounces = BOUNCES.load(Ordering::Relaxed);
+    let fired = interrupts::stats().count(vectors::LAPIC_TIMER);
+    let previous_hz = timer::frequency();
+    timer::start_lapic_timer(1000).ok_or("LAPIC timer calibration failed")?;
+    if timer::source() != TickSource::Lapic {
+        timer::stop_lapic_timer(previous_hz);
+        return Err("tick source did not move to the LAPIC");
+    }
+
+    interrupts::enable();
+    let mut spins = 0;
+    while BOUNCES.load(Ordering::Relaxed) == bounces && spins < LAPIC_WAIT_SPINS {
+        spin_loop();
+        spins += 1;
+    }
+    interrupts::disable();
+    timer::stop_lapic_timer(previous_hz);
+
+    if interrupts::stats().count(vectors::LAPIC_TIMER) == fired {
+        return Err("LAPIC timer vector never fired");
+    }
+    if BOUNCES.load(Ordering::Relaxed) == bounces {
+        return Err("LAPIC ticks did not preempt into the other task");
+    }
+    if process::current_pid().is_none() || process::get_process(other).is_none() {
+        return Err("lost track of the tasks across the switch");
+    }
+    if timer::frequency() != previous_hz {
+        return Err("stopping the LAPIC timer did not restore the tick rate");
+    }
+    Ok(())
+}
+
+const SLEEP_MS: u64 = 50;
+static SLEEPS_DONE: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn sleeper() -> ! {
+    if timer::sleep_ms(SLEEP_MS).is_ok() {
+        SLEEPS_DONE.fetch_add(1, Ordering::Relaxed);
+    }
+    loop {
+        process::yield_now();
+    }
+}
+
+fn is_blocked(pid: Pid) -> bool {
+    process::get_process(pid).is_some_and(|process| process.state() == ProcessState::Blocked)
+}
+
+fn sleep_wakes_at_deadline() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("sleep_leader", |leader| {
+        let done = SLEEPS_DONE.load(Ordering::Relaxed);
+        let child = process::spawn_kernel_process("sleeper", sleeper).map_err(|_| "spawn sleeper failed")?;
+        let outcome = (|| {
+            process::yield_now();
+            if !is_blocked(child) {
+                return Err("sleep_ms should block the caller");
+            }
+
+            let due = timer::ms_to_ticks(SLEEP_MS).max(1);
+            timer::advance_ticks(due - 1);
+            process::yield_now();
+            if SLEEPS_DONE.load(Ordering::Relaxed) != done {
+                return Err("the sleeper woke before its deadline");
+            }
+
+            // The deadline tick lands while the process table is held, as if
+            // it had interrupted a syscall: the wake moves to the next tick.
+            process::with_process_mut(leader, |_| timer::advance_ticks(1)).map_err(|_| "leader vanished")?;
+            if !is_blocked(child) {
+                return Err("a wake with the table held should wait for the next tick");
+            }
+            timer::advance_ticks(1);
+            process::yield_now();
+            if SLEEPS_DONE.load(Ordering::Relaxed) != done + 1 {
+                return Err("the sleeper was not woken once the table was free");
+            }
+            Ok(())
+        })();
+        retire(&[child]);
+        outcome
+    })
+}
@@ -0,0 +1,207 @@
+#![cfg(kernel_test)]
+
+use super::{TestCase, TestResult};
+use crate::fs::tmpfs;
+use crate::process;
+use crate::syscall::{self, nr, SeekWhence, SysError};
+use crate::tests::common::{mount_hello, with_leader};
+use crate::time;
+use crate::vfs::{mode, mount, VfsFile};
+
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("tmpfs.write_read_back", write_read_back),
+    TestCase::new("tmpfs.list_directory", list_directory),
+    TestCase::new("tmpfs.missing_without_create", missing_without_create),
+    TestCase::new("tmpfs.append_ignores_seek", append_ignores_seek),
+    TestCase::new("tmpfs.times_and_dirty", times_and_dirty),
+];
+
+fn setup() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    tmpfs::init().map_err(|_| "tmpfs mount failed")
+}
+
+fn write_read_back() -> TestResult {
+    setup()?;
+    with_leader("tmpfs_rw", |_| {
+        let fd = syscall::open_with("/tmp/note.txt", nr::open_flags::CREATE)
+            .map_err(|_| "create /tmp/note.txt failed")? as u64;
+        let result = (|| {
+            syscall::write(fd, b"hello").map_err(|_| "write failed")?;
+            // Seeking past the end and writing grows the file with a hole.
+            syscall::seek(fd, 8, SeekWhence::Set).map_err(|_| "seek failed")?;
+            syscall::write(fd, b"!").map_err(|_| "write past end failed")?;
+
+            let stat = syscall::fstat(fd).map_err(|_| "fstat failed")?;
+            if stat.size != 9 || stat.mode & mode::TYPE_MASK != mode::FILE || stat.mode & mode::WRITE == 0 {
+                return Err("file should be a 9-byte writable regular file");
+            }
+
+            syscall::seek(fd, 0, SeekWhence::Set).map_err(|_| "rewind failed")?;
+            let mut buf = [0xFFu8; 16];
+            let count = syscall::read(fd, &mut buf).map_err(|_| "read failed")?;
+            if &buf[..count] != b"hello\0\0\0!" {
+                return Err("read back did not match what was written");
+            }
+            Ok(())
+        })();
+        syscall::close(fd).map_err(|_| "close failed")?;
+        result?;
+
+        // A second open sees the same contents.
+        let fd = syscall::open("/tmp/note.txt").map_err(|_| "reopen failed")? as u64;
+        let mut buf = [0u8; 5];
+        let count = syscall::read(fd, &mut buf);
+        syscall::close(fd).map_err(|_| "close failed")?;
+        if count != Ok(5) || &buf != b"hello" {
+            return Err("reopened file lost its contents");
+        }
+        Ok(())
+    })
+}
+
+fn list_directory() -> TestResult {
+    setup()?;
+    tmpfs::mkdir("logs").map_err(|_| "mkdir failed")?;
+    with_leader("tmpfs_ls", |pid| {
+        for path in ["/tmp/logs/a.txt", "/tmp/logs/b.txt"] {
+            let fd = syscall::open_with(path, nr::open_flags::CREATE).map_err(|_| "create failed")?;
+            syscall::close(fd as u64).map_err(|_| "close failed")?;
+        }
+        // Creating an existing name opens it rather than adding a duplicate.
+        let fd = syscall::open_with("/tmp/logs/a.txt", nr::open_flags::CREATE).map_err(|_| "reopen failed")?;
+        syscall::close(fd as u64).map_err(|_| "close failed")?;
+
+        let fd = process::open_path(pid, "/tmp/logs").map_err(|_| "open dir failed")?;
+        let mut seen = [false; 2];
+        let mut total = 0;
+        let listed = process::with_fd_mut(pid, fd, |descriptor| {
+            descriptor.read_dir(|entry| {
+                total += 1;
+                match entry.name() {
+                    b"a.txt" => seen[0] = true,
+                    b"b.txt" => seen[1] = true,
+                    _ => {}
+                }
+                true
+            })
+        });
+        process::close_fd(pid, fd).map_err(|_| "close dir failed")?;
+        listed.map_err(|_| "missing fd")?.map_err(|_| "read_dir failed")?;
+        if total != 2 || seen != [true, true] {
+            return Err("directory should list exactly a.txt and b.txt");
+        }
+
+        let stat = syscall::stat("/tmp/logs").map_err(|_| "stat dir failed")?;
+        if stat.mode & mode::TYPE_MASK != mode::DIR {
+            return Err("/tmp/logs should stat as a directory");
+        }
+        Ok(())
+    })
+}
+
+fn missing_without_create() -> TestResult {
+    setup()?;
+    with_leader("tmpfs_missing", |_| {
+        match syscall::open("/tmp/absent.txt") {
+            Err(SysError::NoEntry) => {}
+            _ => return Err("missing tmpfs file should report NoEntry"),
+        }
+        match syscall::open_with("/tmp/nodir/x.txt", nr::open_flags::CREATE) {
+            Err(SysError::NoEntry) => {}
+            _ => return Err("create under a missing directory should report NoEntry"),
+        }
+        // FAT is read-only; O_CREAT there is refused rather than ignored.
+        mount_hello()?;
+        match syscall::open_with("/fat/NEW.TXT", nr::open_flags::CREATE) {
+            Err(SysError::PermissionDenied) => Ok(()),
+            _ => Err("create on FAT should report PermissionDenied"),
+        }
+    })
+}
+
+fn append_ignores_seek() -> TestResult {
+    setup()?;
+    with_leader("tmpfs_append", |_| {
+        let fd = syscall::open_with("/tmp/log.txt", nr::open_flags::CREATE | nr::open_flags::APPEND)
+            .map_err(|_| "open /tmp/log.txt for append failed")? as u64;
+        let result = (|| {
+            syscall::write(fd, b"one").map_err(|_| "first write failed")?;
+            syscall::seek(fd, 0, SeekWhence::Set).map_err(|_| "seek failed")?;
+            syscall::write(fd, b"two").map_err(|_| "second write failed")?;
+
+            syscall::seek(fd, 0, SeekWhence::Set).map_err(|_| "rewind failed")?;
+            let mut buf = [0u8; 16];
+            let count = syscall::read(fd, &mut buf).map_err(|_| "read failed")?;
+            if &buf[..count] != b"onetwo" {
+                return Err("append-mode write did not go to the end");
+            }
+            Ok(())
+        })();
+        syscall::close(fd).map_err(|_| "close failed")?;
+        result
+    })
+}
+
+const T0: u64 = 1_700_000_000;
+
+fn check_times(file: &dyn VfsFile, modified: u64, accessed: u64) -> TestResult {
+    let stat = file.stat().map_err(|_| "stat failed")?;
+    if stat.created != T0 {
+        return Err("created should stay at the creation time");
+    }
+    if stat.modified != modified {
+        return Err("unexpected modified time");
+    }
+    if stat.accessed != accessed {
+        return Err("unexpected accessed time");
+    }
+    Ok(())
+}
+
+fn times_and_dirty() -> TestResult {
+    setup()?;
+    let result = (|| {
+        let (fs, rest) = mount::lookup("/tmp/stamps.txt").ok_or("/tmp is not mounted")?;
+        time::set_now(T0);
+        let file = fs.create(rest).map_err(|_| "create failed")?;
+        check_times(file, T0, T0)?;
+        if file.is_dirty() {
+            return Err("a new file has nothing to flush");
+        }
+
+        time::set_now(T0 + 100);
+        file.write_at(0, b"stamped").map_err(|_| "write failed")?;
+        check_times(file, T0 + 100, T0)?;
+        if !file.is_dirty() {
+            return Err("write should mark the file dirty");
+        }
+
+        time::set_now(T0 + 200);
+        let mut buf = [0u8; 7];
+        file.read_at(0, &mut buf).map_err(|_| "read failed")?;
+        check_times(file, T0 + 100, T0 + 200)?;
+
+        file.flush().map_err(|_| "flush failed")?;
+        if file.is_dirty() {
+            return Err("flush should clear dirty");
+        }
+
+        time::set_now(T0 + 300);
+        file.truncate(3).map_err(|_| "truncate failed")?;
+        check_times(file, T0 + 300, T0 + 200)?;
+
+        // Closing an fd flushes, so the file is clean again afterwards.
+        with_leader("tmpfs_times", |_| {
+            let fd = syscall::open("/tmp/stamps.txt").map_err(|_| "open failed")? as u64;
+            syscall::close(fd).map_err(|_| "close failed")?;
+            Ok(())
+        })?;
+        if file.is_dirty() {
+            return Err("close should flush the file");
+        }
+        Ok(())
+    })();
+    time::set_now(0);
+    result
+}
@@ -0,0 +1,115 @@
+#![cfg(kernel_test)]
+
+use super::common::{retire, with_leader};
+use super::{TestCase, TestResult};
+use crate::arch::x86_64::drivers::console as vga;
+use crate::arch::x86_64::drivers::keyboard as arch;
+use crate::drivers::{console, keyboard, tty, DriverError};
+use crate::process::{self, Pid};
+use crate::syscall;
+
+pub const TESTS: &[TestCase] = &[
+    TestCase::new("tty.line_echoes_and_waits_for_enter", line_echoes_and_waits_for_enter),
+    TestCase::new("tty.leader_open_takes_the_terminal", leader_open_takes_the_terminal),
+];
+
+// Set 1 make codes; the break code is the make code with bit 7 set.
+const SCANCODE_H: u8 = 0x23;
+const SCANCODE_I: u8 = 0x17;
+const SCANCODE_ENTER: u8 = 0x1C;
+const RELEASE: u8 = 0x80;
+
+fn press(scancode: u8) {
+    arch::inject_scancode(scancode);
+    arch::inject_scancode(scancode | RELEASE);
+}
+
+/// Empty the keyboard ring and any half-typed line, leaving the tty in
+/// canonical mode with echo on.
+fn reset() {
+    let mut byte = [0u8; 1];
+    while keyboard::try_read(&mut byte) > 0 {}
+    tty::set_canonical(false);
+    tty::set_canonical(true);
+    tty::set_echo(true);
+}
+
+fn line_echoes_and_waits_for_enter() -> TestResult {
+    let (canonical, echo) = (tty::is_canonical(), tty::is_echo());
+    reset();
+    console::clear();
+
+    press(SCANCODE_H);
+    press(SCANCODE_I);
+    let mut buf = [0u8; 8];
+    let early = tty::try_read(&mut buf);
+    let row = vga::read_row(0);
+    let echoed = (row[0] & 0xFF) as u8 == b'h' && (row[1] & 0xFF) as u8 == b'i';
+
+    press(SCANCODE_ENTER);
+    let count = tty::try_read(&mut buf);
+
+    console::clear();
+    tty::set_canonical(canonical);
+    tty::set_echo(echo);
+
+    if early != 0 {
+        return Err("a line should not be delivered before Enter");
+    }
+    if !echoed {
+        return Err("typed bytes should be echoed to the console");
+    }
+    if &buf[..count] != b"hi\n" {
+        return Err("Enter should deliver the line with its newline");
+    }
+    Ok(())
+}
+
+extern "C" fn idle_task() -> ! {
+    loop {
+        process::yield_now();
+    }
+}
+
+fn leader_open_takes_the_terminal() -> TestResult {
+    if tty::control().session.is_some() {
+        return Err("another session already owns the terminal");
+    }
+    let (canonical, echo) = (tty::is_canonical(), tty::is_echo());
+    reset();
+
+    let result = with_leader("tty_leader", |leader| {
+        syscall::open("/dev/tty").map_err(|_| "open /dev/tty failed")?;
+        let control = tty::control();
+        let pgid = process::get_pgid(leader).map_err(|_| "leader has no group")?;
+        if control.session != Some(leader) || control.foreground != Some(pgid) {
+            return Err("a session leader opening the tty should own it");
+        }
+
+        let child: Pid = process::spawn_kernel_process("tty_bg", idle_task).map_err(|_| "spawn child failed")?;
+        let outcome = (|| {
+            process::set_pgid(child, child).map_err(|_| "setpgid failed")?;
+            tty::set_foreground(child).map_err(|_| "foreground handoff failed")?;
+
+            // The leader's group is now in the background.
+            let mut byte = [0u8; 1];
+            if !matches!(tty::read_blocking(&mut byte), Err(DriverError::IoError)) {
+                return Err("a background group should not read the terminal");
+            }
+            if tty::set_foreground(Pid::MAX).is_ok() {
+                return Err("the foreground must be a group of the session");
+            }
+            tty::set_foreground(pgid).map_err(|_| "foreground handback failed")
+        })();
+        retire(&[child]);
+        outcome
+    });
+
+    tty::set_canonical(canonical);
+    tty::set_echo(echo);
+    result?;
+    if tty::control().session.is_some() {
+        return Err("the terminal should be free once the leader exits");
+    }
+    Ok(())
+}
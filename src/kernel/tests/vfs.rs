@@ -1,13 +1,22 @@
 #![cfg(kernel_test)]
 
+extern crate alloc;
+
+use alloc::vec;
 use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use super::{TestCase, TestResult};
 use crate::drivers;
 use crate::process;
 use crate::syscall;
-use crate::tests::common::{init_scratch, mount_hello};
+use crate::arch::x86_64::drivers::ata;
+use crate::drivers::{BlockDevice, CharDevice, Driver, DriverError, DriverKind};
+use crate::tests::common::{init_scratch, mount_hello, with_leader, SCRATCH_DEVICE};
+use crate::process::{FileDescriptor, FileIoError, SeekFrom};
 use crate::vfs::ata::AtaScratchFile;
+use crate::vfs::handle::resolve_seek;
+use crate::vfs::symlink;
 use crate::vfs::{VfsError, VfsFile};
 
 const BLOCK_SIZE: usize = 512;
@@ -16,8 +25,17 @@ pub const TESTS: &[TestCase] = &[
     TestCase::new("vfs.scratch_roundtrip", scratch_roundtrip),
     TestCase::new("vfs.scratch_overlap", scratch_overlap),
     TestCase::new("vfs.scratch_bounds", scratch_bounds),
+    TestCase::new("vfs.block_over_range", block_over_range),
     TestCase::new("vfs.scratch_stress", scratch_stress),
     TestCase::new("vfs.ticker_smoke", ticker_smoke_stress),
+    TestCase::new("vfs.symlink_follow", symlink_follow),
+    TestCase::new("vfs.symlink_loop", symlink_loop),
+    TestCase::new("vfs.dotted_paths", dotted_paths),
+    TestCase::new("vfs.char_seek", char_seek),
+    TestCase::new("vfs.char_device_lookup", char_device_lookup),
+    TestCase::new("vfs.driver_unregister", driver_unregister),
+    TestCase::new("vfs.driver_duplicate_name", driver_duplicate_name),
+    TestCase::new("vfs.zero_and_full", zero_and_full),
 ];
 
 fn scratch_roundtrip() -> TestResult {
@@ -67,6 +85,30 @@ fn scratch_bounds() -> TestResult {
     }
 }
 
+fn block_over_range() -> TestResult {
+    let capacity = SCRATCH_DEVICE.capacity_sectors();
+    let mut sector = [0u8; BLOCK_SIZE];
+    if !matches!(SCRATCH_DEVICE.read_blocks(capacity, &mut sector), Err(DriverError::IoError)) {
+        return Err("read past the test device did not fail");
+    }
+    if !matches!(SCRATCH_DEVICE.write_blocks(capacity - 1, &[0u8; BLOCK_SIZE * 2]), Err(DriverError::IoError)) {
+        return Err("write straddling the end of the test device did not fail");
+    }
+
+    // With a real disk identified, a past-the-end read must come back with
+    // an error before the driver starts waiting on the drive.
+    if let Some(geometry) = ata::geometry() {
+        let end = ata::driver().capacity_sectors();
+        if end == u64::MAX || end > geometry.sectors() {
+            return Err("ata capacity does not reflect IDENTIFY");
+        }
+        if !matches!(ata::driver().read_blocks(end, &mut sector), Err(DriverError::IoError)) {
+            return Err("ata read past the disk did not fail");
+        }
+    }
+    Ok(())
+}
+
 fn scratch_stress() -> TestResult {
     init_scratch();
     let file = AtaScratchFile::get().ok_or("scratch not initialised")?;
@@ -156,3 +198,324 @@ fn ticker_sequence() -> Result<(), &'static str> {
 
     Ok(())
 }
+
+fn symlink_follow() -> TestResult {
+    mount_hello()?;
+    process::init().map_err(|_| "process init failed")?;
+    symlink::symlink("/dev/hello", "/dev/hello-next").map_err(|_| "symlink failed")?;
+    symlink::symlink("/dev/hello-next", "/fat/HELLO.TXT").map_err(|_| "symlink failed")?;
+    let result = with_leader("symlink_follow", |pid| {
+        let fd = process::open_path(pid, "/dev/hello").map_err(|_| "open through link failed")?;
+        let mut buf = [0u8; 8];
+        let read = process::with_fd_mut(pid, fd, |descriptor| descriptor.read(&mut buf));
+        process::close_fd(pid, fd).map_err(|_| "close failed")?;
+        let count = read
+            .map_err(|_| "missing descriptor")?
+            .map_err(|_| "read through link failed")?;
+        if &buf[..count] != b"Hello" {
+            return Err("link did not reach HELLO.TXT");
+        }
+        Ok(())
+    });
+    symlink::unlink("/dev/hello");
+    symlink::unlink("/dev/hello-next");
+    result
+}
+
+fn dotted_paths() -> TestResult {
+    mount_hello()?;
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("dotted_paths", |pid| {
+        // Collapsing is lexical, so the missing SUB directory does not matter.
+        for path in ["/fat/./HELLO.TXT", "/fat/SUB/../HELLO.TXT", "//fat//HELLO.TXT"] {
+            let fd = process::open_path(pid, path).map_err(|_| "dotted path did not open")?;
+            let mut buf = [0u8; 5];
+            let read = process::with_fd_mut(pid, fd, |descriptor| descriptor.read(&mut buf));
+            process::close_fd(pid, fd).map_err(|_| "close failed")?;
+            if !matches!(read, Ok(Ok(5))) || &buf != b"Hello" {
+                return Err("dotted path reached the wrong file");
+            }
+        }
+        match process::open_path(pid, "/fat/../../HELLO.TXT") {
+            Err(process::ProcessError::Vfs(VfsError::InvalidPath)) => {}
+            _ => return Err("climbing above / should be InvalidPath"),
+        }
+        match syscall::open("fat/HELLO.TXT") {
+            Err(syscall::SysError::InvalidArgument) => Ok(()),
+            _ => Err("relative path should report EINVAL"),
+        }
+    })
+}
+
+fn symlink_loop() -> TestResult {
+    process::init().map_err(|_| "process init failed")?;
+    symlink::symlink("/dev/self", "/dev/self").map_err(|_| "symlink failed")?;
+    symlink::symlink("/dev/ping", "/dev/pong").map_err(|_| "symlink failed")?;
+    symlink::symlink("/dev/pong", "/dev/ping").map_err(|_| "symlink failed")?;
+    let result = with_leader("symlink_loop", |pid| {
+        for path in ["/dev/self", "/dev/ping"] {
+            match process::open_path(pid, path) {
+                Err(process::ProcessError::Vfs(VfsError::TooManyLinks)) => {}
+                _ => return Err("cyclic link should fail with TooManyLinks"),
+            }
+        }
+        match syscall::open("/dev/self") {
+            Err(syscall::SysError::Loop) => Ok(()),
+            _ => Err("cyclic link should report ELOOP"),
+        }
+    });
+    for path in ["/dev/self", "/dev/ping", "/dev/pong"] {
+        symlink::unlink(path);
+    }
+    result
+}
+
+/// A char device with a fixed-size window and its own cursor, standing in
+/// for something like a framebuffer.
+struct SeekableDevice {
+    pos: AtomicU64,
+}
+
+const SEEKABLE_SIZE: u64 = 64;
+
+static SEEKABLE: SeekableDevice = SeekableDevice { pos: AtomicU64::new(0) };
+
+impl Driver for SeekableDevice {
+    fn name(&self) -> &'static str {
+        "seekable"
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Char
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+}
+
+impl CharDevice for SeekableDevice {
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, DriverError> {
+        Ok(0)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, DriverError> {
+        Ok(buf.len())
+    }
+
+    fn seek(&self, pos: SeekFrom) -> Result<u64, DriverError> {
+        let current = self.pos.load(Ordering::Relaxed);
+        let target = resolve_seek(pos, current, SEEKABLE_SIZE).map_err(|_| DriverError::Unsupported)?;
+        self.pos.store(target, Ordering::Relaxed);
+        Ok(target)
+    }
+}
+
+fn char_seek() -> TestResult {
+    let mut console = FileDescriptor::Char(drivers::console::driver());
+    match console.seek(SeekFrom::Start(0)) {
+        Err(FileIoError::Driver(DriverError::Unsupported)) => {}
+        _ => return Err("console seek should be unsupported"),
+    }
+    for name in ["null", "zero"].iter() {
+        let device = drivers::char_device_by_name(name).ok_or("builtin char device missing")?;
+        match FileDescriptor::Char(device).seek(SeekFrom::Start(0)) {
+            Err(FileIoError::Driver(DriverError::Unsupported)) => {}
+            _ => return Err("/dev/null and /dev/zero should stay unseekable"),
+        }
+    }
+
+    SEEKABLE.pos.store(0, Ordering::Relaxed);
+    let mut fd = FileDescriptor::Char(&SEEKABLE);
+    if fd.seek(SeekFrom::Start(16)).map_err(|_| "seek start failed")? != 16 {
+        return Err("seek start landed wrong");
+    }
+    if fd.seek(SeekFrom::Current(-4)).map_err(|_| "seek current failed")? != 12 {
+        return Err("seek current landed wrong");
+    }
+    if fd.seek(SeekFrom::End(0)).map_err(|_| "seek end failed")? != SEEKABLE_SIZE {
+        return Err("seek end landed wrong");
+    }
+    if fd.seek(SeekFrom::Current(1)).is_ok() {
+        return Err("seek past the end was accepted");
+    }
+    Ok(())
+}
+
+fn char_device_lookup() -> TestResult {
+    let zero = drivers::char_device_by_name("zero").ok_or("zero device not registered")?;
+    if zero.name() != "zero" {
+        return Err("lookup returned the wrong device");
+    }
+    let mut buf = [0xAAu8; 4];
+    if zero.read(&mut buf).map_err(|_| "zero read failed")? != buf.len() || buf != [0u8; 4] {
+        return Err("zero device did not read zeroes");
+    }
+    if drivers::char_device_by_name("no-such-device").is_some() {
+        return Err("unknown name resolved to a device");
+    }
+    if drivers::char_device_by_name("ata0-master").is_some() {
+        return Err("block device returned by the char lookup");
+    }
+    Ok(())
+}
+
+struct UnpluggedDevice {
+    shut_down: AtomicBool,
+}
+
+static UNPLUGGED: UnpluggedDevice = UnpluggedDevice { shut_down: AtomicBool::new(false) };
+
+impl Driver for UnpluggedDevice {
+    fn name(&self) -> &'static str {
+        "unplugged"
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Char
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        Ok(())
+    }
+
+    fn shutdown(&self) {
+        self.shut_down.store(true, Ordering::Relaxed);
+    }
+}
+
+impl CharDevice for UnpluggedDevice {
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, DriverError> {
+        Ok(0)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, DriverError> {
+        Ok(buf.len())
+    }
+}
+
+fn listed(name: &str) -> bool {
+    let mut found = false;
+    drivers::for_each_char_device(|dev| found |= dev.name() == name);
+    found
+}
+
+fn driver_unregister() -> TestResult {
+    UNPLUGGED.shut_down.store(false, Ordering::Relaxed);
+    drivers::register_char(&UNPLUGGED).map_err(|_| "register failed")?;
+    if !listed("unplugged") {
+        return Err("registered device not listed");
+    }
+
+    with_leader("unplug", |pid| {
+        let fd = process::open_path(pid, "/dev/unplugged").map_err(|_| "open /dev/unplugged failed")?;
+        let busy = matches!(drivers::unregister_by_name("unplugged"), Err(DriverError::Busy));
+        process::close_fd(pid, fd).map_err(|_| "close failed")?;
+        if !busy || UNPLUGGED.shut_down.load(Ordering::Relaxed) {
+            return Err("an open device was unregistered");
+        }
+        Ok(())
+    })?;
+
+    drivers::unregister_by_name("unplugged").map_err(|_| "unregister failed")?;
+    if listed("unplugged") {
+        return Err("unregistered device still listed");
+    }
+    if !UNPLUGGED.shut_down.load(Ordering::Relaxed) {
+        return Err("shutdown hook did not run");
+    }
+    match drivers::unregister_by_name("unplugged") {
+        Err(DriverError::NotFound) => Ok(()),
+        _ => Err("second unregister should report NotFound"),
+    }
+}
+
+struct TwinDevice {
+    inits: AtomicU64,
+}
+
+static TWIN_FIRST: TwinDevice = TwinDevice { inits: AtomicU64::new(0) };
+static TWIN_SECOND: TwinDevice = TwinDevice { inits: AtomicU64::new(0) };
+
+impl Driver for TwinDevice {
+    fn name(&self) -> &'static str {
+        "twin"
+    }
+
+    fn kind(&self) -> DriverKind {
+        DriverKind::Char
+    }
+
+    fn init(&self) -> Result<(), DriverError> {
+        self.inits.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl CharDevice for TwinDevice {
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, DriverError> {
+        Ok(0)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, DriverError> {
+        Ok(buf.len())
+    }
+}
+
+fn driver_duplicate_name() -> TestResult {
+    TWIN_SECOND.inits.store(0, Ordering::Relaxed);
+    drivers::register_char(&TWIN_FIRST).map_err(|_| "first register failed")?;
+    let second = drivers::register_char(&TWIN_SECOND);
+    let again = drivers::register_char(&TWIN_FIRST);
+    let found = drivers::char_device_by_name("twin");
+    drivers::unregister_by_name("twin").map_err(|_| "unregister failed")?;
+
+    if !matches!(second, Err(DriverError::DuplicateName)) || !matches!(again, Err(DriverError::DuplicateName)) {
+        return Err("a second device named twin was registered");
+    }
+    if TWIN_SECOND.inits.load(Ordering::Relaxed) != 0 {
+        return Err("the rejected device was initialised");
+    }
+    let first = &TWIN_FIRST as *const TwinDevice as *const u8;
+    match found {
+        Some(dev) if core::ptr::eq(dev as *const dyn CharDevice as *const u8, first) => {}
+        _ => return Err("lookup did not find the first device"),
+    }
+    if drivers::char_device_by_name("twin").is_some() {
+        return Err("twin still registered after unregister");
+    }
+    Ok(())
+}
+
+fn zero_and_full() -> TestResult {
+    drivers::register_builtin();
+    process::init().map_err(|_| "process init failed")?;
+    with_leader("zero_full", |_| {
+        let fd = syscall::open("/dev/zero").map_err(|_| "open /dev/zero failed")? as u64;
+        let mut page = [0xAAu8; 4096];
+        let read = syscall::read(fd, &mut page);
+        // Past READ_CHUNK, so the read goes round more than once.
+        let mut big = vec![0xAAu8; 100 * 1024];
+        let big_read = syscall::read(fd, &mut big);
+        syscall::close(fd).map_err(|_| "close /dev/zero failed")?;
+        if read != Ok(page.len()) || page.iter().any(|&b| b != 0) {
+            return Err("/dev/zero should fill a 4 KiB buffer in one read");
+        }
+        if big_read != Ok(big.len()) || big.iter().any(|&b| b != 0) {
+            return Err("/dev/zero should fill a buffer larger than one chunk");
+        }
+
+        let fd = syscall::open("/dev/full").map_err(|_| "open /dev/full failed")? as u64;
+        let written = syscall::write(fd, b"no room");
+        let mut buf = [0xAAu8; 8];
+        let read = syscall::read(fd, &mut buf);
+        syscall::close(fd).map_err(|_| "close /dev/full failed")?;
+        if written != Err(syscall::SysError::NoSpace) {
+            return Err("/dev/full write should fail with ENOSPC");
+        }
+        if read != Ok(buf.len()) || buf != [0u8; 8] {
+            return Err("/dev/full should read as zeroes");
+        }
+        Ok(())
+    })
+}
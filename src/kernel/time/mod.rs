@@ -0,0 +1,62 @@
+#![allow(dead_code)]
+
+//! Wall-clock time as Unix seconds. `init` reads the RTC once; from then on
+//! the time is that reading plus the uptime counted by the timer tick, so
+//! `now()` is cheap and never touches the CMOS ports. `civil` holds the
+//! calendar arithmetic.
+
+pub mod civil;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::arch::x86_64::drivers::rtc;
+use crate::klog;
+use crate::timer;
+
+/// Unix time at tick 0, or 0 while the wall time is unknown.
+static BOOT_UNIX: AtomicU64 = AtomicU64::new(0);
+
+/// The latest value `now()` has returned.
+static LAST_UNIX: AtomicU64 = AtomicU64::new(0);
+
+fn uptime_secs() -> u64 {
+    timer::ticks_to_ms(timer::ticks()) / 1000
+}
+
+pub fn init() {
+    match rtc::read() {
+        Some(stamp) => {
+            BOOT_UNIX.store(stamp.to_unix().saturating_sub(uptime_secs()), Ordering::Relaxed);
+            klog!(
+                "[time] RTC reads {:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC\n",
+                stamp.year,
+                stamp.month,
+                stamp.day,
+                stamp.hour,
+                stamp.minute,
+                stamp.second
+            );
+        }
+        None => klog!("[time] RTC unreadable; timestamps will be 0\n"),
+    }
+}
+
+/// Unix seconds, or 0 before `init` or when there is no usable RTC.
+/// `ticks_to_ms` rescales the whole uptime when the tick rate changes, so
+/// the sum can drop; `now()` then holds at the latest value it returned
+/// until the clock passes it again, and never goes backwards.
+pub fn now() -> u64 {
+    let unix = match BOOT_UNIX.load(Ordering::Relaxed) {
+        0 => return 0,
+        boot => boot + uptime_secs(),
+    };
+    let last = LAST_UNIX.fetch_max(unix, Ordering::Relaxed);
+    unix.max(last)
+}
+
+/// Make `now()` read `unix` at this moment, so tests can check stamps.
+#[cfg(kernel_test)]
+pub fn set_now(unix: u64) {
+    BOOT_UNIX.store(unix.saturating_sub(uptime_secs()), Ordering::Relaxed);
+    LAST_UNIX.store(0, Ordering::Relaxed);
+}
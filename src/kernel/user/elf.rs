@@ -1,6 +1,8 @@
 use alloc::vec::Vec;
 
-#[derive(Debug)]
+use super::space::USER_ADDR_LIMIT;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum ElfError {
     InvalidMagic,
     UnsupportedClass,
@@ -9,6 +11,14 @@ pub enum ElfError {
     InvalidHeader,
     InvalidProgramHeader,
     NoLoadableSegments,
+    /// `p_offset + p_filesz` runs past the end of the file.
+    SegmentOutOfFile,
+    /// `p_vaddr + p_memsz` overflows or reaches past `USER_ADDR_LIMIT`.
+    SegmentOutsideUserSpace,
+    /// `p_filesz` is larger than `p_memsz`.
+    SegmentFileSizeTooLarge,
+    /// Two `PT_LOAD` segments claim the same virtual memory.
+    OverlappingSegments,
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +35,9 @@ pub struct ElfSegment {
 pub struct ElfImage {
     pub entry: u64,
     pub segments: Vec<ElfSegment>,
+    /// The binary carries a `PT_GNU_STACK` header with `PF_X` set. Without
+    /// the header the stack is not executable.
+    pub executable_stack: bool,
 }
 
 const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
@@ -32,6 +45,7 @@ const ELFCLASS64: u8 = 2;
 const ELFDATA2LSB: u8 = 1;
 const ELF_MACHINE_X86_64: u16 = 0x3E;
 const PT_LOAD: u32 = 1;
+const PT_GNU_STACK: u32 = 0x6474_E551;
 
 pub fn parse(bytes: &[u8]) -> Result<ElfImage, ElfError> {
     if bytes.len() < 64 {
@@ -65,6 +79,7 @@ pub fn parse(bytes: &[u8]) -> Result<ElfImage, ElfError> {
     }
 
     let mut segments = Vec::new();
+    let mut executable_stack = false;
 
     for index in 0..phnum {
         let offset = phoff as usize + index * phentsize;
@@ -73,6 +88,10 @@ pub fn parse(bytes: &[u8]) -> Result<ElfImage, ElfError> {
         }
 
         let p_type = read_u32(bytes, offset)?;
+        if p_type == PT_GNU_STACK {
+            executable_stack = segment_flags_executable(read_u32(bytes, offset + 4)?);
+            continue;
+        }
         if p_type != PT_LOAD {
             continue;
         }
@@ -88,6 +107,24 @@ pub fn parse(bytes: &[u8]) -> Result<ElfImage, ElfError> {
             continue;
         }
 
+        match p_offset.checked_add(p_filesz) {
+            Some(end) if end <= bytes.len() as u64 => {}
+            _ => return Err(ElfError::SegmentOutOfFile),
+        }
+        match p_vaddr.checked_add(p_memsz) {
+            Some(end) if end <= USER_ADDR_LIMIT => {}
+            _ => return Err(ElfError::SegmentOutsideUserSpace),
+        }
+        if p_filesz > p_memsz {
+            return Err(ElfError::SegmentFileSizeTooLarge);
+        }
+        let overlaps = segments
+            .iter()
+            .any(|seg: &ElfSegment| p_vaddr < seg.vaddr + seg.memsz && seg.vaddr < p_vaddr + p_memsz);
+        if overlaps {
+            return Err(ElfError::OverlappingSegments);
+        }
+
         segments.push(ElfSegment {
             vaddr: p_vaddr,
             filesz: p_filesz,
@@ -102,7 +139,11 @@ pub fn parse(bytes: &[u8]) -> Result<ElfImage, ElfError> {
         return Err(ElfError::NoLoadableSegments);
     }
 
-    Ok(ElfImage { entry, segments })
+    Ok(ElfImage {
+        entry,
+        segments,
+        executable_stack,
+    })
 }
 
 fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, ElfError> {
@@ -2,6 +2,7 @@ use alloc::vec;
 use alloc::vec::Vec;
 
 use crate::fs::fat;
+use crate::vfs::mount;
 use crate::vfs::VfsError;
 
 #[derive(Debug)]
@@ -10,17 +11,34 @@ pub enum FileError {
     Io,
 }
 
-pub fn read_binary(path: &str) -> Result<Vec<u8>, FileError> {
-    let trimmed = path.strip_prefix("/bin/").ok_or(FileError::NotFound)?;
-    crate::klog!("[userfs] read_binary trimmed='{}'\n", trimmed);
+/// Mounts a program may be spawned from, besides `/bin/`. Synthetic trees
+/// such as `/proc` are left out even though they are in the mount table.
+const BINARY_MOUNTS: &[&str] = &["/fat/", "/tmp/"];
 
-    let file = fat::open_file(trimmed).map_err(|err| {
-        crate::klog!("[userfs] open_file error {:?}\n", err);
-        match err {
-            fat::FatError::NotFound | fat::FatError::InvalidPath => FileError::NotFound,
-            _ => FileError::Io,
+/// Read a whole executable. Only paths on a mounted filesystem are accepted:
+/// `/bin/<name>` (the FAT root, kept for the boot-time init) and paths under
+/// `BINARY_MOUNTS`, `/fat/<path>` or `/tmp/<path>`.
+pub fn read_binary(path: &str) -> Result<Vec<u8>, FileError> {
+    let file = match path.strip_prefix("/bin/") {
+        Some(trimmed) => {
+            crate::klog!("[userfs] read_binary trimmed='{}'\n", trimmed);
+            fat::open_file(fat::DEFAULT_VOLUME, trimmed).map_err(|err| {
+                crate::klog!("[userfs] open_file error {:?}\n", err);
+                match err {
+                    fat::FatError::NotFound | fat::FatError::InvalidPath => FileError::NotFound,
+                    _ => FileError::Io,
+                }
+            })?
+        }
+        None => {
+            if !BINARY_MOUNTS.iter().any(|prefix| path.starts_with(prefix)) {
+                return Err(FileError::NotFound);
+            }
+            let (fs, rest) = mount::lookup(path).ok_or(FileError::NotFound)?;
+            crate::klog!("[userfs] read_binary mounted path rest='{}'\n", rest);
+            fs.open(rest).map_err(map_vfs_err)?
         }
-    })?;
+    };
     crate::klog!("[userfs] open_file ok\n");
 
     let size = file.size().map_err(map_vfs_err)? as usize;
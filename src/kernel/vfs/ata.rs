@@ -2,23 +2,54 @@ use crate::drivers::BlockDevice;
 
 use super::{VfsError, VfsFile, VfsResult};
 
-const SCRATCH_BYTES: usize = 512;
+/// Largest sector the bounce buffer can hold.
+const MAX_SECTOR_BYTES: usize = 512;
+/// Sectors a scratch file spans unless told otherwise.
+pub const DEFAULT_SCRATCH_SECTORS: u64 = 1;
 
 static mut SCRATCH_FILE: Option<AtaScratchFile> = None;
 
+/// A fixed run of sectors, `lba..lba + sectors`, exposed as one file.
 pub struct AtaScratchFile {
     device: &'static dyn BlockDevice,
     lba: u64,
+    sectors: u64,
     name: &'static str,
 }
 
 impl AtaScratchFile {
     pub fn new(device: &'static dyn BlockDevice, lba: u64, name: &'static str) -> Self {
-        Self { device, lba, name }
+        Self {
+            device,
+            lba,
+            sectors: DEFAULT_SCRATCH_SECTORS,
+            name,
+        }
+    }
+
+    /// Span `sectors` sectors starting at the file's LBA.
+    pub fn with_sectors(mut self, sectors: u64) -> Self {
+        self.sectors = sectors;
+        self
     }
 
     pub unsafe fn init(device: &'static dyn BlockDevice, lba: u64, name: &'static str) -> &'static AtaScratchFile {
-        SCRATCH_FILE = Some(Self::new(device, lba, name));
+        Self::init_with_sectors(device, lba, DEFAULT_SCRATCH_SECTORS, name)
+    }
+
+    /// Like `init`, but spanning `sectors` sectors from `lba`.
+    ///
+    /// # Safety
+    ///
+    /// Replaces the global scratch file; no reference returned by an earlier
+    /// `init` or `get` may still be in use.
+    pub unsafe fn init_with_sectors(
+        device: &'static dyn BlockDevice,
+        lba: u64,
+        sectors: u64,
+        name: &'static str,
+    ) -> &'static AtaScratchFile {
+        SCRATCH_FILE = Some(Self::new(device, lba, name).with_sectors(sectors));
         SCRATCH_FILE.as_ref().unwrap()
     }
 
@@ -31,11 +62,49 @@ impl AtaScratchFile {
     }
 
     fn ensure_scratch_capacity(&self) -> VfsResult<()> {
-        if self.sector_size() > SCRATCH_BYTES {
+        if self.sector_size() > MAX_SECTOR_BYTES {
             return Err(VfsError::Unsupported);
         }
         Ok(())
     }
+
+    fn byte_len(&self) -> u64 {
+        self.sectors.saturating_mul(self.sector_size() as u64)
+    }
+
+    /// Check `len` bytes at `offset` fit, returning the end offset. Starting
+    /// past the end is `InvalidOffset`; running past it is `Unsupported`.
+    fn check_range(&self, offset: u64, len: usize) -> VfsResult<u64> {
+        let size = self.byte_len();
+        if offset >= size {
+            return Err(VfsError::InvalidOffset);
+        }
+        let end = offset.checked_add(len as u64).ok_or(VfsError::Unsupported)?;
+        if end > size {
+            return Err(VfsError::Unsupported);
+        }
+        Ok(end)
+    }
+
+    /// Call `f` once per sector touched by `offset..end` with the sector's
+    /// LBA, the byte range within the sector, and the matching range of the
+    /// caller's buffer.
+    fn for_each_sector<F>(&self, offset: u64, end: u64, mut f: F) -> VfsResult<()>
+    where
+        F: FnMut(u64, core::ops::Range<usize>, core::ops::Range<usize>) -> VfsResult<()>,
+    {
+        let sector_size = self.sector_size() as u64;
+        let mut pos = offset;
+        while pos < end {
+            let index = pos / sector_size;
+            let within = (pos % sector_size) as usize;
+            let chunk = (sector_size - within as u64).min(end - pos) as usize;
+            let done = (pos - offset) as usize;
+            f(self.lba + index, within..within + chunk, done..done + chunk)?;
+            pos += chunk as u64;
+        }
+        Ok(())
+    }
 }
 
 impl VfsFile for AtaScratchFile {
@@ -48,26 +117,17 @@ impl VfsFile for AtaScratchFile {
         if buf.is_empty() {
             return Ok(0);
         }
+        let end = self.check_range(offset, buf.len())?;
 
         let sector_size = self.sector_size();
-        if offset >= sector_size as u64 {
-            return Err(VfsError::InvalidOffset);
-        }
-
-        let start = offset as usize;
-        let end = start
-            .checked_add(buf.len())
-            .ok_or(VfsError::Unsupported)?;
-        if end > sector_size {
-            return Err(VfsError::Unsupported);
-        }
-
-        let mut sector = [0u8; SCRATCH_BYTES];
-        self.device
-            .read_blocks(self.lba, &mut sector[..sector_size])
-            .map_err(VfsError::from)?;
-
-        buf.copy_from_slice(&sector[start..end]);
+        let mut sector = [0u8; MAX_SECTOR_BYTES];
+        self.for_each_sector(offset, end, |lba, within, out| {
+            self.device
+                .read_blocks(lba, &mut sector[..sector_size])
+                .map_err(VfsError::from)?;
+            buf[out].copy_from_slice(&sector[within]);
+            Ok(())
+        })?;
         Ok(buf.len())
     }
 
@@ -76,29 +136,23 @@ impl VfsFile for AtaScratchFile {
         if buf.is_empty() {
             return Ok(0);
         }
+        let end = self.check_range(offset, buf.len())?;
 
         let sector_size = self.sector_size();
-        if offset >= sector_size as u64 {
-            return Err(VfsError::InvalidOffset);
-        }
-
-        let start = offset as usize;
-        let end = start
-            .checked_add(buf.len())
-            .ok_or(VfsError::Unsupported)?;
-        if end > sector_size {
-            return Err(VfsError::Unsupported);
-        }
-
-        let mut sector = [0u8; SCRATCH_BYTES];
-        self.device
-            .read_blocks(self.lba, &mut sector[..sector_size])
-            .map_err(VfsError::from)?;
-
-        sector[start..end].copy_from_slice(buf);
-        self.device
-            .write_blocks(self.lba, &sector[..sector_size])
-            .map_err(VfsError::from)?;
+        let mut sector = [0u8; MAX_SECTOR_BYTES];
+        self.for_each_sector(offset, end, |lba, within, input| {
+            // Whole sectors are overwritten outright; partial ones need the
+            // rest of the sector preserved.
+            if within.len() < sector_size {
+                self.device
+                    .read_blocks(lba, &mut sector[..sector_size])
+                    .map_err(VfsError::from)?;
+            }
+            sector[within].copy_from_slice(&buf[input]);
+            self.device
+                .write_blocks(lba, &sector[..sector_size])
+                .map_err(VfsError::from)
+        })?;
         self.device.flush().map_err(VfsError::from)?;
         Ok(buf.len())
     }
@@ -109,6 +163,6 @@ impl VfsFile for AtaScratchFile {
 
     fn size(&self) -> VfsResult<u64> {
         self.ensure_scratch_capacity()?;
-        Ok(self.sector_size() as u64)
+        Ok(self.byte_len())
     }
 }
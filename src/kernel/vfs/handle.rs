@@ -0,0 +1,249 @@
+#![allow(dead_code)]
+
+//! Cursor bookkeeping for an open VFS object: the offset advanced by reads
+//! and writes, append mode, and `seek` bounds checking against the file
+//! size. A handle that is read front to back also keeps the next
+//! `READ_AHEAD_LEN` bytes of the file in memory, so small reads do not each
+//! go down to the filesystem.
+
+use core::alloc::Layout;
+use core::{cmp, ptr, slice};
+
+use super::{VfsDirEntry, VfsError, VfsFile};
+use crate::mem::heap;
+
+/// How much a sequential read fetches past what was asked for: one 4 KiB
+/// cluster, or eight 512-byte sectors.
+pub const READ_AHEAD_LEN: usize = 4096;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+/// An open VFS object. For directories `offset` holds the `read_dir`
+/// cursor, so seeking to 0 rewinds the listing.
+pub struct VfsHandle {
+    file: &'static dyn VfsFile,
+    offset: u64,
+    append: bool,
+    /// Where the previous read stopped. A read starting there is taken as
+    /// sequential and may fill `read_ahead`.
+    last_read_end: Option<u64>,
+    read_ahead: ReadAhead,
+}
+
+impl VfsHandle {
+    pub fn new(file: &'static dyn VfsFile) -> Self {
+        Self {
+            file,
+            offset: 0,
+            append: false,
+            last_read_end: None,
+            read_ahead: ReadAhead::new(),
+        }
+    }
+
+    /// A handle whose writes always land at the end of the file, wherever
+    /// the cursor was left (`O_APPEND`).
+    pub fn append(file: &'static dyn VfsFile) -> Self {
+        Self {
+            append: true,
+            ..Self::new(file)
+        }
+    }
+
+    pub fn is_append(&self) -> bool {
+        self.append
+    }
+
+    pub fn set_append(&mut self, append: bool) {
+        self.append = append;
+    }
+
+    pub fn file(&self) -> &'static dyn VfsFile {
+        self.file
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Read at the cursor. Bytes already fetched ahead are copied from
+    /// memory; when they run out in the middle of a sequential run, the
+    /// `READ_AHEAD_LEN`-aligned block holding the cursor is fetched in one
+    /// `read_at`. Whatever the buffer cannot cover is read directly.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, VfsError> {
+        let sequential = self.last_read_end == Some(self.offset);
+        let mut count = self.read_ahead.copy_out(self.offset, buf);
+        let mut at_end = false;
+        if count < buf.len() && sequential && buf.len() - count < READ_AHEAD_LEN {
+            let at = self.offset.saturating_add(count as u64);
+            if self.read_ahead.fill(self.file, at) {
+                count += self.read_ahead.copy_out(at, &mut buf[count..]);
+                at_end = self.read_ahead.reached_end();
+            }
+        }
+        if count < buf.len() && !at_end {
+            let at = self.offset.saturating_add(count as u64);
+            match self.file.read_at(at, &mut buf[count..]) {
+                Ok(more) => count += more,
+                // Hand over what the buffer already gave; the error comes
+                // back on the next call.
+                Err(_) if count > 0 => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.offset = self.offset.saturating_add(count as u64);
+        self.last_read_end = Some(self.offset);
+        Ok(count)
+    }
+
+    /// Write at the cursor, or at the end of the file in append mode. The
+    /// cursor ends up just past the bytes written either way.
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, VfsError> {
+        self.drop_read_ahead();
+        if self.append {
+            self.offset = self.file.size()?;
+        }
+        let count = self.file.write_at(self.offset, buf)?;
+        self.offset = self.offset.saturating_add(count as u64);
+        Ok(count)
+    }
+
+    pub fn flush(&self) -> Result<(), VfsError> {
+        self.file.flush()
+    }
+
+    pub fn read_dir<F>(&mut self, mut emit: F) -> Result<usize, VfsError>
+    where
+        F: FnMut(&VfsDirEntry) -> bool,
+    {
+        let mut count = 0;
+        while let Some((entry, next)) = self.file.read_dir(self.offset)? {
+            if !emit(&entry) {
+                break;
+            }
+            self.offset = next;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Move the cursor. Landing before the start or past the end of the file
+    /// is `InvalidOffset` and leaves the cursor where it was.
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<u64, VfsError> {
+        let size = self.file.size()?;
+        let new_offset = resolve_seek(pos, self.offset, size)?;
+        self.offset = new_offset;
+        self.drop_read_ahead();
+        Ok(new_offset)
+    }
+
+    /// Forget the fetched bytes and the sequential run. Only this handle's
+    /// own writes and seeks call it, so a write through another handle to
+    /// the same file can go unseen here for up to `READ_AHEAD_LEN` bytes.
+    fn drop_read_ahead(&mut self) {
+        self.read_ahead.clear();
+        self.last_read_end = None;
+    }
+}
+
+/// Bytes `[start, start + len)` of the file, fetched before they were asked
+/// for. The buffer comes from `mem::heap` on the first fill, so a handle
+/// that is never read sequentially costs nothing.
+struct ReadAhead {
+    buf: *mut u8,
+    start: u64,
+    len: usize,
+}
+
+impl ReadAhead {
+    const fn new() -> Self {
+        Self {
+            buf: ptr::null_mut(),
+            start: 0,
+            len: 0,
+        }
+    }
+
+    fn layout() -> Layout {
+        // Size 4096 with alignment 1 is always a valid layout.
+        Layout::from_size_align(READ_AHEAD_LEN, 1).unwrap()
+    }
+
+    /// Copy whatever part of `buf` starting at `offset` is held here, from
+    /// the front. Zero when `offset` is not inside the buffered range.
+    fn copy_out(&self, offset: u64, buf: &mut [u8]) -> usize {
+        if self.len == 0 || offset < self.start || offset >= self.start + self.len as u64 {
+            return 0;
+        }
+        let skip = (offset - self.start) as usize;
+        let count = cmp::min(buf.len(), self.len - skip);
+        let held = unsafe { slice::from_raw_parts(self.buf.add(skip), count) };
+        buf[..count].copy_from_slice(held);
+        count
+    }
+
+    /// Fetch the aligned block holding `offset`. False, leaving the caller
+    /// to read directly, when the file has no known size (a stream, where
+    /// fetching early would change what other readers see), is a directory,
+    /// the buffer cannot be allocated or the read fails.
+    fn fill(&mut self, file: &dyn VfsFile, offset: u64) -> bool {
+        self.clear();
+        let offset = offset - offset % READ_AHEAD_LEN as u64;
+        if file.is_dir() || file.size().is_err() {
+            return false;
+        }
+        if self.buf.is_null() {
+            self.buf = unsafe { heap::allocate(Self::layout()) };
+            if self.buf.is_null() {
+                return false;
+            }
+        }
+        let buf = unsafe { slice::from_raw_parts_mut(self.buf, READ_AHEAD_LEN) };
+        match file.read_at(offset, buf) {
+            Ok(count) => {
+                self.start = offset;
+                self.len = count;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// The last fill came back short, so the file ends inside the buffer.
+    fn reached_end(&self) -> bool {
+        self.len < READ_AHEAD_LEN
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        if !self.buf.is_null() {
+            unsafe { heap::deallocate(self.buf, Self::layout()) };
+            self.buf = ptr::null_mut();
+        }
+    }
+}
+
+/// Where `pos` lands for a cursor at `current` in a file of `size` bytes.
+pub fn resolve_seek(pos: SeekFrom, current: u64, size: u64) -> Result<u64, VfsError> {
+    // i128 holds every u64 +/- i64, so nothing here can wrap.
+    let target = match pos {
+        SeekFrom::Start(pos) => pos as i128,
+        SeekFrom::Current(delta) => current as i128 + delta as i128,
+        SeekFrom::End(delta) => size as i128 + delta as i128,
+    };
+
+    if target < 0 || target > size as i128 {
+        return Err(VfsError::InvalidOffset);
+    }
+    Ok(target as u64)
+}
@@ -0,0 +1,104 @@
+#![allow(dead_code)]
+
+//! Prefix-based mount table. A filesystem registered at `/tmp` receives every
+//! path under that prefix with the prefix stripped, so `/tmp/a/b` reaches it
+//! as `a/b` and `/tmp` itself as the empty string (its root).
+
+use super::{VfsError, VfsFile, VfsResult};
+use crate::sync::spinlock::SpinLock;
+
+pub const MAX_MOUNTS: usize = 8;
+
+/// A mountable tree of files.
+pub trait FileSystem: Sync {
+    /// Open the file or directory at `path`, relative to this filesystem's
+    /// root. An empty path is the root directory.
+    fn open(&'static self, path: &str) -> VfsResult<&'static dyn VfsFile>;
+
+    /// Open `path`, creating an empty regular file if it does not exist.
+    fn create(&'static self, _path: &str) -> VfsResult<&'static dyn VfsFile> {
+        Err(VfsError::PermissionDenied)
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Mount {
+    prefix: &'static str,
+    fs: &'static dyn FileSystem,
+}
+
+pub struct MountTable {
+    mounts: [Option<Mount>; MAX_MOUNTS],
+}
+
+impl MountTable {
+    pub const fn new() -> Self {
+        Self {
+            mounts: [None; MAX_MOUNTS],
+        }
+    }
+
+    /// Attach `fs` at `prefix` (e.g. `"/tmp"`), replacing whatever was there.
+    pub fn mount(&mut self, prefix: &'static str, fs: &'static dyn FileSystem) -> VfsResult<()> {
+        if !prefix.starts_with('/') || prefix.len() < 2 || prefix.ends_with('/') {
+            return Err(VfsError::Unsupported);
+        }
+        if let Some(mount) = self.mounts.iter_mut().flatten().find(|mount| mount.prefix == prefix) {
+            mount.fs = fs;
+            return Ok(());
+        }
+        let slot = self
+            .mounts
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(VfsError::NoSpace)?;
+        *slot = Some(Mount { prefix, fs });
+        Ok(())
+    }
+
+    pub fn unmount(&mut self, prefix: &str) -> bool {
+        for slot in self.mounts.iter_mut() {
+            if matches!(slot, Some(mount) if mount.prefix == prefix) {
+                *slot = None;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Find the filesystem owning `path`, preferring the longest matching
+    /// prefix, and return it with the remainder of the path.
+    pub fn lookup<'a>(&self, path: &'a str) -> Option<(&'static dyn FileSystem, &'a str)> {
+        let mut best: Option<(Mount, &'a str)> = None;
+        for mount in self.mounts.iter().flatten() {
+            let rest = match path.strip_prefix(mount.prefix) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+                _ => continue,
+            };
+            if best.is_none_or(|(current, _)| mount.prefix.len() > current.prefix.len()) {
+                best = Some((*mount, rest.trim_start_matches('/')));
+            }
+        }
+        best.map(|(mount, rest)| (mount.fs, rest))
+    }
+}
+
+impl Default for MountTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static MOUNTS: SpinLock<MountTable> = SpinLock::new(MountTable::new());
+
+pub fn mount(prefix: &'static str, fs: &'static dyn FileSystem) -> VfsResult<()> {
+    MOUNTS.lock().mount(prefix, fs)
+}
+
+pub fn unmount(prefix: &str) -> bool {
+    MOUNTS.lock().unmount(prefix)
+}
+
+pub fn lookup(path: &str) -> Option<(&'static dyn FileSystem, &str)> {
+    MOUNTS.lock().lookup(path)
+}
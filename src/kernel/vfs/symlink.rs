@@ -0,0 +1,100 @@
+#![allow(dead_code)]
+
+//! Path aliases. A link maps one absolute path onto another; `resolve`
+//! follows links until it reaches a path that is not itself a link. Chains
+//! longer than `MAX_LINK_DEPTH` are treated as cycles.
+
+use super::{VfsError, VfsResult};
+use crate::sync::spinlock::SpinLock;
+
+pub const MAX_SYMLINKS: usize = 16;
+pub const MAX_LINK_DEPTH: usize = 8;
+
+#[derive(Copy, Clone)]
+struct Link {
+    from: &'static str,
+    to: &'static str,
+}
+
+pub struct SymlinkTable {
+    links: [Option<Link>; MAX_SYMLINKS],
+}
+
+impl SymlinkTable {
+    pub const fn new() -> Self {
+        Self {
+            links: [None; MAX_SYMLINKS],
+        }
+    }
+
+    /// Point `from` at `to`, replacing any existing link at `from`.
+    pub fn insert(&mut self, from: &'static str, to: &'static str) -> VfsResult<()> {
+        if let Some(link) = self.links.iter_mut().flatten().find(|link| link.from == from) {
+            link.to = to;
+            return Ok(());
+        }
+        let slot = self
+            .links
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(VfsError::NoSpace)?;
+        *slot = Some(Link { from, to });
+        Ok(())
+    }
+
+    /// Drop the link at `from`, returning whether one existed.
+    pub fn remove(&mut self, from: &str) -> bool {
+        for slot in self.links.iter_mut() {
+            if matches!(slot, Some(link) if link.from == from) {
+                *slot = None;
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn readlink(&self, path: &str) -> Option<&'static str> {
+        self.links
+            .iter()
+            .flatten()
+            .find(|link| link.from == path)
+            .map(|link| link.to)
+    }
+
+    /// Follow links starting at `path`. Returns `path` itself when it is not
+    /// a link and `TooManyLinks` when the chain does not end.
+    pub fn resolve<'a>(&self, path: &'a str) -> VfsResult<&'a str> {
+        let mut current = path;
+        for _ in 0..=MAX_LINK_DEPTH {
+            match self.readlink(current) {
+                Some(target) => current = target,
+                None => return Ok(current),
+            }
+        }
+        Err(VfsError::TooManyLinks)
+    }
+}
+
+impl Default for SymlinkTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static SYMLINKS: SpinLock<SymlinkTable> = SpinLock::new(SymlinkTable::new());
+
+pub fn symlink(from: &'static str, to: &'static str) -> VfsResult<()> {
+    SYMLINKS.lock().insert(from, to)
+}
+
+pub fn unlink(from: &str) -> bool {
+    SYMLINKS.lock().remove(from)
+}
+
+pub fn readlink(path: &str) -> Option<&'static str> {
+    SYMLINKS.lock().readlink(path)
+}
+
+pub fn resolve(path: &str) -> VfsResult<&str> {
+    SYMLINKS.lock().resolve(path)
+}